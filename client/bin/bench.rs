@@ -0,0 +1,513 @@
+//! Benchmark of a running service: replays a recorded operation log, or
+//! writes a synthetic graph and then drives mixed reads and writes against
+//! it. Reports throughput and latency percentiles of reads and writes per
+//! phase. See client/README.md.
+
+use meritrank_client::data::{
+  EdgeKind, FilterOptions, OpReadScores, OpWriteEdge, ReqData, Request,
+  Response,
+};
+use meritrank_client::{Client, ClientConfig};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::Serialize;
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage: bench [OPTIONS] replay FILE
+       bench [OPTIONS] synthetic
+
+Options:
+  --address ADDR        host:port or unix://PATH, MERITRANK_SERVICE_URL
+                        by default
+  --token TOKEN         MERITRANK_SERVICE_TOKEN by default
+  --workers N           Concurrent requests (default 16)
+  --json                Print the report as JSON
+
+replay sends the requests of FILE, one JSON Request per line, as fast as the
+workers allow; --workers 1 keeps their order.
+
+synthetic writes a generated graph edge by edge, then sends random reads and
+writes for --duration seconds:
+  --context NAME        Context to write to (default '')
+  --nodes N             Users in the graph (default 10000)
+  --mean-degree D       Mean out-degree, at least 1 (default 10)
+  --degree DIST         Out-degree distribution, uniform or power
+                        (default power)
+  --exponent A          Exponent of the power law, above 2 (default 2.5)
+  --negative-ratio R    Share of negative edges (default 0.1)
+  --read-ratio R        Share of reads after the graph is written
+                        (default 0.9)
+  --duration SECS       Length of the mixed phase (default 30)
+  --seed N              Seed of the generator (default 0)";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DegreeDistribution {
+  Uniform,
+  Power,
+}
+
+struct Options {
+  address:        String,
+  token:          String,
+  workers:        usize,
+  json:           bool,
+  context:        String,
+  nodes:          usize,
+  mean_degree:    f64,
+  degree:         DegreeDistribution,
+  exponent:       f64,
+  negative_ratio: f64,
+  read_ratio:     f64,
+  duration:       Duration,
+  seed:           u64,
+  command:        Vec<String>,
+}
+
+impl Default for Options {
+  fn default() -> Self {
+    Self {
+      address:        env::var("MERITRANK_SERVICE_URL")
+        .map(|url| url.trim_start_matches("tcp://").to_string())
+        .unwrap_or_else(|_| ClientConfig::default().address),
+      token:          env::var("MERITRANK_SERVICE_TOKEN").unwrap_or_default(),
+      workers:        16,
+      json:           false,
+      context:        String::new(),
+      nodes:          10_000,
+      mean_degree:    10.0,
+      degree:         DegreeDistribution::Power,
+      exponent:       2.5,
+      negative_ratio: 0.1,
+      read_ratio:     0.9,
+      duration:       Duration::from_secs(30),
+      seed:           0,
+      command:        vec![],
+    }
+  }
+}
+
+fn parse<T: std::str::FromStr>(
+  option: &str,
+  value: &str,
+) -> Result<T, String> {
+  value
+    .parse()
+    .map_err(|_| format!("Invalid value of {}: {}", option, value))
+}
+
+fn parse_args(
+  mut args: impl Iterator<Item = String>
+) -> Result<Options, String> {
+  let mut options = Options::default();
+  while let Some(arg) = args.next() {
+    if arg == "--json" {
+      options.json = true;
+      continue;
+    }
+    if !arg.starts_with("--") {
+      options.command.push(arg);
+      continue;
+    }
+    let value = args
+      .next()
+      .ok_or_else(|| format!("Missing value of {}", arg))?;
+    match arg.as_str() {
+      "--address" => options.address = value,
+      "--token" => options.token = value,
+      "--workers" => options.workers = parse(&arg, &value)?,
+      "--context" => options.context = value,
+      "--nodes" => options.nodes = parse(&arg, &value)?,
+      "--mean-degree" => options.mean_degree = parse(&arg, &value)?,
+      "--degree" => {
+        options.degree = match value.as_str() {
+          "uniform" => DegreeDistribution::Uniform,
+          "power" => DegreeDistribution::Power,
+          _ => return Err(format!("Invalid value of {}: {}", arg, value)),
+        }
+      },
+      "--exponent" => options.exponent = parse(&arg, &value)?,
+      "--negative-ratio" => options.negative_ratio = parse(&arg, &value)?,
+      "--read-ratio" => options.read_ratio = parse(&arg, &value)?,
+      "--duration" => {
+        options.duration = Duration::from_secs(parse(&arg, &value)?)
+      },
+      "--seed" => options.seed = parse(&arg, &value)?,
+      _ => return Err(format!("Unknown option {}", arg)),
+    }
+  }
+  if options.workers == 0 {
+    return Err("--workers must be at least 1".into());
+  }
+  if options.nodes < 2 {
+    return Err("--nodes must be at least 2".into());
+  }
+  if options.mean_degree.is_nan() || options.mean_degree < 1.0 {
+    return Err("--mean-degree must be at least 1".into());
+  }
+  if options.exponent.is_nan() || options.exponent <= 2.0 {
+    return Err("--exponent must be above 2".into());
+  }
+  for (name, ratio) in [
+    ("--negative-ratio", options.negative_ratio),
+    ("--read-ratio", options.read_ratio),
+  ] {
+    if !(0.0..=1.0).contains(&ratio) {
+      return Err(format!("{} must be between 0 and 1", name));
+    }
+  }
+  Ok(options)
+}
+
+/// Latencies in microseconds and number of failed requests of one kind of
+/// operation.
+#[derive(Default)]
+struct Samples {
+  latencies: Vec<u64>,
+  errors:    usize,
+}
+
+impl Samples {
+  fn merge(
+    &mut self,
+    other: Samples,
+  ) {
+    self.latencies.extend(other.latencies);
+    self.errors += other.errors;
+  }
+
+  fn summary(
+    mut self,
+    secs: f64,
+  ) -> Summary {
+    self.latencies.sort_unstable();
+    let percentile = |p: usize| {
+      if self.latencies.is_empty() {
+        return 0;
+      }
+      self.latencies[(self.latencies.len() - 1) * p / 100]
+    };
+    Summary {
+      ops:         self.latencies.len(),
+      errors:      self.errors,
+      ops_per_sec: self.latencies.len() as f64 / secs.max(f64::EPSILON),
+      p50_us:      percentile(50),
+      p95_us:      percentile(95),
+      p99_us:      percentile(99),
+      max_us:      self.latencies.last().copied().unwrap_or(0),
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct Summary {
+  ops:         usize,
+  errors:      usize,
+  ops_per_sec: f64,
+  p50_us:      u64,
+  p95_us:      u64,
+  p99_us:      u64,
+  max_us:      u64,
+}
+
+#[derive(Serialize)]
+struct PhaseReport {
+  phase:  &'static str,
+  /// Including the time to apply the queued writes.
+  secs:   f64,
+  reads:  Summary,
+  writes: Summary,
+}
+
+/// Next operation to send, or None once the phase is over. Called
+/// concurrently by the workers, each with its own generator.
+type OpSource = Arc<dyn Fn(&mut StdRng) -> Option<Request> + Send + Sync>;
+
+fn is_success(response: &Response) -> bool {
+  !matches!(
+    response,
+    Response::Fail
+      | Response::Error(_)
+      | Response::Busy
+      | Response::NotImplemented
+  )
+}
+
+/// Sends the operations of `next_op` from `workers` concurrent workers, then
+/// waits until the service has applied the writes.
+async fn run_phase(
+  client: &Arc<Client>,
+  phase: &'static str,
+  options: &Options,
+  next_op: OpSource,
+) -> PhaseReport {
+  let start = Instant::now();
+  let handles: Vec<_> = (0..options.workers)
+    .map(|worker| {
+      let client = Arc::clone(client);
+      let next_op = Arc::clone(&next_op);
+      let seed = options.seed.wrapping_add(worker as u64 + 1);
+      tokio::spawn(async move {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut reads = Samples::default();
+        let mut writes = Samples::default();
+        while let Some(request) = next_op(&mut rng) {
+          let samples = if request.data.is_write() {
+            &mut writes
+          } else {
+            &mut reads
+          };
+          let begin = Instant::now();
+          let result = client.call(&request.subgraph, request.data).await;
+          samples.latencies.push(begin.elapsed().as_micros() as u64);
+          if !matches!(&result, Ok(response) if is_success(response)) {
+            samples.errors += 1;
+          }
+        }
+        (reads, writes)
+      })
+    })
+    .collect();
+
+  let mut reads = Samples::default();
+  let mut writes = Samples::default();
+  for handle in handles {
+    if let Ok((worker_reads, worker_writes)) = handle.await {
+      reads.merge(worker_reads);
+      writes.merge(worker_writes);
+    }
+  }
+  if !writes.latencies.is_empty() {
+    if let Err(e) = client.sync().await {
+      eprintln!("Failed to wait for the writes to apply: {}", e);
+    }
+  }
+  let secs = start.elapsed().as_secs_f64();
+  PhaseReport {
+    phase,
+    secs,
+    reads:  reads.summary(secs),
+    writes: writes.summary(secs),
+  }
+}
+
+fn load_requests(path: &str) -> Result<Vec<Request>, String> {
+  let file =
+    File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+  let mut requests = vec![];
+  for (index, line) in BufReader::new(file).lines().enumerate() {
+    let line =
+      line.map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    let request = serde_json::from_str(&line)
+      .map_err(|e| format!("{}:{}: {}", path, index + 1, e))?;
+    requests.push(request);
+  }
+  Ok(requests)
+}
+
+fn node_name(index: usize) -> String {
+  format!("U{}", index)
+}
+
+fn random_weight(
+  rng: &mut StdRng,
+  negative_ratio: f64,
+) -> f64 {
+  let weight = rng.random_range(0.1..=1.0);
+  if rng.random_bool(negative_ratio) {
+    -weight
+  } else {
+    weight
+  }
+}
+
+fn out_degree(
+  rng: &mut StdRng,
+  options: &Options,
+) -> usize {
+  let degree = match options.degree {
+    DegreeDistribution::Uniform => {
+      rng.random_range(0.0..2.0 * options.mean_degree)
+    },
+    //  Pareto distribution with the mean of `mean_degree`.
+    DegreeDistribution::Power => {
+      let a = options.exponent;
+      let min = options.mean_degree * (a - 2.0) / (a - 1.0);
+      min * (1.0 - rng.random::<f64>()).powf(-1.0 / (a - 1.0))
+    },
+  };
+  (degree.round() as usize).clamp(1, options.nodes - 1)
+}
+
+/// Edges of the synthetic graph, as (src, dst, weight).
+fn generate_graph(options: &Options) -> Vec<(usize, usize, f64)> {
+  let mut rng = StdRng::seed_from_u64(options.seed);
+  let mut edges = vec![];
+  for src in 0..options.nodes {
+    let degree = out_degree(&mut rng, options);
+    let targets =
+      rand::seq::index::sample(&mut rng, options.nodes, degree + 1);
+    for dst in targets.into_iter().filter(|dst| *dst != src).take(degree) {
+      edges.push((src, dst, random_weight(&mut rng, options.negative_ratio)));
+    }
+  }
+  edges
+}
+
+fn write_edge_request(
+  context: &str,
+  src: usize,
+  dst: usize,
+  amount: f64,
+) -> Request {
+  Request {
+    subgraph: context.into(),
+    token:    String::new(),
+    data:     ReqData::WriteEdge(OpWriteEdge {
+      src:        node_name(src),
+      dst:        node_name(dst),
+      amount,
+      magnitude:  0,
+      edge_kind:  EdgeKind::Vote,
+      provenance: None,
+    }),
+  }
+}
+
+async fn replay(
+  client: &Arc<Client>,
+  options: &Options,
+  path: &str,
+) -> Result<Vec<PhaseReport>, String> {
+  let requests = Arc::new(load_requests(path)?);
+  let cursor = Arc::new(AtomicUsize::new(0));
+  let next_op: OpSource = Arc::new(move |_: &mut StdRng| {
+    requests.get(cursor.fetch_add(1, Ordering::Relaxed)).cloned()
+  });
+  Ok(vec![run_phase(client, "replay", options, next_op).await])
+}
+
+async fn synthetic(
+  client: &Arc<Client>,
+  options: &Options,
+) -> Vec<PhaseReport> {
+  let edges = Arc::new(generate_graph(options));
+  if !options.json {
+    println!("Writing {} edges of {} nodes", edges.len(), options.nodes);
+  }
+  let context = options.context.clone();
+  let cursor = Arc::new(AtomicUsize::new(0));
+  let next_op: OpSource = Arc::new(move |_: &mut StdRng| {
+    let (src, dst, amount) =
+      *edges.get(cursor.fetch_add(1, Ordering::Relaxed))?;
+    Some(write_edge_request(&context, src, dst, amount))
+  });
+  let load = run_phase(client, "load", options, next_op).await;
+
+  let context = options.context.clone();
+  let nodes = options.nodes;
+  let read_ratio = options.read_ratio;
+  let negative_ratio = options.negative_ratio;
+  let deadline = Instant::now() + options.duration;
+  let next_op: OpSource = Arc::new(move |rng: &mut StdRng| {
+    if Instant::now() >= deadline {
+      return None;
+    }
+    let src = rng.random_range(0..nodes);
+    if rng.random_bool(read_ratio) {
+      return Some(Request {
+        subgraph: context.clone(),
+        token:    String::new(),
+        data:     ReqData::ReadScores(OpReadScores {
+          ego:           node_name(src),
+          score_options: FilterOptions::default(),
+        }),
+      });
+    }
+    let dst = (src + rng.random_range(1..nodes)) % nodes;
+    let amount = random_weight(rng, negative_ratio);
+    Some(write_edge_request(&context, src, dst, amount))
+  });
+  let mixed = run_phase(client, "mixed", options, next_op).await;
+  vec![load, mixed]
+}
+
+fn print_report(reports: &[PhaseReport]) {
+  for report in reports {
+    println!("\n{}: {:.1} s", report.phase, report.secs);
+    println!(
+      "{:<8}{:>10}{:>8}{:>12}{:>10}{:>10}{:>10}{:>10}",
+      "", "ops", "errors", "ops/s", "p50 us", "p95 us", "p99 us", "max us"
+    );
+    let kinds = [("reads", &report.reads), ("writes", &report.writes)];
+    for (kind, summary) in kinds {
+      println!(
+        "{:<8}{:>10}{:>8}{:>12.1}{:>10}{:>10}{:>10}{:>10}",
+        kind,
+        summary.ops,
+        summary.errors,
+        summary.ops_per_sec,
+        summary.p50_us,
+        summary.p95_us,
+        summary.p99_us,
+        summary.max_us
+      );
+    }
+  }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+  let options = match parse_args(env::args().skip(1)) {
+    Ok(options) => options,
+    Err(message) => {
+      eprintln!("{}\n\n{}", message, USAGE);
+      return ExitCode::from(2);
+    },
+  };
+  //  No retries, so that Busy and timeouts show up as errors.
+  let client = Arc::new(Client::with_config(ClientConfig {
+    address: options.address.clone(),
+    token: options.token.clone(),
+    max_idle: options.workers,
+    retries: 0,
+    ..ClientConfig::default()
+  }));
+
+  let command: Vec<&str> =
+    options.command.iter().map(String::as_str).collect();
+  let result = match command.as_slice() {
+    ["replay", path] => replay(&client, &options, path).await,
+    ["synthetic"] => Ok(synthetic(&client, &options).await),
+    _ => {
+      eprintln!("{}", USAGE);
+      return ExitCode::from(2);
+    },
+  };
+  let reports = match result {
+    Ok(reports) => reports,
+    Err(message) => {
+      eprintln!("{}", message);
+      return ExitCode::FAILURE;
+    },
+  };
+  if !options.json {
+    print_report(&reports);
+    return ExitCode::SUCCESS;
+  }
+  match serde_json::to_string_pretty(&reports) {
+    Ok(json) => println!("{}", json),
+    Err(e) => eprintln!("Failed to serialize the report: {}", e),
+  }
+  ExitCode::SUCCESS
+}
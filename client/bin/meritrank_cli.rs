@@ -0,0 +1,375 @@
+//! Command line client for ops and debugging: reads scores, stats and
+//! snapshots of a running service, writes edges and checks that contexts
+//! agree with the default context. See client/README.md.
+
+use meritrank_client::data::{EdgeResult, FilterOptions, NodeKind};
+use meritrank_client::{Client, ClientConfig, ClientError};
+use meritrank_service::node_registry::node_kind_from_prefix;
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: meritrank-cli [--address ADDR] [--token TOKEN] [--json] COMMAND
+
+Commands:
+  scores CONTEXT EGO [LIMIT]          Scores by the ego, highest first
+  write-edge CONTEXT SRC DST WEIGHT   Set the weight of an edge, 0 deletes it
+  stats                               Stats of the service
+  maintenance on|off                  Reject writes, or accept them again
+  slow-log [LIMIT]                    Slow requests, newest first
+  snapshot CONTEXT                    Edges and zero opinions of a context
+  check-consistency                   Check contexts against the default one
+
+ADDR is host:port or unix://PATH, MERITRANK_SERVICE_URL by default. TOKEN is
+MERITRANK_SERVICE_TOKEN by default. '' is the default context. TCP connections
+use TLS if MERITRANK_TLS_CA is set, with the client certificate in
+MERITRANK_TLS_CLIENT_CERT and MERITRANK_TLS_CLIENT_KEY if set.";
+
+/// Relative difference of weights still considered equal.
+const WEIGHT_TOLERANCE: f64 = 1e-9;
+
+struct Options {
+  address: String,
+  token:   String,
+  json:    bool,
+  command: Vec<String>,
+}
+
+enum CliError {
+  Usage(String),
+  Client(ClientError),
+}
+
+impl From<ClientError> for CliError {
+  fn from(e: ClientError) -> Self {
+    CliError::Client(e)
+  }
+}
+
+fn parse_args(
+  mut args: impl Iterator<Item = String>
+) -> Result<Options, CliError> {
+  let mut options = Options {
+    address: env::var("MERITRANK_SERVICE_URL")
+      .map(|url| url.trim_start_matches("tcp://").to_string())
+      .unwrap_or_else(|_| ClientConfig::default().address),
+    token:   env::var("MERITRANK_SERVICE_TOKEN").unwrap_or_default(),
+    json:    false,
+    command: vec![],
+  };
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--address" | "--token" => {
+        let value = args.next().ok_or_else(|| {
+          CliError::Usage(format!("Missing value of {}", arg))
+        })?;
+        if arg == "--address" {
+          options.address = value;
+        } else {
+          options.token = value;
+        }
+      },
+      "--json" => options.json = true,
+      "-h" | "--help" => return Err(CliError::Usage(String::new())),
+      _ => options.command.push(arg),
+    }
+  }
+  Ok(options)
+}
+
+fn parse<T: std::str::FromStr>(
+  what: &str,
+  value: &str,
+) -> Result<T, CliError> {
+  value
+    .parse()
+    .map_err(|_| CliError::Usage(format!("Invalid {}: {}", what, value)))
+}
+
+/// Prints the rows under the header, each column padded to its widest cell.
+fn print_table(
+  header: &[&str],
+  rows: &[Vec<String>],
+) {
+  let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+  for row in rows {
+    for (width, cell) in widths.iter_mut().zip(row) {
+      *width = (*width).max(cell.len());
+    }
+  }
+  let print_row = |cells: Vec<&str>| {
+    let line: Vec<String> = cells
+      .iter()
+      .zip(&widths)
+      .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+      .collect();
+    println!("{}", line.join("  ").trim_end());
+  };
+  print_row(header.to_vec());
+  for row in rows {
+    print_row(row.iter().map(String::as_str).collect());
+  }
+}
+
+fn print_json<T: Serialize>(value: &T) {
+  match serde_json::to_string_pretty(value) {
+    Ok(json) => println!("{}", json),
+    Err(e) => eprintln!("Failed to serialize the response: {}", e),
+  }
+}
+
+fn print_ok(json: bool) {
+  if json {
+    print_json(&"Ok");
+  } else {
+    println!("Ok");
+  }
+}
+
+/// Fields of a struct as a two-column table, or as JSON.
+fn print_fields<T: Serialize>(
+  value: &T,
+  json: bool,
+) {
+  if json {
+    return print_json(value);
+  }
+  let rows: Vec<Vec<String>> = match serde_json::to_value(value) {
+    Ok(Value::Object(fields)) => fields
+      .into_iter()
+      .map(|(key, value)| vec![key, value.to_string()])
+      .collect(),
+    _ => vec![],
+  };
+  print_table(&["field", "value"], &rows);
+}
+
+fn is_user_edge(edge: &(String, String)) -> bool {
+  node_kind_from_prefix(&edge.0) == Some(NodeKind::User)
+    && node_kind_from_prefix(&edge.1) == Some(NodeKind::User)
+}
+
+fn edge_map(edges: Vec<EdgeResult>) -> HashMap<(String, String), f64> {
+  edges
+    .into_iter()
+    .filter(|e| e.weight != 0.0)
+    .map(|e| ((e.src, e.dst), e.weight))
+    .collect()
+}
+
+fn same_weight(
+  a: f64,
+  b: f64,
+) -> bool {
+  (a - b).abs() <= WEIGHT_TOLERANCE * a.abs().max(b.abs()).max(1.0)
+}
+
+#[derive(Serialize)]
+struct Inconsistency {
+  context: String,
+  src:     String,
+  dst:     String,
+  problem: &'static str,
+}
+
+/// User-user edges are shared by all contexts and every other edge of a
+/// context is also in the default context; lists the edges that break that.
+async fn check_consistency(
+  client: &Client
+) -> Result<Vec<Inconsistency>, ClientError> {
+  let shared = edge_map(client.read_edges("").await?);
+  let mut problems = vec![];
+  for context in client.read_contexts().await? {
+    if context.name.is_empty() {
+      continue;
+    }
+    let edges = edge_map(client.read_edges(&context.name).await?);
+    let mut found = |edge: &(String, String), problem| {
+      problems.push(Inconsistency {
+        context: context.name.clone(),
+        src:     edge.0.clone(),
+        dst:     edge.1.clone(),
+        problem,
+      })
+    };
+    for (edge, weight) in &edges {
+      match shared.get(edge) {
+        None => found(edge, "missing in the default context"),
+        Some(w) if is_user_edge(edge) && !same_weight(*weight, *w) => {
+          found(edge, "weight differs from the default context")
+        },
+        _ => {},
+      }
+    }
+    for edge in shared.keys().filter(|e| is_user_edge(e)) {
+      if !edges.contains_key(edge) {
+        found(edge, "user edge missing in the context");
+      }
+    }
+  }
+  Ok(problems)
+}
+
+/// Runs the command; `Ok(false)` when the check found problems.
+async fn run(
+  client: &Client,
+  options: &Options,
+) -> Result<bool, CliError> {
+  let json = options.json;
+  let command: Vec<&str> =
+    options.command.iter().map(String::as_str).collect();
+  match command.as_slice() {
+    ["scores", context, ego, rest @ ..] if rest.len() <= 1 => {
+      let mut filter = FilterOptions::default();
+      if let Some(limit) = rest.first() {
+        filter.count = parse("limit", limit)?;
+      }
+      let scores = client.read_scores(context, ego, filter).await?;
+      if json {
+        print_json(&scores);
+      } else {
+        let rows: Vec<Vec<String>> = scores
+          .iter()
+          .map(|s| {
+            vec![
+              s.rank.to_string(),
+              s.target.clone(),
+              format!("{:.6}", s.score),
+              s.cluster.to_string(),
+              format!("{:.1}", s.percentile),
+            ]
+          })
+          .collect();
+        print_table(
+          &["rank", "target", "score", "cluster", "percentile"],
+          &rows,
+        );
+      }
+    },
+    ["write-edge", context, src, dst, weight] => {
+      let weight = parse("weight", weight)?;
+      client.write_edge(context, src, dst, weight).await?;
+      print_ok(json);
+    },
+    ["stats"] => print_fields(&client.get_stats().await?, json),
+    ["maintenance", mode @ ("on" | "off")] => {
+      client.set_maintenance_mode(*mode == "on").await?;
+      print_ok(json);
+    },
+    ["slow-log", rest @ ..] if rest.len() <= 1 => {
+      let limit = match rest.first() {
+        Some(limit) => parse("limit", limit)?,
+        None => 0,
+      };
+      let ops = client.read_slow_log(limit).await?;
+      if json {
+        print_json(&ops);
+      } else {
+        let rows: Vec<Vec<String>> = ops
+          .iter()
+          .map(|op| {
+            vec![
+              op.time.to_string(),
+              op.opcode.clone(),
+              op.context.clone(),
+              format!("{:.1}", op.total_us as f64 / 1000.0),
+              format!("{:.1}", op.calculate_us as f64 / 1000.0),
+              op.walks.to_string(),
+              op.outcome.clone(),
+            ]
+          })
+          .collect();
+        print_table(
+          &["time", "opcode", "context", "ms", "calc_ms", "walks", "outcome"],
+          &rows,
+        );
+      }
+    },
+    ["snapshot", context] => {
+      let snapshot = client.read_context_snapshot(context).await?;
+      if json {
+        print_json(&snapshot);
+      } else {
+        let rows: Vec<Vec<String>> = snapshot
+          .edges
+          .iter()
+          .map(|e| vec![e.src.clone(), e.dst.clone(), e.amount.to_string()])
+          .collect();
+        print_table(&["src", "dst", "weight"], &rows);
+        println!(
+          "\n{} edges, {} zero opinions, {} clusters",
+          snapshot.edges.len(),
+          snapshot.zero_opinion.len(),
+          snapshot.num_clusters
+        );
+      }
+    },
+    ["check-consistency"] => {
+      let problems = check_consistency(client).await?;
+      if json {
+        print_json(&problems);
+      } else if problems.is_empty() {
+        println!("Ok");
+      } else {
+        let rows: Vec<Vec<String>> = problems
+          .iter()
+          .map(|p| {
+            vec![
+              p.context.clone(),
+              p.src.clone(),
+              p.dst.clone(),
+              p.problem.to_string(),
+            ]
+          })
+          .collect();
+        print_table(&["context", "src", "dst", "problem"], &rows);
+      }
+      return Ok(problems.is_empty());
+    },
+    [] => return Err(CliError::Usage("Missing command".into())),
+    _ => {
+      return Err(CliError::Usage(format!(
+        "Invalid command: {}",
+        options.command.join(" ")
+      )))
+    },
+  }
+  Ok(true)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+  let result = match parse_args(env::args().skip(1)) {
+    Ok(options) => {
+      let client = Client::with_config(ClientConfig {
+        address: options.address.clone(),
+        token: options.token.clone(),
+        tls_ca: env::var("MERITRANK_TLS_CA").unwrap_or_default(),
+        tls_cert: env::var("MERITRANK_TLS_CLIENT_CERT").unwrap_or_default(),
+        tls_key: env::var("MERITRANK_TLS_CLIENT_KEY").unwrap_or_default(),
+        ..ClientConfig::default()
+      });
+      run(&client, &options).await
+    },
+    Err(e) => Err(e),
+  };
+  match result {
+    Ok(true) => ExitCode::SUCCESS,
+    Ok(false) => ExitCode::FAILURE,
+    Err(CliError::Usage(message)) => {
+      if !message.is_empty() {
+        eprintln!("{}\n", message);
+      }
+      eprintln!("{}", USAGE);
+      ExitCode::from(2)
+    },
+    Err(CliError::Client(e)) => {
+      eprintln!("Error: {}", e);
+      ExitCode::FAILURE
+    },
+  }
+}
@@ -0,0 +1,824 @@
+//! Async client of the MeritRank service.
+//!
+//! ```no_run
+//! use meritrank_client::{data::FilterOptions, Client};
+//!
+//! # async fn example() -> Result<(), meritrank_client::ClientError> {
+//! let client = Client::new("127.0.0.1:8080");
+//! client.write_edge("", "U1", "U2", 1.0).await?;
+//! let scores = client.read_scores("", "U1", FilterOptions::default()).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Connections are kept open and reused. Requests that do not reach the
+//! service, time out or get `Busy` are retried. Writes are sent with an
+//! idempotency key, so that a retry of a write the service already applied
+//! is not applied twice, see `ClientConfig::idempotent_writes`.
+
+pub use meritrank_service::data;
+
+use meritrank_service::data::*;
+use meritrank_service::request_handler::{COMPRESSED_FLAG, COMPRESSION_MAGIC};
+use meritrank_service::tls::{self, tls_client_config};
+
+use bincode::{config::standard, decode_from_slice, encode_to_vec};
+use parking_lot::Mutex;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio_rustls::TlsConnector;
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+  #[error("Connection failed: {0}")]
+  Io(#[from] io::Error),
+  #[error("Request timed out")]
+  Timeout,
+  /// Write queue of the service stayed full for all retries.
+  #[error("Service is busy")]
+  Busy,
+  #[error("Request failed")]
+  Fail,
+  #[error("Not implemented by the service")]
+  NotImplemented,
+  #[error("Service error: {0:?}")]
+  Service(ServiceError),
+  #[error("Unexpected response: {0}")]
+  UnexpectedResponse(String),
+}
+
+impl ClientError {
+  fn is_transient(&self) -> bool {
+    matches!(self, ClientError::Io(_) | ClientError::Timeout)
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+  /// `host:port` of the service, or `unix://` and the path of its socket.
+  pub address:           String,
+  /// Token checked against the ACL of the service.
+  pub token:             String,
+  /// Max number of idle connections kept for reuse.
+  pub max_idle:          usize,
+  /// Retries of a request that failed to reach the service, timed out or got
+  /// `Busy`.
+  pub retries:           usize,
+  /// Delay before the first retry, doubled for each next one.
+  pub retry_delay:       Duration,
+  /// Time budget of one attempt, including connecting.
+  pub timeout:           Duration,
+  /// Ask the service to compress large responses.
+  pub compression:       bool,
+  /// Wrap writes in `Idempotent` with a random key, so that the service
+  /// applies them once however many times they are retried. Retries the
+  /// service deduplicates get the response of the first request.
+  pub idempotent_writes: bool,
+  /// PEM certificates of the CAs the certificate of the service must be
+  /// signed by; when set, TCP connections use TLS.
+  pub tls_ca:            String,
+  /// PEM certificate chain presented to the service (empty = none).
+  pub tls_cert:          String,
+  /// PEM private key of `tls_cert`.
+  pub tls_key:           String,
+}
+
+impl Default for ClientConfig {
+  fn default() -> Self {
+    Self {
+      address:           "127.0.0.1:8080".into(),
+      token:             String::new(),
+      max_idle:          16,
+      retries:           3,
+      retry_delay:       Duration::from_millis(100),
+      timeout:           Duration::from_secs(10),
+      compression:       false,
+      idempotent_writes: true,
+      tls_ca:            String::new(),
+      tls_cert:          String::new(),
+      tls_key:           String::new(),
+    }
+  }
+}
+
+trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin> Connection for S {}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// Sends a length-prefixed (4-byte big-endian) bincode request and reads the
+/// response.
+async fn exchange(
+  connection: &mut dyn Connection,
+  request: &Request,
+) -> io::Result<Response> {
+  let payload = encode_to_vec(request, standard()).map_err(invalid_data)?;
+  let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+  frame.extend_from_slice(&payload);
+  connection.write_all(&frame).await?;
+
+  let len = connection.read_u32().await?;
+  let mut buf = vec![0u8; (len & !COMPRESSED_FLAG) as usize];
+  connection.read_exact(&mut buf).await?;
+  if len & COMPRESSED_FLAG != 0 {
+    buf = zstd::stream::decode_all(&buf[..])?;
+  }
+  decode_from_slice(&buf, standard())
+    .map(|(response, _)| response)
+    .map_err(invalid_data)
+}
+
+fn expect_ok(response: Response) -> Result<(), ClientError> {
+  match response {
+    Response::Ok => Ok(()),
+    response => Err(unexpected(response)),
+  }
+}
+
+fn unexpected(response: Response) -> ClientError {
+  match response {
+    Response::Fail => ClientError::Fail,
+    Response::NotImplemented => ClientError::NotImplemented,
+    Response::Busy => ClientError::Busy,
+    Response::Error(e) => ClientError::Service(e),
+    response => ClientError::UnexpectedResponse(format!("{:?}", response)),
+  }
+}
+
+pub struct Client {
+  config:     ClientConfig,
+  /// Loaded on the first connection, see `tls_connector`.
+  tls:        OnceLock<Option<TlsConnector>>,
+  idle:       Mutex<Vec<Box<dyn Connection>>>,
+  sync_stamp: AtomicU64,
+}
+
+impl Client {
+  /// Client with the default settings, see `ClientConfig`.
+  pub fn new(address: &str) -> Self {
+    Self::with_config(ClientConfig {
+      address: address.into(),
+      ..ClientConfig::default()
+    })
+  }
+
+  pub fn with_config(config: ClientConfig) -> Self {
+    Client {
+      config,
+      tls:        OnceLock::new(),
+      idle:       Mutex::new(vec![]),
+      sync_stamp: AtomicU64::new(0),
+    }
+  }
+
+  /// Connector for TLS, or None if `tls_ca` is not set. Certificates that
+  /// fail to load fail the connection, and are loaded again for the next one.
+  fn tls_connector(&self) -> io::Result<Option<TlsConnector>> {
+    if let Some(connector) = self.tls.get() {
+      return Ok(connector.clone());
+    }
+    let config = &self.config;
+    let connector =
+      tls_client_config(&config.tls_ca, &config.tls_cert, &config.tls_key)
+        .map_err(|e| io::Error::other(e.to_string()))?
+        .map(TlsConnector::from);
+    Ok(self.tls.get_or_init(|| connector).clone())
+  }
+
+  async fn connect(&self) -> io::Result<Box<dyn Connection>> {
+    let mut connection: Box<dyn Connection> =
+      match self.config.address.strip_prefix("unix://") {
+        Some(path) => Box::new(UnixStream::connect(path).await?),
+        None => {
+          let tls = self.tls_connector()?;
+          Box::new(tls::connect(&self.config.address, tls.as_ref()).await?)
+        },
+      };
+    if self.config.compression {
+      connection.write_all(&[COMPRESSION_MAGIC]).await?;
+    }
+    Ok(connection)
+  }
+
+  fn release(
+    &self,
+    connection: Box<dyn Connection>,
+  ) {
+    let mut idle = self.idle.lock();
+    if idle.len() < self.config.max_idle {
+      idle.push(connection);
+    }
+  }
+
+  /// Sends the request over an idle connection. If there is none, or it was
+  /// closed, the request is sent over a new one.
+  async fn attempt(
+    &self,
+    request: &Request,
+  ) -> Result<Response, ClientError> {
+    let pooled = self.idle.lock().pop();
+    if let Some(mut connection) = pooled {
+      if let Ok(response) = exchange(&mut *connection, request).await {
+        self.release(connection);
+        return Ok(response);
+      }
+    }
+
+    let mut connection = self.connect().await?;
+    let response = exchange(&mut *connection, request).await?;
+    self.release(connection);
+    Ok(response)
+  }
+
+  /// Sends any request, with retries. Typed methods below cover the common
+  /// ones. `ReadScoresChunked` is answered with several messages and is not
+  /// supported; use `read_scores` instead.
+  pub async fn call(
+    &self,
+    context: &str,
+    data: ReqData,
+  ) -> Result<Response, ClientError> {
+    if matches!(data, ReqData::ReadScoresChunked(_)) {
+      return Err(ClientError::NotImplemented);
+    }
+    let wrap = self.config.idempotent_writes
+      && data.is_write()
+      && !matches!(data, ReqData::Idempotent(_));
+    let data = if wrap {
+      ReqData::Idempotent(OpIdempotent {
+        key:  format!("{:032x}", rand::random::<u128>()),
+        data: Box::new(data),
+      })
+    } else {
+      data
+    };
+    let request = Request {
+      subgraph: context.into(),
+      token:    self.config.token.clone(),
+      data,
+    };
+
+    let mut delay = self.config.retry_delay;
+    let mut retries = self.config.retries;
+    loop {
+      let result =
+        tokio::time::timeout(self.config.timeout, self.attempt(&request))
+          .await
+          .unwrap_or(Err(ClientError::Timeout));
+      let retry = match &result {
+        Ok(response) => matches!(response, Response::Busy),
+        Err(e) => e.is_transient(),
+      };
+      if !retry || retries == 0 {
+        return result;
+      }
+      retries -= 1;
+      tokio::time::sleep(delay).await;
+      delay *= 2;
+    }
+  }
+
+  pub async fn read_scores(
+    &self,
+    context: &str,
+    ego: &str,
+    options: FilterOptions,
+  ) -> Result<Vec<ScoreResult>, ClientError> {
+    let data = ReqData::ReadScores(OpReadScores {
+      ego:           ego.into(),
+      score_options: options,
+    });
+    match self.call(context, data).await? {
+      Response::Scores(res) => Ok(res.scores),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  /// Scores of several egos, in the order of `egos`.
+  pub async fn read_scores_bulk(
+    &self,
+    context: &str,
+    egos: &[&str],
+    options: FilterOptions,
+  ) -> Result<Vec<(NodeName, Vec<ScoreResult>)>, ClientError> {
+    let data = ReqData::ReadScoresBulk(OpReadScoresBulk {
+      egos:          egos.iter().map(|&ego| ego.into()).collect(),
+      score_options: options,
+      compact:       false,
+    });
+    match self.call(context, data).await? {
+      Response::ScoresBulk(res) => Ok(res.scores),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  /// Same as `read_scores_bulk`, with targets given by id and resolved once
+  /// in a side table; cheaper for large pages.
+  pub async fn read_scores_compact(
+    &self,
+    context: &str,
+    egos: &[&str],
+    options: FilterOptions,
+  ) -> Result<ResScoresCompact, ClientError> {
+    let data = ReqData::ReadScoresBulk(OpReadScoresBulk {
+      egos:          egos.iter().map(|&ego| ego.into()).collect(),
+      score_options: options,
+      compact:       true,
+    });
+    match self.call(context, data).await? {
+      Response::ScoresCompact(res) => Ok(res),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  /// Score of the target by the ego, if the ego reaches it.
+  pub async fn read_node_score(
+    &self,
+    context: &str,
+    ego: &str,
+    target: &str,
+  ) -> Result<Option<ScoreResult>, ClientError> {
+    let data = ReqData::ReadNodeScore(OpReadNodeScore {
+      ego:    ego.into(),
+      target: target.into(),
+    });
+    match self.call(context, data).await? {
+      Response::Scores(res) => Ok(res.scores.into_iter().next()),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  pub async fn read_graph(
+    &self,
+    context: &str,
+    data: OpReadGraph,
+  ) -> Result<Vec<GraphResult>, ClientError> {
+    match self.call(context, ReqData::ReadGraph(data)).await? {
+      Response::Graph(res) => Ok(res.graph),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  pub async fn read_neighbors(
+    &self,
+    context: &str,
+    data: OpReadNeighbors,
+  ) -> Result<Vec<ScoreResult>, ClientError> {
+    match self.call(context, ReqData::ReadNeighbors(data)).await? {
+      Response::Scores(res) => Ok(res.scores),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  pub async fn read_edges(
+    &self,
+    context: &str,
+  ) -> Result<Vec<EdgeResult>, ClientError> {
+    match self.call(context, ReqData::ReadEdges).await? {
+      Response::Edges(res) => Ok(res.edges),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  pub async fn read_contexts(&self) -> Result<Vec<ContextInfo>, ClientError> {
+    match self.call("", ReqData::ReadContexts).await? {
+      Response::Contexts(res) => Ok(res.contexts),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  /// Edges, zero opinions and score clusters of the context.
+  pub async fn read_context_snapshot(
+    &self,
+    context: &str,
+  ) -> Result<ContextSnapshot, ClientError> {
+    match self.call(context, ReqData::ReadContextSnapshot).await? {
+      Response::ContextSnapshot(snapshot) => Ok(snapshot),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  pub async fn get_stats(&self) -> Result<ResStats, ClientError> {
+    match self.call("", ReqData::GetStats).await? {
+      Response::Stats(stats) => Ok(stats),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  /// Sets the weight of the edge; 0 deletes it.
+  pub async fn write_edge(
+    &self,
+    context: &str,
+    src: &str,
+    dst: &str,
+    amount: Weight,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteEdge(OpWriteEdge {
+      src: src.into(),
+      dst: dst.into(),
+      amount,
+      magnitude: 0,
+      edge_kind: EdgeKind::Vote,
+      provenance: None,
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  /// Same as `write_edge`, recording the event the edge was written for. It
+  /// is returned with the edge by `read_neighbors`.
+  pub async fn write_edge_with_provenance(
+    &self,
+    context: &str,
+    src: &str,
+    dst: &str,
+    amount: Weight,
+    provenance: EdgeProvenance,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteEdge(OpWriteEdge {
+      src: src.into(),
+      dst: dst.into(),
+      amount,
+      magnitude: 0,
+      edge_kind: EdgeKind::Vote,
+      provenance: Some(provenance),
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  /// Sets the edge to `new_weight` if its weight is `expected_old_weight`,
+  /// failing with `ServiceError::WriteConflict` otherwise.
+  pub async fn write_edge_cas(
+    &self,
+    context: &str,
+    src: &str,
+    dst: &str,
+    expected_old_weight: Weight,
+    new_weight: Weight,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteEdgeCas(OpWriteEdgeCas {
+      src: src.into(),
+      dst: dst.into(),
+      expected_old_weight,
+      new_weight,
+      edge_kind: EdgeKind::Vote,
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  /// Writes the edges, as `(context, src, dst, weight)`, together: if the
+  /// service rejects one, none is written, see `WriteMulti`.
+  pub async fn write_edges_multi(
+    &self,
+    edges: &[(&str, &str, &str, Weight)],
+  ) -> Result<(), ClientError> {
+    let ops = edges
+      .iter()
+      .map(|&(context, src, dst, amount)| {
+        let op = ReqData::WriteEdge(OpWriteEdge {
+          src: src.into(),
+          dst: dst.into(),
+          amount,
+          magnitude: 0,
+          edge_kind: EdgeKind::Vote,
+          provenance: None,
+        });
+        (context.to_string(), op)
+      })
+      .collect();
+    let data = ReqData::WriteMulti(OpWriteMulti { ops });
+    expect_ok(self.call("", data).await?)
+  }
+
+  pub async fn delete_edge(
+    &self,
+    context: &str,
+    src: &str,
+    dst: &str,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteDeleteEdge(OpWriteDeleteEdge {
+      src:   src.into(),
+      dst:   dst.into(),
+      index: 0,
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  pub async fn delete_node(
+    &self,
+    context: &str,
+    node: &str,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteDeleteNode(OpWriteDeleteNode {
+      node:  node.into(),
+      index: 0,
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  pub async fn write_zero_opinion(
+    &self,
+    context: &str,
+    node: &str,
+    score: Weight,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteZeroOpinion(OpWriteZeroOpinion {
+      node: node.into(),
+      score,
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  /// Creates the node with the kind ahead of its edges, or sets its flags,
+  /// see `WriteNode`.
+  pub async fn write_node(
+    &self,
+    context: &str,
+    name: &str,
+    kind: NodeKind,
+    flags: u32,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteNode(OpWriteNode {
+      name: name.into(),
+      kind,
+      flags,
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  /// Registers a webhook that score changes are POSTed to, replacing the one
+  /// with the same URL, see `WriteWebhook`.
+  pub async fn write_webhook(
+    &self,
+    webhook: OpWriteWebhook,
+  ) -> Result<(), ClientError> {
+    expect_ok(self.call("", ReqData::WriteWebhook(webhook)).await?)
+  }
+
+  pub async fn delete_webhook(
+    &self,
+    url: &str,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::DeleteWebhook(OpDeleteWebhook {
+      url: url.into(),
+    });
+    expect_ok(self.call("", data).await?)
+  }
+
+  pub async fn read_webhooks(
+    &self
+  ) -> Result<Vec<OpWriteWebhook>, ClientError> {
+    match self.call("", ReqData::ReadWebhooks).await? {
+      Response::Webhooks(res) => Ok(res.webhooks),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  /// Overrides the walk settings of the ego's own scores, see
+  /// `SetUserParams`.
+  pub async fn set_user_params(
+    &self,
+    context: &str,
+    ego: &str,
+    alpha: Option<Weight>,
+    walk_policy: WalkPolicy,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::SetUserParams(OpSetUserParams {
+      ego: ego.into(),
+      alpha,
+      walk_policy,
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  /// Hides the node from the ego's scores, or shows it again, see
+  /// `WriteMute`.
+  pub async fn mute(
+    &self,
+    context: &str,
+    ego: &str,
+    node: &str,
+    muted: bool,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteMute(OpWriteMute {
+      ego: ego.into(),
+      node: node.into(),
+      muted,
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  /// Sets the unix time the poll expires at, 0 for never, and whether its
+  /// votes are anonymous, see `WritePoll`.
+  pub async fn write_poll(
+    &self,
+    context: &str,
+    poll: &str,
+    expires_at: u64,
+    anonymous: bool,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WritePoll(OpWritePoll {
+      poll: poll.into(),
+      expires_at,
+      anonymous,
+    });
+    expect_ok(self.call(context, data).await?)
+  }
+
+  pub async fn read_mutes(
+    &self,
+    context: &str,
+    ego: &str,
+  ) -> Result<Vec<NodeName>, ClientError> {
+    let data = ReqData::ReadMutes(OpReadMutes { ego: ego.into() });
+    match self.call(context, data).await? {
+      Response::NodeList(res) => {
+        Ok(res.nodes.into_iter().map(|(name,)| name).collect())
+      },
+      response => Err(unexpected(response)),
+    }
+  }
+
+  /// Replaces all contexts with the edges, see `WriteBulkEdges`.
+  pub async fn bulk_load_edges(
+    &self,
+    edges: Vec<BulkEdge>,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteBulkEdges(OpWriteBulkEdges { edges });
+    expect_ok(self.call("", data).await?)
+  }
+
+  pub async fn create_context(
+    &self,
+    context: &str,
+  ) -> Result<(), ClientError> {
+    expect_ok(self.call(context, ReqData::WriteCreateContext).await?)
+  }
+
+  pub async fn delete_context(
+    &self,
+    context: &str,
+  ) -> Result<(), ClientError> {
+    expect_ok(self.call(context, ReqData::WriteDeleteContext).await?)
+  }
+
+  pub async fn fork_context(
+    &self,
+    source: &str,
+    destination: &str,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::WriteForkContext(OpWriteForkContext {
+      source:      source.into(),
+      destination: destination.into(),
+    });
+    expect_ok(self.call("", data).await?)
+  }
+
+  /// The latest `limit` slow requests, newest first; all for 0. See
+  /// `ReadSlowLog`.
+  pub async fn read_slow_log(
+    &self,
+    limit: u32,
+  ) -> Result<Vec<SlowOp>, ClientError> {
+    let data = ReqData::ReadSlowLog(OpReadSlowLog { limit });
+    match self.call("", data).await? {
+      Response::SlowLog(res) => Ok(res.ops),
+      response => Err(unexpected(response)),
+    }
+  }
+
+  /// Makes the service reject writes until it is called again with
+  /// `read_only` false, see `SetMaintenanceMode`.
+  pub async fn set_maintenance_mode(
+    &self,
+    read_only: bool,
+  ) -> Result<(), ClientError> {
+    let data = ReqData::SetMaintenanceMode(OpSetMaintenanceMode {
+      read_only,
+    });
+    expect_ok(self.call("", data).await?)
+  }
+
+  /// Waits until the writes sent before are applied.
+  pub async fn sync(&self) -> Result<(), ClientError> {
+    let stamp = self.sync_stamp.fetch_add(1, Ordering::SeqCst) + 1;
+    expect_ok(self.call("", ReqData::Sync(stamp)).await?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use meritrank_service::request_handler::run_server;
+  use meritrank_service::settings::Settings;
+  use meritrank_service::state_manager::MultiGraphProcessor;
+  use tokio_util::sync::CancellationToken;
+
+  use std::sync::Arc;
+
+  fn spawn_server(port: u16) -> CancellationToken {
+    let running = CancellationToken::new();
+    let settings = Settings {
+      server_port: port,
+      compression_threshold: 0,
+      ..Settings::default()
+    };
+    let processor = Arc::new(MultiGraphProcessor::new(settings.clone()));
+    let running_cloned = running.clone();
+    tokio::spawn(async move {
+      run_server(settings, processor, running_cloned).await.unwrap();
+    });
+    running
+  }
+
+  #[tokio::test]
+  async fn writes_and_reads_scores() {
+    let running = spawn_server(8091);
+    let client = Client::with_config(ClientConfig {
+      address: "127.0.0.1:8091".into(),
+      compression: true,
+      ..ClientConfig::default()
+    });
+
+    //  Retries until the server is up.
+    client.write_edge("", "U1", "U2", 1.0).await.unwrap();
+    client.write_edge("", "U2", "U3", 1.0).await.unwrap();
+    client.sync().await.unwrap();
+
+    let scores = client
+      .read_scores("", "U1", FilterOptions::default())
+      .await
+      .unwrap();
+    assert!(scores.iter().any(|score| score.target == "U3"));
+    assert_eq!(client.read_edges("").await.unwrap().len(), 2);
+    assert_eq!(client.idle.lock().len(), 1);
+
+    let error = client.delete_context("").await.unwrap_err();
+    assert!(matches!(error, ClientError::Fail));
+
+    running.cancel();
+  }
+
+  #[tokio::test]
+  async fn connects_over_tls() {
+    let cert = |name: &str| {
+      format!("{}/../service/tests/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    };
+    let running = CancellationToken::new();
+    let settings = Settings {
+      server_port: 8096,
+      tls_cert: cert("server.pem"),
+      tls_key: cert("server.key"),
+      tls_client_ca: cert("ca.pem"),
+      ..Settings::default()
+    };
+    let processor = Arc::new(MultiGraphProcessor::new(settings.clone()));
+    let running_cloned = running.clone();
+    tokio::spawn(async move {
+      run_server(settings, processor, running_cloned).await.unwrap();
+    });
+
+    let client = Client::with_config(ClientConfig {
+      address: "localhost:8096".into(),
+      tls_ca: cert("ca.pem"),
+      tls_cert: cert("client.pem"),
+      tls_key: cert("client.key"),
+      ..ClientConfig::default()
+    });
+    client.write_edge("", "U1", "U2", 1.0).await.unwrap();
+    client.sync().await.unwrap();
+    assert_eq!(client.read_edges("").await.unwrap().len(), 1);
+
+    //  Without a client certificate the handshake fails.
+    let client = Client::with_config(ClientConfig {
+      address: "localhost:8096".into(),
+      retries: 0,
+      tls_ca: cert("ca.pem"),
+      ..ClientConfig::default()
+    });
+    assert!(client.read_edges("").await.is_err());
+
+    running.cancel();
+  }
+
+  #[tokio::test]
+  async fn gives_up_after_retries() {
+    let client = Client::with_config(ClientConfig {
+      address: "127.0.0.1:1".into(),
+      retries: 2,
+      retry_delay: Duration::from_millis(1),
+      ..ClientConfig::default()
+    });
+    let error = client.read_edges("").await.unwrap_err();
+    assert!(matches!(error, ClientError::Io(_)));
+  }
+}
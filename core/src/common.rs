@@ -11,5 +11,5 @@ where
   } else if x < -EPSILON {
     return -1;
   }
-  return 0;
+  0
 }
@@ -6,3 +6,6 @@ pub const ASSERT: bool =
 pub const VERBOSE: bool = true;
 pub const OPTIMIZE_INVALIDATION: bool = true;
 pub const EPSILON: f64 = 1e-6;
+/// Number of walks `calculate_until` and `calculate_partial` run between
+/// checks of the deadline.
+pub const CALCULATE_BATCH_SIZE: usize = 256;
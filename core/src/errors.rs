@@ -13,6 +13,8 @@ pub mod internal_fatal {
     "rank::set_edge_ walk first_node None";
   pub const RANK_SET_EDGE_GET_WALK_MUT: &str =
     "rank::set_edge_ get_walk_mut None";
+  pub const RANK_CONTRIBUTIONS_GET_WALK: &str =
+    "rank::get_score_contributions get_walk None";
   pub const RANK_ASSERT_GET_VISITS: &str =
     "rank::assert_counters get_visits_through_node None";
   pub const RANK_ASSERT_POS_HITS_COUNT: &str =
@@ -78,6 +80,10 @@ pub enum MeritRankError {
   NodeNotFound,
   WalkNotFound,
   EdgeNotFound,
+  /// The calculation did not finish before its deadline.
+  Timeout,
+  /// A setting of `MeritRankBuilder` is out of range.
+  InvalidParameter(&'static str),
   InternalFatalError(Option<&'static str>),
 }
 
@@ -124,6 +130,12 @@ impl Display for MeritRankError {
       MeritRankError::EdgeNotFound => {
         write!(f, "Can't find the edge between given nodes")
       },
+      MeritRankError::Timeout => {
+        write!(f, "Calculation timed out")
+      },
+      MeritRankError::InvalidParameter(name) => {
+        write!(f, "Invalid parameter: {}", name)
+      },
       MeritRankError::InternalFatalError(None) => {
         write!(f, "Internal fatal error")
       },
@@ -7,7 +7,7 @@ use crate::RandomWalk;
 use log::error;
 use rand::distr::weighted::WeightedIndex;
 use rand::distr::Distribution;
-use rand::{rng, Rng};
+use rand::Rng;
 
 type IntIndexMap<K, V> = IndexMap<K, V, BuildIntHasher<K>>;
 
@@ -26,6 +26,8 @@ pub struct NodeData {
   // so it is efficient to cache it.
   pub pos_sum:     Weight,
   pub neg_sum:     Weight,
+  /// Application-defined bits, e.g. NSFW; ignored by the ranking.
+  pub flags:       u32,
   abs_distr_cache: Option<WeightedIndex<Weight>>,
   pos_distr_cache: Option<WeightedIndex<Weight>>,
 }
@@ -60,9 +62,10 @@ impl NodeData {
   }
 
   // Return a random neighbor and whether it's from positive or negative edges
-  pub fn random_neighbor(
+  pub fn random_neighbor<R: Rng + ?Sized>(
     &mut self,
     positive_only: bool,
+    rng: &mut R,
   ) -> Result<Option<(NodeId, bool)>, MeritRankError> {
     if positive_only {
       if self.pos_edges.is_empty() {
@@ -88,7 +91,7 @@ impl NodeData {
           internal_fatal::GRAPH_NODEDATA_POS_DISTR_CACHE,
         ))),
       };
-      let index = cache.sample(&mut rng());
+      let index = cache.sample(rng);
       let node_id = match self.pos_edges.keys().nth(index) {
         Some(x) => *x,
         None => return Err(MeritRankError::InternalFatalError(Some(
@@ -107,7 +110,7 @@ impl NodeData {
           .pos_edges
           .values()
           .chain(self.neg_edges.values())
-          .map(|&w| w)
+          .copied()
           .collect();
 
         let wi = match WeightedIndex::new(combined_weights) {
@@ -126,7 +129,7 @@ impl NodeData {
           internal_fatal::GRAPH_NODEDATA_ABS_DISTR_CACHE,
         ))),
       };
-      let index = cache.sample(&mut rng());
+      let index = cache.sample(rng);
       self.get_node_at_index(index)
     }
   }
@@ -160,17 +163,34 @@ impl NodeData {
   pub fn abs_sum(&self) -> Weight {
     self.pos_sum + self.neg_sum
   }
+
+  /// Number of outgoing edges, positive and negative.
+  pub fn out_degree(&self) -> usize {
+    self.pos_edges.len() + self.neg_edges.len()
+  }
+
+  pub fn in_degree(&self) -> usize {
+    self.inbound_edges.len()
+  }
 }
 
 #[derive(Debug, Clone)]
 pub struct Graph {
   pub nodes: Vec<NodeData>,
+  num_edges: usize,
+}
+
+impl Default for Graph {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl Graph {
   pub fn new() -> Self {
     Graph {
-      nodes: Vec::new(),
+      nodes:     Vec::new(),
+      num_edges: 0,
     }
   }
   pub fn get_new_nodeid(&mut self) -> NodeId {
@@ -178,6 +198,24 @@ impl Graph {
     self.nodes.len() - 1
   }
 
+  /// Ids of all nodes, `0..node_count`. Ids are never reused: a node whose
+  /// data was reset, e.g. one tombstoned by the service's garbage collection,
+  /// keeps its id and is included, without edges.
+  pub fn nodes(&self) -> impl Iterator<Item = NodeId> {
+    0..self.nodes.len()
+  }
+
+  /// Number of node ids, tombstoned nodes included, see `nodes`.
+  pub fn node_count(&self) -> usize {
+    self.nodes.len()
+  }
+
+  /// Number of edges, positive and negative, kept up to date by `set_edge`
+  /// and `remove_edge`.
+  pub fn edge_count(&self) -> usize {
+    self.num_edges
+  }
+
   /// Checks if a node with the given `NodeId` exists in the graph.
   pub fn contains_node(
     &self,
@@ -200,12 +238,12 @@ impl Graph {
       error!("Trying to add self-reference edge to node {}", from);
       return Err(MeritRankError::SelfReferenceNotAllowed);
     }
-    if self.edge_weight(from, to)?.is_some() {
-      if self.remove_edge(from, to).is_err() {
-        return Err(MeritRankError::InternalFatalError(Some(
-          internal_fatal::GRAPH_SET_EDGE_REMOVE_FAILED,
-        )));
-      }
+    if self.edge_weight(from, to)?.is_some()
+      && self.remove_edge(from, to).is_err()
+    {
+      return Err(MeritRankError::InternalFatalError(Some(
+        internal_fatal::GRAPH_SET_EDGE_REMOVE_FAILED,
+      )));
     }
     if weight.is_nan() {
       error!("Trying to set NaN weight for edge from {} to {}", from, to);
@@ -242,6 +280,7 @@ impl Graph {
 
     // Update inbound edge cache for the target node
     self.nodes[to].inbound_edges.insert(from, weight);
+    self.num_edges += 1;
 
     Ok(())
   }
@@ -284,6 +323,9 @@ impl Graph {
 
     // Both pos and neg weights should never be present at the same time.
     assert!(!(pos_weight.is_some() && neg_weight.is_some()));
+    if pos_weight.is_some() || neg_weight.is_some() {
+      self.num_edges -= 1;
+    }
     node.abs_distr_cache = None;
     if pos_weight.is_some() {
       node.pos_distr_cache = None;
@@ -311,6 +353,66 @@ impl Graph {
     })
   }
 
+  /// Removes every edge whose weight is below `epsilon` in magnitude, e.g.
+  /// edges that decayed with `scale_edges`, and returns them. Walks taken on
+  /// the graph are not updated, see `MeritRank::prune_edges_below`.
+  pub fn prune_below(
+    &mut self,
+    epsilon: Weight,
+  ) -> Result<Vec<EdgeId>, MeritRankError> {
+    let pruned: Vec<EdgeId> = self
+      .nodes
+      .iter()
+      .enumerate()
+      .flat_map(|(src, data)| {
+        data
+          .pos_edges
+          .iter()
+          .chain(data.neg_edges.iter())
+          .filter(|(_, weight)| **weight < epsilon)
+          .map(move |(dst, _)| (src, *dst))
+      })
+      .collect();
+    for &(src, dst) in &pruned {
+      self.remove_edge(src, dst)?;
+    }
+    Ok(pruned)
+  }
+
+  /// Multiplies weights of all edges by `factor`, e.g. to decay old edges
+  /// relative to new ones. Walks choose edges in proportion to their weights,
+  /// so existing walks stay valid.
+  pub fn scale_edges(
+    &mut self,
+    factor: Weight,
+  ) -> Result<(), MeritRankError> {
+    if factor.is_nan() {
+      return Err(MeritRankError::NaNWeightEncountered);
+    }
+    if factor.is_infinite() {
+      return Err(MeritRankError::InfWeightEncountered);
+    }
+    if factor <= 0.0 {
+      return Err(MeritRankError::ZeroWeightEncountered);
+    }
+
+    for node in self.nodes.iter_mut() {
+      for weight in node
+        .pos_edges
+        .values_mut()
+        .chain(node.neg_edges.values_mut())
+        .chain(node.inbound_edges.values_mut())
+      {
+        *weight *= factor;
+      }
+      node.pos_sum *= factor;
+      node.neg_sum *= factor;
+      node.abs_distr_cache = None;
+      node.pos_distr_cache = None;
+    }
+    Ok(())
+  }
+
   pub fn edge_weight(
     &self,
     from: NodeId,
@@ -320,24 +422,36 @@ impl Graph {
     if !self.contains_node(to) {
       return Err(MeritRankError::NodeNotFound);
     }
-    Ok(if let Some(weight) = node.pos_edges.get(&to) {
-      Some(*weight)
-    } else if let Some(weight) = node.neg_edges.get(&to) {
-      Some(-*weight)
-    } else {
-      None
+    Ok(match node.pos_edges.get(&to) {
+      Some(weight) => Some(*weight),
+      None => node.neg_edges.get(&to).map(|weight| -*weight),
     })
   }
 
-  pub fn generate_walk_segment(
+  pub fn generate_walk_segment<R: Rng + ?Sized>(
     &mut self,
     start_node: NodeId,
     alpha: f64,
     positive_only: bool,
+    rng: &mut R,
   ) -> Result<RandomWalk, MeritRankError> {
-    let mut node = start_node;
     let mut segment = RandomWalk::new();
-    let mut rng = rng();
+    self.extend_walk_from(&mut segment, start_node, alpha, positive_only, rng)?;
+    Ok(segment)
+  }
+
+  /// Pushes the steps of a new segment starting at `start_node` directly
+  /// onto `walk`, so that long walks are extended without building and
+  /// copying a segment. The distributions of the nodes passed are cached.
+  fn extend_walk_from<R: Rng + ?Sized>(
+    &mut self,
+    walk: &mut RandomWalk,
+    start_node: NodeId,
+    alpha: f64,
+    positive_only: bool,
+    rng: &mut R,
+  ) -> Result<(), MeritRankError> {
+    let mut node = start_node;
 
     let mut negative_continuation_mode = false;
     // When this variable becomes true, it means that a walk has encountered a negative edge,
@@ -359,13 +473,10 @@ impl Graph {
       if rng.random::<f64>() > alpha {
         break;
       }
-      if let Some((next_step, step_is_positive)) = match node_data
-        .random_neighbor(negative_continuation_mode || positive_only)
+      if let Some((next_step, step_is_positive)) = node_data
+        .random_neighbor(negative_continuation_mode || positive_only, rng)?
       {
-        Ok(x) => x,
-        Err(e) => return Err(e),
-      } {
-        segment.push(next_step, step_is_positive)?;
+        walk.push(next_step, step_is_positive)?;
         if !step_is_positive {
           assert!(!negative_continuation_mode);
           negative_continuation_mode = true;
@@ -376,32 +487,31 @@ impl Graph {
         break;
       }
     }
-    Ok(segment)
+    Ok(())
   }
 
-  pub fn continue_walk(
+  pub fn continue_walk<R: Rng + ?Sized>(
     &mut self,
     walk: &mut RandomWalk,
     alpha: f64,
+    rng: &mut R,
   ) -> Result<(), MeritRankError> {
     // If the original walk is already in "negative mode",
     // we should restrict segment generation to positive edges
-    let positive_only = walk.negative_segment_start.is_some();
+    let positive_only =
+      walk.positive_only || walk.negative_segment_start.is_some();
     let start_node = match walk.last_node() {
       Some(x) => x,
       None => return Err(MeritRankError::InternalFatalError(Some(
         internal_fatal::GRAPH_CONTINUE_WALK_LAST_NODE,
       ))),
     };
-    let new_segment =
-      self.generate_walk_segment(start_node, alpha, positive_only)?;
-
-    // Borrow mutable `walk` again for `extend`
-    walk.extend(&new_segment)
+    self.extend_walk_from(walk, start_node, alpha, positive_only, rng)
   }
-  pub fn extend_walk_in_case_of_edge_deletion(
+  pub fn extend_walk_in_case_of_edge_deletion<R: Rng + ?Sized>(
     &mut self,
     walk: &mut RandomWalk,
+    rng: &mut R,
   ) -> Result<(), MeritRankError> {
     // Borrow mutable `walk` from `self.walks`
     // No force_first_step, so this is "edge deletion mode"
@@ -415,9 +525,10 @@ impl Graph {
     let src_node = walk.last_node().unwrap();
     let node_data = self.get_node_data_mut(src_node).unwrap();
     let adding_to_negative_subsegment = walk.negative_segment_start.is_some();
-    if let Some((forced_step, step_is_positive)) =
-      node_data.random_neighbor(adding_to_negative_subsegment)?
-    {
+    if let Some((forced_step, step_is_positive)) = node_data.random_neighbor(
+      adding_to_negative_subsegment || walk.positive_only,
+      rng,
+    )? {
       walk.push(forced_step, step_is_positive)?;
     }
     Ok(())
@@ -13,5 +13,11 @@ pub use errors::MeritRankError;
 pub use graph::{EdgeId, Graph, NodeId, Weight};
 pub use integer_hasher::IntMap;
 pub use random_walk::RandomWalk;
-pub use rank::MeritRank;
+pub use rank::{
+  EgoParams,
+  MeritRank,
+  MeritRankBuilder,
+  PartialScores,
+  ScoreParts,
+};
 pub use walk_storage::{WalkId, WalkStorage};
@@ -8,6 +8,14 @@ use tinyset::SetUsize;
 pub struct RandomWalk {
   pub nodes:                  Vec<NodeId>,
   pub negative_segment_start: Option<usize>,
+  /// The walk does not follow negative edges, see `EgoParams`.
+  pub positive_only:          bool,
+}
+
+impl Default for RandomWalk {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl RandomWalk {
@@ -15,6 +23,7 @@ impl RandomWalk {
     RandomWalk {
       nodes:                  Vec::new(),
       negative_segment_start: None,
+      positive_only:          false,
     }
   }
 
@@ -22,6 +31,7 @@ impl RandomWalk {
     RandomWalk {
       nodes,
       negative_segment_start: None,
+      positive_only: false,
     }
   }
 
@@ -101,7 +111,7 @@ impl RandomWalk {
 
     // Update `negative_segment_start` based on `step_is_positive`
     if !step_is_positive {
-      if !self.negative_segment_start.is_none() {
+      if self.negative_segment_start.is_some() {
         return Err(MeritRankError::InternalFatalError(Some(
           internal_fatal::RANDOM_WALK_PUSH_NEG_SEGMENT,
         )));
@@ -149,6 +159,18 @@ impl RandomWalk {
     Ok(())
   }
 
+  /// Drops the nodes from `len` on, like `split_from` without building the
+  /// dropped segment.
+  pub fn truncate(
+    &mut self,
+    len: usize,
+  ) {
+    if self.negative_segment_start.is_some_and(|start| start >= len) {
+      self.negative_segment_start = None;
+    }
+    self.nodes.truncate(len);
+  }
+
   pub fn split_from(
     &mut self,
     at: usize,
@@ -165,6 +187,7 @@ impl RandomWalk {
     RandomWalk {
       nodes:                  split_segment,
       negative_segment_start: new_segment_neg_start,
+      positive_only:          self.positive_only,
     }
   }
 }
@@ -1,23 +1,156 @@
 use integer_hasher::IntMap;
-use rand::random;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::constants::{ASSERT, EPSILON, OPTIMIZE_INVALIDATION};
+use std::time::Instant;
+
+use crate::constants::{
+  ASSERT,
+  CALCULATE_BATCH_SIZE,
+  EPSILON,
+  OPTIMIZE_INVALIDATION,
+};
 use crate::counter::Counter;
 use crate::errors::internal_fatal;
 use crate::errors::MeritRankError;
 use crate::graph::{Graph, NodeId, Weight};
-use crate::walk_storage::WalkStorage;
+use crate::random_walk::RandomWalk;
+use crate::walk_storage::{WalkId, WalkStorage};
+
+/// Walk settings of an ego that override the defaults, see `set_ego_params`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EgoParams {
+  /// Probability of the ego's walks continuing at each step.
+  pub alpha:         Weight,
+  /// The ego's walks do not follow negative edges, so it sees no distrust.
+  pub positive_only: bool,
+}
+
+/// Score of a target split into the shares of the ego's hits that visited it
+/// on positive and on negative walk segments.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ScoreParts {
+  pub positive: Weight,
+  pub negative: Weight,
+}
+
+impl ScoreParts {
+  /// The score, penalized by the negative part.
+  pub fn penalized(&self) -> Weight {
+    self.positive - self.negative
+  }
+
+  /// The score, penalized by the negative part times `scale`, and raised to
+  /// zero if `clamp` is set.
+  pub fn penalized_with(&self, scale: Weight, clamp: bool) -> Weight {
+    let score = self.positive - scale * self.negative;
+    if clamp {
+      score.max(0.0)
+    } else {
+      score
+    }
+  }
+}
+
+/// Scores of an ego from part of its walks, see `calculate_partial`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PartialScores {
+  pub scores: Vec<(NodeId, Weight)>,
+  /// Number of walks the scores are taken from.
+  pub walks:  usize,
+}
 
 #[derive(Clone)]
 pub struct MeritRank {
-  pub graph: Graph,
-  walks:     WalkStorage,
-  pos_hits:  IntMap<NodeId, Counter>,
-  neg_hits:  IntMap<NodeId, Counter>,
-  pub alpha: Weight,
+  pub graph:         Graph,
+  walks:             WalkStorage,
+  pos_hits:          IntMap<NodeId, Counter>,
+  neg_hits:          IntMap<NodeId, Counter>,
+  pub alpha:         Weight,
+  /// Multiplier of the negative part of scores, see `ScoreParts`. Values
+  /// below 1 keep distrust from swamping the positive hits of a target.
+  pub penalty_scale: Weight,
+  /// Scores are raised to zero, so that penalties only cancel trust.
+  pub clamp_scores:  bool,
+  ego_params:        IntMap<NodeId, EgoParams>,
+  /// Source of all randomness of the walks, see `seed_rng`.
+  rng:               StdRng,
+}
+
+/// Settings of a new `MeritRank`, checked by `build`. Settings that are not
+/// set keep the defaults of `MeritRank::new`.
+#[derive(Clone, Debug)]
+pub struct MeritRankBuilder {
+  walks_per_ego: usize,
+  alpha:         Weight,
+  penalty_scale: Weight,
+  clamp_scores:  bool,
+  seed:          Option<u64>,
+}
+
+impl MeritRankBuilder {
+  pub fn new(walks_per_ego: usize) -> Self {
+    Self {
+      walks_per_ego,
+      alpha: 0.85,
+      penalty_scale: 1.0,
+      clamp_scores: false,
+      seed: None,
+    }
+  }
+
+  /// Probability of walks continuing at each step, in [0, 1].
+  pub fn alpha(mut self, alpha: Weight) -> Self {
+    self.alpha = alpha;
+    self
+  }
+
+  /// See `MeritRank::penalty_scale`; finite and not negative.
+  pub fn penalty_scale(mut self, scale: Weight) -> Self {
+    self.penalty_scale = scale;
+    self
+  }
+
+  /// See `MeritRank::clamp_scores`.
+  pub fn clamp_scores(mut self, clamp: bool) -> Self {
+    self.clamp_scores = clamp;
+    self
+  }
+
+  /// See `MeritRank::seed_rng`.
+  pub fn seed(mut self, seed: u64) -> Self {
+    self.seed = Some(seed);
+    self
+  }
+
+  pub fn build(self, graph: Graph) -> Result<MeritRank, MeritRankError> {
+    if self.walks_per_ego == 0 {
+      return Err(MeritRankError::InvalidParameter("walks_per_ego"));
+    }
+    if !(0.0..=1.0).contains(&self.alpha) {
+      return Err(MeritRankError::InvalidParameter("alpha"));
+    }
+    if !self.penalty_scale.is_finite() || self.penalty_scale < 0.0 {
+      return Err(MeritRankError::InvalidParameter("penalty_scale"));
+    }
+    let mut rank = MeritRank::new(graph, self.walks_per_ego);
+    rank.alpha = self.alpha;
+    rank.penalty_scale = self.penalty_scale;
+    rank.clamp_scores = self.clamp_scores;
+    if let Some(seed) = self.seed {
+      rank.seed_rng(seed);
+    }
+    Ok(rank)
+  }
 }
 
 impl MeritRank {
+  /// Shorthand for `MeritRankBuilder::new`.
+  pub fn builder(walks_per_ego: usize) -> MeritRankBuilder {
+    MeritRankBuilder::new(walks_per_ego)
+  }
+
+  /// A `MeritRank` with the default settings, see `MeritRankBuilder`.
   pub fn new(graph: Graph, walks_per_ego: usize) -> Self {
     Self {
       graph,
@@ -25,11 +158,127 @@ impl MeritRank {
       pos_hits: IntMap::default(),
       neg_hits: IntMap::default(),
       alpha: 0.85,
+      penalty_scale: 1.0,
+      clamp_scores: false,
+      ego_params: IntMap::default(),
+      rng: StdRng::from_os_rng(),
+    }
+  }
+
+  /// Makes the walks reproducible: the same sequence of calls on graphs
+  /// seeded alike gives the same walks and scores.
+  pub fn seed_rng(&mut self, seed: u64) {
+    self.rng = StdRng::seed_from_u64(seed);
+  }
+
+  /// Overrides the walk settings of the ego, or restores the defaults with
+  /// None. Existing walks of the ego are kept until its next `calculate`.
+  pub fn set_ego_params(&mut self, ego: NodeId, params: Option<EgoParams>) {
+    match params {
+      Some(params) => self.ego_params.insert(ego, params),
+      None => self.ego_params.remove(&ego),
+    };
+  }
+
+  pub fn get_ego_params(&self, ego: NodeId) -> EgoParams {
+    self.ego_params.get(&ego).copied().unwrap_or(EgoParams {
+      alpha:         self.alpha,
+      positive_only: false,
+    })
+  }
+
+  /// Egos with overridden walk settings.
+  pub fn get_custom_ego_params(&self) -> &IntMap<NodeId, EgoParams> {
+    &self.ego_params
+  }
+
+  /// Drops the ego's walks and counters without running new walks, and frees
+  /// the walks' memory and block, see `WalkStorage::drop_walks_and_visits`.
+  /// Used when evicting an ego from cache; the ego's scores are unavailable
+  /// until the next `calculate`. Returns false if the ego had no walks.
+  pub fn drop_walks(&mut self, ego: NodeId) -> Result<bool, MeritRankError> {
+    let had_walks = self.pos_hits.contains_key(&ego);
+    self.walks.drop_walks_and_visits(
+      ego,
+      &mut self.pos_hits,
+      &mut self.neg_hits,
+    )?;
+    self.pos_hits.remove(&ego);
+    self.neg_hits.remove(&ego);
+    Ok(had_walks)
+  }
+
+  pub fn calculate(&mut self, ego: NodeId) -> Result<(), MeritRankError> {
+    self.calculate_until(ego, None)
+  }
+
+  /// Same as `calculate`, but checks the deadline every `CALCULATE_BATCH_SIZE`
+  /// walks. If it has passed, the walks done so far are dropped, so the ego
+  /// is left uncalculated, and `Timeout` is returned.
+  pub fn calculate_until(
+    &mut self,
+    ego: NodeId,
+    deadline: Option<Instant>,
+  ) -> Result<(), MeritRankError> {
+    if self.run_walks(ego, deadline)?.is_some() {
+      self.drop_walks(ego)?;
+      return Err(MeritRankError::Timeout);
     }
+    Ok(())
+  }
+
+  /// Same as `calculate_until`, but if the deadline passes, returns the
+  /// scores of the walks done so far before dropping them. Scores are shares
+  /// of the hits, so they approximate the full scores, more closely the more
+  /// walks are done. Returns None if all the walks are done in time.
+  pub fn calculate_partial(
+    &mut self,
+    ego: NodeId,
+    deadline: Instant,
+  ) -> Result<Option<PartialScores>, MeritRankError> {
+    let walks = match self.run_walks(ego, Some(deadline))? {
+      Some(x) => x,
+      None => return Ok(None),
+    };
+    let scores = if walks > 0 {
+      self.get_all_scores(ego, None)?
+    } else {
+      vec![]
+    };
+    self.drop_walks(ego)?;
+    Ok(Some(PartialScores {
+      scores,
+      walks,
+    }))
+  }
+
+  /// Walks of the ego from scratch, checking the deadline every
+  /// `CALCULATE_BATCH_SIZE` walks. If it has passed, returns the number of
+  /// walks done, leaving them in place.
+  fn run_walks(
+    &mut self,
+    ego: NodeId,
+    deadline: Option<Instant>,
+  ) -> Result<Option<usize>, MeritRankError> {
+    self.reset_walks(ego)?;
+    let walks_per_ego = self.walks.walks_per_ego();
+    let mut done = 0;
+    while done < walks_per_ego {
+      if deadline.is_some_and(|d| Instant::now() >= d) {
+        return Ok(Some(done));
+      }
+      let batch = CALCULATE_BATCH_SIZE.min(walks_per_ego - done);
+      match self.calculate_batch(ego, batch)? {
+        0 => break,
+        added => done += added,
+      }
+    }
+    Ok(None)
   }
 
-  /// Clears the ego's walk block and counters without running new walks. Used when evicting an ego from cache.
-  pub fn clear_ego(&mut self, ego: NodeId) -> Result<(), MeritRankError> {
+  /// Drops the ego's walks and counters like `drop_walks`, but keeps its
+  /// block of walk slots, e.g. to calculate it again with `calculate_batch`.
+  pub fn reset_walks(&mut self, ego: NodeId) -> Result<(), MeritRankError> {
     if let Some(start_id) = self.walks.get_block_start(ego) {
       self.walks.clear_block_for_ego(
         ego,
@@ -43,18 +292,23 @@ impl MeritRank {
     Ok(())
   }
 
-  pub fn calculate(&mut self, ego: NodeId) -> Result<(), MeritRankError> {
+  /// Adds up to `n` walks of the ego to those it already has, rather than
+  /// replacing them like `calculate`, so that an ego can be calculated in
+  /// batches, e.g. until its scores converge or a deadline passes. An ego
+  /// has at most `walks_per_ego` walks. Returns the number of walks added.
+  pub fn calculate_batch(
+    &mut self,
+    ego: NodeId,
+    n: usize,
+  ) -> Result<usize, MeritRankError> {
     let start_id = self.walks.ensure_block_for_ego(ego)?;
-    self.walks.clear_block_for_ego(
-      ego,
-      start_id,
-      &mut self.pos_hits,
-      &mut self.neg_hits,
-    )?;
-
+    let params = self.get_ego_params(ego);
     let walks_per_ego = self.walks.walks_per_ego();
-    for i in 0..walks_per_ego {
-      let walk_id = start_id + i;
+    let mut added = 0;
+    for walk_id in start_id..start_id + walks_per_ego {
+      if added == n {
+        break;
+      }
       let walk = match self.walks.get_walk_mut(walk_id) {
         Some(x) => x,
         None => {
@@ -63,9 +317,13 @@ impl MeritRank {
           )));
         },
       };
+      if !walk.is_empty() {
+        continue;
+      }
+      walk.positive_only = params.positive_only;
       walk.push(ego, true)?;
 
-      self.graph.continue_walk(walk, self.alpha)?;
+      self.graph.continue_walk(walk, params.alpha, &mut self.rng)?;
 
       self
         .pos_hits
@@ -79,12 +337,66 @@ impl MeritRank {
         .increment_unique_counts(walk.negative_subsegment());
 
       self.walks.update_walk_bookkeeping(walk_id, 0);
+      added += 1;
     }
     if ASSERT {
       self.walks.assert_visits_consistency()?;
       self.assert_counters_consistency_after_edge_addition()?;
     }
 
+    Ok(added)
+  }
+
+  /// Replaces the ego's walks with the given ones, e.g. walks taken with
+  /// `get_ego_walks` from a copy of the same graph. There must be exactly
+  /// `walks_per_ego` walks, each starting at the ego.
+  pub fn import_walks(
+    &mut self,
+    ego: NodeId,
+    walks: Vec<RandomWalk>,
+  ) -> Result<(), MeritRankError> {
+    if walks.len() != self.walks.walks_per_ego() {
+      return Err(MeritRankError::InvalidWalkLength);
+    }
+    for walk in &walks {
+      if walk.first_node() != Some(ego)
+        || walk.negative_segment_start.is_some_and(|pos| pos > walk.len())
+      {
+        return Err(MeritRankError::InvalidWalkLength);
+      }
+      if !walk.iter().all(|node| self.graph.contains_node(*node)) {
+        return Err(MeritRankError::NodeDoesNotExist);
+      }
+    }
+
+    self.drop_walks(ego)?;
+    let start_id = self.walks.ensure_block_for_ego(ego)?;
+    for (i, walk) in walks.into_iter().enumerate() {
+      let walk_id = start_id + i;
+      self
+        .pos_hits
+        .entry(ego)
+        .or_default()
+        .increment_unique_counts(walk.positive_subsegment());
+      self
+        .neg_hits
+        .entry(ego)
+        .or_default()
+        .increment_unique_counts(walk.negative_subsegment());
+      match self.walks.get_walk_mut(walk_id) {
+        Some(x) => *x = walk,
+        None => {
+          return Err(MeritRankError::InternalFatalError(Some(
+            internal_fatal::RANK_CALCULATE_GET_WALK_MUT,
+          )));
+        },
+      }
+      self.walks.update_walk_bookkeeping(walk_id, 0);
+    }
+    if ASSERT {
+      self.walks.assert_visits_consistency()?;
+    }
+
     Ok(())
   }
 
@@ -93,6 +405,16 @@ impl MeritRank {
     ego: NodeId,
     target: NodeId,
   ) -> Result<Weight, MeritRankError> {
+    self
+      .get_node_score_parts(ego, target)
+      .map(|parts| parts.penalized_with(self.penalty_scale, self.clamp_scores))
+  }
+
+  pub fn get_node_score_parts(
+    &self,
+    ego: NodeId,
+    target: NodeId,
+  ) -> Result<ScoreParts, MeritRankError> {
     let ego_positive_hits = self
       .pos_hits
       .get(&ego)
@@ -106,10 +428,70 @@ impl MeritRank {
 
     let ego_neg_hits = self.neg_hits.get(&ego).unwrap_or(&default_counter);
     let total_hits =
-      ego_positive_hits.total_count() + ego_neg_hits.total_count();
-    let hits_penalized: Weight =
-      target_hits as Weight - ego_neg_hits.get_count(&target) as Weight;
-    Ok(hits_penalized / total_hits as Weight)
+      (ego_positive_hits.total_count() + ego_neg_hits.total_count()) as Weight;
+    Ok(ScoreParts {
+      positive: target_hits as Weight / total_hits,
+      negative: ego_neg_hits.get_count(&target) as Weight / total_hits,
+    })
+  }
+
+  /// Splits the target's score between the ego's neighbors, by the first
+  /// step of the walks that hit the target, e.g. to explain a score by the
+  /// peers the ego trusts. Before `clamp_scores`, the contributions add up
+  /// to the score, except for the hits of walks that stopped at the ego.
+  /// Ordered by contribution, highest first.
+  pub fn get_score_contributions(
+    &self,
+    ego: NodeId,
+    target: NodeId,
+  ) -> Result<Vec<(NodeId, Weight)>, MeritRankError> {
+    let ego_positive_hits = self
+      .pos_hits
+      .get(&ego)
+      .ok_or(MeritRankError::NodeIsNotCalculated)?;
+    let ego_neg_hits = self.neg_hits.get(&ego).unwrap_or_default();
+    let total_hits =
+      (ego_positive_hits.total_count() + ego_neg_hits.total_count()) as Weight;
+
+    let mut contributions: IntMap<NodeId, Weight> = IntMap::default();
+    let visits = match self.walks.get_visits_through_node(target) {
+      Some(x) => x,
+      None => return Ok(vec![]),
+    };
+    for walk_id in visits.keys() {
+      if self.walks.get_walk_ego(*walk_id) != Some(ego) {
+        continue;
+      }
+      let walk = match self.walks.get_walk(*walk_id) {
+        Some(x) => x,
+        None => {
+          return Err(MeritRankError::InternalFatalError(Some(
+            internal_fatal::RANK_CONTRIBUTIONS_GET_WALK,
+          )));
+        },
+      };
+      let neighbor = match walk.get_nodes().get(1) {
+        Some(x) => *x,
+        None => continue,
+      };
+      let mut contribution = 0.0;
+      if walk.positive_subsegment().any(|&node| node == target) {
+        contribution += 1.0;
+      }
+      if walk.negative_subsegment().any(|&node| node == target) {
+        contribution -= self.penalty_scale;
+      }
+      *contributions.entry(neighbor).or_default() += contribution / total_hits;
+    }
+
+    let mut contributions: Vec<_> = contributions.into_iter().collect();
+    contributions.sort_unstable_by(|(peer1, score1), (peer2, score2)| {
+      score2
+        .partial_cmp(score1)
+        .unwrap_or(std::cmp::Ordering::Equal)
+        .then(peer1.cmp(peer2))
+    });
+    Ok(contributions)
   }
 
   pub fn get_all_scores(
@@ -137,10 +519,13 @@ impl MeritRank {
       .map(|&peer| self.get_node_score(ego, peer).map(|score| (peer, score)))
       .collect::<Result<_, _>>()?;
 
-    peer_scores.sort_unstable_by(|(_, score1), (_, score2)| {
+    //  Ties are broken by node id, so that the order does not depend on the
+    //  order of the set.
+    peer_scores.sort_unstable_by(|(peer1, score1), (peer2, score2)| {
       score2
         .partial_cmp(score1)
         .unwrap_or(std::cmp::Ordering::Equal)
+        .then(peer1.cmp(peer2))
     });
 
     Ok(
@@ -152,6 +537,39 @@ impl MeritRank {
     )
   }
 
+  /// Cosine similarity of the scores of two egos, in [-1, 1]. Scores of the
+  /// egos themselves are left out, since every ego ranks itself highly.
+  pub fn ego_similarity(
+    &self,
+    a: NodeId,
+    b: NodeId,
+  ) -> Result<Weight, MeritRankError> {
+    let scores_a: IntMap<NodeId, Weight> =
+      self.get_all_scores(a, None)?.into_iter().collect();
+    let scores_b: IntMap<NodeId, Weight> =
+      self.get_all_scores(b, None)?.into_iter().collect();
+
+    let norm = |scores: &IntMap<NodeId, Weight>| {
+      scores
+        .iter()
+        .filter(|&(&node, _)| node != a && node != b)
+        .map(|(_, score)| score * score)
+        .sum::<Weight>()
+        .sqrt()
+    };
+    let dot: Weight = scores_a
+      .iter()
+      .filter(|&(&node, _)| node != a && node != b)
+      .filter_map(|(node, score_a)| scores_b.get(node).map(|x| x * score_a))
+      .sum();
+
+    let norms = norm(&scores_a) * norm(&scores_b);
+    if norms == 0.0 {
+      return Ok(0.0);
+    }
+    Ok(dot / norms)
+  }
+
   pub fn get_new_nodeid(&mut self) -> NodeId {
     self.graph.get_new_nodeid()
   }
@@ -236,51 +654,80 @@ impl MeritRank {
       src,
       Some(dest),
       step_recalc_probability,
+      &mut self.rng,
     )?;
 
-    for (walk_id, visit_pos) in &affected_walkids {
-      // Revert the counters associated with the affected walks, as if the walks never existed
-      let walk = match self.walks.get_walk(*walk_id) {
-        Some(x) => x,
-        None => return Err(MeritRankError::InternalFatalError(Some(
-          internal_fatal::RANK_SET_EDGE_GET_WALK,
-        ))),
-      };
-      let ego = match walk.first_node() {
-        Some(x) => x,
-        None => return Err(MeritRankError::InternalFatalError(Some(
-          internal_fatal::RANK_SET_EDGE_FIRST_NODE,
-        ))),
-      };
-      self
-        .pos_hits
-        .entry(ego)
-        .or_default()
-        .decrement_unique_counts(walk.positive_subsegment());
-      self
-        .neg_hits
-        .entry(ego)
-        .or_default()
-        .decrement_unique_counts(walk.negative_subsegment());
+    let added_edge = if deletion_mode {
+      None
+    } else {
+      Some((dest, new_weight))
+    };
+    for (walk_id, visit_pos) in affected_walkids {
+      self.recalc_invalidated_walk(walk_id, visit_pos, added_edge)?;
+    }
 
-      let cut_position = visit_pos + 1;
-      self
-        .walks
-        .split_and_remove_from_bookkeeping(walk_id, cut_position)?;
+    if ASSERT {
+      self.walks.assert_visits_consistency()?;
+      self.assert_counters_consistency_after_edge_addition()?;
+    }
 
-      let walk = match self.walks.get_walk_mut(*walk_id) {
-        Some(x) => x,
-        None => return Err(MeritRankError::InternalFatalError(Some(
-          internal_fatal::RANK_SET_EDGE_GET_WALK_MUT,
-        ))),
-      };
+    Ok(())
+  }
 
-      let mut skip_continuation = false;
-      //#[cfg(optimize_invalidation)]
-      if OPTIMIZE_INVALIDATION {
-        if deletion_mode {
-          self.graph.extend_walk_in_case_of_edge_deletion(walk)?;
-        } else if random::<f64>() < self.alpha {
+  /// Cuts the walk after the step at `visit_pos` that an edge change made
+  /// stale, and continues it on the current graph, keeping the counters and
+  /// visits up to date. `added_edge` is the new edge from the node at
+  /// `visit_pos` with its weight, None for a removed edge.
+  fn recalc_invalidated_walk(
+    &mut self,
+    walk_id: WalkId,
+    visit_pos: usize,
+    added_edge: Option<(NodeId, Weight)>,
+  ) -> Result<(), MeritRankError> {
+    // Revert the counters associated with the affected walks, as if the walks
+    // never existed
+    let walk = match self.walks.get_walk(walk_id) {
+      Some(x) => x,
+      None => return Err(MeritRankError::InternalFatalError(Some(
+        internal_fatal::RANK_SET_EDGE_GET_WALK,
+      ))),
+    };
+    let ego = match walk.first_node() {
+      Some(x) => x,
+      None => return Err(MeritRankError::InternalFatalError(Some(
+        internal_fatal::RANK_SET_EDGE_FIRST_NODE,
+      ))),
+    };
+    self
+      .pos_hits
+      .entry(ego)
+      .or_default()
+      .decrement_unique_counts(walk.positive_subsegment());
+    self
+      .neg_hits
+      .entry(ego)
+      .or_default()
+      .decrement_unique_counts(walk.negative_subsegment());
+
+    let alpha = self.get_ego_params(ego).alpha;
+
+    let cut_position = visit_pos + 1;
+    self
+      .walks
+      .split_and_remove_from_bookkeeping(&walk_id, cut_position)?;
+
+    let walk = match self.walks.get_walk_mut(walk_id) {
+      Some(x) => x,
+      None => return Err(MeritRankError::InternalFatalError(Some(
+        internal_fatal::RANK_SET_EDGE_GET_WALK_MUT,
+      ))),
+    };
+
+    let mut skip_continuation = false;
+    //#[cfg(optimize_invalidation)]
+    if OPTIMIZE_INVALIDATION {
+      if let Some((dest, new_weight)) = added_edge {
+        if self.rng.random::<f64>() < alpha {
           // If already in negative continuation, appended node is in negative
           // subsegment by position; do not set negative_segment_start again.
           let step_is_positive =
@@ -289,24 +736,59 @@ impl MeritRank {
         } else {
           skip_continuation = true;
         }
+      } else {
+        self
+          .graph
+          .extend_walk_in_case_of_edge_deletion(walk, &mut self.rng)?;
       }
-      if !skip_continuation {
-        self.graph.continue_walk(walk, self.alpha)?;
-      }
+    }
+    if !skip_continuation {
+      self.graph.continue_walk(walk, alpha, &mut self.rng)?;
+    }
 
-      // Update counters associated with the updated walks
-      self
-        .pos_hits
-        .entry(ego)
-        .or_default()
-        .increment_unique_counts(walk.positive_subsegment());
-      self
-        .neg_hits
-        .entry(ego)
-        .or_default()
-        .increment_unique_counts(walk.negative_subsegment());
+    // Update counters associated with the updated walks
+    self
+      .pos_hits
+      .entry(ego)
+      .or_default()
+      .increment_unique_counts(walk.positive_subsegment());
+    self
+      .neg_hits
+      .entry(ego)
+      .or_default()
+      .increment_unique_counts(walk.negative_subsegment());
+
+    self.walks.update_walk_bookkeeping(walk_id, cut_position);
 
-      self.walks.update_walk_bookkeeping(*walk_id, cut_position);
+    Ok(())
+  }
+
+  /// Removes the edges below `epsilon` in magnitude, see
+  /// `Graph::prune_below`, and updates the walks that took them in one pass:
+  /// each walk is cut once, at the first removed edge it took. Returns the
+  /// number of edges removed.
+  pub fn prune_edges_below(
+    &mut self,
+    epsilon: Weight,
+  ) -> Result<usize, MeritRankError> {
+    let pruned = self.graph.prune_below(epsilon)?;
+
+    let mut cuts: IntMap<WalkId, usize> = IntMap::default();
+    for &(src, dst) in &pruned {
+      for (walk_id, visit_pos) in
+        self
+          .walks
+          .find_affected_walkids(src, Some(dst), None, &mut self.rng)?
+      {
+        let cut = cuts.entry(walk_id).or_insert(visit_pos);
+        *cut = (*cut).min(visit_pos);
+      }
+    }
+    //  Walks are updated in a fixed order, so that seeded ranks agree.
+    let mut cuts: Vec<_> = cuts.into_iter().collect();
+    cuts.sort_unstable();
+    for (walk_id, visit_pos) in cuts {
+      self.recalc_invalidated_walk(walk_id, visit_pos, None)?;
     }
 
     if ASSERT {
@@ -314,7 +796,7 @@ impl MeritRank {
       self.assert_counters_consistency_after_edge_addition()?;
     }
 
-    Ok(())
+    Ok(pruned.len())
   }
 
   fn assert_counters_consistency_after_edge_addition(
@@ -335,7 +817,7 @@ impl MeritRank {
               walk.get_nodes().first() == Some(ego)
                 && walk
                   .negative_segment_start
-                  .map_or(true, |seg_start| *pos < seg_start)
+                  .is_none_or(|seg_start| *pos < seg_start)
             } else {
               false
             }
@@ -388,10 +870,92 @@ impl MeritRank {
     &self.pos_hits
   }
 
+  /// Hits of the negative subsegments of the walks, per ego.
+  pub fn get_negative_hits(&self) -> &IntMap<NodeId, Counter> {
+    &self.neg_hits
+  }
+
+  /// Nodes the ego's walks visited on negative subsegments, with the
+  /// negative parts of their scores, see `ScoreParts`. In no particular
+  /// order.
+  pub fn get_ego_negative_hits(
+    &self,
+    ego: NodeId,
+  ) -> Result<impl Iterator<Item = (NodeId, Weight)> + '_, MeritRankError> {
+    let pos_hits = self
+      .pos_hits
+      .get(&ego)
+      .ok_or(MeritRankError::NodeIsNotCalculated)?;
+    let neg_hits: &Counter = self.neg_hits.get(&ego).unwrap_or_default();
+    let total_hits =
+      (pos_hits.total_count() + neg_hits.total_count()) as Weight;
+    Ok(
+      neg_hits
+        .into_iter()
+        .filter(|(_, &count)| count > 0)
+        .map(move |(&node, &count)| (node, count as Weight / total_hits)),
+    )
+  }
+
+  /// Number of walks the ego's scores are taken from: `walks_per_ego` once
+  /// it is calculated, 0 if it has no walks.
+  pub fn walk_count(&self, ego: NodeId) -> usize {
+    if !self.pos_hits.contains_key(&ego) {
+      return 0;
+    }
+    self.get_ego_walks(ego).filter(|walk| !walk.is_empty()).count()
+  }
+
+  /// Walks of the ego, empty if it has none.
+  pub fn get_ego_walks(
+    &self,
+    ego: NodeId,
+  ) -> impl Iterator<Item = &RandomWalk> + '_ {
+    let walks_per_ego = self.walks.walks_per_ego();
+    self.walks.get_block_start(ego).into_iter().flat_map(move |start| {
+      (start..start + walks_per_ego)
+        .filter_map(move |id| self.walks.get_walk(id))
+    })
+  }
+
+  /// Returns the egos that have at least one walk passing through the node.
+  /// These are the egos whose scores may change when the node's outgoing edges change.
+  pub fn egos_visiting(
+    &self,
+    node: NodeId,
+  ) -> Vec<NodeId> {
+    let mut egos: Vec<NodeId> = match self.walks.get_visits_through_node(node) {
+      Some(visits) => visits
+        .keys()
+        .filter_map(|&walk_id| self.walks.get_walk_ego(walk_id))
+        .collect(),
+      None => vec![],
+    };
+    egos.sort_unstable();
+    egos.dedup();
+    egos
+  }
+
   /// Clears all walks and hit counters; graph structure is preserved. Used for bulk load cold start.
   pub fn clear_walks(&mut self) {
     self.walks.clear();
     self.pos_hits.clear();
     self.neg_hits.clear();
   }
+
+  /// Copy of the graph and settings without walks and hit counters, cheaper
+  /// than cloning and then clearing the walks.
+  pub fn clone_without_walks(&self) -> Self {
+    Self {
+      graph: self.graph.clone(),
+      walks: WalkStorage::new(self.walks.walks_per_ego()),
+      pos_hits: IntMap::default(),
+      neg_hits: IntMap::default(),
+      alpha: self.alpha,
+      penalty_scale: self.penalty_scale,
+      clamp_scores: self.clamp_scores,
+      ego_params: self.ego_params.clone(),
+      rng: self.rng.clone(),
+    }
+  }
 }
@@ -17,10 +17,15 @@ pub type WalkId = usize;
 /// Each ego owns a fixed-size contiguous block of walk slots.
 #[derive(Clone)]
 pub struct WalkStorage {
+  // Node -> walks passing it, with the first position of the node in each.
   visits:         Vec<IntMap<WalkId, usize>>,
   walks:          Vec<RandomWalk>,
   walks_per_ego:  usize,
   ego_blocks:     IntMap<NodeId, WalkId>,
+  // Reverse of `ego_blocks`: block start -> ego.
+  block_owners:   IntMap<WalkId, NodeId>,
+  // Starts of blocks given up by dropped egos, reused before growing `walks`.
+  free_blocks:    Vec<WalkId>,
 }
 
 impl WalkStorage {
@@ -30,6 +35,8 @@ impl WalkStorage {
       walks:         Vec::new(),
       walks_per_ego,
       ego_blocks:    IntMap::default(),
+      block_owners:  IntMap::default(),
+      free_blocks:   Vec::new(),
     }
   }
 
@@ -67,6 +74,18 @@ impl WalkStorage {
     self.visits.get(node_id)
   }
 
+  /// Returns the ego whose block contains the given walk.
+  pub fn get_walk_ego(
+    &self,
+    walk_id: WalkId,
+  ) -> Option<NodeId> {
+    if self.walks_per_ego == 0 {
+      return None;
+    }
+    let start = walk_id - walk_id % self.walks_per_ego;
+    self.block_owners.get(&start).copied()
+  }
+
   /// Ensures the given ego has a block of walk slots; returns the start index.
   pub fn ensure_block_for_ego(
     &mut self,
@@ -75,11 +94,18 @@ impl WalkStorage {
     if let Some(&start) = self.ego_blocks.get(&ego) {
       return Ok(start);
     }
-    let start = self.walks.len() as WalkId;
-    for _ in 0..self.walks_per_ego {
-      self.walks.push(RandomWalk::new());
-    }
+    let start = match self.free_blocks.pop() {
+      Some(start) => start,
+      None => {
+        let start = self.walks.len() as WalkId;
+        for _ in 0..self.walks_per_ego {
+          self.walks.push(RandomWalk::new());
+        }
+        start
+      },
+    };
     self.ego_blocks.insert(ego, start);
+    self.block_owners.insert(start, ego);
     Ok(start)
   }
 
@@ -136,6 +162,39 @@ impl WalkStorage {
     Ok(())
   }
 
+  /// Frees the memory of the walks in a cleared block; the block stays reserved for its ego.
+  pub fn release_block(
+    &mut self,
+    start_id: WalkId,
+  ) {
+    let end = (start_id + self.walks_per_ego).min(self.walks.len());
+    for walk in self.walks.iter_mut().take(end).skip(start_id) {
+      *walk = RandomWalk::new();
+    }
+  }
+
+  /// Drops the ego's walks like `clear_block_for_ego`, removing them from
+  /// the visits of every node they pass, so that invalidation no longer scans
+  /// them, and frees the ego's block for reuse by other egos. Returns false if
+  /// the ego had no block.
+  pub fn drop_walks_and_visits(
+    &mut self,
+    ego: NodeId,
+    pos_hits: &mut IntMap<NodeId, Counter>,
+    neg_hits: &mut IntMap<NodeId, Counter>,
+  ) -> Result<bool, MeritRankError> {
+    let start_id = match self.ego_blocks.get(&ego) {
+      Some(&x) => x,
+      None => return Ok(false),
+    };
+    self.clear_block_for_ego(ego, start_id, pos_hits, neg_hits)?;
+    self.release_block(start_id);
+    self.ego_blocks.remove(&ego);
+    self.block_owners.remove(&start_id);
+    self.free_blocks.push(start_id);
+    Ok(true)
+  }
+
   pub fn update_walk_bookkeeping(
     &mut self,
     walk_id: WalkId,
@@ -162,6 +221,8 @@ impl WalkStorage {
     self.visits.clear();
     self.walks.clear();
     self.ego_blocks.clear();
+    self.block_owners.clear();
+    self.free_blocks.clear();
   }
 
   pub fn assert_visits_consistency(&self) -> Result<(), MeritRankError> {
@@ -179,11 +240,12 @@ impl WalkStorage {
 
   /// Returns a walk IDs and cut positions for the walks affected by introducing new outgoing
   /// edge at invalidated_node.
-  pub fn find_affected_walkids(
+  pub fn find_affected_walkids<R: Rng + ?Sized>(
     &self,
     invalidated_node: NodeId,
     dst_node: Option<NodeId>,
     step_recalc_probability: Option<(Weight, Weight)>,
+    rng: &mut R,
   ) -> Result<Vec<(WalkId, usize)>, MeritRankError> {
     let mut invalidated_walks_ids = vec![];
 
@@ -195,7 +257,6 @@ impl WalkStorage {
 
     for (walk_id, visit_pos) in walks {
       let _new_pos = if OPTIMIZE_INVALIDATION && dst_node.is_some() {
-        let mut rng = rng();
         let (may_skip, new_pos) = decide_skip_invalidation(
           match self.get_walk(*walk_id) {
             Some(x) => x,
@@ -214,7 +275,7 @@ impl WalkStorage {
             },
           ),
           step_recalc_probability,
-          Some(&mut rng),
+          Some(&mut *rng),
         )?;
         if may_skip {
           // Skip invalidating this walk if it is determined to be unnecessary
@@ -237,29 +298,26 @@ impl WalkStorage {
     cut_pos: usize,
   ) -> Result<(), MeritRankError> {
     // Cut position is the index of the first element of the invalidated segment
-    // Split the walk and obtain the invalidated segment
     let walk = match self.walks.get_mut(*walk_id) {
       Some(x) => x,
       None => return Err(MeritRankError::InternalFatalError(Some(
         internal_fatal::WALK_STORAGE_SPLIT_GET_MUT,
       ))),
     };
-    let invalidated_segment = walk.split_from(cut_pos);
 
-    // Remove affected nodes from bookkeeping, but ensure we don't accidentally remove references
-    // if there are still copies of the affected node in the remaining walk
-    for &affected_node in invalidated_segment
-      .get_nodes()
-      .iter()
-      .filter(|&node| !walk.contains(node))
-    {
+    // Remove affected nodes from bookkeeping, but keep the references of nodes
+    // that are still in the remaining walk. Visits keep the first position of
+    // a node in the walk, so there is no need to search the remaining walk,
+    // which made cutting very long walks quadratic.
+    for &affected_node in walk.get_nodes().iter().skip(cut_pos) {
       if let Some(affected_walks) = self.visits.get_mut(affected_node) {
-        if affected_walks.get(walk_id).is_some() {
+        if affected_walks.get(walk_id).is_some_and(|&pos| pos >= cut_pos) {
           // Remove the invalidated walk from affected nodes
           affected_walks.remove(walk_id);
         }
       }
     }
+    walk.truncate(cut_pos);
 
     Ok(())
   }
@@ -348,7 +406,12 @@ where
 
   // Positions at or after neg_start are in the negative subsegment (positive-only candidate set).
   // Positions before neg_start are in the positive subsegment (full candidate set).
-  let neg_start = walk.negative_segment_start.unwrap_or(usize::MAX);
+  // Positive-only walks pick among positive edges at every position.
+  let neg_start = if walk.positive_only {
+    0
+  } else {
+    walk.negative_segment_start.unwrap_or(usize::MAX)
+  };
 
   let mut new_pos = pos;
   let result = walk.get_nodes()[pos..]
@@ -158,7 +158,7 @@ mod tests {
       Some(&mut rng),
     )
     .unwrap();
-    assert_eq!(may_skip, false);
+    assert!(!may_skip);
     assert_eq!(new_pos, 2);
   }
 
@@ -1,16 +1,23 @@
 #[allow(unused_imports)]
 #[cfg(test)]
-
 mod tests {
   use super::*;
   use indexmap::indexmap;
   use meritrank_core::graph::{EdgeId, NodeId};
   use meritrank_core::random_walk::RandomWalk;
   use meritrank_core::walk_storage::WalkStorage;
-  use meritrank_core::{assert_approx_eq, Graph, MeritRank, Weight};
+  use meritrank_core::{
+    assert_approx_eq,
+    EgoParams,
+    Graph,
+    MeritRank,
+    MeritRankError,
+    Weight,
+  };
 
   use rand::Rng;
   use std::collections::HashMap;
+  use std::time::{Duration, Instant};
 
   // lets write test for get_personal_hits(&self) -> &HashMap<NodeId, Counter>
   #[test]
@@ -21,6 +28,415 @@ mod tests {
     assert!(result.is_empty());
   }
 
+  #[test]
+  fn test_egos_visiting() {
+    let mut rank = MeritRank::new(Graph::new(), 100);
+    for _ in 0..4 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(2, 1, 1.0).unwrap();
+    rank.calculate(0).unwrap();
+    rank.calculate(2).unwrap();
+
+    assert_eq!(rank.egos_visiting(1), vec![0, 2]);
+    assert_eq!(rank.egos_visiting(0), vec![0]);
+    assert!(rank.egos_visiting(3).is_empty());
+  }
+
+  #[test]
+  fn test_ego_similarity() {
+    let mut rank = MeritRank::new(Graph::new(), 1000);
+    for _ in 0..6 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 2, 1.0).unwrap();
+    rank.set_edge(1, 2, 1.0).unwrap();
+    rank.set_edge(3, 4, 1.0).unwrap();
+    for ego in [0, 1, 3, 5] {
+      rank.calculate(ego).unwrap();
+    }
+
+    assert!((rank.ego_similarity(0, 1).unwrap() - 1.0).abs() < 1e-9);
+    assert_eq!(rank.ego_similarity(0, 3).unwrap(), 0.0);
+    assert_eq!(rank.ego_similarity(0, 5).unwrap(), 0.0);
+    assert!(rank.ego_similarity(0, 4).is_err());
+  }
+
+  #[test]
+  fn test_scale_edges() {
+    let mut rank = MeritRank::new(Graph::new(), 1000);
+    for _ in 0..3 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 2.0).unwrap();
+    rank.set_edge(0, 2, -4.0).unwrap();
+    rank.calculate(0).unwrap();
+    let before = rank.get_node_score(0, 1).unwrap();
+
+    rank.graph.scale_edges(0.5).unwrap();
+
+    assert_eq!(rank.graph.edge_weight(0, 1).unwrap(), Some(1.0));
+    assert_eq!(rank.graph.edge_weight(0, 2).unwrap(), Some(-2.0));
+    let data = rank.graph.get_node_data(0).unwrap();
+    assert_eq!(data.pos_sum, 1.0);
+    assert_eq!(data.neg_sum, 2.0);
+    assert_eq!(rank.get_node_score(0, 1).unwrap(), before);
+    assert!(rank.graph.scale_edges(0.0).is_err());
+  }
+
+  #[test]
+  fn test_prune_edges_below() {
+    let mut rank = MeritRank::new(Graph::new(), 1000);
+    for _ in 0..4 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1000.0).unwrap();
+    rank.set_edge(0, 2, 1.0).unwrap();
+    rank.set_edge(2, 3, -1.0).unwrap();
+    rank.set_edge(1, 3, 1000.0).unwrap();
+    rank.calculate(0).unwrap();
+    rank.calculate(2).unwrap();
+
+    rank.graph.scale_edges(1e-4).unwrap();
+    assert_eq!(rank.prune_edges_below(1e-3).unwrap(), 2);
+    assert_eq!(rank.graph.edge_count(), 2);
+    assert_eq!(rank.graph.edge_weight(0, 2).unwrap(), None);
+    assert_eq!(rank.graph.edge_weight(2, 3).unwrap(), None);
+
+    // No walk takes the pruned edges any more.
+    assert_eq!(rank.get_node_score(0, 2).unwrap(), 0.0);
+    assert_eq!(rank.get_node_score(2, 3).unwrap(), 0.0);
+    assert!(rank.get_node_score(0, 3).unwrap() > 0.0);
+    assert!(rank
+      .get_ego_walks(0)
+      .all(|walk| walk.get_nodes().windows(2).all(|step| step[1] != 2)));
+    assert_eq!(rank.prune_edges_below(1e-3).unwrap(), 0);
+  }
+
+  #[test]
+  fn test_drop_walks() {
+    let mut rank = MeritRank::new(Graph::new(), 100);
+    for _ in 0..3 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(1, 2, 1.0).unwrap();
+    rank.calculate(0).unwrap();
+    rank.calculate(1).unwrap();
+
+    assert_eq!(rank.walk_count(0), 100);
+    assert!(rank.drop_walks(0).unwrap());
+    assert!(!rank.drop_walks(0).unwrap());
+    assert!(rank.get_node_score(0, 1).is_err());
+    assert_eq!(rank.walk_count(0), 0);
+    assert_eq!(rank.egos_visiting(1), vec![1]);
+
+    // Edges can still change, and the ego can be recalculated.
+    rank.set_edge(0, 2, 1.0).unwrap();
+    rank.calculate(0).unwrap();
+    assert!(rank.get_node_score(0, 2).unwrap() > 0.0);
+  }
+
+  #[test]
+  fn test_ego_params() {
+    let mut rank = MeritRank::new(Graph::new(), 1000);
+    for _ in 0..4 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(0, 2, -1.0).unwrap();
+    rank.set_edge(1, 3, 1.0).unwrap();
+
+    rank.set_ego_params(
+      0,
+      Some(EgoParams {
+        alpha:         0.0,
+        positive_only: true,
+      }),
+    );
+    rank.calculate(0).unwrap();
+    assert_eq!(rank.get_node_score(0, 1).unwrap(), 0.0);
+    assert_eq!(rank.get_node_score(0, 2).unwrap(), 0.0);
+
+    rank.set_ego_params(
+      0,
+      Some(EgoParams {
+        alpha:         1.0,
+        positive_only: true,
+      }),
+    );
+    rank.calculate(0).unwrap();
+    assert!(rank.get_node_score(0, 3).unwrap() > 0.0);
+    assert_eq!(rank.get_node_score(0, 2).unwrap(), 0.0);
+
+    // Walks rerouted by edge changes keep the params of their ego.
+    rank.set_edge(1, 2, -1.0).unwrap();
+    assert_eq!(rank.get_node_score(0, 2).unwrap(), 0.0);
+
+    rank.set_ego_params(0, None);
+    rank.calculate(0).unwrap();
+    assert!(rank.get_node_score(0, 2).unwrap() < 0.0);
+  }
+
+  #[test]
+  fn test_ego_walks_and_hits() {
+    let mut rank = MeritRank::new(Graph::new(), 100);
+    for _ in 0..3 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(0, 2, -1.0).unwrap();
+    assert_eq!(rank.get_ego_walks(0).count(), 0);
+
+    rank.calculate(0).unwrap();
+    let walks: Vec<_> = rank.get_ego_walks(0).collect();
+    assert_eq!(walks.len(), 100);
+    assert!(walks.iter().all(|walk| walk.first_node() == Some(0)));
+    assert!(rank.get_personal_hits()[&0].get_count(&1) > 0);
+    assert!(rank.get_negative_hits()[&0].get_count(&2) > 0);
+
+    let negative: Vec<_> = rank.get_ego_negative_hits(0).unwrap().collect();
+    let parts = rank.get_node_score_parts(0, 2).unwrap();
+    assert_eq!(negative, vec![(2, parts.negative)]);
+    assert!(rank.get_ego_negative_hits(1).is_err());
+  }
+
+  #[test]
+  fn test_score_parts() {
+    let mut rank = MeritRank::new(Graph::new(), 100);
+    for _ in 0..3 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(0, 2, -1.0).unwrap();
+    rank.calculate(0).unwrap();
+
+    let parts = rank.get_node_score_parts(0, 2).unwrap();
+    assert_eq!(parts.positive, 0.0);
+    assert!(parts.negative > 0.0);
+    assert_eq!(parts.penalized(), rank.get_node_score(0, 2).unwrap());
+    let parts = rank.get_node_score_parts(0, 1).unwrap();
+    assert!(parts.positive > 0.0);
+    assert_eq!(parts.penalized(), rank.get_node_score(0, 1).unwrap());
+  }
+
+  #[test]
+  fn test_builder() {
+    let rank = MeritRank::builder(100)
+      .alpha(0.5)
+      .penalty_scale(0.5)
+      .clamp_scores(true)
+      .seed(1)
+      .build(Graph::new())
+      .unwrap();
+    assert_eq!(rank.alpha, 0.5);
+    assert_eq!(rank.penalty_scale, 0.5);
+    assert!(rank.clamp_scores);
+
+    let defaults = MeritRank::builder(100).build(Graph::new()).unwrap();
+    let new = MeritRank::new(Graph::new(), 100);
+    assert_eq!(defaults.alpha, new.alpha);
+    assert_eq!(defaults.penalty_scale, new.penalty_scale);
+    assert_eq!(defaults.clamp_scores, new.clamp_scores);
+
+    assert!(matches!(
+      MeritRank::builder(0).build(Graph::new()),
+      Err(MeritRankError::InvalidParameter("walks_per_ego"))
+    ));
+    assert!(matches!(
+      MeritRank::builder(100).alpha(1.5).build(Graph::new()),
+      Err(MeritRankError::InvalidParameter("alpha"))
+    ));
+    assert!(matches!(
+      MeritRank::builder(100).penalty_scale(-1.0).build(Graph::new()),
+      Err(MeritRankError::InvalidParameter("penalty_scale"))
+    ));
+  }
+
+  #[test]
+  fn test_penalty_options() {
+    let mut rank = MeritRank::new(Graph::new(), 100);
+    for _ in 0..4 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(0, 2, -1.0).unwrap();
+    rank.set_edge(1, 3, 1.0).unwrap();
+    rank.set_edge(0, 3, -1.0).unwrap();
+    rank.calculate(0).unwrap();
+    let parts = rank.get_node_score_parts(0, 2).unwrap();
+    assert!(rank.get_node_score(0, 2).unwrap() < 0.0);
+
+    rank.penalty_scale = 0.5;
+    assert_approx_eq!(
+      rank.get_node_score(0, 2).unwrap(),
+      -0.5 * parts.negative,
+      1e-9
+    );
+    rank.penalty_scale = 0.0;
+    assert_eq!(rank.get_node_score(0, 2).unwrap(), 0.0);
+
+    rank.penalty_scale = 1.0;
+    rank.clamp_scores = true;
+    assert_eq!(rank.get_node_score(0, 2).unwrap(), 0.0);
+    let parts = rank.get_node_score_parts(0, 3).unwrap();
+    assert_eq!(
+      rank.get_node_score(0, 3).unwrap(),
+      parts.penalized().max(0.0)
+    );
+    assert!(rank
+      .get_all_scores(0, None)
+      .unwrap()
+      .iter()
+      .all(|(_, score)| *score >= 0.0));
+  }
+
+  #[test]
+  fn test_score_contributions() {
+    let mut rank = MeritRank::new(Graph::new(), 1000);
+    for _ in 0..4 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(0, 2, 1.0).unwrap();
+    rank.set_edge(1, 3, 1.0).unwrap();
+    rank.set_edge(2, 3, 1.0).unwrap();
+    rank.calculate(0).unwrap();
+
+    let contributions = rank.get_score_contributions(0, 3).unwrap();
+    let neighbors: Vec<_> = contributions.iter().map(|x| x.0).collect();
+    assert!(neighbors == vec![1, 2] || neighbors == vec![2, 1]);
+    assert!(contributions[0].1 >= contributions[1].1);
+    let total: Weight = contributions.iter().map(|x| x.1).sum();
+    assert_approx_eq!(total, rank.get_node_score(0, 3).unwrap(), 1e-9);
+
+    //  Only walks through the neighbor reach its own hits.
+    let contributions = rank.get_score_contributions(0, 1).unwrap();
+    assert_eq!(contributions.len(), 1);
+    assert_eq!(contributions[0].0, 1);
+    assert!(rank.get_score_contributions(1, 3).is_err());
+  }
+
+  #[test]
+  fn test_import_walks() {
+    let mut rank = MeritRank::new(Graph::new(), 100);
+    for _ in 0..4 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(1, 2, 1.0).unwrap();
+    rank.set_edge(0, 3, -1.0).unwrap();
+    rank.calculate(0).unwrap();
+    let walks: Vec<RandomWalk> = rank.get_ego_walks(0).cloned().collect();
+
+    let mut copy = MeritRank::new(rank.graph.clone(), 100);
+    assert!(copy.import_walks(0, walks[1..].to_vec()).is_err());
+    assert!(copy.import_walks(1, walks.clone()).is_err());
+    copy.import_walks(0, walks).unwrap();
+    for target in 0..4 {
+      assert_eq!(
+        copy.get_node_score(0, target).unwrap(),
+        rank.get_node_score(0, target).unwrap()
+      );
+    }
+
+    //  Imported walks are updated by later edge changes like any other.
+    let before = copy.get_node_score(0, 3).unwrap();
+    copy.set_edge(2, 3, 1.0).unwrap();
+    assert!(copy.get_node_score(0, 3).unwrap() > before);
+  }
+
+  #[test]
+  fn test_calculate_batch() {
+    let mut rank = MeritRank::new(Graph::new(), 100);
+    for _ in 0..3 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(1, 2, 1.0).unwrap();
+
+    assert_eq!(rank.calculate_batch(0, 30).unwrap(), 30);
+    assert_eq!(rank.walk_count(0), 30);
+    assert!(rank.get_node_score(0, 2).unwrap() > 0.0);
+
+    // Walks are added up to `walks_per_ego`.
+    assert_eq!(rank.calculate_batch(0, 100).unwrap(), 70);
+    assert_eq!(rank.calculate_batch(0, 100).unwrap(), 0);
+    assert_eq!(rank.walk_count(0), 100);
+
+    rank.reset_walks(0).unwrap();
+    assert_eq!(rank.walk_count(0), 0);
+    assert!(rank.get_node_score(0, 1).is_err());
+    assert_eq!(rank.egos_visiting(1), Vec::<NodeId>::new());
+    assert_eq!(rank.calculate_batch(0, 10).unwrap(), 10);
+    assert_eq!(rank.walk_count(0), 10);
+  }
+
+  #[test]
+  fn test_seeded_ranks_agree() {
+    let build = || {
+      let mut rank = MeritRank::new(Graph::new(), 1000);
+      rank.seed_rng(7);
+      for _ in 0..5 {
+        rank.get_new_nodeid();
+      }
+      rank.set_edge(0, 1, 1.0).unwrap();
+      rank.set_edge(1, 2, 2.0).unwrap();
+      rank.set_edge(1, 3, -1.0).unwrap();
+      rank.calculate(0).unwrap();
+      rank.set_edge(2, 4, 1.0).unwrap();
+      rank.set_edge(1, 3, 0.0).unwrap();
+      rank
+    };
+    let (a, b) = (build(), build());
+    assert_eq!(
+      a.get_all_scores(0, None).unwrap(),
+      b.get_all_scores(0, None).unwrap()
+    );
+  }
+
+  #[test]
+  fn test_calculate_until_deadline() {
+    let mut rank = MeritRank::new(Graph::new(), 1000);
+    for _ in 0..2 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+
+    let past = Instant::now() - Duration::from_millis(1);
+    assert!(matches!(
+      rank.calculate_until(0, Some(past)),
+      Err(MeritRankError::Timeout)
+    ));
+    assert!(rank.get_node_score(0, 1).is_err());
+
+    let future = Instant::now() + Duration::from_secs(60);
+    rank.calculate_until(0, Some(future)).unwrap();
+    assert!(rank.get_node_score(0, 1).unwrap() > 0.0);
+  }
+
+  #[test]
+  fn test_calculate_partial() {
+    let mut rank = MeritRank::new(Graph::new(), 1000);
+    for _ in 0..3 {
+      rank.get_new_nodeid();
+    }
+    rank.set_edge(0, 1, 1.0).unwrap();
+    rank.set_edge(1, 2, 1.0).unwrap();
+
+    //  No walk is done before a deadline that has passed.
+    let past = Instant::now() - Duration::from_millis(1);
+    let partial = rank.calculate_partial(0, past).unwrap().unwrap();
+    assert_eq!(partial.walks, 0);
+    assert!(partial.scores.is_empty());
+    assert!(rank.get_node_score(0, 1).is_err());
+
+    let future = Instant::now() + Duration::from_secs(60);
+    assert_eq!(rank.calculate_partial(0, future).unwrap(), None);
+    assert!(rank.get_node_score(0, 2).unwrap() > 0.0);
+  }
+
   #[test]
   fn test_basic_chain_graph() {
     let walk_count = 10000;
@@ -42,8 +458,8 @@ mod tests {
     println!("{:?}", rank_ref.get_all_scores(0, None));
     println!("{:?}", rank.get_all_scores(0, None));
     for n in 1..8 {
-      let ref_score = rank_ref.get_node_score(0, n).unwrap() as f64;
-      let score = rank.get_node_score(0, n).unwrap() as f64;
+      let ref_score = rank_ref.get_node_score(0, n).unwrap();
+      let score = rank.get_node_score(0, n).unwrap();
       assert_approx_eq!(ref_score, score, 0.1);
     }
   }
@@ -69,8 +485,8 @@ mod tests {
     rank_ref.calculate(0).unwrap();
     println!("{:?}", cloned.get_all_scores(0, None));
     for n in 1..8 {
-      let ref_score = rank_ref.get_node_score(0, n).unwrap() as f64;
-      let score = cloned.get_node_score(0, n).unwrap() as f64;
+      let ref_score = rank_ref.get_node_score(0, n).unwrap();
+      let score = cloned.get_node_score(0, n).unwrap();
       assert_approx_eq!(ref_score, score, 0.1);
     }
   }
@@ -118,8 +534,8 @@ mod tests {
 
     //rank.print_walks();
     rank.set_edge(2, 0, 1.0).unwrap();
-    let ref_score = ref_rank.get_node_score(0, 2).unwrap() as f64;
-    let score = rank.get_node_score(0, 2).unwrap() as f64;
+    let ref_score = ref_rank.get_node_score(0, 2).unwrap();
+    let score = rank.get_node_score(0, 2).unwrap();
     assert_approx_eq!(ref_score, score, 0.2);
 
     println!("{:?}", rank.get_all_scores(0, None));
@@ -153,8 +569,8 @@ mod tests {
     //rank.print_walks();
     rank.set_edge(0, 1, 0.0).unwrap();
 
-    let ref_score = ref_rank.get_node_score(0, 2).unwrap() as f64;
-    let score = rank.get_node_score(0, 2).unwrap() as f64;
+    let ref_score = ref_rank.get_node_score(0, 2).unwrap();
+    let score = rank.get_node_score(0, 2).unwrap();
     assert_approx_eq!(ref_score, score, 0.1);
 
     println!("{:?}", rank.get_all_scores(0, None));
@@ -202,7 +618,7 @@ mod tests {
 
     // Sort the edges for consistent comparison
     let mut sorted_edges = outgoing_edges;
-    sorted_edges.sort_by(|a, b| a.0.cmp(&b.0));
+    sorted_edges.sort_by_key(|a| a.0);
 
     // Expected edges (sorted by NodeId)
     let expected_edges = vec![(node1, 1.0), (node2, -2.0), (node3, 3.0)];
@@ -248,6 +664,29 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_node_and_edge_counts() {
+    let mut graph = Graph::new();
+    let nodes: Vec<NodeId> = (0..3).map(|_| graph.get_new_nodeid()).collect();
+    assert_eq!(graph.nodes().collect::<Vec<_>>(), nodes);
+    assert_eq!(graph.node_count(), 3);
+    assert_eq!(graph.edge_count(), 0);
+
+    graph.set_edge(0, 1, 1.0).unwrap();
+    graph.set_edge(0, 2, -1.0).unwrap();
+    assert_eq!(graph.edge_count(), 2);
+
+    // Changing the weight of an edge keeps the count.
+    graph.set_edge(0, 1, 2.0).unwrap();
+    graph.set_edge(0, 2, 1.0).unwrap();
+    assert_eq!(graph.edge_count(), 2);
+
+    graph.remove_edge(0, 1).unwrap();
+    assert_eq!(graph.edge_count(), 1);
+    assert!(graph.set_edge(0, 2, 0.0).is_err());
+    assert_eq!(graph.edge_count(), 0);
+  }
+
   #[test]
   fn test_get_inbound_edges_not_affected_by_outbound_changes() {
     let mut graph = Graph::new();
@@ -279,7 +718,7 @@ mod tests {
       for (to, weight) in node_data.get_outgoing_edges() {
         manual_inbound_edges
           .entry(to)
-          .or_insert_with(HashMap::new)
+          .or_default()
           .insert(from, weight);
       }
     }
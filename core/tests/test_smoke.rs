@@ -76,4 +76,52 @@ mod tests {
       rank.calculate(*ego as NodeId).unwrap();
     }
   }
+
+  //  Stress of invalidation with very long walks: 1M edges, and walks that
+  //  almost never stop, so that every edge change cuts and re-extends walks
+  //  of thousands of steps. Run with --release to get meaningful timings.
+  #[cfg(feature = "expensive_tests")]
+  #[test]
+  fn smoke_long_walks_1m_edges() {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const NODES: usize = 100_000;
+    const DEGREE: usize = 10;
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut rank = MeritRank::new(Graph::new(), 100);
+    rank.seed_rng(0);
+    rank.alpha = 0.999;
+    for _ in 0..NODES {
+      rank.get_new_nodeid();
+    }
+    let begin = SystemTime::now();
+    let get_time =
+      || SystemTime::now().duration_since(begin).unwrap().as_millis();
+    for src in 0..NODES {
+      for _ in 0..DEGREE {
+        let dst = rng.random_range(0..NODES);
+        if dst != src {
+          let weight = if rng.random_bool(0.1) { -1.0 } else { 1.0 };
+          rank.graph.set_edge(src, dst, weight).unwrap();
+        }
+      }
+    }
+    println!("load: {} ms", get_time());
+
+    for ego in 0..10 {
+      rank.calculate(ego).unwrap();
+    }
+    println!("calculate: {} ms", get_time());
+
+    for _ in 0..1000 {
+      let src = rng.random_range(0..NODES);
+      let dst = rng.random_range(0..NODES);
+      if src != dst {
+        rank.set_edge(src, dst, 1.0).unwrap();
+      }
+    }
+    println!("set_edge: {} ms", get_time());
+    assert!(get_time() < 600_000);
+  }
 }
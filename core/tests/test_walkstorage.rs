@@ -32,7 +32,7 @@ mod tests {
     let walk3 = RandomWalk::from_nodes(vec![2, 3, 4]);
 
     walk_storage
-      .get_walk_mut(start1 + 0)
+      .get_walk_mut(start1)
       .unwrap()
       .extend(&walk1)
       .unwrap();
@@ -42,14 +42,14 @@ mod tests {
       .extend(&walk2)
       .unwrap();
     walk_storage
-      .get_walk_mut(start2 + 0)
+      .get_walk_mut(start2)
       .unwrap()
       .extend(&walk3)
       .unwrap();
 
-    walk_storage.update_walk_bookkeeping(start1 + 0, 0);
+    walk_storage.update_walk_bookkeeping(start1, 0);
     walk_storage.update_walk_bookkeeping(start1 + 1, 0);
-    walk_storage.update_walk_bookkeeping(start2 + 0, 0);
+    walk_storage.update_walk_bookkeeping(start2, 0);
 
     // Clear block for ego 1 (decrement counters, remove from visits, clear walks).
     let mut pos_hits = IntMap::default();
@@ -59,11 +59,55 @@ mod tests {
       .unwrap();
 
     // Walks for ego 1 are cleared; ego 2's walk (start2+0) unchanged.
-    assert!(walk_storage.get_walk(start1 + 0).unwrap().is_empty());
+    assert!(walk_storage.get_walk(start1).unwrap().is_empty());
     assert!(walk_storage.get_walk(start1 + 1).unwrap().is_empty());
-    assert_eq!(walk_storage.get_walk(start2 + 0).unwrap().len(), 3);
+    assert_eq!(walk_storage.get_walk(start2).unwrap().len(), 3);
 
     // ensure_block_for_ego(1) still returns same start (block reused in place).
     assert_eq!(walk_storage.ensure_block_for_ego(1).unwrap(), 0);
   }
+
+  #[test]
+  fn test_drop_walks_and_visits() {
+    const WALKS_PER_EGO: usize = 2;
+    let mut walk_storage = WalkStorage::new(WALKS_PER_EGO);
+    let start1 = walk_storage.ensure_block_for_ego(1).unwrap();
+    let start2 = walk_storage.ensure_block_for_ego(2).unwrap();
+
+    let walk1 = RandomWalk::from_nodes(vec![1, 2, 3]);
+    let walk2 = RandomWalk::from_nodes(vec![2, 3]);
+    walk_storage
+      .get_walk_mut(start1)
+      .unwrap()
+      .extend(&walk1)
+      .unwrap();
+    walk_storage
+      .get_walk_mut(start2)
+      .unwrap()
+      .extend(&walk2)
+      .unwrap();
+    walk_storage.update_walk_bookkeeping(start1, 0);
+    walk_storage.update_walk_bookkeeping(start2, 0);
+
+    let mut pos_hits = IntMap::default();
+    let mut neg_hits = IntMap::default();
+    assert!(walk_storage
+      .drop_walks_and_visits(1, &mut pos_hits, &mut neg_hits)
+      .unwrap());
+    assert!(!walk_storage
+      .drop_walks_and_visits(1, &mut pos_hits, &mut neg_hits)
+      .unwrap());
+
+    // No node's visits refer to the dropped walks.
+    for visits in walk_storage.get_walks() {
+      assert!(!visits.contains_key(&start1));
+    }
+    assert_eq!(walk_storage.get_visits_through_node(3).unwrap().len(), 1);
+    assert_eq!(walk_storage.get_block_start(1), None);
+    assert_eq!(walk_storage.get_walk_ego(start1), None);
+
+    // The freed block goes to the next new ego.
+    assert_eq!(walk_storage.ensure_block_for_ego(3).unwrap(), start1);
+    assert_eq!(walk_storage.get_walk_ego(start1), Some(3));
+  }
 }
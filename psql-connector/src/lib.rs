@@ -4,7 +4,7 @@ mod types;
 #[cfg(any(test, feature = "pg_test"))]
 pub mod testing;
 
-use meritrank_service::data::BulkEdge;
+use meritrank_service::data::{BulkEdge, EdgeProvenance};
 use pgrx::iter::TableIterator;
 use pgrx::*;
 use rpc::*;
@@ -94,6 +94,14 @@ fn mr_scores(
   gte: default!(Option<f64>, "null"),
   index: default!(Option<i64>, "0"),
   count: default!(Option<i64>, "16"),
+  num_clusters: default!(Option<i32>, "0"),
+  cluster_min: default!(Option<i32>, "null"),
+  cluster_max: default!(Option<i32>, "null"),
+  filter_on: default!(Option<&str>, "'blended'"),
+  negative_only: default!(Option<bool>, "false"),
+  ascending: default!(Option<bool>, "false"),
+  exclude_flags: default!(Option<i32>, "0"),
+  require_flags: default!(Option<i32>, "0"),
 ) -> Result<
   TableIterator<
     'static,
@@ -120,6 +128,340 @@ fn mr_scores(
     gte,
     index.unwrap_or(0) as u32,
     count.unwrap_or(i32::MAX as i64) as u32,
+    num_clusters.unwrap_or(0).max(0) as u32,
+    cluster_min.unwrap_or(0).max(0) as u32,
+    cluster_max.map_or(u32::MAX, |x| x.max(0) as u32),
+    filter_on.unwrap_or("blended"),
+    negative_only.unwrap_or(false),
+    ascending.unwrap_or(false),
+    exclude_flags.unwrap_or(0) as u32,
+    require_flags.unwrap_or(0) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_scores_bulk(
+  egos: Vec<String>,
+  hide_personal: default!(Option<bool>, "false"),
+  context: default!(Option<&str>, "''"),
+  kind: default!(Option<&str>, "''"),
+  lt: default!(Option<f64>, "null"),
+  lte: default!(Option<f64>, "null"),
+  gt: default!(Option<f64>, "null"),
+  gte: default!(Option<f64>, "null"),
+  index: default!(Option<i64>, "0"),
+  count: default!(Option<i64>, "16"),
+) -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(src, String),
+      name!(dst, String),
+      name!(score_value_of_dst, f64),
+      name!(score_value_of_src, f64),
+      name!(score_cluster_of_dst, i32),
+      name!(score_cluster_of_src, i32),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_scores_bulk(
+    egos,
+    hide_personal.unwrap_or(false),
+    ctx(context),
+    kind.unwrap_or(""),
+    lt,
+    lte,
+    gt,
+    gte,
+    index.unwrap_or(0) as u32,
+    count.unwrap_or(i32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_scores_at(
+  ego: Option<&str>,
+  timestamp: Option<i64>,
+  hide_personal: default!(Option<bool>, "false"),
+  context: default!(Option<&str>, "''"),
+  kind: default!(Option<&str>, "''"),
+  lt: default!(Option<f64>, "null"),
+  lte: default!(Option<f64>, "null"),
+  gt: default!(Option<f64>, "null"),
+  gte: default!(Option<f64>, "null"),
+  index: default!(Option<i64>, "0"),
+  count: default!(Option<i64>, "16"),
+) -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(src, String),
+      name!(dst, String),
+      name!(score_value_of_dst, f64),
+      name!(score_value_of_src, f64),
+      name!(score_cluster_of_dst, i32),
+      name!(score_cluster_of_src, i32),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_scores_at(
+    require(ego, "ego")?,
+    require(timestamp, "timestamp")?.max(0) as u64,
+    hide_personal.unwrap_or(false),
+    ctx(context),
+    kind.unwrap_or(""),
+    lt,
+    lte,
+    gt,
+    gte,
+    index.unwrap_or(0) as u32,
+    count.unwrap_or(i32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_score_diff(
+  ego: Option<&str>,
+  from_generation: Option<i64>,
+  to_generation: default!(Option<i64>, "0"),
+  min_change: default!(Option<f64>, "0"),
+  context: default!(Option<&str>, "''"),
+) -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(dst, String),
+      name!(old_score, f64),
+      name!(new_score, f64),
+      name!(old_cluster, i32),
+      name!(new_cluster, i32),
+      name!(generation, i64),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_score_diff(
+    require(ego, "ego")?,
+    require(from_generation, "from_generation")?.max(0) as u64,
+    to_generation.unwrap_or(0).max(0) as u64,
+    min_change.unwrap_or(0.0),
+    ctx(context),
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_compare_egos(
+  a: Option<&str>,
+  b: Option<&str>,
+  context: default!(Option<&str>, "''"),
+  limit: default!(Option<i64>, "16"),
+) -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(dst, String),
+      name!(score_a, f64),
+      name!(score_b, f64),
+      name!(cluster_a, i32),
+      name!(cluster_b, i32),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_compare_egos(
+    require(a, "a")?,
+    require(b, "b")?,
+    ctx(context),
+    limit.unwrap_or(16).clamp(0, u32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_dry_run_edge(
+  ego: Option<&str>,
+  src: Option<&str>,
+  dst: Option<&str>,
+  weight: Option<f64>,
+  context: default!(Option<&str>, "''"),
+) -> Result<
+  TableIterator<
+    'static,
+    (name!(dst, String), name!(before, f64), name!(after, f64)),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_dry_run_edge(
+    require(ego, "ego")?,
+    require(src, "src")?,
+    require(dst, "dst")?,
+    require(weight, "weight")?,
+    ctx(context),
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_recommendations(
+  ego: Option<&str>,
+  kind: default!(Option<&str>, "''"),
+  context: default!(Option<&str>, "''"),
+  limit: default!(Option<i64>, "16"),
+) -> Result<
+  TableIterator<
+    'static,
+    (name!(dst, String), name!(score, f64), name!(egos, i32)),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_recommendations(
+    require(ego, "ego")?,
+    kind.unwrap_or(""),
+    ctx(context),
+    limit.unwrap_or(16).clamp(0, u32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_top_nodes(
+  context: default!(Option<&str>, "''"),
+  kind: default!(Option<&str>, "''"),
+  ego: default!(Option<&str>, "NULL"),
+  index: default!(Option<i64>, "0"),
+  limit: default!(Option<i64>, "100"),
+) -> Result<
+  TableIterator<'static, (name!(node, String), name!(score, f64))>,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_top_nodes(
+    ctx(context),
+    kind.unwrap_or(""),
+    ego.filter(|x| !x.is_empty()),
+    index.unwrap_or(0).clamp(0, u32::MAX as i64) as u32,
+    limit.unwrap_or(100).clamp(0, u32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_anomalies(
+  context: default!(Option<&str>, "''"),
+  window: default!(Option<i64>, "3600"),
+  limit: default!(Option<i64>, "100"),
+) -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(node, String),
+      name!(score, f64),
+      name!(low_trust, f64),
+      name!(ring, f64),
+      name!(burst, f64),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_anomalies(
+    ctx(context),
+    window.unwrap_or(3600).max(0) as u64,
+    limit.unwrap_or(100).clamp(0, u32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_evaluate_sybil_resistance(
+  suspects: Vec<String>,
+  context: default!(Option<&str>, "''"),
+  egos: default!(Option<i64>, "100"),
+  top_k: default!(Option<i64>, "100"),
+) -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(egos, i64),
+      name!(attack_edges, i64),
+      name!(before, f64),
+      name!(after, f64),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_evaluate_sybil_resistance(
+    suspects,
+    ctx(context),
+    egos.unwrap_or(100).clamp(0, u32::MAX as i64) as u32,
+    top_k.unwrap_or(100).clamp(0, u32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_beacon_comments(
+  ego: Option<&str>,
+  beacon: Option<&str>,
+  comments: Vec<String>,
+  context: default!(Option<&str>, "''"),
+  beacon_weight: default!(Option<f64>, "1.0"),
+  comment_weight: default!(Option<f64>, "1.0"),
+  author_weight: default!(Option<f64>, "1.0"),
+  limit: default!(Option<i64>, "100"),
+) -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(comment, String),
+      name!(author, Option<String>),
+      name!(score, f64),
+      name!(comment_score, f64),
+      name!(author_score, f64),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_beacon_comments(
+    require(ego, "ego")?,
+    require(beacon, "beacon")?,
+    comments,
+    ctx(context),
+    (
+      beacon_weight.unwrap_or(1.0),
+      comment_weight.unwrap_or(1.0),
+      author_weight.unwrap_or(1.0),
+    ),
+    limit.unwrap_or(100).clamp(0, u32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_mutual_suggestions(
+  ego: Option<&str>,
+  context: default!(Option<&str>, "''"),
+  limit: default!(Option<i64>, "16"),
+) -> Result<
+  TableIterator<
+    'static,
+    (name!(dst, String), name!(peers, i32), name!(weight, f64)),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_mutual_suggestions(
+    require(ego, "ego")?,
+    ctx(context),
+    limit.unwrap_or(16).clamp(0, u32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_score_history(
+  ego: Option<&str>,
+  target: Option<&str>,
+  context: default!(Option<&str>, "''"),
+) -> Result<
+  TableIterator<'static, (name!(time, i64), name!(score, f64))>,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_score_history(
+    require(ego, "ego")?,
+    require(target, "target")?,
+    ctx(context),
   )?))
 }
 
@@ -172,6 +514,7 @@ fn mr_neighbors(
   gte: default!(Option<f64>, "null"),
   index: default!(Option<i64>, "0"),
   count: default!(Option<i64>, "16"),
+  edge_kind: default!(Option<&str>, "''"),
 ) -> Result<
   TableIterator<
     'static,
@@ -182,6 +525,9 @@ fn mr_neighbors(
       name!(score_value_of_src, f64),
       name!(score_cluster_of_dst, i32),
       name!(score_cluster_of_src, i32),
+      name!(source, Option<String>),
+      name!(external_id, Option<String>),
+      name!(event_time, Option<i64>),
     ),
   >,
   Box<dyn Error + 'static>,
@@ -209,6 +555,7 @@ fn mr_neighbors(
     gte,
     index.unwrap_or(0) as u32,
     count.unwrap_or(i32::MAX as i64) as u32,
+    edge_kind.unwrap_or(""),
   )?))
 }
 
@@ -235,6 +582,23 @@ fn mr_edgelist(
   Ok(TableIterator::new(new_edgelist(ctx(context))?))
 }
 
+#[pg_extern]
+fn mr_list_contexts() -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(context, String),
+      name!(nodes, i64),
+      name!(edges, i64),
+      name!(last_access, i64),
+      name!(walks, i64),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_list_contexts()?))
+}
+
 #[pg_extern(immutable)]
 fn mr_connected(
   src: Option<&str>,
@@ -312,6 +676,228 @@ fn mr_create_context(
   new_create_context(ctx(context))
 }
 
+#[pg_extern]
+fn mr_delete_context(
+  context: Option<&str>,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_delete_context(require(context, "context")?)
+}
+
+#[pg_extern]
+fn mr_move_context(
+  context: Option<&str>,
+  shard: Option<&str>,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_move_context(require(context, "context")?, require(shard, "shard")?)
+}
+
+#[pg_extern]
+fn mr_set_score_clusters(
+  num_clusters: Option<i32>,
+  context: default!(Option<&str>, "''"),
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let num_clusters = require(num_clusters, "num_clusters")?;
+  if num_clusters < 2 {
+    return Err("num_clusters must be at least 2".into());
+  }
+  new_set_score_clusters(num_clusters as u32, ctx(context))
+}
+
+#[pg_extern]
+fn mr_fork_context(
+  source: Option<&str>,
+  destination: Option<&str>,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_fork_context(ctx(source), require(destination, "destination")?)
+}
+
+#[pg_extern]
+fn mr_merge_context(
+  source: Option<&str>,
+  destination: Option<&str>,
+  strategy: default!(Option<&str>, "'sum'"),
+  dry_run: default!(Option<bool>, "false"),
+) -> Result<
+  TableIterator<'static, (name!(conflicts, i64), name!(merged, i64))>,
+  Box<dyn Error + 'static>,
+> {
+  let strategy = require(strategy, "strategy")?;
+  let dry_run = require(dry_run, "dry_run")?;
+  Ok(TableIterator::new(new_merge_context(
+    ctx(source),
+    ctx(destination),
+    strategy,
+    dry_run,
+  )?))
+}
+
+#[pg_extern]
+fn mr_export_scores(
+  destination: Option<&str>,
+  format: default!(Option<&str>, "'csv'"),
+  egos: default!(Option<Vec<String>>, "NULL"),
+  context: default!(Option<&str>, "''"),
+  timeout_msec: default!(Option<i64>, "600000"),
+) -> Result<
+  TableIterator<'static, (name!(egos, i64), name!(rows, i64))>,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_export_scores(
+    ctx(context),
+    egos.unwrap_or_default(),
+    require(format, "format")?,
+    require(destination, "destination")?,
+    timeout_u64(timeout_msec),
+  )?))
+}
+
+#[pg_extern]
+fn mr_gc_orphans(
+  context: default!(Option<&str>, "''"),
+  dry_run: default!(Option<bool>, "false"),
+) -> Result<
+  TableIterator<'static, (name!(orphans, i64), name!(nodes, i64))>,
+  Box<dyn Error + 'static>,
+> {
+  let dry_run = require(dry_run, "dry_run")?;
+  Ok(TableIterator::new(new_gc_orphans(ctx(context), dry_run)?))
+}
+
+#[pg_extern]
+fn mr_recalculate_ego(
+  ego: Option<&str>,
+  context: default!(Option<&str>, "''"),
+) -> Result<
+  TableIterator<'static, (name!(egos, i64), name!(elapsed_ms, i64))>,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_recalculate_ego(
+    require(ego, "ego")?,
+    ctx(context),
+  )?))
+}
+
+#[pg_extern]
+fn mr_recalculate_all(
+  context: default!(Option<&str>, "''")
+) -> Result<
+  TableIterator<'static, (name!(egos, i64), name!(elapsed_ms, i64))>,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_recalculate_all(ctx(context))?))
+}
+
+#[pg_extern]
+fn mr_put_webhook(
+  url: Option<&str>,
+  context: default!(Option<&str>, "NULL"),
+  egos: default!(Option<Vec<String>>, "'{}'"),
+  min_change: default!(Option<f64>, "0"),
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_put_webhook(
+    require(url, "url")?,
+    context,
+    egos.unwrap_or_default(),
+    min_change.unwrap_or(0.0),
+  )
+}
+
+#[pg_extern]
+fn mr_delete_webhook(
+  url: Option<&str>
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_delete_webhook(require(url, "url")?)
+}
+
+#[pg_extern]
+fn mr_webhooks() -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(url, String),
+      name!(context, Option<String>),
+      name!(egos, Vec<String>),
+      name!(min_change, f64),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_webhooks()?))
+}
+
+#[pg_extern]
+fn mr_slow_log(
+  limit: default!(Option<i64>, "20")
+) -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(time, i64),
+      name!(opcode, String),
+      name!(context, String),
+      name!(total_ms, f64),
+      name!(calculate_ms, f64),
+      name!(walks, i64),
+      name!(outcome, String),
+      name!(request, String),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  let limit = limit.unwrap_or(20).clamp(0, u32::MAX as i64) as u32;
+  Ok(TableIterator::new(new_slow_log(limit)?))
+}
+
+#[pg_extern]
+fn mr_rerank(
+  force: default!(Option<bool>, "false")
+) -> Result<
+  TableIterator<'static, (name!(context, String),)>,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_rerank(force.unwrap_or(false))?))
+}
+
+#[pg_extern]
+fn mr_debug_ego(
+  ego: Option<&str>,
+  context: default!(Option<&str>, "''"),
+  walks: default!(Option<i64>, "10"),
+) -> Result<
+  TableIterator<
+    'static,
+    (name!(entry, String), name!(node, String), name!(count, i64)),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_debug_ego(
+    require(ego, "ego")?,
+    ctx(context),
+    walks.unwrap_or(10).clamp(0, u32::MAX as i64) as u32,
+  )?))
+}
+
+#[pg_extern(immutable)]
+fn mr_quota(
+  context: default!(Option<&str>, "''")
+) -> Result<
+  TableIterator<
+    'static,
+    (
+      name!(nodes, i64),
+      name!(max_nodes, i64),
+      name!(edges, i64),
+      name!(max_edges, i64),
+      name!(memory, i64),
+      name!(total_memory, i64),
+      name!(max_memory, i64),
+    ),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_quota(ctx(context))?))
+}
+
 #[pg_extern]
 fn mr_put_edge(
   src: Option<&str>,
@@ -319,6 +905,7 @@ fn mr_put_edge(
   weight: Option<f64>,
   context: default!(Option<&str>, "''"),
   index: default!(Option<i64>, "-1"),
+  edge_kind: default!(Option<&str>, "''"),
 ) -> Result<
   TableIterator<
     'static,
@@ -331,7 +918,7 @@ fn mr_put_edge(
   let dest = require(dst, "dst")?;
   let weight = require(weight, "weight")?;
   let index = require(index, "index")?;
-  new_put_edge(src, dest, weight, c, index)?;
+  new_put_edge(src, dest, weight, c, index, edge_kind.unwrap_or(""))?;
   Ok(TableIterator::once((
     src.to_string(),
     dest.to_string(),
@@ -339,6 +926,92 @@ fn mr_put_edge(
   )))
 }
 
+#[pg_extern]
+fn mr_put_edge_with_provenance(
+  src: Option<&str>,
+  dst: Option<&str>,
+  weight: Option<f64>,
+  source: Option<&str>,
+  external_id: Option<&str>,
+  event_time: Option<i64>,
+  context: default!(Option<&str>, "''"),
+) -> Result<
+  TableIterator<
+    'static,
+    (name!(src, String), name!(dst, String), name!(weight, f64)),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  let src = require(src, "src")?;
+  let dest = require(dst, "dst")?;
+  let weight = require(weight, "weight")?;
+  new_put_edge_with_provenance(
+    src,
+    dest,
+    weight,
+    EdgeProvenance {
+      source:      require(source, "source")?.to_string(),
+      external_id: require(external_id, "external_id")?.to_string(),
+      timestamp:   require(event_time, "event_time")? as u64,
+    },
+    ctx(context),
+  )?;
+  Ok(TableIterator::once((
+    src.to_string(),
+    dest.to_string(),
+    weight,
+  )))
+}
+
+#[pg_extern]
+fn mr_put_edge_cas(
+  src: Option<&str>,
+  dst: Option<&str>,
+  expected_weight: Option<f64>,
+  weight: Option<f64>,
+  context: default!(Option<&str>, "''"),
+  edge_kind: default!(Option<&str>, "''"),
+) -> Result<
+  TableIterator<
+    'static,
+    (name!(src, String), name!(dst, String), name!(weight, f64)),
+  >,
+  Box<dyn Error + 'static>,
+> {
+  let src = require(src, "src")?;
+  let dest = require(dst, "dst")?;
+  let weight = require(weight, "weight")?;
+  new_put_edge_cas(
+    src,
+    dest,
+    require(expected_weight, "expected_weight")?,
+    weight,
+    ctx(context),
+    edge_kind.unwrap_or(""),
+  )?;
+  Ok(TableIterator::once((
+    src.to_string(),
+    dest.to_string(),
+    weight,
+  )))
+}
+
+#[pg_extern]
+fn mr_put_node(
+  name: Option<&str>,
+  kind: default!(Option<&str>, "null"),
+  flags: default!(Option<i32>, "0"),
+  context: default!(Option<&str>, "''"),
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let name = require(name, "name")?;
+  new_put_node(
+    name,
+    kind.unwrap_or(name),
+    flags.unwrap_or(0) as u32,
+    ctx(context),
+  )
+}
+
 #[pg_extern]
 fn mr_delete_edge(
   src: Option<&str>,
@@ -365,6 +1038,14 @@ fn mr_delete_node(
   new_delete_node(ego, c, index)
 }
 
+#[pg_extern]
+fn mr_rename_node(
+  old: Option<&str>,
+  new: Option<&str>,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_rename_node(require(old, "old")?, require(new, "new")?)
+}
+
 #[pg_extern]
 fn mr_set_zero_opinion(
   node: Option<&str>,
@@ -377,6 +1058,62 @@ fn mr_set_zero_opinion(
   new_set_zero_opinion(node, score, c)
 }
 
+#[pg_extern]
+fn mr_set_user_params(
+  ego: Option<&str>,
+  alpha: Option<f64>,
+  positive_only: default!(Option<bool>, "false"),
+  context: default!(Option<&str>, "''"),
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_set_user_params(
+    require(ego, "ego")?,
+    alpha,
+    positive_only.unwrap_or(false),
+    ctx(context),
+  )
+}
+
+#[pg_extern]
+fn mr_mute(
+  ego: Option<&str>,
+  node: Option<&str>,
+  muted: default!(Option<bool>, "true"),
+  context: default!(Option<&str>, "''"),
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_mute(
+    require(ego, "ego")?,
+    require(node, "node")?,
+    muted.unwrap_or(true),
+    ctx(context),
+  )
+}
+
+#[pg_extern]
+fn mr_set_poll(
+  poll: Option<&str>,
+  expires_at: default!(Option<i64>, "0"),
+  anonymous: default!(Option<bool>, "false"),
+  context: default!(Option<&str>, "''"),
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_set_poll(
+    require(poll, "poll")?,
+    expires_at.unwrap_or(0).max(0) as u64,
+    anonymous.unwrap_or(false),
+    ctx(context),
+  )
+}
+
+#[pg_extern(immutable)]
+fn mr_muted(
+  ego: Option<&str>,
+  context: default!(Option<&str>, "''"),
+) -> Result<
+  TableIterator<'static, (name!(node, String),)>,
+  Box<dyn Error + 'static>,
+> {
+  Ok(TableIterator::new(new_muted(require(ego, "ego")?, ctx(context))?))
+}
+
 #[pg_extern]
 fn mr_bulk_load_edges(
   src_arr: Vec<String>,
@@ -410,6 +1147,50 @@ fn mr_bulk_load_edges(
   new_bulk_load_edges(edges, timeout_u64(timeout_msec))
 }
 
+#[pg_extern]
+fn mr_put_edges_multi(
+  src_arr: Vec<String>,
+  dst_arr: Vec<String>,
+  weight_arr: Vec<f64>,
+  context_arr: Vec<String>,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  if src_arr.len() != dst_arr.len()
+    || src_arr.len() != weight_arr.len()
+    || src_arr.len() != context_arr.len()
+  {
+    return Err("All arrays must have the same length".into());
+  }
+  let edges: Vec<BulkEdge> = src_arr
+    .into_iter()
+    .zip(dst_arr)
+    .zip(weight_arr)
+    .zip(context_arr)
+    .map(|(((src, dst), amount), context)| BulkEdge {
+      src,
+      dst,
+      amount,
+      magnitude: 0,
+      context,
+    })
+    .collect();
+  new_put_edges_multi(edges)
+}
+
+#[pg_extern]
+fn mr_import_from_sql(
+  timeout_msec: default!(Option<i64>, "120000")
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_import_from_sql(timeout_u64(timeout_msec))
+}
+
+#[pg_extern]
+fn mr_import_edge_list(
+  path: &str,
+  timeout_msec: default!(Option<i64>, "120000"),
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_import_edge_list(path, timeout_u64(timeout_msec))
+}
+
 #[pg_extern]
 fn mr_set_new_edges_filter(
   src: Option<&str>,
@@ -448,6 +1229,18 @@ fn mr_reset() -> Result<&'static str, Box<dyn Error + 'static>> {
   new_reset()
 }
 
+#[pg_extern]
+fn mr_promote() -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_promote()
+}
+
+#[pg_extern]
+fn mr_set_maintenance_mode(
+  read_only: Option<bool>
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  new_set_maintenance_mode(require(read_only, "read_only")?)
+}
+
 #[pg_extern]
 fn mr_zerorec(
   //  D6 (JOURNAL): blocking flag is no longer relevant in the new protocol;
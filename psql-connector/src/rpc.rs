@@ -3,21 +3,31 @@
 //  D7 (timeout), D9 (magnitude).
 
 use meritrank_service::data::*;
-use meritrank_service::rpc_sync::{read_response_sync, set_read_timeout, write_request_sync};
+use meritrank_service::rpc_sync::{
+  connect_tls_sync,
+  read_response_sync,
+  request_compression_sync,
+  set_read_timeout,
+  write_request_sync,
+  SyncStream,
+};
+use meritrank_service::tls::{tls_client_config, ClientConfig};
 
 use std::cell::RefCell;
 use std::env::var;
 use std::error::Error;
 use std::io;
 use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
 use std::sync::{
   atomic::{AtomicU64, Ordering},
+  Arc,
   LazyLock,
 };
 use std::time::Duration;
 
 thread_local! {
-  static CONN: RefCell<Option<TcpStream>> = RefCell::new(None);
+  static CONN: RefCell<Option<SyncStream>> = RefCell::new(None);
 }
 
 //  D5 (JOURNAL): reuse MERITRANK_SERVICE_URL, default changes to port 8080.
@@ -34,6 +44,31 @@ pub static RECV_TIMEOUT_MSEC: LazyLock<u64> = LazyLock::new(|| {
     .unwrap_or(10000)
 });
 
+//  Token sent with every request; checked by the service when MERITRANK_ACL is set.
+pub static SERVICE_TOKEN: LazyLock<String> =
+  LazyLock::new(|| var("MERITRANK_SERVICE_TOKEN").unwrap_or_default());
+
+//  Ask the service to compress large responses; worth it when the service
+//  runs on another host.
+pub static COMPRESSION: LazyLock<bool> = LazyLock::new(|| {
+  var("MERITRANK_COMPRESSION")
+    .ok()
+    .and_then(|s| s.parse::<bool>().ok())
+    .unwrap_or(false)
+});
+
+//  TLS to the service, same variables as for the connections of the service
+//  to its shards. Certificates that fail to load fail every call.
+static TLS_CONFIG: LazyLock<Result<Option<Arc<ClientConfig>>, String>> =
+  LazyLock::new(|| {
+    tls_client_config(
+      &var("MERITRANK_TLS_CA").unwrap_or_default(),
+      &var("MERITRANK_TLS_CLIENT_CERT").unwrap_or_default(),
+      &var("MERITRANK_TLS_CLIENT_KEY").unwrap_or_default(),
+    )
+    .map_err(|e| format!("Failed to load TLS certificates: {}", e))
+  });
+
 //  D4 (JOURNAL): monotonically-increasing stamp for Sync requests.
 static SYNC_STAMP: AtomicU64 = AtomicU64::new(0);
 
@@ -45,39 +80,48 @@ fn strip_scheme(url: &str) -> &str {
   }
 }
 
-fn get_or_reconnect(timeout: Duration) -> io::Result<TcpStream> {
-  CONN.with(|cell| {
-    let mut opt = cell.borrow_mut();
-    if let Some(ref stream) = *opt {
-      if let Ok(cloned) = stream.try_clone() {
-        return Ok(cloned);
-      }
-      *opt = None;
-    }
-    let addr_str = strip_scheme(&SERVICE_URL);
-    let addrs: Vec<_> = addr_str.to_socket_addrs()?.collect();
-    let mut last_err = None;
-    for addr in &addrs {
-      match TcpStream::connect_timeout(addr, timeout) {
-        Ok(s) => {
-          let cloned = s
-            .try_clone()
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-          *opt = Some(cloned);
-          return Ok(s);
+fn connect(timeout: Duration) -> io::Result<SyncStream> {
+  if let Some(path) = SERVICE_URL.strip_prefix("unix://") {
+    return Ok(SyncStream::Unix(UnixStream::connect(path)?));
+  }
+  let addr_str = strip_scheme(&SERVICE_URL);
+  let tls = (*TLS_CONFIG).clone().map_err(io::Error::other)?;
+  let addrs: Vec<_> = addr_str.to_socket_addrs()?.collect();
+  let mut last_err = None;
+  for addr in &addrs {
+    match TcpStream::connect_timeout(addr, timeout) {
+      Ok(s) => match &tls {
+        Some(config) => {
+          //  Bounds the handshake as well.
+          s.set_read_timeout(Some(timeout))?;
+          return connect_tls_sync(Arc::clone(config), s, addr_str);
         },
-        Err(e) => last_err = Some(e),
-      }
+        None => return Ok(SyncStream::Tcp(s)),
+      },
+      Err(e) => last_err = Some(e),
     }
-    Err(last_err.unwrap_or_else(|| {
-      io::Error::new(io::ErrorKind::Other, "no addresses resolved")
-    }))
-  })
+  }
+  Err(last_err.unwrap_or_else(|| {
+    io::Error::new(io::ErrorKind::Other, "no addresses resolved")
+  }))
+}
+
+//  The connection is taken out for a call and kept again once the call
+//  succeeds, so a broken one is dropped. TLS connections can't be cloned.
+fn take_or_connect(timeout: Duration) -> io::Result<SyncStream> {
+  if let Some(stream) = CONN.with(|cell| cell.borrow_mut().take()) {
+    return Ok(stream);
+  }
+  let mut s = connect(timeout)?;
+  if *COMPRESSION {
+    request_compression_sync(&mut s)?;
+  }
+  Ok(s)
 }
 
-fn invalidate_conn() {
+fn keep_conn(stream: SyncStream) {
   CONN.with(|cell| {
-    *cell.borrow_mut() = None;
+    *cell.borrow_mut() = Some(stream);
   });
 }
 
@@ -91,25 +135,24 @@ fn tcp_call(
 
   let req = Request {
     subgraph: subgraph.to_string(),
+    token: SERVICE_TOKEN.clone(),
     data,
   };
 
-  let result = (|| -> io::Result<Response> {
-    let mut stream = get_or_reconnect(timeout_dur)?;
+  let call = || -> io::Result<Response> {
+    let mut stream = take_or_connect(timeout_dur)?;
     set_read_timeout(&mut stream, Some(timeout))?;
     write_request_sync(&mut stream, &req)?;
-    read_response_sync(&mut stream)
-  })();
+    let resp = read_response_sync(&mut stream)?;
+    keep_conn(stream);
+    Ok(resp)
+  };
 
-  match result {
+  //  A kept connection may have been closed by the service; the retry
+  //  connects anew.
+  match call() {
     Ok(resp) => Ok(resp),
-    Err(_) => {
-      invalidate_conn();
-      let mut stream = get_or_reconnect(timeout_dur)?;
-      set_read_timeout(&mut stream, Some(timeout))?;
-      write_request_sync(&mut stream, &req)?;
-      Ok(read_response_sync(&mut stream)?)
-    },
+    Err(_) => Ok(call()?),
   }
 }
 
@@ -118,6 +161,8 @@ fn expect_ok(resp: Response) -> Result<&'static str, Box<dyn Error + 'static>> {
     Response::Ok => Ok("Ok"),
     Response::Fail => Err("Service returned Fail".into()),
     Response::NotImplemented => Err("meritrank: operation not implemented".into()),
+    Response::Error(e) => Err(format!("Service returned error: {:?}", e).into()),
+    Response::Busy => Err("Service is busy, retry later".into()),
     other => Err(format!("Unexpected response: {:?}", other).into()),
   }
 }
@@ -133,6 +178,24 @@ pub fn new_reset() -> Result<&'static str, Box<dyn Error + 'static>> {
   expect_ok(resp)
 }
 
+pub fn new_promote() -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call("", ReqData::Promote, Some(*RECV_TIMEOUT_MSEC))?;
+  expect_ok(resp)
+}
+
+pub fn new_set_maintenance_mode(
+  read_only: bool
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    "",
+    ReqData::SetMaintenanceMode(OpSetMaintenanceMode {
+      read_only,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
 pub fn new_create_context(
   context: &str
 ) -> Result<&'static str, Box<dyn Error + 'static>> {
@@ -141,28 +204,385 @@ pub fn new_create_context(
   expect_ok(resp)
 }
 
+pub fn new_fork_context(
+  source: &str,
+  destination: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    "",
+    ReqData::WriteForkContext(OpWriteForkContext {
+      source:      source.to_string(),
+      destination: destination.to_string(),
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_merge_context(
+  source: &str,
+  destination: &str,
+  strategy: &str,
+  dry_run: bool,
+) -> Result<Vec<(i64, i64)>, Box<dyn Error + 'static>> {
+  let strategy = match strategy {
+    "sum" => MergeStrategy::Sum,
+    "max" => MergeStrategy::Max,
+    "overwrite" => MergeStrategy::Overwrite,
+    x => {
+      return Err(
+        format!("Invalid merge strategy: {:?} (sum, max, overwrite)", x)
+          .into(),
+      )
+    },
+  };
+  match tcp_call(
+    "",
+    ReqData::WriteMergeContext(OpWriteMergeContext {
+      source: source.to_string(),
+      destination: destination.to_string(),
+      strategy,
+      dry_run,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::Merge(r) => Ok(vec![(r.conflicts as i64, r.merged as i64)]),
+    other => expect_ok(other).map(|_| vec![]),
+  }
+}
+
+pub fn new_gc_orphans(
+  context: &str,
+  dry_run: bool,
+) -> Result<Vec<(i64, i64)>, Box<dyn Error + 'static>> {
+  match tcp_call(
+    context,
+    ReqData::WriteGcOrphans(OpWriteGcOrphans {
+      dry_run,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::GcOrphans(r) => Ok(vec![(r.orphans as i64, r.nodes as i64)]),
+    other => expect_ok(other).map(|_| vec![]),
+  }
+}
+
+pub fn new_recalculate_ego(
+  ego: &str,
+  context: &str,
+) -> Result<Vec<(i64, i64)>, Box<dyn Error + 'static>> {
+  recalculate(
+    context,
+    ReqData::RecalculateEgo(OpRecalculateEgo {
+      ego: ego.to_string(),
+    }),
+  )
+}
+
+pub fn new_recalculate_all(
+  context: &str
+) -> Result<Vec<(i64, i64)>, Box<dyn Error + 'static>> {
+  recalculate(context, ReqData::RecalculateAll)
+}
+
+fn recalculate(
+  context: &str,
+  data: ReqData,
+) -> Result<Vec<(i64, i64)>, Box<dyn Error + 'static>> {
+  match tcp_call(context, data, Some(*RECV_TIMEOUT_MSEC))? {
+    Response::Recalculation(r) => {
+      Ok(vec![(r.egos as i64, r.elapsed_ms as i64)])
+    },
+    other => expect_ok(other).map(|_| vec![]),
+  }
+}
+
+pub fn new_put_webhook(
+  url: &str,
+  context: Option<&str>,
+  egos: Vec<String>,
+  min_change: f64,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    "",
+    ReqData::WriteWebhook(OpWriteWebhook {
+      url: url.to_string(),
+      context: context.map(|x| x.to_string()),
+      egos,
+      min_change,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_delete_webhook(
+  url: &str
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    "",
+    ReqData::DeleteWebhook(OpDeleteWebhook {
+      url: url.to_string(),
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+/// `url`, `context` (NULL for all contexts), `egos` (empty for all
+/// calculated egos) and `min_change`.
+pub type WebhookRow = (String, Option<String>, Vec<String>, f64);
+
+pub fn new_webhooks() -> Result<Vec<WebhookRow>, Box<dyn Error + 'static>> {
+  match tcp_call("", ReqData::ReadWebhooks, Some(*RECV_TIMEOUT_MSEC))? {
+    Response::Webhooks(r) => Ok(
+      r.webhooks
+        .into_iter()
+        .map(|x| (x.url, x.context, x.egos, x.min_change))
+        .collect(),
+    ),
+    other => expect_ok(other).map(|_| vec![]),
+  }
+}
+
+/// `time`, `opcode`, `context`, `total_ms`, `calculate_ms`, `walks`,
+/// `outcome` and `request` of a slow request.
+pub type SlowOpRow = (i64, String, String, f64, f64, i64, String, String);
+
+pub fn new_slow_log(
+  limit: u32
+) -> Result<Vec<SlowOpRow>, Box<dyn Error + 'static>> {
+  match tcp_call(
+    "",
+    ReqData::ReadSlowLog(OpReadSlowLog { limit }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::SlowLog(r) => Ok(
+      r.ops
+        .into_iter()
+        .map(|x| {
+          (
+            x.time as i64,
+            x.opcode,
+            x.context,
+            x.total_us as f64 / 1000.0,
+            x.calculate_us as f64 / 1000.0,
+            x.walks as i64,
+            x.outcome,
+            x.request,
+          )
+        })
+        .collect(),
+    ),
+    other => expect_ok(other).map(|_| vec![]),
+  }
+}
+
+/// Contexts whose egos were recalculated.
+pub fn new_rerank(
+  force: bool
+) -> Result<Vec<(String,)>, Box<dyn Error + 'static>> {
+  match tcp_call(
+    "",
+    ReqData::Rerank(OpRerank {
+      force,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::Rerank(r) => {
+      Ok(r.contexts.into_iter().map(|context| (context,)).collect())
+    },
+    other => expect_ok(other).map(|_| vec![]),
+  }
+}
+
+/// Rows of `walks` (the number of walks), `positive` and `negative` hits,
+/// and `walk` paths of the sample, numbered from 0.
+pub fn new_debug_ego(
+  ego: &str,
+  context: &str,
+  walks: u32,
+) -> Result<Vec<(String, String, i64)>, Box<dyn Error + 'static>> {
+  match tcp_call(
+    context,
+    ReqData::DebugEgo(OpDebugEgo {
+      ego: ego.to_string(),
+      walks,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::DebugEgo(r) => {
+      let mut rows = vec![("walks".into(), String::new(), r.walks as i64)];
+      for (entry, hits) in
+        [("positive", r.positive_hits), ("negative", r.negative_hits)]
+      {
+        rows.extend(
+          hits
+            .into_iter()
+            .map(|(node, count)| (entry.to_string(), node, count)),
+        );
+      }
+      rows.extend(r.sample.into_iter().enumerate().map(|(i, walk)| {
+        ("walk".to_string(), walk.join(" > "), i as i64)
+      }));
+      Ok(rows)
+    },
+    Response::NotImplemented => {
+      Err("Debug ops are disabled, see MERITRANK_DEBUG_OPS".into())
+    },
+    other => expect_ok(other).map(|_| vec![]),
+  }
+}
+
+pub fn new_quota(
+  context: &str
+) -> Result<Vec<(i64, i64, i64, i64, i64, i64, i64)>, Box<dyn Error + 'static>>
+{
+  match tcp_call(context, ReqData::ReadQuota, Some(*RECV_TIMEOUT_MSEC))? {
+    Response::Quota(r) => Ok(vec![(
+      r.nodes as i64,
+      r.max_nodes as i64,
+      r.edges as i64,
+      r.max_edges as i64,
+      r.memory as i64,
+      r.total_memory as i64,
+      r.max_memory as i64,
+    )]),
+    other => expect_ok(other).map(|_| vec![]),
+  }
+}
+
+pub fn new_delete_context(
+  context: &str
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp =
+    tcp_call(context, ReqData::WriteDeleteContext, Some(*RECV_TIMEOUT_MSEC))?;
+  expect_ok(resp)
+}
+
+pub fn new_move_context(
+  context: &str,
+  shard: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    context,
+    ReqData::MoveContext(OpMoveContext {
+      shard: shard.to_string(),
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_set_score_clusters(
+  num_clusters: u32,
+  context: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    context,
+    ReqData::WriteScoreClusters(OpWriteScoreClusters {
+      num_clusters,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
 pub fn new_put_edge(
   src: &str,
   dst: &str,
   weight: f64,
   context: &str,
   index: i64,
+  edge_kind: &str,
 ) -> Result<&'static str, Box<dyn Error + 'static>> {
   //  D9 (JOURNAL): negative index maps to magnitude 0.
   let magnitude = if index < 0 { 0u32 } else { index as u32 };
   let resp = tcp_call(
     context,
     ReqData::WriteEdge(OpWriteEdge {
-      src:       src.to_string(),
-      dst:       dst.to_string(),
-      amount:    weight,
+      src:        src.to_string(),
+      dst:        dst.to_string(),
+      amount:     weight,
       magnitude,
+      edge_kind:  edge_kind_filter(edge_kind)?.unwrap_or_default(),
+      provenance: None,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_put_edge_with_provenance(
+  src: &str,
+  dst: &str,
+  weight: f64,
+  provenance: EdgeProvenance,
+  context: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    context,
+    ReqData::WriteEdge(OpWriteEdge {
+      src:        src.to_string(),
+      dst:        dst.to_string(),
+      amount:     weight,
+      magnitude:  0,
+      edge_kind:  EdgeKind::Vote,
+      provenance: Some(provenance),
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_put_edge_cas(
+  src: &str,
+  dst: &str,
+  expected_weight: f64,
+  weight: f64,
+  context: &str,
+  edge_kind: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    context,
+    ReqData::WriteEdgeCas(OpWriteEdgeCas {
+      src:                 src.to_string(),
+      dst:                 dst.to_string(),
+      expected_old_weight: expected_weight,
+      new_weight:          weight,
+      edge_kind:           edge_kind_filter(edge_kind)?.unwrap_or_default(),
     }),
     Some(*RECV_TIMEOUT_MSEC),
   )?;
   expect_ok(resp)
 }
 
+/// Writes the edges to their contexts together, see `WriteMulti`.
+pub fn new_put_edges_multi(
+  edges: Vec<BulkEdge>
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let ops = edges
+    .into_iter()
+    .map(|edge| {
+      let op = ReqData::WriteEdge(OpWriteEdge {
+        src:        edge.src,
+        dst:        edge.dst,
+        amount:     edge.amount,
+        magnitude:  edge.magnitude,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      });
+      (edge.context, op)
+    })
+    .collect();
+  let resp = tcp_call(
+    "",
+    ReqData::WriteMulti(OpWriteMulti { ops }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
 pub fn new_bulk_load_edges(
   edges: Vec<BulkEdge>,
   timeout_msec: Option<u64>,
@@ -176,184 +596,746 @@ pub fn new_bulk_load_edges(
   expect_ok(resp)
 }
 
-pub fn new_delete_edge(
+pub fn new_import_from_sql(
+  timeout_msec: Option<u64>
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let timeout = timeout_msec.unwrap_or(120_000);
+  let resp = tcp_call("", ReqData::ImportFromSql, Some(timeout))?;
+  expect_ok(resp)
+}
+
+pub fn new_import_edge_list(
+  path: &str,
+  timeout_msec: Option<u64>,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let timeout = timeout_msec.unwrap_or(120_000);
+  let resp = tcp_call(
+    "",
+    ReqData::ImportEdgeList(OpImportEdgeList {
+      path: path.into(),
+    }),
+    Some(timeout),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_export_scores(
+  context: &str,
+  egos: Vec<String>,
+  format: &str,
+  destination: &str,
+  timeout_msec: Option<u64>,
+) -> Result<Vec<(i64, i64)>, Box<dyn Error + 'static>> {
+  let format = match format {
+    "csv" => ExportFormat::Csv,
+    "parquet" => ExportFormat::Parquet,
+    x => {
+      return Err(
+        format!("Invalid export format: {:?} (csv, parquet)", x).into(),
+      )
+    },
+  };
+  match tcp_call(
+    context,
+    ReqData::ExportScores(OpExportScores {
+      egos,
+      format,
+      destination: destination.to_string(),
+    }),
+    Some(timeout_msec.unwrap_or(600_000)),
+  )? {
+    Response::Export(r) => Ok(vec![(r.egos as i64, r.rows as i64)]),
+    other => expect_ok(other).map(|_| vec![]),
+  }
+}
+
+pub fn new_delete_edge(
+  src: &str,
+  dst: &str,
+  context: &str,
+  index: i64,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    context,
+    ReqData::WriteDeleteEdge(OpWriteDeleteEdge {
+      src:   src.to_string(),
+      dst:   dst.to_string(),
+      index,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_delete_node(
+  node: &str,
+  context: &str,
+  index: i64,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    context,
+    ReqData::WriteDeleteNode(OpWriteDeleteNode {
+      node:  node.to_string(),
+      index,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_rename_node(
+  old: &str,
+  new: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    "",
+    ReqData::WriteAliasNode(OpWriteAliasNode {
+      old: old.to_string(),
+      new: new.to_string(),
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_set_zero_opinion(
+  node: &str,
+  score: f64,
+  context: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    context,
+    ReqData::WriteZeroOpinion(OpWriteZeroOpinion {
+      node:  node.to_string(),
+      score,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_put_node(
+  name: &str,
+  kind: &str,
+  flags: u32,
+  context: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let kind = kind_from_prefix(kind)
+    .ok_or_else(|| format!("Unknown node kind: {:?}", kind))?;
+  let resp = tcp_call(
+    context,
+    ReqData::WriteNode(OpWriteNode {
+      name: name.to_string(),
+      kind,
+      flags,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_set_user_params(
+  ego: &str,
+  alpha: Option<f64>,
+  positive_only: bool,
+  context: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let walk_policy = if positive_only {
+    WalkPolicy::PositiveOnly
+  } else {
+    WalkPolicy::Default
+  };
+  let resp = tcp_call(
+    context,
+    ReqData::SetUserParams(OpSetUserParams {
+      ego: ego.to_string(),
+      alpha,
+      walk_policy,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_mute(
+  ego: &str,
+  node: &str,
+  muted: bool,
+  context: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    context,
+    ReqData::WriteMute(OpWriteMute {
+      ego: ego.to_string(),
+      node: node.to_string(),
+      muted,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_set_poll(
+  poll: &str,
+  expires_at: u64,
+  anonymous: bool,
+  context: &str,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    context,
+    ReqData::WritePoll(OpWritePoll {
+      poll: poll.to_string(),
+      expires_at,
+      anonymous,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  expect_ok(resp)
+}
+
+pub fn new_sync(
+  timeout_msec: Option<u64>
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  //  D4 (JOURNAL): increment the per-process stamp and send it.
+  let stamp = SYNC_STAMP.fetch_add(1, Ordering::SeqCst) + 1;
+  let resp = tcp_call("", ReqData::Sync(stamp), timeout_msec)?;
+  expect_ok(resp)
+}
+
+pub fn new_reset_stats(
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call("", ReqData::ResetStats, Some(*RECV_TIMEOUT_MSEC))?;
+  expect_ok(resp)
+}
+
+pub fn new_get_stats(
+) -> Result<ResStats, Box<dyn Error + 'static>> {
+  let resp = tcp_call("", ReqData::GetStats, Some(*RECV_TIMEOUT_MSEC))?;
+  match resp {
+    Response::Stats(s) => Ok(s),
+    Response::Fail => Err("Service returned Fail".into()),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
+}
+
+pub fn new_zerorec(
+  _timeout_msec: Option<u64>,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  Err("recalculate_zero has been removed".into())
+}
+
+pub fn new_recalculate_clustering(
+  timeout_msec: Option<u64>
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    "",
+    ReqData::WriteRecalculateClustering,
+    timeout_msec,
+  )?;
+  expect_ok(resp)
+}
+
+//  D10 (JOURNAL): server returns NotImplemented for new edges filter; connector reports as error.
+pub fn new_set_new_edges_filter(
+  src: &str,
+  filter: Vec<u8>,
+) -> Result<&'static str, Box<dyn Error + 'static>> {
+  let resp = tcp_call(
+    "",
+    ReqData::WriteNewEdgesFilter(OpWriteNewEdgesFilter {
+      src:    src.to_string(),
+      filter,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )?;
+  match resp {
+    Response::NotImplemented => Err("meritrank: set_new_edges_filter not implemented".into()),
+    _ => expect_ok(resp),
+  }
+}
+
+// ================================================================
+//
+//    Read operations
+//
+// ================================================================
+
+pub fn new_node_list(
+  context: &str
+) -> Result<Vec<(String,)>, Box<dyn Error + 'static>> {
+  match tcp_call(context, ReqData::ReadNodeList, Some(*RECV_TIMEOUT_MSEC))? {
+    Response::NodeList(r) => Ok(r.nodes),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
+}
+
+pub fn new_muted(
+  ego: &str,
+  context: &str,
+) -> Result<Vec<(String,)>, Box<dyn Error + 'static>> {
+  match tcp_call(
+    context,
+    ReqData::ReadMutes(OpReadMutes {
+      ego: ego.to_string(),
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::NodeList(r) => Ok(r.nodes),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
+}
+
+pub fn new_node_score(
+  ego: &str,
+  target: &str,
+  context: &str,
+) -> Result<Vec<(String, String, f64, f64, i32, i32)>, Box<dyn Error + 'static>> {
+  match tcp_call(
+    context,
+    ReqData::ReadNodeScore(OpReadNodeScore {
+      ego: ego.to_string(),
+      target: target.to_string(),
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::Scores(r) => Ok(scores_to_tuples(r.scores)),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
+}
+
+pub fn new_scores(
+  ego: &str,
+  hide_personal: bool,
+  context: &str,
+  kind: &str,
+  lt: Option<f64>,
+  lte: Option<f64>,
+  gt: Option<f64>,
+  gte: Option<f64>,
+  index: u32,
+  count: u32,
+  num_clusters: u32,
+  cluster_min: u32,
+  cluster_max: u32,
+  filter_on: &str,
+  include_negative_only: bool,
+  ascending: bool,
+  exclude_flags: u32,
+  require_flags: u32,
+) -> Result<Vec<(String, String, f64, f64, i32, i32)>, Box<dyn Error + 'static>> {
+  //  D8 (JOURNAL): map None bounds to f64::MAX/MIN with appropriate lte/gte flags.
+  let (score_lt, score_lte, score_gt, score_gte) = map_bounds(lt, lte, gt, gte)?;
+  let filter_on = score_component(filter_on)?;
+  match tcp_call(
+    context,
+    ReqData::ReadScores(OpReadScores {
+      ego:           ego.to_string(),
+      score_options: FilterOptions {
+        node_kind: kind_from_prefix(kind),
+        hide_personal,
+        score_lt,
+        score_lte,
+        score_gt,
+        score_gte,
+        index,
+        count,
+        num_clusters,
+        cluster_min,
+        cluster_max,
+        filter_on,
+        include_negative_only,
+        ascending,
+        exclude_flags,
+        require_flags,
+        max_latency_ms: 0,
+        include_reverse_scores: true,
+      },
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::Scores(r) => Ok(scores_to_tuples(r.scores)),
+    Response::Fail => Ok(vec![]),
+    Response::Error(e) => Err(format!("Service returned error: {:?}", e).into()),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
+}
+
+pub fn new_scores_bulk(
+  egos: Vec<String>,
+  hide_personal: bool,
+  context: &str,
+  kind: &str,
+  lt: Option<f64>,
+  lte: Option<f64>,
+  gt: Option<f64>,
+  gte: Option<f64>,
+  index: u32,
+  count: u32,
+) -> Result<Vec<(String, String, f64, f64, i32, i32)>, Box<dyn Error + 'static>> {
+  let (score_lt, score_lte, score_gt, score_gte) = map_bounds(lt, lte, gt, gte)?;
+  match tcp_call(
+    context,
+    ReqData::ReadScoresBulk(OpReadScoresBulk {
+      egos,
+      score_options: FilterOptions {
+        node_kind: kind_from_prefix(kind),
+        hide_personal,
+        score_lt,
+        score_lte,
+        score_gt,
+        score_gte,
+        index,
+        count,
+        include_reverse_scores: true,
+        ..FilterOptions::default()
+      },
+      compact: false,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::ScoresBulk(r) => Ok(
+      r.scores
+        .into_iter()
+        .flat_map(|(_, scores)| scores_to_tuples(scores))
+        .collect(),
+    ),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
+}
+
+pub fn new_scores_at(
+  ego: &str,
+  timestamp: u64,
+  hide_personal: bool,
+  context: &str,
+  kind: &str,
+  lt: Option<f64>,
+  lte: Option<f64>,
+  gt: Option<f64>,
+  gte: Option<f64>,
+  index: u32,
+  count: u32,
+) -> Result<Vec<(String, String, f64, f64, i32, i32)>, Box<dyn Error + 'static>> {
+  let (score_lt, score_lte, score_gt, score_gte) = map_bounds(lt, lte, gt, gte)?;
+  match tcp_call(
+    context,
+    ReqData::ReadScoresAt(OpReadScoresAt {
+      ego: ego.to_string(),
+      timestamp,
+      score_options: FilterOptions {
+        node_kind: kind_from_prefix(kind),
+        hide_personal,
+        score_lt,
+        score_lte,
+        score_gt,
+        score_gte,
+        index,
+        count,
+        include_reverse_scores: true,
+        ..FilterOptions::default()
+      },
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::Scores(r) => Ok(scores_to_tuples(r.scores)),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
+}
+
+pub fn new_score_diff(
+  ego: &str,
+  from: u64,
+  to: u64,
+  min_change: f64,
+  context: &str,
+) -> Result<Vec<(String, f64, f64, i32, i32, i64)>, Box<dyn Error + 'static>>
+{
+  match tcp_call(
+    context,
+    ReqData::ReadScoreDiff(OpReadScoreDiff {
+      ego: ego.to_string(),
+      from,
+      to,
+      min_change,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::ScoreDiff(r) => Ok(
+      r.nodes
+        .into_iter()
+        .map(|x| {
+          (
+            x.target,
+            x.old_score,
+            x.new_score,
+            x.old_cluster as i32,
+            x.new_cluster as i32,
+            r.generation as i64,
+          )
+        })
+        .collect(),
+    ),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
+}
+
+pub fn new_dry_run_edge(
+  ego: &str,
   src: &str,
   dst: &str,
+  weight: f64,
   context: &str,
-  index: i64,
-) -> Result<&'static str, Box<dyn Error + 'static>> {
-  let resp = tcp_call(
+) -> Result<Vec<(String, f64, f64)>, Box<dyn Error + 'static>> {
+  match tcp_call(
     context,
-    ReqData::WriteDeleteEdge(OpWriteDeleteEdge {
-      src:   src.to_string(),
-      dst:   dst.to_string(),
-      index,
+    ReqData::ReadDryRun(OpReadDryRun {
+      ego:           ego.to_string(),
+      edges:         vec![OpWriteEdge {
+        src:        src.to_string(),
+        dst:        dst.to_string(),
+        amount:     weight,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }],
+      score_options: FilterOptions::default(),
     }),
     Some(*RECV_TIMEOUT_MSEC),
-  )?;
-  expect_ok(resp)
+  )? {
+    Response::DryRun(r) => Ok(
+      r.nodes
+        .into_iter()
+        .map(|x| (x.target, x.before, x.after))
+        .collect(),
+    ),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
 }
 
-pub fn new_delete_node(
-  node: &str,
+pub fn new_compare_egos(
+  a: &str,
+  b: &str,
   context: &str,
-  index: i64,
-) -> Result<&'static str, Box<dyn Error + 'static>> {
-  let resp = tcp_call(
+  limit: u32,
+) -> Result<Vec<(String, f64, f64, i32, i32)>, Box<dyn Error + 'static>> {
+  match tcp_call(
     context,
-    ReqData::WriteDeleteNode(OpWriteDeleteNode {
-      node:  node.to_string(),
-      index,
+    ReqData::ReadCompareEgos(OpReadCompareEgos {
+      a: a.to_string(),
+      b: b.to_string(),
+      limit,
     }),
     Some(*RECV_TIMEOUT_MSEC),
-  )?;
-  expect_ok(resp)
+  )? {
+    Response::EgoComparison(r) => Ok(
+      r.nodes
+        .into_iter()
+        .map(|x| {
+          (
+            x.target,
+            x.score_a,
+            x.score_b,
+            x.cluster_a as i32,
+            x.cluster_b as i32,
+          )
+        })
+        .collect(),
+    ),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
 }
 
-pub fn new_set_zero_opinion(
-  node: &str,
-  score: f64,
+pub fn new_recommendations(
+  ego: &str,
+  kind: &str,
   context: &str,
-) -> Result<&'static str, Box<dyn Error + 'static>> {
-  let resp = tcp_call(
+  limit: u32,
+) -> Result<Vec<(String, f64, i32)>, Box<dyn Error + 'static>> {
+  match tcp_call(
     context,
-    ReqData::WriteZeroOpinion(OpWriteZeroOpinion {
-      node:  node.to_string(),
-      score,
+    ReqData::ReadRecommendations(OpReadRecommendations {
+      ego: ego.to_string(),
+      kind: kind_from_prefix(kind),
+      limit,
     }),
     Some(*RECV_TIMEOUT_MSEC),
-  )?;
-  expect_ok(resp)
-}
-
-pub fn new_sync(
-  timeout_msec: Option<u64>
-) -> Result<&'static str, Box<dyn Error + 'static>> {
-  //  D4 (JOURNAL): increment the per-process stamp and send it.
-  let stamp = SYNC_STAMP.fetch_add(1, Ordering::SeqCst) + 1;
-  let resp = tcp_call("", ReqData::Sync(stamp), timeout_msec)?;
-  expect_ok(resp)
-}
-
-pub fn new_reset_stats(
-) -> Result<&'static str, Box<dyn Error + 'static>> {
-  let resp = tcp_call("", ReqData::ResetStats, Some(*RECV_TIMEOUT_MSEC))?;
-  expect_ok(resp)
-}
-
-pub fn new_get_stats(
-) -> Result<ResStats, Box<dyn Error + 'static>> {
-  let resp = tcp_call("", ReqData::GetStats, Some(*RECV_TIMEOUT_MSEC))?;
-  match resp {
-    Response::Stats(s) => Ok(s),
-    Response::Fail => Err("Service returned Fail".into()),
+  )? {
+    Response::Recommendations(r) => Ok(
+      r.nodes
+        .into_iter()
+        .map(|x| (x.target, x.score, x.egos as i32))
+        .collect(),
+    ),
+    Response::Fail => Ok(vec![]),
     other => Err(format!("Unexpected response: {:?}", other).into()),
   }
 }
 
-pub fn new_zerorec(
-  _timeout_msec: Option<u64>,
-) -> Result<&'static str, Box<dyn Error + 'static>> {
-  Err("recalculate_zero has been removed".into())
+pub fn new_top_nodes(
+  context: &str,
+  kind: &str,
+  ego: Option<&str>,
+  index: u32,
+  limit: u32,
+) -> Result<Vec<(String, f64)>, Box<dyn Error + 'static>> {
+  match tcp_call(
+    context,
+    ReqData::ReadTopNodes(OpReadTopNodes {
+      ego: ego.map(|x| x.to_string()),
+      kind: kind_from_prefix(kind),
+      index,
+      limit,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::TopNodes(r) => {
+      Ok(r.nodes.into_iter().map(|x| (x.node, x.score)).collect())
+    },
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
 }
 
-pub fn new_recalculate_clustering(
-  timeout_msec: Option<u64>
-) -> Result<&'static str, Box<dyn Error + 'static>> {
-  let resp = tcp_call(
-    "",
-    ReqData::WriteRecalculateClustering,
-    timeout_msec,
-  )?;
-  expect_ok(resp)
+pub fn new_anomalies(
+  context: &str,
+  window: u64,
+  limit: u32,
+) -> Result<Vec<(String, f64, f64, f64, f64)>, Box<dyn Error + 'static>> {
+  match tcp_call(
+    context,
+    ReqData::ReadAnomalies(OpReadAnomalies {
+      window,
+      limit,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::Anomalies(r) => Ok(
+      r.nodes
+        .into_iter()
+        .map(|x| (x.node, x.score, x.low_trust, x.ring, x.burst))
+        .collect(),
+    ),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
 }
 
-//  D10 (JOURNAL): server returns NotImplemented for new edges filter; connector reports as error.
-pub fn new_set_new_edges_filter(
-  src: &str,
-  filter: Vec<u8>,
-) -> Result<&'static str, Box<dyn Error + 'static>> {
-  let resp = tcp_call(
-    "",
-    ReqData::WriteNewEdgesFilter(OpWriteNewEdgesFilter {
-      src:    src.to_string(),
-      filter,
+pub fn new_evaluate_sybil_resistance(
+  suspects: Vec<String>,
+  context: &str,
+  egos: u32,
+  top_k: u32,
+) -> Result<Vec<(i64, i64, f64, f64)>, Box<dyn Error + 'static>> {
+  match tcp_call(
+    context,
+    ReqData::EvaluateSybilResistance(OpEvaluateSybilResistance {
+      suspects,
+      egos,
+      top_k,
     }),
     Some(*RECV_TIMEOUT_MSEC),
-  )?;
-  match resp {
-    Response::NotImplemented => Err("meritrank: set_new_edges_filter not implemented".into()),
-    _ => expect_ok(resp),
+  )? {
+    Response::SybilResistance(r) => Ok(vec![(
+      r.egos as i64,
+      r.attack_edges as i64,
+      r.before,
+      r.after,
+    )]),
+    Response::Fail => Ok(vec![]),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
   }
 }
 
-// ================================================================
-//
-//    Read operations
-//
-// ================================================================
-
-pub fn new_node_list(
-  context: &str
-) -> Result<Vec<(String,)>, Box<dyn Error + 'static>> {
-  match tcp_call(context, ReqData::ReadNodeList, Some(*RECV_TIMEOUT_MSEC))? {
-    Response::NodeList(r) => Ok(r.nodes),
+/// Weights are for the beacon, comment and author scores.
+pub fn new_beacon_comments(
+  ego: &str,
+  beacon: &str,
+  comments: Vec<String>,
+  context: &str,
+  weights: (f64, f64, f64),
+  limit: u32,
+) -> Result<
+  Vec<(String, Option<String>, f64, f64, f64)>,
+  Box<dyn Error + 'static>,
+> {
+  let (beacon_weight, comment_weight, author_weight) = weights;
+  match tcp_call(
+    context,
+    ReqData::ReadBeaconComments(OpReadBeaconComments {
+      ego: ego.to_string(),
+      beacon: beacon.to_string(),
+      comments,
+      beacon_weight,
+      comment_weight,
+      author_weight,
+      limit,
+    }),
+    Some(*RECV_TIMEOUT_MSEC),
+  )? {
+    Response::BeaconComments(r) => Ok(
+      r.comments
+        .into_iter()
+        .map(|x| {
+          (x.comment, x.author, x.score, x.comment_score, x.author_score)
+        })
+        .collect(),
+    ),
     Response::Fail => Ok(vec![]),
     other => Err(format!("Unexpected response: {:?}", other).into()),
   }
 }
 
-pub fn new_node_score(
+pub fn new_mutual_suggestions(
   ego: &str,
-  target: &str,
   context: &str,
-) -> Result<Vec<(String, String, f64, f64, i32, i32)>, Box<dyn Error + 'static>> {
+  limit: u32,
+) -> Result<Vec<(String, i32, f64)>, Box<dyn Error + 'static>> {
   match tcp_call(
     context,
-    ReqData::ReadNodeScore(OpReadNodeScore {
-      ego:    ego.to_string(),
-      target: target.to_string(),
+    ReqData::ReadMutualSuggestions(OpReadMutualSuggestions {
+      ego: ego.to_string(),
+      limit,
     }),
     Some(*RECV_TIMEOUT_MSEC),
   )? {
-    Response::Scores(r) => Ok(scores_to_tuples(r.scores)),
+    Response::MutualSuggestions(r) => Ok(
+      r.users
+        .into_iter()
+        .map(|x| (x.target, x.peers as i32, x.weight))
+        .collect(),
+    ),
     Response::Fail => Ok(vec![]),
     other => Err(format!("Unexpected response: {:?}", other).into()),
   }
 }
 
-pub fn new_scores(
+pub fn new_score_history(
   ego: &str,
-  hide_personal: bool,
+  target: &str,
   context: &str,
-  kind: &str,
-  lt: Option<f64>,
-  lte: Option<f64>,
-  gt: Option<f64>,
-  gte: Option<f64>,
-  index: u32,
-  count: u32,
-) -> Result<Vec<(String, String, f64, f64, i32, i32)>, Box<dyn Error + 'static>> {
-  //  D8 (JOURNAL): map None bounds to f64::MAX/MIN with appropriate lte/gte flags.
-  let (score_lt, score_lte, score_gt, score_gte) = map_bounds(lt, lte, gt, gte)?;
+) -> Result<Vec<(i64, f64)>, Box<dyn Error + 'static>> {
   match tcp_call(
     context,
-    ReqData::ReadScores(OpReadScores {
-      ego:           ego.to_string(),
-      score_options: FilterOptions {
-        node_kind: kind_from_prefix(kind),
-        hide_personal,
-        score_lt,
-        score_lte,
-        score_gt,
-        score_gte,
-        index,
-        count,
-      },
+    ReqData::ReadScoreHistory(OpReadScoreHistory {
+      ego: ego.to_string(),
+      target: target.to_string(),
     }),
     Some(*RECV_TIMEOUT_MSEC),
   )? {
-    Response::Scores(r) => Ok(scores_to_tuples(r.scores)),
+    Response::ScoreHistory(r) => Ok(
+      r.samples
+        .into_iter()
+        .map(|(time, score)| (time as i64, score))
+        .collect(),
+    ),
     Response::Fail => Ok(vec![]),
     other => Err(format!("Unexpected response: {:?}", other).into()),
   }
@@ -397,7 +1379,8 @@ pub fn new_neighbors(
   gte: Option<f64>,
   index: u32,
   count: u32,
-) -> Result<Vec<(String, String, f64, f64, i32, i32)>, Box<dyn Error + 'static>> {
+  edge_kind: &str,
+) -> Result<Vec<NeighborTuple>, Box<dyn Error + 'static>> {
   let (score_lt, score_lte, score_gt, score_gte) = map_bounds(lt, lte, gt, gte)?;
   match tcp_call(
     context,
@@ -406,6 +1389,7 @@ pub fn new_neighbors(
       focus:         focus.to_string(),
       direction,
       kind:          kind_from_prefix(kind),
+      edge_kind:     edge_kind_filter(edge_kind)?,
       hide_personal,
       lt:            score_lt,
       lte:           score_lte,
@@ -416,7 +1400,7 @@ pub fn new_neighbors(
     }),
     Some(*RECV_TIMEOUT_MSEC),
   )? {
-    Response::Scores(r) => Ok(scores_to_tuples(r.scores)),
+    Response::Scores(r) => Ok(neighbors_to_tuples(r.scores)),
     Response::Fail => Ok(vec![]),
     other => Err(format!("Unexpected response: {:?}", other).into()),
   }
@@ -437,6 +1421,27 @@ pub fn new_edgelist(
   }
 }
 
+pub fn new_list_contexts(
+) -> Result<Vec<(String, i64, i64, i64, i64)>, Box<dyn Error + 'static>> {
+  match tcp_call("", ReqData::ReadContexts, Some(*RECV_TIMEOUT_MSEC))? {
+    Response::Contexts(r) => Ok(
+      r.contexts
+        .into_iter()
+        .map(|c| {
+          (
+            c.name,
+            c.nodes as i64,
+            c.edges as i64,
+            c.last_access as i64,
+            c.walks as i64,
+          )
+        })
+        .collect(),
+    ),
+    other => Err(format!("Unexpected response: {:?}", other).into()),
+  }
+}
+
 pub fn new_connected(
   ego: &str,
   context: &str,
@@ -545,6 +1550,39 @@ fn scores_to_tuples(
     .collect()
 }
 
+/// Scores of the neighbors with the provenance of their edges.
+pub type NeighborTuple = (
+  String,
+  String,
+  f64,
+  f64,
+  i32,
+  i32,
+  Option<String>,
+  Option<String>,
+  Option<i64>,
+);
+
+fn neighbors_to_tuples(scores: Vec<ScoreResult>) -> Vec<NeighborTuple> {
+  scores
+    .into_iter()
+    .map(|s| {
+      let provenance = s.provenance;
+      (
+        s.ego,
+        s.target,
+        s.score,
+        s.reverse_score,
+        s.cluster as i32,
+        s.reverse_cluster as i32,
+        provenance.as_ref().map(|x| x.source.clone()),
+        provenance.as_ref().map(|x| x.external_id.clone()),
+        provenance.as_ref().map(|x| x.timestamp as i64),
+      )
+    })
+    .collect()
+}
+
 fn graph_to_tuples(
   graph: Vec<GraphResult>
 ) -> Vec<(String, String, f64, f64, f64, i32, i32)> {
@@ -572,6 +1610,8 @@ fn kind_from_prefix(prefix: &str) -> Option<NodeKind> {
     Some('O') => Some(NodeKind::Opinion),
     Some('V') => Some(NodeKind::PollVariant),
     Some('P') => Some(NodeKind::Poll),
+    //  Kinds registered in the service with `MERITRANK_CUSTOM_NODE_KINDS`.
+    Some(c) if c.is_ascii_uppercase() => Some(NodeKind::Custom(c)),
     _ => None,
   }
 }
@@ -597,6 +1637,31 @@ fn map_bounds(
   ))
 }
 
+/// Score the bounds of `mr_scores` apply to.
+fn score_component(
+  name: &str
+) -> Result<ScoreComponent, Box<dyn Error + 'static>> {
+  match name {
+    "" | "blended" => Ok(ScoreComponent::Blended),
+    "personal" => Ok(ScoreComponent::Personal),
+    "zero_opinion" => Ok(ScoreComponent::ZeroOpinion),
+    _ => Err(format!("Unknown score component: {:?}", name).into()),
+  }
+}
+
+/// Edge kind by name; empty for any kind.
+fn edge_kind_filter(
+  name: &str
+) -> Result<Option<EdgeKind>, Box<dyn Error + 'static>> {
+  if name.is_empty() {
+    return Ok(None);
+  }
+  match edge_kind_from_name(name) {
+    Some(kind) => Ok(Some(kind)),
+    None => Err(format!("Unknown edge kind: {:?}", name).into()),
+  }
+}
+
 // ================================================================
 //
 //    Unit tests (no server required)
@@ -613,6 +1678,23 @@ mod tests {
     assert_eq!(strip_scheme("127.0.0.1:8080"), "127.0.0.1:8080");
   }
 
+  #[test]
+  fn score_components() {
+    assert_eq!(score_component("").unwrap(), ScoreComponent::Blended);
+    assert_eq!(
+      score_component("personal").unwrap(),
+      ScoreComponent::Personal
+    );
+    assert!(score_component("global").is_err());
+  }
+
+  #[test]
+  fn edge_kind_filters() {
+    assert_eq!(edge_kind_filter("").unwrap(), None);
+    assert_eq!(edge_kind_filter("flag").unwrap(), Some(EdgeKind::Flag));
+    assert!(edge_kind_filter("like").is_err());
+  }
+
   #[test]
   fn map_bounds_defaults() {
     let (lt, lte, gt, gte) = map_bounds(None, None, None, None).unwrap();
@@ -662,6 +1744,7 @@ mod tests {
     assert_eq!(kind_from_prefix("B1"), Some(NodeKind::Beacon));
     assert_eq!(kind_from_prefix(""), None);
     assert_eq!(kind_from_prefix("?"), None);
+    assert_eq!(kind_from_prefix("A1"), Some(NodeKind::Custom('A')));
   }
 
   #[test]
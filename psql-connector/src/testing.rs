@@ -7,7 +7,7 @@ fn put_edge_(
   weight: f64,
 ) {
   let _ =
-    crate::mr_put_edge(Some(src), Some(dst), Some(weight), None, Some(-1))
+    crate::mr_put_edge(Some(src), Some(dst), Some(weight), None, Some(-1), None)
       .unwrap();
 }
 
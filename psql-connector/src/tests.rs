@@ -162,7 +162,7 @@ fn edge_uncontexted() {
   let _ = crate::mr_sync(Some(1000)).unwrap();
 
   let res =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1), None)
       .unwrap();
 
   let n = res
@@ -188,6 +188,7 @@ fn edge_contexted() {
     Some(1.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
 
@@ -207,7 +208,7 @@ fn edge_contexted() {
 fn create_context() {
   let _ = crate::mr_reset().unwrap();
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1), None)
       .unwrap();
   let _ = crate::mr_create_context(Some("X"));
 
@@ -239,6 +240,7 @@ fn null_context_is_sum() {
     Some(1.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_put_edge(
@@ -247,6 +249,7 @@ fn null_context_is_sum() {
     Some(2.0),
     Some("Y"),
     Some(-1),
+    None,
   )
   .unwrap();
 
@@ -272,6 +275,7 @@ fn delete_contexted_edge() {
     Some(1.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_put_edge(
@@ -280,6 +284,7 @@ fn delete_contexted_edge() {
     Some(2.0),
     Some("Y"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_delete_edge(Some("B1"), Some("U2"), Some("X"), Some(-1))
@@ -307,7 +312,7 @@ fn delete_nodes() {
   let _ = crate::mr_reset().unwrap();
 
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1), None)
       .unwrap();
   let _ = crate::mr_delete_node(Some("U1"), None, Some(-1)).unwrap();
   let _ = crate::mr_delete_node(Some("U2"), None, Some(-1)).unwrap();
@@ -318,6 +323,35 @@ fn delete_nodes() {
   assert_eq!(res.count(), 0);
 }
 
+#[pg_test]
+fn rename_node() {
+  let _ = crate::mr_reset().unwrap();
+
+  let _ =
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1), None)
+      .unwrap();
+  let _ = crate::mr_rename_node(Some("U2"), Some("U3")).unwrap();
+  //  The old name still refers to the same node.
+  let _ =
+    crate::mr_put_edge(Some("U2"), Some("U1"), Some(1.0), None, Some(-1), None)
+      .unwrap();
+
+  let _ = crate::mr_sync(Some(1000)).unwrap();
+
+  let mut edges: Vec<_> = crate::mr_edgelist(None)
+    .unwrap()
+    .map(|(src, dst, _)| (src, dst))
+    .collect();
+  edges.sort();
+  assert_eq!(
+    edges,
+    vec![
+      ("U1".to_string(), "U3".to_string()),
+      ("U3".to_string(), "U1".to_string()),
+    ]
+  );
+}
+
 #[pg_test]
 fn null_context_invariant() {
   let _ = crate::mr_reset().unwrap();
@@ -328,6 +362,7 @@ fn null_context_invariant() {
     Some(1.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_put_edge(
@@ -336,6 +371,7 @@ fn null_context_invariant() {
     Some(2.0),
     Some("Y"),
     Some(-1),
+    None,
   )
   .unwrap();
 
@@ -346,6 +382,7 @@ fn null_context_invariant() {
     Some(1.0),
     Some("X"),
     Some(-1),
+    None,
   );
 
   let _ = crate::mr_sync(Some(1000)).unwrap();
@@ -370,6 +407,7 @@ fn node_score_context() {
     Some(2.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_put_edge(
@@ -378,6 +416,7 @@ fn node_score_context() {
     Some(1.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_put_edge(
@@ -386,6 +425,7 @@ fn node_score_context() {
     Some(3.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
 
@@ -413,15 +453,33 @@ fn node_score_context() {
 fn scores_null_context() {
   let _ = crate::mr_reset().unwrap();
 
-  let _ =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(2.0), Some(""), Some(-1))
-      .unwrap();
-  let _ =
-    crate::mr_put_edge(Some("U1"), Some("U3"), Some(1.0), Some(""), Some(-1))
-      .unwrap();
-  let _ =
-    crate::mr_put_edge(Some("U2"), Some("U3"), Some(3.0), Some(""), Some(-1))
-      .unwrap();
+  let _ = crate::mr_put_edge(
+    Some("U1"),
+    Some("U2"),
+    Some(2.0),
+    Some(""),
+    Some(-1),
+    None,
+  )
+  .unwrap();
+  let _ = crate::mr_put_edge(
+    Some("U1"),
+    Some("U3"),
+    Some(1.0),
+    Some(""),
+    Some(-1),
+    None,
+  )
+  .unwrap();
+  let _ = crate::mr_put_edge(
+    Some("U2"),
+    Some("U3"),
+    Some(3.0),
+    Some(""),
+    Some(-1),
+    None,
+  )
+  .unwrap();
 
   // sleep(Duration::from_millis(100));
   let _ = crate::mr_sync(Some(1000)).unwrap();
@@ -437,6 +495,14 @@ fn scores_null_context() {
     None,
     None,
     None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
   )
   .unwrap()
   .collect();
@@ -467,6 +533,51 @@ fn scores_null_context() {
   }
 }
 
+#[pg_test]
+fn scores_bulk() {
+  let _ = crate::mr_reset().unwrap();
+
+  let _ = crate::mr_put_edge(
+    Some("U1"),
+    Some("U2"),
+    Some(1.0),
+    Some(""),
+    Some(-1),
+    None,
+  )
+  .unwrap();
+  let _ = crate::mr_put_edge(
+    Some("U3"),
+    Some("U2"),
+    Some(1.0),
+    Some(""),
+    Some(-1),
+    None,
+  )
+  .unwrap();
+
+  let _ = crate::mr_sync(Some(1000)).unwrap();
+
+  let res: Vec<_> = crate::mr_scores_bulk(
+    vec!["U3".to_string(), "U1".to_string()],
+    Some(false),
+    Some(""),
+    Some("U"),
+    Some(10.0),
+    None,
+    Some(0.0),
+    None,
+    None,
+    None,
+  )
+  .unwrap()
+  .collect();
+
+  let egos: Vec<_> = res.iter().map(|x| x.0.as_str()).collect();
+  assert_eq!(egos, vec!["U3", "U3", "U1", "U1"]);
+  assert!(res.iter().all(|x| x.1 != "U1" || x.0 == "U1"));
+}
+
 #[pg_test]
 fn scores_context() {
   let _ = crate::mr_reset().unwrap();
@@ -477,6 +588,7 @@ fn scores_context() {
     Some(2.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_put_edge(
@@ -485,6 +597,7 @@ fn scores_context() {
     Some(1.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_put_edge(
@@ -493,6 +606,7 @@ fn scores_context() {
     Some(3.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
 
@@ -510,6 +624,14 @@ fn scores_context() {
     None,
     None,
     None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
   )
   .unwrap()
   .collect();
@@ -550,6 +672,7 @@ fn scores_defaults() {
     Some(2.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_put_edge(
@@ -558,6 +681,7 @@ fn scores_defaults() {
     Some(1.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
   let _ = crate::mr_put_edge(
@@ -566,6 +690,7 @@ fn scores_defaults() {
     Some(3.0),
     Some("X"),
     Some(-1),
+    None,
   )
   .unwrap();
 
@@ -583,6 +708,14 @@ fn scores_defaults() {
     None,
     None,
     None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
   )
   .unwrap()
   .collect();
@@ -618,13 +751,13 @@ fn nodelist() {
   let _ = crate::mr_reset().unwrap();
 
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(2.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(2.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U3"), Some(1.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U3"), Some(1.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U2"), Some("U3"), Some(3.0), None, Some(-1))
+    crate::mr_put_edge(Some("U2"), Some("U3"), Some(3.0), None, Some(-1), None)
       .unwrap();
 
   // sleep(Duration::from_millis(100));
@@ -644,13 +777,13 @@ fn connected() {
   let _ = crate::mr_reset().unwrap();
 
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(2.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(2.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U3"), Some(1.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U3"), Some(1.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U2"), Some("U3"), Some(3.0), None, Some(-1))
+    crate::mr_put_edge(Some("U2"), Some("U3"), Some(3.0), None, Some(-1), None)
       .unwrap();
 
   // sleep(Duration::from_millis(100));
@@ -671,22 +804,22 @@ fn mutual_scores() {
   let _ = crate::mr_reset().unwrap();
 
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(3.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(3.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U3"), Some(1.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U3"), Some(1.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U2"), Some("U1"), Some(2.0), None, Some(-1))
+    crate::mr_put_edge(Some("U2"), Some("U1"), Some(2.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U2"), Some("U3"), Some(4.0), None, Some(-1))
+    crate::mr_put_edge(Some("U2"), Some("U3"), Some(4.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U3"), Some("U1"), Some(3.0), None, Some(-1))
+    crate::mr_put_edge(Some("U3"), Some("U1"), Some(3.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U3"), Some("U2"), Some(2.0), None, Some(-1))
+    crate::mr_put_edge(Some("U3"), Some("U2"), Some(2.0), None, Some(-1), None)
       .unwrap();
 
   // sleep(Duration::from_millis(100));
@@ -788,19 +921,19 @@ fn five_scores_clustering() {
   let _ = crate::mr_reset().unwrap();
 
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(5.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(5.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U3"), Some(1.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U3"), Some(1.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U4"), Some(2.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U4"), Some(2.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U5"), Some(3.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U5"), Some(3.0), None, Some(-1), None)
       .unwrap();
   let _ =
-    crate::mr_put_edge(Some("U2"), Some("U1"), Some(4.0), None, Some(-1))
+    crate::mr_put_edge(Some("U2"), Some("U1"), Some(4.0), None, Some(-1), None)
       .unwrap();
 
   // sleep(Duration::from_millis(100));
@@ -817,6 +950,14 @@ fn five_scores_clustering() {
     None,
     Some(0),
     Some(i32::MAX as i64),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
   )
   .unwrap()
   .collect();
@@ -844,7 +985,7 @@ fn set_zero_opinion() {
   let _ = crate::mr_reset().unwrap();
 
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(5.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(5.0), None, Some(-1), None)
       .unwrap();
 
   // sleep(Duration::from_millis(100));
@@ -871,7 +1012,7 @@ fn neighbors_inbound() {
   let _ = crate::mr_reset().unwrap();
 
   let _ =
-    crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1))
+    crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1), None)
       .unwrap();
 
   // sleep(Duration::from_millis(100));
@@ -889,7 +1030,7 @@ fn neighbors_inbound() {
     None,
     None,
     None,
-    None,
+    None,    None,
   )
   .unwrap()
   .collect();
@@ -927,6 +1068,14 @@ fn bulk_load_basic() {
     None,
     Some(0),
     Some(16),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
   )
   .unwrap()
   .collect();
@@ -946,6 +1095,14 @@ fn bulk_load_basic() {
       None,
       Some(0),
       Some(16),
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
     )
     .unwrap()
     .collect();
@@ -1002,6 +1159,14 @@ fn bulk_load_then_scores() {
     None,
     Some(0),
     Some(16),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
   )
   .unwrap()
   .collect();
@@ -1021,6 +1186,14 @@ fn bulk_load_then_scores() {
       None,
       Some(0),
       Some(16),
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
+      None,
     )
     .unwrap()
     .collect();
@@ -1032,8 +1205,15 @@ fn bulk_load_then_scores() {
 #[pg_test]
 fn bulk_load_replaces_state() {
   let _ = crate::mr_reset().unwrap();
-  let _ = crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1))
-    .unwrap();
+  let _ = crate::mr_put_edge(
+    Some("U1"),
+    Some("U2"),
+    Some(1.0),
+    None,
+    Some(-1),
+    None,
+  )
+  .unwrap();
   let _ = crate::mr_sync(Some(1000)).unwrap();
 
   let before: Vec<_> = crate::mr_edgelist(None).unwrap().collect();
@@ -1061,8 +1241,15 @@ fn stats_reset_and_read() {
   let _ = crate::mr_reset().unwrap();
   let _ = crate::mr_sync(Some(1000)).unwrap();
   crate::new_reset_stats().unwrap();
-  let _ = crate::mr_put_edge(Some("U1"), Some("U2"), Some(1.0), None, Some(-1))
-    .unwrap();
+  let _ = crate::mr_put_edge(
+    Some("U1"),
+    Some("U2"),
+    Some(1.0),
+    None,
+    Some(-1),
+    None,
+  )
+  .unwrap();
   let _ = crate::mr_sync(Some(1000)).unwrap();
   let s = crate::new_get_stats().unwrap();
   assert!(s.count <= 50_000);
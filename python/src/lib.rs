@@ -0,0 +1,173 @@
+//! Python bindings of the MeritRank engine, see README.md.
+
+use meritrank_core::MeritRankError as EngineError;
+use meritrank_core::{Graph, MeritRank, NodeId, Weight};
+
+use numpy::{IntoPyArray, PyArray1, PyReadonlyArray1};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+
+create_exception!(
+  meritrank_python,
+  MeritRankError,
+  PyException,
+  "Error of the MeritRank engine."
+);
+
+fn engine_error(e: EngineError) -> PyErr {
+  MeritRankError::new_err(e.to_string())
+}
+
+/// Random walk ranking of a weighted directed graph. Nodes are numbered from
+/// 0; adding an edge adds all nodes up to its ends.
+#[pyclass(name = "MeritRank")]
+struct PyMeritRank {
+  rank: MeritRank,
+}
+
+impl PyMeritRank {
+  fn set_edge(
+    &mut self,
+    src: NodeId,
+    dst: NodeId,
+    weight: Weight,
+  ) -> PyResult<()> {
+    if !weight.is_finite() {
+      return Err(PyValueError::new_err(format!(
+        "Weight of edge {} -> {} is not finite",
+        src, dst
+      )));
+    }
+    while !self.rank.graph.contains_node(src.max(dst)) {
+      self.rank.get_new_nodeid();
+    }
+    self.rank.set_edge(src, dst, weight).map_err(engine_error)
+  }
+
+  fn scores(
+    &self,
+    ego: NodeId,
+    limit: Option<usize>,
+  ) -> PyResult<Vec<(NodeId, Weight)>> {
+    self.rank.get_all_scores(ego, limit).map_err(engine_error)
+  }
+}
+
+#[pymethods]
+impl PyMeritRank {
+  #[new]
+  #[pyo3(signature = (walks_per_ego = 10000, alpha = 0.85))]
+  fn new(
+    walks_per_ego: usize,
+    alpha: Weight,
+  ) -> PyResult<Self> {
+    let rank = MeritRank::builder(walks_per_ego)
+      .alpha(alpha)
+      .build(Graph::new())
+      .map_err(engine_error)?;
+    Ok(PyMeritRank { rank })
+  }
+
+  #[getter]
+  fn num_nodes(&self) -> usize {
+    self.rank.graph.nodes.len()
+  }
+
+  /// Sets the weight of the edge, 0 removes it. Walks of calculated egos are
+  /// updated.
+  fn add_edge(
+    &mut self,
+    src: NodeId,
+    dst: NodeId,
+    weight: Weight,
+  ) -> PyResult<()> {
+    self.set_edge(src, dst, weight)
+  }
+
+  /// Same as `add_edge` for each element of the arrays.
+  fn add_edges(
+    &mut self,
+    src: PyReadonlyArray1<u64>,
+    dst: PyReadonlyArray1<u64>,
+    weight: PyReadonlyArray1<f64>,
+  ) -> PyResult<()> {
+    let src = src.as_slice()?;
+    let dst = dst.as_slice()?;
+    let weight = weight.as_slice()?;
+    if src.len() != dst.len() || src.len() != weight.len() {
+      return Err(PyValueError::new_err("Arrays differ in length"));
+    }
+    for ((&from, &to), &amount) in src.iter().zip(dst).zip(weight) {
+      self.set_edge(from as NodeId, to as NodeId, amount)?;
+    }
+    Ok(())
+  }
+
+  /// Runs the walks of the ego. Scores of an ego are only available after
+  /// that.
+  fn calculate(
+    &mut self,
+    ego: NodeId,
+  ) -> PyResult<()> {
+    if !self.rank.graph.contains_node(ego) {
+      return Err(engine_error(EngineError::NodeDoesNotExist));
+    }
+    self.rank.calculate(ego).map_err(engine_error)
+  }
+
+  fn get_node_score(
+    &self,
+    ego: NodeId,
+    target: NodeId,
+  ) -> PyResult<Weight> {
+    self.rank.get_node_score(ego, target).map_err(engine_error)
+  }
+
+  /// (node, score) pairs of the nodes the ego reaches, highest score first.
+  #[pyo3(signature = (ego, limit = None))]
+  fn get_ranks(
+    &self,
+    ego: NodeId,
+    limit: Option<usize>,
+  ) -> PyResult<Vec<(NodeId, Weight)>> {
+    self.scores(ego, limit)
+  }
+
+  /// Same as `get_ranks`, as arrays of nodes and of scores.
+  #[pyo3(signature = (ego, limit = None))]
+  fn get_ranks_arrays<'py>(
+    &self,
+    py: Python<'py>,
+    ego: NodeId,
+    limit: Option<usize>,
+  ) -> PyResult<(Bound<'py, PyArray1<u64>>, Bound<'py, PyArray1<f64>>)> {
+    let (nodes, scores): (Vec<u64>, Vec<f64>) = self
+      .scores(ego, limit)?
+      .into_iter()
+      .map(|(node, score)| (node as u64, score))
+      .unzip();
+    Ok((nodes.into_pyarray(py), scores.into_pyarray(py)))
+  }
+
+  /// Scores of all nodes by the ego, indexed by node; 0 for nodes the ego
+  /// does not reach.
+  fn get_scores<'py>(
+    &self,
+    py: Python<'py>,
+    ego: NodeId,
+  ) -> PyResult<Bound<'py, PyArray1<f64>>> {
+    let mut scores = vec![0.0; self.rank.graph.nodes.len()];
+    for (node, score) in self.scores(ego, None)? {
+      scores[node] = score;
+    }
+    Ok(scores.into_pyarray(py))
+  }
+}
+
+#[pymodule]
+fn meritrank_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+  m.add_class::<PyMeritRank>()?;
+  m.add("MeritRankError", m.py().get_type::<MeritRankError>())?;
+  Ok(())
+}
@@ -0,0 +1,46 @@
+import numpy as np
+import pytest
+
+from meritrank_python import MeritRank, MeritRankError
+
+
+def chain():
+    rank = MeritRank(walks_per_ego=1000)
+    rank.add_edges(
+        np.array([0, 1], dtype=np.uint64),
+        np.array([1, 2], dtype=np.uint64),
+        np.array([1.0, 1.0]),
+    )
+    rank.calculate(0)
+    return rank
+
+
+def test_scores_follow_edges():
+    rank = chain()
+    assert rank.num_nodes == 3
+    ranks = rank.get_ranks(0)
+    assert [node for node, _ in ranks] == [0, 1, 2]
+    assert rank.get_node_score(0, 2) == pytest.approx(ranks[2][1])
+
+    nodes, scores = rank.get_ranks_arrays(0, limit=2)
+    assert list(nodes) == [0, 1]
+    assert np.allclose(scores, [score for _, score in ranks[:2]])
+    dense = rank.get_scores(0)
+    assert dense.shape == (3,)
+    assert np.allclose(dense[nodes], scores)
+
+
+def test_walks_are_updated_on_writes():
+    rank = chain()
+    rank.add_edge(1, 2, 0.0)
+    assert rank.get_node_score(0, 2) == 0.0
+
+
+def test_errors():
+    rank = chain()
+    with pytest.raises(MeritRankError):
+        rank.get_node_score(1, 2)
+    with pytest.raises(MeritRankError):
+        rank.add_edge(1, 1, 1.0)
+    with pytest.raises(ValueError):
+        rank.add_edge(0, 1, float("nan"))
@@ -2,7 +2,7 @@
 //! read/write load from a shared op queue with configurable worker count and pacing per phase.
 
 use meritrank_service::data::{
-  BulkEdge, FilterOptions, NodeKind, OpReadMutualScores, OpReadScores, OpWriteBulkEdges,
+  BulkEdge, EdgeKind, FilterOptions, NodeKind, OpReadMutualScores, OpReadScores, OpWriteBulkEdges,
   OpWriteCalculate, OpWriteDeleteNode, OpWriteEdge, ReqData, Request, ResNodeList, ResStats,
   Response,
 };
@@ -141,11 +141,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let stats = Arc::new(ProcessorStats::new(MAX_STATS_SAMPLES));
   let processor = Arc::new(MultiGraphProcessor::new_with_stats(
     settings.clone(),
-    Arc::clone(&stats),
+    Some(Arc::clone(&stats)),
   ));
 
   let req = Request {
     subgraph: String::new(),
+    token:    String::new(),
     data:     ReqData::WriteBulkEdges(OpWriteBulkEdges {
       edges: edges.clone(),
     }),
@@ -159,6 +160,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let _ = processor
     .process_request(&Request {
       subgraph: String::new(),
+      token:    String::new(),
       data:     ReqData::Stamp(stamp),
     })
     .await;
@@ -168,6 +170,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let node_list = processor
     .process_request(&Request {
       subgraph: String::new(),
+      token:    String::new(),
       data:     ReqData::ReadNodeList,
     })
     .await;
@@ -212,6 +215,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let _ = processor
       .process_request(&Request {
         subgraph: String::new(),
+        token:    String::new(),
         data:     ReqData::WriteCalculate(OpWriteCalculate { ego: u.clone() }),
       })
       .await;
@@ -224,6 +228,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let _ = processor
     .process_request(&Request {
       subgraph: String::new(),
+      token:    String::new(),
       data:     ReqData::Stamp(warmup_stamp),
     })
     .await;
@@ -233,6 +238,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let _ = processor
     .process_request(&Request {
       subgraph: String::new(),
+      token:    String::new(),
       data:     ReqData::ResetStats,
     })
     .await;
@@ -241,7 +247,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let stats_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("load_test_stats.csv");
   let mut file = OpenOptions::new()
     .create(true)
-    .write(true)
     .append(true)
     .open(&stats_path)?;
   writeln!(
@@ -294,8 +299,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
       let users = users.clone();
       let write_targets = write_targets.clone();
       let beacons = beacons.clone();
-      let start = start;
-      let phase_duration = phase_duration;
       let delay = phase_cfg.delay;
       tokio::spawn(async move {
         let mut rng = rand::rngs::StdRng::seed_from_u64(
@@ -321,10 +324,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
               let u = users.choose(&mut rng).unwrap().clone();
               let b = beacons.choose(&mut rng).unwrap().clone();
               Some(LoadTestOp::WriteEdge(u, b))
-            } else if let Some(node) = write_targets.choose(&mut rng) {
-              Some(LoadTestOp::WriteDeleteNode(node.clone()))
             } else {
-              None
+              write_targets
+                .choose(&mut rng)
+                .map(|node| LoadTestOp::WriteDeleteNode(node.clone()))
             }
           };
           if let Some(op) = op {
@@ -347,8 +350,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
       let queue = Arc::clone(&queue);
       let reads = Arc::clone(&reads);
       let writes = Arc::clone(&writes);
-      let start = start;
-      let phase_duration = phase_duration;
       let delay = phase_cfg.delay;
       async move {
         let _ = worker_id;
@@ -370,26 +371,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
           let req = match op {
             LoadTestOp::ReadScores(ego) => Request {
               subgraph: String::new(),
+              token:    String::new(),
               data:     ReqData::ReadScores(OpReadScores {
-                ego:           ego,
+                ego,
                 score_options: FilterOptions::default(),
               }),
             },
             LoadTestOp::ReadMutualScores(ego) => Request {
               subgraph: String::new(),
+              token:    String::new(),
               data:     ReqData::ReadMutualScores(OpReadMutualScores { ego }),
             },
             LoadTestOp::WriteEdge(src, dst) => Request {
               subgraph: String::new(),
+              token:    String::new(),
               data:     ReqData::WriteEdge(OpWriteEdge {
                 src,
                 dst,
-                amount:    1.0,
-                magnitude: 0,
+                amount:     1.0,
+                magnitude:  0,
+                edge_kind:  EdgeKind::Vote,
+                provenance: None,
               }),
             },
             LoadTestOp::WriteDeleteNode(node) => Request {
               subgraph: String::new(),
+              token:    String::new(),
               data:     ReqData::WriteDeleteNode(OpWriteDeleteNode { node, index: 0 }),
             },
           };
@@ -414,7 +421,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
       let stats = Arc::clone(&stats);
       let file = OpenOptions::new()
         .create(true)
-        .write(true)
         .append(true)
         .open(&stats_path)?;
       let phase = phase_name.clone();
@@ -469,14 +475,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
           let w = writes.load(Ordering::Relaxed);
           let queue_len = queue.lock().map(|q| q.len()).unwrap_or(0);
           let interval_secs = elapsed_secs.saturating_sub(last_elapsed_secs);
-          let (r_s, w_s) = if interval_secs > 0 {
-            (
-              (r - last_reads) / interval_secs,
-              (w - last_writes) / interval_secs,
-            )
-          } else {
-            (0u64, 0u64)
-          };
+          let r_s = (r - last_reads).checked_div(interval_secs).unwrap_or(0);
+          let w_s = (w - last_writes).checked_div(interval_secs).unwrap_or(0);
           last_elapsed_secs = elapsed_secs;
           last_reads = r;
           last_writes = w;
@@ -517,6 +517,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
   let res_stats = match processor
     .process_request(&Request {
       subgraph: String::new(),
+      token:    String::new(),
       data:     ReqData::GetStats,
     })
     .await
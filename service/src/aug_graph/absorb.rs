@@ -3,6 +3,9 @@ use crate::utils::log::*;
 
 use meritrank_core::NodeId;
 
+use std::time::Duration;
+
+use super::polls::is_poll_edge;
 use super::AugGraph;
 
 impl AugGraph {
@@ -17,14 +20,10 @@ impl AugGraph {
       AugGraphOp::WriteReset => {
         *self = AugGraph::new(self.settings.clone());
       },
-      AugGraphOp::WriteEdge(OpWriteEdge {
-        src,
-        dst,
-        amount,
-        magnitude,
-      }) => {
-        self.set_edge(src.clone(), dst.clone(), *amount, *magnitude);
+      AugGraphOp::WriteEdge(data) if is_poll_edge(&data.src, &data.dst) => {
+        self.set_poll_edge(data)
       },
+      AugGraphOp::WriteEdge(data) => self.set_edge_of_kind(data),
       AugGraphOp::BulkLoadEdges(edges) => {
         self.bulk_load_edges(edges.clone());
       },
@@ -53,14 +52,12 @@ impl AugGraph {
       AugGraphOp::WriteRecalculateClustering => {
         log_warning!("Recalculate clustering is ignored!")
       },
-      AugGraphOp::ClearEgo(ego_id) => {
-        if let Err(e) = self.mr.clear_ego(*ego_id) {
-          log_error!("ClearEgo failed: {}", e);
-        }
-      },
+      AugGraphOp::ClearEgo(ego_id) => self.drop_walks(*ego_id),
       AugGraphOp::DeleteNode(node) => {
         if let Some(src_info) = self.nodes.get_by_name(node) {
           let src_id = src_info.id;
+          self.invalidate_egos_visiting(src_id);
+          let owners_before = self.personal_owners(src_id);
           let dst_ids: Vec<NodeId> = self
             .mr
             .graph
@@ -73,16 +70,63 @@ impl AugGraph {
             })
             .unwrap_or_default();
           for dst_id in dst_ids {
-            match self.mr.set_edge(src_id, dst_id, 0.0) {
+            match self.set_mr_edge(src_id, dst_id, 0.0) {
               Ok(_) => {},
               Err(e) => log_error!("{}", e),
             }
           }
+          self.update_personal_filters(src_id, owners_before);
         } else {
           log_warning!("DeleteNode: node not found: {:?}", node);
         }
       },
       AugGraphOp::Stamp(value) => self.stamp = *value,
+      AugGraphOp::WriteScoreClusters(OpWriteScoreClusters {
+        num_clusters,
+      }) => {
+        //  Cached bounds are keyed by the number of clusters, so the old ones
+        //  are not used anymore and expire on their own.
+        self.settings.num_score_quantiles = *num_clusters as usize;
+      },
+      AugGraphOp::RemoveOrphans(names) => {
+        self.remove_orphans(names);
+      },
+      AugGraphOp::DecayEdges(factor) => {
+        self.decay_edges(*factor);
+      },
+      AugGraphOp::CalculateWithin(ego, budget_msec) => {
+        let budget = Duration::from_millis(*budget_msec);
+        self.calculate_within(ego.clone(), budget);
+      },
+      AugGraphOp::CalculatePartial(ego, budget_msec) => {
+        let budget = Duration::from_millis(*budget_msec);
+        self.calculate_partial(ego.clone(), budget);
+      },
+      AugGraphOp::AliasNode(OpWriteAliasNode { old, new }) => {
+        //  Contexts that never saw the node have nothing to rename.
+        if self.nodes.get_by_name(old).is_none() {
+          log_verbose!("AliasNode: node not found: {:?}", old);
+        } else if self.nodes.rename(old, new.clone()).is_none() {
+          log_warning!("AliasNode: cannot rename {:?} to {:?}", old, new);
+        }
+      },
+      AugGraphOp::SetUserParams(data) => self.set_user_params(data),
+      AugGraphOp::Mute(data) => self.set_mute(data),
+      AugGraphOp::WritePoll(data) => self.write_poll(data),
+      AugGraphOp::ArchivePolls(now) => {
+        self.archive_polls(*now);
+      },
+      AugGraphOp::WriteNode(data) => self.write_node(data),
+      AugGraphOp::WriteEdgeCas(data, reply) => {
+        reply.send(self.set_edge_cas(data));
+      },
+      AugGraphOp::RecalculateAll => self.recalculate_all(),
+      AugGraphOp::Batch(ops) | AugGraphOp::Staged(ops, _) => {
+        for op in ops {
+          self.apply_op(op);
+        }
+      },
     }
+    self.fit_caches();
   }
 }
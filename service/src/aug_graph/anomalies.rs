@@ -0,0 +1,163 @@
+use crate::data::*;
+use crate::history::unix_time_secs;
+use crate::utils::log::*;
+
+use meritrank_core::{IntMap, NodeId};
+
+use super::AugGraph;
+
+use std::collections::HashSet;
+
+impl AugGraph {
+  /// Average score of each node by the calculated egos other than itself.
+  /// Empty if no ego is calculated.
+  fn reputations(&self) -> IntMap<NodeId, NodeScore> {
+    let egos = self.mr.get_personal_hits();
+    let mut totals: IntMap<NodeId, NodeScore> = IntMap::default();
+    for ego in egos.keys() {
+      match self.mr.get_all_scores(*ego, None) {
+        Ok(scores) => {
+          for (id, score) in scores {
+            if id != *ego {
+              *totals.entry(id).or_default() += score;
+            }
+          }
+        },
+        Err(e) => log_verbose!("Skip ego {}: {}", ego, e),
+      }
+    }
+    for (id, total) in totals.iter_mut() {
+      let others = egos.len() - usize::from(egos.contains_key(id));
+      *total /= others.max(1) as NodeScore;
+    }
+    totals
+  }
+
+  /// Number of inbound edges of each node added since `since` that still
+  /// exist, from the edge log.
+  fn recent_inbound_edges(
+    &self,
+    since: u64,
+  ) -> IntMap<NodeId, usize> {
+    let mut edges: HashSet<(NodeId, NodeId)> = HashSet::new();
+    for change in self.edge_log.iter().rev() {
+      if change.time < since {
+        break;
+      }
+      let exists = self
+        .mr
+        .graph
+        .edge_weight(change.src, change.dst)
+        .is_ok_and(|weight| weight.is_some());
+      if change.old_weight == 0.0 && exists {
+        edges.insert((change.src, change.dst));
+      }
+    }
+    let mut counts: IntMap<NodeId, usize> = IntMap::default();
+    for (_, dst) in edges {
+      *counts.entry(dst).or_default() += 1;
+    }
+    counts
+  }
+
+  /// Share of the node's outgoing edges returned by users, weighted by the
+  /// similarity of their scores to the node's. 0 unless the node is a
+  /// calculated ego; partners that are not calculated count as dissimilar.
+  fn ring_signal(
+    &self,
+    id: NodeId,
+  ) -> f64 {
+    if !self.mr.get_personal_hits().contains_key(&id) {
+      return 0.0;
+    }
+    let node_data = match self.mr.graph.get_node_data(id) {
+      Some(x) => x,
+      None => return 0.0,
+    };
+    let out_degree = node_data.out_degree();
+    if out_degree == 0 {
+      return 0.0;
+    }
+    let similar: f64 = node_data
+      .get_outgoing_edges()
+      .filter(|(dst, weight)| {
+        *weight > 0.0
+          && self
+            .nodes
+            .get_by_id(*dst)
+            .is_some_and(|info| info.kind == NodeKind::User)
+          && matches!(
+            self.mr.graph.edge_weight(*dst, id),
+            Ok(Some(back)) if back > 0.0
+          )
+      })
+      .filter_map(|(dst, _)| self.mr.ego_similarity(id, dst).ok())
+      .map(|similarity| similarity.max(0.0))
+      .sum();
+    similar / out_degree as f64
+  }
+
+  /// Nodes with suspicious edge patterns: positive in-weight from nodes no
+  /// calculated ego trusts, reciprocal rings of users that score alike, and
+  /// bursts of new inbound edges. Only egos that are already calculated are
+  /// used, and bursts need the edge log, see `edge_log_size`.
+  pub fn read_anomalies(
+    &self,
+    data: OpReadAnomalies,
+  ) -> Vec<AnomalyResult> {
+    log_command!("{:?}", data);
+
+    let reputations = self.reputations();
+    let recent = self
+      .recent_inbound_edges(unix_time_secs().saturating_sub(data.window));
+
+    let mut res: Vec<AnomalyResult> = vec![];
+    for info in self.nodes.iter() {
+      let node_data = match self.mr.graph.get_node_data(info.id) {
+        Some(x) => x,
+        None => continue,
+      };
+      let in_degree = node_data.in_degree();
+      if in_degree == 0 && node_data.out_degree() == 0 {
+        continue;
+      }
+
+      let (in_weight, low_trust_weight) = node_data
+        .get_inbound_edges()
+        .filter(|(_, weight)| *weight > 0.0)
+        .fold((0.0, 0.0), |(total, low), (src, weight)| {
+          let trusted = reputations.get(&src).is_some_and(|x| *x > 0.0);
+          (total + weight, if trusted { low } else { low + weight })
+        });
+      let low_trust = if reputations.is_empty() || in_weight == 0.0 {
+        0.0
+      } else {
+        low_trust_weight / in_weight
+      };
+      let ring = self.ring_signal(info.id);
+      let burst = if in_degree == 0 {
+        0.0
+      } else {
+        let added = recent.get(&info.id).copied().unwrap_or(0);
+        (added as f64 / in_degree as f64).min(1.0)
+      };
+
+      let score = (low_trust + ring + burst) / 3.0;
+      if score > 0.0 {
+        res.push(AnomalyResult {
+          node: info.name.clone(),
+          score,
+          low_trust,
+          ring,
+          burst,
+        });
+      }
+    }
+
+    res.sort_by(|a, b| {
+      b.score.total_cmp(&a.score).then_with(|| a.node.cmp(&b.node))
+    });
+    res.truncate(data.limit as usize);
+    res
+  }
+}
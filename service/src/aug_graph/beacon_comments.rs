@@ -0,0 +1,80 @@
+use crate::data::*;
+use crate::utils::log::*;
+
+use super::AugGraph;
+
+impl AugGraph {
+  /// Comments of the request, known to the context, ranked for the ego.
+  /// Each comment scores `beacon_weight * s(beacon) + comment_weight *
+  /// s(comment) + author_weight * s(author)`, scores being the ego's.
+  pub fn read_beacon_comments(
+    &self,
+    data: OpReadBeaconComments,
+  ) -> ResBeaconComments {
+    log_command!("{:?}", data);
+
+    let empty = ResBeaconComments {
+      beacon_score: 0.0,
+      comments:     vec![],
+    };
+
+    let ego_info = match self.nodes.get_by_name(&data.ego) {
+      Some(x) => x,
+      None => {
+        log_error!("Node not found: {:?}", data.ego);
+        return empty;
+      },
+    };
+    if !self.ensure_ego_kind_enabled(&data.ego, ego_info) {
+      return empty;
+    }
+    let ego_id = ego_info.id;
+
+    let beacon_score = match self.nodes.get_by_name(&data.beacon) {
+      Some(info) => self.fetch_score_cached(ego_id, info.id).0,
+      None => {
+        log_warning!("Beacon not found: {:?}", data.beacon);
+        0.0
+      },
+    };
+
+    let mut comments: Vec<BeaconCommentResult> = data
+      .comments
+      .iter()
+      .filter_map(|name| match self.nodes.get_by_name(name) {
+        Some(info) => Some(info.id),
+        None => {
+          log_verbose!("Skip unknown comment {:?}", name);
+          None
+        },
+      })
+      .map(|comment_id| {
+        let comment_score = self.fetch_score_cached(ego_id, comment_id).0;
+        let author_id = self
+          .get_object_owner(comment_id)
+          .filter(|id| *id != comment_id);
+        let author_score = author_id
+          .map_or(0.0, |id| self.fetch_score_cached(ego_id, id).0);
+        BeaconCommentResult {
+          comment: self.nodes.id_to_info[comment_id].name.clone(),
+          author: author_id
+            .and_then(|id| self.nodes.get_by_id(id))
+            .map(|info| info.name.clone()),
+          score: data.beacon_weight * beacon_score
+            + data.comment_weight * comment_score
+            + data.author_weight * author_score,
+          comment_score,
+          author_score,
+        }
+      })
+      .collect();
+
+    comments.sort_by(|a, b| b.score.total_cmp(&a.score));
+    comments.truncate(data.limit as usize);
+
+    ResBeaconComments {
+      beacon_score,
+      comments,
+    }
+  }
+}
@@ -2,33 +2,164 @@ use crate::data::*;
 use crate::node_registry::*;
 use crate::utils::log::*;
 
+use meritrank_core::{MeritRankError, NodeId, RandomWalk};
+
+use std::time::{Duration, Instant};
+
 use super::AugGraph;
 
 impl AugGraph {
   pub fn calculate(
     &mut self,
     ego: NodeName,
+  ) {
+    self.calculate_until(ego, None);
+  }
+
+  /// Calculates again every ego that has walks, dropping their cached scores.
+  pub fn recalculate_all(&mut self) {
+    log_command!();
+
+    let egos: Vec<NodeName> = self
+      .mr
+      .get_personal_hits()
+      .keys()
+      .filter_map(|id| self.nodes.get_by_id(*id))
+      .map(|info| info.name.clone())
+      .collect();
+    self.invalidate_all();
+    for ego in egos {
+      self.calculate(ego);
+    }
+    self.edges_changed = 0;
+  }
+
+  /// Whether so many edges changed since the walks were calculated from
+  /// scratch that recalculating them all beats extending them further, see
+  /// `rerank_threshold`.
+  pub fn needs_rerank(&self) -> bool {
+    let threshold = self.settings.rerank_threshold;
+    threshold > 0.0
+      && !self.mr.get_personal_hits().is_empty()
+      && self.edges_changed as f64 > threshold * self.edge_count().max(1) as f64
+  }
+
+  /// Replaces the walks of the ego with walks saved from the same graph, see
+  /// `StateStore`.
+  pub fn import_walks(
+    &mut self,
+    ego: NodeId,
+    walks: Vec<RandomWalk>,
+  ) -> Result<(), MeritRankError> {
+    self.invalidate_ego(ego);
+    self.mr.import_walks(ego, walks)?;
+    self.evicted_scores.remove(&ego);
+    self.partial_scores.remove(&ego);
+    Ok(())
+  }
+
+  /// Calculates the ego if it has no walks yet. Gives up after `budget`,
+  /// leaving the ego uncalculated. Egos that already have walks are kept, so
+  /// that a copy of the graph that applies the op late does not lose them.
+  pub fn calculate_within(
+    &mut self,
+    ego: NodeName,
+    budget: Duration,
+  ) {
+    if let Some(info) = self.nodes.get_by_name(&ego) {
+      if self.mr.get_personal_hits().contains_key(&info.id) {
+        return;
+      }
+    }
+    self.calculate_until(ego, Some(Instant::now() + budget));
+  }
+
+  /// Same as `calculate_within`, but if the walks are not done within
+  /// `budget`, keeps the scores of those done so far for reads of the ego,
+  /// see `has_partial_scores`. The ego is left uncalculated.
+  pub fn calculate_partial(
+    &mut self,
+    ego: NodeName,
+    budget: Duration,
+  ) {
+    if let Some(info) = self.nodes.get_by_name(&ego) {
+      if self.mr.get_personal_hits().contains_key(&info.id) {
+        return;
+      }
+    }
+    let ego_id = match self.register_ego(ego) {
+      Some(x) => x,
+      None => return,
+    };
+
+    let _span = tracing::debug_span!("walks", ego = ego_id).entered();
+    match self.mr.calculate_partial(ego_id, Instant::now() + budget) {
+      Ok(None) => {
+        self.evicted_scores.remove(&ego_id);
+        self.partial_scores.remove(&ego_id);
+      },
+      Ok(Some(partial)) if partial.walks == 0 => {
+        log_warning!("Calculation of node {} timed out", ego_id);
+      },
+      Ok(Some(mut partial)) => {
+        log_verbose!(
+          "Calculation of node {} stopped after {} walks",
+          ego_id,
+          partial.walks
+        );
+        partial.scores.sort_unstable_by_key(|(id, _)| *id);
+        self.partial_scores.insert(ego_id, partial);
+      },
+      Err(e) => log_error!("{}", e),
+    };
+  }
+
+  fn calculate_until(
+    &mut self,
+    ego: NodeName,
+    deadline: Option<Instant>,
   ) {
     log_trace!("{:?}", ego);
 
+    let ego_id = match self.register_ego(ego) {
+      Some(x) => x,
+      None => return,
+    };
+
+    let _span = tracing::debug_span!("walks", ego = ego_id).entered();
+    match self.mr.calculate_until(ego_id, deadline) {
+      Ok(_) => {
+        self.evicted_scores.remove(&ego_id);
+        self.partial_scores.remove(&ego_id);
+      },
+      Err(MeritRankError::Timeout) => {
+        log_warning!("Calculation of node {} timed out", ego_id);
+      },
+      Err(e) => log_error!("{}", e),
+    };
+  }
+
+  /// Registers the ego for a calculation, unless its kind can't be one, and
+  /// invalidates its cached scores.
+  fn register_ego(
+    &mut self,
+    ego: NodeName,
+  ) -> Option<NodeId> {
     let kind = match node_kind_from_prefix(&ego) {
       Some(x) => x,
       None => {
         log_error!("Failed to get node kind for {:?}", ego);
-        return;
+        return None;
       },
     };
 
-    if kind != NodeKind::User {
-      log_error!("Non-user node used as ego for calculation (rejected): {:?}", ego);
-      return;
+    if !self.settings.ego_kinds.contains(&kind) {
+      log_error!("Node of disabled kind used as ego for calculation (rejected): {:?}", ego);
+      return None;
     }
 
     let ego_id = self.nodes.register(&mut self.mr, ego, kind);
-
-    match self.mr.calculate(ego_id) {
-      Ok(_) => {},
-      Err(e) => log_error!("{}", e),
-    };
+    self.invalidate_ego(ego_id);
+    Some(ego_id)
   }
 }
@@ -0,0 +1,71 @@
+use crate::data::*;
+use crate::utils::log::*;
+
+use meritrank_core::{Counter, NodeId};
+use rand::seq::IndexedRandom;
+
+use super::AugGraph;
+
+impl AugGraph {
+  /// Raw hits and a sample of the walks of the ego, to find out why it
+  /// scores a node the way it does. Uncalculated egos have no walks.
+  pub fn debug_ego(
+    &self,
+    data: OpDebugEgo,
+  ) -> ResDebugEgo {
+    log_command!("{:?}", data);
+
+    let mut res = ResDebugEgo {
+      walks:         0,
+      positive_hits: vec![],
+      negative_hits: vec![],
+      sample:        vec![],
+    };
+
+    let ego_id = match self.nodes.get_by_name(&data.ego) {
+      Some(x) => x.id,
+      None => {
+        log_error!("Node not found: {:?}", data.ego);
+        return res;
+      },
+    };
+
+    let walks: Vec<_> = self.mr.get_ego_walks(ego_id).collect();
+    res.walks = walks.len() as u32;
+    res.sample = walks
+      .choose_multiple(&mut self.sample_rng(), data.walks as usize)
+      .map(|walk| walk.iter().map(|id| self.debug_name(*id)).collect())
+      .collect();
+    res.positive_hits =
+      self.debug_hits(self.mr.get_personal_hits().get(&ego_id));
+    res.negative_hits =
+      self.debug_hits(self.mr.get_negative_hits().get(&ego_id));
+    res
+  }
+
+  fn debug_hits(
+    &self,
+    counter: Option<&Counter>,
+  ) -> Vec<(NodeName, i64)> {
+    let mut hits: Vec<(NodeName, i64)> = counter
+      .into_iter()
+      .flatten()
+      .filter(|(_, count)| **count != 0)
+      .map(|(id, count)| (self.debug_name(*id), *count as i64))
+      .collect();
+    hits.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    hits
+  }
+
+  /// Name of the node, or its id if it is not registered.
+  fn debug_name(
+    &self,
+    id: NodeId,
+  ) -> NodeName {
+    self
+      .nodes
+      .id_to_info
+      .get(id)
+      .map_or_else(|| id.to_string(), |info| info.name.clone())
+  }
+}
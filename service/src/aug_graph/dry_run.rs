@@ -0,0 +1,71 @@
+use crate::data::*;
+use crate::utils::log::*;
+
+use super::AugGraph;
+
+use std::collections::HashMap;
+
+impl AugGraph {
+  /// Scores of the ego before and after the edge writes. The writes are
+  /// applied to a fork of the graph, which is dropped afterwards. The ego
+  /// must be calculated.
+  pub fn read_dry_run(
+    &self,
+    data: OpReadDryRun,
+  ) -> Vec<ScoreDelta> {
+    log_command!("{:?}", data);
+
+    let read = |graph: &AugGraph| {
+      graph
+        .read_scores(OpReadScores {
+          ego:           data.ego.clone(),
+          score_options: data.score_options.clone(),
+        })
+        .unwrap_or_default()
+    };
+
+    let mut nodes: HashMap<NodeName, ScoreDelta> = HashMap::new();
+    for score in read(self) {
+      nodes.insert(
+        score.target.clone(),
+        ScoreDelta {
+          target: score.target,
+          before: score.score,
+          after:  0.0,
+        },
+      );
+    }
+
+    let mut fork = self.fork();
+    for edge in &data.edges {
+      fork.set_edge_of_kind(&OpWriteEdge {
+        amount: edge.amount * self.settings.edge_kind_weight(edge.edge_kind),
+        ..edge.clone()
+      });
+    }
+    //  Writes that change the ego's own edges may drop its walks.
+    let calculated = fork.nodes.get_by_name(&data.ego).is_some_and(|info| {
+      fork.mr.get_personal_hits().contains_key(&info.id)
+    });
+    if !calculated {
+      fork.calculate(data.ego.clone());
+    }
+    for score in read(&fork) {
+      let entry = nodes.entry(score.target.clone()).or_insert(ScoreDelta {
+        target: score.target,
+        before: 0.0,
+        after:  0.0,
+      });
+      entry.after = score.score;
+    }
+
+    let mut nodes: Vec<ScoreDelta> = nodes.into_values().collect();
+    nodes.sort_by(|x, y| {
+      (y.after - y.before)
+        .abs()
+        .total_cmp(&(x.after - x.before).abs())
+        .then_with(|| x.target.cmp(&y.target))
+    });
+    nodes
+  }
+}
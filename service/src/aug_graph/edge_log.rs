@@ -0,0 +1,178 @@
+use crate::data::*;
+use crate::history::unix_time_secs;
+use crate::utils::log::*;
+
+use meritrank_core::{MeritRankError, NodeId, Weight};
+
+use super::AugGraph;
+
+use parking_lot::Mutex;
+
+use std::sync::Arc;
+
+/// Change of an edge in the underlying graph, including rescales and
+/// deletions done by VSIDS. Undoing the changes in reverse order restores
+/// the graph at an earlier time.
+#[derive(Clone, Debug)]
+pub struct EdgeChange {
+  pub time:       u64,
+  pub src:        NodeId,
+  pub dst:        NodeId,
+  pub old_weight: Weight,
+}
+
+impl AugGraph {
+  /// Sets the edge in the underlying graph, logging the change if
+  /// `edge_log_size` is set, and keeping `edges_changed` up to date.
+  pub(crate) fn set_mr_edge(
+    &mut self,
+    src: NodeId,
+    dst: NodeId,
+    weight: Weight,
+  ) -> Result<(), MeritRankError> {
+    let old_weight = self.mr.graph.edge_weight(src, dst).ok().flatten();
+    if self.settings.edge_log_size > 0 {
+      let old_weight = old_weight.unwrap_or(0.0);
+      let time = unix_time_secs();
+      self.edge_log_since.get_or_insert(time);
+      while self.edge_log.len() >= self.settings.edge_log_size {
+        match self.edge_log.pop_front() {
+          Some(dropped) => self.edge_log_since = Some(dropped.time),
+          None => break,
+        }
+      }
+      self.edge_log.push_back(EdgeChange {
+        time,
+        src,
+        dst,
+        old_weight,
+      });
+    } else if self.edge_log_since.is_some() {
+      //  Changes are no longer logged, so the log can't be undone past them.
+      self.edge_log.clear();
+      self.edge_log_since = None;
+    }
+    let result = self.mr.set_edge(src, dst, weight);
+    let new_weight = self.mr.graph.edge_weight(src, dst).ok().flatten();
+    //  Changes made before any ego is calculated cost the walks nothing.
+    if old_weight != new_weight && !self.mr.get_personal_hits().is_empty() {
+      self.edges_changed += 1;
+    }
+    result
+  }
+
+  /// Weight of the edge as of Unix time `time`, missing edges weighing 0.
+  /// Changes that are no longer in the edge log are not seen.
+  pub fn edge_weight_at(
+    &self,
+    src: &NodeName,
+    dst: &NodeName,
+    time: u64,
+  ) -> Weight {
+    let (src_id, dst_id) =
+      match (self.nodes.get_by_name(src), self.nodes.get_by_name(dst)) {
+        (Some(src), Some(dst)) => (src.id, dst.id),
+        _ => return 0.0,
+      };
+    self
+      .edge_log
+      .iter()
+      .find(|x| x.time > time && x.src == src_id && x.dst == dst_id)
+      .map_or_else(|| self.edge_weight(src, dst), |x| x.old_weight)
+  }
+
+  /// Copy of the graph as of Unix time `time`, without walks. None if
+  /// changes since then are no longer in the edge log, or if it was made
+  /// before the changes were logged.
+  pub fn state_at(
+    &self,
+    time: u64,
+  ) -> Option<AugGraph> {
+    if self.settings.edge_log_size == 0
+      || self.edge_log_since.is_none_or(|since| time < since)
+    {
+      return None;
+    }
+
+    let mut past = AugGraph::new(self.settings.clone());
+    //  Undoing the changes is not logged.
+    past.settings.edge_log_size = 0;
+    past.mr = self.mr.clone_without_walks();
+    past.nodes = self.nodes.clone();
+    past.zero_opinion = self.zero_opinion.clone();
+    past.vsids = self.vsids.clone();
+    past.stamp = self.stamp;
+    past.personal_filters = self.personal_filters.clone();
+    past.edge_kinds = self.edge_kinds.clone();
+    past.edge_provenance = self.edge_provenance.clone();
+    past.mutes = self.mutes.clone();
+    past.polls = self.polls.clone();
+
+    for change in self.edge_log.iter().rev() {
+      if change.time <= time {
+        break;
+      }
+      let owners_before = past.personal_owners(change.src);
+      if let Err(e) =
+        past.set_mr_edge(change.src, change.dst, change.old_weight)
+      {
+        log_error!("{}", e);
+      }
+      past.update_personal_filters(change.src, owners_before);
+    }
+    Some(past)
+  }
+
+  /// Same as `state_at`, shared with later reads of the same time. States
+  /// are only kept once a change is logged after `time`, since until then
+  /// changes at `time` itself may still come.
+  pub fn past_state(
+    &self,
+    time: u64,
+  ) -> Option<Arc<Mutex<AugGraph>>> {
+    if self.settings.edge_log_size == 0
+      || self.edge_log_since.is_none_or(|since| time < since)
+    {
+      return None;
+    }
+    if let Some(past) = self.past_states.get(&time) {
+      return Some(past);
+    }
+    let past = Arc::new(Mutex::new(self.state_at(time)?));
+    if self.edge_log.back().is_some_and(|last| time < last.time) {
+      self.past_states.insert(time, past.clone());
+    }
+    Some(past)
+  }
+
+  /// Scores of the ego in the graph as of the given time. Zero opinions and
+  /// node names are those of when the past state was reconstructed.
+  pub fn read_scores_at(
+    &self,
+    data: OpReadScoresAt,
+  ) -> Vec<ScoreResult> {
+    log_command!("{:?}", data);
+
+    let past = match self.past_state(data.timestamp) {
+      Some(x) => x,
+      None => {
+        log_error!("Edge log does not reach back to {}", data.timestamp);
+        return vec![];
+      },
+    };
+    let mut past = past.lock();
+    let calculated = past
+      .nodes
+      .get_by_name(&data.ego)
+      .is_some_and(|info| past.mr.get_personal_hits().contains_key(&info.id));
+    if !calculated {
+      past.calculate(data.ego.clone());
+    }
+    past
+      .read_scores(OpReadScores {
+        ego:           data.ego,
+        score_options: data.score_options,
+      })
+      .unwrap_or_default()
+  }
+}
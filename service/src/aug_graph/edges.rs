@@ -1,9 +1,10 @@
 use crate::data::*;
-use crate::node_registry::*;
 use crate::utils::log::*;
 use crate::vsids::Magnitude;
 
-use meritrank_core::{NodeId, Weight};
+use meritrank_core::{constants::EPSILON, NodeId, Weight};
+
+use std::collections::BTreeSet;
 
 use super::{AugGraph, AugGraphError};
 
@@ -17,6 +18,9 @@ impl AugGraph {
   ) {
     log_trace!();
 
+    self.invalidate_egos_visiting(src_id);
+    let owners_before = self.personal_owners(src_id);
+
     let (new_weight_scaled, rescale_factor, new_max_weight, updated_min) =
       self.vsids.apply_edge_update(src_id, amount, magnitude);
 
@@ -31,7 +35,7 @@ impl AugGraph {
       must_rescale,
     );
 
-    match self.mr.set_edge(src_id, dst_id, new_weight_scaled) {
+    match self.set_mr_edge(src_id, dst_id, new_weight_scaled) {
       Ok(_) => {},
       Err(e) => {
         log_error!("{}", e);
@@ -54,6 +58,7 @@ impl AugGraph {
       );
     }
     self.vsids.finish_edge_update(src_id, new_min_weight);
+    self.update_personal_filters(src_id, owners_before);
   }
 
   fn apply_edge_rescales_and_deletions(
@@ -104,7 +109,7 @@ impl AugGraph {
         dst_id_iter,
         weight_iter
       );
-      match self.mr.set_edge(src_id, dst_id_iter, weight_iter) {
+      match self.set_mr_edge(src_id, dst_id_iter, weight_iter) {
         Ok(_) => {},
         Err(e) => {
           log_error!("{}", e);
@@ -138,6 +143,245 @@ impl AugGraph {
     }
   }
 
+  /// Sets the edge like `set_edge` and records its kind and provenance. The
+  /// amount is already multiplied by the factor of the kind.
+  pub fn set_edge_of_kind(
+    &mut self,
+    data: &OpWriteEdge,
+  ) {
+    let (src, dst) = (&data.src, &data.dst);
+    self.set_edge(src.clone(), dst.clone(), data.amount, data.magnitude);
+    let (src_id, dst_id) =
+      match (self.nodes.get_by_name(src), self.nodes.get_by_name(dst)) {
+        (Some(src), Some(dst)) => (src.id, dst.id),
+        _ => return,
+      };
+    self.record_edge_kind(src_id, dst_id, data.edge_kind);
+    self.record_edge_provenance(src_id, dst_id, data.provenance.clone());
+  }
+
+  fn record_edge_kind(
+    &mut self,
+    src_id: NodeId,
+    dst_id: NodeId,
+    kind: EdgeKind,
+  ) {
+    if kind == EdgeKind::Vote {
+      self.edge_kinds.remove(&(src_id, dst_id));
+    } else {
+      self.edge_kinds.insert((src_id, dst_id), kind);
+    }
+  }
+
+  /// Kind the edge was last written with; missing edges are votes.
+  pub fn edge_kind(
+    &self,
+    src: &NodeName,
+    dst: &NodeName,
+  ) -> EdgeKind {
+    match (self.nodes.get_by_name(src), self.nodes.get_by_name(dst)) {
+      (Some(src), Some(dst)) => self.edge_kind_by_id(src.id, dst.id),
+      _ => EdgeKind::Vote,
+    }
+  }
+
+  pub(crate) fn edge_kind_by_id(
+    &self,
+    src_id: NodeId,
+    dst_id: NodeId,
+  ) -> EdgeKind {
+    self.edge_kinds.get(&(src_id, dst_id)).copied().unwrap_or_default()
+  }
+
+  fn record_edge_provenance(
+    &mut self,
+    src_id: NodeId,
+    dst_id: NodeId,
+    provenance: Option<EdgeProvenance>,
+  ) {
+    match provenance {
+      Some(x) => self.edge_provenance.insert((src_id, dst_id), x),
+      None => self.edge_provenance.remove(&(src_id, dst_id)),
+    };
+  }
+
+  /// Provenance the edge was last written with, if any.
+  pub fn edge_provenance(
+    &self,
+    src: &NodeName,
+    dst: &NodeName,
+  ) -> Option<EdgeProvenance> {
+    match (self.nodes.get_by_name(src), self.nodes.get_by_name(dst)) {
+      (Some(src), Some(dst)) => self.edge_provenance_by_id(src.id, dst.id),
+      _ => None,
+    }
+  }
+
+  pub(crate) fn edge_provenance_by_id(
+    &self,
+    src_id: NodeId,
+    dst_id: NodeId,
+  ) -> Option<EdgeProvenance> {
+    self.edge_provenance.get(&(src_id, dst_id)).cloned()
+  }
+
+  /// Sets the edge like `set_edge` if its weight is the expected one, and
+  /// an existing edge is of the same kind. Returns whether it was set.
+  pub fn set_edge_cas(
+    &mut self,
+    data: &OpWriteEdgeCas,
+  ) -> bool {
+    let old_weight = self.edge_weight(&data.src, &data.dst);
+    if old_weight != data.expected_old_weight
+      || (old_weight != 0.0
+        && self.edge_kind(&data.src, &data.dst) != data.edge_kind)
+    {
+      log_warning!("Edge {:?} -> {:?} changed, skipped", data.src, data.dst);
+      return false;
+    }
+    //  Compare-and-set writes carry no provenance.
+    self.set_edge_of_kind(&OpWriteEdge {
+      src:        data.src.clone(),
+      dst:        data.dst.clone(),
+      amount:     data.new_weight,
+      magnitude:  0,
+      edge_kind:  data.edge_kind,
+      provenance: None,
+    });
+    true
+  }
+
+  /// Whether the node has no edges in either direction, or is not in the
+  /// graph at all, e.g. a new user.
+  pub fn has_no_edges(
+    &self,
+    name: &NodeName,
+  ) -> bool {
+    let id = match self.nodes.get_by_name(name) {
+      Some(x) => x.id,
+      None => return true,
+    };
+    self
+      .mr
+      .graph
+      .get_node_data(id)
+      .is_none_or(|data| data.out_degree() == 0 && data.in_degree() == 0)
+  }
+
+  /// Current weight of the edge, missing edges weighing 0.
+  pub fn edge_weight(
+    &self,
+    src: &NodeName,
+    dst: &NodeName,
+  ) -> Weight {
+    match (self.nodes.get_by_name(src), self.nodes.get_by_name(dst)) {
+      (Some(src), Some(dst)) => self
+        .mr
+        .graph
+        .edge_weight(src.id, dst.id)
+        .ok()
+        .flatten()
+        .unwrap_or(0.0),
+      _ => 0.0,
+    }
+  }
+
+  /// How many distinct nodes and edges the writes add to the graph.
+  pub fn growth(
+    &self,
+    writes: &[&OpWriteEdge],
+  ) -> (usize, usize) {
+    let mut new_nodes = BTreeSet::new();
+    let mut new_edges = BTreeSet::new();
+    for data in writes {
+      let src = self.nodes.get_by_name(&data.src).map(|x| x.id);
+      let dst = self.nodes.get_by_name(&data.dst).map(|x| x.id);
+      if src.is_none() {
+        new_nodes.insert(&data.src);
+      }
+      if dst.is_none() {
+        new_nodes.insert(&data.dst);
+      }
+      let new_edge = match (src, dst) {
+        (Some(src), Some(dst)) => {
+          !matches!(self.mr.graph.edge_weight(src, dst), Ok(Some(_)))
+        },
+        _ => true,
+      };
+      if new_edge {
+        new_edges.insert((&data.src, &data.dst));
+      }
+    }
+    (new_nodes.len(), new_edges.len())
+  }
+
+  /// Rejects the edges a `WriteMulti` writes to the context if they would
+  /// take it over `max_context_nodes` or `max_context_edges`. Unlike the
+  /// check before the part is queued, the writes queued before it are
+  /// counted.
+  pub fn check_part(
+    &self,
+    writes: &[OpWriteEdge],
+  ) -> Result<(), QuotaLimit> {
+    let writes: Vec<&OpWriteEdge> =
+      writes.iter().filter(|data| data.amount != 0.0).collect();
+    let (new_nodes, new_edges) = self.growth(&writes);
+    let settings = &self.settings;
+    if settings.max_context_nodes > 0
+      && new_nodes > 0
+      && self.nodes.len() + new_nodes > settings.max_context_nodes
+    {
+      return Err(QuotaLimit::Nodes);
+    }
+    if settings.max_context_edges > 0
+      && new_edges > 0
+      && self.edge_count() + new_edges > settings.max_context_edges
+    {
+      return Err(QuotaLimit::Edges);
+    }
+    Ok(())
+  }
+
+  /// Multiplies weights of all edges by `factor` and removes edges that fell
+  /// below `EPSILON`. Only walks through removed edges change. Returns the
+  /// number of removed edges.
+  pub fn decay_edges(
+    &mut self,
+    factor: Weight,
+  ) -> usize {
+    log_trace!("{}", factor);
+
+    if let Err(e) = self.mr.graph.scale_edges(factor) {
+      log_error!("{}", e);
+      return 0;
+    }
+    self.vsids.scale(factor);
+
+    let pruned: Vec<(NodeId, NodeId)> = self
+      .mr
+      .graph
+      .nodes
+      .iter()
+      .enumerate()
+      .flat_map(|(src_id, data)| {
+        data
+          .get_outgoing_edges()
+          .filter(|(_, weight)| weight.abs() < EPSILON)
+          .map(move |(dst_id, _)| (src_id, dst_id))
+      })
+      .collect();
+
+    for (src_id, dst_id) in &pruned {
+      self.invalidate_egos_visiting(*src_id);
+      let owners_before = self.personal_owners(*src_id);
+      if let Err(e) = self.set_mr_edge(*src_id, *dst_id, 0.0) {
+        log_error!("{}", e);
+      }
+      self.update_personal_filters(*src_id, owners_before);
+    }
+    pruned.len()
+  }
+
   /// Bulk-load edges without creating walks. Clears walks first; VSIDS is not reset.
   /// Used for cold start. Walks are created lazily on first read via ensure_calculated.
   pub fn bulk_load_edges(
@@ -145,10 +389,15 @@ impl AugGraph {
     edges: Vec<OpWriteEdge>,
   ) {
     self.mr.clear_walks();
+    self.evicted_scores.clear();
+    self.partial_scores.clear();
+    self.invalidate_all();
     for edge in edges {
       match self.reg_owner_and_get_ids(edge.src.clone(), edge.dst.clone()) {
         Ok((src_id, dst_id)) => {
           self.set_edge_by_id(src_id, dst_id, edge.amount, edge.magnitude);
+          self.record_edge_kind(src_id, dst_id, edge.edge_kind);
+          self.record_edge_provenance(src_id, dst_id, edge.provenance);
         },
         Err(e) => match e {
           AugGraphError::SelfReference => {
@@ -160,6 +409,8 @@ impl AugGraph {
         },
       }
     }
+    //  There are no walks to extend; they are calculated from scratch.
+    self.edges_changed = 0;
   }
 
   fn reg_owner_and_get_ids(
@@ -171,8 +422,8 @@ impl AugGraph {
       return Err(AugGraphError::SelfReference);
     }
 
-    let opt_src_kind = node_kind_from_prefix(&src);
-    let opt_dst_kind = node_kind_from_prefix(&dst);
+    let opt_src_kind = self.node_kind(&src);
+    let opt_dst_kind = self.node_kind(&dst);
 
     match (opt_src_kind, opt_dst_kind) {
       (Some(NodeKind::User), Some(NodeKind::User)) => {
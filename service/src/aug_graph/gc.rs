@@ -0,0 +1,67 @@
+use crate::data::*;
+use crate::utils::log::*;
+
+use meritrank_core::graph::NodeData;
+use meritrank_core::NodeId;
+
+use super::AugGraph;
+
+impl AugGraph {
+  /// True for a non-user node with no edges in either direction and no walks
+  /// through it or from it. Users are never orphans, since they are shared by
+  /// all contexts.
+  fn is_orphan(
+    &self,
+    id: NodeId,
+  ) -> bool {
+    match self.nodes.get_by_id(id) {
+      Some(info) if info.kind != NodeKind::User => {},
+      _ => return false,
+    }
+    let data = match self.mr.graph.get_node_data(id) {
+      Some(x) => x,
+      None => return false,
+    };
+    data.pos_edges.is_empty()
+      && data.neg_edges.is_empty()
+      && data.inbound_edges.is_empty()
+      && !self.mr.get_personal_hits().contains_key(&id)
+      && self.mr.egos_visiting(id).is_empty()
+  }
+
+  pub fn find_orphans(&self) -> Vec<NodeName> {
+    self
+      .nodes
+      .iter()
+      .filter(|info| self.is_orphan(info.id))
+      .map(|info| info.name.clone())
+      .collect()
+  }
+
+  /// Tombstones the nodes that are still orphans and frees their data in the
+  /// core graph. Returns the number of nodes removed.
+  pub fn remove_orphans(
+    &mut self,
+    names: &[NodeName],
+  ) -> usize {
+    log_trace!("{}", names.len());
+
+    let ids: Vec<NodeId> = names
+      .iter()
+      .filter_map(|name| self.nodes.get_by_name(name))
+      .map(|info| info.id)
+      .filter(|&id| self.is_orphan(id))
+      .collect();
+
+    for &id in &ids {
+      self.mr.graph.nodes[id] = NodeData::default();
+      if let Some(score) = self.zero_opinion.get_mut(id) {
+        *score = 0.0;
+      }
+    }
+    self.nodes.tombstone(&ids);
+
+    log_verbose!("Removed {} orphan nodes", ids.len());
+    ids.len()
+  }
+}
@@ -10,21 +10,38 @@ use std::collections::HashMap;
 
 use super::AugGraph;
 
+/// Ego, focus, the graph to search and the maps between its indices and
+/// node ids.
+type GraphSetup = (
+  NodeId,
+  NodeId,
+  DiGraph<NodeId, Weight>,
+  HashMap<NodeId, NodeIndex>,
+  HashMap<NodeIndex, NodeId>,
+);
+
+/// Approximate memory per node, edge and walk, in bytes. A walk is about
+/// seven steps long on average, each step stored in the walk and in the
+/// visits index.
+const NODE_MEMORY: usize = 256;
+const EDGE_MEMORY: usize = 64;
+const WALK_MEMORY: usize = 256;
+
+/// Rough estimate of the memory used by a graph of the given size, in bytes.
+pub fn estimate_memory(
+  nodes: usize,
+  edges: usize,
+  walks: usize,
+) -> usize {
+  nodes * NODE_MEMORY + edges * EDGE_MEMORY + walks * WALK_MEMORY
+}
+
 impl AugGraph {
   pub fn validate_read_graph_params_and_setup(
     &self,
     ego_str: &str,
     focus_str: &str,
-  ) -> Result<
-    (
-      NodeId,
-      NodeId,
-      DiGraph<NodeId, Weight>,
-      HashMap<NodeId, NodeIndex>,
-      HashMap<NodeIndex, NodeId>,
-    ),
-    String,
-  > {
+  ) -> Result<GraphSetup, String> {
     let ego_id = match self.nodes.get_by_name(ego_str) {
       Some(x) => x.id,
       None => {
@@ -84,7 +101,7 @@ impl AugGraph {
 
   fn add_shortest_path_to_graph(
     &self,
-    node_infos: &Vec<NodeInfo>,
+    node_infos: &[NodeInfo],
     ego_id: NodeId,
     focus_id: NodeId,
     indices: &mut HashMap<NodeId, NodeIndex>,
@@ -95,8 +112,8 @@ impl AugGraph {
 
     let ego_to_focus =
       match perform_astar_search(&self.mr.graph, ego_id, focus_id) {
-        Ok(path) => path,
-        Err(_) => return vec![],
+        Some(path) => path,
+        None => return vec![],
       };
 
     let mut edges = Vec::<(NodeId, NodeId, Weight)>::new();
@@ -111,15 +128,9 @@ impl AugGraph {
       let b_info_opt = node_infos.get(b);
       let a_b_weight = self.edge_weight_normalized(a, b);
 
-      let a_kind_opt = match a_info_opt {
-        Some(info) => Some(info.kind),
-        None => None,
-      };
+      let a_kind_opt = a_info_opt.map(|info| info.kind);
 
-      let b_kind_opt = match b_info_opt {
-        Some(info) => Some(info.kind),
-        None => None,
-      };
+      let b_kind_opt = b_info_opt.map(|info| info.kind);
 
       if k + 2 == ego_to_focus.len() {
         if a_kind_opt == Some(NodeKind::User) {
@@ -196,23 +207,21 @@ impl AugGraph {
     v
   }
 
+  #[allow(clippy::too_many_arguments)]
   fn add_focus_neighbor_connections(
     &self,
     focus_id: NodeId,
     im_graph: &mut DiGraph<NodeId, Weight>,
     indices: &mut HashMap<NodeId, NodeIndex>,
     ids: &mut HashMap<NodeIndex, NodeId>,
-    node_infos: &Vec<NodeInfo>,
+    node_infos: &[NodeInfo],
     positive_only: bool,
     focus_neighbors: &[(NodeId, Weight)],
   ) {
     log_trace!();
 
     for (dst_id, focus_dst_weight) in focus_neighbors.iter() {
-      let dst_kind_opt = match node_infos.get(*dst_id) {
-        Some(x) => Some(x.kind),
-        None => None,
-      };
+      let dst_kind_opt = node_infos.get(*dst_id).map(|x| x.kind);
 
       if positive_only && *focus_dst_weight <= 0.0 {
         continue;
@@ -230,6 +239,7 @@ impl AugGraph {
       } else if dst_kind_opt == Some(NodeKind::Comment)
         || dst_kind_opt == Some(NodeKind::Beacon)
         || dst_kind_opt == Some(NodeKind::Opinion)
+        || matches!(dst_kind_opt, Some(NodeKind::Custom(_)))
       {
         let dst_neighbors = self.all_outbound_neighbors_normalized(*dst_id);
         for (ngh_id, dst_ngh_weight) in dst_neighbors {
@@ -291,6 +301,7 @@ impl AugGraph {
       .collect()
   }
 
+  #[allow(clippy::too_many_arguments)]
   fn collect_all_edges(
     &self,
     indices: &HashMap<NodeId, NodeIndex>,
@@ -363,12 +374,12 @@ impl AugGraph {
       };
 
     if let Some(ego_info) = self.nodes.get_by_name(ego_str) {
-      if !self.ensure_ego_is_user(ego_str, ego_info) {
+      if !self.ensure_ego_kind_enabled(ego_str, ego_info) {
         return vec![];
       }
     }
 
-    let node_infos = self.nodes.id_to_info.clone();
+    let node_infos = &self.nodes.id_to_info;
     let force_read_graph_conn = self.settings.force_read_graph_conn;
 
     let mut path_edges = if ego_id == focus_id {
@@ -376,7 +387,7 @@ impl AugGraph {
       vec![]
     } else {
       self.add_shortest_path_to_graph(
-        &node_infos,
+        node_infos,
         ego_id,
         focus_id,
         &mut indices,
@@ -406,7 +417,7 @@ impl AugGraph {
       &mut im_graph,
       &mut indices,
       &mut ids,
-      &node_infos,
+      node_infos,
       positive_only,
       &focus_neighbors,
     );
@@ -423,4 +434,39 @@ impl AugGraph {
       count as u32,
     )
   }
+
+  /// Lists every edge of the graph with its stored weight.
+  pub fn read_edges(&self) -> Vec<EdgeResult> {
+    let mut edges = Vec::with_capacity(self.nodes.id_to_info.len() * 2);
+
+    for (src_id, info) in self.nodes.id_to_info.iter().enumerate() {
+      if let Some(data) = self.mr.graph.get_node_data(src_id) {
+        let src_name = &info.name;
+
+        for (dst_id, weight) in data.get_outgoing_edges() {
+          match self.nodes.get_by_id(dst_id) {
+            Some(x) => edges.push(EdgeResult {
+              src: src_name.to_string(),
+              dst: x.name.clone(),
+              weight,
+            }),
+            None => log_error!("Node does not exist: {}", dst_id),
+          }
+        }
+      };
+    }
+
+    edges
+  }
+
+  pub fn edge_count(&self) -> usize {
+    self.mr.graph.edge_count()
+  }
+
+  /// Rough estimate of the memory used by the graph, in bytes, see
+  /// `estimate_memory`.
+  pub fn estimated_memory(&self) -> usize {
+    let walks = self.mr.get_personal_hits().len() * self.settings.num_walks;
+    estimate_memory(self.nodes.len(), self.edge_count(), walks)
+  }
 }
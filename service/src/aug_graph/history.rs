@@ -0,0 +1,163 @@
+use crate::data::*;
+use crate::history::ScoreSample;
+use crate::utils::log::*;
+
+use meritrank_core::NodeId;
+
+use super::AugGraph;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+impl AugGraph {
+  pub fn read_score_history(
+    &self,
+    data: OpReadScoreHistory,
+  ) -> Vec<ScoreSample> {
+    log_command!("{:?}", data);
+
+    let ego_id = match self.nodes.get_by_name(&data.ego) {
+      Some(x) => x.id,
+      None => {
+        log_error!("Node not found: {:?}", data.ego);
+        return vec![];
+      },
+    };
+    let target_id = match self.nodes.get_by_name(&data.target) {
+      Some(x) => x.id,
+      None => {
+        log_error!("Node not found: {:?}", data.target);
+        return vec![];
+      },
+    };
+
+    self.score_history.samples(ego_id, target_id)
+  }
+
+  /// Records current scores of all pairs with history whose ego has walks.
+  /// Returns the number of pairs sampled.
+  pub fn sample_score_history(&self) -> usize {
+    let mut num_sampled = 0;
+    for ego in self.score_history.egos() {
+      if !self.mr.get_personal_hits().contains_key(&ego) {
+        continue;
+      }
+      for target in self.score_history.targets(ego) {
+        let (score, _) = self.fetch_score_cached(ego, target);
+        self.score_history.record(ego, target, score);
+        num_sampled += 1;
+      }
+    }
+    num_sampled
+  }
+
+  /// Keeps all scores of the ego as of the generation for `read_score_diff`,
+  /// unless they are kept already.
+  pub(crate) fn snapshot_scores(
+    &self,
+    ego_id: NodeId,
+    generation: u64,
+    scores: &[(NodeId, NodeScore)],
+  ) {
+    if self.settings.score_snapshots == 0 {
+      return;
+    }
+    let key = (ego_id, generation);
+    if !self.score_snapshots.contains_key(&key) {
+      self.score_snapshots.insert(key, Arc::new(scores.to_vec()));
+    }
+  }
+
+  /// Targets whose score or cluster by the ego changed between two
+  /// generations of its scores, biggest changes first. Both generations are
+  /// snapshots of the scores served then, so scores of the same walks never
+  /// differ. Zero opinions and cluster bounds are the current ones. None if
+  /// the scores of either generation are not kept.
+  pub fn read_score_diff(
+    &self,
+    data: OpReadScoreDiff,
+  ) -> Option<ResScoreDiff> {
+    log_command!("{:?}", data);
+
+    let ego_id = match self.nodes.get_by_name(&data.ego) {
+      Some(x) => x.id,
+      None => {
+        log_error!("Node not found: {:?}", data.ego);
+        return None;
+      },
+    };
+    let current = self.ego_generation(ego_id);
+    let generation = |x: u64| if x == 0 { current } else { x };
+    let (from, to) = (generation(data.from), generation(data.to));
+    if (from == current || to == current)
+      && !self.score_snapshots.contains_key(&(ego_id, current))
+    {
+      //  Snapshots the current scores.
+      let _ = self
+        .try_fetch_all_raw_scores(ego_id, self.settings.zero_opinion_factor);
+    }
+
+    let num_clusters = self.num_clusters(0);
+    let scores_at = |generation: u64| {
+      let scores = match self.score_snapshots.get(&(ego_id, generation)) {
+        Some(x) => x,
+        None => {
+          log_error!(
+            "Scores of {:?} at generation {} are not kept",
+            data.ego,
+            generation
+          );
+          return None;
+        },
+      };
+      let scores: HashMap<NodeId, (NodeScore, NodeCluster)> = self
+        .with_zero_opinions(scores.to_vec())
+        .into_iter()
+        .filter_map(|(id, score)| {
+          let kind = self.nodes.get_by_id(id)?.kind;
+          Some((
+            id,
+            self.apply_score_clustering_at(ego_id, score, kind, num_clusters),
+          ))
+        })
+        .collect();
+      Some(scores)
+    };
+    let old_scores = scores_at(from)?;
+    let new_scores = scores_at(to)?;
+
+    let mut ids: Vec<NodeId> = old_scores.keys().copied().collect();
+    ids.extend(new_scores.keys().filter(|id| !old_scores.contains_key(id)));
+
+    let change = |x: &ScoreDiff| (x.new_score - x.old_score).abs();
+    let mut nodes: Vec<ScoreDiff> = ids
+      .into_iter()
+      .filter_map(|id| {
+        let info = self.nodes.get_by_id(id)?;
+        let (old_score, old_cluster) =
+          old_scores.get(&id).copied().unwrap_or((0.0, 0));
+        let (new_score, new_cluster) =
+          new_scores.get(&id).copied().unwrap_or((0.0, 0));
+        Some(ScoreDiff {
+          target: info.name.clone(),
+          kind: info.kind,
+          old_score,
+          new_score,
+          old_cluster,
+          new_cluster,
+        })
+      })
+      .filter(|x| {
+        x.old_cluster != x.new_cluster
+          || (change(x) > 0.0 && change(x) >= data.min_change)
+      })
+      .collect();
+    nodes.sort_by(|a, b| {
+      change(b).total_cmp(&change(a)).then_with(|| a.target.cmp(&b.target))
+    });
+    Some(ResScoreDiff {
+      nodes,
+      generation: to,
+    })
+  }
+}
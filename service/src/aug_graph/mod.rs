@@ -1,33 +1,121 @@
+use crate::cache_sizing::*;
 use crate::data::*;
+use crate::history::ScoreHistory;
 use crate::node_registry::*;
 use crate::settings::*;
+use crate::utils::bloom_filter::*;
 use crate::utils::log::*;
 use crate::vsids::VSIDSManager;
 
-use meritrank_core::{Graph, MeritRank, NodeId};
+use meritrank_core::{Graph, IntMap, MeritRank, NodeId, PartialScores};
 use moka::sync::Cache;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{rng, SeedableRng};
 
-use std::time::Duration;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 mod absorb;
+mod anomalies;
+mod beacon_comments;
 mod calc;
+mod debug;
+mod dry_run;
+mod edge_log;
 mod edges;
+mod gc;
 mod graph_read;
+mod history;
+mod mutes;
 mod neighbors;
+mod nodes;
+mod polls;
+mod recommendations;
+mod requests;
 mod scores;
+mod shared_scores;
+mod sybil;
+mod top_nodes;
+mod user_params;
+
+pub use graph_read::estimate_memory;
+
+use edge_log::EdgeChange;
+use polls::PollStore;
 
 pub type ClusterGroupBounds = Vec<NodeScore>;
 
+/// Seed of the walks and samples of every context with `deterministic`.
+const DETERMINISTIC_SEED: u64 = 0;
+
+/// Past states of a context kept for reads of past scores, see `past_state`.
+const PAST_STATES_CACHE_SIZE: u64 = 4;
+
+/// Ego, kind and number of clusters.
+pub type ClusterKey = (NodeId, NodeKind, usize);
+
+/// All scores of an ego at one generation.
+type ScoreSnapshot = Arc<Vec<(NodeId, NodeScore)>>;
+
 #[derive(Clone)]
 pub struct AugGraph {
   pub mr:                    MeritRank,
   pub nodes:                 NodeRegistry,
   pub settings:              Settings,
   pub zero_opinion:          Vec<NodeScore>, // FIXME: change to map because of sparseness
-  pub cached_scores:         Cache<(NodeId, NodeId), NodeScore>,
-  pub cached_score_clusters: Cache<(NodeId, NodeKind), ClusterGroupBounds>,
+  /// Cached entries carry the ego generation they were computed at, see `ego_generation`.
+  pub cached_scores:         Cache<(NodeId, NodeId), (u64, NodeScore)>,
+  /// Also carries the time the bounds were computed at.
+  pub cached_score_clusters: Cache<ClusterKey, (u64, Instant, ClusterGroupBounds)>,
   pub vsids:                 VSIDSManager,
   pub stamp:                 u64,
+  /// Write generation of the context, advanced by every change of walks.
+  generation:                u64,
+  /// Generation of the last change of all walks, see `invalidate_all`.
+  all_egos_generation:       u64,
+  /// Generation of the last change of each ego's walks.
+  ego_generations:           IntMap<NodeId, u64>,
+  /// Last scores of egos whose walks were dropped, sorted by node id.
+  /// Only kept with `keep_evicted_scores`.
+  evicted_scores:            IntMap<NodeId, Vec<(NodeId, NodeScore)>>,
+  /// Scores of uncalculated egos from the walks done before a deadline,
+  /// sorted by node id, see `calculate_partial`.
+  partial_scores:            IntMap<NodeId, PartialScores>,
+  /// Stale cluster bounds waiting for the background worker, see
+  /// `background_clustering`.
+  pending_clusters:          Arc<Mutex<HashSet<ClusterKey>>>,
+  /// Counting bloom filters of personal nodes of each user, used by
+  /// `hide_personal`, see `personal_owners`.
+  personal_filters:          IntMap<NodeId, CountingBloomFilter>,
+  /// Shared by published copies, so that samples recorded by reads are kept.
+  pub score_history:         Arc<ScoreHistory>,
+  /// All scores of egos by generation, see `read_score_diff`. Shared by
+  /// published copies, like `cached_scores`.
+  score_snapshots:           Cache<(NodeId, u64), ScoreSnapshot>,
+  /// Recent edge changes, oldest first, see `state_at`.
+  edge_log:                  VecDeque<EdgeChange>,
+  /// Time since which every edge change is in the edge log, None until one
+  /// is logged; the graph can't be reconstructed before it.
+  edge_log_since:            Option<u64>,
+  /// Past states reconstructed for reads, by time, see `past_state`. Shared
+  /// by published copies, like `cached_scores`.
+  past_states:               Cache<u64, Arc<Mutex<AugGraph>>>,
+  /// Edge changes made to walks since they were last calculated from
+  /// scratch, see `needs_rerank`.
+  edges_changed:             usize,
+  /// Kinds of edges that are not votes, see `edge_kind`.
+  edge_kinds:                HashMap<(NodeId, NodeId), EdgeKind>,
+  /// Provenance of edges written with one, see `edge_provenance`.
+  edge_provenance:           HashMap<(NodeId, NodeId), EdgeProvenance>,
+  /// Nodes hidden from the scores of each ego, see `set_mute`.
+  mutes:                     IntMap<NodeId, HashSet<NodeId>>,
+  /// Polls with their options and votes, see `set_poll_edge`.
+  polls:                     PollStore,
+  /// Node count the scores cache is sized for, 0 with a fixed size, see
+  /// `fit_caches`.
+  scores_cache_nodes:        usize,
 }
 
 #[derive(Debug)]
@@ -36,21 +124,44 @@ pub(crate) enum AugGraphError {
   IncorrectNodeKinds(NodeName, NodeName),
 }
 
+fn new_scores_cache(
+  settings: &Settings,
+  capacity: usize,
+) -> Cache<(NodeId, NodeId), (u64, NodeScore)> {
+  Cache::builder()
+    .max_capacity(capacity as u64)
+    .time_to_live(Duration::from_secs(settings.scores_cache_timeout))
+    .build()
+}
+
 impl AugGraph {
   pub fn new(settings: Settings) -> AugGraph {
-    let cached_scores: Cache<(NodeId, NodeId), NodeScore> = Cache::builder()
-      .max_capacity(settings.scores_cache_size as u64)
-      .time_to_live(Duration::from_secs(settings.scores_cache_timeout))
-      .build();
+    let (scores_cache_size, scores_cache_nodes) =
+      if settings.cache_memory_budget > 0 {
+        let sizes = auto_cache_sizes(0, &settings);
+        (sizes.scores, sizes.nodes)
+      } else {
+        (settings.scores_cache_size, 0)
+      };
+    let cached_scores = new_scores_cache(&settings, scores_cache_size);
 
-    let cached_score_clusters: Cache<(NodeId, NodeKind), ClusterGroupBounds> =
-      Cache::builder()
-        .max_capacity(settings.score_clusters_cache_size as u64)
-        .time_to_live(Duration::from_secs(settings.score_clusters_timeout))
-        .build();
+    //  Bounds older than `score_clusters_timeout` are stale, see `apply_score_clustering_at`.
+    //  With background clustering they are still served until recalculated.
+    let mut cached_score_clusters =
+      Cache::builder().max_capacity(settings.score_clusters_cache_size as u64);
+    if !settings.background_clustering && !settings.deterministic {
+      cached_score_clusters = cached_score_clusters
+        .time_to_live(Duration::from_secs(settings.score_clusters_timeout));
+    }
+    let cached_score_clusters = cached_score_clusters.build();
+
+    let mut mr = MeritRank::new(Graph::new(), settings.num_walks);
+    if settings.deterministic {
+      mr.seed_rng(DETERMINISTIC_SEED);
+    }
 
     AugGraph {
-      mr: MeritRank::new(Graph::new(), settings.num_walks),
+      mr,
       nodes: NodeRegistry::new(),
       settings: settings.clone(),
       zero_opinion: Vec::new(),
@@ -58,16 +169,375 @@ impl AugGraph {
       cached_score_clusters,
       vsids: VSIDSManager::new(),
       stamp: 0,
+      generation: 0,
+      all_egos_generation: 0,
+      ego_generations: IntMap::default(),
+      evicted_scores: IntMap::default(),
+      partial_scores: IntMap::default(),
+      pending_clusters: Arc::new(Mutex::new(HashSet::new())),
+      personal_filters: IntMap::default(),
+      score_history: Arc::new(ScoreHistory::new(
+        settings.history_size,
+        settings.history_interval,
+        settings.history_retention,
+      )),
+      score_snapshots: Cache::new(settings.score_snapshots as u64),
+      edge_log: VecDeque::new(),
+      edge_log_since: None,
+      past_states: Cache::new(PAST_STATES_CACHE_SIZE),
+      edges_changed: 0,
+      edge_kinds: HashMap::new(),
+      edge_provenance: HashMap::new(),
+      mutes: IntMap::default(),
+      polls: PollStore::default(),
+      scores_cache_nodes,
+    }
+  }
+
+  /// Generation of the ego's scores; changes whenever the ego's walks may have
+  /// changed. Cached scores and clusters stamped with another generation are stale.
+  pub(crate) fn ego_generation(
+    &self,
+    ego: NodeId,
+  ) -> u64 {
+    let ego_generation = self.ego_generations.get(&ego).copied().unwrap_or(0);
+    ego_generation.max(self.all_egos_generation)
+  }
+
+  pub(crate) fn invalidate_ego(
+    &mut self,
+    ego: NodeId,
+  ) {
+    self.generation += 1;
+    self.ego_generations.insert(ego, self.generation);
+  }
+
+  pub(crate) fn invalidate_all(&mut self) {
+    self.generation += 1;
+    self.all_egos_generation = self.generation;
+  }
+
+  /// Invalidates cached scores of every ego whose walks pass through `src`, i.e.
+  /// every ego affected by a change of `src`'s outgoing edges. Call it before the change.
+  pub(crate) fn invalidate_egos_visiting(
+    &mut self,
+    src: NodeId,
+  ) {
+    for ego in self.mr.egos_visiting(src) {
+      self.invalidate_ego(ego);
+    }
+  }
+
+  /// Drops the ego's walks, keeping its last scores if `keep_evicted_scores` is set.
+  pub(crate) fn drop_walks(
+    &mut self,
+    ego: NodeId,
+  ) {
+    self.invalidate_ego(ego);
+    if self.settings.keep_evicted_scores {
+      if let Ok(mut scores) = self.mr.get_all_scores(ego, None) {
+        scores.sort_unstable_by_key(|(id, _)| *id);
+        self.evicted_scores.insert(ego, scores);
+      }
+    }
+    if let Err(e) = self.mr.drop_walks(ego) {
+      log_error!("Failed to drop walks: {}", e);
+    }
+  }
+
+  /// Returns true if reads of the ego can be served from its last scores
+  /// until it is recalculated.
+  pub fn has_evicted_scores(
+    &self,
+    ego: NodeId,
+  ) -> bool {
+    self.evicted_scores.contains_key(&ego)
+  }
+
+  /// Whether reads of the ego are served from the walks done before a
+  /// deadline, see `calculate_partial`.
+  pub fn has_partial_scores(
+    &self,
+    ego: NodeId,
+  ) -> bool {
+    self.partial_scores.contains_key(&ego)
+  }
+
+  /// Scores of an ego without walks: its evicted scores, or else its
+  /// partial ones.
+  pub(crate) fn kept_scores(
+    &self,
+    ego: NodeId,
+  ) -> Option<&Vec<(NodeId, NodeScore)>> {
+    self
+      .evicted_scores
+      .get(&ego)
+      .or_else(|| self.partial_scores.get(&ego).map(|x| &x.scores))
+  }
+
+  /// Number of walks the scores of the ego are taken from: those of its
+  /// walks, or of its partial scores. Evicted scores were taken from a full
+  /// calculation. 0 if the ego has no scores.
+  pub fn walk_count(
+    &self,
+    ego: NodeId,
+  ) -> usize {
+    let walks = self.mr.walk_count(ego);
+    if walks > 0 {
+      return walks;
     }
+    if self.evicted_scores.contains_key(&ego) {
+      return self.settings.num_walks;
+    }
+    self.partial_scores.get(&ego).map_or(0, |x| x.walks)
   }
 
-  /// Returns true if ego is a User node (valid for score/calculation).
+  pub(crate) fn evicted_score(
+    &self,
+    ego: NodeId,
+    dst: NodeId,
+  ) -> Option<NodeScore> {
+    let scores = self.kept_scores(ego)?;
+    match scores.binary_search_by_key(&dst, |(id, _)| *id) {
+      Ok(index) => Some(scores[index].1),
+      Err(_) => Some(0.0),
+    }
+  }
+
+  /// Deep copy for a new context. Unlike `clone`, the copy gets its own score
+  /// caches, so it does not see or evict scores of the original.
+  pub fn fork(&self) -> AugGraph {
+    let empty = AugGraph::new(self.settings.clone());
+    let mut fork = AugGraph {
+      cached_scores: empty.cached_scores,
+      cached_score_clusters: empty.cached_score_clusters,
+      pending_clusters: empty.pending_clusters,
+      score_history: empty.score_history,
+      score_snapshots: empty.score_snapshots,
+      past_states: empty.past_states,
+      scores_cache_nodes: empty.scores_cache_nodes,
+      ..self.clone()
+    };
+    fork.fit_caches();
+    fork
+  }
+
+  /// With `cache_memory_budget`, replaces the scores cache with one sized
+  /// for the node count once the graph has grown or shrunk enough. Cached
+  /// scores are dropped and recomputed on demand.
+  pub fn fit_caches(&mut self) {
+    if self.scores_cache_nodes == 0
+      || !needs_resize(self.scores_cache_nodes, self.nodes.len())
+    {
+      return;
+    }
+    let sizes = auto_cache_sizes(self.nodes.len(), &self.settings);
+    log_verbose!(
+      "Resizing scores cache to {} for {} nodes",
+      sizes.scores,
+      self.nodes.len()
+    );
+    self.cached_scores = new_scores_cache(&self.settings, sizes.scores);
+    self.scores_cache_nodes = sizes.nodes;
+  }
+
+  /// Nonzero zero opinions by node name.
+  pub fn zero_opinions(&self) -> Vec<(NodeName, Weight)> {
+    self
+      .zero_opinion
+      .iter()
+      .enumerate()
+      .filter(|(_, score)| **score != 0.0)
+      .filter_map(|(id, score)| {
+        self.nodes.get_by_id(id).map(|info| (info.name.clone(), *score))
+      })
+      .collect()
+  }
+
+  pub fn set_zero_opinions(
+    &mut self,
+    zero_opinion: Vec<(NodeName, Weight)>,
+  ) {
+    for (name, score) in zero_opinion {
+      match self.nodes.get_by_name(&name) {
+        Some(info) => {
+          let id = info.id;
+          if id >= self.zero_opinion.len() {
+            self.zero_opinion.resize(id + 1, 0.0);
+          }
+          self.zero_opinion[id] = score;
+        },
+        None => log_warning!("Zero opinion for unknown node: {:?}", name),
+      }
+    }
+  }
+
+  /// Random source of samples taken by reads, seeded with `deterministic`.
+  fn sample_rng(&self) -> StdRng {
+    if self.settings.deterministic {
+      StdRng::seed_from_u64(DETERMINISTIC_SEED)
+    } else {
+      StdRng::from_rng(&mut rng())
+    }
+  }
+
+  /// State of the graph without walks, see `from_snapshot`.
+  pub fn snapshot(
+    &self,
+    context: SubgraphName,
+  ) -> ContextSnapshot {
+    ContextSnapshot {
+      context,
+      edges: self
+        .read_edges()
+        .into_iter()
+        .map(|edge| OpWriteEdge {
+          edge_kind:  self.edge_kind(&edge.src, &edge.dst),
+          provenance: self.edge_provenance(&edge.src, &edge.dst),
+          src:        edge.src,
+          dst:        edge.dst,
+          amount:     edge.weight,
+          magnitude:  0,
+        })
+        .collect(),
+      zero_opinion: self.zero_opinions(),
+      num_clusters: self.settings.num_score_quantiles,
+      user_params:  self.user_params(),
+      nodes:        self.written_nodes(),
+      mutes:        self.mutes(),
+      polls:        self.polls(),
+    }
+  }
+
+  /// Rebuilds a graph from its snapshot. Walks are recalculated lazily.
+  pub fn from_snapshot(
+    settings: Settings,
+    snapshot: ContextSnapshot,
+  ) -> AugGraph {
+    let mut aug_graph = AugGraph::new(Settings {
+      num_score_quantiles: snapshot.num_clusters,
+      ..settings
+    });
+    //  Nodes go first, so that the edges find their kinds.
+    for data in &snapshot.nodes {
+      aug_graph.write_node(data);
+    }
+    aug_graph.bulk_load_edges(snapshot.edges);
+    aug_graph.set_zero_opinions(snapshot.zero_opinion);
+    for data in &snapshot.user_params {
+      aug_graph.set_user_params(data);
+    }
+    for data in &snapshot.mutes {
+      aug_graph.set_mute(data);
+    }
+    for data in &snapshot.polls {
+      aug_graph.restore_poll(data);
+    }
+    aug_graph.fit_caches();
+    aug_graph
+  }
+
+  fn is_user(
+    &self,
+    node: NodeId,
+  ) -> bool {
+    self
+      .nodes
+      .id_to_info
+      .get(node)
+      .is_some_and(|info| info.kind == NodeKind::User)
+  }
+
+  /// Users the node is personal for: those it has an edge to, unless it is a
+  /// user itself. Unlike `NodeInfo::owner`, there can be several.
+  pub(crate) fn personal_owners(
+    &self,
+    node: NodeId,
+  ) -> Vec<NodeId> {
+    if self.is_user(node) {
+      return vec![];
+    }
+    match self.mr.graph.get_node_data(node) {
+      Some(data) => data
+        .get_outgoing_edges()
+        .map(|(dst, _)| dst)
+        .filter(|&dst| self.is_user(dst))
+        .collect(),
+      None => vec![],
+    }
+  }
+
+  /// Updates the filters after the node's outgoing edges changed.
+  /// `owners_before` are its `personal_owners` before the change.
+  pub(crate) fn update_personal_filters(
+    &mut self,
+    node: NodeId,
+    owners_before: Vec<NodeId>,
+  ) {
+    if self.settings.filter_capacity == 0 {
+      return;
+    }
+    let owners_after = self.personal_owners(node);
+
+    for owner in owners_before.iter().filter(|x| !owners_after.contains(x)) {
+      if let Some(filter) = self.personal_filters.get_mut(owner) {
+        filter.remove(node);
+        if filter.needs_rebuild() {
+          self.rebuild_personal_filter(*owner);
+        }
+      }
+    }
+    for owner in owners_after.iter().filter(|x| !owners_before.contains(x)) {
+      let filter = self.personal_filters.entry(*owner).or_insert_with(|| {
+        CountingBloomFilter::new(
+          self.settings.filter_capacity,
+          self.settings.filter_fp_rate,
+        )
+      });
+      filter.add(node);
+      if filter.needs_rebuild() {
+        self.rebuild_personal_filter(*owner);
+      }
+    }
+  }
+
+  fn rebuild_personal_filter(
+    &mut self,
+    owner: NodeId,
+  ) {
+    log_verbose!("Rebuild personal filter of {}", owner);
+    let nodes: Vec<NodeId> = match self.mr.graph.get_node_data(owner) {
+      Some(data) => data
+        .get_inbound_edges()
+        .map(|(src, _)| src)
+        .filter(|&src| !self.is_user(src))
+        .collect(),
+      None => vec![],
+    };
+    if let Some(filter) = self.personal_filters.get_mut(&owner) {
+      filter.rebuild(nodes);
+    }
+  }
+
+  /// May return false positives.
+  pub(crate) fn is_personal_node(
+    &self,
+    ego: NodeId,
+    node: NodeId,
+  ) -> bool {
+    self
+      .personal_filters
+      .get(&ego)
+      .is_some_and(|filter| filter.contains(node))
+  }
+
+  /// Returns true if ego's kind is enabled in `ego_kinds` (valid for score/calculation).
   /// Logs error and returns false if not; callers should return empty/fail.
-  pub(crate) fn ensure_ego_is_user(&self, ego_name: &str, ego_info: &NodeInfo) -> bool {
-    if ego_info.kind == NodeKind::User {
+  pub(crate) fn ensure_ego_kind_enabled(&self, ego_name: &str, ego_info: &NodeInfo) -> bool {
+    if self.settings.ego_kinds.contains(&ego_info.kind) {
       return true;
     }
-    log_error!("Non-user node used as ego (rejected): {}", ego_name);
+    log_error!("Node of disabled kind used as ego (rejected): {}", ego_name);
     false
   }
 
@@ -140,9 +610,196 @@ mod tests {
     assert_eq!(registry.get_by_id(2), None);
     assert_eq!(registry.get_by_name("Bob"), None);
 
-    // Test nodes_by_kind (index by kind)
-    assert_eq!(registry.nodes_by_kind(NodeKind::User), &[0]);
-    assert_eq!(registry.nodes_by_kind(NodeKind::Comment), &[1]);
-    assert!(registry.nodes_by_kind(NodeKind::Beacon).is_empty());
+    // Test ids_by_kind (index by kind)
+    assert_eq!(registry.ids_by_kind(NodeKind::User), &[0]);
+    assert_eq!(registry.ids_by_kind(NodeKind::Comment), &[1]);
+    assert!(registry.ids_by_kind(NodeKind::Beacon).is_empty());
+
+    // Test rename: the old name becomes an alias
+    assert_eq!(registry.rename("Alice", "Alicia".to_string()), None);
+    registry.rename("Comment1", "Comment2".to_string()).unwrap();
+    assert_eq!(registry.get_by_name("Comment1").unwrap().name, "Comment2");
+    assert_eq!(registry.get_by_name("Comment2").unwrap().id, 1);
+    assert_eq!(registry.rename("Comment2", "Comment1".to_string()), Some(1));
+    assert_eq!(registry.rename("Bob", "Comment3".to_string()), None);
+
+    // Test counts_by_kind
+    let counts = registry.counts_by_kind();
+    assert_eq!(counts.get(&NodeKind::User), Some(&1));
+    assert_eq!(counts.get(&NodeKind::Comment), Some(&1));
+    assert_eq!(counts.get(&NodeKind::Beacon), None);
+  }
+
+  #[test]
+  fn custom_node_kinds() {
+    assert_eq!(register_node_kind('M', "Market"), Some(NodeKind::Custom('M')));
+    assert_eq!(register_node_kind('M', "Market"), Some(NodeKind::Custom('M')));
+    assert_eq!(register_node_kind('M', "Meme"), None);
+    assert_eq!(register_node_kind('U', "Unit"), None);
+
+    assert_eq!(node_kind_from_prefix("M1"), Some(NodeKind::Custom('M')));
+    assert_eq!(node_kind_from_prefix("U1"), Some(NodeKind::User));
+    assert_eq!(node_kind_from_prefix("Z1"), None);
+    assert!(all_node_kinds().contains(&NodeKind::Custom('M')));
+  }
+
+  #[test]
+  fn state_at_undoes_later_edge_changes() {
+    let mut graph = AugGraph::new(Settings {
+      num_walks: 50,
+      zero_opinion_factor: 0.0,
+      edge_log_size: 100,
+      ..Settings::default()
+    });
+    graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+    for change in graph.edge_log.iter_mut() {
+      change.time = 100;
+    }
+    graph.set_edge("U1".into(), "U3".into(), 1.0, 0);
+    for change in graph.edge_log.iter_mut().filter(|c| c.time != 100) {
+      change.time = 200;
+    }
+    assert!(graph.state_at(150).is_none());
+    graph.edge_log_since = Some(50);
+
+    let id = |name: &str| graph.nodes.get_by_name(name).unwrap().id;
+    let (u1, u2, u3) = (id("U1"), id("U2"), id("U3"));
+
+    let past = graph.state_at(150).unwrap();
+    assert!(past.mr.graph.edge_weight(u1, u2).unwrap().is_some());
+    assert!(past.mr.graph.edge_weight(u1, u3).unwrap().is_none());
+    assert!(graph.mr.graph.edge_weight(u1, u3).unwrap().is_some());
+
+    let past = graph.state_at(50).unwrap();
+    assert!(past.mr.graph.edge_weight(u1, u2).unwrap().is_none());
+    assert!(graph.state_at(49).is_none());
+
+    let scores = graph.read_scores_at(OpReadScoresAt {
+      ego:           "U1".into(),
+      timestamp:     150,
+      score_options: FilterOptions {
+        node_kind: Some(NodeKind::User),
+        ..FilterOptions::default()
+      },
+    });
+    assert!(scores.iter().any(|x| x.target == "U2"));
+    assert!(scores.iter().all(|x| x.target != "U3"));
+    assert!(Arc::ptr_eq(
+      &graph.past_state(150).unwrap(),
+      &graph.past_state(150).unwrap()
+    ));
+
+    graph.settings.edge_log_size = 1;
+    graph.set_edge("U1".into(), "U4".into(), 1.0, 0);
+    assert!(graph.state_at(150).is_none());
+  }
+
+  #[test]
+  fn score_diff_reports_changed_targets() {
+    let mut graph = AugGraph::new(Settings {
+      num_walks: 50,
+      zero_opinion_factor: 0.0,
+      score_snapshots: 100,
+      ..Settings::default()
+    });
+    graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+    graph.calculate("U1".into());
+
+    let diff = |graph: &AugGraph, from, min_change| {
+      graph.read_score_diff(OpReadScoreDiff {
+        ego: "U1".into(),
+        from,
+        to: 0,
+        min_change,
+      })
+    };
+    let start = diff(&graph, 0, 0.0).unwrap();
+    assert!(start.nodes.is_empty());
+    assert!(diff(&graph, start.generation, 0.0).unwrap().nodes.is_empty());
+
+    graph.set_edge("U1".into(), "U3".into(), 1.0, 0);
+    let res = diff(&graph, start.generation, 0.0).unwrap();
+    assert!(res.generation > start.generation);
+    let u3 = res.nodes.iter().find(|x| x.target == "U3").unwrap();
+    assert_eq!(u3.old_score, 0.0);
+    assert!(u3.new_score > 0.0);
+
+    let res = diff(&graph, start.generation, 2.0).unwrap();
+    assert!(res.nodes.iter().all(|x| x.old_cluster != x.new_cluster));
+    assert!(diff(&graph, res.generation + 1, 0.0).is_none());
+  }
+
+  #[test]
+  fn stale_scores_are_flagged() {
+    let mut graph = AugGraph::new(Settings {
+      num_walks: 50,
+      serve_stale_scores: true,
+      ..Settings::default()
+    });
+    graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+    graph.calculate("U1".into());
+    let id = |name: &str| graph.nodes.get_by_name(name).unwrap().id;
+    let (u1, u2) = (id("U1"), id("U2"));
+
+    let (score, cluster, stale) = graph.fetch_score_checked(u1, u2, 10);
+    assert!(!stale);
+    graph.invalidate_ego(u1);
+    assert_eq!(graph.fetch_score_checked(u1, u2, 10), (score, cluster, true));
+
+    graph.settings.serve_stale_scores = false;
+    let (_, _, stale) = graph.fetch_score_checked(u1, u2, 10);
+    assert!(!stale);
+    //  Recomputed scores are cached with the current generation.
+    graph.settings.serve_stale_scores = true;
+    let (_, _, stale) = graph.fetch_score_checked(u1, u2, 10);
+    assert!(!stale);
+  }
+
+  #[test]
+  fn degraded_mode_serves_last_scores() {
+    let mut graph = AugGraph::new(Settings {
+      num_walks: 50,
+      ..Settings::default()
+    });
+    graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+    graph.calculate("U1".into());
+    let read = |graph: &AugGraph| {
+      graph.read_scores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions::default(),
+      })
+    };
+    let scores = read(&graph).unwrap();
+    assert!(!scores.is_empty() && scores.iter().all(|x| !x.stale));
+
+    let u1 = graph.nodes.get_by_name("U1").unwrap().id;
+    graph.drop_walks(u1);
+    assert!(matches!(
+      read(&graph),
+      Err(ServiceError::ScoresUnavailable(CoreErrorCode::NotCalculated))
+    ));
+
+    graph.settings.degraded_mode = true;
+    let last = read(&graph).unwrap();
+    assert_eq!(last.len(), scores.len());
+    assert!(last.iter().all(|x| x.stale));
+  }
+
+  #[test]
+  fn read_request_answers_reads_only() {
+    let mut graph = AugGraph::new(Settings {
+      num_walks: 50,
+      ..Settings::default()
+    });
+    graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+
+    match graph.read_request(ReqData::ReadNodeList) {
+      Response::NodeList(ResNodeList { nodes }) => assert_eq!(nodes.len(), 2),
+      _ => panic!("Unexpected response"),
+    }
+    assert!(matches!(
+      graph.read_request(ReqData::WriteReset),
+      Response::Fail
+    ));
   }
 }
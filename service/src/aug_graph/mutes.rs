@@ -0,0 +1,91 @@
+use crate::data::*;
+use crate::node_registry::*;
+use crate::utils::log::*;
+
+use super::AugGraph;
+
+use meritrank_core::NodeId;
+
+impl AugGraph {
+  /// Hides the node from the scores of the ego, or shows it again. Walks are
+  /// not touched, so the scores themselves don't change.
+  pub fn set_mute(
+    &mut self,
+    data: &OpWriteMute,
+  ) {
+    log_command!("{:?}", data);
+
+    let mut ids = vec![];
+    for name in [&data.ego, &data.node] {
+      match node_kind_from_prefix(name) {
+        Some(kind) => {
+          ids.push(self.nodes.register(&mut self.mr, name.clone(), kind))
+        },
+        None => {
+          log_error!("Failed to get node kind for {:?}", name);
+          return;
+        },
+      }
+    }
+    let (ego_id, node_id) = (ids[0], ids[1]);
+
+    if data.muted {
+      self.mutes.entry(ego_id).or_default().insert(node_id);
+    } else if let Some(muted) = self.mutes.get_mut(&ego_id) {
+      muted.remove(&node_id);
+      if muted.is_empty() {
+        self.mutes.remove(&ego_id);
+      }
+    }
+  }
+
+  pub fn is_muted(
+    &self,
+    ego: NodeId,
+    node: NodeId,
+  ) -> bool {
+    self.mutes.get(&ego).is_some_and(|muted| muted.contains(&node))
+  }
+
+  /// Nodes muted by the ego, sorted by name.
+  pub fn muted_nodes(
+    &self,
+    ego: &NodeName,
+  ) -> Vec<NodeName> {
+    let ego_id = match self.nodes.get_by_name(ego) {
+      Some(info) => info.id,
+      None => return vec![],
+    };
+    let mut nodes: Vec<NodeName> = self
+      .mutes
+      .get(&ego_id)
+      .into_iter()
+      .flatten()
+      .filter_map(|id| self.nodes.get_by_id(*id))
+      .map(|info| info.name.clone())
+      .collect();
+    nodes.sort();
+    nodes
+  }
+
+  /// All mutes of the context, see `set_mute`.
+  pub fn mutes(&self) -> Vec<OpWriteMute> {
+    let mut mutes = vec![];
+    for (ego_id, muted) in &self.mutes {
+      let ego = match self.nodes.get_by_id(*ego_id) {
+        Some(info) => info.name.clone(),
+        None => continue,
+      };
+      for id in muted {
+        if let Some(info) = self.nodes.get_by_id(*id) {
+          mutes.push(OpWriteMute {
+            ego:   ego.clone(),
+            node:  info.name.clone(),
+            muted: true,
+          });
+        }
+      }
+    }
+    mutes
+  }
+}
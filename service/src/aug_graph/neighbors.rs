@@ -7,11 +7,14 @@ use meritrank_core::NodeId;
 use super::AugGraph;
 
 impl AugGraph {
+  /// Neighbors of the focus with the edge weights, only over edges of the
+  /// kind if one is given.
   pub fn fetch_neighbors(
     &self,
     ego_id: NodeId,
     focus_id: NodeId,
     dir: i64,
+    edge_kind: Option<EdgeKind>,
   ) -> Vec<(NodeInfo, Weight, NodeCluster)> {
     log_trace!("{} {} {:?}", ego_id, focus_id, dir);
 
@@ -23,10 +26,17 @@ impl AugGraph {
       },
     };
 
-    let outgoing: Vec<(NodeId, Weight)> =
-      node_data.get_outgoing_edges().collect();
-    let inbound: Vec<(NodeId, Weight)> =
-      node_data.get_inbound_edges().collect();
+    let is_kind = |src_id: NodeId, dst_id: NodeId| {
+      edge_kind.is_none_or(|kind| self.edge_kind_by_id(src_id, dst_id) == kind)
+    };
+    let outgoing: Vec<(NodeId, Weight)> = node_data
+      .get_outgoing_edges()
+      .filter(|(dst_id, _)| is_kind(focus_id, *dst_id))
+      .collect();
+    let inbound: Vec<(NodeId, Weight)> = node_data
+      .get_inbound_edges()
+      .filter(|(src_id, _)| is_kind(*src_id, focus_id))
+      .collect();
 
     let items: Vec<(NodeId, Weight)> = match dir {
       NEIGHBORS_OUTBOUND => outgoing,
@@ -82,7 +92,7 @@ impl AugGraph {
       },
     };
 
-    if !self.ensure_ego_is_user(ego, ego_info) {
+    if !self.ensure_ego_kind_enabled(ego, ego_info) {
       return vec![];
     }
 
@@ -104,11 +114,11 @@ impl AugGraph {
       && node_kind_from_prefix(focus) == Some(NodeKind::Poll)
       && dir == NEIGHBORS_INBOUND
     {
-      log_error!("Poll variant not implemented.");
-      return vec![];
+      return self.read_poll_results(ego_info, focus, focus_id);
     }
 
-    let mut scores = self.fetch_neighbors(ego_id, focus_id, dir);
+    let mut scores =
+      self.fetch_neighbors(ego_id, focus_id, dir, data.edge_kind);
 
     if kind_opt == Some(NodeKind::Opinion) && dir == NEIGHBORS_INBOUND {
       scores.retain(|(node_info, _, _)| {
@@ -116,21 +126,42 @@ impl AugGraph {
       });
     }
 
-    self.apply_filters_and_pagination(
+    let mut results = self.apply_filters_and_pagination(
       scores,
       ego_info,
       &FilterOptions {
-        node_kind:     None,
-        hide_personal: data.hide_personal,
-        score_lt:      data.lt,
-        score_lte:     data.lte,
-        score_gt:      data.gt,
-        score_gte:     data.gte,
-        index:         data.index,
-        count:         data.count,
+        node_kind:              None,
+        hide_personal:          data.hide_personal,
+        score_lt:               data.lt,
+        score_lte:              data.lte,
+        score_gt:               data.gt,
+        score_gte:              data.gte,
+        index:                  data.index,
+        count:                  data.count,
+        num_clusters:           0,
+        cluster_min:            0,
+        cluster_max:            u32::MAX,
+        filter_on:              ScoreComponent::Blended,
+        include_negative_only:  false,
+        ascending:              false,
+        exclude_flags:          0,
+        require_flags:          0,
+        max_latency_ms:         0,
+        include_reverse_scores: true,
       },
       true,
-    )
+    );
+
+    for x in &mut results {
+      let outbound = || self.edge_provenance(focus, &x.target);
+      let inbound = || self.edge_provenance(&x.target, focus);
+      x.provenance = match dir {
+        NEIGHBORS_OUTBOUND => outbound(),
+        NEIGHBORS_INBOUND => inbound(),
+        _ => outbound().or_else(inbound),
+      };
+    }
+    results
   }
 
   pub fn read_mutual_scores(
@@ -147,23 +178,35 @@ impl AugGraph {
       },
     };
 
-    if !self.ensure_ego_is_user(&data.ego, ego_info) {
+    if !self.ensure_ego_kind_enabled(&data.ego, ego_info) {
       return vec![];
     }
 
     let ego_id = ego_info.id;
+    let walks = self.walk_count(ego_id) as u64;
 
-    let ranks = self.fetch_all_scores(ego_info);
+    let ranks =
+      self.fetch_all_scores(ego_info, self.settings.num_score_quantiles);
     let mut v = Vec::<ScoreResult>::new();
     v.reserve_exact(ranks.len());
 
     for (node, score_value_of_dst, score_cluster_of_dst) in ranks {
       if score_value_of_dst > 0.0 && node.kind == NodeKind::User {
-        let (score_value_of_ego, score_cluster_of_ego) =
+        let (score_value_of_ego, score_cluster_of_ego, stale) =
           match self.get_object_owner(node.id) {
-            Some(dst_owner_id) => self.fetch_score_cached(dst_owner_id, ego_id),
-            None => (0.0, 0),
+            Some(dst_owner_id) => self.fetch_score_checked(
+              dst_owner_id,
+              ego_id,
+              self.settings.num_score_quantiles,
+            ),
+            None => (0.0, 0, false),
           };
+        let percentile = self.score_percentile(
+          ego_id,
+          score_value_of_dst,
+          node.kind,
+          self.settings.num_score_quantiles,
+        );
         v.push(ScoreResult {
           ego:             data.ego.clone(),
           target:          node.name,
@@ -171,6 +214,15 @@ impl AugGraph {
           reverse_score:   score_value_of_ego,
           cluster:         score_cluster_of_dst,
           reverse_cluster: score_cluster_of_ego,
+          kind:            node.kind,
+          rank:            v.len() as u32 + 1,
+          percentile,
+          stale,
+          approximate:     false,
+          fallback:        false,
+          shared:          false,
+          walks,
+          provenance:      None,
         });
       }
     }
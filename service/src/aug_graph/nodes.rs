@@ -0,0 +1,75 @@
+use crate::data::*;
+use crate::node_registry::*;
+use crate::utils::log::*;
+
+use meritrank_core::NodeId;
+
+use super::AugGraph;
+
+impl AugGraph {
+  /// Registers the node with the kind and sets its flags. An existing node
+  /// keeps its kind, since ownership and clustering already depend on it.
+  pub fn write_node(
+    &mut self,
+    data: &OpWriteNode,
+  ) {
+    log_command!("{:?}", data);
+
+    let existing = self
+      .nodes
+      .get_by_name(&data.name)
+      .map(|info| (info.id, info.kind));
+    let id = match existing {
+      Some((id, kind)) => {
+        if kind != data.kind {
+          log_warning!("Node {:?} is already a {:?}", data.name, kind);
+        }
+        id
+      },
+      None => self.nodes.register(&mut self.mr, data.name.clone(), data.kind),
+    };
+
+    match self.mr.graph.get_node_data_mut(id) {
+      Some(node) => node.flags = data.flags,
+      None => log_error!("Node data not found: {:?}", data.name),
+    }
+  }
+
+  /// Flags set by `write_node`, 0 for unknown nodes.
+  pub fn node_flags(
+    &self,
+    id: NodeId,
+  ) -> u32 {
+    self.mr.graph.get_node_data(id).map_or(0, |node| node.flags)
+  }
+
+  /// Kind of the registered node, or the kind of its name prefix.
+  pub fn node_kind(
+    &self,
+    name: &str,
+  ) -> Option<NodeKind> {
+    match self.nodes.get_by_name(name) {
+      Some(info) => Some(info.kind),
+      None => node_kind_from_prefix(name),
+    }
+  }
+
+  /// Nodes whose kind or flags were set by `write_node`, for snapshots.
+  pub fn written_nodes(&self) -> Vec<OpWriteNode> {
+    self
+      .nodes
+      .id_to_info
+      .iter()
+      .filter(|info| !info.tombstone)
+      .filter_map(|info| {
+        let flags = self.mr.graph.get_node_data(info.id)?.flags;
+        let prefix_kind = node_kind_from_prefix(&info.name) == Some(info.kind);
+        (flags != 0 || !prefix_kind).then(|| OpWriteNode {
+          name: info.name.clone(),
+          kind: info.kind,
+          flags,
+        })
+      })
+      .collect()
+  }
+}
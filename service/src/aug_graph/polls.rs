@@ -0,0 +1,659 @@
+use crate::data::*;
+use crate::history::unix_time_secs;
+use crate::node_registry::*;
+use crate::utils::bloom_filter::*;
+use crate::utils::log::*;
+use crate::utils::quantiles::calculate_quantiles_bounds;
+
+use super::AugGraph;
+
+use indexmap::IndexSet;
+use meritrank_core::{IntMap, NodeId};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+/// Scores of voters are capped at the top bound of that many quantiles, so
+/// that a few users with the highest scores can't decide a poll.
+const POLL_SCORE_QUANTILES: usize = 10;
+
+#[derive(Clone, Debug)]
+struct Vote {
+  option: NodeId,
+  weight: Weight,
+}
+
+/// Votes for an option of an anonymous poll. Voters are only kept in a bloom
+/// filter, so they can't be listed, but a second vote is detected.
+#[derive(Clone, Debug)]
+struct Tally {
+  filter:     Vec<u64>,
+  num_hashes: usize,
+  count:      u64,
+  /// Sum of the vote weights times the zero opinions of the voters.
+  score:      Weight,
+}
+
+impl Tally {
+  fn new(
+    capacity: usize,
+    fp_rate: f64,
+  ) -> Self {
+    let (size, num_hashes) = bloom_filter_size(capacity, fp_rate);
+    Tally {
+      filter: vec![0; size.div_ceil(64)],
+      num_hashes,
+      count: 0,
+      score: 0.0,
+    }
+  }
+
+  fn bits(
+    &self,
+    voter: usize,
+  ) -> Vec<u64> {
+    bloom_filter_bits(self.filter.len(), self.num_hashes, voter)
+  }
+
+  /// May return false positives.
+  fn contains(
+    &self,
+    voter: usize,
+  ) -> bool {
+    bloom_filter_contains(&self.filter, &self.bits(voter))
+  }
+
+  fn add(
+    &mut self,
+    voter: usize,
+    score: Weight,
+  ) {
+    let bits = self.bits(voter);
+    bloom_filter_add(&mut self.filter, &bits);
+    self.count += 1;
+    self.score += score;
+  }
+}
+
+/// Filters hash the names of voters rather than their ids, which change when
+/// the graph is rebuilt.
+fn voter_key(name: &NodeName) -> usize {
+  let mut hasher = DefaultHasher::new();
+  hasher.write(name.as_bytes());
+  hasher.finish() as usize
+}
+
+#[derive(Clone, Debug, Default)]
+struct Poll {
+  options:    IndexSet<NodeId>,
+  /// Vote of each user, for one of the options. Empty for anonymous polls.
+  votes:      IntMap<NodeId, Vote>,
+  anonymous:  bool,
+  /// Votes of each option of an anonymous poll.
+  tallies:    IntMap<NodeId, Tally>,
+  /// Unix time votes are rejected from, 0 if the poll never expires.
+  expires_at: u64,
+  /// Results frozen when the poll was archived: share of the weighted votes
+  /// and number of votes of each option, see `archive_polls`.
+  results:    Option<Vec<(NodeId, Weight, u64)>>,
+}
+
+impl Poll {
+  fn is_closed(
+    &self,
+    now: u64,
+  ) -> bool {
+    self.results.is_some() || (self.expires_at != 0 && self.expires_at <= now)
+  }
+
+  /// Share of the score and number of votes of each option of an anonymous
+  /// poll.
+  fn tally_results(&self) -> Vec<(NodeId, Weight, u64)> {
+    let total: Weight = self.tallies.values().map(|x| x.score).sum();
+    self
+      .options
+      .iter()
+      .map(|option| match self.tallies.get(option) {
+        Some(tally) if total > 0.0 => {
+          (*option, tally.score / total, tally.count)
+        },
+        Some(tally) => (*option, 0.0, tally.count),
+        None => (*option, 0.0, 0),
+      })
+      .collect()
+  }
+}
+
+/// Polls of a context. Options are added by edges from the option to the
+/// poll, and users vote by edges to an option. Neither is part of the graph,
+/// so walks don't see them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PollStore {
+  polls:   IntMap<NodeId, Poll>,
+  /// Poll of each option.
+  options: IntMap<NodeId, NodeId>,
+}
+
+/// Returns true for votes, from a user to an option, and for options, from
+/// an option to a poll.
+pub(crate) fn is_poll_edge(
+  src: &NodeName,
+  dst: &NodeName,
+) -> bool {
+  matches!(
+    (node_kind_from_prefix(src), node_kind_from_prefix(dst)),
+    (Some(NodeKind::User), Some(NodeKind::PollVariant))
+      | (Some(NodeKind::PollVariant), Some(NodeKind::Poll))
+  )
+}
+
+/// Sum of the votes for each option weighted by the scores of their voters,
+/// normalized to shares with `normalize`.
+fn poll_results(
+  votes: &IntMap<NodeId, Vote>,
+  scores: &[(NodeId, Weight)],
+  num_quantiles: usize,
+  normalize: bool,
+) -> HashMap<NodeId, Weight> {
+  let bounds = calculate_quantiles_bounds(
+    scores.iter().map(|(_, score)| *score).collect(),
+    num_quantiles,
+  );
+  let cap = bounds.last().copied().unwrap_or(Weight::MAX);
+  let scores: HashMap<NodeId, Weight> =
+    scores.iter().map(|(id, score)| (*id, score.min(cap))).collect();
+
+  let mut results: HashMap<NodeId, Weight> = HashMap::new();
+  for (user, vote) in votes {
+    let score = scores.get(user).copied().unwrap_or(0.0);
+    *results.entry(vote.option).or_insert(0.0) += vote.weight * score;
+  }
+  let total: Weight = results.values().sum();
+  if normalize && total > 0.0 {
+    for weight in results.values_mut() {
+      *weight /= total;
+    }
+  }
+  results
+}
+
+impl AugGraph {
+  fn poll_of_option(
+    &self,
+    option: &NodeName,
+  ) -> Option<NodeId> {
+    let id = self.nodes.get_by_name(option)?.id;
+    self.polls.options.get(&id).copied()
+  }
+
+  /// Poll the vote or option is written to, if it is closed: expired or
+  /// archived. Edges of closed polls are not applied.
+  pub fn closed_poll(
+    &self,
+    data: &OpWriteEdge,
+  ) -> Option<NodeName> {
+    let poll_id = match node_kind_from_prefix(&data.dst) {
+      Some(NodeKind::Poll) => self.nodes.get_by_name(&data.dst)?.id,
+      _ => self.poll_of_option(&data.dst)?,
+    };
+    let poll = self.polls.polls.get(&poll_id)?;
+    if !poll.is_closed(unix_time_secs()) {
+      return None;
+    }
+    self.nodes.get_by_id(poll_id).map(|info| info.name.clone())
+  }
+
+  /// Anonymous poll the user already voted in, if the edge is a vote in it.
+  /// Anonymous votes can't be changed or removed. May return false
+  /// positives, see `poll_filter_fp_rate`.
+  pub fn already_voted(
+    &self,
+    data: &OpWriteEdge,
+  ) -> Option<NodeName> {
+    if node_kind_from_prefix(&data.dst) != Some(NodeKind::PollVariant) {
+      return None;
+    }
+    let poll_id = self.poll_of_option(&data.dst)?;
+    let poll = self.polls.polls.get(&poll_id)?;
+    let voter = voter_key(&data.src);
+    if !poll.anonymous || !poll.tallies.values().any(|x| x.contains(voter)) {
+      return None;
+    }
+    self.nodes.get_by_id(poll_id).map(|info| info.name.clone())
+  }
+
+  /// Applies a vote or an option of a poll, see `is_poll_edge`. A zero
+  /// weight removes it. A user has one vote per poll, so a vote for another
+  /// option replaces it.
+  pub fn set_poll_edge(
+    &mut self,
+    data: &OpWriteEdge,
+  ) {
+    log_command!("{:?}", data);
+
+    if let Some(poll) = self.closed_poll(data) {
+      log_warning!("Poll {:?} is closed, {:?} not applied", poll, data);
+      return;
+    }
+    if let Some(poll) = self.already_voted(data) {
+      log_warning!("{:?} already voted in {:?}", data.src, poll);
+      return;
+    }
+    match node_kind_from_prefix(&data.dst) {
+      Some(NodeKind::Poll) => self.set_poll_option(data),
+      Some(NodeKind::PollVariant) => self.set_vote(data),
+      _ => log_error!("Not a poll edge: {:?}", data),
+    }
+  }
+
+  fn set_poll_option(
+    &mut self,
+    data: &OpWriteEdge,
+  ) {
+    let option = self.nodes.register(
+      &mut self.mr,
+      data.src.clone(),
+      NodeKind::PollVariant,
+    );
+    let poll_id =
+      self.nodes.register(&mut self.mr, data.dst.clone(), NodeKind::Poll);
+
+    match self.polls.options.get(&option) {
+      Some(&other) if other != poll_id => {
+        log_warning!("Option {:?} belongs to another poll", data.src);
+        return;
+      },
+      None if data.amount == 0.0 => return,
+      _ => {},
+    }
+    let poll = self.polls.polls.entry(poll_id).or_default();
+    if data.amount == 0.0 {
+      poll.options.shift_remove(&option);
+      poll.votes.retain(|_, vote| vote.option != option);
+      self.polls.options.remove(&option);
+    } else {
+      poll.options.insert(option);
+      self.polls.options.insert(option, poll_id);
+    }
+  }
+
+  fn set_vote(
+    &mut self,
+    data: &OpWriteEdge,
+  ) {
+    let poll_id = match self.poll_of_option(&data.dst) {
+      Some(x) => x,
+      None => {
+        log_warning!("Option {:?} is not in a poll", data.dst);
+        return;
+      },
+    };
+    let option = match self.nodes.get_by_name(&data.dst) {
+      Some(info) => info.id,
+      None => return,
+    };
+    if self.polls.polls.get(&poll_id).is_some_and(|x| x.anonymous) {
+      self.add_anonymous_vote(poll_id, option, data);
+      return;
+    }
+    let user =
+      self.nodes.register(&mut self.mr, data.src.clone(), NodeKind::User);
+
+    let poll = self.polls.polls.entry(poll_id).or_default();
+    if data.amount != 0.0 {
+      poll.votes.insert(
+        user,
+        Vote {
+          option,
+          weight: data.amount,
+        },
+      );
+    } else if poll.votes.get(&user).is_some_and(|x| x.option == option) {
+      poll.votes.remove(&user);
+    }
+  }
+
+  /// Voters are not registered, so that an anonymous vote leaves nothing
+  /// but the tally.
+  fn add_anonymous_vote(
+    &mut self,
+    poll_id: NodeId,
+    option: NodeId,
+    data: &OpWriteEdge,
+  ) {
+    if data.amount == 0.0 {
+      return;
+    }
+    let score = match self.nodes.get_by_name(&data.src) {
+      Some(info) => self.zero_opinion.get(info.id).copied().unwrap_or(0.0),
+      None => 0.0,
+    };
+    let (capacity, fp_rate) = (
+      self.settings.poll_filter_capacity,
+      self.settings.poll_filter_fp_rate,
+    );
+    if let Some(poll) = self.polls.polls.get_mut(&poll_id) {
+      poll
+        .tallies
+        .entry(option)
+        .or_insert_with(|| Tally::new(capacity, fp_rate))
+        .add(voter_key(&data.src), data.amount * score);
+    }
+  }
+
+  /// Sets the expiration of the poll and makes it anonymous, see
+  /// `WritePoll`. Votes of a poll made anonymous are moved to its tallies.
+  /// An anonymous poll stays anonymous.
+  pub fn write_poll(
+    &mut self,
+    data: &OpWritePoll,
+  ) {
+    log_command!("{:?}", data);
+
+    let poll_id =
+      self.nodes.register(&mut self.mr, data.poll.clone(), NodeKind::Poll);
+    let poll = self.polls.polls.entry(poll_id).or_default();
+    if poll.results.is_some() {
+      log_warning!("Poll {:?} is archived", data.poll);
+      return;
+    }
+    poll.expires_at = data.expires_at;
+    if poll.anonymous && !data.anonymous {
+      log_warning!("Poll {:?} stays anonymous", data.poll);
+    }
+    if poll.anonymous || !data.anonymous {
+      return;
+    }
+    poll.anonymous = true;
+    let votes = std::mem::take(&mut poll.votes);
+    for (user, vote) in votes {
+      if let Some(name) = self.nodes.get_by_id(user).map(|x| x.name.clone()) {
+        let data = OpWriteEdge {
+          src:        name,
+          dst:        NodeName::new(),
+          amount:     vote.weight,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        };
+        self.add_anonymous_vote(poll_id, vote.option, &data);
+      }
+    }
+  }
+
+  /// Returns true if a poll expired by `now` is not archived yet.
+  pub fn has_expired_polls(
+    &self,
+    now: u64,
+  ) -> bool {
+    self
+      .polls
+      .polls
+      .values()
+      .any(|poll| poll.results.is_none() && poll.is_closed(now))
+  }
+
+  /// Scores of users by the zero opinion, the scores of the whole context.
+  fn global_user_scores(&self) -> Vec<(NodeId, Weight)> {
+    self
+      .zero_opinion
+      .iter()
+      .enumerate()
+      .filter(|(id, score)| **score > 0.0 && self.is_user(*id))
+      .map(|(id, score)| (id, *score))
+      .collect()
+  }
+
+  /// Archives the polls expired by `now`: their results by the scores of the
+  /// whole context are frozen, and their votes dropped. Returns the number of
+  /// polls archived.
+  pub fn archive_polls(
+    &mut self,
+    now: u64,
+  ) -> usize {
+    let scores = self.global_user_scores();
+    let mut num_archived = 0;
+    for poll in self.polls.polls.values_mut() {
+      if poll.results.is_some() || !poll.is_closed(now) {
+        continue;
+      }
+      let shares =
+        poll_results(&poll.votes, &scores, POLL_SCORE_QUANTILES, true);
+      let results = if poll.anonymous {
+        poll.tally_results()
+      } else {
+        poll
+          .options
+          .iter()
+          .map(|option| {
+            let votes =
+              poll.votes.values().filter(|x| x.option == *option).count();
+            let share = shares.get(option).copied().unwrap_or(0.0);
+            (*option, share, votes as u64)
+          })
+          .collect()
+      };
+      poll.results = Some(results);
+      poll.votes = IntMap::default();
+      poll.tallies = IntMap::default();
+      num_archived += 1;
+    }
+    if num_archived > 0 {
+      log_verbose!("Archived {} polls", num_archived);
+    }
+    num_archived
+  }
+
+  /// Results of the poll for the ego, one row per option sorted by name,
+  /// read as the inbound poll variants of the poll with `ReadNeighbors`.
+  /// `score` is the share of the option in the votes weighted by the ego's
+  /// scores of the voters, and `reverse_score` in those weighted by the zero
+  /// opinion. `cluster` is the percentage of users with a positive score by
+  /// the ego, the ego included, who voted, and `reverse_cluster` how many of
+  /// them voted for the option. Archived polls have the frozen results for
+  /// every ego: the share by the zero opinion as both scores, and the number
+  /// of all votes of the option as `reverse_cluster`. Anonymous polls have
+  /// the same rows from their tallies before they are archived too.
+  pub(crate) fn read_poll_results(
+    &self,
+    ego_info: &NodeInfo,
+    poll_name: &NodeName,
+    poll_id: NodeId,
+  ) -> Vec<ScoreResult> {
+    let poll = match self.polls.polls.get(&poll_id) {
+      Some(x) => x,
+      None => return vec![],
+    };
+    let ego_id = ego_info.id;
+
+    let frozen = |results: &[(NodeId, Weight, u64)]| {
+      results
+        .iter()
+        .map(|(option, share, votes)| {
+          (*option, *share, *share, 0, *votes as NodeCluster)
+        })
+        .collect()
+    };
+    let rows: Vec<(NodeId, Weight, Weight, NodeCluster, NodeCluster)> =
+      match &poll.results {
+        Some(results) => frozen(results),
+        None if poll.anonymous => frozen(&poll.tally_results()),
+        None => {
+          let personal: Vec<(NodeId, Weight)> = self
+            .fetch_all_raw_scores(ego_id, self.settings.zero_opinion_factor)
+            .into_iter()
+            .filter(|(id, score)| {
+              *id != ego_id && *score > 0.0 && self.is_user(*id)
+            })
+            .collect();
+          let personal_results = poll_results(
+            &poll.votes,
+            &personal,
+            POLL_SCORE_QUANTILES,
+            true,
+          );
+          let global_results = poll_results(
+            &poll.votes,
+            &self.global_user_scores(),
+            POLL_SCORE_QUANTILES,
+            true,
+          );
+
+          //  Each user of the circle counts once, the ego included.
+          let circle: Vec<(NodeId, Weight)> = std::iter::once((ego_id, 1.0))
+            .chain(personal.iter().map(|(id, _)| (*id, 1.0)))
+            .collect();
+          let circle_votes = poll_results(&poll.votes, &circle, 1, false);
+          let voted_share =
+            circle_votes.values().sum::<Weight>() / circle.len() as Weight;
+
+          poll
+            .options
+            .iter()
+            .map(|option| {
+              let get = |results: &HashMap<NodeId, Weight>| {
+                results.get(option).copied().unwrap_or(0.0)
+              };
+              (
+                *option,
+                get(&personal_results),
+                get(&global_results),
+                (voted_share * 100.0).round() as NodeCluster,
+                get(&circle_votes).round() as NodeCluster,
+              )
+            })
+            .collect()
+        },
+      };
+
+    let walks = self.walk_count(ego_id) as u64;
+    let mut results: Vec<ScoreResult> = rows
+      .into_iter()
+      .filter_map(|(option, score, reverse_score, cluster, reverse_cluster)| {
+        let info = self.nodes.get_by_id(option)?;
+        Some(ScoreResult {
+          ego: poll_name.clone(),
+          target: info.name.clone(),
+          score,
+          reverse_score,
+          cluster,
+          reverse_cluster,
+          kind: NodeKind::PollVariant,
+          rank: 0,
+          percentile: 0.0,
+          stale: false,
+          approximate: false,
+          fallback: false,
+          shared: false,
+          walks,
+          provenance: None,
+        })
+      })
+      .collect();
+    results.sort_by(|a, b| a.target.cmp(&b.target));
+    for (index, x) in results.iter_mut().enumerate() {
+      x.rank = index as u32 + 1;
+    }
+    results
+  }
+
+  /// All polls of the context, see `restore_poll`.
+  pub fn polls(&self) -> Vec<PollSnapshot> {
+    let name = |id: &NodeId| self.nodes.get_by_id(*id).map(|x| x.name.clone());
+    self
+      .polls
+      .polls
+      .iter()
+      .filter_map(|(poll_id, poll)| {
+        Some(PollSnapshot {
+          poll:       name(poll_id)?,
+          options:    poll.options.iter().filter_map(name).collect(),
+          votes:      poll
+            .votes
+            .iter()
+            .filter_map(|(user, vote)| {
+              Some((name(user)?, name(&vote.option)?, vote.weight))
+            })
+            .collect(),
+          expires_at: poll.expires_at,
+          anonymous:  poll.anonymous,
+          tallies:    poll
+            .tallies
+            .iter()
+            .filter_map(|(option, tally)| {
+              Some(PollTally {
+                option:     name(option)?,
+                filter:     tally.filter.clone(),
+                num_hashes: tally.num_hashes as u64,
+                count:      tally.count,
+                score:      tally.score,
+              })
+            })
+            .collect(),
+          results:    poll.results.as_ref().map(|results| {
+            results
+              .iter()
+              .filter_map(|(option, share, votes)| {
+                Some((name(option)?, *share, *votes))
+              })
+              .collect()
+          }),
+        })
+      })
+      .collect()
+  }
+
+  /// Adds a poll saved by `polls`.
+  pub fn restore_poll(
+    &mut self,
+    data: &PollSnapshot,
+  ) {
+    let mut register = |name: &NodeName, kind: NodeKind| {
+      self.nodes.register(&mut self.mr, name.clone(), kind)
+    };
+    let poll_id = register(&data.poll, NodeKind::Poll);
+    let mut poll = Poll {
+      expires_at: data.expires_at,
+      anonymous: data.anonymous,
+      ..Poll::default()
+    };
+    for option in &data.options {
+      let option = register(option, NodeKind::PollVariant);
+      poll.options.insert(option);
+      self.polls.options.insert(option, poll_id);
+    }
+    for (user, option, weight) in &data.votes {
+      let user = register(user, NodeKind::User);
+      let option = register(option, NodeKind::PollVariant);
+      poll.votes.insert(
+        user,
+        Vote {
+          option,
+          weight: *weight,
+        },
+      );
+    }
+    for tally in &data.tallies {
+      let option = register(&tally.option, NodeKind::PollVariant);
+      poll.tallies.insert(
+        option,
+        Tally {
+          filter:     tally.filter.clone(),
+          num_hashes: tally.num_hashes as usize,
+          count:      tally.count,
+          score:      tally.score,
+        },
+      );
+    }
+    poll.results = data.results.as_ref().map(|results| {
+      results
+        .iter()
+        .map(|(option, share, votes)| {
+          (register(option, NodeKind::PollVariant), *share, *votes)
+        })
+        .collect()
+    });
+    self.polls.polls.insert(poll_id, poll);
+  }
+}
@@ -0,0 +1,185 @@
+use crate::data::*;
+use crate::utils::log::*;
+
+use meritrank_core::{IntMap, NodeId};
+
+use super::AugGraph;
+
+impl AugGraph {
+  /// Users the ego trusts most, whose similarity to the ego is checked.
+  /// Their walks must be calculated before `read_recommendations`.
+  pub fn recommendation_candidates(
+    &self,
+    ego: &str,
+  ) -> Vec<NodeName> {
+    let ego_id = match self.nodes.get_by_name(ego) {
+      Some(info) => info.id,
+      None => return vec![],
+    };
+    let mut users: Vec<(NodeId, NodeScore)> = self
+      .fetch_all_raw_scores(ego_id, self.settings.zero_opinion_factor)
+      .into_iter()
+      .filter(|(id, score)| {
+        *id != ego_id
+          && *score > 0.0
+          && self
+            .nodes
+            .get_by_id(*id)
+            .is_some_and(|info| info.kind == NodeKind::User)
+      })
+      .collect();
+    users.sort_by(|a, b| b.1.total_cmp(&a.1));
+    users
+      .into_iter()
+      .take(self.settings.recommendation_egos * 2)
+      .filter_map(|(id, _)| self.nodes.get_by_id(id).map(|x| x.name.clone()))
+      .collect()
+  }
+
+  /// Nodes of the kind scored highly by the `recommendation_egos` candidates
+  /// most similar to the ego, excluding nodes the ego has an edge to or owns.
+  pub fn read_recommendations(
+    &self,
+    data: OpReadRecommendations,
+  ) -> Vec<RecommendationResult> {
+    log_command!("{:?}", data);
+
+    let ego_info = match self.nodes.get_by_name(&data.ego) {
+      Some(x) => x,
+      None => {
+        log_error!("Node not found: {:?}", data.ego);
+        return vec![];
+      },
+    };
+    if !self.ensure_ego_kind_enabled(&data.ego, ego_info) {
+      return vec![];
+    }
+    let ego_id = ego_info.id;
+
+    let mut similar: Vec<(NodeId, f64)> = self
+      .recommendation_candidates(&data.ego)
+      .iter()
+      .filter_map(|name| self.nodes.get_by_name(name))
+      .filter_map(|info| match self.mr.ego_similarity(ego_id, info.id) {
+        Ok(similarity) if similarity > 0.0 => Some((info.id, similarity)),
+        Ok(_) => None,
+        Err(e) => {
+          log_verbose!("Skip recommendation ego {}: {}", info.id, e);
+          None
+        },
+      })
+      .collect();
+    similar.sort_by(|a, b| b.1.total_cmp(&a.1));
+    similar.truncate(self.settings.recommendation_egos);
+
+    let total_similarity: f64 = similar.iter().map(|(_, x)| x).sum();
+    let mut nodes: IntMap<NodeId, (NodeScore, u32)> = IntMap::default();
+    for (other, similarity) in &similar {
+      let scores = self
+        .fetch_all_raw_scores(*other, self.settings.zero_opinion_factor);
+      for (id, score) in scores {
+        if score <= 0.0 || id == ego_id || id == *other {
+          continue;
+        }
+        let kind = match self.nodes.get_by_id(id) {
+          Some(info) => info.kind,
+          None => continue,
+        };
+        if data.kind.is_some_and(|x| x != kind)
+          || matches!(self.mr.graph.edge_weight(ego_id, id), Ok(Some(_)))
+          || self.get_object_owner(id) == Some(ego_id)
+        {
+          continue;
+        }
+        let entry = nodes.entry(id).or_insert((0.0, 0));
+        entry.0 += similarity * score / total_similarity;
+        entry.1 += 1;
+      }
+    }
+
+    let mut res: Vec<RecommendationResult> = nodes
+      .into_iter()
+      .filter_map(|(id, (score, egos))| {
+        self.nodes.get_by_id(id).map(|info| RecommendationResult {
+          target: info.name.clone(),
+          score,
+          egos,
+        })
+      })
+      .collect();
+    res.sort_by(|a, b| {
+      b.score.total_cmp(&a.score).then_with(|| a.target.cmp(&b.target))
+    });
+    res.truncate(data.limit as usize);
+    res
+  }
+
+  /// Users that the ego's positively trusted peers trust, but the ego has
+  /// no edge to. Sorted by the number of such peers, then by the total
+  /// weight of their edges.
+  pub fn read_mutual_suggestions(
+    &self,
+    data: OpReadMutualSuggestions,
+  ) -> Vec<MutualSuggestionResult> {
+    log_command!("{:?}", data);
+
+    let ego_info = match self.nodes.get_by_name(&data.ego) {
+      Some(x) => x,
+      None => {
+        log_error!("Node not found: {:?}", data.ego);
+        return vec![];
+      },
+    };
+    if !self.ensure_ego_kind_enabled(&data.ego, ego_info) {
+      return vec![];
+    }
+    let ego_id = ego_info.id;
+
+    let ego_data = match self.mr.graph.get_node_data(ego_id) {
+      Some(x) => x,
+      None => return vec![],
+    };
+
+    let mut users: IntMap<NodeId, (u32, Weight)> = IntMap::default();
+    for (&peer, _) in ego_data.pos_edges.iter() {
+      if !self.is_user(peer) {
+        continue;
+      }
+      let peer_data = match self.mr.graph.get_node_data(peer) {
+        Some(x) => x,
+        None => continue,
+      };
+      for (&dst, &weight) in peer_data.pos_edges.iter() {
+        if dst == ego_id
+          || !self.is_user(dst)
+          || ego_data.pos_edges.contains_key(&dst)
+          || ego_data.neg_edges.contains_key(&dst)
+        {
+          continue;
+        }
+        let entry = users.entry(dst).or_insert((0, 0.0));
+        entry.0 += 1;
+        entry.1 += weight;
+      }
+    }
+
+    let mut res: Vec<MutualSuggestionResult> = users
+      .into_iter()
+      .filter_map(|(id, (peers, weight))| {
+        self.nodes.get_by_id(id).map(|info| MutualSuggestionResult {
+          target: info.name.clone(),
+          peers,
+          weight,
+        })
+      })
+      .collect();
+    res.sort_by(|a, b| {
+      b.peers
+        .cmp(&a.peers)
+        .then_with(|| b.weight.total_cmp(&a.weight))
+        .then_with(|| a.target.cmp(&b.target))
+    });
+    res.truncate(data.limit as usize);
+    res
+  }
+}
@@ -0,0 +1,134 @@
+use crate::data::*;
+use crate::utils::log::*;
+
+use super::AugGraph;
+
+impl AugGraph {
+  /// Answers a read request from this graph copy, see `protocol::route`.
+  pub fn read_request(
+    &self,
+    data: ReqData,
+  ) -> Response {
+    match data {
+      ReqData::ReadScores(data) => scores_response(self.read_scores(data)),
+      ReqData::ReadScoresChunked(data) => {
+        scores_response(self.read_scores(OpReadScores {
+          ego:           data.ego,
+          score_options: data.score_options,
+        }))
+      },
+      ReqData::ReadNodeScore(data) => Response::Scores(ResScores {
+        scores: self.read_node_score(data),
+      }),
+      ReqData::ReadGraph(data) => Response::Graph(ResGraph {
+        graph: self.read_graph(data),
+      }),
+      ReqData::ReadNeighbors(data) => Response::Scores(ResScores {
+        scores: self.read_neighbors(data),
+      }),
+      ReqData::ReadNodeList => Response::NodeList(ResNodeList {
+        nodes: self.nodes.iter().map(|info| (info.name.clone(),)).collect(),
+      }),
+      ReqData::ReadMutes(data) => Response::NodeList(ResNodeList {
+        nodes: self
+          .muted_nodes(&data.ego)
+          .into_iter()
+          .map(|name| (name,))
+          .collect(),
+      }),
+      ReqData::ReadEdges => Response::Edges(ResEdges {
+        edges: self.read_edges(),
+      }),
+      ReqData::ReadConnected(data) => match self.nodes.get_by_name(&data.node)
+      {
+        Some(src) => Response::Connections(ResConnections {
+          connections: self
+            .mr
+            .graph
+            .get_node_data(src.id)
+            .unwrap()
+            .get_outgoing_edges()
+            .map(|(dst_id, _)| ConnectionResult {
+              src: data.node.clone(),
+              dst: self.nodes.get_by_id(dst_id).unwrap().name.clone(),
+            })
+            .collect(),
+        }),
+        None => {
+          log_error!("Node not found: {:?}", data.node);
+          Response::Fail
+        },
+      },
+      ReqData::ReadCompareEgos(data) => {
+        Response::EgoComparison(ResEgoComparison {
+          nodes: self.compare_egos(data),
+        })
+      },
+      ReqData::ReadRecommendations(data) => {
+        Response::Recommendations(ResRecommendations {
+          nodes: self.read_recommendations(data),
+        })
+      },
+      ReqData::ReadAnomalies(data) => Response::Anomalies(ResAnomalies {
+        nodes: self.read_anomalies(data),
+      }),
+      ReqData::ReadTopNodes(data) => Response::TopNodes(ResTopNodes {
+        nodes: self.read_top_nodes(data),
+      }),
+      ReqData::ReadBeaconComments(data) => {
+        Response::BeaconComments(self.read_beacon_comments(data))
+      },
+      ReqData::EvaluateSybilResistance(data) => {
+        Response::SybilResistance(self.evaluate_sybil_resistance(data))
+      },
+      ReqData::ReadMutualSuggestions(data) => {
+        Response::MutualSuggestions(ResMutualSuggestions {
+          users: self.read_mutual_suggestions(data),
+        })
+      },
+      ReqData::ReadScoreHistory(data) => {
+        Response::ScoreHistory(ResScoreHistory {
+          samples: self.read_score_history(data),
+        })
+      },
+      ReqData::ReadScoresAt(data) => Response::Scores(ResScores {
+        scores: self.read_scores_at(data),
+      }),
+      ReqData::ReadScoreDiff(data) => match self.read_score_diff(data) {
+        Some(diff) => Response::ScoreDiff(diff),
+        None => Response::Fail,
+      },
+      ReqData::ReadDryRun(data) => Response::DryRun(ResDryRun {
+        nodes: self.read_dry_run(data),
+      }),
+      ReqData::ReadMutualScores(data) => Response::Scores(ResScores {
+        scores: self.read_mutual_scores(data),
+      }),
+      ReqData::DebugEgo(data) => {
+        if !self.settings.debug_ops {
+          log_warning!("Debug ops are disabled, see MERITRANK_DEBUG_OPS");
+          return Response::NotImplemented;
+        }
+        Response::DebugEgo(self.debug_ego(data))
+      },
+      ReqData::WriteFetchNewEdges(_)
+      | ReqData::WriteNewEdgesFilter(_)
+      | ReqData::ReadNewEdgesFilter(_) => Response::NotImplemented,
+      data => {
+        log_error!("Not a read request: {:?}", data);
+        Response::Fail
+      },
+    }
+  }
+}
+
+fn scores_response(
+  scores: Result<Vec<ScoreResult>, ServiceError>,
+) -> Response {
+  match scores {
+    Ok(scores) => Response::Scores(ResScores {
+      scores,
+    }),
+    Err(e) => Response::Error(e),
+  }
+}
@@ -5,30 +5,139 @@ use crate::utils::{log::*, quantiles::*};
 
 use meritrank_core::{NodeId, Weight};
 
-use super::AugGraph;
+use super::{AugGraph, ClusterGroupBounds, ClusterKey};
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::Instant;
+
+/// Scored target with its cluster.
+type ScoredNode = (NodeInfo, NodeScore, NodeCluster);
 
 impl AugGraph {
+  /// Number of clusters for a request: the context setting, or a coarser
+  /// requested one.
+  pub fn num_clusters(
+    &self,
+    requested: u32,
+  ) -> usize {
+    let default = self.settings.num_score_quantiles;
+    match requested as usize {
+      0 => default,
+      n => n.clamp(2, default.max(2)),
+    }
+  }
+
   pub fn update_node_score_clustering(
     &self,
     ego: NodeId,
     kind: NodeKind,
-  ) -> super::ClusterGroupBounds {
-    log_trace!("{} {:?}", ego, kind);
-    let node_ids = self.nodes.nodes_by_kind(kind);
-    let bounds = self.calculate_score_clusters_bounds(ego, kind, node_ids);
-    self
-      .cached_score_clusters
-      .insert((ego, kind), bounds.clone());
+    num_clusters: usize,
+  ) -> ClusterGroupBounds {
+    log_trace!("{} {:?} {}", ego, kind, num_clusters);
+    let node_ids = self.nodes.ids_by_kind(kind);
+    let bounds =
+      self.calculate_score_clusters_bounds(ego, kind, node_ids, num_clusters);
+    self.cached_score_clusters.insert(
+      (ego, kind, num_clusters),
+      (self.ego_generation(ego), Instant::now(), bounds.clone()),
+    );
     bounds
   }
 
+  /// Bounds are stale if the ego's walks changed or they timed out. With
+  /// `deterministic` they don't time out, so reads don't depend on timing.
+  fn cluster_bounds_are_fresh(
+    &self,
+    ego: NodeId,
+    generation: u64,
+    computed_at: Instant,
+  ) -> bool {
+    generation == self.ego_generation(ego)
+      && (self.settings.deterministic
+        || computed_at.elapsed().as_secs()
+          < self.settings.score_clusters_timeout)
+  }
+
+  /// Cached bounds, recalculated if missing. Stale bounds are recalculated
+  /// too, or, with `background_clustering`, served as is and queued for the
+  /// background worker.
+  fn cluster_bounds(
+    &self,
+    key: ClusterKey,
+  ) -> ClusterGroupBounds {
+    let (ego, kind, num_clusters) = key;
+    match self.cached_score_clusters.get(&key) {
+      Some((generation, computed_at, bounds)) => {
+        if self.cluster_bounds_are_fresh(ego, generation, computed_at) {
+          bounds
+        } else if self.settings.background_clustering
+          && !self.settings.deterministic
+        {
+          self.pending_clusters.lock().insert(key);
+          bounds
+        } else {
+          self.update_node_score_clustering(ego, kind, num_clusters)
+        }
+      },
+      None => self.update_node_score_clustering(ego, kind, num_clusters),
+    }
+  }
+
+  /// Recalculates the queued stale bounds. Returns the number recalculated.
+  pub fn process_pending_clusters(&self) -> usize {
+    let keys: Vec<ClusterKey> = self.pending_clusters.lock().drain().collect();
+    for (ego, kind, num_clusters) in &keys {
+      self.update_node_score_clustering(*ego, *kind, *num_clusters);
+    }
+    keys.len()
+  }
+
+  pub fn pending_clusters_len(&self) -> usize {
+    self.pending_clusters.lock().len()
+  }
+
+  /// Precomputes stale or missing cluster bounds of the ego for every node kind.
+  pub fn warm_score_clusters(
+    &self,
+    ego: NodeId,
+  ) {
+    let num_clusters = self.settings.num_score_quantiles;
+    for kind in all_node_kinds() {
+      if self.nodes.ids_by_kind(kind).is_empty() {
+        continue;
+      }
+      let fresh = self
+        .cached_score_clusters
+        .get(&(ego, kind, num_clusters))
+        .is_some_and(|(generation, computed_at, _)| {
+          self.cluster_bounds_are_fresh(ego, generation, computed_at)
+        });
+      if !fresh {
+        self.update_node_score_clustering(ego, kind, num_clusters);
+      }
+    }
+  }
+
   fn calculate_score_clusters_bounds(
     &self,
     ego: NodeId,
     kind: NodeKind,
     node_ids: &[NodeId],
+    num_clusters: usize,
   ) -> Vec<NodeScore> {
     log_trace!("{} {:?}", ego, kind);
+    let _span = tracing::debug_span!("clustering", ego).entered();
+
+    if node_ids.len() > STREAMING_QUANTILES_MIN_NODES {
+      let mut summary = QuantileSummary::new(STREAMING_QUANTILES_EPSILON);
+      for dst in node_ids {
+        let score = self.fetch_raw_score(ego, *dst);
+        if score >= f64::EPSILON {
+          summary.insert(score);
+        }
+      }
+      return summary.bounds(num_clusters);
+    }
 
     let scores: Vec<NodeScore> = node_ids
       .iter()
@@ -37,10 +146,10 @@ impl AugGraph {
       .collect();
 
     if scores.is_empty() {
-      return vec![0.0; self.settings.num_score_quantiles - 1];
+      return vec![0.0; num_clusters - 1];
     }
 
-    calculate_quantiles_bounds(scores, self.settings.num_score_quantiles)
+    calculate_quantiles_bounds(scores, num_clusters)
   }
 
   pub fn apply_score_clustering(
@@ -49,17 +158,30 @@ impl AugGraph {
     score: NodeScore,
     kind: NodeKind,
   ) -> (NodeScore, NodeCluster) {
-    log_trace!("{} {} {:?}", ego_id, score, kind);
+    self.apply_score_clustering_at(
+      ego_id,
+      score,
+      kind,
+      self.settings.num_score_quantiles,
+    )
+  }
+
+  pub fn apply_score_clustering_at(
+    &self,
+    ego_id: NodeId,
+    score: NodeScore,
+    kind: NodeKind,
+    num_clusters: usize,
+  ) -> (NodeScore, NodeCluster) {
+    log_trace!("{} {} {:?} {}", ego_id, score, kind, num_clusters);
 
     if score < f64::EPSILON {
       //  Clusterize only positive scores.
       return (score, 0);
     }
 
-    let bounds: &Vec<Weight> = &self
-      .cached_score_clusters
-      .get(&(ego_id, kind))
-      .unwrap_or_else(|| self.update_node_score_clustering(ego_id, kind));
+    let bounds: &Vec<Weight> =
+      &self.cluster_bounds((ego_id, kind, num_clusters));
 
     if bounds_are_empty(bounds) {
       return (score, 1); // Return 1 instead of 0 for empty bounds
@@ -75,30 +197,129 @@ impl AugGraph {
     (score, cluster)
   }
 
+  /// Approximate percentile of the score among ego's scores of nodes of the
+  /// kind, from the cluster bounds: the share of clusters below the score's
+  /// cluster, plus the interpolated position within it. The top cluster has
+  /// no upper bound, so its nodes get the percentile of its lower bound.
+  pub fn score_percentile(
+    &self,
+    ego_id: NodeId,
+    score: NodeScore,
+    kind: NodeKind,
+    num_clusters: usize,
+  ) -> f64 {
+    if score < f64::EPSILON {
+      return 0.0;
+    }
+
+    let bounds = self.cluster_bounds((ego_id, kind, num_clusters));
+    if bounds_are_empty(&bounds) {
+      return 0.0;
+    }
+
+    let below = bounds.iter().filter(|bound| score > **bound).count();
+    let lower = if below == 0 { 0.0 } else { bounds[below - 1] };
+    let within = match bounds.get(below) {
+      Some(&upper) if upper > lower => (score - lower) / (upper - lower),
+      _ => 0.0,
+    };
+    100.0 * (below as f64 + within) / (bounds.len() + 1) as f64
+  }
+
+  /// Fails with `ScoresUnavailable` if the core fails to score the ego; an
+  /// unknown ego has no scores.
   pub fn read_scores(
     &self,
     data: OpReadScores,
-  ) -> Vec<ScoreResult> {
+  ) -> Result<Vec<ScoreResult>, ServiceError> {
     log_command!("{:?}", data);
 
-    let ego = data.ego;
+    let ego_info = match self.scored_ego(&data.ego) {
+      Some(x) => x,
+      None => return Ok(vec![]),
+    };
     let filter_options = data.score_options;
+    let num_clusters = self.num_clusters(filter_options.num_clusters);
+    let (scores, stale) = self
+      .try_fetch_all_scores(ego_info, num_clusters)
+      .map_err(ServiceError::ScoresUnavailable)?;
+    let mut results = self.apply_filters_and_pagination(
+      scores,
+      ego_info,
+      &filter_options,
+      false,
+    );
+    let approximate = self.has_partial_scores(ego_info.id);
+    for x in &mut results {
+      x.stale |= stale;
+      x.approximate = approximate;
+    }
+    Ok(results)
+  }
 
-    if let Some(ego_info) = self.nodes.get_by_name(&ego) {
-      if !self.ensure_ego_is_user(&ego, ego_info) {
-        return vec![];
-      }
-      let scores = self.fetch_all_scores(ego_info);
-      self.apply_filters_and_pagination(
-        scores,
-        ego_info,
-        &filter_options,
-        false,
-      )
-    } else {
-      // Ego not in this context's graph (no edges involving this user were written here).
-      log_warning!("Ego not found in context (no scores): {:?}", ego);
-      vec![]
+  /// Same as `read_scores`, with targets given by id, see `ResScoresCompact`.
+  pub fn read_scores_compact(
+    &self,
+    data: OpReadScores,
+  ) -> Result<Vec<CompactScoreResult>, ServiceError> {
+    log_command!("{:?}", data);
+
+    let ego_info = match self.scored_ego(&data.ego) {
+      Some(x) => x,
+      None => return Ok(vec![]),
+    };
+    let filter_options = data.score_options;
+    let num_clusters = self.num_clusters(filter_options.num_clusters);
+    let (scores, stale) = self
+      .try_fetch_all_scores(ego_info, num_clusters)
+      .map_err(ServiceError::ScoresUnavailable)?;
+    let items = self.apply_filters(scores, ego_info, &filter_options, false);
+    let mut results = self.paginate_items(
+      &items,
+      ego_info,
+      &filter_options,
+      num_clusters,
+    );
+    let approximate = self.has_partial_scores(ego_info.id);
+    for x in &mut results {
+      x.stale |= stale;
+      x.approximate = approximate;
+    }
+    Ok(results)
+  }
+
+  /// Names and kinds of the nodes, in one pass over the registry. Unknown
+  /// ids are skipped.
+  pub fn resolve_nodes(
+    &self,
+    ids: &BTreeSet<NodeId>,
+  ) -> Vec<CompactNode> {
+    ids
+      .iter()
+      .filter_map(|id| self.nodes.id_to_info.get(*id))
+      .map(|info| CompactNode {
+        id:   info.id,
+        name: info.name.clone(),
+        kind: info.kind,
+      })
+      .collect()
+  }
+
+  /// Info of the ego if it can have scores read.
+  fn scored_ego(
+    &self,
+    ego: &NodeName,
+  ) -> Option<&NodeInfo> {
+    match self.nodes.get_by_name(ego) {
+      Some(ego_info) if self.ensure_ego_kind_enabled(ego, ego_info) => {
+        Some(ego_info)
+      },
+      Some(_) => None,
+      None => {
+        // Ego not in this context's graph (no edges involving this user were written here).
+        log_warning!("Ego not found in context (no scores): {:?}", ego);
+        None
+      },
     }
   }
 
@@ -119,7 +340,7 @@ impl AugGraph {
       },
     };
 
-    if !self.ensure_ego_is_user(&ego, ego_info) {
+    if !self.ensure_ego_kind_enabled(&ego, ego_info) {
       return vec![];
     }
 
@@ -131,24 +352,41 @@ impl AugGraph {
       },
     };
 
-    let (score, cluster) = self.apply_score_clustering(
-      ego_info.id,
-      self.fetch_raw_score(ego_info.id, dst_id),
-      ego_info.kind,
-    );
-    let (reverse_score, reverse_cluster) =
+    //  Bucket among nodes of the target's kind, so that e.g. users are
+    //  clustered relative to each other for a beacon ego.
+    let num_clusters = self.settings.num_score_quantiles;
+    let (score, cluster, stale) =
+      self.fetch_score_checked(ego_info.id, dst_id, num_clusters);
+    let (reverse_score, reverse_cluster, reverse_stale) =
       match self.get_object_owner(dst_id) {
-        Some(dst_owner_id) => self.fetch_score_cached(dst_owner_id, ego_info.id),
-        None => (0.0, 0),
+        Some(dst_owner_id) => {
+          self.fetch_score_checked(dst_owner_id, ego_info.id, num_clusters)
+        },
+        None => (0.0, 0, false),
       };
 
+    self.score_history.record(ego_info.id, dst_id, score);
+
+    let kind = self.nodes.id_to_info[dst_id].kind;
+    let percentile =
+      self.score_percentile(ego_info.id, score, kind, num_clusters);
+
     vec![ScoreResult {
-      ego: ego.into(),
-      target: dst.into(),
+      ego,
+      target: dst,
       score,
       reverse_score,
       cluster,
       reverse_cluster,
+      kind,
+      rank: 1,
+      percentile,
+      stale: stale || reverse_stale,
+      approximate: false,
+      fallback: false,
+      shared: false,
+      walks: self.walk_count(ego_info.id) as u64,
+      provenance: None,
     }]
   }
 
@@ -171,46 +409,160 @@ impl AugGraph {
     filter_options: &FilterOptions,
     prioritize_ego_owned_nodes: bool,
   ) -> Vec<ScoreResult> {
-    let mut filtered_sorted_scores =
-      filter_and_sort_scores(scores, ego_info, filter_options);
+    let items = self.apply_filters(
+      scores,
+      ego_info,
+      filter_options,
+      prioritize_ego_owned_nodes,
+    );
+    self.paginate_and_format_items(
+      &items,
+      ego_info,
+      filter_options,
+      self.num_clusters(filter_options.num_clusters),
+    )
+  }
+
+  fn apply_filters(
+    &self,
+    scores: Vec<(NodeInfo, NodeScore, NodeCluster)>,
+    ego_info: &NodeInfo,
+    filter_options: &FilterOptions,
+    prioritize_ego_owned_nodes: bool,
+  ) -> Vec<(NodeInfo, NodeScore, NodeCluster)> {
+    //  Personal filters follow edge and node deletions, unlike `NodeInfo::owner`,
+    //  so when enabled they replace the owner check.
+    let use_personal_filters =
+      filter_options.hide_personal && self.settings.filter_capacity > 0;
+    let mut filtered_sorted_scores = filter_and_sort_scores(
+      scores,
+      ego_info,
+      &FilterOptions {
+        hide_personal: filter_options.hide_personal && !use_personal_filters,
+        ..filter_options.clone()
+      },
+    );
+
+    if use_personal_filters {
+      filtered_sorted_scores
+        .retain(|(info, _, _)| !self.is_personal_node(ego_info.id, info.id));
+    }
+
+    let (exclude, require) =
+      (filter_options.exclude_flags, filter_options.require_flags);
+    if exclude != 0 || require != 0 {
+      filtered_sorted_scores.retain(|(info, _, _)| {
+        let flags = self.node_flags(info.id);
+        flags & exclude == 0 && flags & require == require
+      });
+    }
+
+    if self.mutes.contains_key(&ego_info.id) {
+      filtered_sorted_scores
+        .retain(|(info, _, _)| !self.is_muted(ego_info.id, info.id));
+    }
+
+    if filter_options.include_negative_only {
+      filtered_sorted_scores
+        .retain(|(info, _, _)| self.is_penalized(ego_info.id, info.id));
+    }
+
+    let component = filter_options.filter_on;
+    if component != ScoreComponent::Blended {
+      filtered_sorted_scores.retain(|(info, _, _)| {
+        let score = self.score_component(ego_info.id, info.id, component);
+        score_in_range(score, filter_options)
+      });
+    }
+
+    let clusters = filter_options.cluster_min as NodeCluster
+      ..=filter_options.cluster_max as NodeCluster;
+    filtered_sorted_scores.retain(|(_, _, cluster)| clusters.contains(cluster));
 
     if prioritize_ego_owned_nodes {
       prioritize_ego_owned_items(&mut filtered_sorted_scores, ego_info);
     }
-
-    self.paginate_and_format_items(
-      filtered_sorted_scores,
-      ego_info,
-      filter_options.index,
-      filter_options.count,
-    )
+    filtered_sorted_scores
   }
 
   fn paginate_and_format_items(
     &self,
-    items: Vec<(NodeInfo, NodeScore, NodeCluster)>,
+    items: &[(NodeInfo, NodeScore, NodeCluster)],
     ego_info: &NodeInfo,
-    index: u32,
-    count: u32,
+    filter_options: &FilterOptions,
+    num_clusters: usize,
   ) -> Vec<ScoreResult> {
-    let start = index as usize;
-    let end = (index + count) as usize;
+    let start = (filter_options.index as usize).min(items.len());
+    self
+      .paginate_items(items, ego_info, filter_options, num_clusters)
+      .into_iter()
+      .zip(&items[start..])
+      .map(|(x, (target_info, _, _))| ScoreResult {
+        ego:             ego_info.name.clone(),
+        target:          target_info.name.clone(),
+        score:           x.score,
+        reverse_score:   x.reverse_score,
+        cluster:         x.cluster,
+        reverse_cluster: x.reverse_cluster,
+        kind:            target_info.kind,
+        rank:            x.rank,
+        percentile:      x.percentile,
+        stale:           x.stale,
+        approximate:     x.approximate,
+        fallback:        false,
+        shared:          false,
+        walks:           x.walks,
+        provenance:      None,
+      })
+      .collect()
+  }
+
+  /// Reverse scores are only read for the items of the page, and only if
+  /// `include_reverse_scores` is set.
+  fn paginate_items(
+    &self,
+    items: &[(NodeInfo, NodeScore, NodeCluster)],
+    ego_info: &NodeInfo,
+    filter_options: &FilterOptions,
+    num_clusters: usize,
+  ) -> Vec<CompactScoreResult> {
+    let start = filter_options.index as usize;
+    let end =
+      filter_options.index.saturating_add(filter_options.count) as usize;
+    let walks = self.walk_count(ego_info.id) as u64;
 
     items[start..end.min(items.len())]
       .iter()
-      .map(|(target_info, score, cluster)| {
-        let (reverse_score, reverse_cluster) =
-          match self.get_object_owner(target_info.id) {
-            Some(owner_id) => self.fetch_score_cached(owner_id, ego_info.id),
-            None => (0.0, 0),
-          };
-        ScoreResult {
-          ego: ego_info.name.clone(),
-          target: target_info.name.clone(),
+      .enumerate()
+      .map(|(i, (target_info, score, cluster))| {
+        let owner_id = if filter_options.include_reverse_scores {
+          self.get_object_owner(target_info.id)
+        } else {
+          None
+        };
+        let (reverse_score, reverse_cluster, stale) = match owner_id {
+          Some(owner_id) => {
+            self.fetch_score_checked(owner_id, ego_info.id, num_clusters)
+          },
+          None => (0.0, 0, false),
+        };
+        self.score_history.record(ego_info.id, target_info.id, *score);
+        CompactScoreResult {
+          target: target_info.id,
           score: *score,
           reverse_score,
           cluster: *cluster,
           reverse_cluster,
+          rank: (start + i + 1) as u32,
+          percentile: self.score_percentile(
+            ego_info.id,
+            *score,
+            target_info.kind,
+            num_clusters,
+          ),
+          stale,
+          approximate: false,
+          walks,
         }
       })
       .collect()
@@ -221,44 +573,216 @@ impl AugGraph {
     ego_id: NodeId,
     dst_id: NodeId,
   ) -> (NodeScore, NodeCluster) {
+    self.fetch_score_cached_at(ego_id, dst_id, self.settings.num_score_quantiles)
+  }
+
+  pub fn fetch_score_cached_at(
+    &self,
+    ego_id: NodeId,
+    dst_id: NodeId,
+    num_clusters: usize,
+  ) -> (NodeScore, NodeCluster) {
+    let (score, cluster, _stale) =
+      self.fetch_score_checked(ego_id, dst_id, num_clusters);
+    (score, cluster)
+  }
+
+  /// Same as `fetch_score_cached_at`, and tells whether the score is stale.
+  /// Cached scores stamped with an older generation of the ego are stale:
+  /// they are served with `serve_stale_scores`, and recomputed otherwise.
+  pub(crate) fn fetch_score_checked(
+    &self,
+    ego_id: NodeId,
+    dst_id: NodeId,
+    num_clusters: usize,
+  ) -> (NodeScore, NodeCluster, bool) {
     log_trace!("{} {}", dst_id, ego_id);
 
-    let score = match self.cached_scores.get(&(ego_id, dst_id)) {
-      Some(score) => self.with_zero_opinion(dst_id, score),
-      None => self.fetch_raw_score(ego_id, dst_id),
+    let generation = self.ego_generation(ego_id);
+    let (score, stale) = match self.cached_scores.get(&(ego_id, dst_id)) {
+      Some((g, score)) if g == generation => {
+        (self.with_zero_opinion(dst_id, score), false)
+      },
+      Some((_, score)) if self.settings.serve_stale_scores => {
+        (self.with_zero_opinion(dst_id, score), true)
+      },
+      _ => (self.fetch_raw_score(ego_id, dst_id), false),
     };
 
     let kind_opt = self
       .nodes
       .get_by_id(dst_id)
-      .and_then(|node_info| Some(node_info.kind));
+      .map(|node_info| node_info.kind);
 
-    if let Some(kind) = kind_opt {
-      self.apply_score_clustering(ego_id, score, kind)
+    let (score, cluster) = if let Some(kind) = kind_opt {
+      self.apply_score_clustering_at(ego_id, score, kind, num_clusters)
     } else {
       (score, 0) // Default cluster if kind is None
-    }
+    };
+    (score, cluster, stale)
   }
 
   pub(crate) fn fetch_all_scores(
     &self,
     ego_info: &NodeInfo,
-  ) -> Vec<(NodeInfo, NodeScore, NodeCluster)> {
-    log_trace!("{}", ego_info.id);
+    num_clusters: usize,
+  ) -> Vec<ScoredNode> {
     self
-      .fetch_all_raw_scores(ego_info.id, self.settings.zero_opinion_factor)
+      .try_fetch_all_scores(ego_info, num_clusters)
+      .map(|(scores, _stale)| scores)
+      .unwrap_or_default()
+  }
+
+  /// Same as `fetch_all_scores`, see `try_fetch_all_raw_scores`.
+  pub(crate) fn try_fetch_all_scores(
+    &self,
+    ego_info: &NodeInfo,
+    num_clusters: usize,
+  ) -> Result<(Vec<ScoredNode>, bool), CoreErrorCode> {
+    log_trace!("{}", ego_info.id);
+    let (scores, stale) = self.try_fetch_all_raw_scores(
+      ego_info.id,
+      self.settings.zero_opinion_factor,
+    )?;
+    let scores = scores
       .iter()
       .filter_map(|(dst_id, score)| {
         self.nodes.get_by_id(*dst_id).map(|node_info| {
           let cluster = self
-            .apply_score_clustering(ego_info.id, *score, node_info.kind)
+            .apply_score_clustering_at(
+              ego_info.id,
+              *score,
+              node_info.kind,
+              num_clusters,
+            )
             .1;
           (node_info.clone(), *score, cluster)
         })
       })
-      .collect()
+      .collect();
+    Ok((scores, stale))
+  }
+
+  /// Nodes scored by either ego, sorted by how much their scores differ.
+  /// A node scored by only one ego counts as scored 0 by the other.
+  pub fn compare_egos(
+    &self,
+    data: OpReadCompareEgos,
+  ) -> Vec<EgoComparisonResult> {
+    log_command!("{:?}", data);
+
+    let mut infos = vec![];
+    for ego in [&data.a, &data.b] {
+      match self.nodes.get_by_name(ego) {
+        Some(info) if self.ensure_ego_kind_enabled(ego, info) => {
+          infos.push(info)
+        },
+        Some(_) => return vec![],
+        None => {
+          log_error!("Node not found: {:?}", ego);
+          return vec![];
+        },
+      }
+    }
+
+    let num_clusters = self.settings.num_score_quantiles;
+    let mut nodes: HashMap<NodeId, EgoComparisonResult> = HashMap::new();
+    for (info, score, cluster) in self.fetch_all_scores(infos[0], num_clusters)
+    {
+      nodes.insert(
+        info.id,
+        EgoComparisonResult {
+          target:    info.name,
+          score_a:   score,
+          score_b:   0.0,
+          cluster_a: cluster,
+          cluster_b: 0,
+        },
+      );
+    }
+    for (info, score, cluster) in self.fetch_all_scores(infos[1], num_clusters)
+    {
+      let entry = nodes.entry(info.id).or_insert(EgoComparisonResult {
+        target:    info.name,
+        score_a:   0.0,
+        score_b:   0.0,
+        cluster_a: 0,
+        cluster_b: 0,
+      });
+      entry.score_b = score;
+      entry.cluster_b = cluster;
+    }
+
+    let mut nodes: Vec<EgoComparisonResult> = nodes.into_values().collect();
+    nodes.sort_by(|x, y| {
+      (y.score_a - y.score_b)
+        .abs()
+        .total_cmp(&(x.score_a - x.score_b).abs())
+        .then_with(|| x.target.cmp(&y.target))
+    });
+    nodes.truncate(data.limit as usize);
+    nodes
   }
 
+  /// Factor of `score_weights` for the node's kind.
+  pub(crate) fn kind_weight(
+    &self,
+    dst_id: NodeId,
+  ) -> f64 {
+    if self.settings.score_weights.is_empty() {
+      return 1.0;
+    }
+    let kind = match self.nodes.get_by_id(dst_id) {
+      Some(info) => info.kind,
+      None => return 1.0,
+    };
+    self
+      .settings
+      .score_weights
+      .iter()
+      .find(|(k, _)| *k == kind)
+      .map_or(1.0, |(_, factor)| *factor)
+  }
+
+  /// Part of the ego's score of the node. The personal score and the zero
+  /// opinion are not weighted by kind.
+  pub(crate) fn score_component(
+    &self,
+    ego_id: NodeId,
+    dst_id: NodeId,
+    component: ScoreComponent,
+  ) -> NodeScore {
+    match component {
+      ScoreComponent::Personal => self
+        .mr
+        .get_node_score(ego_id, dst_id)
+        .ok()
+        .or_else(|| self.evicted_score(ego_id, dst_id))
+        .unwrap_or(0.0),
+      ScoreComponent::ZeroOpinion => {
+        self.zero_opinion.get(dst_id).copied().unwrap_or(0.0)
+      },
+      ScoreComponent::Blended => self.fetch_raw_score(ego_id, dst_id),
+    }
+  }
+
+  /// Whether the ego's walks visit the node on negative segments more than
+  /// on positive ones. Egos with evicted walks fall back to their saved
+  /// score.
+  pub(crate) fn is_penalized(
+    &self,
+    ego_id: NodeId,
+    dst_id: NodeId,
+  ) -> bool {
+    match self.mr.get_node_score_parts(ego_id, dst_id) {
+      Ok(parts) => parts.negative > parts.positive,
+      Err(_) => self
+        .evicted_score(ego_id, dst_id)
+        .is_some_and(|score| score < 0.0),
+    }
+  }
+
+  /// Blends the score with the zero opinion and applies the kind weight.
   pub fn with_zero_opinion(
     &self,
     dst_id: NodeId,
@@ -271,10 +795,10 @@ impl AugGraph {
       _ => 0.0,
     };
     let k = self.settings.zero_opinion_factor;
-    score * (1.0 - k) + k * zero_score
+    (score * (1.0 - k) + k * zero_score) * self.kind_weight(dst_id)
   }
 
-  fn with_zero_opinions(
+  pub(crate) fn with_zero_opinions(
     &self,
     scores: Vec<(NodeId, NodeScore)>,
   ) -> Vec<(NodeId, NodeScore)> {
@@ -291,8 +815,8 @@ impl AugGraph {
       if *id >= res.len() {
         let n = res.len();
         res.resize(id + 1, (0, 0.0));
-        for id in n..res.len() {
-          res[id].0 = id;
+        for (id, entry) in res.iter_mut().enumerate().skip(n) {
+          entry.0 = id;
         }
       }
       res[*id].1 += (1.0 - k) * score;
@@ -300,6 +824,7 @@ impl AugGraph {
 
     res
       .into_iter()
+      .map(|(id, score)| (id, score * self.kind_weight(id)))
       .filter(|(_id, score)| *score != 0.0)
       .collect::<Vec<_>>()
   }
@@ -313,21 +838,72 @@ impl AugGraph {
 
     match self.mr.get_node_score(ego_id, dst_id) {
       Ok(score) => {
-        self.cached_scores.insert((ego_id, dst_id), score);
+        self
+          .cached_scores
+          .insert((ego_id, dst_id), (self.ego_generation(ego_id), score));
         self.with_zero_opinion(dst_id, score)
       },
-      Err(e) => {
-        log_trace!("Failed to get node score: {}", e);
-        0.0
+      Err(e) => match self.evicted_score(ego_id, dst_id) {
+        Some(score) => self.with_zero_opinion(dst_id, score),
+        None => {
+          log_trace!("Failed to get node score: {}", e);
+          0.0
+        },
       },
     }
   }
 
+  /// Scores of the egos by target name, or of all calculated egos if `egos`
+  /// is empty. Egos without walks are skipped rather than calculated.
+  pub fn ego_scores(
+    &self,
+    egos: &[NodeName],
+  ) -> HashMap<NodeName, HashMap<NodeName, NodeScore>> {
+    let ids: Vec<NodeId> = if egos.is_empty() {
+      self.mr.get_personal_hits().keys().copied().collect()
+    } else {
+      egos
+        .iter()
+        .filter_map(|ego| self.nodes.get_by_name(ego))
+        .map(|info| info.id)
+        .filter(|id| self.mr.get_personal_hits().contains_key(id))
+        .collect()
+    };
+    ids
+      .into_iter()
+      .filter_map(|ego_id| {
+        let ego = self.nodes.get_by_id(ego_id)?.name.clone();
+        let scores = self
+          .fetch_all_raw_scores(ego_id, self.settings.zero_opinion_factor)
+          .into_iter()
+          .filter_map(|(id, score)| {
+            Some((self.nodes.get_by_id(id)?.name.clone(), score))
+          })
+          .collect();
+        Some((ego, scores))
+      })
+      .collect()
+  }
+
   pub(crate) fn fetch_all_raw_scores(
     &self,
     ego_id: NodeId,
     zero_opinion_factor: f64,
   ) -> Vec<(NodeId, NodeScore)> {
+    self
+      .try_fetch_all_raw_scores(ego_id, zero_opinion_factor)
+      .map(|(scores, _stale)| scores)
+      .unwrap_or_default()
+  }
+
+  /// Same as `fetch_all_raw_scores`, and tells whether the scores are stale.
+  /// Fails if the core fails to score the ego and no last scores of it are
+  /// kept, see `keep_evicted_scores` and `degraded_mode`.
+  pub(crate) fn try_fetch_all_raw_scores(
+    &self,
+    ego_id: NodeId,
+    zero_opinion_factor: f64,
+  ) -> Result<(Vec<(NodeId, NodeScore)>, bool), CoreErrorCode> {
     log_trace!(
       "{} {} {}",
       ego_id,
@@ -335,43 +911,75 @@ impl AugGraph {
       zero_opinion_factor
     );
 
-    match self.mr.get_all_scores(ego_id, None) {
+    let (scores, stale) = match self.mr.get_all_scores(ego_id, None) {
       Ok(scores) => {
+        let generation = self.ego_generation(ego_id);
         for (dst_id, score) in &scores {
-          self.cached_scores.insert((ego_id, *dst_id), *score);
-        }
-        let scores = self.with_zero_opinions(scores);
-
-        // Filter out nodes that have a direct negative edge from ego
-        if self.settings.omit_neg_edges_scores {
-          let before = scores.len();
-          let (kept, dropped): (Vec<_>, Vec<_>) = scores
-            .into_iter()
-            .partition(|(dst_id, _)| {
-              match self.mr.graph.edge_weight(ego_id, *dst_id) {
-                Ok(Some(weight)) => weight > 0.0,
-                _ => true,
-              }
-            });
-          if !dropped.is_empty() {
-            log_trace!(
-              "omit_neg_edges_scores: ego_id={} before={} kept={} dropped={} dropped_ids={:?}",
-              ego_id,
-              before,
-              kept.len(),
-              dropped.len(),
-              dropped.iter().map(|(id, _)| *id).collect::<Vec<_>>()
-            );
-          }
-          kept
-        } else {
-          scores
+          self.cached_scores.insert((ego_id, *dst_id), (generation, *score));
         }
+        self.snapshot_scores(ego_id, generation, &scores);
+        (scores, false)
       },
-      Err(e) => {
-        log_trace!("{}", e);
-        vec![]
+      Err(e) => match self.kept_scores(ego_id) {
+        Some(scores) => (scores.clone(), false),
+        None => {
+          log_warning!("Failed to get scores of ego {}: {}", ego_id, e);
+          match self.last_cached_scores(ego_id) {
+            Some(scores) => (scores, true),
+            None => return Err(CoreErrorCode::from(&e)),
+          }
+        },
       },
+    };
+    let scores = self.with_zero_opinions(scores);
+
+    // Filter out nodes that have a direct negative edge from ego
+    if self.settings.omit_neg_edges_scores {
+      let before = scores.len();
+      let (kept, dropped): (Vec<_>, Vec<_>) = scores
+        .into_iter()
+        .partition(|(dst_id, _)| {
+          match self.mr.graph.edge_weight(ego_id, *dst_id) {
+            Ok(Some(weight)) => weight > 0.0,
+            _ => true,
+          }
+        });
+      if !dropped.is_empty() {
+        log_trace!(
+          "omit_neg_edges_scores: ego_id={} before={} kept={} dropped={} dropped_ids={:?}",
+          ego_id,
+          before,
+          kept.len(),
+          dropped.len(),
+          dropped.iter().map(|(id, _)| *id).collect::<Vec<_>>()
+        );
+      }
+      Ok((kept, stale))
+    } else {
+      Ok((scores, stale))
+    }
+  }
+
+  /// Scores of the ego still in the scores cache, whatever their generation,
+  /// if `degraded_mode` is set.
+  fn last_cached_scores(
+    &self,
+    ego_id: NodeId,
+  ) -> Option<Vec<(NodeId, NodeScore)>> {
+    if !self.settings.degraded_mode {
+      return None;
+    }
+    let mut scores: Vec<(NodeId, NodeScore)> = self
+      .cached_scores
+      .iter()
+      .filter(|(key, _)| key.0 == ego_id)
+      .map(|(key, (_, score))| (key.1, score))
+      .collect();
+    if scores.is_empty() {
+      return None;
     }
+    log_warning!("Serving last cached scores of ego {}", ego_id);
+    scores.sort_unstable_by_key(|(id, _)| *id);
+    Some(scores)
   }
 }
@@ -0,0 +1,84 @@
+use crate::data::*;
+
+use meritrank_core::NodeId;
+
+use super::AugGraph;
+
+use std::collections::HashSet;
+
+/// Calculated egos compared at most per lookup, so that an ego following
+/// popular nodes does not compare itself to all of their followers.
+const MAX_SIMILAR_CANDIDATES: usize = 1000;
+
+impl AugGraph {
+  fn positive_neighbors(
+    &self,
+    id: NodeId,
+  ) -> HashSet<NodeId> {
+    match self.mr.graph.get_node_data(id) {
+      Some(data) => data
+        .get_outgoing_edges()
+        .filter(|(_, weight)| *weight > 0.0)
+        .map(|(dst_id, _)| dst_id)
+        .collect(),
+      None => HashSet::new(),
+    }
+  }
+
+  /// Calculated user whose positive neighbors are the most similar to those
+  /// of the ego, if their Jaccard index is at least `threshold`, see
+  /// `MERITRANK_SHARED_SCORES_SIMILARITY`. Candidates are the calculated
+  /// users with a positive edge to a positive neighbor of the ego. None for
+  /// an ego with walks or without positive edges.
+  pub fn similar_calculated_ego(
+    &self,
+    ego: &NodeName,
+    threshold: f64,
+  ) -> Option<NodeName> {
+    let ego_id = self.nodes.get_by_name(ego)?.id;
+    let hits = self.mr.get_personal_hits();
+    if hits.contains_key(&ego_id) {
+      return None;
+    }
+    let neighbors = self.positive_neighbors(ego_id);
+    if neighbors.is_empty() {
+      return None;
+    }
+
+    let mut candidates: Vec<NodeId> = vec![];
+    let mut seen = HashSet::from([ego_id]);
+    'neighbors: for dst_id in &neighbors {
+      let data = match self.mr.graph.get_node_data(*dst_id) {
+        Some(x) => x,
+        None => continue,
+      };
+      for (src_id, weight) in data.get_inbound_edges() {
+        if weight <= 0.0 || !seen.insert(src_id) {
+          continue;
+        }
+        let is_user = self
+          .nodes
+          .get_by_id(src_id)
+          .is_some_and(|info| info.kind == NodeKind::User);
+        if is_user && hits.contains_key(&src_id) {
+          candidates.push(src_id);
+          if candidates.len() == MAX_SIMILAR_CANDIDATES {
+            break 'neighbors;
+          }
+        }
+      }
+    }
+
+    let jaccard = |other: &HashSet<NodeId>| {
+      let common = neighbors.intersection(other).count();
+      common as f64 / (neighbors.len() + other.len() - common) as f64
+    };
+    candidates
+      .into_iter()
+      .map(|id| (id, jaccard(&self.positive_neighbors(id))))
+      .filter(|(_, similarity)| *similarity >= threshold)
+      .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+      .and_then(|(id, _)| self.nodes.get_by_id(id))
+      .map(|info| info.name.clone())
+  }
+}
@@ -0,0 +1,125 @@
+use crate::data::*;
+use crate::utils::log::*;
+
+use meritrank_core::NodeId;
+use rand::seq::IndexedRandom;
+
+use super::AugGraph;
+
+use std::collections::HashSet;
+
+impl AugGraph {
+  /// Share of the positive top `top_k` scores of the ego that go to the
+  /// suspects. None if the ego has no positive scores.
+  fn suspect_share(
+    &self,
+    ego: NodeId,
+    suspects: &HashSet<NodeId>,
+    top_k: usize,
+  ) -> Option<f64> {
+    let scores = match self.mr.get_all_scores(ego, None) {
+      Ok(x) => x,
+      Err(e) => {
+        log_warning!("Skip ego {}: {}", ego, e);
+        return None;
+      },
+    };
+    let (total, captured) = scores
+      .into_iter()
+      .filter(|(id, score)| *id != ego && *score > 0.0)
+      .take(top_k)
+      .fold((0.0, 0.0), |(total, captured), (id, score)| {
+        let captured = if suspects.contains(&id) {
+          captured + score
+        } else {
+          captured
+        };
+        (total + score, captured)
+      });
+    (total > 0.0).then_some(captured / total)
+  }
+
+  /// How much of the top scores of sampled honest users the suspects
+  /// capture, before and after their attack edges (edges from other nodes
+  /// to the suspects) are removed. Runs on a fork of the graph.
+  pub fn evaluate_sybil_resistance(
+    &self,
+    data: OpEvaluateSybilResistance,
+  ) -> ResSybilResistance {
+    log_command!("{:?}", data);
+
+    let suspects: HashSet<NodeId> = data
+      .suspects
+      .iter()
+      .filter_map(|name| self.nodes.get_by_name(name).map(|info| info.id))
+      .collect();
+    let honest: Vec<NodeName> = self
+      .nodes
+      .iter()
+      .filter(|info| {
+        info.kind == NodeKind::User && !suspects.contains(&info.id)
+      })
+      .map(|info| info.name.clone())
+      .collect();
+    let egos: Vec<NodeName> = honest
+      .choose_multiple(&mut self.sample_rng(), data.egos as usize)
+      .cloned()
+      .collect();
+
+    let is_suspect = |name: &NodeName| {
+      self
+        .nodes
+        .get_by_name(name)
+        .is_some_and(|info| suspects.contains(&info.id))
+    };
+    let attack_edges: Vec<(NodeName, NodeName)> = self
+      .read_edges()
+      .into_iter()
+      .filter(|edge| {
+        edge.weight > 0.0 && !is_suspect(&edge.src) && is_suspect(&edge.dst)
+      })
+      .map(|edge| (edge.src, edge.dst))
+      .collect();
+
+    let mut fork = self.fork();
+    for ego in &egos {
+      let calculated = fork.nodes.get_by_name(ego).is_some_and(|info| {
+        fork.mr.get_personal_hits().contains_key(&info.id)
+      });
+      if !calculated {
+        fork.calculate(ego.clone());
+      }
+    }
+    let ego_ids: Vec<NodeId> = egos
+      .iter()
+      .filter_map(|name| fork.nodes.get_by_name(name).map(|info| info.id))
+      .collect();
+
+    let average_share = |graph: &AugGraph| {
+      let shares: Vec<f64> = ego_ids
+        .iter()
+        .filter_map(|ego| {
+          graph.suspect_share(*ego, &suspects, data.top_k as usize)
+        })
+        .collect();
+      if shares.is_empty() {
+        0.0
+      } else {
+        shares.iter().sum::<f64>() / shares.len() as f64
+      }
+    };
+
+    let before = average_share(&fork);
+    for (src, dst) in &attack_edges {
+      fork.set_edge(src.clone(), dst.clone(), 0.0, 0);
+    }
+    let after = average_share(&fork);
+
+    ResSybilResistance {
+      egos: ego_ids.len() as u32,
+      attack_edges: attack_edges.len() as u32,
+      before,
+      after,
+    }
+  }
+}
@@ -0,0 +1,70 @@
+use crate::data::*;
+use crate::utils::log::*;
+
+use meritrank_core::NodeId;
+
+use super::AugGraph;
+
+impl AugGraph {
+  /// Nodes ranked by the zero opinion of the context, or by the ego's scores
+  /// blended with it when an ego is given. At most `top_nodes_limit` nodes
+  /// are returned per page.
+  pub fn read_top_nodes(
+    &self,
+    data: OpReadTopNodes,
+  ) -> Vec<TopNodeResult> {
+    log_command!("{:?}", data);
+
+    let scores: Vec<(NodeId, NodeScore)> = match &data.ego {
+      Some(ego) => {
+        let ego_info = match self.nodes.get_by_name(ego) {
+          Some(x) => x,
+          None => {
+            log_error!("Node not found: {:?}", ego);
+            return vec![];
+          },
+        };
+        if !self.ensure_ego_kind_enabled(ego, ego_info) {
+          return vec![];
+        }
+        self.fetch_all_raw_scores(
+          ego_info.id,
+          self.settings.zero_opinion_factor,
+        )
+      },
+      None => self
+        .zero_opinion
+        .iter()
+        .enumerate()
+        .filter(|(_, score)| **score != 0.0)
+        .map(|(id, score)| (id, score * self.kind_weight(id)))
+        .collect(),
+    };
+
+    let mut nodes: Vec<TopNodeResult> = scores
+      .into_iter()
+      .filter_map(|(id, score)| {
+        let info = self.nodes.get_by_id(id)?;
+        if data.kind.is_some_and(|kind| kind != info.kind) {
+          return None;
+        }
+        Some(TopNodeResult {
+          node: info.name.clone(),
+          kind: info.kind,
+          score,
+        })
+      })
+      .collect();
+    nodes.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let mut limit = data.limit as usize;
+    if self.settings.top_nodes_limit > 0 {
+      limit = limit.min(self.settings.top_nodes_limit);
+    }
+    nodes
+      .into_iter()
+      .skip(data.index as usize)
+      .take(limit)
+      .collect()
+  }
+}
@@ -0,0 +1,62 @@
+use crate::data::*;
+use crate::node_registry::*;
+use crate::utils::log::*;
+
+use super::AugGraph;
+
+use meritrank_core::EgoParams;
+
+impl AugGraph {
+  /// Overrides the walk settings of the ego. An ego that is already
+  /// calculated is recalculated, so its reads never mix the settings.
+  pub fn set_user_params(
+    &mut self,
+    data: &OpSetUserParams,
+  ) {
+    log_command!("{:?}", data);
+
+    let kind = match node_kind_from_prefix(&data.ego) {
+      Some(x) => x,
+      None => {
+        log_error!("Failed to get node kind for {:?}", data.ego);
+        return;
+      },
+    };
+    let ego_id = self.nodes.register(&mut self.mr, data.ego.clone(), kind);
+
+    let params = match (data.alpha, data.walk_policy) {
+      (None, WalkPolicy::Default) => None,
+      (alpha, walk_policy) => Some(EgoParams {
+        alpha:         alpha.unwrap_or(self.mr.alpha),
+        positive_only: walk_policy == WalkPolicy::PositiveOnly,
+      }),
+    };
+    self.mr.set_ego_params(ego_id, params);
+
+    if self.mr.get_personal_hits().contains_key(&ego_id) {
+      self.calculate(data.ego.clone());
+    }
+  }
+
+  /// Overridden walk settings by ego name, see `set_user_params`.
+  pub fn user_params(&self) -> Vec<OpSetUserParams> {
+    let default_alpha = self.mr.alpha;
+    self
+      .mr
+      .get_custom_ego_params()
+      .iter()
+      .filter_map(|(id, params)| {
+        let info = self.nodes.get_by_id(*id)?;
+        Some(OpSetUserParams {
+          ego:         info.name.clone(),
+          alpha:       (params.alpha != default_alpha).then_some(params.alpha),
+          walk_policy: if params.positive_only {
+            WalkPolicy::PositiveOnly
+          } else {
+            WalkPolicy::Default
+          },
+        })
+      })
+      .collect()
+  }
+}
@@ -0,0 +1,203 @@
+//! Token-based access control. Each token maps to a set of permitted contexts and
+//! to read-only or read-write rights. An empty ACL disables authentication.
+
+use crate::data::{ServiceError, SubgraphName};
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessRights {
+  ReadOnly,
+  ReadWrite,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContextScope {
+  All,
+  Only(HashSet<SubgraphName>),
+}
+
+impl ContextScope {
+  fn contains(
+    &self,
+    context: &str,
+  ) -> bool {
+    match self {
+      ContextScope::All => true,
+      ContextScope::Only(set) => set.contains(context),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclEntry {
+  pub rights:   AccessRights,
+  pub contexts: ContextScope,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AccessControl {
+  entries: HashMap<String, AclEntry>,
+}
+
+impl AccessControl {
+  pub fn is_enabled(&self) -> bool {
+    !self.entries.is_empty()
+  }
+
+  pub fn insert(
+    &mut self,
+    token: String,
+    entry: AclEntry,
+  ) {
+    self.entries.insert(token, entry);
+  }
+
+  /// Returns the ACL entry for a known token.
+  pub fn authenticate(
+    &self,
+    token: &str,
+  ) -> Result<&AclEntry, ServiceError> {
+    self.entries.get(token).ok_or(ServiceError::Unauthorized)
+  }
+
+  /// Checks that the token may access the context, with write rights if `write` is set.
+  pub fn check(
+    &self,
+    token: &str,
+    context: &SubgraphName,
+    write: bool,
+  ) -> Result<(), ServiceError> {
+    //  The default context aggregates the edges of all contexts, so its scores
+    //  would leak those of contexts out of the token's scope.
+    if context.is_empty() {
+      return self.check_all(token, write);
+    }
+    let entry = self.authenticate(token)?;
+    if !entry.contexts.contains(context) {
+      return Err(ServiceError::Forbidden(context.clone()));
+    }
+    if write && entry.rights != AccessRights::ReadWrite {
+      return Err(ServiceError::Forbidden(context.clone()));
+    }
+    Ok(())
+  }
+
+  /// Checks that the token has unrestricted access to every context. Used for ops that
+  /// affect the whole service (reset, bulk load).
+  pub fn check_all(
+    &self,
+    token: &str,
+    write: bool,
+  ) -> Result<(), ServiceError> {
+    let entry = self.authenticate(token)?;
+    if entry.contexts != ContextScope::All
+      || (write && entry.rights != AccessRights::ReadWrite)
+    {
+      return Err(ServiceError::Forbidden("*".into()));
+    }
+    Ok(())
+  }
+}
+
+/// Parses `token:rights:contexts` entries separated by `;`.
+/// Rights are `ro` or `rw`; contexts are comma-separated names, or `*` for all.
+/// The default context is only open to tokens with `*`, see `check`.
+impl FromStr for AccessControl {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut acl = AccessControl::default();
+
+    for item in s.split(';').map(str::trim).filter(|x| !x.is_empty()) {
+      let parts: Vec<&str> = item.splitn(3, ':').collect();
+      if parts.len() != 3 || parts[0].is_empty() {
+        return Err(format!("Invalid ACL entry: {:?}", item));
+      }
+
+      let rights = match parts[1] {
+        "ro" => AccessRights::ReadOnly,
+        "rw" => AccessRights::ReadWrite,
+        x => return Err(format!("Invalid ACL rights: {:?}", x)),
+      };
+
+      let contexts = if parts[2] == "*" {
+        ContextScope::All
+      } else {
+        ContextScope::Only(parts[2].split(',').map(String::from).collect())
+      };
+
+      acl.insert(
+        parts[0].to_string(),
+        AclEntry {
+          rights,
+          contexts,
+        },
+      );
+    }
+
+    Ok(acl)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_and_check() {
+    let acl: AccessControl = "admin:rw:*;alice:rw:X;bob:ro:X".parse().unwrap();
+    assert!(acl.is_enabled());
+
+    assert!(acl.check_all("admin", true).is_ok());
+    assert!(acl.check("admin", &String::new(), true).is_ok());
+    assert!(acl.check("alice", &"X".into(), true).is_ok());
+    assert_eq!(
+      acl.check("alice", &"Y".into(), false),
+      Err(ServiceError::Forbidden("Y".into()))
+    );
+    assert!(acl.check("bob", &"X".into(), false).is_ok());
+    assert_eq!(
+      acl.check("bob", &"X".into(), true),
+      Err(ServiceError::Forbidden("X".into()))
+    );
+    assert!(acl.check_all("alice", true).is_err());
+    assert_eq!(
+      acl.check("eve", &"X".into(), false),
+      Err(ServiceError::Unauthorized)
+    );
+  }
+
+  #[test]
+  fn scoped_token_cannot_read_default_context() {
+    let acl: AccessControl = "admin:ro:*;alice:rw:,X".parse().unwrap();
+    assert!(acl.check("admin", &String::new(), false).is_ok());
+    assert_eq!(
+      acl.check("admin", &String::new(), true),
+      Err(ServiceError::Forbidden("*".into()))
+    );
+    //  Listing the default context does not open it to a scoped token.
+    assert_eq!(
+      acl.check("alice", &String::new(), false),
+      Err(ServiceError::Forbidden("*".into()))
+    );
+    assert_eq!(
+      acl.check("alice", &String::new(), true),
+      Err(ServiceError::Forbidden("*".into()))
+    );
+  }
+
+  #[test]
+  fn empty_acl_is_disabled() {
+    let acl: AccessControl = "".parse().unwrap();
+    assert!(!acl.is_enabled());
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert!("token:rx:*".parse::<AccessControl>().is_err());
+    assert!("token:rw".parse::<AccessControl>().is_err());
+    assert!(":rw:*".parse::<AccessControl>().is_err());
+  }
+}
@@ -0,0 +1,85 @@
+//! Sizes of the scores and walks caches of a context, see
+//! `MERITRANK_CACHE_MEMORY_BUDGET`.
+//!
+//! With a memory budget, half of it goes to the walks of calculated egos and
+//! half to cached scores, but neither cache is made larger than the context
+//! can fill: no more egos than nodes, no more scores than those of the egos
+//! kept. Sizes follow the node count rounded up to a power of two, so the
+//! caches are resized only when the graph doubles, or shrinks to a quarter.
+
+use crate::aug_graph::estimate_memory;
+use crate::settings::Settings;
+
+/// Contexts are sized for at least this many nodes.
+const MIN_SIZED_NODES: usize = 1024;
+
+/// Approximate memory of a cached score, in bytes.
+const SCORE_ENTRY_MEMORY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheSizes {
+  /// Node count the sizes are for, see `needs_resize`.
+  pub nodes:     usize,
+  pub scores:    usize,
+  pub walk_egos: usize,
+}
+
+fn sized_nodes(nodes: usize) -> usize {
+  nodes.max(MIN_SIZED_NODES).next_power_of_two()
+}
+
+/// Cache sizes for a context of `nodes` nodes. Only meaningful with
+/// `cache_memory_budget` set.
+pub fn auto_cache_sizes(
+  nodes: usize,
+  settings: &Settings,
+) -> CacheSizes {
+  let nodes = sized_nodes(nodes);
+  let half = settings.cache_memory_budget / 2;
+  let ego_memory = estimate_memory(0, 0, settings.num_walks).max(1);
+  let walk_egos = (half / ego_memory).clamp(1, nodes);
+  let scores = (half / SCORE_ENTRY_MEMORY).min(nodes * walk_egos).max(1);
+  CacheSizes {
+    nodes,
+    scores,
+    walk_egos,
+  }
+}
+
+/// Whether caches sized for `sized_for` nodes should be resized for a
+/// context of `nodes` nodes.
+pub fn needs_resize(
+  sized_for: usize,
+  nodes: usize,
+) -> bool {
+  let target = sized_nodes(nodes);
+  target > sized_for || target * 4 <= sized_for
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sizes_follow_node_count() {
+    let settings = Settings {
+      num_walks: 1000,
+      cache_memory_budget: 1 << 30,
+      ..Settings::default()
+    };
+    let small = auto_cache_sizes(10, &settings);
+    assert_eq!(small.nodes, MIN_SIZED_NODES);
+    assert_eq!(small.walk_egos, 1024);
+    assert_eq!(small.scores, 1024 * 1024);
+
+    //  Big contexts are bound by the budget.
+    let big = auto_cache_sizes(1_000_000, &settings);
+    assert_eq!(big.walk_egos, (1 << 29) / (1000 * 256));
+    assert_eq!(big.scores, (1 << 29) / SCORE_ENTRY_MEMORY);
+
+    assert!(!needs_resize(small.nodes, 1000));
+    assert!(needs_resize(small.nodes, 1025));
+    assert!(!needs_resize(4096, 1500));
+    assert!(needs_resize(4096, 1000));
+  }
+}
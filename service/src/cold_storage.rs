@@ -0,0 +1,211 @@
+//! On-disk storage for evicted (idle) contexts.
+//!
+//! Only the context-specific state is stored: non-user edges, zero opinions and
+//! the number of score clusters.
+//! User-to-user edges are shared by all contexts and are re-seeded from the
+//! aggregate on reload, so writes that happened while a context was evicted
+//! are not lost. Walks are not stored; they are recalculated lazily on read.
+
+use crate::aug_graph::AugGraph;
+use crate::data::*;
+use crate::node_registry::*;
+use crate::settings::Settings;
+use crate::utils::files::{context_file, write_atomic};
+use crate::utils::log::*;
+
+use bincode::{config::standard, decode_from_slice, encode_to_vec, Decode, Encode};
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const EXTENSION: &str = "ctx";
+
+#[derive(Encode, Decode)]
+struct ColdContext {
+  edges:        Vec<OpWriteEdge>,
+  zero_opinion: Vec<(NodeName, Weight)>,
+  num_clusters: usize,
+  user_params:  Vec<OpSetUserParams>,
+  nodes:        Vec<OpWriteNode>,
+  mutes:        Vec<OpWriteMute>,
+  polls:        Vec<PollSnapshot>,
+}
+
+pub struct ColdStorage {
+  dir:           PathBuf,
+  pub evictions: AtomicU64,
+  pub reloads:   AtomicU64,
+}
+
+fn to_io_error<E: std::fmt::Display>(e: E) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl ColdStorage {
+  pub fn new(dir: PathBuf) -> io::Result<Self> {
+    fs::create_dir_all(&dir)?;
+    Ok(ColdStorage {
+      dir,
+      evictions: AtomicU64::new(0),
+      reloads: AtomicU64::new(0),
+    })
+  }
+
+  fn path(
+    &self,
+    context: &SubgraphName,
+  ) -> PathBuf {
+    context_file(&self.dir, context, EXTENSION)
+  }
+
+  pub fn contains(
+    &self,
+    context: &SubgraphName,
+  ) -> bool {
+    self.path(context).exists()
+  }
+
+  pub fn save(
+    &self,
+    context: &SubgraphName,
+    aug_graph: &AugGraph,
+  ) -> io::Result<()> {
+    let edges = aug_graph
+      .read_edges()
+      .into_iter()
+      .filter(|edge| {
+        node_kind_from_prefix(&edge.src) != Some(NodeKind::User)
+          || node_kind_from_prefix(&edge.dst) != Some(NodeKind::User)
+      })
+      .map(|edge| OpWriteEdge {
+        src:        edge.src,
+        dst:        edge.dst,
+        amount:     edge.weight,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      })
+      .collect();
+
+    let bytes = encode_to_vec(
+      ColdContext {
+        edges,
+        zero_opinion: aug_graph.zero_opinions(),
+        num_clusters: aug_graph.settings.num_score_quantiles,
+        user_params:  aug_graph.user_params(),
+        nodes:        aug_graph.written_nodes(),
+        mutes:        aug_graph.mutes(),
+        polls:        aug_graph.polls(),
+      },
+      standard(),
+    )
+    .map_err(to_io_error)?;
+
+    write_atomic(&self.path(context), &bytes)?;
+
+    self.evictions.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+  }
+
+  /// Rebuilds the context graph without user-to-user edges; the caller seeds them.
+  pub fn load(
+    &self,
+    context: &SubgraphName,
+    settings: &Settings,
+  ) -> io::Result<AugGraph> {
+    let bytes = fs::read(self.path(context))?;
+    let (cold, _): (ColdContext, usize) =
+      decode_from_slice(&bytes, standard()).map_err(to_io_error)?;
+
+    let mut aug_graph = AugGraph::new(Settings {
+      num_score_quantiles: cold.num_clusters,
+      ..settings.clone()
+    });
+    for data in &cold.nodes {
+      aug_graph.write_node(data);
+    }
+    aug_graph.bulk_load_edges(cold.edges);
+    aug_graph.set_zero_opinions(cold.zero_opinion);
+    for data in &cold.user_params {
+      aug_graph.set_user_params(data);
+    }
+    for data in &cold.mutes {
+      aug_graph.set_mute(data);
+    }
+    for data in &cold.polls {
+      aug_graph.restore_poll(data);
+    }
+    aug_graph.fit_caches();
+
+    self.reloads.fetch_add(1, Ordering::Relaxed);
+    Ok(aug_graph)
+  }
+
+  pub fn remove(
+    &self,
+    context: &SubgraphName,
+  ) -> bool {
+    fs::remove_file(self.path(context)).is_ok()
+  }
+
+  /// Removes all stored contexts.
+  pub fn clear(&self) {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(x) => x,
+      Err(e) => {
+        log_error!("Failed to read cold storage dir: {}", e);
+        return;
+      },
+    };
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if path.extension().and_then(|x| x.to_str()) == Some(EXTENSION) {
+        if let Err(e) = fs::remove_file(&path) {
+          log_error!("Failed to remove {:?}: {}", path, e);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+      .join(format!("meritrank-cold-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+  }
+
+  #[test]
+  fn save_and_load_skip_user_edges() {
+    let storage = ColdStorage::new(temp_dir("roundtrip")).unwrap();
+    let settings = Settings::default();
+    let context: SubgraphName = "ctx/1".into();
+
+    let mut aug_graph = AugGraph::new(settings.clone());
+    aug_graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+    aug_graph.set_edge("B1".into(), "U2".into(), 2.0, 0);
+
+    assert!(!storage.contains(&context));
+    storage.save(&context, &aug_graph).unwrap();
+    assert!(storage.contains(&context));
+
+    let loaded = storage.load(&context, &settings).unwrap();
+    let edges: Vec<_> = loaded
+      .read_edges()
+      .into_iter()
+      .map(|e| (e.src, e.dst, e.weight))
+      .collect();
+    assert_eq!(edges, vec![("B1".to_string(), "U2".to_string(), 2.0)]);
+    assert_eq!(storage.evictions.load(Ordering::Relaxed), 1);
+    assert_eq!(storage.reloads.load(Ordering::Relaxed), 1);
+
+    storage.clear();
+    assert!(!storage.contains(&context));
+  }
+}
@@ -7,13 +7,20 @@ pub const NEIGHBORS_OUTBOUND: i64 = 1;
 pub const NEIGHBORS_INBOUND: i64 = 2;
 use serde::{Deserialize, Serialize};
 
+use crate::op_reply::OpReply;
+use crate::publish_barrier::PublishGate;
+
+use std::time::Duration;
+
 pub type NodeName = String;
 pub type NodeScore = f64;
 pub use meritrank_core::{NodeId, Weight};
 pub type NodeCluster = usize;
 pub type SubgraphName = String;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Encode, Decode, Hash)]
+#[derive(
+  Debug, PartialEq, Eq, Clone, Copy, Encode, Decode, Hash, Serialize, Deserialize,
+)]
 pub enum NodeKind {
   User,
   Beacon,
@@ -21,47 +28,161 @@ pub enum NodeKind {
   Opinion,
   PollVariant,
   Poll,
+  /// Kind registered at startup, identified by its name prefix. Behaves like
+  /// a beacon: it can be owned by the users it has edges to.
+  Custom(char),
+}
+
+/// Score the thresholds of `FilterOptions` are evaluated on.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize,
+)]
+pub enum ScoreComponent {
+  /// The ego's own score, before blending with the zero opinion.
+  Personal,
+  /// The zero opinion of the context.
+  ZeroOpinion,
+  /// The returned score, blended with the zero opinion.
+  #[default]
+  Blended,
+}
+
+/// What an edge stands for. Amounts of edges are multiplied by the factor of
+/// their kind, see `MERITRANK_EDGE_KIND_WEIGHTS`.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize,
+)]
+pub enum EdgeKind {
+  #[default]
+  Vote,
+  Follow,
+  Flag,
+}
+
+/// Parses `vote`, `follow` or `flag`.
+pub fn edge_kind_from_name(name: &str) -> Option<EdgeKind> {
+  match name {
+    "vote" => Some(EdgeKind::Vote),
+    "follow" => Some(EdgeKind::Follow),
+    "flag" => Some(EdgeKind::Flag),
+    _ => None,
+  }
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct FilterOptions {
-  pub node_kind:     Option<NodeKind>,
-  pub hide_personal: bool,
-  pub score_lt:      f64,
-  pub score_lte:     bool,
-  pub score_gt:      f64,
-  pub score_gte:     bool,
-  pub index:         u32,
-  pub count:         u32,
+  pub node_kind:              Option<NodeKind>,
+  pub hide_personal:          bool,
+  pub score_lt:               f64,
+  pub score_lte:              bool,
+  pub score_gt:               f64,
+  pub score_gte:              bool,
+  pub index:                  u32,
+  pub count:                  u32,
+  /// Number of score clusters to bucket into; only coarser than the context
+  /// setting takes effect. 0 means the context setting.
+  pub num_clusters:           u32,
+  /// Inclusive range of clusters to return, e.g. the top 3 of 10 clusters
+  /// are `8..=10`.
+  pub cluster_min:            u32,
+  pub cluster_max:            u32,
+  /// Score that `score_lt` and `score_gt` apply to.
+  pub filter_on:              ScoreComponent,
+  /// Only nodes the ego's walks penalize more than they reward, e.g. for
+  /// blocklists.
+  pub include_negative_only:  bool,
+  /// Sort by score, lowest first, rather than by absolute score, highest
+  /// first.
+  pub ascending:              bool,
+  /// Only nodes with none of these flags set, see `OpWriteNode`.
+  pub exclude_flags:          u32,
+  /// Only nodes with all of these flags set.
+  pub require_flags:          u32,
+  /// Time the ego's walks are given if it is not calculated yet, in
+  /// milliseconds. If they are not done by then, the scores of the walks
+  /// done so far are returned, marked `approximate`. 0 means no limit.
+  pub max_latency_ms:         u64,
+  /// Fill in `reverse_score` and `reverse_cluster`, the scores of the ego by
+  /// the owners of the targets. Only read for the page returned, and only
+  /// from owners already calculated, but each is a read of another ego's
+  /// walks, so they are left 0 unless asked for.
+  pub include_reverse_scores: bool,
 }
 
 impl Default for FilterOptions {
   fn default() -> Self {
     Self {
-      node_kind:     None,
-      hide_personal: false,
-      score_lt:      f64::MAX,
-      score_lte:     true,
-      score_gt:      f64::MIN,
-      score_gte:     true,
-      index:         0,
-      count:         u32::MAX,
+      node_kind:              None,
+      hide_personal:          false,
+      score_lt:               f64::MAX,
+      score_lte:              true,
+      score_gt:               f64::MIN,
+      score_gte:              true,
+      index:                  0,
+      count:                  u32::MAX,
+      num_clusters:           0,
+      cluster_min:            0,
+      cluster_max:            u32::MAX,
+      filter_on:              ScoreComponent::Blended,
+      include_negative_only:  false,
+      ascending:              false,
+      exclude_flags:          0,
+      require_flags:          0,
+      max_latency_ms:         0,
+      include_reverse_scores: false,
     }
   }
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpReadScores {
   pub ego:           NodeName,
   pub score_options: FilterOptions,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+/// Same as `OpReadScores`, but the scores are sent as `ScoresChunk`
+/// responses of at most `chunk_size` scores each.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadScoresChunked {
+  pub ego:           NodeName,
+  pub score_options: FilterOptions,
+  pub chunk_size:    u32,
+}
+
+/// Scores of the ego as of a Unix time in seconds, see `MERITRANK_EDGE_LOG_SIZE`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadScoresAt {
+  pub ego:           NodeName,
+  pub timestamp:     u64,
+  pub score_options: FilterOptions,
+}
+
+/// Scores of the ego before and after the edge writes, without applying
+/// them, e.g. to preview a moderation action.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadDryRun {
+  pub ego:           NodeName,
+  pub edges:         Vec<OpWriteEdge>,
+  pub score_options: FilterOptions,
+}
+
+/// Scores of several egos with the same options, e.g. for one feed page.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadScoresBulk {
+  pub egos:          Vec<NodeName>,
+  pub score_options: FilterOptions,
+  /// Answer with `Response::ScoresCompact` instead of `ScoresBulk`.
+  pub compact:       bool,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpReadNeighbors {
   pub ego:           NodeName,
   pub focus:         NodeName,
   pub direction:     i64,
   pub kind:          Option<NodeKind>,
+  /// Only edges of the kind, if set.
+  pub edge_kind:     Option<EdgeKind>,
   pub hide_personal: bool,
   pub lt:            Weight,
   pub lte:           bool,
@@ -71,15 +192,44 @@ pub struct OpReadNeighbors {
   pub count:         u32,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+/// Event an edge was written for, kept to trace disputed edges back to it.
+#[derive(
+  Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize,
+)]
+pub struct EdgeProvenance {
+  /// System the edge comes from, e.g. the name of the app.
+  pub source:      String,
+  /// Id of the vote or event in that system.
+  pub external_id: String,
+  /// Unix time of the event in seconds.
+  pub timestamp:   u64,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpWriteEdge {
-  pub src:       NodeName,
-  pub dst:       NodeName,
-  pub amount:    Weight,
-  pub magnitude: u32,
+  pub src:        NodeName,
+  pub dst:        NodeName,
+  pub amount:     Weight,
+  pub magnitude:  u32,
+  pub edge_kind:  EdgeKind,
+  /// Replaces the provenance of the edge; a write without one clears it.
+  pub provenance: Option<EdgeProvenance>,
+}
+
+/// Sets the edge to `new_weight` only if its weight is `expected_old_weight`
+/// (0 for a missing edge), so that concurrent writes are detected rather
+/// than overwritten. Both weights are before the factor of `edge_kind`, as
+/// in `WriteEdge`, and an existing edge of another kind is a conflict.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteEdgeCas {
+  pub src:                 NodeName,
+  pub dst:                 NodeName,
+  pub expected_old_weight: Weight,
+  pub new_weight:          Weight,
+  pub edge_kind:           EdgeKind,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct BulkEdge {
   pub src:       NodeName,
   pub dst:       NodeName,
@@ -88,23 +238,178 @@ pub struct BulkEdge {
   pub context:   SubgraphName,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpWriteBulkEdges {
   pub edges: Vec<BulkEdge>,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpWriteCalculate {
   pub ego: NodeName,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+/// Drops the walks and cached scores of the ego and calculates it again.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpRecalculateEgo {
+  pub ego: NodeName,
+}
+
+/// Recalculates the egos of every context whose edges changed beyond
+/// `rerank_threshold`, or of every context with `force`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpRerank {
+  pub force: bool,
+}
+
+/// Webhook that score changes of the egos are POSTed to as JSON digests,
+/// see `webhooks`. Replaces the webhook with the same URL.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteWebhook {
+  /// `http://` URL.
+  pub url:        String,
+  /// Context to watch; all contexts if None.
+  pub context:    Option<SubgraphName>,
+  /// Egos to watch; all calculated egos if empty.
+  pub egos:       Vec<NodeName>,
+  /// Changes of a score smaller than that since it was last reported are
+  /// not reported.
+  pub min_change: NodeScore,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpDeleteWebhook {
+  pub url: String,
+}
+
+/// Targets whose score or cluster by the ego changed between two generations
+/// of its scores, see `MERITRANK_SCORE_SNAPSHOTS`. `from` or `to` of 0 stands
+/// for the current generation.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadScoreDiff {
+  pub ego:        NodeName,
+  pub from:       u64,
+  pub to:         u64,
+  /// Score changes smaller than that are not reported, unless the cluster
+  /// changed too.
+  pub min_change: NodeScore,
+}
+
+/// Latest requests of `ReadSlowLog`, newest first; 0 for all of them.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadSlowLog {
+  pub limit: u32,
+}
+
+/// Creates `destination` as a copy of the current state of `source`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteForkContext {
+  pub source:      SubgraphName,
+  pub destination: SubgraphName,
+}
+
+/// How to combine weights when an edge exists in both contexts of a merge.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize,
+)]
+pub enum MergeStrategy {
+  Sum,
+  Max,
+  Overwrite,
+}
+
+impl MergeStrategy {
+  pub fn resolve(
+    &self,
+    existing: Weight,
+    incoming: Weight,
+  ) -> Weight {
+    match self {
+      MergeStrategy::Sum => existing + incoming,
+      MergeStrategy::Max => existing.max(incoming),
+      MergeStrategy::Overwrite => incoming,
+    }
+  }
+}
+
+/// Moves the context to the shard with the given address, see `router`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpMoveContext {
+  pub shard: String,
+}
+
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize,
+)]
+pub enum ExportFormat {
+  Csv,
+  /// Needs the `parquet` feature.
+  Parquet,
+}
+
+/// Writes the scores by the egos (all egos of the context if empty) to
+/// `destination`, a path relative to `MERITRANK_EXPORT_DIR`, see `export`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpExportScores {
+  pub egos:        Vec<NodeName>,
+  pub format:      ExportFormat,
+  pub destination: String,
+}
+
+/// Replaces all contexts with the edges of the CSV or JSONL file at `path`,
+/// relative to `MERITRANK_IMPORT_DIR`, see `file_import`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpImportEdgeList {
+  pub path: String,
+}
+
+/// With `read_only` set, writes are rejected with a `Maintenance` error
+/// until it is cleared, e.g. during an export or a shard move.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpSetMaintenanceMode {
+  pub read_only: bool,
+}
+
+/// Write `data` that is applied once per `key` within
+/// `MERITRANK_IDEMPOTENCY_WINDOW`: retries with the same key get the response
+/// of the first request without being applied again, see `idempotency`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpIdempotent {
+  pub key:  String,
+  pub data: Box<ReqData>,
+}
+
+/// Edge writes (`WriteEdge` or `WriteDeleteEdge`) to several contexts,
+/// applied together: if any part is rejected, none is applied. Each context
+/// publishes its parts in one step, see `process_write_multi`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteMulti {
+  pub ops: Vec<(SubgraphName, ReqData)>,
+}
+
+/// Folds the edges of `source` into `destination`. With `dry_run` set, only
+/// reports the number of conflicting edges.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteMergeContext {
+  pub source:      SubgraphName,
+  pub destination: SubgraphName,
+  pub strategy:    MergeStrategy,
+  pub dry_run:     bool,
+}
+
+/// Removes nodes with no edges and no walks of the context. With `dry_run`
+/// set, only reports their number.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteGcOrphans {
+  pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpReadNodeScore {
   pub ego:    NodeName,
   pub target: NodeName,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpReadGraph {
   pub ego:           NodeName,
   pub focus:         NodeName,
@@ -113,53 +418,238 @@ pub struct OpReadGraph {
   pub count:         u64,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpReadConnected {
   pub node: NodeName,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpReadMutualScores {
   pub ego: NodeName,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+/// Nodes of the kind scored highly by egos similar to the ego.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadRecommendations {
+  pub ego:   NodeName,
+  pub kind:  Option<NodeKind>,
+  pub limit: u32,
+}
+
+/// Nodes of the context with suspicious edge patterns, for moderators.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadAnomalies {
+  /// Edges added in the last that many seconds count as a burst.
+  pub window: u64,
+  pub limit:  u32,
+}
+
+/// Share of the top scores of sampled honest users captured by the
+/// suspected sybils, with and without their attack edges.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpEvaluateSybilResistance {
+  pub suspects: Vec<NodeName>,
+  /// Number of users outside the suspects sampled as egos.
+  pub egos:     u32,
+  pub top_k:    u32,
+}
+
+/// Comments of a beacon ranked for the ego. The score of a comment blends
+/// the ego's scores of the beacon, the comment and the comment's author.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadBeaconComments {
+  pub ego:            NodeName,
+  pub beacon:         NodeName,
+  pub comments:       Vec<NodeName>,
+  pub beacon_weight:  f64,
+  pub comment_weight: f64,
+  pub author_weight:  f64,
+  pub limit:          u32,
+}
+
+/// Nodes of the context with the highest zero opinion, or with the highest
+/// scores of the ego blended with it, see `MERITRANK_TOP_NODES_LIMIT`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadTopNodes {
+  pub ego:   Option<NodeName>,
+  pub kind:  Option<NodeKind>,
+  pub index: u32,
+  pub limit: u32,
+}
+
+/// Raw walk data of the ego, with at most `walks` sampled walk paths. Only
+/// served with `MERITRANK_DEBUG_OPS`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpDebugEgo {
+  pub ego:   NodeName,
+  pub walks: u32,
+}
+
+/// Users trusted by the ego's peers that the ego has no edge to.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadMutualSuggestions {
+  pub ego:   NodeName,
+  pub limit: u32,
+}
+
+/// Recorded scores of the target for the ego, see `MERITRANK_HISTORY_SIZE`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadScoreHistory {
+  pub ego:    NodeName,
+  pub target: NodeName,
+}
+
+/// Nodes whose scores differ most between two egos.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadCompareEgos {
+  pub a:     NodeName,
+  pub b:     NodeName,
+  pub limit: u32,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpReadNewEdgesFilter {
   pub src: NodeName,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpWriteZeroOpinion {
   pub node:  NodeName,
   pub score: Weight,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+/// How walks of an ego treat negative edges.
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  Default,
+  PartialEq,
+  Eq,
+  Encode,
+  Decode,
+  Serialize,
+  Deserialize,
+)]
+pub enum WalkPolicy {
+  /// Walks follow one negative edge, then only positive ones.
+  #[default]
+  Default,
+  /// Walks do not follow negative edges, so the ego sees no distrust.
+  PositiveOnly,
+}
+
+/// Walk settings of the ego's own scores in the context. Without `alpha` and
+/// with the default policy, the ego uses the defaults again.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpSetUserParams {
+  pub ego:         NodeName,
+  /// Probability of walks continuing at each step, in `[0, 1]`.
+  pub alpha:       Option<Weight>,
+  pub walk_policy: WalkPolicy,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteScoreClusters {
+  pub num_clusters: u32,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpWriteDeleteEdge {
   pub src:   NodeName,
   pub dst:   NodeName,
   pub index: i64,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+/// Creates the node ahead of its edges, or sets the flags of an existing one.
+/// The kind overrides the one of the name prefix; an existing node keeps its
+/// kind.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteNode {
+  pub name:  NodeName,
+  pub kind:  NodeKind,
+  /// Application-defined bits, e.g. NSFW; ignored by the ranking.
+  pub flags: u32,
+}
+
+/// Hides `node` from the scores read by `ego` in the context, or shows it
+/// again with `muted` unset. Unlike a negative edge, a mute does not change
+/// the walks, so the scores of other egos are not affected.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteMute {
+  pub ego:   NodeName,
+  pub node:  NodeName,
+  pub muted: bool,
+}
+
+/// Nodes muted by the ego in the context, see `WriteMute`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpReadMutes {
+  pub ego: NodeName,
+}
+
+/// Sets the unix time from which votes for the poll are rejected and after
+/// which it is archived with its results frozen; 0 for a poll that never
+/// expires. Votes of an anonymous poll are only kept as a bloom filter of the
+/// voters and a weighted tally per option, so they can't be listed.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWritePoll {
+  pub poll:       NodeName,
+  pub expires_at: u64,
+  pub anonymous:  bool,
+}
+
+/// Votes for an option of an anonymous poll, see `OpWritePoll`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct PollTally {
+  pub option:     NodeName,
+  /// Bloom filter of the hashed names of the voters.
+  pub filter:     Vec<u64>,
+  pub num_hashes: u64,
+  pub count:      u64,
+  /// Sum of the vote weights times the zero opinions of the voters.
+  pub score:      Weight,
+}
+
+/// Poll of a context with its options and votes, see `WritePoll`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct PollSnapshot {
+  pub poll:       NodeName,
+  pub options:    Vec<NodeName>,
+  /// Voter, option and weight of each vote.
+  pub votes:      Vec<(NodeName, NodeName, Weight)>,
+  pub expires_at: u64,
+  pub anonymous:  bool,
+  pub tallies:    Vec<PollTally>,
+  /// Option, share and number of votes frozen when the poll was archived.
+  pub results:    Option<Vec<(NodeName, Weight, u64)>>,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpWriteDeleteNode {
   pub node:  NodeName,
   pub index: i64,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct OpWriteAliasNode {
+  pub old: NodeName,
+  pub new: NodeName,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpWriteNewEdgesFilter {
   pub src:    NodeName,
   pub filter: Vec<u8>,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct OpWriteFetchNewEdges {
   pub src:    NodeName,
   pub prefix: NodeName,
 }
 
-#[derive(Debug, Encode, Decode, Clone)]
+#[derive(Debug, Encode, Decode, Clone, Serialize, Deserialize)]
 pub enum AugGraphOp {
   WriteEdge(OpWriteEdge),
   BulkLoadEdges(Vec<OpWriteEdge>),
@@ -170,6 +660,121 @@ pub enum AugGraphOp {
   ClearEgo(NodeId),
   DeleteNode(NodeName),
   Stamp(u64),
+  WriteScoreClusters(OpWriteScoreClusters),
+  AliasNode(OpWriteAliasNode),
+  /// Multiplies weights of all edges by the factor, see `decay_edges`.
+  DecayEdges(f64),
+  RemoveOrphans(Vec<NodeName>),
+  /// Calculates the ego if it has no walks yet, giving up after that many
+  /// milliseconds, see `calculate_within`.
+  CalculateWithin(NodeName, u64),
+  /// Same as `CalculateWithin`, but keeps the scores of the walks done so
+  /// far, see `calculate_partial`.
+  CalculatePartial(NodeName, u64),
+  SetUserParams(OpSetUserParams),
+  /// Calculates again every ego that has walks, see `recalculate_all`.
+  RecalculateAll,
+  WriteNode(OpWriteNode),
+  /// Answers whether the edge was set, see `set_edge_cas`.
+  WriteEdgeCas(OpWriteEdgeCas, OpReply),
+  /// Ops applied one after another in the same publish.
+  Batch(Vec<AugGraphOp>),
+  /// The part of a context in a `WriteMulti`: staged like a `Batch`, then
+  /// published together with the other contexts, or dropped by all of them,
+  /// see `PublishBarrier`.
+  Staged(Vec<AugGraphOp>, PublishGate),
+  Mute(OpWriteMute),
+  WritePoll(OpWritePoll),
+  /// Archives the polls expired by the given unix time, see `archive_polls`.
+  ArchivePolls(u64),
+}
+
+impl AugGraphOp {
+  /// Ops that change the graph state and are sent to replicas. Calculations
+  /// and stamps only concern the local copy.
+  pub fn is_replicated(&self) -> bool {
+    use AugGraphOp::*;
+    if let Batch(ops) | Staged(ops, _) = self {
+      return ops.iter().any(|op| op.is_replicated());
+    }
+    matches!(
+      self,
+      WriteEdge(_)
+        | BulkLoadEdges(_)
+        | WriteZeroOpinion(_)
+        | WriteReset
+        | DeleteNode(_)
+        | WriteScoreClusters(_)
+        | AliasNode(_)
+        | DecayEdges(_)
+        | RemoveOrphans(_)
+        | SetUserParams(_)
+        | WriteNode(_)
+        | WriteEdgeCas(..)
+        | Mute(_)
+        | WritePoll(_)
+        | ArchivePolls(_)
+    )
+  }
+}
+
+/// Change of the state of a context, numbered and sent to replicas.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub enum ReplicationRecord {
+  Op(AugGraphOp),
+  CreateContext,
+  /// The context is created as a copy of the given one.
+  Fork(SubgraphName),
+  DeleteContext,
+  /// All contexts are removed, on reset and before a bulk load.
+  Reset,
+  /// The context is replaced with the snapshot.
+  Snapshot(ContextSnapshot),
+  /// Whether the staged parts of the `WriteMulti` with the given number are
+  /// published, see `PublishGate`.
+  StagedOutcome(u64, bool),
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct ReplicationEntry {
+  pub seq:      u64,
+  pub subgraph: SubgraphName,
+  pub record:   ReplicationRecord,
+}
+
+/// State of a context without walks, enough to rebuild it on a replica.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct ContextSnapshot {
+  pub context:      SubgraphName,
+  pub edges:        Vec<OpWriteEdge>,
+  pub zero_opinion: Vec<(NodeName, Weight)>,
+  pub num_clusters: usize,
+  pub user_params:  Vec<OpSetUserParams>,
+  /// Nodes without edges, or with a kind or flags set by `WriteNode`.
+  pub nodes:        Vec<OpWriteNode>,
+  pub mutes:        Vec<OpWriteMute>,
+  pub polls:        Vec<PollSnapshot>,
+}
+
+/// State of all contexts that includes the entries up to `seq`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct ReplicationSnapshot {
+  pub seq:      u64,
+  pub contexts: Vec<ContextSnapshot>,
+}
+
+/// First message of a replica. `next_seq` is the seq of the first entry it
+/// needs, or 0 if it needs a snapshot.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct ReplicationHello {
+  pub next_seq: u64,
+}
+
+/// Message of the primary to a replica.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub enum ReplicationMessage {
+  Snapshot(ReplicationSnapshot),
+  Entry(ReplicationEntry),
 }
 
 #[derive(Debug, Encode, Decode, Serialize, Deserialize)]
@@ -180,6 +785,30 @@ pub struct ScoreResult {
   pub reverse_score:   NodeScore,
   pub cluster:         NodeCluster,
   pub reverse_cluster: NodeCluster,
+  pub kind:            NodeKind,
+  /// 1-based position in the filtered and sorted results.
+  pub rank:            u32,
+  /// Approximate share of nodes of the same kind with a lower score, in
+  /// percent, interpolated between the cluster bounds.
+  pub percentile:      f64,
+  /// Score or reverse score was cached before the walks changed, see
+  /// `MERITRANK_SERVE_STALE_SCORES`.
+  pub stale:           bool,
+  /// Score is taken from part of the ego's walks, see
+  /// `FilterOptions::max_latency_ms`.
+  pub approximate:     bool,
+  /// The ego has no edges in the context, and the scores are those of its
+  /// house ego, see `MERITRANK_HOUSE_EGOS`.
+  pub fallback:        bool,
+  /// The ego has no walks, and the scores are those of a calculated ego with
+  /// nearly the same edges, see `MERITRANK_SHARED_SCORES_SIMILARITY`.
+  pub shared:          bool,
+  /// Number of walks the scores of the ego are taken from, less than
+  /// `MERITRANK_NUM_WALKS` for approximate scores, see `walk_count`.
+  pub walks:           u64,
+  /// Provenance of the edge between the focus and the target, only set by
+  /// `ReadNeighbors`.
+  pub provenance:      Option<EdgeProvenance>,
 }
 
 #[derive(Debug, Encode, Decode, Serialize, Deserialize)]
@@ -220,6 +849,51 @@ pub struct ResScores {
   pub scores: Vec<ScoreResult>,
 }
 
+/// Part of the scores of `ReadScoresChunked`, in order. `more` is unset in
+/// the last chunk.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResScoresChunk {
+  pub scores: Vec<ScoreResult>,
+  pub more:   bool,
+}
+
+/// Scores grouped per ego, in the order of the request.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResScoresBulk {
+  pub scores: Vec<(NodeName, Vec<ScoreResult>)>,
+}
+
+/// Same as `ScoreResult`, with the target given by id, see `ResScoresCompact`.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct CompactScoreResult {
+  pub target:          NodeId,
+  pub score:           NodeScore,
+  pub reverse_score:   NodeScore,
+  pub cluster:         NodeCluster,
+  pub reverse_cluster: NodeCluster,
+  pub rank:            u32,
+  pub percentile:      f64,
+  pub stale:           bool,
+  pub approximate:     bool,
+  pub walks:           u64,
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct CompactNode {
+  pub id:   NodeId,
+  pub name: NodeName,
+  pub kind: NodeKind,
+}
+
+/// Same as `ResScoresBulk`, with targets given by id. Every target is
+/// resolved once in `nodes`, sorted by id, however many egos scored it.
+/// Ids are only meaningful within the response.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResScoresCompact {
+  pub nodes:  Vec<CompactNode>,
+  pub scores: Vec<(NodeName, Vec<CompactScoreResult>)>,
+}
+
 #[derive(Debug, Encode, Decode, Serialize, Deserialize)]
 pub struct ResNodeList {
   pub nodes: Vec<(NodeName,)>,
@@ -250,19 +924,305 @@ pub struct ResNewEdges {
   pub new_edges: Vec<NewEdgeResult>,
 }
 
-/// Stats snapshot returned by GetStats (same shape as ProcessorStats snapshot).
+/// Result of a context merge: edges present in both contexts, and edges written
+/// (or that would be written, for a dry run) to the destination.
+#[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ResMerge {
+  pub conflicts: usize,
+  pub merged:    usize,
+}
+
+/// Result of orphan collection: orphan nodes found (and removed, unless it
+/// is a dry run), and nodes of the context before the collection.
+#[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ResGcOrphans {
+  pub orphans: usize,
+  pub nodes:   usize,
+}
+
+/// Result of a forced recalculation: egos recalculated, and milliseconds
+/// until their new walks were published.
+#[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ResRecalculation {
+  pub egos:       usize,
+  pub elapsed_ms: u64,
+}
+
+/// Contexts whose egos were recalculated by `Rerank`.
+#[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ResRerank {
+  pub contexts: Vec<SubgraphName>,
+}
+
+#[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ResWebhooks {
+  pub webhooks: Vec<OpWriteWebhook>,
+}
+
+/// Score and cluster of the target at both generations of `ReadScoreDiff`;
+/// 0 where the target is missing from the scores.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct ScoreDiff {
+  pub target:      NodeName,
+  pub kind:        NodeKind,
+  pub old_score:   NodeScore,
+  pub new_score:   NodeScore,
+  pub old_cluster: NodeCluster,
+  pub new_cluster: NodeCluster,
+}
+
+#[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ResScoreDiff {
+  pub nodes:      Vec<ScoreDiff>,
+  /// Generation of the new scores, to pass as `from` next time.
+  pub generation: u64,
+}
+
+/// Request that took longer than its threshold, see
+/// `MERITRANK_SLOW_OP_THRESHOLD`.
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct SlowOp {
+  /// Unix time the request finished at, in seconds.
+  pub time:            u64,
+  pub context:         SubgraphName,
+  pub opcode:          String,
+  /// The request data as `{:?}`, cut at `MAX_SLOW_OP_REQUEST_LEN` chars.
+  pub request:         String,
+  /// `ok`, `fail` or the error.
+  pub outcome:         String,
+  pub total_us:        u64,
+  /// Time spent calculating the egos the request reads.
+  pub calculate_us:    u64,
+  /// Egos whose walks were in memory, and egos calculated for the request
+  /// with the walks run for them.
+  pub egos_cached:     u64,
+  pub egos_calculated: u64,
+  pub walks:           u64,
+}
+
+/// Longest request data kept in a `SlowOp`, in chars.
+pub const MAX_SLOW_OP_REQUEST_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ResSlowLog {
+  pub ops: Vec<SlowOp>,
+}
+
+/// Result of a scores export: egos exported and rows written.
+#[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ResExport {
+  pub egos: usize,
+  pub rows: usize,
+}
+
+/// Usage and limits of a context; limits of 0 are unlimited. Memory is a
+/// rough estimate, `total_memory` is that of all loaded contexts.
+#[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ResQuota {
+  pub nodes:        usize,
+  pub max_nodes:    usize,
+  pub edges:        usize,
+  pub max_edges:    usize,
+  pub memory:       usize,
+  pub total_memory: usize,
+  pub max_memory:   usize,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct ContextInfo {
+  pub name:        SubgraphName,
+  pub nodes:       usize,
+  pub edges:       usize,
+  /// Unix time in seconds of the last read or write to the context.
+  pub last_access: u64,
+  /// Walks run for reads within the period of the context's walk budget, 0
+  /// without a budget, see `MERITRANK_WALK_BUDGETS`.
+  pub walks:       u64,
+}
+
+/// Score of the target before and after a dry run; 0 where the target is
+/// missing from the scores.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ScoreDelta {
+  pub target: NodeName,
+  pub before: NodeScore,
+  pub after:  NodeScore,
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct EgoComparisonResult {
+  pub target:    NodeName,
+  pub score_a:   NodeScore,
+  pub score_b:   NodeScore,
+  pub cluster_a: NodeCluster,
+  pub cluster_b: NodeCluster,
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct RecommendationResult {
+  pub target: NodeName,
+  /// Average score of the node by the similar egos, weighted by similarity.
+  pub score:  NodeScore,
+  /// Number of similar egos that scored the node.
+  pub egos:   u32,
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResRecommendations {
+  pub nodes: Vec<RecommendationResult>,
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct TopNodeResult {
+  pub node:  NodeName,
+  pub kind:  NodeKind,
+  pub score: NodeScore,
+}
+
+/// Hit counts are sorted, highest first; names of deleted nodes are their
+/// ids.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResDebugEgo {
+  pub walks:         u32,
+  pub positive_hits: Vec<(NodeName, i64)>,
+  pub negative_hits: Vec<(NodeName, i64)>,
+  pub sample:        Vec<Vec<NodeName>>,
+}
+
+/// Sorted by score, highest first.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResTopNodes {
+  pub nodes: Vec<TopNodeResult>,
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct BeaconCommentResult {
+  pub comment:       NodeName,
+  /// None if the comment has no owner yet.
+  pub author:        Option<NodeName>,
+  /// Blend of the three scores below with the weights of the request.
+  pub score:         NodeScore,
+  pub comment_score: NodeScore,
+  pub author_score:  NodeScore,
+}
+
+/// Sorted by score, highest first.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResBeaconComments {
+  pub beacon_score: NodeScore,
+  pub comments:     Vec<BeaconCommentResult>,
+}
+
+/// Signals are in `[0, 1]`; the score is their average.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct AnomalyResult {
+  pub node:      NodeName,
+  pub score:     f64,
+  /// Share of the positive in-weight from nodes no calculated ego trusts.
+  pub low_trust: f64,
+  /// Share of outgoing edges returned by users the node scores alike.
+  pub ring:      f64,
+  /// Share of the inbound edges added within the window.
+  pub burst:     f64,
+}
+
+/// Sorted by score, highest first.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResAnomalies {
+  pub nodes: Vec<AnomalyResult>,
+}
+
+/// Shares are averaged over the egos with positive scores, in `[0, 1]`.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResSybilResistance {
+  /// Egos evaluated, and positive edges from other nodes to the suspects.
+  pub egos:         u32,
+  pub attack_edges: u32,
+  /// Share of the top scores going to the suspects with the attack edges.
+  pub before:       f64,
+  /// The same without the attack edges.
+  pub after:        f64,
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct MutualSuggestionResult {
+  pub target: NodeName,
+  /// Number of the ego's peers with a positive edge to the user.
+  pub peers:  u32,
+  /// Sum of the weights of those edges.
+  pub weight: Weight,
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResMutualSuggestions {
+  pub users: Vec<MutualSuggestionResult>,
+}
+
+/// Unix time in seconds and the score, oldest first.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResScoreHistory {
+  pub samples: Vec<(u64, NodeScore)>,
+}
+
+/// Sorted by the absolute score difference, largest first.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResEgoComparison {
+  pub nodes: Vec<EgoComparisonResult>,
+}
+
+/// Sorted by the absolute score change, largest first.
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
+pub struct ResDryRun {
+  pub nodes: Vec<ScoreDelta>,
+}
+
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+pub struct ResContexts {
+  pub contexts: Vec<ContextInfo>,
+}
+
+/// Stats snapshot returned by GetStats: ProcessorStats snapshot plus write queue depth.
 #[derive(Debug, Clone, Default, Encode, Decode, Serialize, Deserialize)]
 pub struct ResStats {
-  pub pending:   usize,
-  pub median_us: u64,
-  pub p95_us:    u64,
-  pub p99_us:    u64,
-  pub min_us:    u64,
-  pub max_us:    u64,
-  pub count:     usize,
+  pub pending:             usize,
+  pub median_us:           u64,
+  pub p95_us:              u64,
+  pub p99_us:              u64,
+  pub min_us:              u64,
+  pub max_us:              u64,
+  pub count:               usize,
+  /// Ops waiting in the fullest subgraph write queue.
+  pub queue_depth:         usize,
+  /// Per-subgraph write queue bound; writes get `Busy` once a queue is full.
+  pub queue_capacity:      usize,
+  /// Contexts moved to cold storage / loaded back from it since startup.
+  pub evictions:           u64,
+  pub reloads:             u64,
+  /// Stale cluster bounds waiting for the background worker, in all contexts.
+  pub cluster_queue_depth: usize,
+  /// Bytes saved by compressing responses since startup.
+  pub compression_saved:   u64,
+  /// Publishes of graph copies to readers, ops they carried, and the largest
+  /// batch (collected with stats only).
+  pub publishes:           u64,
+  pub published_ops:       u64,
+  pub max_publish_batch:   usize,
+  /// Ops that panicked in the writer and were skipped (collected with stats
+  /// only).
+  pub writer_panics:       u64,
+  /// Seq of the last replicated change, see `replication`.
+  pub replication_seq:     u64,
+  /// Rows read by the running file import and the share of the file read,
+  /// 0 when none runs, see `ImportEdgeList`.
+  pub import_rows:         u64,
+  pub import_progress:     f64,
+  /// Writes are rejected, see `SetMaintenanceMode`.
+  pub maintenance:         bool,
+  /// Calculations rejected for exceeding walk budgets since startup.
+  pub walk_rejections:     u64,
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub enum ReqData {
   ReadScores(OpReadScores),
   WriteEdge(OpWriteEdge),
@@ -291,36 +1251,347 @@ pub enum ReqData {
   WriteCreateContext,
   WriteNewEdgesFilter(OpWriteNewEdgesFilter),
   WriteFetchNewEdges(OpWriteFetchNewEdges),
+
+  WriteForkContext(OpWriteForkContext),
+  WriteMergeContext(OpWriteMergeContext),
+  ReadContexts,
+  WriteDeleteContext,
+  WriteScoreClusters(OpWriteScoreClusters),
+  WriteAliasNode(OpWriteAliasNode),
+  ReadScoresBulk(OpReadScoresBulk),
+  ReadCompareEgos(OpReadCompareEgos),
+  ReadRecommendations(OpReadRecommendations),
+  ReadMutualSuggestions(OpReadMutualSuggestions),
+  ReadScoreHistory(OpReadScoreHistory),
+  ReadScoresAt(OpReadScoresAt),
+  WriteGcOrphans(OpWriteGcOrphans),
+  ReadQuota,
+  ReadScoresChunked(OpReadScoresChunked),
+  /// Makes a replica the primary, see `replication`.
+  Promote,
+  ReadContextSnapshot,
+  /// Replaces the context with the snapshot.
+  WriteContextSnapshot(ContextSnapshot),
+  MoveContext(OpMoveContext),
+  /// Replaces all contexts with the edges of `MERITRANK_IMPORT_QUERY`, like
+  /// `WriteBulkEdges`.
+  ImportFromSql,
+  ExportScores(OpExportScores),
+  ReadDryRun(OpReadDryRun),
+  SetUserParams(OpSetUserParams),
+  ReadAnomalies(OpReadAnomalies),
+  EvaluateSybilResistance(OpEvaluateSybilResistance),
+  ReadBeaconComments(OpReadBeaconComments),
+  ReadTopNodes(OpReadTopNodes),
+  RecalculateEgo(OpRecalculateEgo),
+  RecalculateAll,
+  DebugEgo(OpDebugEgo),
+  WriteNode(OpWriteNode),
+  Rerank(OpRerank),
+  WriteEdgeCas(OpWriteEdgeCas),
+  WriteWebhook(OpWriteWebhook),
+  DeleteWebhook(OpDeleteWebhook),
+  ReadWebhooks,
+  ReadScoreDiff(OpReadScoreDiff),
+  ImportEdgeList(OpImportEdgeList),
+  Idempotent(OpIdempotent),
+  SetMaintenanceMode(OpSetMaintenanceMode),
+  ReadSlowLog(OpReadSlowLog),
+  WriteMulti(OpWriteMulti),
+  WriteMute(OpWriteMute),
+  ReadMutes(OpReadMutes),
+  WritePoll(OpWritePoll),
 }
 
 impl ReqData {
+  /// Name of the request type, as used in `MERITRANK_REQUEST_TIMEOUTS`.
+  pub fn opcode(&self) -> &'static str {
+    use ReqData::*;
+    match self {
+      ReadScores(_) => "ReadScores",
+      WriteEdge(_) => "WriteEdge",
+      WriteBulkEdges(_) => "WriteBulkEdges",
+      WriteCalculate(_) => "WriteCalculate",
+      Stamp(_) => "Stamp",
+      Sync(_) => "Sync",
+      ResetStats => "ResetStats",
+      GetStats => "GetStats",
+      ReadNodeList => "ReadNodeList",
+      ReadNodeScore(_) => "ReadNodeScore",
+      ReadGraph(_) => "ReadGraph",
+      ReadConnected(_) => "ReadConnected",
+      ReadEdges => "ReadEdges",
+      ReadMutualScores(_) => "ReadMutualScores",
+      ReadNewEdgesFilter(_) => "ReadNewEdgesFilter",
+      ReadNeighbors(_) => "ReadNeighbors",
+      WriteReset => "WriteReset",
+      WriteZeroOpinion(_) => "WriteZeroOpinion",
+      WriteRecalculateClustering => "WriteRecalculateClustering",
+      WriteDeleteEdge(_) => "WriteDeleteEdge",
+      WriteDeleteNode(_) => "WriteDeleteNode",
+      WriteCreateContext => "WriteCreateContext",
+      WriteNewEdgesFilter(_) => "WriteNewEdgesFilter",
+      WriteFetchNewEdges(_) => "WriteFetchNewEdges",
+      WriteForkContext(_) => "WriteForkContext",
+      WriteMergeContext(_) => "WriteMergeContext",
+      ReadContexts => "ReadContexts",
+      WriteDeleteContext => "WriteDeleteContext",
+      WriteScoreClusters(_) => "WriteScoreClusters",
+      WriteAliasNode(_) => "WriteAliasNode",
+      ReadScoresBulk(_) => "ReadScoresBulk",
+      ReadCompareEgos(_) => "ReadCompareEgos",
+      ReadRecommendations(_) => "ReadRecommendations",
+      ReadMutualSuggestions(_) => "ReadMutualSuggestions",
+      ReadScoreHistory(_) => "ReadScoreHistory",
+      ReadScoresAt(_) => "ReadScoresAt",
+      WriteGcOrphans(_) => "WriteGcOrphans",
+      ReadQuota => "ReadQuota",
+      ReadScoresChunked(_) => "ReadScoresChunked",
+      Promote => "Promote",
+      ReadContextSnapshot => "ReadContextSnapshot",
+      WriteContextSnapshot(_) => "WriteContextSnapshot",
+      MoveContext(_) => "MoveContext",
+      ImportFromSql => "ImportFromSql",
+      ExportScores(_) => "ExportScores",
+      ReadDryRun(_) => "ReadDryRun",
+      SetUserParams(_) => "SetUserParams",
+      ReadAnomalies(_) => "ReadAnomalies",
+      EvaluateSybilResistance(_) => "EvaluateSybilResistance",
+      ReadBeaconComments(_) => "ReadBeaconComments",
+      ReadTopNodes(_) => "ReadTopNodes",
+      RecalculateEgo(_) => "RecalculateEgo",
+      RecalculateAll => "RecalculateAll",
+      DebugEgo(_) => "DebugEgo",
+      WriteNode(_) => "WriteNode",
+      Rerank(_) => "Rerank",
+      WriteEdgeCas(_) => "WriteEdgeCas",
+      WriteWebhook(_) => "WriteWebhook",
+      DeleteWebhook(_) => "DeleteWebhook",
+      ReadWebhooks => "ReadWebhooks",
+      ReadScoreDiff(_) => "ReadScoreDiff",
+      ImportEdgeList(_) => "ImportEdgeList",
+      Idempotent(data) => data.data.opcode(),
+      SetMaintenanceMode(_) => "SetMaintenanceMode",
+      ReadSlowLog(_) => "ReadSlowLog",
+      WriteMulti(_) => "WriteMulti",
+      WriteMute(_) => "WriteMute",
+      ReadMutes(_) => "ReadMutes",
+      WritePoll(_) => "WritePoll",
+    }
+  }
+
   /// Returns the ego for read operations that require walks (scores, graph, neighbors, mutual).
   pub fn read_ego(&self) -> Option<&NodeName> {
     use ReqData::*;
     match self {
       ReadScores(data) => Some(&data.ego),
+      ReadScoresChunked(data) => Some(&data.ego),
       ReadNodeScore(data) => Some(&data.ego),
       ReadGraph(data) => Some(&data.ego),
       ReadNeighbors(data) => Some(&data.ego),
       ReadMutualScores(data) => Some(&data.ego),
+      ReadRecommendations(data) => Some(&data.ego),
+      ReadDryRun(data) => Some(&data.ego),
+      ReadBeaconComments(data) => Some(&data.ego),
+      ReadTopNodes(data) => data.ego.as_ref(),
+      ReadScoreDiff(data) => Some(&data.ego),
       _ => None,
     }
   }
+
+  /// Time the ego's walks are given before approximate scores are read,
+  /// see `FilterOptions::max_latency_ms`.
+  pub fn max_latency(&self) -> Option<Duration> {
+    match self {
+      ReqData::ReadScores(data) if data.score_options.max_latency_ms > 0 => {
+        Some(Duration::from_millis(data.score_options.max_latency_ms))
+      },
+      _ => None,
+    }
+  }
+
+  /// Returns true for operations that modify graph state.
+  pub fn is_write(&self) -> bool {
+    use ReqData::*;
+    if let Idempotent(data) = self {
+      return data.data.is_write();
+    }
+    matches!(
+      self,
+      WriteEdge(_)
+        | WriteBulkEdges(_)
+        | WriteCalculate(_)
+        | Stamp(_)
+        | ResetStats
+        | WriteReset
+        | WriteZeroOpinion(_)
+        | WriteRecalculateClustering
+        | WriteDeleteEdge(_)
+        | WriteDeleteNode(_)
+        | WriteCreateContext
+        | WriteNewEdgesFilter(_)
+        | WriteFetchNewEdges(_)
+        | WriteForkContext(_)
+        | WriteMergeContext(_)
+        | WriteDeleteContext
+        | WriteScoreClusters(_)
+        | WriteAliasNode(_)
+        | WriteGcOrphans(_)
+        | WriteContextSnapshot(_)
+        | MoveContext(_)
+        | ImportFromSql
+        | ImportEdgeList(_)
+        | SetUserParams(_)
+        | RecalculateEgo(_)
+        | RecalculateAll
+        | WriteNode(_)
+        | Rerank(_)
+        | WriteEdgeCas(_)
+        | WriteMulti(_)
+        | WriteMute(_)
+        | WritePoll(_)
+    )
+  }
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
 pub struct Request {
   //  NOTE: Subgraph name is ignored for some requests.
   pub subgraph: SubgraphName,
 
+  /// Client token checked against the ACL. Ignored when no ACL is configured.
+  pub token: String,
+
   pub data: ReqData,
 }
 
-#[derive(Debug, Encode, Decode)]
+/// Typed failure reasons returned as `Response::Error`.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum ServiceError {
+  /// Token is missing or unknown.
+  Unauthorized,
+  /// Token is not allowed to access (or write to) the given context.
+  Forbidden(SubgraphName),
+  /// The write would exceed a limit of the given context.
+  QuotaExceeded(SubgraphName, QuotaLimit),
+  /// The edge weight would change faster than allowed in the given context,
+  /// see `MERITRANK_EDGE_RATE_LIMITS`.
+  EdgeRateExceeded(SubgraphName),
+  /// The edge weight was not the expected one, see `WriteEdgeCas`. The write
+  /// was not applied, or was overwritten by a concurrent one.
+  WriteConflict(SubgraphName),
+  /// The request did not finish within its time budget, see
+  /// `MERITRANK_REQUEST_TIMEOUT`.
+  Timeout,
+  /// Writes are not accepted by replicas, see `MERITRANK_REPLICATE_FROM`.
+  ReadOnly,
+  /// Edges could not be imported, see `ImportFromSql` and `ImportEdgeList`.
+  ImportFailed(String),
+  /// Scores could not be exported, see `ExportScores`.
+  ExportFailed(String),
+  /// The core failed to score the ego, and no last scores of it were kept,
+  /// see `MERITRANK_DEGRADED_MODE`.
+  ScoresUnavailable(CoreErrorCode),
+  /// The write is malformed and was not applied.
+  InvalidWrite(InvalidWrite),
+  /// Writes are not accepted in maintenance mode, see `SetMaintenanceMode`.
+  Maintenance,
+  /// The context has used its budget of walks for reads, see
+  /// `MERITRANK_WALK_BUDGETS`. Reads of calculated egos are still served.
+  WalkBudgetExceeded(SubgraphName),
+  /// The poll expired, so its votes and options can't change anymore, see
+  /// `WritePoll`.
+  PollClosed(NodeName),
+  /// The user already voted in the anonymous poll, and anonymous votes can't
+  /// change, see `WritePoll`.
+  AlreadyVoted(NodeName),
+}
+
+/// Names longer than this, in bytes, are rejected as `NameTooLong`.
+pub const MAX_NODE_NAME_LEN: usize = 256;
+
+/// Longest `source` and `external_id` of an `EdgeProvenance`, in bytes.
+pub const MAX_PROVENANCE_LEN: usize = 256;
+
+/// Reason a write was rejected before reaching the graph.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum InvalidWrite {
+  /// Edge from the node to itself.
+  SelfLoop(NodeName),
+  /// The name prefix is not a known node kind, see `register_node_kind`.
+  UnknownNodeKind(NodeName),
+  /// Weight or score is NaN or infinite.
+  NonFiniteWeight,
+  /// The name is longer than `MAX_NODE_NAME_LEN`.
+  NameTooLong(NodeName),
+  /// Alpha of `SetUserParams` is not in `[0, 1]`.
+  AlphaOutOfRange,
+  /// The webhook URL does not start with `http://`.
+  UnsupportedUrl(String),
+  /// The idempotency key is empty or longer than `MAX_KEY_LEN`, or the
+  /// wrapped request is not a write or is itself `Idempotent`.
+  InvalidIdempotent,
+  /// `WriteMulti` has no parts, or a part that is not an edge write or is an
+  /// edge of a poll.
+  InvalidMulti,
+  /// `source` or `external_id` of the edge provenance is longer than
+  /// `MAX_PROVENANCE_LEN`.
+  ProvenanceTooLong,
+  /// The node of `WritePoll` is not a poll.
+  NotAPoll(NodeName),
+  /// `WriteScoreClusters` asks for fewer than 2 clusters.
+  TooFewClusters(u32),
+  /// `WriteAliasNode` renames a node of the first kind to a name of the
+  /// second one.
+  AliasKindMismatch(NodeKind, NodeKind),
+}
+
+/// Limit exceeded by a write, see `MERITRANK_MAX_CONTEXT_NODES`,
+/// `MERITRANK_MAX_CONTEXT_EDGES` and `MERITRANK_MAX_MEMORY`.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize,
+)]
+pub enum QuotaLimit {
+  Nodes,
+  Edges,
+  Memory,
+}
+
+/// Reason the core failed to score an ego, see `ScoresUnavailable`.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize,
+)]
+pub enum CoreErrorCode {
+  /// The ego is not in the graph.
+  NodeNotFound,
+  /// The ego has no walks.
+  NotCalculated,
+  /// The calculation did not finish before its deadline.
+  Timeout,
+  /// Any other error of the core.
+  Internal,
+}
+
+impl From<&meritrank_core::MeritRankError> for CoreErrorCode {
+  fn from(e: &meritrank_core::MeritRankError) -> Self {
+    use meritrank_core::MeritRankError::*;
+    match e {
+      NodeDoesNotExist | NodeNotFound => CoreErrorCode::NodeNotFound,
+      NodeIsNotCalculated | WalkNotFound => CoreErrorCode::NotCalculated,
+      Timeout => CoreErrorCode::Timeout,
+      _ => CoreErrorCode::Internal,
+    }
+  }
+}
+
+#[derive(Debug, Encode, Decode, Serialize, Deserialize)]
 pub enum Response {
   Ok,
   Fail,
   NotImplemented,
+  Error(ServiceError),
+  /// Write queue is full; the op was not applied and may be retried.
+  Busy,
   Stamp(u64),
   Scores(ResScores),
   NodeList(ResNodeList),
@@ -330,4 +1601,28 @@ pub enum Response {
   Edges(ResEdges),
   NewEdges(ResNewEdges),
   Stats(ResStats),
+  Merge(ResMerge),
+  Contexts(ResContexts),
+  ScoresBulk(ResScoresBulk),
+  ScoresCompact(ResScoresCompact),
+  EgoComparison(ResEgoComparison),
+  Recommendations(ResRecommendations),
+  MutualSuggestions(ResMutualSuggestions),
+  ScoreHistory(ResScoreHistory),
+  GcOrphans(ResGcOrphans),
+  Quota(ResQuota),
+  ScoresChunk(ResScoresChunk),
+  ContextSnapshot(ContextSnapshot),
+  Export(ResExport),
+  DryRun(ResDryRun),
+  Anomalies(ResAnomalies),
+  SybilResistance(ResSybilResistance),
+  BeaconComments(ResBeaconComments),
+  TopNodes(ResTopNodes),
+  Recalculation(ResRecalculation),
+  DebugEgo(ResDebugEgo),
+  Rerank(ResRerank),
+  Webhooks(ResWebhooks),
+  ScoreDiff(ResScoreDiff),
+  SlowLog(ResSlowLog),
 }
@@ -0,0 +1,241 @@
+//! Export of scores to files for offline analytics, see `ExportScores`.
+//!
+//! Each row is (ego, target, score, reverse_score, cluster, reverse_cluster).
+//! Scores are read one ego at a time and handed to a blocking writer, so an
+//! export holds the scores of a few egos at most, plus one row group for
+//! Parquet. Parquet needs the `parquet` feature.
+
+use crate::data::{ExportFormat, ScoreResult, ServiceError};
+use crate::utils::files::path_inside;
+
+use serde::Serialize;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Path of the export file. `destination` must be a relative path that stays
+/// inside `export_dir`.
+pub fn export_path(
+  export_dir: &str,
+  destination: &str,
+) -> Result<PathBuf, ServiceError> {
+  if export_dir.is_empty() {
+    return Err(ServiceError::ExportFailed(
+      "MERITRANK_EXPORT_DIR is not set".into(),
+    ));
+  }
+  path_inside(export_dir, destination).ok_or_else(|| {
+    ServiceError::ExportFailed(format!("Invalid destination {:?}", destination))
+  })
+}
+
+fn create_file(path: &Path) -> io::Result<BufWriter<File>> {
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  Ok(BufWriter::new(File::create(path)?))
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+  ego:             &'a str,
+  target:          &'a str,
+  score:           f64,
+  reverse_score:   f64,
+  cluster:         usize,
+  reverse_cluster: usize,
+}
+
+pub enum ScoresWriter {
+  Csv(csv::Writer<BufWriter<File>>),
+  #[cfg(feature = "parquet")]
+  Parquet(parquet_writer::ParquetWriter),
+}
+
+impl ScoresWriter {
+  pub fn create(
+    format: ExportFormat,
+    path: &Path,
+  ) -> io::Result<Self> {
+    match format {
+      ExportFormat::Csv => {
+        Ok(ScoresWriter::Csv(csv::Writer::from_writer(create_file(path)?)))
+      },
+      #[cfg(feature = "parquet")]
+      ExportFormat::Parquet => {
+        parquet_writer::ParquetWriter::new(create_file(path)?)
+          .map(ScoresWriter::Parquet)
+      },
+      #[cfg(not(feature = "parquet"))]
+      ExportFormat::Parquet => Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "The service is built without the parquet feature",
+      )),
+    }
+  }
+
+  pub fn write(
+    &mut self,
+    ego: &str,
+    scores: &[ScoreResult],
+  ) -> io::Result<()> {
+    match self {
+      ScoresWriter::Csv(writer) => {
+        for score in scores {
+          writer.serialize(Row {
+            ego,
+            target:          &score.target,
+            score:           score.score,
+            reverse_score:   score.reverse_score,
+            cluster:         score.cluster,
+            reverse_cluster: score.reverse_cluster,
+          })?;
+        }
+        Ok(())
+      },
+      #[cfg(feature = "parquet")]
+      ScoresWriter::Parquet(writer) => writer.write(ego, scores),
+    }
+  }
+
+  /// Writes out buffered rows and closes the file.
+  pub fn finish(self) -> io::Result<()> {
+    match self {
+      ScoresWriter::Csv(mut writer) => writer.flush(),
+      #[cfg(feature = "parquet")]
+      ScoresWriter::Parquet(writer) => writer.finish(),
+    }
+  }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_writer {
+  use crate::data::ScoreResult;
+
+  use parquet::data_type::{
+    ByteArray, ByteArrayType, DataType, DoubleType, Int64Type,
+  };
+  use parquet::errors::ParquetError;
+  use parquet::file::properties::WriterProperties;
+  use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+  use parquet::schema::parser::parse_message_type;
+
+  use std::fs::File;
+  use std::io::{self, BufWriter};
+  use std::sync::Arc;
+
+  const SCHEMA: &str = "message scores {
+    REQUIRED BYTE_ARRAY ego (UTF8);
+    REQUIRED BYTE_ARRAY target (UTF8);
+    REQUIRED DOUBLE score;
+    REQUIRED DOUBLE reverse_score;
+    REQUIRED INT64 cluster;
+    REQUIRED INT64 reverse_cluster;
+  }";
+
+  /// Rows buffered before they are written as a row group.
+  const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+  fn io_error(e: ParquetError) -> io::Error {
+    io::Error::other(e)
+  }
+
+  #[derive(Default)]
+  struct Columns {
+    ego:             Vec<ByteArray>,
+    target:          Vec<ByteArray>,
+    score:           Vec<f64>,
+    reverse_score:   Vec<f64>,
+    cluster:         Vec<i64>,
+    reverse_cluster: Vec<i64>,
+  }
+
+  pub struct ParquetWriter {
+    file: SerializedFileWriter<BufWriter<File>>,
+    rows: Columns,
+  }
+
+  fn write_column<T: DataType>(
+    group: &mut SerializedRowGroupWriter<'_, BufWriter<File>>,
+    values: &[T::T],
+  ) -> Result<(), ParquetError> {
+    let mut column = group
+      .next_column()?
+      .ok_or_else(|| ParquetError::General("Too few columns".into()))?;
+    column.typed::<T>().write_batch(values, None, None)?;
+    column.close()
+  }
+
+  impl ParquetWriter {
+    pub fn new(file: BufWriter<File>) -> io::Result<Self> {
+      let schema = Arc::new(parse_message_type(SCHEMA).map_err(io_error)?);
+      let properties = Arc::new(WriterProperties::builder().build());
+      Ok(ParquetWriter {
+        file: SerializedFileWriter::new(file, schema, properties)
+          .map_err(io_error)?,
+        rows: Columns::default(),
+      })
+    }
+
+    pub fn write(
+      &mut self,
+      ego: &str,
+      scores: &[ScoreResult],
+    ) -> io::Result<()> {
+      for score in scores {
+        let rows = &mut self.rows;
+        rows.ego.push(ByteArray::from(ego));
+        rows.target.push(ByteArray::from(score.target.as_str()));
+        rows.score.push(score.score);
+        rows.reverse_score.push(score.reverse_score);
+        rows.cluster.push(score.cluster as i64);
+        rows.reverse_cluster.push(score.reverse_cluster as i64);
+      }
+      if self.rows.score.len() >= ROW_GROUP_SIZE {
+        self.flush().map_err(io_error)?;
+      }
+      Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), ParquetError> {
+      if self.rows.score.is_empty() {
+        return Ok(());
+      }
+      let rows = std::mem::take(&mut self.rows);
+      let mut group = self.file.next_row_group()?;
+      write_column::<ByteArrayType>(&mut group, &rows.ego)?;
+      write_column::<ByteArrayType>(&mut group, &rows.target)?;
+      write_column::<DoubleType>(&mut group, &rows.score)?;
+      write_column::<DoubleType>(&mut group, &rows.reverse_score)?;
+      write_column::<Int64Type>(&mut group, &rows.cluster)?;
+      write_column::<Int64Type>(&mut group, &rows.reverse_cluster)?;
+      group.close()?;
+      Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+      self.flush().map_err(io_error)?;
+      self.file.close().map_err(io_error)?;
+      Ok(())
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn destination_stays_in_export_dir() {
+    assert!(export_path("", "scores.csv").is_err());
+    assert!(export_path("/exports", "").is_err());
+    assert!(export_path("/exports", "/etc/passwd").is_err());
+    assert!(export_path("/exports", "../scores.csv").is_err());
+    assert!(export_path("/exports", "a/../../scores.csv").is_err());
+    assert_eq!(
+      export_path("/exports", "daily/scores.csv").unwrap(),
+      PathBuf::from("/exports/daily/scores.csv")
+    );
+  }
+}
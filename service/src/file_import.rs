@@ -0,0 +1,292 @@
+//! Import of the graph from an edge list file, see `ImportEdgeList` and the
+//! `--import` flag.
+//!
+//! CSV files have one `src,dst,weight[,context]` row per edge; a first row
+//! whose weight is not a number is taken for a header and skipped. Files
+//! ending in `.jsonl` or `.ndjson` have one `{"src", "dst", "weight",
+//! "context"}` object per line, `context` being optional. The edges are bulk
+//! loaded like `WriteBulkEdges`.
+
+use crate::data::{BulkEdge, ServiceError};
+use crate::utils::files::path_inside;
+use crate::utils::log::*;
+
+use serde::Deserialize;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Rows between throughput log lines.
+const LOG_INTERVAL: u64 = 100_000;
+
+/// Progress of the running import, reported by `GetStats`.
+#[derive(Default)]
+pub struct ImportProgress {
+  pub rows:        AtomicU64,
+  /// Bytes of the file read so far and its size, 0 when no import runs.
+  pub bytes_read:  AtomicU64,
+  pub bytes_total: AtomicU64,
+}
+
+impl ImportProgress {
+  /// Share of the file read, 0 when no import runs.
+  pub fn fraction(&self) -> f64 {
+    let total = self.bytes_total.load(Ordering::Relaxed);
+    if total == 0 {
+      return 0.0;
+    }
+    self.bytes_read.load(Ordering::Relaxed) as f64 / total as f64
+  }
+
+  pub fn reset(&self) {
+    self.rows.store(0, Ordering::Relaxed);
+    self.bytes_read.store(0, Ordering::Relaxed);
+    self.bytes_total.store(0, Ordering::Relaxed);
+  }
+}
+
+/// Path of the file `source` under `import_dir`. Like export destinations,
+/// it must be relative and stay inside the directory.
+pub fn import_path(
+  import_dir: &str,
+  source: &str,
+) -> Result<PathBuf, ServiceError> {
+  if import_dir.is_empty() {
+    return Err(ServiceError::ImportFailed(
+      "MERITRANK_IMPORT_DIR is not set".into(),
+    ));
+  }
+  path_inside(import_dir, source).ok_or_else(|| {
+    ServiceError::ImportFailed(format!("Invalid source {:?}", source))
+  })
+}
+
+/// Counts the bytes read into the progress.
+struct ProgressReader<'a, R> {
+  inner:    R,
+  progress: &'a ImportProgress,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+  fn read(
+    &mut self,
+    buf: &mut [u8],
+  ) -> io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    self.progress.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+    Ok(n)
+  }
+}
+
+#[derive(Deserialize)]
+struct JsonEdge {
+  src:     String,
+  dst:     String,
+  weight:  f64,
+  #[serde(default)]
+  context: String,
+}
+
+/// Reads the edges of the file. Blocks, so it is run on a blocking thread.
+pub fn read_edge_list(
+  path: &Path,
+  progress: &ImportProgress,
+) -> Result<Vec<BulkEdge>, ServiceError> {
+  let failed = |e: String| ServiceError::ImportFailed(e);
+  let file = File::open(path)
+    .map_err(|e| failed(format!("Failed to open {:?}: {}", path, e)))?;
+  let size = file.metadata().map(|x| x.len()).unwrap_or(0);
+  progress.reset();
+  progress.bytes_total.store(size, Ordering::Relaxed);
+  let reader = BufReader::new(ProgressReader {
+    inner: file,
+    progress,
+  });
+
+  let jsonl = matches!(
+    path.extension().and_then(|x| x.to_str()),
+    Some("jsonl") | Some("ndjson")
+  );
+  let mut counter = RowCounter::new(progress);
+  let edges = if jsonl {
+    read_jsonl(reader, &mut counter)
+  } else {
+    read_csv(reader, &mut counter)
+  }
+  .map_err(failed)?;
+  log_info!(
+    "Read {} edges from {:?} in {:.1}s",
+    edges.len(),
+    path,
+    counter.start.elapsed().as_secs_f64()
+  );
+  Ok(edges)
+}
+
+/// Counts the rows read and logs the throughput every `LOG_INTERVAL` rows.
+struct RowCounter<'a> {
+  progress: &'a ImportProgress,
+  start:    Instant,
+  last:     Instant,
+}
+
+impl<'a> RowCounter<'a> {
+  fn new(progress: &'a ImportProgress) -> Self {
+    let now = Instant::now();
+    RowCounter {
+      progress,
+      start: now,
+      last: now,
+    }
+  }
+
+  fn add(&mut self) {
+    let rows = self.progress.rows.fetch_add(1, Ordering::Relaxed) + 1;
+    if rows.is_multiple_of(LOG_INTERVAL) {
+      let elapsed = self.last.elapsed().as_secs_f64().max(1e-9);
+      self.last = Instant::now();
+      log_info!(
+        "Imported {} rows ({:.1}%), {:.0} rows/s",
+        rows,
+        self.progress.fraction() * 100.0,
+        LOG_INTERVAL as f64 / elapsed
+      );
+    }
+  }
+}
+
+fn read_csv<R: Read>(
+  reader: R,
+  counter: &mut RowCounter,
+) -> Result<Vec<BulkEdge>, String> {
+  let mut csv = csv::ReaderBuilder::new()
+    .has_headers(false)
+    .flexible(true)
+    .trim(csv::Trim::All)
+    .from_reader(reader);
+  let mut edges = vec![];
+  for (index, record) in csv.records().enumerate() {
+    let record = record.map_err(|e| e.to_string())?;
+    let line = index + 1;
+    if record.len() != 3 && record.len() != 4 {
+      return Err(format!(
+        "Line {}: expected 3 or 4 columns, got {}",
+        line,
+        record.len()
+      ));
+    }
+    let amount = match record[2].parse::<f64>() {
+      Ok(x) => x,
+      Err(_) if index == 0 => continue,
+      Err(e) => return Err(format!("Line {}: invalid weight: {}", line, e)),
+    };
+    edges.push(BulkEdge {
+      src: record[0].to_string(),
+      dst: record[1].to_string(),
+      amount,
+      magnitude: 0,
+      context: record.get(3).unwrap_or_default().to_string(),
+    });
+    counter.add();
+  }
+  Ok(edges)
+}
+
+fn read_jsonl<R: BufRead>(
+  reader: R,
+  counter: &mut RowCounter,
+) -> Result<Vec<BulkEdge>, String> {
+  let mut edges = vec![];
+  for (index, line) in reader.lines().enumerate() {
+    let line = line.map_err(|e| e.to_string())?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    let edge: JsonEdge = serde_json::from_str(&line)
+      .map_err(|e| format!("Line {}: {}", index + 1, e))?;
+    edges.push(BulkEdge {
+      src:       edge.src,
+      dst:       edge.dst,
+      amount:    edge.weight,
+      magnitude: 0,
+      context:   edge.context,
+    });
+    counter.add();
+  }
+  Ok(edges)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::fs;
+
+  fn temp_file(
+    name: &str,
+    contents: &str,
+  ) -> PathBuf {
+    let path = std::env::temp_dir()
+      .join(format!("meritrank-import-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+  }
+
+  fn summary(edges: &[BulkEdge]) -> Vec<(&str, &str, f64, &str)> {
+    edges
+      .iter()
+      .map(|x| {
+        (x.src.as_str(), x.dst.as_str(), x.amount, x.context.as_str())
+      })
+      .collect()
+  }
+
+  #[test]
+  fn reads_csv_and_jsonl() {
+    let progress = ImportProgress::default();
+
+    let path =
+      temp_file("edges.csv", "src,dst,weight\nU1,U2,1.5\nU2,B1,-1,ctx\n");
+    let edges = read_edge_list(&path, &progress).unwrap();
+    assert_eq!(
+      summary(&edges),
+      vec![("U1", "U2", 1.5, ""), ("U2", "B1", -1.0, "ctx")]
+    );
+    assert_eq!(progress.rows.load(Ordering::Relaxed), 2);
+    assert_eq!(progress.fraction(), 1.0);
+    fs::remove_file(path).unwrap();
+
+    let path = temp_file(
+      "edges.jsonl",
+      "{\"src\":\"U1\",\"dst\":\"U2\",\"weight\":2}\n\n\
+       {\"src\":\"U2\",\"dst\":\"B1\",\"weight\":1,\"context\":\"ctx\"}\n",
+    );
+    let edges = read_edge_list(&path, &progress).unwrap();
+    assert_eq!(
+      summary(&edges),
+      vec![("U1", "U2", 2.0, ""), ("U2", "B1", 1.0, "ctx")]
+    );
+    fs::remove_file(path).unwrap();
+
+    let path = temp_file("bad.csv", "U1,U2,1\nU2,B1,x\n");
+    assert!(matches!(
+      read_edge_list(&path, &progress),
+      Err(ServiceError::ImportFailed(_))
+    ));
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn import_path_stays_inside_dir() {
+    assert!(import_path("", "edges.csv").is_err());
+    assert!(import_path("/data", "../edges.csv").is_err());
+    assert!(import_path("/data", "/etc/passwd").is_err());
+    assert_eq!(
+      import_path("/data", "dumps/edges.csv").unwrap(),
+      PathBuf::from("/data/dumps/edges.csv")
+    );
+  }
+}
@@ -14,7 +14,7 @@ pub fn perform_astar_search(
   graph: &Graph,
   ego_id: NodeId,
   focus_id: NodeId,
-) -> Result<Vec<NodeId>, ()> {
+) -> Option<Vec<NodeId>> {
   log_trace!();
 
   let mut open: Vec<Node<NodeId, Weight>> = vec![];
@@ -76,13 +76,13 @@ pub fn perform_astar_search(
     let mut ego_to_focus: Vec<NodeId> = vec![0; astar_state.num_closed];
     let n = path(&closed, &astar_state, &mut ego_to_focus);
     ego_to_focus.resize(n, 0);
-    Ok(ego_to_focus)
+    Some(ego_to_focus)
   } else if status == Status::FAIL {
     log_error!("Path not found.");
-    Err(())
+    None
   } else {
     log_error!("Too many iterations.");
-    Err(())
+    None
   }
 }
 
@@ -173,6 +173,19 @@ pub fn extract_unique_edges_from_graph_data(
   edge_ids
 }
 
+/// Checks the score against `score_gt` and `score_lt` of the options.
+pub fn score_in_range(
+  score: NodeScore,
+  filter_options: &FilterOptions,
+) -> bool {
+  (score > filter_options.score_gt
+    || (!filter_options.score_gte && score >= filter_options.score_gt))
+    && (score < filter_options.score_lt
+      || (!filter_options.score_lte && score <= filter_options.score_lt))
+}
+
+/// Score thresholds are only checked here for the blended score, see
+/// `ScoreComponent`.
 pub fn filter_and_sort_scores(
   scores: Vec<(NodeInfo, NodeScore, NodeCluster)>,
   ego_info: &NodeInfo,
@@ -184,26 +197,25 @@ pub fn filter_and_sort_scores(
       // Apply kind filter
       filter_options
         .node_kind
-        .map_or(true, |filter_kind| node_info.kind == filter_kind)
+        .is_none_or(|filter_kind| node_info.kind == filter_kind)
         && !(filter_options.hide_personal
           && node_info.owner == Some(ego_info.id))
-        && {
-          // Apply score filters
-          (*score > filter_options.score_gt
-            || (!filter_options.score_gte && *score >= filter_options.score_gt))
-            && (*score < filter_options.score_lt
-              || (!filter_options.score_lte
-                && *score <= filter_options.score_lt))
-        }
+        && (filter_options.filter_on != ScoreComponent::Blended
+          || score_in_range(*score, filter_options))
     })
     .collect();
 
-  filtered_scores.sort_by(|(_, a, _), (_, b, _)| b.abs().total_cmp(&a.abs()));
+  if filter_options.ascending {
+    filtered_scores.sort_by(|(_, a, _), (_, b, _)| a.total_cmp(b));
+  } else {
+    filtered_scores
+      .sort_by(|(_, a, _), (_, b, _)| b.abs().total_cmp(&a.abs()));
+  }
   filtered_scores
 }
 
 pub fn prioritize_ego_owned_items(
-  items: &mut Vec<(NodeInfo, NodeScore, NodeCluster)>,
+  items: &mut [(NodeInfo, NodeScore, NodeCluster)],
   ego_info: &NodeInfo,
 ) {
   let mut insert_index = 0;
@@ -0,0 +1,167 @@
+//! Score history of (ego, target) pairs, sampled when scores are read and on
+//! a schedule, so that clients can plot how trust evolves over time.
+
+use crate::data::NodeScore;
+
+use meritrank_core::{IntMap, NodeId};
+use parking_lot::Mutex;
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Unix time in seconds and the score at that time.
+pub type ScoreSample = (u64, NodeScore);
+
+pub struct ScoreHistory {
+  samples:   Mutex<IntMap<NodeId, IntMap<NodeId, VecDeque<ScoreSample>>>>,
+  /// Samples per pair; 0 disables the history.
+  size:      usize,
+  /// Minimal number of seconds between two samples of a pair.
+  interval:  u64,
+  /// Samples older than that many seconds are dropped; 0 keeps them until
+  /// they are pushed out by newer ones.
+  retention: u64,
+}
+
+pub fn unix_time_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+impl ScoreHistory {
+  pub fn new(
+    size: usize,
+    interval: u64,
+    retention: u64,
+  ) -> Self {
+    ScoreHistory {
+      samples: Mutex::new(IntMap::default()),
+      size,
+      interval,
+      retention,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.size > 0
+  }
+
+  pub fn record(
+    &self,
+    ego: NodeId,
+    target: NodeId,
+    score: NodeScore,
+  ) {
+    self.record_at(ego, target, score, unix_time_secs());
+  }
+
+  /// Skips the sample if the last one of the pair is more recent than
+  /// `interval`.
+  pub fn record_at(
+    &self,
+    ego: NodeId,
+    target: NodeId,
+    score: NodeScore,
+    now: u64,
+  ) {
+    if !self.is_enabled() {
+      return;
+    }
+    let mut samples = self.samples.lock();
+    let pair = samples.entry(ego).or_default().entry(target).or_default();
+    if let Some((time, _)) = pair.back() {
+      if now < time + self.interval {
+        return;
+      }
+    }
+    if pair.len() >= self.size {
+      pair.pop_front();
+    }
+    pair.push_back((now, score));
+    if self.retention > 0 {
+      while pair.front().is_some_and(|(t, _)| t + self.retention < now) {
+        pair.pop_front();
+      }
+    }
+  }
+
+  /// Targets with samples for the ego.
+  pub fn targets(
+    &self,
+    ego: NodeId,
+  ) -> Vec<NodeId> {
+    match self.samples.lock().get(&ego) {
+      Some(targets) => targets.keys().copied().collect(),
+      None => vec![],
+    }
+  }
+
+  /// Egos with samples.
+  pub fn egos(&self) -> Vec<NodeId> {
+    self.samples.lock().keys().copied().collect()
+  }
+
+  /// Samples of the pair within the retention period, oldest first.
+  pub fn samples(
+    &self,
+    ego: NodeId,
+    target: NodeId,
+  ) -> Vec<ScoreSample> {
+    self.samples_at(ego, target, unix_time_secs())
+  }
+
+  pub fn samples_at(
+    &self,
+    ego: NodeId,
+    target: NodeId,
+    now: u64,
+  ) -> Vec<ScoreSample> {
+    let samples = self.samples.lock();
+    match samples.get(&ego).and_then(|targets| targets.get(&target)) {
+      Some(pair) => pair
+        .iter()
+        .filter(|(t, _)| self.retention == 0 || t + self.retention >= now)
+        .copied()
+        .collect(),
+      None => vec![],
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ring_buffer_keeps_latest_samples() {
+    let history = ScoreHistory::new(3, 0, 0);
+    for t in 0..5 {
+      history.record_at(1, 2, t as f64, t);
+    }
+
+    assert_eq!(history.samples_at(1, 2, 5), vec![(2, 2.0), (3, 3.0), (4, 4.0)]);
+    assert_eq!(history.targets(1), vec![2]);
+    assert!(history.samples_at(2, 1, 5).is_empty());
+  }
+
+  #[test]
+  fn interval_and_retention() {
+    let history = ScoreHistory::new(10, 10, 100);
+    history.record_at(1, 2, 0.1, 0);
+    history.record_at(1, 2, 0.2, 5);
+    history.record_at(1, 2, 0.3, 10);
+
+    assert_eq!(history.samples_at(1, 2, 10), vec![(0, 0.1), (10, 0.3)]);
+    assert_eq!(history.samples_at(1, 2, 105), vec![(10, 0.3)]);
+  }
+
+  #[test]
+  fn disabled_history_records_nothing() {
+    let history = ScoreHistory::new(0, 0, 0);
+    history.record_at(1, 2, 0.5, 0);
+
+    assert!(history.egos().is_empty());
+  }
+}
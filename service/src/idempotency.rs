@@ -0,0 +1,269 @@
+//! Deduplication of retried writes, see `Idempotent`.
+//!
+//! Clients wrap a write in `Idempotent` with a key unique to it, and send the
+//! same key again when they retry it, e.g. after a network error. The first
+//! request with a key is processed. Later ones within the window get the
+//! response of the first one without being applied again, or `Busy` while
+//! the first one is still being processed. Keys of requests that fail, or
+//! whose processing is dropped or panics, are forgotten, so that their
+//! retries are applied.
+
+use crate::data::*;
+
+use parking_lot::Mutex;
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Keys kept at most; the oldest are dropped before their window ends.
+const MAX_KEYS: usize = 100_000;
+
+/// Longest key accepted, in bytes.
+pub const MAX_KEY_LEN: usize = 256;
+
+type Key = (SubgraphName, String);
+
+#[derive(Default)]
+struct Keys {
+  /// Time each key was claimed at, and the encoded response of its request
+  /// once it succeeded: `Response` is not `Clone`.
+  claimed: HashMap<Key, (Instant, Option<Vec<u8>>)>,
+  /// Keys in the order they were claimed in, for expiry.
+  order:   VecDeque<(Instant, Key)>,
+}
+
+pub struct IdempotencyWindow {
+  window: Duration,
+  keys:   Mutex<Keys>,
+}
+
+enum Claim {
+  New(Instant),
+  Done(Response),
+  Pending,
+}
+
+/// Forgets a claimed key when dropped, unless its request succeeded, so that
+/// a request whose processing was dropped or panicked doesn't keep the key
+/// pending for the whole window.
+struct ClaimGuard<'a> {
+  window: &'a IdempotencyWindow,
+  key:    &'a Key,
+  time:   Instant,
+  done:   bool,
+}
+
+impl Drop for ClaimGuard<'_> {
+  fn drop(&mut self) {
+    if !self.done {
+      self.window.forget(self.key, self.time);
+    }
+  }
+}
+
+impl IdempotencyWindow {
+  /// A zero window disables deduplication.
+  pub fn new(window: Duration) -> Self {
+    IdempotencyWindow {
+      window,
+      keys: Mutex::new(Keys::default()),
+    }
+  }
+
+  fn claim(
+    &self,
+    key: &Key,
+  ) -> Claim {
+    let now = Instant::now();
+    let mut keys = self.keys.lock();
+    while let Some((time, _)) = keys.order.front() {
+      if now.duration_since(*time) < self.window && keys.order.len() < MAX_KEYS
+      {
+        break;
+      }
+      let (time, old) = keys.order.pop_front().unwrap();
+      //  The key may have been forgotten and claimed again since.
+      if keys.claimed.get(&old).is_some_and(|(claimed, _)| *claimed == time) {
+        keys.claimed.remove(&old);
+      }
+    }
+    match keys.claimed.get(key) {
+      Some((_, Some(response))) => {
+        match bincode::decode_from_slice(response, bincode::config::standard())
+        {
+          Ok((response, _)) => Claim::Done(response),
+          Err(_) => Claim::Done(Response::Ok),
+        }
+      },
+      Some((_, None)) => Claim::Pending,
+      None => {
+        keys.claimed.insert(key.clone(), (now, None));
+        keys.order.push_back((now, key.clone()));
+        Claim::New(now)
+      },
+    }
+  }
+
+  /// Keeps the response of the request of the key claimed at `time`.
+  fn finish(
+    &self,
+    key: &Key,
+    time: Instant,
+    response: &Response,
+  ) {
+    let encoded =
+      bincode::encode_to_vec(response, bincode::config::standard())
+        .unwrap_or_default();
+    let mut keys = self.keys.lock();
+    //  The key may have expired and been claimed again since.
+    if let Some((claimed, done)) = keys.claimed.get_mut(key) {
+      if *claimed == time {
+        *done = Some(encoded);
+      }
+    }
+  }
+
+  /// Forgets the key if it is still the one claimed at `time`.
+  fn forget(
+    &self,
+    key: &Key,
+    time: Instant,
+  ) {
+    let mut keys = self.keys.lock();
+    if keys.claimed.get(key).is_some_and(|(claimed, _)| *claimed == time) {
+      keys.claimed.remove(key);
+    }
+  }
+
+  /// Processes the request with `process` unless a request with the same
+  /// key in the context was processed within the window.
+  pub async fn process<F: Future<Output = Response>>(
+    &self,
+    context: &SubgraphName,
+    key: &str,
+    process: F,
+  ) -> Response {
+    if self.window.is_zero() {
+      return process.await;
+    }
+    let key = (context.clone(), key.to_string());
+    match self.claim(&key) {
+      Claim::Done(response) => response,
+      Claim::Pending => Response::Busy,
+      Claim::New(time) => {
+        let mut guard = ClaimGuard {
+          window: self,
+          key:    &key,
+          time,
+          done:   false,
+        };
+        let response = process.await;
+        let failed = matches!(
+          response,
+          Response::Fail
+            | Response::NotImplemented
+            | Response::Error(_)
+            | Response::Busy
+        );
+        if !failed {
+          self.finish(&key, time, &response);
+          guard.done = true;
+        }
+        response
+      },
+    }
+  }
+}
+
+/// Checks the key and that the wrapped request is a write other than
+/// `Idempotent`.
+pub fn validate(data: &OpIdempotent) -> Result<(), InvalidWrite> {
+  if data.key.is_empty()
+    || data.key.len() > MAX_KEY_LEN
+    || !data.data.is_write()
+    || matches!(*data.data, ReqData::Idempotent(_))
+  {
+    return Err(InvalidWrite::InvalidIdempotent);
+  }
+  Ok(())
+}
+
+/// The request wrapped in `Idempotent`.
+pub fn inner_request(
+  req: &Request,
+  data: &OpIdempotent,
+) -> Request {
+  Request {
+    subgraph: req.subgraph.clone(),
+    token:    req.token.clone(),
+    data:     (*data.data).clone(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  async fn processed_twice() -> Response {
+    panic!("processed twice")
+  }
+
+  #[tokio::test]
+  async fn retries_are_not_processed_again() {
+    let window = IdempotencyWindow::new(Duration::from_secs(60));
+    let context: SubgraphName = String::new();
+
+    let response = window.process(&context, "a", async { Response::Ok }).await;
+    assert!(matches!(response, Response::Ok));
+    let response = window.process(&context, "a", processed_twice()).await;
+    assert!(matches!(response, Response::Ok));
+
+    //  Keys of failed requests are forgotten.
+    let response =
+      window.process(&context, "b", async { Response::Fail }).await;
+    assert!(matches!(response, Response::Fail));
+    let response = window.process(&context, "b", async { Response::Ok }).await;
+    assert!(matches!(response, Response::Ok));
+
+    //  A retry while the request is processed is told to wait.
+    let key = (context.clone(), "c".to_string());
+    assert!(matches!(window.claim(&key), Claim::New(_)));
+    let response = window.process(&context, "c", processed_twice()).await;
+    assert!(matches!(response, Response::Busy));
+
+    //  Retries get the response of the first request.
+    let response =
+      window.process(&context, "d", async { Response::Stamp(7) }).await;
+    assert!(matches!(response, Response::Stamp(7)));
+    let response = window.process(&context, "d", processed_twice()).await;
+    assert!(matches!(response, Response::Stamp(7)));
+  }
+
+  #[tokio::test]
+  async fn keys_of_dropped_or_panicked_requests_are_forgotten() {
+    let window = IdempotencyWindow::new(Duration::from_secs(60));
+    let context: SubgraphName = String::new();
+
+    //  The request is dropped while it is processed, e.g. on a timeout.
+    let pending = window.process(&context, "a", std::future::pending());
+    let timeout = tokio::time::timeout(Duration::from_millis(10), pending);
+    assert!(timeout.await.is_err());
+    let response = window.process(&context, "a", async { Response::Ok }).await;
+    assert!(matches!(response, Response::Ok));
+
+    let window = std::sync::Arc::new(window);
+    let panicked = tokio::spawn({
+      let window = std::sync::Arc::clone(&window);
+      let context = context.clone();
+      async move {
+        window
+          .process(&context, "b", async { panic!("write panicked") })
+          .await
+      }
+    });
+    assert!(panicked.await.unwrap_err().is_panic());
+    let response = window.process(&context, "b", async { Response::Ok }).await;
+    assert!(matches!(response, Response::Ok));
+  }
+}
@@ -0,0 +1,134 @@
+//  Dump of the state of all subgraphs for the `migrate` subcommand of the
+//  new service. The structs mirror `LegacyState`, `LegacySubgraph` and
+//  `LegacyPoll` of `src/legacy_import.rs` and must be kept in sync with them.
+
+use meritrank_core::NodeId;
+use serde::Serialize;
+
+use crate::aug_multi_graph::AugMultiGraph;
+use crate::data::Weight;
+use crate::log::*;
+use crate::subgraph::Subgraph;
+
+#[derive(Serialize)]
+pub struct DumpState<'a> {
+  pub nodes:     Vec<&'a str>,
+  pub subgraphs: Vec<DumpSubgraph<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct DumpSubgraph<'a> {
+  pub context:               &'a str,
+  pub edges:                 Vec<(NodeId, NodeId, Weight)>,
+  pub zero_opinion:          &'a [Weight],
+  pub cached_scores:         Vec<(NodeId, NodeId, Weight)>,
+  pub cached_walks:          Vec<NodeId>,
+  pub omit_neg_edges_scores: bool,
+  pub num_walks:             usize,
+  pub polls:                 Vec<DumpPoll>,
+}
+
+#[derive(Serialize)]
+pub struct DumpPoll {
+  pub poll:    NodeId,
+  pub options: Vec<NodeId>,
+  pub votes:   Vec<(NodeId, NodeId, Weight)>,
+}
+
+fn dump_subgraph<'a>(
+  context: &'a str,
+  subgraph: &'a Subgraph,
+  num_nodes: usize,
+) -> DumpSubgraph<'a> {
+  let mut edges = vec![];
+  for src_id in 0..num_nodes {
+    if let Some(data) = subgraph.meritrank_data.graph.get_node_data(src_id) {
+      for (dst_id, weight) in data.get_outgoing_edges() {
+        edges.push((src_id, dst_id, weight));
+      }
+    }
+  }
+
+  let polls = subgraph
+    .poll_store
+    .polls
+    .iter()
+    .map(|(poll, options)| DumpPoll {
+      poll:    *poll,
+      options: options.iter().copied().collect(),
+      votes:   subgraph
+        .poll_store
+        .votes
+        .get(poll)
+        .map(|votes| {
+          votes
+            .iter()
+            .map(|(user, vote)| (*user, vote.option, vote.weight))
+            .collect()
+        })
+        .unwrap_or_default(),
+    })
+    .collect();
+
+  DumpSubgraph {
+    context,
+    edges,
+    zero_opinion: &subgraph.zero_opinion,
+    cached_scores: subgraph
+      .cached_scores
+      .iter()
+      .map(|((ego, dst), score)| (*ego, *dst, *score))
+      .collect(),
+    cached_walks: subgraph.cached_walks.iter().map(|(ego, _)| *ego).collect(),
+    omit_neg_edges_scores: subgraph.omit_neg_edges_scores,
+    num_walks: subgraph.num_walks,
+    polls,
+  }
+}
+
+impl AugMultiGraph {
+  pub fn dump_state(&self) -> DumpState<'_> {
+    let nodes: Vec<&str> =
+      self.node_infos.iter().map(|info| info.name.as_str()).collect();
+    let subgraphs = self
+      .subgraphs
+      .iter()
+      .map(|(context, subgraph)| {
+        dump_subgraph(context, subgraph, nodes.len())
+      })
+      .collect();
+
+    DumpState {
+      nodes,
+      subgraphs,
+    }
+  }
+
+  /// Writes `dump_state` to `path` as JSON. Returns the number of dumped
+  /// subgraphs, or 0 if the file could not be written.
+  pub fn read_dump_state(
+    &self,
+    path: &str,
+  ) -> usize {
+    log_command!("{:?}", path);
+
+    let state = self.dump_state();
+    let count = state.subgraphs.len();
+
+    let json = match serde_json::to_vec(&state) {
+      Ok(x) => x,
+      Err(e) => {
+        log_error!("{}", e);
+        return 0;
+      },
+    };
+
+    match std::fs::write(path, json) {
+      Ok(()) => count,
+      Err(e) => {
+        log_error!("Failed to write {:?}: {}", path, e);
+        0
+      },
+    }
+  }
+}
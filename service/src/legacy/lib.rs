@@ -3,6 +3,7 @@ pub mod aug_graph;
 pub mod aug_multi_graph;
 pub mod bloom_filter;
 pub mod constants;
+pub mod dump;
 pub mod errors;
 pub mod log;
 pub mod new_server_ops;
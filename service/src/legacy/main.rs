@@ -2,6 +2,7 @@ pub mod astar;
 pub mod aug_multi_graph;
 pub mod bloom_filter;
 pub mod constants;
+pub mod dump;
 pub mod errors;
 pub mod log;
 pub mod nodes;
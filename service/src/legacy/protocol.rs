@@ -13,6 +13,7 @@ pub const CMD_NODE_LIST: &str = "node_list";
 pub const CMD_READ_NEW_EDGES_FILTER: &str = "read_new_edges_filter";
 pub const CMD_WRITE_NEW_EDGES_FILTER: &str = "write_new_edges_filter";
 pub const CMD_FETCH_NEW_EDGES: &str = "fetch_new_edges";
+pub const CMD_DUMP_STATE: &str = "dump_state";
 
 //  With context
 pub const CMD_NODE_SCORE: &str = "node_score";
@@ -98,6 +98,11 @@ fn request_from_command(command: &Command) -> Request {
         return Request::ReadNodeList;
       }
     },
+    CMD_DUMP_STATE => {
+      if let Ok(path) = rmp_serde::from_slice(command.payload.as_slice()) {
+        return Request::ReadDumpState(path);
+      }
+    },
     CMD_NODE_SCORE => {
       if let Ok((ego, target)) =
         rmp_serde::from_slice(command.payload.as_slice())
@@ -209,6 +214,7 @@ fn encode_response_dispatch(
     Response::Connections(connections) => encode_response(&connections),
     Response::Edges(edges) => encode_response(&edges),
     Response::NewEdges(new_edges) => encode_response(&new_edges),
+    Response::DumpedContexts(count) => encode_response(&count),
     _ => encode_response(&()),
   }
 }
@@ -232,7 +238,8 @@ fn decode_and_handle_request(
       || command.id == CMD_NODE_LIST
       || command.id == CMD_READ_NEW_EDGES_FILTER
       || command.id == CMD_WRITE_NEW_EDGES_FILTER
-      || command.id == CMD_FETCH_NEW_EDGES)
+      || command.id == CMD_FETCH_NEW_EDGES
+      || command.id == CMD_DUMP_STATE)
   {
     let err_msg = "Context should be empty.".to_string();
     log_error!("{}", err_msg);
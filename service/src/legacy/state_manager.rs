@@ -16,6 +16,7 @@ pub enum Request {
   //  TODO: Factor out types.
   None,
   ReadNodeList,
+  ReadDumpState(String),
   ReadNewEdgesFilter(String),
   ReadNodeScore(String, String, String),
   ReadScores(
@@ -76,6 +77,7 @@ pub enum Response {
   Connections(Vec<(String, String)>),
   Edges(Vec<(String, String, Weight)>),
   NewEdges(Vec<(String, Weight, Weight, Cluster, Cluster)>),
+  DumpedContexts(usize),
 }
 
 #[derive(Default, Clone)]
@@ -121,6 +123,10 @@ fn perform_request(
   match request {
     Request::ReadNodeList => Response::NodeList(graph.read_node_list()),
 
+    Request::ReadDumpState(path) => {
+      Response::DumpedContexts(graph.read_dump_state(path))
+    },
+
     Request::ReadNewEdgesFilter(src) => {
       Response::NewEdgesFilter(graph.read_new_edges_filter(src))
     },
@@ -50,9 +50,8 @@ impl Subgraph {
       if old_ego != ego {
         log_verbose!("Drop walks {}", old_ego);
 
-        // Clear the evicted ego's walk block and counters (no new walks run).
-        match self.meritrank_data.clear_ego(old_ego) {
-          Ok(()) => {},
+        match self.meritrank_data.drop_walks(old_ego) {
+          Ok(_) => {},
           Err(e) => {
             log_error!("{}", e);
           },
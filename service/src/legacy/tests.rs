@@ -2220,3 +2220,30 @@ fn read_neighbors_poll_results() {
   let res = &results[3];
   assert_approx_eq!(res.3, 0.045 as Weight, 0.2); // warring users diminish each other's vote 
 }
+
+#[test]
+fn dump_state() {
+  let mut graph = default_graph();
+
+  graph.write_create_context("X");
+  graph.write_put_edge("X", "U1", "U2", 1.0, -1);
+  graph.write_put_edge("X", "V1", "P1", 1.0, -1);
+  graph.write_put_edge("X", "U1", "V1", 1.0, -1);
+  graph.read_scores("X", "U1", "U", false, 10.0, false, 0.0, false, 0, 10);
+
+  let state = serde_json::to_value(graph.dump_state()).unwrap();
+
+  let nodes = state["nodes"].as_array().unwrap();
+  assert!(nodes.contains(&"U1".into()));
+
+  let subgraph = state["subgraphs"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .find(|s| s["context"] == "X")
+    .unwrap();
+  assert_eq!(subgraph["edges"].as_array().unwrap().len(), 3);
+  assert_eq!(subgraph["cached_walks"].as_array().unwrap().len(), 1);
+  assert_eq!(subgraph["polls"].as_array().unwrap().len(), 1);
+  assert_eq!(subgraph["polls"][0]["votes"].as_array().unwrap().len(), 1);
+}
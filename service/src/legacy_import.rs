@@ -0,0 +1,252 @@
+//! Import of the state of the legacy service, see `src/legacy` and the
+//! `migrate` subcommand.
+//!
+//! A legacy state file, written by the `dump_state` command of the legacy
+//! service (`src/legacy/dump.rs`), is a JSON document with the node names of
+//! the legacy service, indexed by node id, and the fields of each of its
+//! `Subgraph`s: the edges of the graph, the zero opinion vector, the score
+//! and walk caches, the settings and the polls. Every subgraph is loaded into an
+//! `AugGraph` and saved to the state dir, from which the service loads it on
+//! startup like after a warm restart.
+
+use crate::aug_graph::AugGraph;
+use crate::data::*;
+use crate::settings::Settings;
+use crate::state_store::StateStore;
+use crate::utils::log::*;
+
+use meritrank_core::NodeId;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyState {
+  /// Node names, indexed by the node ids of the subgraphs.
+  pub nodes:     Vec<NodeName>,
+  pub subgraphs: Vec<LegacySubgraph>,
+}
+
+/// Fields of a legacy `Subgraph`, with node ids of `LegacyState::nodes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacySubgraph {
+  pub context:               SubgraphName,
+  /// Source, destination and weight of the edges of `meritrank_data`.
+  pub edges:                 Vec<(NodeId, NodeId, Weight)>,
+  /// Zero opinion of each node, indexed by node id.
+  pub zero_opinion:          Vec<Weight>,
+  /// Ego, destination and score of the entries of `cached_scores`.
+  pub cached_scores:         Vec<(NodeId, NodeId, Weight)>,
+  /// Egos of `cached_walks`.
+  pub cached_walks:          Vec<NodeId>,
+  pub omit_neg_edges_scores: bool,
+  pub num_walks:             usize,
+  pub polls:                 Vec<LegacyPoll>,
+}
+
+/// Poll of a legacy `PollStore`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyPoll {
+  pub poll:    NodeId,
+  pub options: Vec<NodeId>,
+  /// User, option and weight of each vote.
+  pub votes:   Vec<(NodeId, NodeId, Weight)>,
+}
+
+pub fn read_legacy_state(path: &Path) -> Result<LegacyState, ServiceError> {
+  let failed = |e: String| ServiceError::ImportFailed(e);
+  let bytes = fs::read(path)
+    .map_err(|e| failed(format!("Failed to open {:?}: {}", path, e)))?;
+  serde_json::from_slice(&bytes)
+    .map_err(|e| failed(format!("Invalid legacy state {:?}: {}", path, e)))
+}
+
+/// Loads the subgraph into a graph with its settings. Egos whose walks or
+/// scores were cached are calculated, so they are warm once loaded; cached
+/// scores themselves are not kept, since the new walks are sampled anew.
+/// Score clusters are calculated on demand.
+pub fn legacy_aug_graph(
+  settings: &Settings,
+  nodes: &[NodeName],
+  subgraph: &LegacySubgraph,
+) -> AugGraph {
+  let name = |id: &NodeId| {
+    let name = nodes.get(*id).cloned();
+    if name.is_none() {
+      log_warning!("Unknown legacy node {} in {:?}", id, subgraph.context);
+    }
+    name
+  };
+
+  let edges = subgraph
+    .edges
+    .iter()
+    .filter_map(|(src, dst, weight)| {
+      Some(OpWriteEdge {
+        src:        name(src)?,
+        dst:        name(dst)?,
+        amount:     *weight,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      })
+    })
+    .collect();
+  let zero_opinion = subgraph
+    .zero_opinion
+    .iter()
+    .enumerate()
+    .filter(|(_, score)| **score != 0.0)
+    .filter_map(|(id, score)| Some((name(&id)?, *score)))
+    .collect();
+  let polls = subgraph
+    .polls
+    .iter()
+    .filter_map(|poll| {
+      Some(PollSnapshot {
+        poll:       name(&poll.poll)?,
+        options:    poll.options.iter().filter_map(name).collect(),
+        votes:      poll
+          .votes
+          .iter()
+          .filter_map(|(user, option, weight)| {
+            Some((name(user)?, name(option)?, *weight))
+          })
+          .collect(),
+        expires_at: 0,
+        anonymous:  false,
+        tallies:    vec![],
+        results:    None,
+      })
+    })
+    .collect();
+
+  let snapshot = ContextSnapshot {
+    context: subgraph.context.clone(),
+    edges,
+    zero_opinion,
+    num_clusters: settings.num_score_quantiles,
+    user_params: vec![],
+    nodes: vec![],
+    mutes: vec![],
+    polls,
+  };
+  let mut aug_graph = AugGraph::from_snapshot(
+    Settings {
+      num_walks: subgraph.num_walks,
+      omit_neg_edges_scores: subgraph.omit_neg_edges_scores,
+      ..settings.clone()
+    },
+    snapshot,
+  );
+
+  let egos: BTreeSet<NodeId> = subgraph
+    .cached_walks
+    .iter()
+    .copied()
+    .chain(subgraph.cached_scores.iter().map(|(ego, _, _)| *ego))
+    .collect();
+  for ego in egos.iter().filter_map(name) {
+    aug_graph.calculate(ego);
+  }
+  aug_graph
+}
+
+/// Saves the contexts of the legacy state file to the state dir. Returns the
+/// number of contexts.
+pub fn migrate(
+  settings: &Settings,
+  source: &Path,
+) -> Result<usize, ServiceError> {
+  if settings.state_dir.is_empty() {
+    return Err(ServiceError::ImportFailed(
+      "MERITRANK_STATE_DIR is not set".into(),
+    ));
+  }
+  let store = StateStore::new(settings.state_dir.clone().into())
+    .map_err(|e| ServiceError::ImportFailed(e.to_string()))?;
+  let state = read_legacy_state(source)?;
+
+  for subgraph in &state.subgraphs {
+    if subgraph.num_walks != settings.num_walks {
+      log_warning!(
+        "Context {:?} has {} walks per ego, see MERITRANK_NUM_WALKS",
+        subgraph.context,
+        subgraph.num_walks
+      );
+    }
+    if subgraph.omit_neg_edges_scores != settings.omit_neg_edges_scores {
+      log_warning!(
+        "Context {:?} has MERITRANK_OMIT_NEG_EDGES_SCORES {}",
+        subgraph.context,
+        subgraph.omit_neg_edges_scores
+      );
+    }
+    let aug_graph = legacy_aug_graph(settings, &state.nodes, subgraph);
+    let num_egos = store
+      .save(&subgraph.context, &aug_graph)
+      .map_err(|e| ServiceError::ImportFailed(e.to_string()))?;
+    log_info!(
+      "Migrated context {:?} with walks of {} egos",
+      subgraph.context,
+      num_egos
+    );
+  }
+  Ok(state.subgraphs.len())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn migrate_loads_legacy_state() {
+    let dir = std::env::temp_dir()
+      .join(format!("meritrank-legacy-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("legacy.json");
+    let state = LegacyState {
+      nodes:     ["U1", "U2", "B1", "P1", "V1"].map(String::from).to_vec(),
+      subgraphs: vec![LegacySubgraph {
+        context:               "ctx".into(),
+        edges:                 vec![(0, 1, 1.0), (1, 0, 1.0), (0, 2, 2.0)],
+        zero_opinion:          vec![0.5, 0.25],
+        cached_scores:         vec![(1, 0, 0.4)],
+        cached_walks:          vec![0],
+        omit_neg_edges_scores: false,
+        num_walks:             100,
+        polls:                 vec![LegacyPoll {
+          poll:    3,
+          options: vec![4],
+          votes:   vec![(0, 4, 1.0)],
+        }],
+      }],
+    };
+    fs::write(&source, serde_json::to_vec(&state).unwrap()).unwrap();
+
+    let settings = Settings {
+      num_walks: 100,
+      state_dir: dir.join("state").to_string_lossy().into(),
+      ..Settings::default()
+    };
+    assert_eq!(migrate(&settings, &source), Ok(1));
+
+    let store = StateStore::new(settings.state_dir.clone().into()).unwrap();
+    let contexts = store.load_all(&settings);
+    assert_eq!(contexts.len(), 1);
+    let (context, aug_graph) = &contexts[0];
+    assert_eq!(context, "ctx");
+    let snapshot = aug_graph.snapshot(context.clone());
+    assert_eq!(snapshot.edges.len(), 3);
+    assert_eq!(snapshot.zero_opinion.len(), 2);
+    assert_eq!(
+      snapshot.polls[0].votes,
+      vec![("U1".to_string(), "V1".to_string(), 1.0)]
+    );
+    //  Egos of both caches were calculated, so their walks were saved.
+    assert_eq!(aug_graph.mr.get_personal_hits().len(), 2);
+  }
+}
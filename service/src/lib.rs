@@ -1,12 +1,34 @@
 pub mod aug_graph;
+pub mod auth;
+pub mod cache_sizing;
+pub mod cold_storage;
 pub mod data;
+pub mod export;
+pub mod file_import;
 pub mod helpers;
+pub mod history;
+pub mod idempotency;
+pub mod legacy_import;
 pub mod node_registry;
+pub mod op_reply;
 pub mod processor_stats;
+pub mod protocol;
+pub mod publish_barrier;
+pub mod read_pool;
+pub mod replication;
 pub mod request_handler;
+pub mod router;
 pub mod rpc_sync;
 pub mod settings;
+pub mod slow_log;
+pub mod sql_import;
 pub mod state_manager;
+pub mod state_store;
+pub mod telemetry;
+pub mod tls;
 pub mod utils;
 pub mod vsids;
+pub mod walk_budget;
 pub mod walk_tracker;
+pub mod warming;
+pub mod webhooks;
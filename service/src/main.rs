@@ -1,36 +1,163 @@
+use meritrank_service::data::Response;
+use meritrank_service::legacy_import::migrate;
 use meritrank_service::processor_stats::ProcessorStats;
+use meritrank_service::replication::{run_replica, run_replication_server};
 use meritrank_service::request_handler::run_server;
+use meritrank_service::router::Router;
 use meritrank_service::settings::load_from_env;
 use meritrank_service::state_manager::MultiGraphProcessor;
+use meritrank_service::telemetry::init_telemetry;
 use meritrank_service::utils::log::{init_log_cmd_from_env, *};
 
 use tokio_util::sync::CancellationToken;
 
-use std::{error::Error, sync::Arc};
+use std::{error::Error, path::Path, sync::Arc};
 
 /// Max samples to keep when stats collection is enabled (env MERITRANK_COLLECT_STATS).
 const DEFAULT_STATS_MAX_SAMPLES: usize = 50_000;
 
+/// Path of the edge list given with `--import <path>`, see `file_import`.
+fn import_arg() -> Option<String> {
+  let mut args = std::env::args().skip(1);
+  while let Some(arg) = args.next() {
+    if arg == "--import" {
+      return args.next();
+    }
+    if let Some(path) = arg.strip_prefix("--import=") {
+      return Some(path.into());
+    }
+  }
+  None
+}
+
+/// Legacy state file given with `migrate <path>`, see `legacy_import`.
+fn migrate_arg() -> Result<Option<String>, String> {
+  let mut args = std::env::args().skip(1);
+  match args.next() {
+    Some(arg) if arg == "migrate" => match args.next() {
+      Some(path) => Ok(Some(path)),
+      None => Err("Usage: migrate <legacy state file>".into()),
+    },
+    _ => Ok(None),
+  }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
   init_log_cmd_from_env();
+
+  //  The first signal stops the server so that the state can be saved, the
+  //  second one exits right away.
+  let running = CancellationToken::new();
+  let stop = running.clone();
   let _ = ctrlc::set_handler(move || {
     println!();
-    std::process::exit(0);
+    if stop.is_cancelled() {
+      std::process::exit(0);
+    }
+    stop.cancel();
   });
 
   log_info!("MeritRank Service");
 
   let settings = load_from_env();
+  set_log_format(settings.log_format);
+  let _telemetry = init_telemetry(&settings);
+
+  if let Some(path) = migrate_arg()? {
+    let n = migrate(&settings, Path::new(&path))
+      .map_err(|e| format!("Migration failed: {:?}", e))?;
+    log_info!("Migrated {} contexts to {:?}", n, settings.state_dir);
+    return Ok(());
+  }
+
+  if !settings.shards.is_empty() {
+    log_info!("Routing to {} shards", settings.shards.len());
+    let router = Arc::new(Router::new(&settings)?);
+    let _ = run_server(settings, router, running).await;
+    return Ok(());
+  }
+
+  let stats = settings
+    .collect_stats
+    .then(|| Arc::new(ProcessorStats::new(DEFAULT_STATS_MAX_SAMPLES)));
+  let processor =
+    Arc::new(MultiGraphProcessor::new_with_stats(settings.clone(), stats));
+
+  let restored = processor.load_state();
+
+  if settings.import_on_startup
+    && settings.replicate_from.is_empty()
+    && !restored
+  {
+    if let Response::Error(e) = processor.import_from_sql().await {
+      return Err(format!("Import failed: {:?}", e).into());
+    }
+  }
+
+  if let Some(path) = import_arg() {
+    if !settings.replicate_from.is_empty() {
+      log_warning!("--import is ignored by replicas");
+    } else if let Response::Error(e) =
+      processor.import_edge_list(path.into()).await
+    {
+      return Err(format!("Import failed: {:?}", e).into());
+    }
+  }
+
+  if settings.background_clustering {
+    tokio::spawn(Arc::clone(&processor).run_cluster_worker(running.clone()));
+  }
+
+  if settings.warm_egos > 0 {
+    tokio::spawn(Arc::clone(&processor).run_warming(running.clone()));
+  }
+
+  if settings.decay_half_life > 0 {
+    tokio::spawn(Arc::clone(&processor).run_decay(running.clone()));
+  }
+
+  if settings.rerank_threshold > 0.0 {
+    tokio::spawn(Arc::clone(&processor).run_rerank(running.clone()));
+  }
+
+  if settings.poll_archive_interval > 0 {
+    tokio::spawn(Arc::clone(&processor).run_poll_archiving(running.clone()));
+  }
+
+  if settings.webhook_interval > 0 {
+    tokio::spawn(Arc::clone(&processor).run_webhooks(running.clone()));
+  }
+
+  if settings.history_size > 0 {
+    tokio::spawn(
+      Arc::clone(&processor).run_history_sampling(running.clone()),
+    );
+  }
+
+  if settings.replication_port != 0 {
+    let settings = settings.clone();
+    let processor = Arc::clone(&processor);
+    let running = running.clone();
+    tokio::spawn(async move {
+      if let Err(e) = run_replication_server(settings, processor, running).await
+      {
+        log_error!("Replication server failed: {}", e);
+      }
+    });
+  }
+
+  if !settings.replicate_from.is_empty() {
+    tokio::spawn(run_replica(
+      settings.clone(),
+      Arc::clone(&processor),
+      running.clone(),
+    ));
+  }
 
-  let processor = if settings.collect_stats {
-    let stats = Arc::new(ProcessorStats::new(DEFAULT_STATS_MAX_SAMPLES));
-    Arc::new(MultiGraphProcessor::new_with_stats(settings.clone(), stats))
-  } else {
-    Arc::new(MultiGraphProcessor::new(settings.clone()))
-  };
+  let _ = run_server(settings, Arc::clone(&processor), running).await;
 
-  let _ = run_server(settings, processor, CancellationToken::new()).await;
+  processor.save_state().await;
 
   Ok(())
 }
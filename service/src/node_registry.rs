@@ -2,15 +2,31 @@ use crate::data::*;
 use crate::utils::log::*;
 
 use meritrank_core::{MeritRank, NodeId};
+use parking_lot::{const_rwlock, RwLock};
 
 use std::collections::HashMap;
 
+const BUILTIN_NODE_KINDS: [(char, NodeKind); 6] = [
+  ('U', NodeKind::User),
+  ('B', NodeKind::Beacon),
+  ('C', NodeKind::Comment),
+  ('O', NodeKind::Opinion),
+  ('V', NodeKind::PollVariant),
+  ('P', NodeKind::Poll),
+];
+
+/// Names of kinds registered with `register_node_kind`, by prefix.
+static CUSTOM_NODE_KINDS: RwLock<Vec<(char, String)>> =
+  const_rwlock(Vec::new());
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct NodeInfo {
-  pub id:    NodeId,
-  pub name:  NodeName,
-  pub kind:  NodeKind,
-  pub owner: Option<NodeId>,
+  pub id:        NodeId,
+  pub name:      NodeName,
+  pub kind:      NodeKind,
+  pub owner:     Option<NodeId>,
+  /// Removed by orphan collection; the id is not reused.
+  pub tombstone: bool,
 }
 
 #[derive(Clone)]
@@ -19,6 +35,14 @@ pub struct NodeRegistry {
   pub id_to_info:   Vec<NodeInfo>,
   pub kind_to_ids:  HashMap<NodeKind, Vec<NodeId>>,
   pub next_id:      NodeId,
+  /// Number of tombstoned nodes in `id_to_info`.
+  pub tombstones:   usize,
+}
+
+impl Default for NodeRegistry {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl NodeRegistry {
@@ -28,6 +52,7 @@ impl NodeRegistry {
       id_to_info:  Vec::new(),
       kind_to_ids: HashMap::new(),
       next_id:     0,
+      tombstones:  0,
     }
   }
 
@@ -53,6 +78,7 @@ impl NodeRegistry {
       name: name.clone(),
       kind,
       owner: None,
+      tombstone: false,
     };
     self.name_to_id.insert(name, id);
     self.id_to_info.push(info);
@@ -84,6 +110,7 @@ impl NodeRegistry {
       name: name.clone(),
       kind,
       owner: Some(owner),
+      tombstone: false,
     };
     self.name_to_id.insert(name, id);
     self.id_to_info.push(info);
@@ -96,7 +123,7 @@ impl NodeRegistry {
     &self,
     id: NodeId,
   ) -> Option<&NodeInfo> {
-    self.id_to_info.get(id)
+    self.id_to_info.get(id).filter(|info| !info.tombstone)
   }
 
   pub fn get_by_name(
@@ -109,25 +136,132 @@ impl NodeRegistry {
       .and_then(|&id| self.id_to_info.get(id))
   }
 
-  pub fn nodes_by_kind(
+  /// Points `new` at the node of `old`. The node keeps its id and edges,
+  /// `new` becomes its name, and `old` stays resolvable as an alias.
+  /// Returns None if `old` is unknown, `new` is of another kind, or `new`
+  /// is a name of another node.
+  pub fn rename(
+    &mut self,
+    old: &str,
+    new: NodeName,
+  ) -> Option<NodeId> {
+    let id = *self.name_to_id.get(old)?;
+    if node_kind_from_prefix(&new) != Some(self.id_to_info[id].kind) {
+      return None;
+    }
+    match self.name_to_id.get(&new) {
+      Some(&other) if other != id => return None,
+      Some(_) => {},
+      None => {
+        self.name_to_id.insert(new.clone(), id);
+      },
+    }
+    self.id_to_info[id].name = new;
+    Some(id)
+  }
+
+  /// Nodes that are not tombstoned, by id.
+  pub fn iter(&self) -> impl Iterator<Item = &NodeInfo> {
+    self.id_to_info.iter().filter(|info| !info.tombstone)
+  }
+
+  /// Number of nodes that are not tombstoned.
+  pub fn len(&self) -> usize {
+    self.id_to_info.len() - self.tombstones
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Forgets names and the kind of the nodes, keeping their ids taken.
+  /// A name of a tombstoned node registers a new node.
+  pub fn tombstone(
+    &mut self,
+    ids: &[NodeId],
+  ) {
+    let mut removed = vec![];
+    for &id in ids {
+      match self.id_to_info.get_mut(id) {
+        Some(info) if !info.tombstone => {
+          info.tombstone = true;
+          removed.push(id);
+        },
+        _ => {},
+      }
+    }
+    if removed.is_empty() {
+      return;
+    }
+    self.tombstones += removed.len();
+    let id_to_info = &self.id_to_info;
+    //  Also drops aliases of renamed nodes.
+    self.name_to_id.retain(|_, id| !id_to_info[*id].tombstone);
+    for kind_ids in self.kind_to_ids.values_mut() {
+      kind_ids.retain(|id| !id_to_info[*id].tombstone);
+    }
+  }
+
+  /// Ids of nodes of the kind, in registration order.
+  pub fn ids_by_kind(
     &self,
     kind: NodeKind,
   ) -> &[NodeId] {
     self.kind_to_ids.get(&kind).map(Vec::as_slice).unwrap_or(&[])
   }
+
+  /// Number of registered nodes of each kind.
+  pub fn counts_by_kind(&self) -> HashMap<NodeKind, usize> {
+    self
+      .kind_to_ids
+      .iter()
+      .map(|(kind, ids)| (*kind, ids.len()))
+      .collect()
+  }
 }
 
 pub fn node_kind_from_prefix(name: &str) -> Option<NodeKind> {
-  if name.is_empty() {
+  let prefix = name.chars().next()?;
+  if let Some((_, kind)) = BUILTIN_NODE_KINDS.iter().find(|(c, _)| *c == prefix)
+  {
+    return Some(*kind);
+  }
+  CUSTOM_NODE_KINDS
+    .read()
+    .iter()
+    .find(|(c, _)| *c == prefix)
+    .map(|_| NodeKind::Custom(prefix))
+}
+
+/// Registers a node kind for names starting with `prefix`, e.g. `A` for
+/// articles. Should be called at startup, before any node of the kind is
+/// written. Returns None if the prefix is taken by another kind.
+pub fn register_node_kind(
+  prefix: char,
+  name: &str,
+) -> Option<NodeKind> {
+  if BUILTIN_NODE_KINDS.iter().any(|(c, _)| *c == prefix) {
     return None;
   }
-  match name.chars().next() {
-    Some('U') => Some(NodeKind::User),
-    Some('B') => Some(NodeKind::Beacon),
-    Some('C') => Some(NodeKind::Comment),
-    Some('O') => Some(NodeKind::Opinion),
-    Some('V') => Some(NodeKind::PollVariant),
-    Some('P') => Some(NodeKind::Poll),
-    _ => None,
+  let mut kinds = CUSTOM_NODE_KINDS.write();
+  match kinds.iter().find(|(c, _)| *c == prefix) {
+    Some((_, existing)) if existing != name => return None,
+    Some(_) => {},
+    None => kinds.push((prefix, name.to_string())),
   }
+  Some(NodeKind::Custom(prefix))
+}
+
+/// Built-in kinds followed by registered ones.
+pub fn all_node_kinds() -> Vec<NodeKind> {
+  BUILTIN_NODE_KINDS
+    .iter()
+    .map(|(_, kind)| *kind)
+    .chain(
+      CUSTOM_NODE_KINDS
+        .read()
+        .iter()
+        .map(|(c, _)| NodeKind::Custom(*c)),
+    )
+    .collect()
 }
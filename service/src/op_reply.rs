@@ -0,0 +1,110 @@
+//! Answer of the writer to the request that queued an op.
+//!
+//! Both copies of a context apply every op, to the same state, so the first
+//! one to apply the op answers and the other one finds the answer sent.
+
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use parking_lot::Mutex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::sync::oneshot;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Tells the request whether the op was applied, e.g. the compare-and-set of
+/// a `WriteEdgeCas`. Not encoded: replicated ops answer nobody.
+#[derive(Clone, Default)]
+pub struct OpReply {
+  tx: Option<Arc<Mutex<Option<oneshot::Sender<bool>>>>>,
+}
+
+impl OpReply {
+  /// Reply to attach to the op, and the receiver of the answer. The receiver
+  /// fails if the op is dropped unapplied, e.g. if it panicked.
+  pub fn new() -> (Self, oneshot::Receiver<bool>) {
+    let (tx, rx) = oneshot::channel();
+    let reply = OpReply {
+      tx: Some(Arc::new(Mutex::new(Some(tx)))),
+    };
+    (reply, rx)
+  }
+
+  pub fn send(
+    &self,
+    applied: bool,
+  ) {
+    if let Some(tx) = self.tx.as_ref().and_then(|tx| tx.lock().take()) {
+      let _ = tx.send(applied);
+    }
+  }
+}
+
+impl fmt::Debug for OpReply {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    write!(f, "OpReply")
+  }
+}
+
+impl Encode for OpReply {
+  fn encode<E: Encoder>(
+    &self,
+    _encoder: &mut E,
+  ) -> Result<(), EncodeError> {
+    Ok(())
+  }
+}
+
+impl<Context> Decode<Context> for OpReply {
+  fn decode<D: Decoder<Context = Context>>(
+    _decoder: &mut D
+  ) -> Result<Self, DecodeError> {
+    Ok(OpReply::default())
+  }
+}
+
+bincode::impl_borrow_decode!(OpReply);
+
+impl Serialize for OpReply {
+  fn serialize<S: Serializer>(
+    &self,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    serializer.serialize_unit()
+  }
+}
+
+impl<'de> Deserialize<'de> for OpReply {
+  fn deserialize<D: Deserializer<'de>>(
+    deserializer: D
+  ) -> Result<Self, D::Error> {
+    <()>::deserialize(deserializer)?;
+    Ok(OpReply::default())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn first_copy_answers() {
+    let (reply, mut rx) = OpReply::new();
+    let other_copy = reply.clone();
+    reply.send(true);
+    other_copy.send(false);
+    assert_eq!(rx.try_recv(), Ok(true));
+
+    let config = bincode::config::standard();
+    let bytes = bincode::encode_to_vec(&reply, config).unwrap();
+    assert!(bytes.is_empty());
+    let (decoded, _): (OpReply, _) =
+      bincode::decode_from_slice(&bytes, config).unwrap();
+    decoded.send(true);
+  }
+}
@@ -1,6 +1,6 @@
 //! Ops queue and processing-time stats for load testing and tuning.
 
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Mutex;
 use std::time::Duration;
 
@@ -14,6 +14,12 @@ pub struct StatsSnapshot {
   pub min_us:     u64,
   pub max_us:     u64,
   pub count:      usize,
+  /// Publishes of the back copy, ops published by them, and the largest batch.
+  pub publishes:  u64,
+  pub published:  u64,
+  pub max_batch:  usize,
+  /// Ops that panicked in the writer.
+  pub panics:     u64,
 }
 
 /// Collects ops pending count and per-op processing time samples for percentile reporting.
@@ -21,6 +27,10 @@ pub struct ProcessorStats {
   pub ops_pending: AtomicUsize,
   samples:         Mutex<Vec<Duration>>,
   max_samples:     usize,
+  publishes:       AtomicU64,
+  published:       AtomicU64,
+  max_batch:       AtomicUsize,
+  panics:          AtomicU64,
 }
 
 impl ProcessorStats {
@@ -29,12 +39,20 @@ impl ProcessorStats {
       ops_pending: AtomicUsize::new(0),
       samples:     Mutex::new(Vec::with_capacity(max_samples.min(4096))),
       max_samples,
+      publishes:   AtomicU64::new(0),
+      published:   AtomicU64::new(0),
+      max_batch:   AtomicUsize::new(0),
+      panics:      AtomicU64::new(0),
     }
   }
 
   /// Reset stats: clear samples and pending count (e.g. after warmup, before load phase).
   pub fn reset(&self) {
     self.ops_pending.store(0, Ordering::Relaxed);
+    self.publishes.store(0, Ordering::Relaxed);
+    self.published.store(0, Ordering::Relaxed);
+    self.max_batch.store(0, Ordering::Relaxed);
+    self.panics.store(0, Ordering::Relaxed);
     if let Ok(mut g) = self.samples.lock() {
       g.clear();
     }
@@ -60,9 +78,28 @@ impl ProcessorStats {
     }
   }
 
+  /// Call when the writer publishes a batch of ops (e.g. in processing_loop).
+  pub fn record_publish(
+    &self,
+    batch: usize,
+  ) {
+    self.publishes.fetch_add(1, Ordering::Relaxed);
+    self.published.fetch_add(batch as u64, Ordering::Relaxed);
+    self.max_batch.fetch_max(batch, Ordering::Relaxed);
+  }
+
+  /// Call when an op panics in the writer (e.g. in processing_loop).
+  pub fn record_panic(&self) {
+    self.panics.fetch_add(1, Ordering::Relaxed);
+  }
+
   /// Take a snapshot: current pending and percentiles over the sample buffer.
   pub fn snapshot(&self) -> StatsSnapshot {
     let pending = self.ops_pending.load(Ordering::Relaxed);
+    let publishes = self.publishes.load(Ordering::Relaxed);
+    let published = self.published.load(Ordering::Relaxed);
+    let max_batch = self.max_batch.load(Ordering::Relaxed);
+    let panics = self.panics.load(Ordering::Relaxed);
     let mut samples: Vec<Duration> = if let Ok(g) = self.samples.lock() {
       g.clone()
     } else {
@@ -78,6 +115,10 @@ impl ProcessorStats {
         min_us:    0,
         max_us:    0,
         count:     0,
+        publishes,
+        published,
+        max_batch,
+        panics,
       };
     }
     samples.sort();
@@ -97,6 +138,10 @@ impl ProcessorStats {
       min_us,
       max_us,
       count,
+      publishes,
+      published,
+      max_batch,
+      panics,
     }
   }
 }
@@ -0,0 +1,399 @@
+//! How requests are served, and adapters from the legacy wire format.
+//!
+//! `ReqData` is the one request type of the service. `route` decides how
+//! each request is served: reads are answered from the published copy of
+//! their context on the read pool, see `AugGraph::read_request`, writes to a
+//! single context are applied by its writer, and the rest is handled by the
+//! processor, e.g. writes to several contexts or requests about the service.
+//!
+//! Legacy clients (`src/legacy`) send msgpack commands, `(id, context,
+//! blocking, payload)` with a msgpack payload of the command arguments, and
+//! get the results as msgpack tuples. `legacy_request` and `legacy_response`
+//! convert them to and from `Request` and `Response`.
+
+use crate::data::*;
+use crate::node_registry::node_kind_from_prefix;
+
+/// How a request is served, see `route`.
+#[derive(Debug)]
+pub enum Route {
+  /// Answered from the published copy of the context on the read pool.
+  Read(ReqData),
+  /// Applied to the context by its writer.
+  Write(AugGraphOp),
+  /// Handled by the processor.
+  Processor(ReqData),
+}
+
+pub fn route(data: ReqData) -> Route {
+  use ReqData::*;
+  match data {
+    Stamp(value) => Route::Write(AugGraphOp::Stamp(value)),
+    WriteCalculate(data) => Route::Write(AugGraphOp::WriteCalculate(data)),
+    WriteZeroOpinion(data) => Route::Write(AugGraphOp::WriteZeroOpinion(data)),
+    WriteRecalculateClustering => {
+      Route::Write(AugGraphOp::WriteRecalculateClustering)
+    },
+    SetUserParams(data) => Route::Write(AugGraphOp::SetUserParams(data)),
+    WriteMute(data) => Route::Write(AugGraphOp::Mute(data)),
+    WritePoll(data) => Route::Write(AugGraphOp::WritePoll(data)),
+    ReadScores(_)
+    | ReadScoresChunked(_)
+    | ReadNodeScore(_)
+    | ReadGraph(_)
+    | ReadNeighbors(_)
+    | ReadNodeList
+    | ReadMutes(_)
+    | ReadEdges
+    | ReadConnected(_)
+    | ReadCompareEgos(_)
+    | ReadRecommendations(_)
+    | ReadAnomalies(_)
+    | ReadTopNodes(_)
+    | ReadBeaconComments(_)
+    | EvaluateSybilResistance(_)
+    | ReadMutualSuggestions(_)
+    | ReadScoreHistory(_)
+    | ReadScoresAt(_)
+    | ReadScoreDiff(_)
+    | ReadDryRun(_)
+    | ReadMutualScores(_)
+    | DebugEgo(_)
+    | WriteFetchNewEdges(_)
+    | WriteNewEdgesFilter(_)
+    | ReadNewEdgesFilter(_) => Route::Read(data),
+    data => Route::Processor(data),
+  }
+}
+
+/// Legacy command names, see `legacy/protocol.rs`.
+const CMD_SYNC: &str = "sync";
+const CMD_RESET: &str = "reset";
+const CMD_RECALCULATE_CLUSTERING: &str = "recalculate_clustering";
+const CMD_NODE_LIST: &str = "node_list";
+const CMD_READ_NEW_EDGES_FILTER: &str = "read_new_edges_filter";
+const CMD_WRITE_NEW_EDGES_FILTER: &str = "write_new_edges_filter";
+const CMD_FETCH_NEW_EDGES: &str = "fetch_new_edges";
+const CMD_NODE_SCORE: &str = "node_score";
+const CMD_SCORES: &str = "scores";
+const CMD_PUT_EDGE: &str = "put_edge";
+const CMD_DELETE_EDGE: &str = "delete_edge";
+const CMD_DELETE_NODE: &str = "delete_node";
+const CMD_GRAPH: &str = "graph";
+const CMD_CONNECTED: &str = "connected";
+const CMD_EDGES: &str = "edges";
+const CMD_MUTUAL_SCORES: &str = "mutual_scores";
+const CMD_CREATE_CONTEXT: &str = "create_context";
+const CMD_SET_ZERO_OPINION: &str = "set_zero_opinion";
+const CMD_NEIGHBORS: &str = "neighbors";
+
+/// Request of a legacy msgpack command. Commands the service no longer has,
+/// e.g. `version` and `log_level`, are errors.
+pub fn legacy_request(buf: &[u8]) -> Result<Request, String> {
+  let (id, context, _blocking, payload): (String, String, bool, Vec<u8>) =
+    rmp_serde::from_slice(buf).map_err(|e| e.to_string())?;
+  let args = |e: rmp_serde::decode::Error| {
+    format!("Invalid payload for command {:?}: {}", id, e)
+  };
+  let payload = payload.as_slice();
+
+  let data = match id.as_str() {
+    CMD_SYNC => ReqData::Sync(0),
+    CMD_RESET => ReqData::WriteReset,
+    CMD_RECALCULATE_CLUSTERING => ReqData::WriteRecalculateClustering,
+    CMD_NODE_LIST => ReqData::ReadNodeList,
+    CMD_CREATE_CONTEXT => ReqData::WriteCreateContext,
+    CMD_EDGES => ReqData::ReadEdges,
+    CMD_READ_NEW_EDGES_FILTER => {
+      let src = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::ReadNewEdgesFilter(OpReadNewEdgesFilter {
+        src,
+      })
+    },
+    CMD_WRITE_NEW_EDGES_FILTER => {
+      let (src, filter) = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::WriteNewEdgesFilter(OpWriteNewEdgesFilter {
+        src,
+        filter,
+      })
+    },
+    CMD_FETCH_NEW_EDGES => {
+      let (src, prefix) = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::WriteFetchNewEdges(OpWriteFetchNewEdges {
+        src,
+        prefix,
+      })
+    },
+    CMD_SET_ZERO_OPINION => {
+      let (node, score) = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::WriteZeroOpinion(OpWriteZeroOpinion {
+        node,
+        score,
+      })
+    },
+    CMD_PUT_EDGE => {
+      let (src, dst, amount, index): (NodeName, NodeName, Weight, i64) =
+        rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::WriteEdge(OpWriteEdge {
+        src,
+        dst,
+        amount,
+        magnitude: index.max(0) as u32,
+        edge_kind: EdgeKind::Vote,
+        provenance: None,
+      })
+    },
+    CMD_DELETE_EDGE => {
+      let (src, dst, index) = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::WriteDeleteEdge(OpWriteDeleteEdge {
+        src,
+        dst,
+        index,
+      })
+    },
+    CMD_DELETE_NODE => {
+      let (node, index) = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::WriteDeleteNode(OpWriteDeleteNode {
+        node,
+        index,
+      })
+    },
+    CMD_NODE_SCORE => {
+      let (ego, target) = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::ReadNodeScore(OpReadNodeScore {
+        ego,
+        target,
+      })
+    },
+    CMD_SCORES => {
+      let (ego, kind, hide_personal, lt, lte, gt, gte, index, count): (
+        NodeName,
+        String,
+        bool,
+        Weight,
+        bool,
+        Weight,
+        bool,
+        u32,
+        u32,
+      ) = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::ReadScores(OpReadScores {
+        ego,
+        score_options: FilterOptions {
+          //  Legacy kinds are prefixes, `""` for any.
+          node_kind: node_kind_from_prefix(&kind),
+          hide_personal,
+          score_lt: lt,
+          score_lte: lte,
+          score_gt: gt,
+          score_gte: gte,
+          index,
+          count,
+          include_reverse_scores: true,
+          ..FilterOptions::default()
+        },
+      })
+    },
+    CMD_GRAPH => {
+      let (ego, focus, positive_only, index, count): (_, _, _, u32, u32) =
+        rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::ReadGraph(OpReadGraph {
+        ego,
+        focus,
+        positive_only,
+        index: index as u64,
+        count: count as u64,
+      })
+    },
+    CMD_CONNECTED => {
+      let node = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::ReadConnected(OpReadConnected {
+        node,
+      })
+    },
+    CMD_MUTUAL_SCORES => {
+      let ego = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::ReadMutualScores(OpReadMutualScores {
+        ego,
+      })
+    },
+    CMD_NEIGHBORS => {
+      let (
+        ego,
+        focus,
+        direction,
+        kind,
+        hide_personal,
+        lt,
+        lte,
+        gt,
+        gte,
+        index,
+        count,
+      ): (
+        NodeName,
+        NodeName,
+        i64,
+        String,
+        bool,
+        Weight,
+        bool,
+        Weight,
+        bool,
+        u32,
+        u32,
+      ) = rmp_serde::from_slice(payload).map_err(args)?;
+      ReqData::ReadNeighbors(OpReadNeighbors {
+        ego,
+        focus,
+        direction,
+        kind: node_kind_from_prefix(&kind),
+        edge_kind: None,
+        hide_personal,
+        lt,
+        lte,
+        gt,
+        gte,
+        index,
+        count,
+      })
+    },
+    _ => return Err(format!("Unsupported legacy command {:?}", id)),
+  };
+
+  Ok(Request {
+    subgraph: context,
+    token: String::new(),
+    data,
+  })
+}
+
+/// Legacy msgpack encoding of the response: the rows as tuples, `()` for
+/// other successful responses, and an error message for failures.
+pub fn legacy_response(response: Response) -> Vec<u8> {
+  let encoded = match response {
+    Response::Scores(res) => rmp_serde::to_vec(
+      &res
+        .scores
+        .into_iter()
+        .map(|x| {
+          (
+            x.ego,
+            x.target,
+            x.score,
+            x.reverse_score,
+            x.cluster,
+            x.reverse_cluster,
+          )
+        })
+        .collect::<Vec<_>>(),
+    ),
+    Response::Graph(res) => rmp_serde::to_vec(
+      &res
+        .graph
+        .into_iter()
+        .map(|x| {
+          (
+            x.src,
+            x.dst,
+            x.weight,
+            x.score,
+            x.reverse_score,
+            x.cluster,
+            x.reverse_cluster,
+          )
+        })
+        .collect::<Vec<_>>(),
+    ),
+    Response::NodeList(res) => rmp_serde::to_vec(&res.nodes),
+    Response::Connections(res) => rmp_serde::to_vec(
+      &res
+        .connections
+        .into_iter()
+        .map(|x| (x.src, x.dst))
+        .collect::<Vec<_>>(),
+    ),
+    Response::Edges(res) => rmp_serde::to_vec(
+      &res
+        .edges
+        .into_iter()
+        .map(|x| (x.src, x.dst, x.weight))
+        .collect::<Vec<_>>(),
+    ),
+    Response::Fail | Response::NotImplemented | Response::Error(_) => {
+      return legacy_error("Internal error, see server logs");
+    },
+    _ => rmp_serde::to_vec(&()),
+  };
+  encoded.unwrap_or_else(|e| legacy_error(&e.to_string()))
+}
+
+/// Legacy clients get errors as a message in place of the result.
+pub fn legacy_error(message: &str) -> Vec<u8> {
+  rmp_serde::to_vec(message).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn routes_reads_writes_and_the_rest() {
+    let read = ReqData::ReadEdges;
+    assert!(matches!(route(read), Route::Read(ReqData::ReadEdges)));
+    let zero = ReqData::WriteZeroOpinion(OpWriteZeroOpinion {
+      node:  "U1".into(),
+      score: 0.5,
+    });
+    assert!(matches!(
+      route(zero),
+      Route::Write(AugGraphOp::WriteZeroOpinion(_))
+    ));
+    assert!(matches!(
+      route(ReqData::WriteReset),
+      Route::Processor(ReqData::WriteReset)
+    ));
+  }
+
+  #[test]
+  fn legacy_commands_become_requests() {
+    let command = |id: &str, payload: Vec<u8>| {
+      rmp_serde::to_vec(&(id, "ctx", true, payload)).unwrap()
+    };
+    let put = rmp_serde::to_vec(&("U1", "U2", 1.5, -1i64)).unwrap();
+    let req = legacy_request(&command(CMD_PUT_EDGE, put)).unwrap();
+    assert_eq!(req.subgraph, "ctx");
+    match req.data {
+      ReqData::WriteEdge(data) => {
+        assert_eq!((data.src, data.dst), ("U1".into(), "U2".into()));
+        assert_eq!((data.amount, data.magnitude), (1.5, 0));
+      },
+      other => panic!("expected WriteEdge, got {:?}", other),
+    }
+
+    let scores = rmp_serde::to_vec(&(
+      "U1", "B", false, 100.0, false, 0.0, true, 0u32, 10u32,
+    ))
+    .unwrap();
+    match legacy_request(&command(CMD_SCORES, scores)).unwrap().data {
+      ReqData::ReadScores(data) => {
+        let options = data.score_options;
+        assert_eq!(options.node_kind, Some(NodeKind::Beacon));
+        assert_eq!((options.score_lt, options.count), (100.0, 10));
+      },
+      other => panic!("expected ReadScores, got {:?}", other),
+    }
+    assert!(legacy_request(&command("version", vec![])).is_err());
+
+    let rows = legacy_response(Response::Edges(ResEdges {
+      edges: vec![EdgeResult {
+        src:    "U1".into(),
+        dst:    "U2".into(),
+        weight: 1.5,
+      }],
+    }));
+    let rows: Vec<(String, String, f64)> =
+      rmp_serde::from_slice(&rows).unwrap();
+    assert_eq!(rows, vec![("U1".into(), "U2".into(), 1.5)]);
+  }
+}
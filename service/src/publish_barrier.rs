@@ -0,0 +1,333 @@
+//! Coordinated publish of the contexts of a `WriteMulti`.
+//!
+//! Each context gets its part as one `AugGraphOp::Staged` op. The writer of
+//! a context stages the part on its back copy once the copy has no other
+//! unpublished ops, checks it, and votes at the shared `PublishBarrier`. The
+//! parts are published only if all the contexts vote to. If a context
+//! rejects its part, or doesn't vote in time, every writer drops the staged
+//! part by syncing its back copy from the front one, so no context changes.
+//!
+//! On replicas, the parts wait for the outcome on the primary, which is
+//! replicated as a `ReplicationRecord::StagedOutcome`.
+
+use crate::data::*;
+
+use bincode::de::Decoder;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{Decode, Encode};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::sync::oneshot;
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a writer waits for the other contexts to vote before it drops
+/// its part.
+pub const STAGED_VOTE_TIMEOUT_MSEC: u64 = 1_000;
+
+type OnDecide = Box<dyn FnOnce(bool) + Send>;
+
+struct BarrierState {
+  votes:     usize,
+  published: usize,
+  /// Whether the parts are published, once all the contexts voted to, or
+  /// one of them rejected its part.
+  decision:  Option<bool>,
+  /// Gets the response to the request: `Ok` once every part is published,
+  /// or the reason the parts were dropped.
+  done:      Option<oneshot::Sender<Response>>,
+  on_decide: Option<OnDecide>,
+}
+
+/// Where the writers of the contexts of a `WriteMulti` vote on publishing
+/// their parts.
+pub struct PublishBarrier {
+  parties: usize,
+  state:   Mutex<BarrierState>,
+  decided: Condvar,
+}
+
+impl PublishBarrier {
+  /// Barrier for that many contexts, and the receiver of the response to the
+  /// request.
+  pub fn new(parties: usize) -> (Arc<Self>, oneshot::Receiver<Response>) {
+    let (done, response) = oneshot::channel();
+    let barrier = PublishBarrier {
+      parties,
+      state:   Mutex::new(BarrierState {
+        votes:     0,
+        published: 0,
+        decision:  None,
+        done:      Some(done),
+        on_decide: None,
+      }),
+      decided: Condvar::new(),
+    };
+    (Arc::new(barrier), response)
+  }
+
+  /// Barrier of a part replicated from the primary, decided only by
+  /// `decide`.
+  pub fn replicated() -> Arc<Self> {
+    Self::new(usize::MAX).0
+  }
+
+  /// Calls `f` with the decision as it is made, before any writer goes on.
+  pub fn on_decide<F: FnOnce(bool) + Send + 'static>(
+    &self,
+    f: F,
+  ) {
+    self.state.lock().on_decide = Some(Box::new(f));
+  }
+
+  pub fn decision(&self) -> Option<bool> {
+    self.state.lock().decision
+  }
+
+  /// Called by a writer that staged its part, with the response to the
+  /// request if it rejects the part. Returns whether to publish the part,
+  /// once all the contexts voted for it, or false as soon as one rejected
+  /// its part, or after the timeout.
+  pub fn vote(
+    &self,
+    rejected: Option<Response>,
+    timeout: Duration,
+  ) -> bool {
+    let deadline = Instant::now() + timeout;
+    let mut state = self.state.lock();
+    if state.decision.is_none() {
+      state.votes += 1;
+      match rejected {
+        Some(response) => self.decide_locked(&mut state, false, response),
+        None if state.votes >= self.parties => {
+          self.decide_locked(&mut state, true, Response::Ok)
+        },
+        None => {},
+      }
+    }
+    while state.decision.is_none() {
+      if self.decided.wait_until(&mut state, deadline).timed_out() {
+        let response = Response::Error(ServiceError::Timeout);
+        self.decide_locked(&mut state, false, response);
+      }
+    }
+    state.decision == Some(true)
+  }
+
+  /// Decides the outcome, if it is not decided yet. Used on replicas, with
+  /// the outcome on the primary.
+  pub fn decide(
+    &self,
+    publish: bool,
+  ) {
+    let mut state = self.state.lock();
+    self.decide_locked(&mut state, publish, Response::Fail);
+  }
+
+  fn decide_locked(
+    &self,
+    state: &mut BarrierState,
+    publish: bool,
+    response: Response,
+  ) {
+    if state.decision.is_some() {
+      return;
+    }
+    state.decision = Some(publish);
+    if let Some(f) = state.on_decide.take() {
+      f(publish);
+    }
+    if !publish {
+      if let Some(done) = state.done.take() {
+        let _ = done.send(response);
+      }
+    }
+    self.decided.notify_all();
+  }
+
+  /// Called by a writer once it published its part.
+  pub fn published(&self) {
+    let mut state = self.state.lock();
+    state.published += 1;
+    if state.published >= self.parties {
+      if let Some(done) = state.done.take() {
+        let _ = done.send(Response::Ok);
+      }
+    }
+  }
+}
+
+/// Handle of the part of a context to the `PublishBarrier` of the request.
+///
+/// Only the number of the request is encoded: a replica attaches the part to
+/// a barrier of its own, see `attach`.
+#[derive(Clone)]
+pub struct PublishGate {
+  /// Number of the `WriteMulti` on the primary.
+  pub id:  u64,
+  /// Context of the part, for the response if it is rejected.
+  context: SubgraphName,
+  /// Edges the request writes to the context, checked against its quotas.
+  /// Like with `WriteEdge`, their copies in other contexts are not.
+  counted: Vec<OpWriteEdge>,
+  barrier: Option<Arc<PublishBarrier>>,
+}
+
+impl PublishGate {
+  pub fn new(
+    id: u64,
+    context: &SubgraphName,
+    counted: Vec<OpWriteEdge>,
+    barrier: &Arc<PublishBarrier>,
+  ) -> Self {
+    PublishGate {
+      id,
+      context: context.clone(),
+      counted,
+      barrier: Some(Arc::clone(barrier)),
+    }
+  }
+
+  pub fn context(&self) -> &SubgraphName {
+    &self.context
+  }
+
+  pub fn counted(&self) -> &[OpWriteEdge] {
+    &self.counted
+  }
+
+  /// The barrier to vote at, None if the part is applied like a `Batch`.
+  pub fn barrier(&self) -> Option<&Arc<PublishBarrier>> {
+    self.barrier.as_ref()
+  }
+
+  pub fn attach(
+    &mut self,
+    barrier: Arc<PublishBarrier>,
+  ) {
+    self.barrier = Some(barrier);
+  }
+}
+
+impl fmt::Debug for PublishGate {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    write!(f, "PublishGate({})", self.id)
+  }
+}
+
+impl Encode for PublishGate {
+  fn encode<E: Encoder>(
+    &self,
+    encoder: &mut E,
+  ) -> Result<(), EncodeError> {
+    self.id.encode(encoder)
+  }
+}
+
+impl<Context> Decode<Context> for PublishGate {
+  fn decode<D: Decoder<Context = Context>>(
+    decoder: &mut D
+  ) -> Result<Self, DecodeError> {
+    Ok(PublishGate {
+      id:      u64::decode(decoder)?,
+      context: SubgraphName::new(),
+      counted: Vec::new(),
+      barrier: None,
+    })
+  }
+}
+
+bincode::impl_borrow_decode!(PublishGate);
+
+impl Serialize for PublishGate {
+  fn serialize<S: Serializer>(
+    &self,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(self.id)
+  }
+}
+
+impl<'de> Deserialize<'de> for PublishGate {
+  fn deserialize<D: Deserializer<'de>>(
+    deserializer: D
+  ) -> Result<Self, D::Error> {
+    Ok(PublishGate {
+      id:      u64::deserialize(deserializer)?,
+      context: SubgraphName::new(),
+      counted: Vec::new(),
+      barrier: None,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::thread;
+
+  #[test]
+  fn parts_are_published_once_all_voted() {
+    let (barrier, mut response) = PublishBarrier::new(2);
+    let first = {
+      let barrier = Arc::clone(&barrier);
+      thread::spawn(move || barrier.vote(None, Duration::from_secs(10)))
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(!first.is_finished());
+    assert_eq!(barrier.decision(), None);
+
+    assert!(barrier.vote(None, Duration::from_secs(10)));
+    assert!(first.join().unwrap());
+    barrier.published();
+    assert!(response.try_recv().is_err());
+    barrier.published();
+    assert!(matches!(response.try_recv(), Ok(Response::Ok)));
+  }
+
+  #[test]
+  fn rejected_or_late_part_drops_all() {
+    let (barrier, response) = PublishBarrier::new(3);
+    let waiting = {
+      let barrier = Arc::clone(&barrier);
+      thread::spawn(move || barrier.vote(None, Duration::from_secs(10)))
+    };
+    assert!(!barrier.vote(Some(Response::Fail), Duration::from_secs(10)));
+    //  The waiting writer doesn't wait for the timeout.
+    assert!(!waiting.join().unwrap());
+    //  Nor does one that votes after the decision.
+    assert!(!barrier.vote(None, Duration::from_secs(10)));
+    assert!(matches!(response.blocking_recv(), Ok(Response::Fail)));
+
+    let (barrier, response) = PublishBarrier::new(2);
+    assert!(!barrier.vote(None, Duration::from_millis(10)));
+    assert!(matches!(
+      response.blocking_recv(),
+      Ok(Response::Error(ServiceError::Timeout))
+    ));
+  }
+
+  #[test]
+  fn replicas_decode_the_number_of_the_request() {
+    let (barrier, _response) = PublishBarrier::new(1);
+    let gate = PublishGate::new(7, &"X".into(), Vec::new(), &barrier);
+    let config = bincode::config::standard();
+    let bytes = bincode::encode_to_vec(&gate, config).unwrap();
+    let (mut gate, _): (PublishGate, _) =
+      bincode::decode_from_slice(&bytes, config).unwrap();
+    assert_eq!(gate.id, 7);
+    assert!(gate.barrier().is_none());
+
+    let replicated = PublishBarrier::replicated();
+    gate.attach(Arc::clone(&replicated));
+    replicated.decide(true);
+    assert!(gate.barrier().unwrap().vote(None, Duration::ZERO));
+  }
+}
@@ -0,0 +1,149 @@
+//! Reader thread pool. Reads run on dedicated threads instead of the async runtime,
+//! so a heavy `read_scores` on one context does not serialize all other reads.
+//! Pinned (hot) contexts get a worker of their own; all other contexts share the
+//! common workers.
+
+use crate::data::SubgraphName;
+use crate::utils::log::*;
+
+use parking_lot::Mutex;
+
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+pub type ReadJob = Box<dyn FnOnce() + Send + 'static>;
+
+pub struct ReadPool {
+  shared: Option<mpsc::Sender<ReadJob>>,
+  pinned: HashMap<SubgraphName, mpsc::Sender<ReadJob>>,
+}
+
+fn spawn_worker(
+  name: String,
+  rx: Arc<Mutex<mpsc::Receiver<ReadJob>>>,
+) {
+  let result = thread::Builder::new().name(name.clone()).spawn(move || loop {
+    let job = match rx.lock().recv() {
+      Ok(job) => job,
+      Err(_) => break,
+    };
+    if catch_unwind(AssertUnwindSafe(job)).is_err() {
+      log_error!("Read job panicked in {}", name);
+    }
+  });
+  if let Err(e) = result {
+    log_error!("Failed to spawn read worker: {}", e);
+  }
+}
+
+impl ReadPool {
+  /// Spawns `num_workers` shared workers plus one dedicated worker per pinned context.
+  pub fn new(
+    num_workers: usize,
+    pinned_contexts: &[SubgraphName],
+  ) -> Self {
+    let shared = if num_workers > 0 {
+      let (tx, rx) = mpsc::channel();
+      let rx = Arc::new(Mutex::new(rx));
+      for n in 0..num_workers {
+        spawn_worker(format!("mr-read-{}", n), Arc::clone(&rx));
+      }
+      Some(tx)
+    } else {
+      None
+    };
+
+    let mut pinned = HashMap::new();
+    for context in pinned_contexts {
+      if pinned.contains_key(context) {
+        continue;
+      }
+      let (tx, rx) = mpsc::channel();
+      spawn_worker(
+        format!("mr-read-pinned-{}", context),
+        Arc::new(Mutex::new(rx)),
+      );
+      pinned.insert(context.clone(), tx);
+    }
+
+    ReadPool {
+      shared,
+      pinned,
+    }
+  }
+
+  /// Queues the job on the worker for the context. Returns the job back if no
+  /// worker can take it, so the caller can run it inline.
+  pub fn dispatch(
+    &self,
+    context: &SubgraphName,
+    job: ReadJob,
+  ) -> Result<(), ReadJob> {
+    let sender = match self.pinned.get(context) {
+      Some(tx) => tx,
+      None => match &self.shared {
+        Some(tx) => tx,
+        None => return Err(job),
+      },
+    };
+    sender.send(job).map_err(|mpsc::SendError(job)| job)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn run_on_pool(
+    pool: &ReadPool,
+    context: &str,
+  ) -> Option<String> {
+    let (tx, rx) = mpsc::channel();
+    let job: ReadJob = Box::new(move || {
+      let _ = tx.send(thread::current().name().map(String::from));
+    });
+    match pool.dispatch(&context.to_string(), job) {
+      Ok(()) => rx.recv().unwrap(),
+      Err(job) => {
+        job();
+        rx.recv().unwrap()
+      },
+    }
+  }
+
+  #[test]
+  fn pinned_context_runs_on_dedicated_worker() {
+    let pool = ReadPool::new(2, &["hot".to_string()]);
+
+    assert_eq!(
+      run_on_pool(&pool, "hot").as_deref(),
+      Some("mr-read-pinned-hot")
+    );
+    let name = run_on_pool(&pool, "other").unwrap();
+    assert!(name.starts_with("mr-read-") && !name.contains("pinned"));
+  }
+
+  #[test]
+  fn no_workers_returns_job() {
+    let pool = ReadPool::new(0, &["hot".to_string()]);
+
+    let job: ReadJob = Box::new(|| {});
+    assert!(pool.dispatch(&"other".to_string(), job).is_err());
+    assert_eq!(
+      run_on_pool(&pool, "hot").as_deref(),
+      Some("mr-read-pinned-hot")
+    );
+  }
+
+  #[test]
+  fn worker_survives_panicking_job() {
+    let pool = ReadPool::new(1, &[]);
+
+    let job: ReadJob = Box::new(|| panic!("read failed"));
+    assert!(pool.dispatch(&String::new(), job).is_ok());
+    assert_eq!(run_on_pool(&pool, "").as_deref(), Some("mr-read-0"));
+  }
+}
@@ -0,0 +1,513 @@
+//! Primary/replica replication of write ops.
+//!
+//! The primary numbers every change of graph state (ops that are not local
+//! calculations, and creation, forks and removal of contexts) and streams
+//! them to replicas, which apply them to their own graphs in the same order.
+//! Walks are not replicated; replicas calculate egos on their own reads.
+//!
+//! A replica sends the seq of the first entry it needs. If the primary still
+//! has it in its log, it sends the entries from there; otherwise it sends a
+//! snapshot of all contexts first. A replica that sees a gap in the seqs
+//! reconnects. Replicas keep the primary's seqs in their own log, so other
+//! replicas can follow one once it is promoted.
+
+use crate::data::*;
+use crate::request_handler::{read_message, write_message};
+use crate::settings::Settings;
+use crate::state_manager::MultiGraphProcessor;
+use crate::tls::{connect, tls_acceptor, tls_connector};
+use crate::utils::log::*;
+
+use parking_lot::Mutex;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio_rustls::TlsConnector;
+use tokio_util::sync::CancellationToken;
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Entries buffered for each connected replica. A replica that falls further
+/// behind is disconnected and catches up from the log when it reconnects.
+const LIVE_CHANNEL_SIZE: usize = 4096;
+
+const RECONNECT_INTERVAL_MSEC: u64 = 1000;
+
+struct ReplicationLog {
+  next_seq: u64,
+  entries:  VecDeque<ReplicationEntry>,
+  capacity: usize,
+}
+
+pub struct Replication {
+  log:       Mutex<ReplicationLog>,
+  live:      broadcast::Sender<ReplicationEntry>,
+  replica:   AtomicBool,
+  following: CancellationToken,
+}
+
+impl Replication {
+  pub fn new(settings: &Settings) -> Self {
+    let (live, _) = broadcast::channel(LIVE_CHANNEL_SIZE);
+    Replication {
+      log:       Mutex::new(ReplicationLog {
+        next_seq: 1,
+        entries:  VecDeque::new(),
+        capacity: settings.replication_log_size,
+      }),
+      live,
+      replica:   AtomicBool::new(!settings.replicate_from.is_empty()),
+      following: CancellationToken::new(),
+    }
+  }
+
+  pub fn is_replica(&self) -> bool {
+    self.replica.load(Ordering::SeqCst)
+  }
+
+  /// Stops following the primary. False if this is not a replica.
+  pub fn promote(&self) -> bool {
+    if !self.replica.swap(false, Ordering::SeqCst) {
+      return false;
+    }
+    self.following.cancel();
+    true
+  }
+
+  /// Seq of the last entry, 0 if there is none.
+  pub fn last_seq(&self) -> u64 {
+    self.log.lock().next_seq - 1
+  }
+
+  /// Numbers the record and sends it to replicas. `apply` is called under
+  /// the lock, so ops of a context are numbered in the order they are queued.
+  pub fn append<F: FnOnce()>(
+    &self,
+    subgraph: &SubgraphName,
+    record: ReplicationRecord,
+    apply: F,
+  ) {
+    let mut log = self.log.lock();
+    apply();
+    let entry = ReplicationEntry {
+      seq:      log.next_seq,
+      subgraph: subgraph.clone(),
+      record,
+    };
+    log.next_seq += 1;
+    //  No receivers is not an error: there may be no replicas.
+    let _ = self.live.send(entry.clone());
+    if log.capacity > 0 {
+      log.entries.push_back(entry);
+      if log.entries.len() > log.capacity {
+        log.entries.pop_front();
+      }
+    }
+  }
+
+  /// Makes the next entry get the given seq. Used by replicas to keep the
+  /// seqs of the primary.
+  fn resume_at(
+    &self,
+    seq: u64,
+  ) {
+    self.log.lock().next_seq = seq;
+  }
+
+  /// Drops all entries after a snapshot is applied.
+  fn reset(
+    &self,
+    next_seq: u64,
+  ) {
+    let mut log = self.log.lock();
+    log.next_seq = next_seq;
+    log.entries.clear();
+  }
+
+  /// Subscribes to new entries, along with the logged ones from `next_seq`.
+  /// None instead of the logged entries if some of them are not in the log
+  /// anymore, and the replica needs a snapshot.
+  fn subscribe_from(
+    &self,
+    next_seq: u64,
+  ) -> (
+    broadcast::Receiver<ReplicationEntry>,
+    Option<Vec<ReplicationEntry>>,
+  ) {
+    let log = self.log.lock();
+    let live = self.live.subscribe();
+    let first_seq = log.next_seq - log.entries.len() as u64;
+    if next_seq == 0 || next_seq < first_seq || next_seq > log.next_seq {
+      return (live, None);
+    }
+    let backlog = log
+      .entries
+      .iter()
+      .filter(|entry| entry.seq >= next_seq)
+      .cloned()
+      .collect();
+    (live, Some(backlog))
+  }
+}
+
+/// Streams entries to one replica until it disconnects or falls behind.
+async fn serve_replica<S: AsyncRead + AsyncWrite + Unpin>(
+  mut stream: S,
+  processor: Arc<MultiGraphProcessor>,
+  replication: Arc<Replication>,
+  running: CancellationToken,
+) -> Result<(), Box<dyn Error>> {
+  let hello: ReplicationHello = read_message(&mut stream).await?;
+  let (mut live, backlog) = replication.subscribe_from(hello.next_seq);
+
+  let mut last_seq = match backlog {
+    Some(entries) => {
+      log_verbose!(
+        "Replica resumes at {}, sending {} entries",
+        hello.next_seq,
+        entries.len()
+      );
+      let mut last_seq = hello.next_seq - 1;
+      for entry in entries {
+        last_seq = entry.seq;
+        write_message(&mut stream, &ReplicationMessage::Entry(entry)).await?;
+      }
+      last_seq
+    },
+    None => {
+      //  Entries after `seq` come from the live channel. The snapshot may
+      //  already include some of them; replicated ops are idempotent, except
+      //  for decay.
+      let seq = replication.last_seq();
+      let contexts = processor.snapshot_contexts().await;
+      log_verbose!(
+        "Sending snapshot of {} contexts at {} to replica",
+        contexts.len(),
+        seq
+      );
+      let snapshot = ReplicationSnapshot { seq, contexts };
+      write_message(&mut stream, &ReplicationMessage::Snapshot(snapshot))
+        .await?;
+      seq
+    },
+  };
+
+  loop {
+    let entry = tokio::select! {
+      _ = running.cancelled() => return Ok(()),
+      received = live.recv() => match received {
+        Ok(entry) => entry,
+        Err(broadcast::error::RecvError::Lagged(n)) => {
+          return Err(format!("Replica fell behind by {} entries", n).into());
+        },
+        Err(broadcast::error::RecvError::Closed) => return Ok(()),
+      },
+    };
+    if entry.seq <= last_seq {
+      continue;
+    }
+    last_seq = entry.seq;
+    write_message(&mut stream, &ReplicationMessage::Entry(entry)).await?;
+  }
+}
+
+/// Accepts replicas on `replication_port`, over TLS if `tls_cert` is set.
+pub async fn run_replication_server(
+  settings: Settings,
+  processor: Arc<MultiGraphProcessor>,
+  running: CancellationToken,
+) -> Result<(), Box<dyn Error>> {
+  let replication = match processor.replication() {
+    Some(x) => Arc::clone(x),
+    None => return Err("Replication is disabled".into()),
+  };
+
+  let tls = tls_acceptor(&settings)?;
+  let url =
+    format!("{}:{}", settings.server_address, settings.replication_port);
+  let listener = TcpListener::bind(&url).await?;
+  log_verbose!("Replication server running on {}, TLS: {}", url, tls.is_some());
+
+  loop {
+    tokio::select! {
+      _ = running.cancelled() => break,
+      accept_result = listener.accept() => {
+        let (stream, address) = match accept_result {
+          Ok(x) => x,
+          Err(e) => {
+            log_error!("Socket accept failed: {}", e);
+            break;
+          },
+        };
+        log_verbose!("Replica connected from {}", address);
+        let processor = Arc::clone(&processor);
+        let replication = Arc::clone(&replication);
+        let running = running.clone();
+        let tls = tls.clone();
+        tokio::spawn(async move {
+          let served = match tls {
+            Some(acceptor) => match acceptor.accept(stream).await {
+              Ok(stream) => {
+                serve_replica(stream, processor, replication, running).await
+              },
+              Err(e) => {
+                log_warning!("TLS handshake with {} failed: {}", address, e);
+                return;
+              },
+            },
+            None => {
+              serve_replica(stream, processor, replication, running).await
+            },
+          };
+          if let Err(e) = served {
+            log_warning!("Replica {} disconnected: {}", address, e);
+          }
+        });
+      }
+    };
+  }
+
+  Ok(())
+}
+
+/// Applies entries from the primary until the connection breaks or a gap is
+/// found.
+async fn follow(
+  address: &str,
+  tls: Option<&TlsConnector>,
+  processor: &MultiGraphProcessor,
+  replication: &Replication,
+) -> Result<(), Box<dyn Error>> {
+  let mut stream = connect(address, tls).await?;
+  let next_seq = match replication.last_seq() {
+    0 => 0,
+    seq => seq + 1,
+  };
+  write_message(&mut stream, &ReplicationHello { next_seq }).await?;
+
+  loop {
+    let message: ReplicationMessage = read_message(&mut stream).await?;
+    match message {
+      ReplicationMessage::Snapshot(snapshot) => {
+        log_verbose!(
+          "Applying snapshot of {} contexts at {}",
+          snapshot.contexts.len(),
+          snapshot.seq
+        );
+        processor.restore_contexts(snapshot.contexts);
+        replication.reset(snapshot.seq + 1);
+      },
+      ReplicationMessage::Entry(entry) => {
+        let expected = replication.last_seq() + 1;
+        if entry.seq < expected {
+          continue;
+        }
+        if entry.seq > expected {
+          return Err(
+            format!("Expected entry {}, got {}", expected, entry.seq).into(),
+          );
+        }
+        let seq = entry.seq;
+        replication.resume_at(seq);
+        processor.apply_replicated(entry).await;
+        //  Entries that fail to apply do not append, but are done with.
+        replication.resume_at(seq + 1);
+      },
+    }
+  }
+}
+
+/// Follows the primary at `replicate_from` until promoted, over TLS if
+/// `tls_ca` is set.
+pub async fn run_replica(
+  settings: Settings,
+  processor: Arc<MultiGraphProcessor>,
+  running: CancellationToken,
+) {
+  let replication = match processor.replication() {
+    Some(x) => Arc::clone(x),
+    None => return,
+  };
+  let tls = match tls_connector(&settings) {
+    Ok(x) => x,
+    Err(e) => {
+      log_error!("Failed to load TLS certificates: {}", e);
+      return;
+    },
+  };
+
+  let reconnect_interval = Duration::from_millis(RECONNECT_INTERVAL_MSEC);
+  loop {
+    tokio::select! {
+      _ = running.cancelled() => break,
+      _ = replication.following.cancelled() => {
+        log_info!("Promoted to primary");
+        break;
+      },
+      result = follow(
+        &settings.replicate_from,
+        tls.as_ref(),
+        &processor,
+        &replication,
+      ) => {
+        match result {
+          Ok(()) => {},
+          Err(e) => log_warning!(
+            "Replication from {} interrupted: {}",
+            settings.replicate_from,
+            e
+          ),
+        }
+      },
+    };
+    tokio::select! {
+      _ = running.cancelled() => break,
+      _ = replication.following.cancelled() => {},
+      _ = tokio::time::sleep(reconnect_interval) => {},
+    };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::time::Instant;
+
+  fn entry_seqs(entries: &[ReplicationEntry]) -> Vec<u64> {
+    entries.iter().map(|entry| entry.seq).collect()
+  }
+
+  #[test]
+  fn log_keeps_recent_entries() {
+    let replication = Replication::new(&Settings {
+      replication_log_size: 3,
+      ..Settings::default()
+    });
+    for _ in 0..5 {
+      replication.append(&String::new(), ReplicationRecord::Reset, || {});
+    }
+    assert_eq!(replication.last_seq(), 5);
+
+    let (_, backlog) = replication.subscribe_from(4);
+    assert_eq!(entry_seqs(&backlog.unwrap()), vec![4, 5]);
+    let (_, backlog) = replication.subscribe_from(6);
+    assert!(backlog.unwrap().is_empty());
+    //  Entry 2 is not in the log anymore.
+    assert!(replication.subscribe_from(2).1.is_none());
+    assert!(replication.subscribe_from(0).1.is_none());
+    assert!(replication.subscribe_from(7).1.is_none());
+  }
+
+  async fn request(
+    processor: &MultiGraphProcessor,
+    data: ReqData,
+  ) -> Response {
+    processor
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data,
+      })
+      .await
+  }
+
+  async fn write_edge(
+    processor: &MultiGraphProcessor,
+    src: &str,
+    dst: &str,
+  ) -> Response {
+    let data = ReqData::WriteEdge(OpWriteEdge {
+      src:        src.into(),
+      dst:        dst.into(),
+      amount:     1.0,
+      magnitude:  0,
+      edge_kind:  EdgeKind::Vote,
+      provenance: None,
+    });
+    request(processor, data).await
+  }
+
+  async fn wait_for_edges(
+    processor: &MultiGraphProcessor,
+    num_edges: usize,
+  ) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+      request(processor, ReqData::Sync(1)).await;
+      if let Response::Edges(res) = request(processor, ReqData::ReadEdges).await
+      {
+        if res.edges.len() == num_edges {
+          return;
+        }
+      }
+      tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("replica did not get {} edges", num_edges);
+  }
+
+  #[tokio::test]
+  async fn replica_follows_primary() {
+    let running = CancellationToken::new();
+    let primary_settings = Settings {
+      replication_port: 8088,
+      ..Settings::default()
+    };
+    let primary = Arc::new(MultiGraphProcessor::new(primary_settings.clone()));
+    //  Written before the replica connects, so it comes with the snapshot.
+    write_edge(&primary, "U1", "U2").await;
+    let server = Arc::clone(&primary);
+    let server_running = running.clone();
+    tokio::spawn(async move {
+      let _ = run_replication_server(primary_settings, server, server_running)
+        .await;
+    });
+
+    let replica_settings = Settings {
+      replicate_from: "127.0.0.1:8088".into(),
+      ..Settings::default()
+    };
+    let replica = Arc::new(MultiGraphProcessor::new(replica_settings.clone()));
+    tokio::spawn(run_replica(
+      replica_settings,
+      Arc::clone(&replica),
+      running.clone(),
+    ));
+    wait_for_edges(&replica, 1).await;
+
+    write_edge(&primary, "U2", "U3").await;
+    write_edge(&primary, "U3", "U1").await;
+    wait_for_edges(&replica, 3).await;
+
+    let response = write_edge(&replica, "U1", "U3").await;
+    assert!(matches!(response, Response::Error(ServiceError::ReadOnly)));
+
+    let response = request(&primary, ReqData::Promote).await;
+    assert!(matches!(response, Response::Fail));
+    let response = request(&replica, ReqData::Promote).await;
+    assert!(matches!(response, Response::Ok));
+    assert!(matches!(write_edge(&replica, "U1", "U3").await, Response::Ok));
+    wait_for_edges(&replica, 4).await;
+
+    running.cancel();
+  }
+
+  #[test]
+  fn promote_only_replicas() {
+    let primary = Replication::new(&Settings::default());
+    assert!(!primary.is_replica());
+    assert!(!primary.promote());
+
+    let replica = Replication::new(&Settings {
+      replicate_from: "127.0.0.1:1".into(),
+      ..Settings::default()
+    });
+    assert!(replica.is_replica());
+    assert!(replica.promote());
+    assert!(!replica.is_replica());
+  }
+}
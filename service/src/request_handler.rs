@@ -1,8 +1,12 @@
 use crate::data::*;
+use crate::protocol::{legacy_error, legacy_request, legacy_response};
 use crate::settings::*;
-use crate::state_manager::MultiGraphProcessor;
+use crate::state_manager::{MultiGraphProcessor, ScorePages};
+use crate::telemetry::{request_span, TraceId};
+use crate::tls::tls_acceptor;
 use crate::utils::log::*;
 
+use async_trait::async_trait;
 use bincode::{
   config::standard,
   decode_from_slice,
@@ -10,116 +14,639 @@ use bincode::{
   Decode,
   Encode,
 };
+use serde::{de::DeserializeOwned, Serialize};
 use tokio::{
-  io::{AsyncReadExt, AsyncWriteExt},
-  net::{TcpListener, TcpStream},
+  io::{
+    AsyncBufRead,
+    AsyncBufReadExt,
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWrite,
+    AsyncWriteExt,
+    BufReader,
+  },
+  net::{TcpListener, UnixListener},
 };
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
-use std::{error::Error, sync::Arc};
+use std::{
+  error::Error,
+  io::Read,
+  sync::{atomic::Ordering, Arc},
+  time::{Duration, Instant},
+};
 
-/// Writes a length-prefixed (4-byte big-endian) bincode message.
-async fn write_message<T: Encode>(
-  stream: &mut TcpStream,
+/// First byte sent by a client that wants CBOR messages.
+pub const CBOR_MAGIC: u8 = b'C';
+/// First byte sent by a client that wants JSON messages.
+pub const JSON_MAGIC: u8 = b'J';
+/// First byte sent by a client that sends legacy msgpack commands, see
+/// `protocol::legacy_request`. Messages are length-prefixed like bincode.
+pub const LEGACY_MAGIC: u8 = b'M';
+/// First byte sent by a client that accepts zstd-compressed messages. Goes
+/// before the encoding magic byte, if any.
+pub const COMPRESSION_MAGIC: u8 = b'Z';
+/// First byte sent by a client that sends a `TraceId` before each request.
+/// Goes after the compression magic byte and before the encoding one.
+pub const TRACE_MAGIC: u8 = b'T';
+/// Set in the length prefix of a compressed message.
+pub const COMPRESSED_FLAG: u32 = 1 << 31;
+/// Largest message accepted, before and after decompression, and the longest
+/// JSON line. Larger bincode lengths could begin with a magic byte anyway.
+pub const MAX_FRAME: usize = 1 << 30;
+
+/// Serialization of the messages of a connection, chosen by its magic byte.
+/// Bincode connections start right away with the length of the first
+/// message, which never begins with a magic byte for messages under 1 GB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireEncoding {
+  /// Length-prefixed (4-byte big-endian) bincode.
+  Bincode,
+  /// Length-prefixed (4-byte big-endian) CBOR.
+  Cbor,
+  /// One JSON document per line.
+  Json,
+}
+
+/// Compression of messages sent to clients that accept it. Only applies to
+/// length-prefixed encodings.
+#[derive(Debug, Clone, Copy)]
+pub struct Compression {
+  /// zstd compression level.
+  pub level:     i32,
+  /// Smaller messages are sent uncompressed.
+  pub threshold: usize,
+}
+
+/// Consumes the next byte if it is one of `magic`.
+async fn take_magic_byte<S: AsyncBufRead + Unpin>(
+  stream: &mut S,
+  magic: &[u8],
+) -> Result<Option<u8>, Box<dyn Error>> {
+  let first = match stream.fill_buf().await?.first() {
+    Some(&x) => x,
+    None => return Err("Connection closed".into()),
+  };
+  if !magic.contains(&first) {
+    return Ok(None);
+  }
+  stream.consume(1);
+  Ok(Some(first))
+}
+
+/// Reads the magic bytes, if any, without consuming a bincode length prefix.
+/// Returns the encoding, whether the client accepts compressed messages and
+/// whether it sends trace ids.
+async fn negotiate_encoding<S: AsyncBufRead + Unpin>(
+  stream: &mut S,
+) -> Result<(WireEncoding, bool, bool), Box<dyn Error>> {
+  let compressed = take_magic_byte(stream, &[COMPRESSION_MAGIC])
+    .await?
+    .is_some();
+  let traced = take_magic_byte(stream, &[TRACE_MAGIC]).await?.is_some();
+  let encoding = match take_magic_byte(stream, &[CBOR_MAGIC, JSON_MAGIC]).await?
+  {
+    Some(CBOR_MAGIC) => WireEncoding::Cbor,
+    Some(_) => WireEncoding::Json,
+    None => WireEncoding::Bincode,
+  };
+  log_verbose!(
+    "Connection uses {:?}, compressed: {}, traced: {}",
+    encoding,
+    compressed,
+    traced
+  );
+  Ok((encoding, compressed, traced))
+}
+
+fn encode_message<T: Encode + Serialize>(
+  encoding: WireEncoding,
   value: &T,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<Vec<u8>, Box<dyn Error>> {
+  Ok(match encoding {
+    WireEncoding::Bincode => encode_to_vec(value, standard())?,
+    WireEncoding::Cbor => {
+      let mut out = vec![];
+      ciborium::into_writer(value, &mut out)?;
+      out
+    },
+    WireEncoding::Json => serde_json::to_vec(value)?,
+  })
+}
+
+fn decode_message<T: Decode<()> + DeserializeOwned>(
+  encoding: WireEncoding,
+  buf: &[u8],
+) -> Result<T, Box<dyn Error>> {
+  Ok(match encoding {
+    WireEncoding::Bincode => decode_from_slice(buf, standard())?.0,
+    WireEncoding::Cbor => ciborium::from_reader(buf)?,
+    WireEncoding::Json => serde_json::from_slice(buf)?,
+  })
+}
+
+/// Writes a message in the given encoding, compressing it if `compression`
+/// is set and the message is large enough. Returns the number of bytes saved.
+async fn write_message_as<S, T>(
+  stream: &mut S,
+  encoding: WireEncoding,
+  compression: Option<Compression>,
+  value: &T,
+) -> Result<usize, Box<dyn Error>>
+where
+  S: AsyncWrite + Unpin,
+  T: Encode + Serialize,
+{
   log_trace!();
-  let out = encode_to_vec(value, standard())?;
-  let len_bytes = (out.len() as u32).to_be_bytes();
-  stream.write_all(&len_bytes).await?;
+  let mut out =
+    tracing::debug_span!("encode").in_scope(|| encode_message(encoding, value))?;
+  let mut saved = 0;
+  if encoding == WireEncoding::Json {
+    out.push(b'\n');
+  } else {
+    let mut len = out.len() as u32;
+    if let Some(c) = compression.filter(|c| out.len() >= c.threshold) {
+      let compressed = tracing::debug_span!("compress")
+        .in_scope(|| zstd::bulk::compress(&out, c.level))?;
+      if compressed.len() < out.len() {
+        saved = out.len() - compressed.len();
+        out = compressed;
+        len = out.len() as u32 | COMPRESSED_FLAG;
+      }
+    }
+    stream.write_all(&len.to_be_bytes()).await?;
+  }
   stream.write_all(&out).await?;
-  Ok(())
+  Ok(saved)
 }
 
-/// Reads a length-prefixed (4-byte big-endian) bincode message.
-async fn read_message<T: Decode<()>>(stream: &mut TcpStream) -> Result<T, Box<dyn Error>> {
-  log_trace!();
+/// Writes the scores a page at a time, each as a `ScoresChunk` message of at
+/// most `chunk_size` scores, so that the whole response is never held at
+/// once. Returns the number of bytes saved by compression.
+async fn write_score_pages_as<S: AsyncWrite + Unpin>(
+  stream: &mut S,
+  encoding: WireEncoding,
+  compression: Option<Compression>,
+  mut pages: ScorePages,
+  chunk_size: u32,
+) -> Result<usize, Box<dyn Error>> {
+  let chunk_size = chunk_size.max(1);
+  let mut saved = 0;
+  loop {
+    let (scores, more) = pages.read_page(chunk_size);
+    let response = Response::ScoresChunk(ResScoresChunk {
+      scores,
+      more,
+    });
+    saved += write_message_as(stream, encoding, compression, &response).await?;
+    if !more {
+      return Ok(saved);
+    }
+  }
+}
+
+/// Reads a length-prefixed (4-byte big-endian) frame, decompressing it if
+/// `COMPRESSED_FLAG` is set. Compressed frames are only accepted if the
+/// connection negotiated `COMPRESSION_MAGIC`.
+async fn read_frame<S: AsyncRead + Unpin>(
+  stream: &mut S,
+  compressed: bool,
+) -> Result<Vec<u8>, Box<dyn Error>> {
   let mut len_buf = [0u8; 4];
   stream.read_exact(&mut len_buf).await?;
-  let len = u32::from_be_bytes(len_buf) as usize;
-  let mut buf = vec![0u8; len];
+  let len = u32::from_be_bytes(len_buf);
+  let is_compressed = len & COMPRESSED_FLAG != 0;
+  if is_compressed && !compressed {
+    return Err("Compressed frame on a connection without compression".into());
+  }
+  let size = (len & !COMPRESSED_FLAG) as usize;
+  if size > MAX_FRAME {
+    return Err(format!("Frame of {} bytes is over {}", size, MAX_FRAME).into());
+  }
+  let mut buf = vec![0u8; size];
   stream.read_exact(&mut buf).await?;
-  Ok(decode_from_slice(&buf, standard())?.0)
+  if is_compressed {
+    buf = decompress_limited(&buf, MAX_FRAME)?;
+  }
+  Ok(buf)
+}
+
+/// Decompresses a zstd frame, failing instead of growing past `limit` bytes.
+fn decompress_limited(
+  buf: &[u8],
+  limit: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+  let mut out = vec![];
+  zstd::stream::read::Decoder::new(buf)?
+    .take(limit as u64 + 1)
+    .read_to_end(&mut out)?;
+  if out.len() > limit {
+    return Err(format!("Frame decompresses to over {} bytes", limit).into());
+  }
+  Ok(out)
+}
+
+/// Reads a JSON line, failing instead of buffering more than `limit` bytes.
+async fn read_line_limited<S: AsyncBufRead + Unpin>(
+  stream: &mut S,
+  limit: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+  let mut buf = vec![];
+  if (&mut *stream)
+    .take(limit as u64 + 1)
+    .read_until(b'\n', &mut buf)
+    .await?
+    == 0
+  {
+    return Err("Connection closed".into());
+  }
+  if buf.len() > limit {
+    return Err(format!("Line is over {} bytes", limit).into());
+  }
+  Ok(buf)
+}
+
+/// Reads the trace id sent before a request by clients that use `TRACE_MAGIC`.
+async fn read_trace_id<S: AsyncRead + Unpin>(
+  stream: &mut S,
+) -> Result<TraceId, Box<dyn Error>> {
+  let mut trace_id = TraceId::default();
+  stream.read_exact(&mut trace_id).await?;
+  Ok(trace_id)
+}
+
+/// Reads a message in the given encoding, see `read_frame` for `compressed`.
+async fn read_message_as<S, T>(
+  stream: &mut S,
+  encoding: WireEncoding,
+  compressed: bool,
+) -> Result<T, Box<dyn Error>>
+where
+  S: AsyncBufRead + Unpin,
+  T: Decode<()> + DeserializeOwned,
+{
+  log_trace!();
+  let buf = if encoding == WireEncoding::Json {
+    read_line_limited(stream, MAX_FRAME).await?
+  } else {
+    read_frame(stream, compressed).await?
+  };
+  tracing::debug_span!("decode").in_scope(|| decode_message(encoding, &buf))
+}
+
+/// Writes a length-prefixed (4-byte big-endian) bincode message.
+pub(crate) async fn write_message<S, T>(
+  stream: &mut S,
+  value: &T,
+) -> Result<(), Box<dyn Error>>
+where
+  S: AsyncWrite + Unpin,
+  T: Encode + Serialize,
+{
+  write_message_as(stream, WireEncoding::Bincode, None, value).await?;
+  Ok(())
+}
+
+/// Reads a length-prefixed (4-byte big-endian) bincode message.
+pub(crate) async fn read_message<S, T>(
+  stream: &mut S,
+) -> Result<T, Box<dyn Error>>
+where
+  S: AsyncRead + Unpin,
+  T: Decode<()> + DeserializeOwned,
+{
+  log_trace!();
+  decode_message(WireEncoding::Bincode, &read_frame(stream, false).await?)
 }
 
 #[allow(unused)]
-pub async fn write_request(
-  stream: &mut TcpStream,
+pub async fn write_request<S: AsyncWrite + Unpin>(
+  stream: &mut S,
   request: Request,
 ) -> Result<(), Box<dyn Error>> {
   write_message(stream, &request).await
 }
 
 #[allow(unused)]
-pub async fn read_request(
-  stream: &mut TcpStream,
+pub async fn read_request<S: AsyncRead + Unpin>(
+  stream: &mut S,
 ) -> Result<Request, Box<dyn Error>> {
   read_message(stream).await
 }
 
 #[allow(unused)]
-pub async fn write_response(
-  stream: &mut TcpStream,
+pub async fn write_response<S: AsyncWrite + Unpin>(
+  stream: &mut S,
   response: Response,
 ) -> Result<(), Box<dyn Error>> {
   write_message(stream, &response).await
 }
 
 #[allow(unused)]
-pub async fn read_response(
-  stream: &mut TcpStream,
+pub async fn read_response<S: AsyncRead + Unpin>(
+  stream: &mut S,
 ) -> Result<Response, Box<dyn Error>> {
   read_message(stream).await
 }
 
-pub async fn run_server(
+/// Answers legacy commands of one connection until it is closed.
+async fn serve_legacy_connection<S, P>(
+  mut stream: BufReader<S>,
+  processor: Arc<P>,
+) where
+  S: AsyncRead + AsyncWrite + Unpin,
+  P: RequestProcessor,
+{
+  loop {
+    let buf = match read_frame(&mut stream, false).await {
+      Ok(x) => x,
+      Err(_) => break,
+    };
+    let out = match legacy_request(&buf) {
+      Ok(req) => legacy_response(processor.process_request(&req).await),
+      Err(e) => {
+        log_warning!("Legacy command rejected: {}", e);
+        legacy_error(&e)
+      },
+    };
+    let len = out.len() as u32;
+    if stream.write_all(&len.to_be_bytes()).await.is_err()
+      || stream.write_all(&out).await.is_err()
+    {
+      break;
+    }
+  }
+}
+
+/// Whatever answers the requests of the server.
+#[async_trait]
+pub trait RequestProcessor: Send + Sync + 'static {
+  async fn process_request(
+    &self,
+    req: &Request,
+  ) -> Response;
+
+  /// Reads the scores of a `ReadScoresChunked`, to be written a page at a
+  /// time. Fails with the response to send instead.
+  async fn read_score_pages(
+    &self,
+    req: &Request,
+  ) -> Result<ScorePages, Response> {
+    match self.process_request(req).await {
+      Response::Scores(res) => Ok(ScorePages::new(res.scores)),
+      response => Err(response),
+    }
+  }
+
+  /// Counts the bytes saved by compressing responses.
+  fn add_compression_saved(
+    &self,
+    bytes: u64,
+  );
+}
+
+#[async_trait]
+impl RequestProcessor for MultiGraphProcessor {
+  async fn process_request(
+    &self,
+    req: &Request,
+  ) -> Response {
+    MultiGraphProcessor::process_request(self, req).await
+  }
+
+  async fn read_score_pages(
+    &self,
+    req: &Request,
+  ) -> Result<ScorePages, Response> {
+    MultiGraphProcessor::read_score_pages(self, req).await
+  }
+
+  fn add_compression_saved(
+    &self,
+    bytes: u64,
+  ) {
+    self.compression_saved.fetch_add(bytes, Ordering::Relaxed);
+  }
+}
+
+/// Logs the latency and outcome of a request at the verbose level.
+fn log_request(
+  log_fields: LogFields,
+  latency: Duration,
+  response: &Response,
+) {
+  if !VERBOSE.load(Ordering::Relaxed) {
+    return;
+  }
+  let outcome = match response {
+    Response::Fail => "fail".to_string(),
+    Response::Error(e) => format!("{:?}", e),
+    _ => "ok".to_string(),
+  };
+  let _log_fields = enter_log_fields(Some(log_fields));
+  log_event(LogEvent {
+    level:    "VERBOSE",
+    location: None,
+    function: None,
+    message:  "Request processed".into(),
+    extra:    &[
+      ("latency_ms", (latency.as_secs_f64() * 1000.0).into()),
+      ("outcome", outcome.into()),
+    ],
+  });
+}
+
+/// Answers requests of one connection until it is closed.
+async fn serve_connection<S, P>(
+  stream: S,
+  processor: Arc<P>,
+  compression_settings: Compression,
+) where
+  S: AsyncRead + AsyncWrite + Unpin,
+  P: RequestProcessor,
+{
+  let mut stream = BufReader::new(stream);
+  let legacy = match take_magic_byte(&mut stream, &[LEGACY_MAGIC]).await {
+    Ok(x) => x.is_some(),
+    Err(_) => return,
+  };
+  if legacy {
+    return serve_legacy_connection(stream, processor).await;
+  }
+  let (encoding, compressed, traced) =
+    match negotiate_encoding(&mut stream).await {
+      Ok(x) => x,
+      Err(_) => return,
+    };
+  let compression = compressed.then_some(compression_settings);
+
+  loop {
+    let trace_id = if traced {
+      match read_trace_id(&mut stream).await {
+        Ok(x) => Some(x),
+        Err(_) => break,
+      }
+    } else {
+      None
+    };
+    let span = request_span(trace_id);
+
+    let req: Request = match read_message_as(&mut stream, encoding, compressed)
+      .instrument(span.clone())
+      .await
+    {
+      Ok(x) => x,
+      Err(_) => break,
+    };
+    span.record("opcode", req.data.opcode());
+    span.record("context", req.subgraph.as_str());
+
+    let log_fields = LogFields {
+      context: Some(req.subgraph.clone()),
+      opcode:  Some(req.data.opcode()),
+      ego:     req.data.read_ego().cloned(),
+    };
+    let start = Instant::now();
+
+    //  Chunked scores are written page by page, so only the time to read
+    //  them is logged.
+    let response = match &req.data {
+      ReqData::ReadScoresChunked(data) => {
+        match with_log_fields(
+          log_fields.clone(),
+          processor.read_score_pages(&req),
+        )
+        .instrument(span.clone())
+        .await
+        {
+          Ok(pages) => {
+            log_request(log_fields, start.elapsed(), &Response::Ok);
+            let written = write_score_pages_as(
+              &mut stream,
+              encoding,
+              compression,
+              pages,
+              data.chunk_size,
+            )
+            .instrument(span)
+            .await;
+            match written {
+              Ok(saved) => processor.add_compression_saved(saved as u64),
+              Err(_) => break,
+            }
+            continue;
+          },
+          Err(response) => response,
+        }
+      },
+      _ => {
+        with_log_fields(log_fields.clone(), processor.process_request(&req))
+          .instrument(span.clone())
+          .await
+      },
+    };
+    log_request(log_fields, start.elapsed(), &response);
+
+    let written =
+      write_message_as(&mut stream, encoding, compression, &response)
+        .instrument(span)
+        .await;
+
+    match written {
+      Ok(saved) => processor.add_compression_saved(saved as u64),
+      Err(_) => break,
+    }
+  }
+}
+
+pub async fn run_server<P: RequestProcessor>(
   settings: Settings,
-  processor: Arc<MultiGraphProcessor>,
+  processor: Arc<P>,
   running: CancellationToken,
 ) -> Result<(), Box<dyn Error>> {
   log_trace!();
 
+  let compression_settings = Compression {
+    level:     settings.compression_level,
+    threshold: settings.compression_threshold,
+  };
+  let tls = tls_acceptor(&settings)?;
+
   let url = format!("{}:{}", settings.server_address, settings.server_port);
 
   let listener = TcpListener::bind(&url).await?;
 
-  log_verbose!("Server running on {}", url);
+  log_verbose!("Server running on {}, TLS: {}", url, tls.is_some());
 
-  loop {
-    let mut stream;
+  let unix_listener = if settings.unix_socket_path.is_empty() {
+    None
+  } else {
+    //  Remove the socket left by a previous run.
+    let _ = std::fs::remove_file(&settings.unix_socket_path);
+    let unix_listener = UnixListener::bind(&settings.unix_socket_path)?;
+    log_verbose!("Server running on {}", settings.unix_socket_path);
+    Some(unix_listener)
+  };
 
+  loop {
     tokio::select! {
       _ = running.cancelled() => {
         log_verbose!("Server stopped.");
         break;
       }
       accept_result = listener.accept() => {
-        match accept_result {
-          Ok((s, _)) => stream = s,
+        let stream = match accept_result {
+          Ok((s, _)) => s,
           Err(e) => {
             log_error!("Socket accept failed: {}", e);
             break;
           },
         };
+        let processor_cloned = Arc::clone(&processor);
+        match &tls {
+          Some(acceptor) => {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+              match acceptor.accept(stream).await {
+                Ok(s) => {
+                  serve_connection(s, processor_cloned, compression_settings)
+                    .await
+                },
+                Err(e) => log_warning!("TLS handshake failed: {}", e),
+              }
+            });
+          },
+          None => {
+            tokio::spawn(serve_connection(
+              stream,
+              processor_cloned,
+              compression_settings,
+            ));
+          },
+        }
       }
-    };
-
-    let processor_cloned = Arc::clone(&processor);
-
-    tokio::spawn(async move {
-      loop {
-        let req = match read_request(&mut stream).await {
-          Ok(x) => x,
-          Err(_) => break,
+      accept_result = async { unix_listener.as_ref().unwrap().accept().await },
+        if unix_listener.is_some() =>
+      {
+        let stream = match accept_result {
+          Ok((s, _)) => s,
+          Err(e) => {
+            log_error!("Socket accept failed: {}", e);
+            break;
+          },
         };
-
-        let response = processor_cloned.process_request(&req).await;
-
-        if write_response(&mut stream, response).await.is_err() {
-          break;
-        }
+        tokio::spawn(serve_connection(
+          stream,
+          Arc::clone(&processor),
+          compression_settings,
+        ));
       }
-    });
+    };
+  }
+
+  if unix_listener.is_some() {
+    let _ = std::fs::remove_file(&settings.unix_socket_path);
   }
 
   Ok(())
@@ -128,9 +655,10 @@ pub async fn run_server(
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::state_manager::test_utils::write_edge_req;
 
   use tokio::{
-    net::TcpSocket,
+    net::{TcpSocket, TcpStream, UnixStream},
     time::{timeout, Duration},
   };
 
@@ -138,6 +666,7 @@ mod tests {
     Settings {
       server_port: port,
       min_ops_before_swap: 1,
+      compression_threshold: 0,
       ..Settings::default()
     }
   }
@@ -198,6 +727,7 @@ mod tests {
       stream,
       Request {
         subgraph: "".into(),
+        token:    String::new(),
         data:     ReqData::Sync(1),
       },
     )
@@ -207,14 +737,24 @@ mod tests {
 
   fn test_score_options() -> FilterOptions {
     FilterOptions {
-      node_kind:     None,
-      hide_personal: true,
-      score_lt:      100.0,
-      score_lte:     false,
-      score_gt:      -100.0,
-      score_gte:     false,
-      index:         0,
-      count:         100,
+      node_kind:              None,
+      hide_personal:          true,
+      score_lt:               100.0,
+      score_lte:              false,
+      score_gt:               -100.0,
+      score_gte:              false,
+      index:                  0,
+      count:                  100,
+      num_clusters:           0,
+      cluster_min:            0,
+      cluster_max:            u32::MAX,
+      filter_on:              ScoreComponent::Blended,
+      include_negative_only:  false,
+      ascending:              false,
+      exclude_flags:          0,
+      require_flags:          0,
+      max_latency_ms:         0,
+      include_reverse_scores: false,
     }
   }
 
@@ -233,24 +773,14 @@ mod tests {
     wait_for_server(8082).await;
 
     let mut stream = connect_to(8082).await;
-    let _ = roundtrip_then_sync(
-      &mut stream,
-      Request {
-        subgraph: "".into(),
-        data:     ReqData::WriteEdge(OpWriteEdge {
-          src:       "U1".into(),
-          dst:       "U2".into(),
-          amount:    1.0,
-          magnitude: 1,
-        }),
-      },
-    )
-    .await;
+    let write = write_edge_req("", "U1", "U2", 1.0);
+    let _ = roundtrip_then_sync(&mut stream, write).await;
     // Lazy calculation on read: poll until scores appear (no sleep; same pattern as state_manager tests).
     let mut scores_resp = roundtrip(
       &mut stream,
       Request {
         subgraph: "".into(),
+        token:    String::new(),
         data:     ReqData::ReadScores(OpReadScores {
           ego:           "U1".into(),
           score_options: test_score_options(),
@@ -269,6 +799,7 @@ mod tests {
         &mut stream,
         Request {
           subgraph: "".into(),
+          token:    String::new(),
           data:     ReqData::ReadScores(OpReadScores {
             ego:           "U1".into(),
             score_options: test_score_options(),
@@ -280,9 +811,9 @@ mod tests {
     match scores_resp {
       Response::Scores(scores) => {
         // U2 is not "owned by U1" so hide_personal=true does not filter it out.
-        assert!(scores.scores.len() > 0);
+        assert!(!scores.scores.is_empty());
       },
-      _ => assert!(false),
+      _ => unreachable!(),
     };
 
     running.cancel();
@@ -297,23 +828,13 @@ mod tests {
     wait_for_server(8083).await;
 
     let mut stream = connect_to(8083).await;
-    let _ = roundtrip(
-      &mut stream,
-      Request {
-        subgraph: "".into(),
-        data:     ReqData::WriteEdge(OpWriteEdge {
-          src:       "U1".into(),
-          dst:       "U2".into(),
-          amount:    1.0,
-          magnitude: 1,
-        }),
-      },
-    )
-    .await;
+    let write = write_edge_req("", "U1", "U2", 1.0);
+    let _ = roundtrip(&mut stream, write).await;
     let _ = roundtrip_then_sync(
       &mut stream,
       Request {
         subgraph: "".into(),
+        token:    String::new(),
         data:     ReqData::WriteCalculate(OpWriteCalculate { ego: "U1".into() }),
       },
     )
@@ -322,6 +843,7 @@ mod tests {
       &mut stream,
       Request {
         subgraph: "".into(),
+        token:    String::new(),
         data:     ReqData::ReadScores(OpReadScores {
           ego:           "U1".into(),
           score_options: test_score_options(),
@@ -338,12 +860,349 @@ mod tests {
         assert!(scores.scores[1].score > 0.25);
         assert!(scores.scores[1].score < 0.45);
       },
-      _ => assert!(false),
+      _ => unreachable!(),
+    };
+
+    running.cancel();
+    let _ = timeout(Duration::from_secs(1), &mut server_task)
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn legacy_connections() {
+    let (mut server_task, running) = spawn_server(8093);
+    wait_for_server(8093).await;
+
+    let mut stream = connect_to(8093).await;
+    stream.write_all(&[LEGACY_MAGIC]).await.unwrap();
+    let mut call = async |id: &str, payload: Vec<u8>| {
+      let command = rmp_serde::to_vec(&(id, "", true, payload)).unwrap();
+      let len = command.len() as u32;
+      stream.write_all(&len.to_be_bytes()).await.unwrap();
+      stream.write_all(&command).await.unwrap();
+      read_frame(&mut stream, false).await.unwrap()
+    };
+
+    let put = rmp_serde::to_vec(&("U1", "U2", 1.0, 0i64)).unwrap();
+    let _ = call("put_edge", put).await;
+    let _ = call("sync", rmp_serde::to_vec(&()).unwrap()).await;
+    let edges = call("edges", rmp_serde::to_vec(&()).unwrap()).await;
+    let edges: Vec<(String, String, f64)> =
+      rmp_serde::from_slice(&edges).unwrap();
+    assert_eq!(edges, vec![("U1".into(), "U2".into(), 1.0)]);
+
+    running.cancel();
+    let _ = timeout(Duration::from_secs(1), &mut server_task)
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn json_and_cbor_connections() {
+    let (mut server_task, running) = spawn_server(8084);
+    wait_for_server(8084).await;
+
+    let mut stream = connect_to(8084).await;
+    stream.write_all(&[JSON_MAGIC]).await.unwrap();
+    let request = b"{\"subgraph\":\"\",\"token\":\"\",\"data\":\"GetStats\"}\n";
+    stream.write_all(request).await.unwrap();
+    let mut stream = BufReader::new(stream);
+    let mut line = vec![];
+    stream.read_until(b'\n', &mut line).await.unwrap();
+    match serde_json::from_slice::<Response>(&line).unwrap() {
+      Response::Stats(_) => {},
+      other => panic!("expected Stats, got {:?}", other),
+    };
+
+    let mut stream = BufReader::new(connect_to(8084).await);
+    stream.write_all(&[CBOR_MAGIC]).await.unwrap();
+    let request = Request {
+      subgraph: "".into(),
+      token:    String::new(),
+      data:     ReqData::GetStats,
+    };
+    write_message_as(&mut stream, WireEncoding::Cbor, None, &request)
+      .await
+      .unwrap();
+    let response: Response =
+      read_message_as(&mut stream, WireEncoding::Cbor, false)
+        .await
+        .unwrap();
+    match response {
+      Response::Stats(_) => {},
+      other => panic!("expected Stats, got {:?}", other),
+    };
+
+    running.cancel();
+    let _ = timeout(Duration::from_secs(1), &mut server_task)
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn compressed_messages() {
+    let response = Response::NodeList(ResNodeList {
+      nodes: (0..1000).map(|n| (format!("U{}", n % 10),)).collect(),
+    });
+    let compression = Compression {
+      level:     3,
+      threshold: 0,
+    };
+    let (mut client, server) = tokio::io::duplex(1 << 20);
+    let saved = write_message_as(
+      &mut client,
+      WireEncoding::Bincode,
+      Some(compression),
+      &response,
+    )
+    .await
+    .unwrap();
+    assert!(saved > 0);
+
+    let mut server = BufReader::new(server);
+    let decoded: Response =
+      read_message_as(&mut server, WireEncoding::Bincode, true)
+        .await
+        .unwrap();
+    match decoded {
+      Response::NodeList(list) => {
+        assert_eq!(list.nodes.len(), 1000);
+        assert_eq!(list.nodes[999].0, "U9");
+      },
+      other => panic!("expected NodeList, got {:?}", other),
+    };
+
+    let (mut server_task, running) = spawn_server(8085);
+    wait_for_server(8085).await;
+
+    let mut stream = connect_to(8085).await;
+    stream.write_all(&[COMPRESSION_MAGIC]).await.unwrap();
+    write_request(
+      &mut stream,
+      Request {
+        subgraph: "".into(),
+        token:    String::new(),
+        data:     ReqData::GetStats,
+      },
+    )
+    .await
+    .unwrap();
+    let stats: Response = decode_message(
+      WireEncoding::Bincode,
+      &read_frame(&mut stream, true).await.unwrap(),
+    )
+    .unwrap();
+    assert!(matches!(stats, Response::Stats(_)));
+
+    running.cancel();
+    let _ = timeout(Duration::from_secs(1), &mut server_task)
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn rejected_frames() {
+    let response = Response::NodeList(ResNodeList {
+      nodes: (0..1000).map(|n| (format!("U{}", n % 10),)).collect(),
+    });
+    let compression = Compression {
+      level:     3,
+      threshold: 0,
     };
+    let (mut client, server) = tokio::io::duplex(1 << 20);
+    write_message_as(
+      &mut client,
+      WireEncoding::Bincode,
+      Some(compression),
+      &response,
+    )
+    .await
+    .unwrap();
+    let mut server = BufReader::new(server);
+    assert!(read_frame(&mut server, false).await.is_err());
+
+    let bomb = zstd::bulk::compress(&[0u8; 4096], 3).unwrap();
+    assert_eq!(decompress_limited(&bomb, 4096).unwrap().len(), 4096);
+    assert!(decompress_limited(&bomb, 4095).is_err());
+
+    let (mut client, server) = tokio::io::duplex(1 << 10);
+    client.write_all(b"{}\n").await.unwrap();
+    client.write_all(&[b' '; 64]).await.unwrap();
+    let mut server = BufReader::new(server);
+    assert_eq!(read_line_limited(&mut server, 16).await.unwrap(), b"{}\n");
+    assert!(read_line_limited(&mut server, 16).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn traced_requests() {
+    let (mut server_task, running) = spawn_server(8092);
+    wait_for_server(8092).await;
+
+    let mut stream = connect_to(8092).await;
+    stream.write_all(&[TRACE_MAGIC]).await.unwrap();
+    for trace_id in [[0x4b; 16], [0; 16]] {
+      stream.write_all(&trace_id).await.unwrap();
+      let stats = roundtrip(
+        &mut stream,
+        Request {
+          subgraph: "".into(),
+          token:    String::new(),
+          data:     ReqData::GetStats,
+        },
+      )
+      .await;
+      assert!(matches!(stats, Response::Stats(_)));
+    }
+
+    running.cancel();
+    let _ = timeout(Duration::from_secs(1), &mut server_task)
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn chunked_scores() {
+    let (mut server_task, running) = spawn_server(8086);
+    wait_for_server(8086).await;
+
+    let mut stream = connect_to(8086).await;
+    let write = write_edge_req("", "U1", "U2", 1.0);
+    let _ = roundtrip(&mut stream, write).await;
+    let _ = roundtrip_then_sync(
+      &mut stream,
+      Request {
+        subgraph: "".into(),
+        token:    String::new(),
+        data:     ReqData::WriteCalculate(OpWriteCalculate { ego: "U1".into() }),
+      },
+    )
+    .await;
+
+    write_request(
+      &mut stream,
+      Request {
+        subgraph: "".into(),
+        token:    String::new(),
+        data:     ReqData::ReadScoresChunked(OpReadScoresChunked {
+          ego:           "U1".into(),
+          score_options: test_score_options(),
+          chunk_size:    1,
+        }),
+      },
+    )
+    .await
+    .unwrap();
+    let mut chunks = vec![];
+    loop {
+      match read_response(&mut stream).await.unwrap() {
+        Response::ScoresChunk(chunk) => {
+          let more = chunk.more;
+          chunks.push(chunk);
+          if !more {
+            break;
+          }
+        },
+        other => panic!("expected ScoresChunk, got {:?}", other),
+      };
+    }
+
+    assert_eq!(chunks.len(), 2);
+    assert!(chunks.iter().all(|chunk| chunk.scores.len() == 1));
+    assert!(chunks[0].scores[0].score >= chunks[1].scores[0].score);
+
+    running.cancel();
+    let _ = timeout(Duration::from_secs(1), &mut server_task)
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn stalled_chunked_read_does_not_hold_back_writes() {
+    let proc = MultiGraphProcessor::new(test_settings(0));
+    let request = |data: ReqData| Request {
+      subgraph: "".into(),
+      token: String::new(),
+      data,
+    };
+    for dst in ["U2", "U3", "U4"] {
+      let _ = proc.process_request(&write_edge_req("", "U1", dst, 1.0)).await;
+    }
+    let _ = proc.process_request(&request(ReqData::Sync(1))).await;
+
+    let pages = proc
+      .read_score_pages(&request(ReqData::ReadScoresChunked(
+        OpReadScoresChunked {
+          ego:           "U1".into(),
+          score_options: test_score_options(),
+          chunk_size:    1,
+        },
+      )))
+      .await
+      .unwrap();
+    //  The client reads nothing, so the first chunk fills the pipe.
+    let (mut server_end, _client_end) = tokio::io::duplex(16);
+    let stalled = tokio::spawn(async move {
+      let encoding = WireEncoding::Bincode;
+      write_score_pages_as(&mut server_end, encoding, None, pages, 1)
+        .await
+        .is_ok()
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!stalled.is_finished());
+
+    let _ = proc.process_request(&write_edge_req("", "U1", "U5", 1.0)).await;
+    timeout(
+      Duration::from_secs(5),
+      proc.process_request(&request(ReqData::Sync(2))),
+    )
+    .await
+    .expect("writes were not published");
+    stalled.abort();
+  }
+
+  #[tokio::test]
+  async fn unix_socket_connection() {
+    let path = std::env::temp_dir().join(format!(
+      "meritrank-test-{}.sock",
+      std::process::id()
+    ));
+    let running = CancellationToken::new();
+    let running_cloned = running.clone();
+    let settings = Settings {
+      unix_socket_path: path.to_string_lossy().into(),
+      ..test_settings(8087)
+    };
+    let mut server_task = tokio::spawn(async move {
+      run_server(
+        settings.clone(),
+        Arc::new(MultiGraphProcessor::new(settings)),
+        running_cloned,
+      )
+      .await
+      .unwrap();
+    });
+    wait_for_server(8087).await;
+
+    let mut stream = BufReader::new(UnixStream::connect(&path).await.unwrap());
+    let request = Request {
+      subgraph: "".into(),
+      token:    String::new(),
+      data:     ReqData::GetStats,
+    };
+    write_message_as(&mut stream, WireEncoding::Bincode, None, &request)
+      .await
+      .unwrap();
+    let response: Response =
+      read_message_as(&mut stream, WireEncoding::Bincode, false)
+        .await
+        .unwrap();
+    assert!(matches!(response, Response::Stats(_)));
 
     running.cancel();
     let _ = timeout(Duration::from_secs(1), &mut server_task)
       .await
       .unwrap();
+    assert!(!path.exists());
   }
 }
@@ -0,0 +1,1161 @@
+//! Router mode: contexts are sharded across several services by consistent
+//! hashing of their names, and the router forwards each request to the
+//! service that owns its context.
+//!
+//! Users are present in every context, so user-to-user edges and renames go
+//! to every shard. The default context aggregates the edges of all contexts,
+//! so the shard that owns it also gets the edges written to other shards.
+//!
+//! `MoveContext` moves a context to another shard: the router copies a
+//! snapshot of it, replays the writes made to it since with writes paused,
+//! switches the context over and deletes it on the old shard. Moved contexts
+//! stay pinned to their shard, also across restarts with
+//! `MERITRANK_ROUTER_STATE`.
+
+use crate::auth::AccessControl;
+use crate::data::*;
+use crate::file_import::{import_path, read_edge_list, ImportProgress};
+use crate::idempotency::{self, IdempotencyWindow};
+use crate::node_registry::node_kind_from_prefix;
+use crate::request_handler::{read_response, write_request, RequestProcessor};
+use crate::settings::Settings;
+use crate::sql_import::fetch_edges;
+use crate::tls::{connect, tls_connector, ServiceStream};
+use crate::utils::files::write_atomic;
+use crate::utils::log::*;
+
+use async_trait::async_trait;
+use parking_lot::{Mutex, RwLock};
+use tokio_rustls::TlsConnector;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Points of each shard on the ring. More points spread contexts more evenly.
+const VIRTUAL_NODES: usize = 128;
+
+/// FNV-1a with a final mix, so that similar names land far apart.
+fn hash_name(name: &str) -> u64 {
+  let mut hash: u64 = 0xcbf29ce484222325;
+  for byte in name.bytes() {
+    hash ^= byte as u64;
+    hash = hash.wrapping_mul(0x100000001b3);
+  }
+  hash ^= hash >> 33;
+  hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+  hash ^= hash >> 33;
+  hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+  hash ^ (hash >> 33)
+}
+
+/// Consistent hashing of context names onto shards. Adding a shard only
+/// changes the owner of the contexts that land on its points.
+pub struct HashRing {
+  points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+  /// `shards` must not be empty.
+  pub fn new(shards: &[String]) -> Self {
+    let mut points: Vec<(u64, usize)> = shards
+      .iter()
+      .enumerate()
+      .flat_map(|(index, address)| {
+        (0..VIRTUAL_NODES)
+          .map(move |i| (hash_name(&format!("{}#{}", address, i)), index))
+      })
+      .collect();
+    points.sort_unstable();
+    HashRing { points }
+  }
+
+  /// Index of the shard that owns the context.
+  pub fn shard_of(
+    &self,
+    context: &str,
+  ) -> usize {
+    let hash = hash_name(context);
+    let i = self.points.partition_point(|(point, _)| *point < hash);
+    self.points[i % self.points.len()].1
+  }
+}
+
+fn is_success(response: &Response) -> bool {
+  !matches!(
+    response,
+    Response::Fail
+      | Response::NotImplemented
+      | Response::Error(_)
+      | Response::Busy
+  )
+}
+
+fn is_user_edge(
+  src: &NodeName,
+  dst: &NodeName,
+) -> bool {
+  node_kind_from_prefix(src) == Some(NodeKind::User)
+    && node_kind_from_prefix(dst) == Some(NodeKind::User)
+}
+
+async fn exchange(
+  stream: &mut Box<dyn ServiceStream>,
+  request: &Request,
+) -> Option<Response> {
+  if let Err(e) = write_request(stream, request.clone()).await {
+    log_verbose!("Shard connection failed: {}", e);
+    return None;
+  }
+  match read_response(stream).await {
+    Ok(response) => Some(response),
+    Err(e) => {
+      log_verbose!("Shard connection failed: {}", e);
+      None
+    },
+  }
+}
+
+struct Shard {
+  address: String,
+  /// Set if shards are connected to over TLS, see `MERITRANK_TLS_CA`.
+  tls:     Option<TlsConnector>,
+  idle:    Mutex<Vec<Box<dyn ServiceStream>>>,
+}
+
+impl Shard {
+  /// Sends the request over an idle connection. If there is none, or it was
+  /// closed, the request is sent over a new one.
+  async fn call(
+    &self,
+    request: &Request,
+  ) -> Response {
+    let pooled = self.idle.lock().pop();
+    if let Some(mut stream) = pooled {
+      if let Some(response) = exchange(&mut stream, request).await {
+        self.idle.lock().push(stream);
+        return response;
+      }
+    }
+
+    let mut stream = match connect(&self.address, self.tls.as_ref()).await {
+      Ok(stream) => stream,
+      Err(e) => {
+        log_error!("Shard {} is unreachable: {}", self.address, e);
+        return Response::Fail;
+      },
+    };
+    match exchange(&mut stream, request).await {
+      Some(response) => {
+        self.idle.lock().push(stream);
+        response
+      },
+      None => {
+        log_error!("Request to shard {} failed", self.address);
+        Response::Fail
+      },
+    }
+  }
+}
+
+/// Loads the shards of moved contexts, saved as context name to address.
+fn load_placement(
+  path: &str,
+  shards: &[String],
+) -> HashMap<SubgraphName, usize> {
+  if path.is_empty() {
+    return HashMap::new();
+  }
+  let state: HashMap<SubgraphName, String> = match fs::read(path) {
+    Ok(bytes) => match serde_json::from_slice(&bytes) {
+      Ok(state) => state,
+      Err(e) => {
+        log_error!("Failed to parse router state {:?}: {}", path, e);
+        return HashMap::new();
+      },
+    },
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return HashMap::new(),
+    Err(e) => {
+      log_error!("Failed to read router state {:?}: {}", path, e);
+      return HashMap::new();
+    },
+  };
+  state
+    .into_iter()
+    .filter_map(|(context, address)| {
+      match shards.iter().position(|shard| *shard == address) {
+        Some(index) => Some((context, index)),
+        None => {
+          log_warning!("Context {:?} is on unknown shard {}", context, address);
+          None
+        },
+      }
+    })
+    .collect()
+}
+
+pub struct Router {
+  shards:            Vec<Shard>,
+  ring:              HashRing,
+  /// Contexts pinned to a shard by `MoveContext`, whatever the ring gives.
+  placement:         RwLock<HashMap<SubgraphName, usize>>,
+  state_file:        String,
+  acl:               AccessControl,
+  /// See `ImportFromSql`; the router imports and splits the edges itself.
+  import_url:        String,
+  import_query:      String,
+  /// See `ImportEdgeList`; the router reads the file itself.
+  import_dir:        String,
+  import_progress:   Arc<ImportProgress>,
+  /// Keys of `Idempotent` writes, deduplicated by the router itself.
+  idempotency:       IdempotencyWindow,
+  /// Writes are rejected by the router, see `SetMaintenanceMode`.
+  maintenance:       AtomicBool,
+  /// Writes to the contexts being moved, replayed on the new shard.
+  migrations:        Mutex<HashMap<SubgraphName, Vec<Request>>>,
+  /// Held by writes, and exclusively while a moved context is switched over.
+  write_gate:        tokio::sync::RwLock<()>,
+  compression_saved: AtomicU64,
+}
+
+impl Router {
+  /// `settings.shards` must not be empty. Fails if the TLS certificates can't
+  /// be loaded.
+  pub fn new(settings: &Settings) -> Result<Self, Box<dyn Error>> {
+    let tls = tls_connector(settings)?;
+    let placement =
+      load_placement(&settings.router_state_file, &settings.shards);
+    Ok(Router {
+      shards:            settings
+        .shards
+        .iter()
+        .map(|address| Shard {
+          address: address.clone(),
+          tls:     tls.clone(),
+          idle:    Mutex::new(vec![]),
+        })
+        .collect(),
+      ring:              HashRing::new(&settings.shards),
+      placement:         RwLock::new(placement),
+      state_file:        settings.router_state_file.clone(),
+      acl:               settings.acl.clone(),
+      import_url:        settings.import_database_url.clone(),
+      import_query:      settings.import_query.clone(),
+      import_dir:        settings.import_dir.clone(),
+      import_progress:   Arc::new(ImportProgress::default()),
+      idempotency:       IdempotencyWindow::new(Duration::from_secs(
+        settings.idempotency_window,
+      )),
+      maintenance:       AtomicBool::new(false),
+      migrations:        Mutex::new(HashMap::new()),
+      write_gate:        tokio::sync::RwLock::new(()),
+      compression_saved: AtomicU64::new(0),
+    })
+  }
+
+  fn shard_of(
+    &self,
+    context: &SubgraphName,
+  ) -> usize {
+    match self.placement.read().get(context) {
+      Some(&index) => index,
+      None => self.ring.shard_of(context),
+    }
+  }
+
+  fn is_moving(
+    &self,
+    context: &SubgraphName,
+  ) -> bool {
+    self.migrations.lock().contains_key(context)
+  }
+
+  pub async fn process_request(
+    &self,
+    req: &Request,
+  ) -> Response {
+    log_trace!();
+
+    if let ReqData::MoveContext(data) = &req.data {
+      return self.move_context(req, &data.shard).await;
+    }
+    if let ReqData::Idempotent(data) = &req.data {
+      if let Err(e) = idempotency::validate(data) {
+        return Response::Error(ServiceError::InvalidWrite(e));
+      }
+      let inner = idempotency::inner_request(req, data);
+      let process = Box::pin(self.process_request(&inner));
+      return self.idempotency.process(&req.subgraph, &data.key, process).await;
+    }
+    if let ReqData::SetMaintenanceMode(data) = &req.data {
+      return self.set_maintenance_mode(req, data);
+    }
+    if !req.data.is_write() {
+      return self.route(req).await;
+    }
+    if self.maintenance.load(Ordering::SeqCst)
+      && !matches!(&req.data, ReqData::ResetStats)
+    {
+      log_warning!("Write rejected in maintenance: {}", req.data.opcode());
+      return Response::Error(ServiceError::Maintenance);
+    }
+
+    let _gate = self.write_gate.read().await;
+    let response = self.route(req).await;
+    if is_success(&response) {
+      if let Some(tail) = self.migrations.lock().get_mut(&req.subgraph) {
+        tail.push(req.clone());
+      }
+    }
+    response
+  }
+
+  async fn route(
+    &self,
+    req: &Request,
+  ) -> Response {
+    match &req.data {
+      ReqData::WriteBulkEdges(data) => self.bulk_load(req, data).await,
+      ReqData::ImportFromSql => self.import_from_sql(req).await,
+      ReqData::ImportEdgeList(data) => self.import_edge_list(req, data).await,
+      ReqData::ReadContexts => self.read_contexts(req).await,
+      ReqData::GetStats => self.read_stats(req).await,
+      ReqData::ReadSlowLog(data) => self.read_slow_log(req, data.limit).await,
+      ReqData::WriteForkContext(data) => {
+        if self.is_moving(&data.destination) {
+          return Response::Busy;
+        }
+        let from = self.shard_of(&data.source);
+        let to = self.shard_of(&data.destination);
+        if from == to {
+          return self.shards[from].call(req).await;
+        }
+        self
+          .copy_context(&req.token, &data.source, from, &data.destination, to)
+          .await
+      },
+      ReqData::WriteMergeContext(data) => {
+        if self.is_moving(&data.destination) {
+          return Response::Busy;
+        }
+        let shard = self.shard_of(&data.destination);
+        if self.shard_of(&data.source) != shard {
+          log_warning!(
+            "Cannot merge {:?} into {:?} on another shard",
+            data.source,
+            data.destination
+          );
+          return Response::Fail;
+        }
+        self.shards[shard].call(req).await
+      },
+      ReqData::WriteReset => {
+        if !self.migrations.lock().is_empty() {
+          return Response::Busy;
+        }
+        self.broadcast(req).await
+      },
+      ReqData::Sync(_)
+      | ReqData::ResetStats
+      | ReqData::WriteAliasNode(_)
+      | ReqData::Promote
+      | ReqData::WriteWebhook(_)
+      | ReqData::DeleteWebhook(_) => self.broadcast(req).await,
+      ReqData::Rerank(_) => self.rerank(req).await,
+      ReqData::WriteEdge(data) => {
+        self.write_edge(req, &data.src, &data.dst).await
+      },
+      ReqData::WriteEdgeCas(data) => self.write_edge_cas(req, data).await,
+      ReqData::WriteMulti(data) => self.write_multi(req, data).await,
+      ReqData::WriteDeleteEdge(data) => {
+        self.write_edge(req, &data.src, &data.dst).await
+      },
+      ReqData::WriteDeleteNode(_) | ReqData::WriteNode(_) => {
+        self.write_with_aggregate(req).await
+      },
+      ReqData::ReadScoresChunked(data) => {
+        //  Chunks are written by the connection of the router.
+        let request = Request {
+          subgraph: req.subgraph.clone(),
+          token:    req.token.clone(),
+          data:     ReqData::ReadScores(OpReadScores {
+            ego:           data.ego.clone(),
+            score_options: data.score_options.clone(),
+          }),
+        };
+        self.shards[self.shard_of(&req.subgraph)].call(&request).await
+      },
+      _ => self.shards[self.shard_of(&req.subgraph)].call(req).await,
+    }
+  }
+
+  /// Sends the request to every shard. Returns the first failure, if any.
+  async fn broadcast(
+    &self,
+    req: &Request,
+  ) -> Response {
+    let mut result = Response::Ok;
+    for shard in &self.shards {
+      let response = shard.call(req).await;
+      if !is_success(&response) {
+        return response;
+      }
+      result = response;
+    }
+    result
+  }
+
+  /// The owner of the context checks and writes the edge; the other shards
+  /// that get it, as with `write_edge`, get a plain `WriteEdge`.
+  async fn write_edge_cas(
+    &self,
+    req: &Request,
+    data: &OpWriteEdgeCas,
+  ) -> Response {
+    let owner = self.shard_of(&req.subgraph);
+    let response = self.shards[owner].call(req).await;
+    if !is_success(&response) {
+      return response;
+    }
+    let request = Request {
+      subgraph: String::new(),
+      token:    req.token.clone(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        data.src.clone(),
+        dst:        data.dst.clone(),
+        amount:     data.new_weight,
+        magnitude:  0,
+        edge_kind:  data.edge_kind,
+        provenance: None,
+      }),
+    };
+    let user_edge = is_user_edge(&data.src, &data.dst);
+    let aggregate = self.shard_of(&String::new());
+    for (index, shard) in self.shards.iter().enumerate() {
+      if index == owner || !(user_edge || index == aggregate) {
+        continue;
+      }
+      let response = shard.call(&request).await;
+      if !is_success(&response) {
+        return response;
+      }
+    }
+    response
+  }
+
+  /// User-to-user edges go to every shard, since users are in every context.
+  async fn write_edge(
+    &self,
+    req: &Request,
+    src: &NodeName,
+    dst: &NodeName,
+  ) -> Response {
+    if !is_user_edge(src, dst) {
+      return self.write_with_aggregate(req).await;
+    }
+    let owner = self.shard_of(&req.subgraph);
+    let response = self.shards[owner].call(req).await;
+    if !is_success(&response) {
+      return response;
+    }
+    let request = Request {
+      subgraph: String::new(),
+      ..req.clone()
+    };
+    for (index, shard) in self.shards.iter().enumerate() {
+      if index == owner {
+        continue;
+      }
+      let response = shard.call(&request).await;
+      if !is_success(&response) {
+        return response;
+      }
+    }
+    response
+  }
+
+  /// Parts of `WriteMulti` are applied together by a single shard, so their
+  /// contexts must be on the shard of the default context, and user-to-user
+  /// edges, which every shard gets, can only be written with one shard.
+  async fn write_multi(
+    &self,
+    req: &Request,
+    data: &OpWriteMulti,
+  ) -> Response {
+    let shard = self.shard_of(&String::new());
+    let single_shard = data.ops.iter().all(|(context, op)| {
+      let user_edge = match op {
+        ReqData::WriteEdge(x) => is_user_edge(&x.src, &x.dst),
+        ReqData::WriteDeleteEdge(x) => is_user_edge(&x.src, &x.dst),
+        _ => false,
+      };
+      self.shard_of(context) == shard && (self.shards.len() == 1 || !user_edge)
+    });
+    if !single_shard {
+      log_warning!("Cannot write {} parts across shards", data.ops.len());
+      return Response::Fail;
+    }
+    if data.ops.iter().any(|(context, _)| self.is_moving(context)) {
+      return Response::Busy;
+    }
+    self.shards[shard].call(req).await
+  }
+
+  /// Writes to the owner of the context, and to the owner of the default
+  /// context, which aggregates the edges of all contexts.
+  async fn write_with_aggregate(
+    &self,
+    req: &Request,
+  ) -> Response {
+    let owner = self.shard_of(&req.subgraph);
+    let response = self.shards[owner].call(req).await;
+    let aggregate = self.shard_of(&String::new());
+    if aggregate == owner || !is_success(&response) {
+      return response;
+    }
+    let request = Request {
+      subgraph: String::new(),
+      ..req.clone()
+    };
+    self.shards[aggregate].call(&request).await
+  }
+
+  /// Shards keep accepting writes, so that contexts can be moved while
+  /// clients of the router are kept from writing.
+  fn set_maintenance_mode(
+    &self,
+    req: &Request,
+    data: &OpSetMaintenanceMode,
+  ) -> Response {
+    if self.acl.is_enabled() {
+      if let Err(e) = self.acl.check_all(&req.token, true) {
+        log_warning!("Request rejected: {:?}", e);
+        return Response::Error(e);
+      }
+    }
+    self.maintenance.store(data.read_only, Ordering::SeqCst);
+    log_info!("Maintenance mode: {}", data.read_only);
+    Response::Ok
+  }
+
+  async fn import_from_sql(
+    &self,
+    req: &Request,
+  ) -> Response {
+    if self.acl.is_enabled() {
+      if let Err(e) = self.acl.check_all(&req.token, true) {
+        return Response::Error(e);
+      }
+    }
+    match fetch_edges(&self.import_url, &self.import_query).await {
+      Ok(edges) => {
+        log_info!("Importing {} edges", edges.len());
+        self.bulk_load(req, &OpWriteBulkEdges { edges }).await
+      },
+      Err(e) => {
+        log_error!("Import failed: {:?}", e);
+        Response::Error(e)
+      },
+    }
+  }
+
+  async fn import_edge_list(
+    &self,
+    req: &Request,
+    data: &OpImportEdgeList,
+  ) -> Response {
+    if self.acl.is_enabled() {
+      if let Err(e) = self.acl.check_all(&req.token, true) {
+        return Response::Error(e);
+      }
+    }
+    let path = match import_path(&self.import_dir, &data.path) {
+      Ok(x) => x,
+      Err(e) => return Response::Error(e),
+    };
+    let progress = Arc::clone(&self.import_progress);
+    let edges = tokio::task::spawn_blocking(move || {
+      read_edge_list(&path, &progress)
+    })
+    .await
+    .unwrap_or_else(|e| Err(ServiceError::ImportFailed(e.to_string())));
+    let response = match edges {
+      Ok(edges) => {
+        log_info!("Importing {} edges", edges.len());
+        self.bulk_load(req, &OpWriteBulkEdges { edges }).await
+      },
+      Err(e) => {
+        log_error!("Import failed: {:?}", e);
+        Response::Error(e)
+      },
+    };
+    self.import_progress.reset();
+    response
+  }
+
+  async fn bulk_load(
+    &self,
+    req: &Request,
+    data: &OpWriteBulkEdges,
+  ) -> Response {
+    if !self.migrations.lock().is_empty() {
+      return Response::Busy;
+    }
+
+    let aggregate = self.shard_of(&String::new());
+    let mut edges: Vec<Vec<BulkEdge>> = vec![vec![]; self.shards.len()];
+    for edge in &data.edges {
+      if is_user_edge(&edge.src, &edge.dst) {
+        for shard_edges in &mut edges {
+          shard_edges.push(edge.clone());
+        }
+        continue;
+      }
+      let owner = self.shard_of(&edge.context);
+      if owner != aggregate {
+        edges[aggregate].push(BulkEdge {
+          context: String::new(),
+          ..edge.clone()
+        });
+      }
+      edges[owner].push(edge.clone());
+    }
+
+    for (shard, edges) in self.shards.iter().zip(edges) {
+      let request = Request {
+        subgraph: req.subgraph.clone(),
+        token:    req.token.clone(),
+        data:     ReqData::WriteBulkEdges(OpWriteBulkEdges { edges }),
+      };
+      let response = shard.call(&request).await;
+      if !is_success(&response) {
+        return response;
+      }
+    }
+    Response::Ok
+  }
+
+  /// Contexts of all shards. Each shard has its own default context; only the
+  /// one of its owner is listed.
+  async fn read_contexts(
+    &self,
+    req: &Request,
+  ) -> Response {
+    let mut contexts = vec![];
+    for (index, shard) in self.shards.iter().enumerate() {
+      match shard.call(req).await {
+        Response::Contexts(res) => contexts.extend(
+          res
+            .contexts
+            .into_iter()
+            .filter(|context| self.shard_of(&context.name) == index),
+        ),
+        response => return response,
+      }
+    }
+    Response::Contexts(ResContexts { contexts })
+  }
+
+  /// Contexts reranked by all shards.
+  async fn rerank(
+    &self,
+    req: &Request,
+  ) -> Response {
+    let mut contexts = vec![];
+    for shard in &self.shards {
+      match shard.call(req).await {
+        Response::Rerank(res) => contexts.extend(res.contexts),
+        response => return response,
+      }
+    }
+    Response::Rerank(ResRerank { contexts })
+  }
+
+  /// Stats of all shards: counters are summed, latencies and bounds are the
+  /// largest of the shards.
+  async fn read_stats(
+    &self,
+    req: &Request,
+  ) -> Response {
+    let mut total = ResStats {
+      compression_saved: self.compression_saved.load(Ordering::Relaxed),
+      import_rows:       self.import_progress.rows.load(Ordering::Relaxed),
+      import_progress:   self.import_progress.fraction(),
+      maintenance:       self.maintenance.load(Ordering::SeqCst),
+      ..ResStats::default()
+    };
+    for shard in &self.shards {
+      let stats = match shard.call(req).await {
+        Response::Stats(stats) => stats,
+        response => return response,
+      };
+      total.pending += stats.pending;
+      total.median_us = total.median_us.max(stats.median_us);
+      total.p95_us = total.p95_us.max(stats.p95_us);
+      total.p99_us = total.p99_us.max(stats.p99_us);
+      total.min_us = total.min_us.max(stats.min_us);
+      total.max_us = total.max_us.max(stats.max_us);
+      total.count += stats.count;
+      total.queue_depth = total.queue_depth.max(stats.queue_depth);
+      total.queue_capacity = total.queue_capacity.max(stats.queue_capacity);
+      total.evictions += stats.evictions;
+      total.reloads += stats.reloads;
+      total.cluster_queue_depth += stats.cluster_queue_depth;
+      total.compression_saved += stats.compression_saved;
+      total.publishes += stats.publishes;
+      total.published_ops += stats.published_ops;
+      total.max_publish_batch =
+        total.max_publish_batch.max(stats.max_publish_batch);
+      total.writer_panics += stats.writer_panics;
+      total.replication_seq = total.replication_seq.max(stats.replication_seq);
+      total.import_rows = total.import_rows.max(stats.import_rows);
+      total.import_progress = total.import_progress.max(stats.import_progress);
+      total.walk_rejections += stats.walk_rejections;
+    }
+    Response::Stats(total)
+  }
+
+  /// Slow requests of all shards, newest first.
+  async fn read_slow_log(
+    &self,
+    req: &Request,
+    limit: u32,
+  ) -> Response {
+    let mut ops = vec![];
+    for shard in &self.shards {
+      match shard.call(req).await {
+        Response::SlowLog(res) => ops.extend(res.ops),
+        response => return response,
+      }
+    }
+    ops.sort_by_key(|x| std::cmp::Reverse(x.time));
+    if limit > 0 {
+      ops.truncate(limit as usize);
+    }
+    Response::SlowLog(ResSlowLog { ops })
+  }
+
+  /// Copies `context` of one shard to `destination` on another. Walks are
+  /// not copied; the other shard calculates them on reads.
+  async fn copy_context(
+    &self,
+    token: &str,
+    context: &SubgraphName,
+    from: usize,
+    destination: &SubgraphName,
+    to: usize,
+  ) -> Response {
+    let request = Request {
+      subgraph: context.clone(),
+      token:    token.to_string(),
+      data:     ReqData::ReadContextSnapshot,
+    };
+    let snapshot = match self.shards[from].call(&request).await {
+      Response::ContextSnapshot(snapshot) => snapshot,
+      response => {
+        log_warning!(
+          "No snapshot of {:?} from shard {}",
+          context,
+          self.shards[from].address
+        );
+        return response;
+      },
+    };
+    let request = Request {
+      subgraph: destination.clone(),
+      token:    token.to_string(),
+      data:     ReqData::WriteContextSnapshot(ContextSnapshot {
+        context: destination.clone(),
+        ..snapshot
+      }),
+    };
+    self.shards[to].call(&request).await
+  }
+
+  async fn move_context(
+    &self,
+    req: &Request,
+    address: &str,
+  ) -> Response {
+    if self.acl.is_enabled() {
+      if let Err(e) = self.acl.check_all(&req.token, true) {
+        log_warning!("Request rejected: {:?}", e);
+        return Response::Error(e);
+      }
+    }
+
+    let context = &req.subgraph;
+    if context.is_empty() {
+      log_warning!("The default context cannot be moved");
+      return Response::Fail;
+    }
+    let to = match self.shards.iter().position(|shard| shard.address == address)
+    {
+      Some(index) => index,
+      None => {
+        log_warning!("Unknown shard: {:?}", address);
+        return Response::Fail;
+      },
+    };
+    let from = self.shard_of(context);
+    if from == to {
+      self.set_placement(context, to);
+      return Response::Ok;
+    }
+
+    {
+      let mut migrations = self.migrations.lock();
+      if migrations.contains_key(context) {
+        log_warning!("Context {:?} is already being moved", context);
+        return Response::Busy;
+      }
+      migrations.insert(context.clone(), vec![]);
+    }
+
+    let response = self
+      .copy_context(&req.token, context, from, context, to)
+      .await;
+    if !is_success(&response) {
+      self.migrations.lock().remove(context);
+      return response;
+    }
+
+    {
+      //  No writes until the switch, so that none is missed.
+      let _gate = self.write_gate.write().await;
+      let tail = self.migrations.lock().remove(context).unwrap_or_default();
+      for request in &tail {
+        let response = self.shards[to].call(request).await;
+        if !is_success(&response) {
+          log_warning!(
+            "Replay of {} to {:?} failed",
+            request.data.opcode(),
+            context
+          );
+        }
+      }
+      self.set_placement(context, to);
+    }
+
+    let request = Request {
+      subgraph: context.clone(),
+      token:    req.token.clone(),
+      data:     ReqData::WriteDeleteContext,
+    };
+    let response = self.shards[from].call(&request).await;
+    if !is_success(&response) {
+      log_warning!(
+        "Context {:?} is left on shard {}",
+        context,
+        self.shards[from].address
+      );
+    }
+
+    log_verbose!("Moved context {:?} to {}", context, address);
+    Response::Ok
+  }
+
+  fn set_placement(
+    &self,
+    context: &SubgraphName,
+    shard: usize,
+  ) {
+    let mut placement = self.placement.write();
+    placement.insert(context.clone(), shard);
+    if self.state_file.is_empty() {
+      return;
+    }
+
+    let state: HashMap<&SubgraphName, &String> = placement
+      .iter()
+      .map(|(context, &index)| (context, &self.shards[index].address))
+      .collect();
+    let saved = serde_json::to_vec(&state)
+      .map_err(io::Error::from)
+      .and_then(|bytes| write_atomic(Path::new(&self.state_file), &bytes));
+    if let Err(e) = saved {
+      log_error!("Failed to save router state {:?}: {}", self.state_file, e);
+    }
+  }
+}
+
+#[async_trait]
+impl RequestProcessor for Router {
+  async fn process_request(
+    &self,
+    req: &Request,
+  ) -> Response {
+    Router::process_request(self, req).await
+  }
+
+  fn add_compression_saved(
+    &self,
+    bytes: u64,
+  ) {
+    self.compression_saved.fetch_add(bytes, Ordering::Relaxed);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::replication::{run_replica, run_replication_server};
+  use crate::request_handler::run_server;
+  use crate::state_manager::MultiGraphProcessor;
+
+  use tokio::net::TcpStream;
+  use tokio_util::sync::CancellationToken;
+
+  use std::sync::Arc;
+  use std::time::{Duration, Instant};
+
+  fn addresses(num_shards: usize) -> Vec<String> {
+    (0..num_shards).map(|i| format!("10.0.0.{}:8080", i)).collect()
+  }
+
+  #[test]
+  fn ring_is_deterministic() {
+    let a = HashRing::new(&addresses(3));
+    let b = HashRing::new(&addresses(3));
+    let mut used = [false; 3];
+    for i in 0..300 {
+      let context = format!("ctx{}", i);
+      assert_eq!(a.shard_of(&context), b.shard_of(&context));
+      used[a.shard_of(&context)] = true;
+    }
+    assert_eq!(used, [true; 3]);
+  }
+
+  #[test]
+  fn new_shard_moves_only_its_contexts() {
+    let before = HashRing::new(&addresses(3));
+    let after = HashRing::new(&addresses(4));
+    let mut moved = 0;
+    for i in 0..1000 {
+      let context = format!("ctx{}", i);
+      if before.shard_of(&context) != after.shard_of(&context) {
+        assert_eq!(after.shard_of(&context), 3);
+        moved += 1;
+      }
+    }
+    assert!(moved > 100 && moved < 400, "moved {}", moved);
+  }
+
+  fn request(
+    subgraph: &str,
+    data: ReqData,
+  ) -> Request {
+    Request {
+      subgraph: subgraph.into(),
+      token:    String::new(),
+      data,
+    }
+  }
+
+  fn write_edge(
+    subgraph: &str,
+    src: &str,
+    dst: &str,
+  ) -> Request {
+    let data = ReqData::WriteEdge(OpWriteEdge {
+      src:        src.into(),
+      dst:        dst.into(),
+      amount:     1.0,
+      magnitude:  0,
+      edge_kind:  EdgeKind::Vote,
+      provenance: None,
+    });
+    request(subgraph, data)
+  }
+
+  async fn read_edges(
+    shard: &Shard,
+    subgraph: &str,
+  ) -> Option<Vec<(NodeName, NodeName)>> {
+    shard.call(&request("", ReqData::Sync(1))).await;
+    match shard.call(&request(subgraph, ReqData::ReadEdges)).await {
+      Response::Edges(res) => {
+        let mut edges: Vec<_> =
+          res.edges.into_iter().map(|edge| (edge.src, edge.dst)).collect();
+        edges.sort();
+        Some(edges)
+      },
+      _ => None,
+    }
+  }
+
+  async fn wait_for_shard(address: &str) {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while Instant::now() < deadline {
+      if TcpStream::connect(address).await.is_ok() {
+        return;
+      }
+      tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("shard {} did not start", address);
+  }
+
+  #[tokio::test]
+  async fn router_moves_context_between_shards() {
+    let running = CancellationToken::new();
+    let ports = [8089, 8090];
+    for port in ports {
+      let settings = Settings {
+        server_port: port,
+        ..Settings::default()
+      };
+      let processor = Arc::new(MultiGraphProcessor::new(settings.clone()));
+      let running = running.clone();
+      tokio::spawn(async move {
+        run_server(settings, processor, running).await.unwrap();
+      });
+    }
+    let shards: Vec<String> =
+      ports.iter().map(|port| format!("127.0.0.1:{}", port)).collect();
+    for address in &shards {
+      wait_for_shard(address).await;
+    }
+
+    let router = Router::new(&Settings {
+      shards: shards.clone(),
+      ..Settings::default()
+    })
+    .unwrap();
+    let context = String::from("ctx");
+    let from = router.shard_of(&context);
+    let to = 1 - from;
+
+    let response = router.process_request(&write_edge("ctx", "U1", "B1")).await;
+    assert!(matches!(response, Response::Ok));
+    let response = router.process_request(&write_edge("ctx", "U1", "U2")).await;
+    assert!(matches!(response, Response::Ok));
+
+    //  User edges reach the other shard too.
+    let user_edge = ("U1".to_string(), "U2".to_string());
+    let edges = read_edges(&router.shards[to], "").await.unwrap();
+    assert!(edges.contains(&user_edge));
+
+    let data = ReqData::MoveContext(OpMoveContext {
+      shard: shards[to].clone(),
+    });
+    let response = router.process_request(&request("ctx", data)).await;
+    assert!(matches!(response, Response::Ok));
+    assert_eq!(router.shard_of(&context), to);
+
+    let expected = vec![
+      ("U1".to_string(), "B1".to_string()),
+      ("U1".to_string(), "U2".to_string()),
+    ];
+    assert_eq!(read_edges(&router.shards[to], "ctx").await, Some(expected));
+    let names: Vec<SubgraphName> =
+      match router.process_request(&request("", ReqData::ReadContexts)).await {
+        Response::Contexts(res) => {
+          res.contexts.into_iter().map(|context| context.name).collect()
+        },
+        _ => vec![],
+      };
+    assert_eq!(names.iter().filter(|name| name.as_str() == "ctx").count(), 1);
+
+    running.cancel();
+  }
+
+  fn test_cert(name: &str) -> String {
+    format!("{}/tests/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+  }
+
+  /// Settings of a service that connects to others over TLS with a client
+  /// certificate.
+  fn tls_client_settings() -> Settings {
+    Settings {
+      tls_ca: test_cert("ca.pem"),
+      tls_client_cert: test_cert("client.pem"),
+      tls_client_key: test_cert("client.key"),
+      ..Settings::default()
+    }
+  }
+
+  #[tokio::test]
+  async fn router_and_replica_over_tls() {
+    let running = CancellationToken::new();
+    let primary_settings = Settings {
+      server_port: 8094,
+      replication_port: 8095,
+      tls_cert: test_cert("server.pem"),
+      tls_key: test_cert("server.key"),
+      tls_client_ca: test_cert("ca.pem"),
+      ..Settings::default()
+    };
+    let primary = Arc::new(MultiGraphProcessor::new(primary_settings.clone()));
+    for replication in [false, true] {
+      let settings = primary_settings.clone();
+      let processor = Arc::clone(&primary);
+      let running = running.clone();
+      tokio::spawn(async move {
+        if replication {
+          run_replication_server(settings, processor, running).await.unwrap();
+        } else {
+          run_server(settings, processor, running).await.unwrap();
+        }
+      });
+    }
+    wait_for_shard("127.0.0.1:8094").await;
+
+    let replica_settings = Settings {
+      replicate_from: "localhost:8095".into(),
+      ..tls_client_settings()
+    };
+    let replica = Arc::new(MultiGraphProcessor::new(replica_settings.clone()));
+    tokio::spawn(run_replica(
+      replica_settings,
+      Arc::clone(&replica),
+      running.clone(),
+    ));
+
+    let router = Router::new(&Settings {
+      shards: vec!["localhost:8094".into()],
+      ..tls_client_settings()
+    })
+    .unwrap();
+    let response = router.process_request(&write_edge("", "U1", "U2")).await;
+    assert!(matches!(response, Response::Ok));
+    let expected = vec![("U1".to_string(), "U2".to_string())];
+    assert_eq!(read_edges(&router.shards[0], "").await, Some(expected.clone()));
+
+    let read = request("", ReqData::ReadEdges);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+      if let Response::Edges(res) = replica.process_request(&read).await {
+        if res.edges.len() == expected.len() {
+          break;
+        }
+      }
+      assert!(Instant::now() < deadline, "replica did not get the edge");
+      tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    //  Shards that require TLS reject plain connections and clients without
+    //  a certificate.
+    let plain = Router::new(&Settings {
+      shards: vec!["localhost:8094".into()],
+      ..Settings::default()
+    })
+    .unwrap();
+    let response = plain.process_request(&write_edge("", "U2", "U3")).await;
+    assert!(matches!(response, Response::Fail));
+    let anonymous = Router::new(&Settings {
+      shards: vec!["localhost:8094".into()],
+      tls_ca: test_cert("ca.pem"),
+      ..Settings::default()
+    })
+    .unwrap();
+    let response = anonymous.process_request(&write_edge("", "U2", "U3")).await;
+    assert!(matches!(response, Response::Fail));
+
+    running.cancel();
+  }
+}
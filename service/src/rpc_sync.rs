@@ -1,14 +1,88 @@
 use crate::data::{Request, Response};
+use crate::request_handler::{COMPRESSED_FLAG, COMPRESSION_MAGIC};
+use crate::tls::server_name;
 
 use bincode::{config::standard, decode_from_slice, encode_to_vec};
+use tokio_rustls::rustls::{ClientConfig, ClientConnection, StreamOwned};
 
 use std::io::{self, Read, Write};
 use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
 use std::time::Duration;
 
+/// Connection to the service, over TCP, TLS or a Unix domain socket.
+pub enum SyncStream {
+  Tcp(TcpStream),
+  Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+  Unix(UnixStream),
+}
+
+impl SyncStream {
+  /// Fails for TLS connections, whose state can't be shared.
+  pub fn try_clone(&self) -> io::Result<SyncStream> {
+    match self {
+      SyncStream::Tcp(s) => s.try_clone().map(SyncStream::Tcp),
+      SyncStream::Tls(_) => Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "TLS connections can't be cloned",
+      )),
+      SyncStream::Unix(s) => s.try_clone().map(SyncStream::Unix),
+    }
+  }
+}
+
+/// Completes the TLS handshake with the service at `address` over `stream`,
+/// see `tls_client_config`.
+pub fn connect_tls_sync(
+  config: Arc<ClientConfig>,
+  mut stream: TcpStream,
+  address: &str,
+) -> io::Result<SyncStream> {
+  let mut conn = ClientConnection::new(config, server_name(address)?)
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+  while conn.is_handshaking() {
+    conn.complete_io(&mut stream)?;
+  }
+  Ok(SyncStream::Tls(Box::new(StreamOwned::new(conn, stream))))
+}
+
+impl Read for SyncStream {
+  fn read(
+    &mut self,
+    buf: &mut [u8],
+  ) -> io::Result<usize> {
+    match self {
+      SyncStream::Tcp(s) => s.read(buf),
+      SyncStream::Tls(s) => s.read(buf),
+      SyncStream::Unix(s) => s.read(buf),
+    }
+  }
+}
+
+impl Write for SyncStream {
+  fn write(
+    &mut self,
+    buf: &[u8],
+  ) -> io::Result<usize> {
+    match self {
+      SyncStream::Tcp(s) => s.write(buf),
+      SyncStream::Tls(s) => s.write(buf),
+      SyncStream::Unix(s) => s.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      SyncStream::Tcp(s) => s.flush(),
+      SyncStream::Tls(s) => s.flush(),
+      SyncStream::Unix(s) => s.flush(),
+    }
+  }
+}
 
-pub fn write_request_sync(
-  stream: &mut TcpStream,
+pub fn write_request_sync<S: Write>(
+  stream: &mut S,
   request: &Request,
 ) -> io::Result<()> {
   let payload = encode_to_vec(request, standard())
@@ -19,23 +93,36 @@ pub fn write_request_sync(
   Ok(())
 }
 
-pub fn read_response_sync(stream: &mut TcpStream) -> io::Result<Response> {
+/// Tells the service that compressed responses are accepted. Must be the
+/// first thing sent on the connection.
+pub fn request_compression_sync<S: Write>(stream: &mut S) -> io::Result<()> {
+  stream.write_all(&[COMPRESSION_MAGIC])
+}
+
+pub fn read_response_sync<S: Read>(stream: &mut S) -> io::Result<Response> {
   let mut len_buf = [0u8; 4];
   stream.read_exact(&mut len_buf)?;
-  let len = u32::from_be_bytes(len_buf) as usize;
-  let mut buf = vec![0u8; len];
+  let len = u32::from_be_bytes(len_buf);
+  let mut buf = vec![0u8; (len & !COMPRESSED_FLAG) as usize];
   stream.read_exact(&mut buf)?;
+  if len & COMPRESSED_FLAG != 0 {
+    buf = zstd::stream::decode_all(&buf[..])?;
+  }
   decode_from_slice(&buf, standard())
     .map(|(v, _)| v)
     .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
 }
 
 pub fn set_read_timeout(
-  stream: &mut TcpStream,
+  stream: &mut SyncStream,
   timeout_msec: Option<u64>,
 ) -> io::Result<()> {
   let t = timeout_msec.map(Duration::from_millis);
-  stream.set_read_timeout(t)
+  match stream {
+    SyncStream::Tcp(s) => s.set_read_timeout(t),
+    SyncStream::Tls(s) => s.sock.set_read_timeout(t),
+    SyncStream::Unix(s) => s.set_read_timeout(t),
+  }
 }
 
 #[cfg(test)]
@@ -59,11 +146,14 @@ mod tests {
   fn request_write_edge_roundtrip() {
     let req = Request {
       subgraph: "ctx".into(),
+      token:    String::new(),
       data:     ReqData::WriteEdge(OpWriteEdge {
-        src:       "U1".into(),
-        dst:       "U2".into(),
-        amount:    1.5,
-        magnitude: 3,
+        src:        "U1".into(),
+        dst:        "U2".into(),
+        amount:     1.5,
+        magnitude:  3,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
       }),
     };
     let framed = encode_framed(&req);
@@ -88,6 +178,15 @@ mod tests {
         reverse_score:   0.1,
         cluster:         2,
         reverse_cluster: 1,
+        kind:            NodeKind::User,
+        rank:            1,
+        percentile:      50.0,
+        stale:           false,
+        approximate:     false,
+        fallback:        false,
+        shared:          false,
+        walks:           0,
+        provenance:      None,
       }],
     });
     let framed = encode_framed(&resp);
@@ -106,4 +205,55 @@ mod tests {
     let decoded: Response = decode_framed(&framed);
     assert!(matches!(decoded, Response::Ok));
   }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn tls_roundtrip() {
+    use crate::request_handler::run_server;
+    use crate::settings::Settings;
+    use crate::state_manager::MultiGraphProcessor;
+    use crate::tls::tls_client_config;
+
+    use tokio_util::sync::CancellationToken;
+
+    let cert = |name: &str| {
+      format!("{}/tests/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    };
+    let running = CancellationToken::new();
+    let settings = Settings {
+      server_port: 8097,
+      tls_cert: cert("server.pem"),
+      tls_key: cert("server.key"),
+      ..Settings::default()
+    };
+    let processor = Arc::new(MultiGraphProcessor::new(settings.clone()));
+    let running_cloned = running.clone();
+    tokio::spawn(async move {
+      run_server(settings, processor, running_cloned).await.unwrap();
+    });
+
+    let config = tls_client_config(&cert("ca.pem"), "", "").unwrap().unwrap();
+    let response = tokio::task::spawn_blocking(move || {
+      let stream = (0..100)
+        .find_map(|_| {
+          std::thread::sleep(Duration::from_millis(20));
+          TcpStream::connect("127.0.0.1:8097").ok()
+        })
+        .unwrap();
+      stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+      let mut stream =
+        connect_tls_sync(config, stream, "127.0.0.1:8097").unwrap();
+      let request = Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadEdges,
+      };
+      write_request_sync(&mut stream, &request).unwrap();
+      read_response_sync(&mut stream).unwrap()
+    })
+    .await
+    .unwrap();
+    assert!(matches!(response, Response::Edges(_)));
+
+    running.cancel();
+  }
 }
@@ -1,8 +1,32 @@
+use crate::auth::AccessControl;
+use crate::data::{edge_kind_from_name, EdgeKind, NodeKind, NodeName};
+use crate::node_registry::{node_kind_from_prefix, register_node_kind};
 use crate::utils::log::*;
 
 use std::env::*;
 use std::fmt::*;
 use std::str::FromStr;
+use std::time::Duration;
+
+/// Limit on how much the weight of an edge of the context may change within
+/// `period` seconds. The context `*` stands for contexts without a limit of
+/// their own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeRateLimit {
+  pub context:   String,
+  pub max_delta: f64,
+  pub period:    u64,
+}
+
+/// Walks that ego calculations may run for reads of the context within
+/// `period` seconds. The context `*` stands for contexts without a budget of
+/// their own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalkBudget {
+  pub context:   String,
+  pub max_walks: u64,
+  pub period:    u64,
+}
 
 #[derive(Clone)]
 pub struct Settings {
@@ -10,26 +34,195 @@ pub struct Settings {
   pub legacy_server_num_threads: usize,
   pub server_address: String,
   pub server_port: u16,
+  /// Path of a Unix domain socket to listen on in addition to TCP
+  /// (empty = disabled).
+  pub unix_socket_path: String,
+  /// PEM certificate chain; when set, TCP connections use TLS.
+  pub tls_cert: String,
+  /// PEM private key of the certificate.
+  pub tls_key: String,
+  /// PEM certificates of the CAs client certificates must be signed by
+  /// (empty = no client authentication).
+  pub tls_client_ca: String,
+  /// PEM certificates of the CAs the certificates of shards and the primary
+  /// must be signed by; when set, connections to them use TLS.
+  pub tls_ca: String,
+  /// PEM certificate chain presented to shards and the primary (empty = none).
+  pub tls_client_cert: String,
+  /// PEM private key of the client certificate.
+  pub tls_client_key: String,
+  /// Port replicas connect to for the stream of write ops (0 = disabled).
+  pub replication_port: u16,
+  /// Number of recent ops kept for replicas that reconnect; replicas that
+  /// are further behind get a snapshot.
+  pub replication_log_size: usize,
+  /// Replication address of the primary, as `host:port`. When set, the
+  /// service is a read-only replica until promoted.
+  pub replicate_from: String,
+  /// Addresses of the services contexts are sharded across, as `host:port`.
+  /// When set, the service is a router that forwards requests to them.
+  pub shards: Vec<String>,
+  /// File the router keeps the shards of moved contexts in (empty = not
+  /// kept across restarts).
+  pub router_state_file: String,
+  /// Postgres URL edges are imported from, see `ImportFromSql`.
+  pub import_database_url: String,
+  /// Query returning the imported edges as (src, dst, weight) rows, with an
+  /// optional fourth column of the context.
+  pub import_query: String,
+  /// Import the edges before serving requests.
+  pub import_on_startup: bool,
+  /// Directory `ImportEdgeList` reads from (empty = the op is disabled).
+  pub import_dir: String,
+  /// Directory `ExportScores` writes to (empty = exports disabled).
+  pub export_dir: String,
+  /// Seconds the keys of `Idempotent` writes are kept (0 = not deduplicated).
+  pub idempotency_window: u64,
   pub num_walks: usize,
   pub zero_opinion_factor: f64,
   pub score_clusters_cache_size: usize,
   pub score_clusters_timeout: u64,
+  /// Recalculate stale cluster bounds in the background instead of on read.
+  pub background_clustering: bool,
   pub scores_cache_size: usize,
   pub scores_cache_timeout: u64,
+  /// Serve cached scores computed before the last change of the ego's walks,
+  /// flagged as stale, instead of recomputing them.
+  pub serve_stale_scores: bool,
   /// Max number of egos to keep walk data for per subgraph (0 = unlimited).
   pub walks_cache_size: usize,
-  // pub filter_num_hashes: usize,
-  // pub filter_max_size: usize,
-  // pub filter_min_size: usize,
+  /// Memory of the scores and walks caches of each context in bytes. When
+  /// set, cache sizes follow the node count of the context instead of
+  /// `scores_cache_size` and `walks_cache_size`, see `cache_sizing`.
+  pub cache_memory_budget: usize,
+  /// Keep the last scores of egos evicted from the walks cache and serve them
+  /// while the ego is recalculated.
+  pub keep_evicted_scores: bool,
+  /// When the core fails to score an ego, serve its last cached scores,
+  /// flagged as stale, instead of failing the read.
+  pub degraded_mode: bool,
+  /// Number of hottest egos per context to keep warm in the background (0 = disabled).
+  pub warm_egos: usize,
+  /// Interval in seconds between warming passes.
+  pub warm_interval: u64,
+  /// Number of most similar egos recommendations are taken from.
+  pub recommendation_egos: usize,
+  /// Max number of nodes per page of top nodes (0 = unlimited).
+  pub top_nodes_limit: usize,
+  /// Serve `DebugEgo`, which exposes the raw walks of an ego.
+  pub debug_ops: bool,
+  /// Seed the walks of every context alike and refresh cluster bounds only
+  /// when the walks change, so that the same ops give the same scores.
+  pub deterministic: bool,
+  /// Score samples kept per (ego, target) pair (0 = history disabled).
+  pub history_size: usize,
+  /// Minimal interval in seconds between score samples of a pair, also the
+  /// interval of scheduled sampling.
+  pub history_interval: u64,
+  /// Max age in seconds of score samples (0 = unlimited).
+  pub history_retention: u64,
+  /// Snapshots of all scores of an ego kept per context, one per ego and
+  /// generation, for `ReadScoreDiff` (0 = disabled).
+  pub score_snapshots: usize,
+  /// Number of recent edge changes kept for reads of past scores
+  /// (0 = disabled).
+  pub edge_log_size: usize,
+  /// Half-life in seconds of edge weights (0 = no decay).
+  pub decay_half_life: u64,
+  /// Interval in seconds between edge decay passes.
+  pub decay_interval: u64,
+  /// Share of a context's edges changed since its walks were calculated from
+  /// scratch, beyond which they are recalculated (0 = never).
+  pub rerank_threshold: f64,
+  /// Interval in seconds between checks of `rerank_threshold`.
+  pub rerank_interval: u64,
+  /// Interval in seconds between passes that archive expired polls
+  /// (0 = never).
+  pub poll_archive_interval: u64,
+  /// Number of voters per option the filters of anonymous polls are sized
+  /// for.
+  pub poll_filter_capacity: usize,
+  /// Target false positive rate of the filters of anonymous polls, i.e. of
+  /// votes rejected as repeated.
+  pub poll_filter_fp_rate: f64,
+  /// Interval in seconds between webhook digests (0 = webhooks disabled).
+  pub webhook_interval: u64,
+  /// Max number of nodes per context (0 = unlimited).
+  pub max_context_nodes: usize,
+  /// Max number of edges per context (0 = unlimited).
+  pub max_context_edges: usize,
+  /// Max estimated memory of all contexts in bytes (0 = unlimited).
+  pub max_memory: usize,
+  /// Limits on how fast edge weights may change, see `edge_rate_limit`.
+  pub edge_rate_limits: Vec<EdgeRateLimit>,
+  /// Egos whose scores are read for egos without edges, by context, see
+  /// `house_ego`.
+  pub house_egos: Vec<(String, NodeName)>,
+  /// Budgets of walks run for reads, see `walk_budget`.
+  pub walk_budgets: Vec<WalkBudget>,
+  /// Least similarity of an ego without walks to a calculated ego whose
+  /// scores it is served, see `similar_calculated_ego` (0 = disabled).
+  pub shared_scores_similarity: f64,
+  /// Initial number of personal nodes per-ego filters are sized for, see
+  /// `hide_personal` (0 = disabled). Filters grow when they get more.
+  pub filter_capacity: usize,
+  /// Target false positive rate of per-ego filters of personal nodes.
+  pub filter_fp_rate: f64,
   pub omit_neg_edges_scores: bool,
   pub force_read_graph_conn: bool,
   pub num_score_quantiles: usize,
+  /// Node kinds registered in addition to the built-in ones, as `prefix:name`.
+  pub custom_node_kinds: Vec<String>,
+  /// Factors scores of nodes of the kind are multiplied by (1.0 if not listed).
+  pub score_weights: Vec<(NodeKind, f64)>,
+  /// Factors amounts of written edges of the kind are multiplied by (1.0 if
+  /// not listed).
+  pub edge_kind_weights: Vec<(EdgeKind, f64)>,
+  /// Node kinds that can be used as egos for scores and clustering.
+  pub ego_kinds: Vec<NodeKind>,
   // pub cache_capacity: u64,
   // pub cache_ttl: u64,
   pub min_ops_before_swap: usize,
+  /// Ops after which the writer publishes even if more are queued
+  /// (0 = unlimited).
+  pub max_ops_per_publish: usize,
+  /// Milliseconds after the first op of a batch the writer publishes at the
+  /// latest, even before `min_ops_before_swap` ops (0 = no limit).
+  pub max_publish_latency: u64,
   pub subgraph_queue_capacity: usize,
   /// When true, collect ops queue and processing-time stats (for GetStats / ResetStats). Off by default.
   pub collect_stats: bool,
+  /// Token ACL for client requests. Empty means authentication is disabled.
+  pub acl: AccessControl,
+  /// Number of shared reader threads (0 = run reads on the async runtime).
+  pub read_workers: usize,
+  /// Contexts that get a dedicated reader thread each.
+  pub pinned_read_contexts: Vec<String>,
+  /// Directory for evicted contexts (empty = eviction disabled).
+  pub cold_storage_dir: String,
+  /// Max number of contexts kept in memory when cold storage is enabled (0 = unlimited).
+  pub max_resident_contexts: usize,
+  /// Directory the contexts and their walks are saved to on shutdown and
+  /// loaded from on startup (empty = disabled).
+  pub state_dir: String,
+  /// zstd level of responses to clients that accept compression.
+  pub compression_level: i32,
+  /// Responses smaller than that many bytes are sent uncompressed.
+  pub compression_threshold: usize,
+  /// Time budget in milliseconds of reads that calculate egos (0 = none).
+  pub request_timeout: u64,
+  /// Budgets overriding `request_timeout` for the given request types.
+  pub request_timeouts: Vec<(String, u64)>,
+  /// Requests taking longer than that many milliseconds are kept in the
+  /// slow log (0 = none), see `ReadSlowLog`.
+  pub slow_op_threshold: u64,
+  /// Thresholds overriding `slow_op_threshold` for the given request types.
+  pub slow_op_thresholds: Vec<(String, u64)>,
+  /// Slow requests kept; the oldest are dropped first.
+  pub slow_log_size: usize,
+  /// OTLP/HTTP endpoint traces are exported to (empty = no export).
+  pub otlp_endpoint: String,
+  pub log_format: LogFormat,
 }
 
 impl Default for Settings {
@@ -39,23 +232,169 @@ impl Default for Settings {
       legacy_server_num_threads: 4,
       server_address: "127.0.0.1".into(),
       server_port: 8080,
+      unix_socket_path: String::new(),
+      tls_cert: String::new(),
+      tls_key: String::new(),
+      tls_client_ca: String::new(),
+      tls_ca: String::new(),
+      tls_client_cert: String::new(),
+      tls_client_key: String::new(),
+      replication_port: 0,
+      replication_log_size: 100_000,
+      replicate_from: String::new(),
+      shards: vec![],
+      router_state_file: String::new(),
+      import_database_url: String::new(),
+      import_query: String::new(),
+      import_on_startup: false,
+      import_dir: String::new(),
+      export_dir: String::new(),
+      idempotency_window: 300,
       num_walks: 10000,
       zero_opinion_factor: 0.2,
       score_clusters_cache_size: 1024 * 10,
       score_clusters_timeout: 60 * 60 * 6,
+      background_clustering: false,
       scores_cache_size: 1024 * 10,
       scores_cache_timeout: 60 * 60,
+      serve_stale_scores: false,
       walks_cache_size: 0,
+      cache_memory_budget: 0,
+      keep_evicted_scores: false,
+      degraded_mode: false,
+      warm_egos: 0,
+      warm_interval: 10,
+      recommendation_egos: 10,
+      top_nodes_limit: 100,
+      debug_ops: false,
+      deterministic: false,
+      history_size: 0,
+      history_interval: 60,
+      history_retention: 0,
+      score_snapshots: 0,
+      edge_log_size: 0,
+      decay_half_life: 0,
+      decay_interval: 3600,
+      rerank_threshold: 0.0,
+      rerank_interval: 60,
+      poll_archive_interval: 60,
+      poll_filter_capacity: 10000,
+      poll_filter_fp_rate: 0.0001,
+      webhook_interval: 10,
+      max_context_nodes: 0,
+      max_context_edges: 0,
+      max_memory: 0,
+      edge_rate_limits: vec![],
+      house_egos: vec![],
+      walk_budgets: vec![],
+      shared_scores_similarity: 0.0,
+      filter_capacity: 100,
+      filter_fp_rate: 0.001,
       omit_neg_edges_scores: false,
       force_read_graph_conn: false,
       num_score_quantiles: 100,
+      custom_node_kinds: vec![],
+      score_weights: vec![],
+      edge_kind_weights: vec![],
+      ego_kinds: vec![NodeKind::User],
       min_ops_before_swap: 1,
+      max_ops_per_publish: 0,
+      max_publish_latency: 0,
       subgraph_queue_capacity: 1024,
       collect_stats: false,
+      acl: AccessControl::default(),
+      read_workers: 4,
+      pinned_read_contexts: vec![],
+      cold_storage_dir: String::new(),
+      max_resident_contexts: 0,
+      state_dir: String::new(),
+      compression_level: 3,
+      compression_threshold: 64 * 1024,
+      request_timeout: 0,
+      request_timeouts: vec![],
+      slow_op_threshold: 0,
+      slow_op_thresholds: vec![],
+      slow_log_size: 100,
+      otlp_endpoint: String::new(),
+      log_format: LogFormat::Human,
     }
   }
 }
 
+impl Settings {
+  /// Time budget of requests of the type, see `ReqData::opcode`.
+  pub fn timeout_of(
+    &self,
+    opcode: &str,
+  ) -> Option<Duration> {
+    let msec = self
+      .request_timeouts
+      .iter()
+      .find(|(name, _)| name == opcode)
+      .map_or(self.request_timeout, |(_, msec)| *msec);
+    (msec > 0).then_some(Duration::from_millis(msec))
+  }
+
+  /// Latency over which requests of the type are kept in the slow log.
+  pub fn slow_threshold_of(
+    &self,
+    opcode: &str,
+  ) -> Option<Duration> {
+    let msec = self
+      .slow_op_thresholds
+      .iter()
+      .find(|(name, _)| name == opcode)
+      .map_or(self.slow_op_threshold, |(_, msec)| *msec);
+    (msec > 0).then_some(Duration::from_millis(msec))
+  }
+
+  /// Limit on edge weight changes in the context, if any.
+  pub fn edge_rate_limit(
+    &self,
+    context: &str,
+  ) -> Option<&EdgeRateLimit> {
+    let find = |name: &str| {
+      self.edge_rate_limits.iter().find(|limit| limit.context == name)
+    };
+    find(context).or_else(|| find("*"))
+  }
+
+  /// Ego whose scores are served in the context to egos that have no edges
+  /// there, if any.
+  pub fn house_ego(
+    &self,
+    context: &str,
+  ) -> Option<&NodeName> {
+    let find = |name: &str| {
+      self.house_egos.iter().find(|(x, _)| x == name).map(|(_, ego)| ego)
+    };
+    find(context).or_else(|| find("*"))
+  }
+
+  /// Budget of walks run for reads of the context, if any.
+  pub fn walk_budget(
+    &self,
+    context: &str,
+  ) -> Option<&WalkBudget> {
+    let find = |name: &str| {
+      self.walk_budgets.iter().find(|budget| budget.context == name)
+    };
+    find(context).or_else(|| find("*"))
+  }
+
+  /// Factor of `edge_kind_weights` for the edge kind.
+  pub fn edge_kind_weight(
+    &self,
+    kind: EdgeKind,
+  ) -> f64 {
+    self
+      .edge_kind_weights
+      .iter()
+      .find(|(x, _)| *x == kind)
+      .map_or(1.0, |(_, factor)| *factor)
+  }
+}
+
 enum AllErrors {
   Var,
   Parse(String),
@@ -97,7 +436,7 @@ fn load_zero_opinion_factor(val: &mut f64) {
   const MAX: f64 = 1.0;
   if let Ok(s) = var(NAME) {
     match s.parse::<f64>() {
-      Ok(x) if x >= MIN && x <= MAX => *val = x,
+      Ok(x) if (MIN..=MAX).contains(&x) => *val = x,
       Ok(x) => {
         log_error!(
           "{} must be in [{}, {}], got {}; using default {}",
@@ -115,6 +454,208 @@ fn load_zero_opinion_factor(val: &mut f64) {
   }
 }
 
+/// Load a comma-separated list. An empty item stands for the default context.
+fn load_list(
+  name: &str,
+  val: &mut Vec<String>,
+) {
+  if let Ok(s) = var(name) {
+    if !s.is_empty() {
+      *val = s.split(',').map(|x| x.trim().to_string()).collect();
+    }
+  }
+}
+
+/// Comma-separated node name prefixes, e.g. `U,B`.
+fn load_node_kinds(
+  name: &str,
+  val: &mut Vec<NodeKind>,
+) {
+  let mut prefixes = vec![];
+  load_list(name, &mut prefixes);
+  if prefixes.is_empty() {
+    return;
+  }
+  let kinds: Option<Vec<NodeKind>> =
+    prefixes.iter().map(|x| node_kind_from_prefix(x)).collect();
+  match kinds {
+    Some(kinds) => *val = kinds,
+    None => log_error!("{}", AllErrors::Parse(name.into())),
+  }
+}
+
+/// Comma-separated `prefix:name` pairs, e.g. `A:Article,M:Market`. Registers
+/// the kinds, so that settings loaded after it can refer to them.
+fn load_custom_node_kinds(
+  name: &str,
+  val: &mut Vec<String>,
+) {
+  let mut kinds = vec![];
+  load_list(name, &mut kinds);
+  for kind in kinds {
+    let mut chars = kind.chars();
+    let registered = match (chars.next(), chars.next()) {
+      (Some(prefix), Some(':')) if !chars.as_str().is_empty() => {
+        register_node_kind(prefix, chars.as_str()).is_some()
+      },
+      _ => false,
+    };
+    if registered {
+      val.push(kind);
+    } else {
+      log_error!("{}: {}", AllErrors::Parse(name.into()), kind);
+    }
+  }
+}
+
+/// Comma-separated `prefix:factor` pairs, e.g. `C:0.5,B:1.5`.
+fn load_score_weights(
+  name: &str,
+  val: &mut Vec<(NodeKind, f64)>,
+) {
+  let mut items = vec![];
+  load_list(name, &mut items);
+  let weights: Option<Vec<(NodeKind, f64)>> = items
+    .iter()
+    .map(|item| {
+      let (prefix, factor) = item.split_once(':')?;
+      let factor: f64 = factor.trim().parse().ok()?;
+      if !factor.is_finite() || factor < 0.0 {
+        return None;
+      }
+      Some((node_kind_from_prefix(prefix)?, factor))
+    })
+    .collect();
+  match weights {
+    Some(weights) => *val = weights,
+    None => log_error!("{}", AllErrors::Parse(name.into())),
+  }
+}
+
+/// Comma-separated `kind:factor` pairs, e.g. `follow:0.5,flag:-1`.
+fn load_edge_kind_weights(
+  name: &str,
+  val: &mut Vec<(EdgeKind, f64)>,
+) {
+  let mut items = vec![];
+  load_list(name, &mut items);
+  let weights: Option<Vec<(EdgeKind, f64)>> = items
+    .iter()
+    .map(|item| {
+      let (kind, factor) = item.split_once(':')?;
+      let factor: f64 = factor.trim().parse().ok()?;
+      if !factor.is_finite() {
+        return None;
+      }
+      Some((edge_kind_from_name(kind.trim())?, factor))
+    })
+    .collect();
+  match weights {
+    Some(weights) => *val = weights,
+    None => log_error!("{}", AllErrors::Parse(name.into())),
+  }
+}
+
+/// Comma-separated request types and budgets in milliseconds, e.g.
+/// `ReadGraph:5000,ReadScores:500`.
+fn load_request_timeouts(
+  name: &str,
+  val: &mut Vec<(String, u64)>,
+) {
+  let mut items = vec![];
+  load_list(name, &mut items);
+  let timeouts: Option<Vec<(String, u64)>> = items
+    .iter()
+    .map(|item| {
+      let (opcode, msec) = item.split_once(':')?;
+      Some((opcode.trim().to_string(), msec.trim().parse().ok()?))
+    })
+    .collect();
+  match timeouts {
+    Some(timeouts) => *val = timeouts,
+    None => log_error!("{}", AllErrors::Parse(name.into())),
+  }
+}
+
+/// Comma-separated `context:max_delta:period` entries, e.g.
+/// `*:1:3600,news:0.5:600`. Context names may contain colons.
+fn load_edge_rate_limits(
+  name: &str,
+  val: &mut Vec<EdgeRateLimit>,
+) {
+  let mut items = vec![];
+  load_list(name, &mut items);
+  let limits: Option<Vec<EdgeRateLimit>> = items
+    .iter()
+    .map(|item| {
+      let mut parts = item.rsplitn(3, ':');
+      let period = parts.next()?.trim().parse().ok()?;
+      let max_delta: f64 = parts.next()?.trim().parse().ok()?;
+      if !max_delta.is_finite() || max_delta < 0.0 {
+        return None;
+      }
+      Some(EdgeRateLimit {
+        context: parts.next()?.to_string(),
+        max_delta,
+        period,
+      })
+    })
+    .collect();
+  match limits {
+    Some(limits) => *val = limits,
+    None => log_error!("{}", AllErrors::Parse(name.into())),
+  }
+}
+
+/// Comma-separated `context:ego` entries, e.g. `*:U0,news:U1`. Context names
+/// may contain colons.
+fn load_house_egos(
+  name: &str,
+  val: &mut Vec<(String, NodeName)>,
+) {
+  let mut items = vec![];
+  load_list(name, &mut items);
+  let egos: Option<Vec<(String, NodeName)>> = items
+    .iter()
+    .map(|item| {
+      let (context, ego) = item.rsplit_once(':')?;
+      let ego = ego.trim();
+      (!ego.is_empty()).then(|| (context.to_string(), ego.to_string()))
+    })
+    .collect();
+  match egos {
+    Some(egos) => *val = egos,
+    None => log_error!("{}", AllErrors::Parse(name.into())),
+  }
+}
+
+/// Comma-separated `context:max_walks:period` entries, e.g.
+/// `*:100000:60,news:20000:60`. Context names may contain colons.
+fn load_walk_budgets(
+  name: &str,
+  val: &mut Vec<WalkBudget>,
+) {
+  let mut items = vec![];
+  load_list(name, &mut items);
+  let budgets: Option<Vec<WalkBudget>> = items
+    .iter()
+    .map(|item| {
+      let mut parts = item.rsplitn(3, ':');
+      let period = parts.next()?.trim().parse().ok()?;
+      let max_walks = parts.next()?.trim().parse().ok()?;
+      Some(WalkBudget {
+        context: parts.next()?.to_string(),
+        max_walks,
+        period,
+      })
+    })
+    .collect();
+  match budgets {
+    Some(budgets) => *val = budgets,
+    None => log_error!("{}", AllErrors::Parse(name.into())),
+  }
+}
+
 pub fn load_from_env() -> Settings {
   let mut s = Settings::default();
 
@@ -125,6 +666,24 @@ pub fn load_from_env() -> Settings {
   );
   load_var("MERITRANK_SERVER_ADDRESS", &mut s.server_address);
   load_var("MERITRANK_SERVER_PORT", &mut s.server_port);
+  load_var("MERITRANK_UNIX_SOCKET", &mut s.unix_socket_path);
+  load_var("MERITRANK_TLS_CERT", &mut s.tls_cert);
+  load_var("MERITRANK_TLS_KEY", &mut s.tls_key);
+  load_var("MERITRANK_TLS_CLIENT_CA", &mut s.tls_client_ca);
+  load_var("MERITRANK_TLS_CA", &mut s.tls_ca);
+  load_var("MERITRANK_TLS_CLIENT_CERT", &mut s.tls_client_cert);
+  load_var("MERITRANK_TLS_CLIENT_KEY", &mut s.tls_client_key);
+  load_var("MERITRANK_REPLICATION_PORT", &mut s.replication_port);
+  load_var("MERITRANK_REPLICATION_LOG_SIZE", &mut s.replication_log_size);
+  load_var("MERITRANK_REPLICATE_FROM", &mut s.replicate_from);
+  load_list("MERITRANK_SHARDS", &mut s.shards);
+  load_var("MERITRANK_ROUTER_STATE", &mut s.router_state_file);
+  load_var("MERITRANK_IMPORT_DATABASE_URL", &mut s.import_database_url);
+  load_var("MERITRANK_IMPORT_QUERY", &mut s.import_query);
+  load_var("MERITRANK_IMPORT_ON_STARTUP", &mut s.import_on_startup);
+  load_var("MERITRANK_IMPORT_DIR", &mut s.import_dir);
+  load_var("MERITRANK_EXPORT_DIR", &mut s.export_dir);
+  load_var("MERITRANK_IDEMPOTENCY_WINDOW", &mut s.idempotency_window);
   load_var("MERITRANK_NUM_WALKS", &mut s.num_walks);
   load_zero_opinion_factor(&mut s.zero_opinion_factor);
   load_var(
@@ -135,12 +694,57 @@ pub fn load_from_env() -> Settings {
     "MERITRANK_SCORE_CLUSTERS_TIMEOUT",
     &mut s.score_clusters_timeout,
   );
+  load_var(
+    "MERITRANK_BACKGROUND_CLUSTERING",
+    &mut s.background_clustering,
+  );
   load_var("MERITRANK_SCORES_CACHE_SIZE", &mut s.scores_cache_size);
   load_var(
     "MERITRANK_SCORES_CACHE_TIMEOUT",
     &mut s.scores_cache_timeout,
   );
+  load_var("MERITRANK_SERVE_STALE_SCORES", &mut s.serve_stale_scores);
   load_var("MERITRANK_WALKS_CACHE_SIZE", &mut s.walks_cache_size);
+  load_var("MERITRANK_CACHE_MEMORY_BUDGET", &mut s.cache_memory_budget);
+  load_var(
+    "MERITRANK_KEEP_EVICTED_SCORES",
+    &mut s.keep_evicted_scores,
+  );
+  load_var("MERITRANK_DEGRADED_MODE", &mut s.degraded_mode);
+  load_var("MERITRANK_WARM_EGOS", &mut s.warm_egos);
+  load_var("MERITRANK_WARM_INTERVAL", &mut s.warm_interval);
+  load_var(
+    "MERITRANK_RECOMMENDATION_EGOS",
+    &mut s.recommendation_egos,
+  );
+  load_var("MERITRANK_TOP_NODES_LIMIT", &mut s.top_nodes_limit);
+  load_var("MERITRANK_DEBUG_OPS", &mut s.debug_ops);
+  load_var("MERITRANK_DETERMINISTIC", &mut s.deterministic);
+  load_var("MERITRANK_HISTORY_SIZE", &mut s.history_size);
+  load_var("MERITRANK_HISTORY_INTERVAL", &mut s.history_interval);
+  load_var("MERITRANK_HISTORY_RETENTION", &mut s.history_retention);
+  load_var("MERITRANK_SCORE_SNAPSHOTS", &mut s.score_snapshots);
+  load_var("MERITRANK_EDGE_LOG_SIZE", &mut s.edge_log_size);
+  load_var("MERITRANK_DECAY_HALF_LIFE", &mut s.decay_half_life);
+  load_var("MERITRANK_DECAY_INTERVAL", &mut s.decay_interval);
+  load_var("MERITRANK_RERANK_THRESHOLD", &mut s.rerank_threshold);
+  load_var("MERITRANK_RERANK_INTERVAL", &mut s.rerank_interval);
+  load_var("MERITRANK_POLL_ARCHIVE_INTERVAL", &mut s.poll_archive_interval);
+  load_var("MERITRANK_POLL_FILTER_CAPACITY", &mut s.poll_filter_capacity);
+  load_var("MERITRANK_POLL_FILTER_FP_RATE", &mut s.poll_filter_fp_rate);
+  load_var("MERITRANK_WEBHOOK_INTERVAL", &mut s.webhook_interval);
+  load_var("MERITRANK_MAX_CONTEXT_NODES", &mut s.max_context_nodes);
+  load_var("MERITRANK_MAX_CONTEXT_EDGES", &mut s.max_context_edges);
+  load_var("MERITRANK_MAX_MEMORY", &mut s.max_memory);
+  load_edge_rate_limits("MERITRANK_EDGE_RATE_LIMITS", &mut s.edge_rate_limits);
+  load_house_egos("MERITRANK_HOUSE_EGOS", &mut s.house_egos);
+  load_walk_budgets("MERITRANK_WALK_BUDGETS", &mut s.walk_budgets);
+  load_var(
+    "MERITRANK_SHARED_SCORES_SIMILARITY",
+    &mut s.shared_scores_similarity,
+  );
+  load_var("MERITRANK_FILTER_CAPACITY", &mut s.filter_capacity);
+  load_var("MERITRANK_FILTER_FP_RATE", &mut s.filter_fp_rate);
   load_var(
     "MERITRANK_OMIT_NEG_EDGES_SCORES",
     &mut s.omit_neg_edges_scores,
@@ -150,15 +754,57 @@ pub fn load_from_env() -> Settings {
     &mut s.force_read_graph_conn,
   );
   load_var("MERITRANK_NUM_SCORE_QUANTILES", &mut s.num_score_quantiles);
+  load_custom_node_kinds(
+    "MERITRANK_CUSTOM_NODE_KINDS",
+    &mut s.custom_node_kinds,
+  );
+  load_node_kinds("MERITRANK_EGO_KINDS", &mut s.ego_kinds);
+  load_score_weights("MERITRANK_SCORE_WEIGHTS", &mut s.score_weights);
+  load_edge_kind_weights(
+    "MERITRANK_EDGE_KIND_WEIGHTS",
+    &mut s.edge_kind_weights,
+  );
   load_var(
     "MERITRANK_MIN_OPS_BEFORE_SWAP",
     &mut s.min_ops_before_swap,
   );
+  load_var("MERITRANK_MAX_OPS_PER_PUBLISH", &mut s.max_ops_per_publish);
+  load_var("MERITRANK_MAX_PUBLISH_LATENCY", &mut s.max_publish_latency);
   load_var(
     "MERITRANK_SUBGRAPH_QUEUE_CAPACITY",
     &mut s.subgraph_queue_capacity,
   );
   load_var("MERITRANK_COLLECT_STATS", &mut s.collect_stats);
+  load_var("MERITRANK_ACL", &mut s.acl);
+  load_var("MERITRANK_READ_WORKERS", &mut s.read_workers);
+  load_list(
+    "MERITRANK_PINNED_READ_CONTEXTS",
+    &mut s.pinned_read_contexts,
+  );
+  load_var("MERITRANK_COLD_STORAGE_DIR", &mut s.cold_storage_dir);
+  load_var(
+    "MERITRANK_MAX_RESIDENT_CONTEXTS",
+    &mut s.max_resident_contexts,
+  );
+  load_var("MERITRANK_STATE_DIR", &mut s.state_dir);
+  load_var("MERITRANK_COMPRESSION_LEVEL", &mut s.compression_level);
+  load_var(
+    "MERITRANK_COMPRESSION_THRESHOLD",
+    &mut s.compression_threshold,
+  );
+  load_var("MERITRANK_REQUEST_TIMEOUT", &mut s.request_timeout);
+  load_request_timeouts(
+    "MERITRANK_REQUEST_TIMEOUTS",
+    &mut s.request_timeouts,
+  );
+  load_var("MERITRANK_SLOW_OP_THRESHOLD", &mut s.slow_op_threshold);
+  load_request_timeouts(
+    "MERITRANK_SLOW_OP_THRESHOLDS",
+    &mut s.slow_op_thresholds,
+  );
+  load_var("MERITRANK_SLOW_LOG_SIZE", &mut s.slow_log_size);
+  load_var("MERITRANK_OTLP_ENDPOINT", &mut s.otlp_endpoint);
+  load_var("MERITRANK_LOG_FORMAT", &mut s.log_format);
 
   s
 }
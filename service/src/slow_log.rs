@@ -0,0 +1,166 @@
+//! Log of slow requests, see `ReadSlowLog`.
+//!
+//! Requests that take longer than the threshold of their type are kept with
+//! their data, the time spent calculating egos and the walks run for them,
+//! so that slow requests can be looked into without a profiler. Counters of
+//! the request being processed live in a task-local `RequestTrace`.
+
+use crate::data::*;
+use crate::history::unix_time_secs;
+
+use parking_lot::Mutex;
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+tokio::task_local! {
+  static TRACE: Arc<RequestTrace>;
+}
+
+/// Counters of a request, filled in while it is processed.
+#[derive(Default)]
+pub struct RequestTrace {
+  calculate_us:    AtomicU64,
+  egos_cached:     AtomicU64,
+  egos_calculated: AtomicU64,
+  walks:           AtomicU64,
+}
+
+fn with_trace(f: impl FnOnce(&RequestTrace)) {
+  let _ = TRACE.try_with(|trace| f(trace));
+}
+
+/// The ego's walks were in memory.
+pub fn record_cached() {
+  with_trace(|trace| {
+    trace.egos_cached.fetch_add(1, Ordering::Relaxed);
+  });
+}
+
+/// The ego was calculated for the request.
+pub fn record_calculated(walks: usize) {
+  with_trace(|trace| {
+    trace.egos_calculated.fetch_add(1, Ordering::Relaxed);
+    trace.walks.fetch_add(walks as u64, Ordering::Relaxed);
+  });
+}
+
+pub fn record_calculate_time(elapsed: Duration) {
+  with_trace(|trace| {
+    trace
+      .calculate_us
+      .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+  });
+}
+
+/// Runs `f` with `trace` collecting its counters.
+pub async fn traced<F: Future>(
+  trace: Arc<RequestTrace>,
+  f: F,
+) -> F::Output {
+  TRACE.scope(trace, f).await
+}
+
+pub struct SlowLog {
+  capacity: usize,
+  ops:      Mutex<VecDeque<SlowOp>>,
+}
+
+impl SlowLog {
+  pub fn new(capacity: usize) -> Self {
+    SlowLog {
+      capacity,
+      ops: Mutex::new(VecDeque::new()),
+    }
+  }
+
+  pub fn record(
+    &self,
+    req: &Request,
+    response: &Response,
+    elapsed: Duration,
+    trace: &RequestTrace,
+  ) {
+    if self.capacity == 0 {
+      return;
+    }
+    let outcome = match response {
+      Response::Fail => "fail".to_string(),
+      Response::Error(e) => format!("{:?}", e),
+      _ => "ok".to_string(),
+    };
+    let op = SlowOp {
+      time: unix_time_secs(),
+      context: req.subgraph.clone(),
+      opcode: req.data.opcode().to_string(),
+      request: format!("{:?}", req.data)
+        .chars()
+        .take(MAX_SLOW_OP_REQUEST_LEN)
+        .collect(),
+      outcome,
+      total_us: elapsed.as_micros() as u64,
+      calculate_us: trace.calculate_us.load(Ordering::Relaxed),
+      egos_cached: trace.egos_cached.load(Ordering::Relaxed),
+      egos_calculated: trace.egos_calculated.load(Ordering::Relaxed),
+      walks: trace.walks.load(Ordering::Relaxed),
+    };
+    let mut ops = self.ops.lock();
+    if ops.len() == self.capacity {
+      ops.pop_front();
+    }
+    ops.push_back(op);
+  }
+
+  /// The latest `limit` slow requests, newest first; all for 0.
+  pub fn read(
+    &self,
+    limit: u32,
+  ) -> Vec<SlowOp> {
+    let limit = if limit == 0 { usize::MAX } else { limit as usize };
+    self.ops.lock().iter().rev().take(limit).cloned().collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(ego: &str) -> Request {
+    Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::ReadScores(OpReadScores {
+        ego:           ego.into(),
+        score_options: FilterOptions::default(),
+      }),
+    }
+  }
+
+  #[tokio::test]
+  async fn keeps_latest_ops_with_counters() {
+    let log = SlowLog::new(2);
+    for ego in ["U1", "U2", "U3"] {
+      let trace = Arc::new(RequestTrace::default());
+      traced(Arc::clone(&trace), async {
+        record_cached();
+        record_calculated(100);
+      })
+      .await;
+      let elapsed = Duration::from_millis(5);
+      log.record(&request(ego), &Response::Ok, elapsed, &trace);
+    }
+
+    let ops = log.read(0);
+    assert_eq!(ops.len(), 2);
+    assert!(ops[0].request.contains("U3"));
+    assert!(ops[1].request.contains("U2"));
+    assert_eq!(ops[0].total_us, 5000);
+    assert_eq!(ops[0].egos_cached, 1);
+    assert_eq!(ops[0].egos_calculated, 1);
+    assert_eq!(ops[0].walks, 100);
+    assert_eq!(log.read(1).len(), 1);
+  }
+}
@@ -0,0 +1,68 @@
+//! Import of the graph from Postgres, see `ImportFromSql`.
+//!
+//! `MERITRANK_IMPORT_QUERY` returns one row per edge: source and destination
+//! node names, the weight as `float8`, and optionally the context as a fourth
+//! column (NULL = the default context). Nodes are registered by the bulk load
+//! as their edges are set. Needs the `sql` feature.
+
+use crate::data::{BulkEdge, ServiceError};
+
+/// Runs the import query against the database at `url`.
+pub async fn fetch_edges(
+  url: &str,
+  query: &str,
+) -> Result<Vec<BulkEdge>, ServiceError> {
+  if url.is_empty() || query.is_empty() {
+    return Err(ServiceError::ImportFailed(
+      "MERITRANK_IMPORT_DATABASE_URL and MERITRANK_IMPORT_QUERY are not set"
+        .into(),
+    ));
+  }
+  query_edges(url, query).await.map_err(ServiceError::ImportFailed)
+}
+
+#[cfg(feature = "sql")]
+async fn query_edges(
+  url: &str,
+  query: &str,
+) -> Result<Vec<BulkEdge>, String> {
+  use sqlx::{Connection, PgConnection, Row};
+
+  let mut connection =
+    PgConnection::connect(url).await.map_err(|e| e.to_string())?;
+  let rows = sqlx::query(query)
+    .fetch_all(&mut connection)
+    .await
+    .map_err(|e| e.to_string())?;
+  let _ = connection.close().await;
+
+  rows
+    .iter()
+    .map(|row| {
+      let context: Option<String> = match row.len() {
+        0..=2 => return Err(sqlx::Error::ColumnIndexOutOfBounds {
+          index: 2,
+          len:   row.len(),
+        }),
+        3 => None,
+        _ => row.try_get(3)?,
+      };
+      Ok(BulkEdge {
+        src:       row.try_get(0)?,
+        dst:       row.try_get(1)?,
+        amount:    row.try_get(2)?,
+        magnitude: 0,
+        context:   context.unwrap_or_default(),
+      })
+    })
+    .collect::<Result<_, sqlx::Error>>()
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "sql"))]
+async fn query_edges(
+  _url: &str,
+  _query: &str,
+) -> Result<Vec<BulkEdge>, String> {
+  Err("The service is built without the sql feature".into())
+}
@@ -0,0 +1,1528 @@
+use crate::aug_graph::*;
+use crate::cache_sizing::auto_cache_sizes;
+use crate::data::*;
+use crate::file_import::read_edge_list;
+use crate::node_registry::*;
+use crate::publish_barrier::PublishBarrier;
+use crate::settings::*;
+use crate::sql_import::fetch_edges;
+use crate::state_store::StateStore;
+use crate::utils::log::*;
+
+use meritrank_core::NodeId;
+use parking_lot::RwLock;
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::{
+  FanoutSender, GraphProcessor, MultiGraphProcessor, PublishPolicy,
+  ReplicationTap,
+};
+
+fn new_state_store(settings: &Settings) -> Option<StateStore> {
+  if settings.state_dir.is_empty() {
+    return None;
+  }
+  match StateStore::new(settings.state_dir.clone().into()) {
+    Ok(x) => Some(x),
+    Err(e) => {
+      log_error!("Failed to open state dir: {}", e);
+      None
+    },
+  }
+}
+
+impl MultiGraphProcessor {
+  /// Replaces all contexts with the edges. Other requests fail until the
+  /// load is done.
+  pub(crate) async fn bulk_load(
+    &self,
+    edges: Vec<BulkEdge>,
+  ) -> Response {
+    self.loading.store(true, Ordering::SeqCst);
+
+    self.clear_contexts();
+
+    let mut contexts: HashSet<SubgraphName> = HashSet::new();
+    for edge in &edges {
+      if !edge.context.is_empty() {
+        contexts.insert(edge.context.clone());
+      }
+    }
+    for ctx in &contexts {
+      self.insert_subgraph_if_does_not_exist(ctx);
+    }
+
+    let mut user_user_edges: Vec<OpWriteEdge> = vec![];
+    let mut context_non_user_edges: HashMap<SubgraphName, Vec<OpWriteEdge>> =
+      HashMap::new();
+
+    for edge in edges {
+      let op = OpWriteEdge {
+        src:        edge.src,
+        dst:        edge.dst,
+        amount:     edge.amount,
+        magnitude:  edge.magnitude,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      };
+      let src_kind = node_kind_from_prefix(&op.src);
+      let dst_kind = node_kind_from_prefix(&op.dst);
+
+      if matches!(
+        (src_kind, dst_kind),
+        (Some(NodeKind::User), Some(NodeKind::User))
+      ) {
+        user_user_edges.push(op);
+      } else {
+        context_non_user_edges
+          .entry(edge.context)
+          .or_default()
+          .push(op);
+      }
+    }
+
+    let mut aggregate_edges = user_user_edges.clone();
+    for edges in context_non_user_edges.values() {
+      aggregate_edges.extend(edges.iter().cloned());
+    }
+    let _ = self
+      .send_op(&String::new(), AugGraphOp::BulkLoadEdges(aggregate_edges))
+      .await;
+
+    for ctx in &contexts {
+      let mut ctx_edges = user_user_edges.clone();
+      if let Some(specific) = context_non_user_edges.get(ctx) {
+        ctx_edges.extend(specific.iter().cloned());
+      }
+      let _ = self.send_op(ctx, AugGraphOp::BulkLoadEdges(ctx_edges)).await;
+    }
+
+    let stamp = self.next_stamp();
+    self.sync_future(stamp).await;
+
+    self.loading.store(false, Ordering::SeqCst);
+    Response::Ok
+  }
+
+  /// Replaces all contexts with the edges of the import query, see
+  /// `sql_import`.
+  pub async fn import_from_sql(&self) -> Response {
+    let edges = match fetch_edges(
+      &self.settings.import_database_url,
+      &self.settings.import_query,
+    )
+    .await
+    {
+      Ok(edges) => edges,
+      Err(e) => {
+        log_error!("Import failed: {:?}", e);
+        return Response::Error(e);
+      },
+    };
+    log_info!("Importing {} edges", edges.len());
+    self.bulk_load(edges).await
+  }
+
+  /// Replaces all contexts with the edges of the CSV or JSONL file, see
+  /// `file_import`. The file is read on a blocking thread; `GetStats`
+  /// reports the progress until the edges are loaded.
+  pub async fn import_edge_list(
+    &self,
+    path: PathBuf,
+  ) -> Response {
+    log_info!("Importing edges from {:?}", path);
+    let progress = Arc::clone(&self.import_progress);
+    let edges = tokio::task::spawn_blocking(move || {
+      read_edge_list(&path, &progress)
+    })
+    .await
+    .unwrap_or_else(|e| Err(ServiceError::ImportFailed(e.to_string())));
+    let response = match edges {
+      Ok(edges) => {
+        log_info!("Importing {} edges", edges.len());
+        self.bulk_load(edges).await
+      },
+      Err(e) => {
+        log_error!("Import failed: {:?}", e);
+        Response::Error(e)
+      },
+    };
+    self.import_progress.reset();
+    response
+  }
+
+  /// Removes all contexts, leaving an empty default one.
+  pub(crate) fn clear_contexts(&self) {
+    self.subgraphs_map.clear();
+    if let Some(storage) = &self.cold_storage {
+      storage.clear();
+    }
+    self.insert_subgraph_if_does_not_exist(&String::new());
+    self.replicate(&String::new(), ReplicationRecord::Reset);
+  }
+
+  pub(crate) fn delete_context(
+    &self,
+    subgraph_name: &SubgraphName,
+  ) -> Response {
+    if subgraph_name.is_empty() {
+      log_warning!("The default context cannot be deleted");
+      return Response::Fail;
+    }
+    //  The processing thread exits and frees both graph copies once the
+    //  remaining senders are dropped and its queue is drained.
+    let stored = self
+      .cold_storage
+      .as_ref()
+      .is_some_and(|storage| storage.remove(subgraph_name));
+    match self.subgraphs_map.remove(subgraph_name) {
+      Some(_) => {},
+      None if stored => {},
+      None => {
+        log_warning!("Subgraph not found for name: {:?}", subgraph_name);
+        return Response::Fail;
+      },
+    }
+    self.walk_budgets.remove(subgraph_name);
+    self.replicate(subgraph_name, ReplicationRecord::DeleteContext);
+    Response::Ok
+  }
+
+  /// Seeds the given (new) context with user-user edges from the "" aggregate. Does not update tracking or "".
+  pub(crate) fn new_graph_processor(
+    &self,
+    subgraph_name: &SubgraphName,
+    mut aug_graph: AugGraph,
+  ) -> GraphProcessor {
+    //  Stamps sent so far went to the context it replaces, if any, so a sync
+    //  that started before, e.g. while a replica restores a snapshot, does
+    //  not wait for this one forever.
+    aug_graph.stamp = self.internal_stamp.load(Ordering::SeqCst);
+    let walks_cache_size = if self.settings.cache_memory_budget > 0 {
+      auto_cache_sizes(aug_graph.nodes.len(), &self.settings).walk_egos
+    } else {
+      self.settings.walks_cache_size
+    };
+    let mut processor = GraphProcessor::new(
+      subgraph_name,
+      aug_graph,
+      self.settings.subgraph_queue_capacity,
+      PublishPolicy::from_settings(&self.settings),
+      self.publish_notify.clone(),
+      self.stats.clone(),
+      walks_cache_size,
+    );
+    processor.op_sender.tap =
+      self.replication.as_ref().map(|replication| ReplicationTap {
+        subgraph:    subgraph_name.clone(),
+        replication: Arc::clone(replication),
+      });
+    processor
+  }
+
+  /// Loads the context back from cold storage if it was evicted, then seeds
+  /// its user-to-user edges from the aggregate.
+  pub(crate) async fn reload_if_evicted(
+    &self,
+    subgraph_name: &SubgraphName,
+  ) {
+    let storage = match &self.cold_storage {
+      Some(x) => x,
+      None => return,
+    };
+    if self.subgraphs_map.contains_key(subgraph_name)
+      || !storage.contains(subgraph_name)
+    {
+      return;
+    }
+
+    let aug_graph = match storage.load(subgraph_name, &self.settings) {
+      Ok(x) => x,
+      Err(e) => {
+        log_error!("Failed to reload {:?}: {}", subgraph_name, e);
+        return;
+      },
+    };
+
+    use dashmap::mapref::entry::Entry;
+    let was_new = match self.subgraphs_map.entry(subgraph_name.clone()) {
+      Entry::Occupied(_) => false,
+      Entry::Vacant(v) => {
+        v.insert(self.new_graph_processor(subgraph_name, aug_graph));
+        true
+      },
+    };
+    if was_new {
+      log_verbose!("Reloaded {:?} from cold storage", subgraph_name);
+      self.seed_context_from_aggregate(subgraph_name).await;
+    }
+  }
+
+  /// Moves the least recently accessed contexts to cold storage while more than
+  /// `max_resident_contexts` are loaded. The aggregate, `keep`, and contexts
+  /// with queued writes stay in memory.
+  pub(crate) fn evict_idle_contexts(
+    &self,
+    keep: &SubgraphName,
+  ) {
+    let storage = match &self.cold_storage {
+      Some(x) => x,
+      None => return,
+    };
+    let max = self.settings.max_resident_contexts;
+    //  Reloads seed contexts with ops, which would take seqs of the primary.
+    if max == 0 || self.subgraphs_map.len() <= max || self.is_replica() {
+      return;
+    }
+
+    let mut candidates: Vec<(u64, SubgraphName)> = self
+      .subgraphs_map
+      .iter()
+      .filter(|r| !r.key().is_empty() && r.key() != keep)
+      .map(|r| {
+        (r.value().last_access.load(Ordering::Relaxed), r.key().clone())
+      })
+      .collect();
+    candidates.sort();
+
+    let mut excess = self.subgraphs_map.len() - max;
+    for (_, name) in candidates {
+      if excess == 0 {
+        break;
+      }
+      let (name, processor) = match self
+        .subgraphs_map
+        .remove_if(&name, |_, p| p.op_sender.depth() == 0)
+      {
+        Some(x) => x,
+        None => continue,
+      };
+
+      let arc = processor.shared.load_full();
+      let result = storage.save(&name, &arc.read());
+      match result {
+        Ok(()) => {
+          log_verbose!("Evicted {:?} to cold storage", name);
+          excess -= 1;
+        },
+        Err(e) => {
+          log_error!("Failed to evict {:?}: {}", name, e);
+          self.subgraphs_map.entry(name).or_insert(processor);
+        },
+      }
+    }
+  }
+
+  /// Lists loaded contexts with their sizes. With an ACL, only contexts the
+  /// token may read are listed.
+  pub(crate) fn process_read_contexts(
+    &self,
+    token: &str,
+  ) -> Response {
+    let acl = &self.settings.acl;
+    let subgraphs: Vec<_> = self
+      .subgraphs_map
+      .iter()
+      .filter(|r| !acl.is_enabled() || acl.check(token, r.key(), false).is_ok())
+      .map(|r| {
+        (
+          r.key().clone(),
+          Arc::clone(&r.value().shared),
+          r.value().last_access.load(Ordering::Relaxed),
+        )
+      })
+      .collect();
+
+    let mut contexts: Vec<ContextInfo> = subgraphs
+      .into_iter()
+      .map(|(name, shared, last_access)| {
+        let arc = shared.load_full();
+        let aug_graph = arc.read();
+        let walks = self
+          .walk_budgets
+          .used(&name, self.settings.walk_budget(&name));
+        ContextInfo {
+          name,
+          nodes: aug_graph.nodes.len(),
+          edges: aug_graph.edge_count(),
+          last_access,
+          walks,
+        }
+      })
+      .collect();
+    contexts.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Response::Contexts(ResContexts {
+      contexts,
+    })
+  }
+
+  /// Creates `destination` from a snapshot of `source`, taken after all writes
+  /// queued so far have been applied. Fails if `destination` already exists.
+  pub(crate) async fn process_fork_context(
+    &self,
+    source: &SubgraphName,
+    destination: &SubgraphName,
+  ) -> Response {
+    let stored = self
+      .cold_storage
+      .as_ref()
+      .is_some_and(|storage| storage.contains(destination));
+    if stored || self.subgraphs_map.contains_key(destination) {
+      log_warning!("Fork destination already exists: {:?}", destination);
+      return Response::Fail;
+    }
+    self.reload_if_evicted(source).await;
+    let shared = match self.subgraphs_map.get(source) {
+      Some(subgraph) => Arc::clone(&subgraph.shared),
+      None => {
+        log_warning!("Fork source not found: {:?}", source);
+        return Response::Fail;
+      },
+    };
+
+    let stamp = self.next_stamp();
+    self.sync_future(stamp).await;
+
+    let snapshot = shared.load_full().read().fork();
+
+    use dashmap::mapref::entry::Entry;
+    match self.subgraphs_map.entry(destination.clone()) {
+      Entry::Occupied(_) => {
+        log_warning!("Fork destination already exists: {:?}", destination);
+        Response::Fail
+      },
+      Entry::Vacant(v) => {
+        v.insert(self.new_graph_processor(destination, snapshot));
+        self.replicate(destination, ReplicationRecord::Fork(source.clone()));
+        Response::Ok
+      },
+    }
+  }
+
+  /// Folds the non-user edges of `source` into `destination`, resolving edges present
+  /// in both with the given strategy. User-to-user edges are already identical in all
+  /// contexts and are skipped. Merged edges go through the bulk edge path, so walks of
+  /// the destination are recalculated lazily on the next read. Non-aggregate merges
+  /// are mirrored to the aggregate, as for regular writes.
+  pub(crate) async fn process_merge_context(
+    &self,
+    data: &OpWriteMergeContext,
+  ) -> Response {
+    self.reload_if_evicted(&data.source).await;
+    self.reload_if_evicted(&data.destination).await;
+
+    if data.source == data.destination
+      || !self.subgraphs_map.contains_key(&data.source)
+      || !self.subgraphs_map.contains_key(&data.destination)
+    {
+      log_warning!(
+        "Invalid merge: {:?} -> {:?}",
+        data.source,
+        data.destination
+      );
+      return Response::Fail;
+    }
+
+    let stamp = self.next_stamp();
+    self.sync_future(stamp).await;
+
+    let read_edges = |subgraph: &SubgraphName| {
+      match self.process_read(subgraph, |aug_graph| {
+        Response::Edges(ResEdges {
+          edges: aug_graph.read_edges(),
+        })
+      }) {
+        Response::Edges(ResEdges { edges }) => edges,
+        _ => vec![],
+      }
+    };
+    let is_user_edge = |edge: &EdgeResult| {
+      node_kind_from_prefix(&edge.src) == Some(NodeKind::User)
+        && node_kind_from_prefix(&edge.dst) == Some(NodeKind::User)
+    };
+
+    let existing: HashMap<(NodeName, NodeName), Weight> =
+      read_edges(&data.destination)
+        .into_iter()
+        .filter(|edge| !is_user_edge(edge) && edge.weight != 0.0)
+        .map(|edge| ((edge.src, edge.dst), edge.weight))
+        .collect();
+
+    let mut conflicts = 0;
+    let mut merged = vec![];
+    for edge in read_edges(&data.source) {
+      if is_user_edge(&edge) || edge.weight == 0.0 {
+        continue;
+      }
+      let amount = match existing.get(&(edge.src.clone(), edge.dst.clone())) {
+        Some(&weight) => {
+          conflicts += 1;
+          let amount = data.strategy.resolve(weight, edge.weight);
+          if amount == weight {
+            continue;
+          }
+          amount
+        },
+        None => edge.weight,
+      };
+      merged.push(OpWriteEdge {
+        src: edge.src,
+        dst: edge.dst,
+        amount,
+        magnitude: 0,
+        edge_kind: EdgeKind::Vote,
+        provenance: None,
+      });
+    }
+
+    let result = ResMerge {
+      conflicts,
+      merged: merged.len(),
+    };
+    if data.dry_run || merged.is_empty() {
+      return Response::Merge(result);
+    }
+
+    let mut senders = vec![self.get_tx_channel(&data.destination)];
+    if !data.destination.is_empty() {
+      senders.push(self.get_tx_channel(&String::new()));
+    }
+    match self.try_send_op_all(&senders, AugGraphOp::BulkLoadEdges(merged)) {
+      Response::Ok => Response::Merge(result),
+      other => other,
+    }
+  }
+
+  pub(crate) async fn seed_context_from_aggregate(
+    &self,
+    subgraph_name: &SubgraphName,
+  ) {
+    let default_ctx = String::new();
+    let response = self.process_read(&default_ctx, |aug_graph| {
+      Response::Edges(ResEdges {
+        edges: aug_graph.read_edges(),
+      })
+    });
+
+    if let Response::Edges(ResEdges { edges }) = response {
+      let tx = match self.subgraphs_map.get(subgraph_name) {
+        Some(entry) => entry.op_sender.clone(),
+        None => return,
+      };
+      for edge in edges {
+        if node_kind_from_prefix(&edge.src) == Some(NodeKind::User)
+          && node_kind_from_prefix(&edge.dst) == Some(NodeKind::User)
+          && edge.weight != 0.0
+        {
+          let _ = tx
+            .send(AugGraphOp::WriteEdge(OpWriteEdge {
+              src:        edge.src,
+              dst:        edge.dst,
+              amount:     edge.weight,
+              magnitude:  0,
+              edge_kind:  EdgeKind::Vote,
+              provenance: None,
+            }))
+            .await;
+        }
+      }
+    }
+  }
+
+  /// Saves all contexts in memory with their walks to `state_dir`, with the
+  /// ops queued so far applied. Replicas save nothing.
+  pub async fn save_state(&self) {
+    if self.is_replica() {
+      return;
+    }
+    let store = match new_state_store(&self.settings) {
+      Some(x) => x,
+      None => return,
+    };
+    let stamp = self.next_stamp();
+    self.sync_future(stamp).await;
+
+    let started = Instant::now();
+    let contexts: Vec<(SubgraphName, Arc<RwLock<AugGraph>>)> = self
+      .subgraphs_map
+      .iter()
+      .map(|r| (r.key().clone(), r.value().shared.load_full()))
+      .collect();
+    let mut num_egos = 0;
+    for (name, aug_graph) in &contexts {
+      match store.save(name, &aug_graph.read()) {
+        Ok(n) => num_egos += n,
+        Err(e) => log_error!("Failed to save context {:?}: {}", name, e),
+      }
+    }
+    log_info!(
+      "Saved {} contexts with walks of {} egos in {:?}",
+      contexts.len(),
+      num_egos,
+      started.elapsed()
+    );
+  }
+
+  /// Replaces the contexts in memory with those saved by `save_state`, if
+  /// any. Returns false if there was nothing to load.
+  pub fn load_state(&self) -> bool {
+    if self.is_replica() {
+      return false;
+    }
+    let store = match new_state_store(&self.settings) {
+      Some(x) => x,
+      None => return false,
+    };
+    let contexts = store.load_all(&self.settings);
+    if contexts.is_empty() {
+      return false;
+    }
+    log_info!("Loaded {} contexts from the state dir", contexts.len());
+    for (name, aug_graph) in contexts {
+      let egos: Vec<NodeId> =
+        aug_graph.mr.get_personal_hits().keys().copied().collect();
+      let processor = self.new_graph_processor(&name, aug_graph);
+      if let Some(tracker) = &processor.walk_tracker {
+        for ego in egos {
+          tracker.touch(ego);
+        }
+      }
+      self.subgraphs_map.insert(name, processor);
+    }
+    true
+  }
+
+  /// Snapshots of all contexts in memory, with the ops queued so far applied.
+  pub async fn snapshot_contexts(&self) -> Vec<ContextSnapshot> {
+    let stamp = self.next_stamp();
+    self.sync_future(stamp).await;
+    self
+      .subgraphs_map
+      .iter()
+      .map(|r| r.value().shared.load_full().read().snapshot(r.key().clone()))
+      .collect()
+  }
+
+  /// Snapshot of the context, with the ops queued so far applied.
+  pub(crate) async fn snapshot_context(
+    &self,
+    subgraph_name: &SubgraphName,
+  ) -> Option<ContextSnapshot> {
+    let shared = Arc::clone(&self.subgraphs_map.get(subgraph_name)?.shared);
+    let stamp = self.next_stamp();
+    self.sync_future(stamp).await;
+    let snapshot = shared.load_full().read().snapshot(subgraph_name.clone());
+    Some(snapshot)
+  }
+
+  fn insert_context_snapshot(
+    &self,
+    subgraph_name: &SubgraphName,
+    snapshot: ContextSnapshot,
+  ) {
+    let aug_graph = AugGraph::from_snapshot(self.settings.clone(), snapshot);
+    let processor = self.new_graph_processor(subgraph_name, aug_graph);
+    self.subgraphs_map.insert(subgraph_name.clone(), processor);
+  }
+
+  /// Replaces the context with the snapshot, whatever context it was taken
+  /// from.
+  pub(crate) fn restore_context(
+    &self,
+    subgraph_name: &SubgraphName,
+    snapshot: ContextSnapshot,
+  ) {
+    if let Some(storage) = &self.cold_storage {
+      storage.remove(subgraph_name);
+    }
+    self.insert_context_snapshot(subgraph_name, snapshot.clone());
+    self.replicate(subgraph_name, ReplicationRecord::Snapshot(snapshot));
+  }
+
+  /// Replaces all contexts with the snapshots.
+  pub fn restore_contexts(
+    &self,
+    contexts: Vec<ContextSnapshot>,
+  ) {
+    self.subgraphs_map.clear();
+    for snapshot in contexts {
+      let name = snapshot.context.clone();
+      self.insert_context_snapshot(&name, snapshot);
+    }
+    self.insert_subgraph_if_does_not_exist(&String::new());
+  }
+
+  /// Applies an entry received from the primary. Entries are appended to the
+  /// local replication log the same way as on the primary.
+  pub async fn apply_replicated(
+    &self,
+    entry: ReplicationEntry,
+  ) {
+    let subgraph = &entry.subgraph;
+    match entry.record {
+      ReplicationRecord::Op(mut op) => {
+        //  The part waits for the outcome on the primary.
+        if let AugGraphOp::Staged(_, gate) = &mut op {
+          let barrier = self
+            .replicated_parts
+            .entry(gate.id)
+            .or_insert_with(PublishBarrier::replicated)
+            .clone();
+          gate.attach(barrier);
+        }
+        let sender = self.get_tx_channel(subgraph);
+        if sender.send(op).await.is_err() {
+          log_error!("Write queue is closed");
+        }
+      },
+      ReplicationRecord::CreateContext => {
+        self.insert_subgraph_if_does_not_exist(subgraph);
+        self.replicate(subgraph, ReplicationRecord::CreateContext);
+      },
+      ReplicationRecord::Fork(source) => {
+        let _ = self.process_fork_context(&source, subgraph).await;
+      },
+      ReplicationRecord::DeleteContext => {
+        let _ = self.delete_context(subgraph);
+      },
+      ReplicationRecord::Reset => self.clear_contexts(),
+      ReplicationRecord::Snapshot(snapshot) => {
+        self.restore_context(subgraph, snapshot)
+      },
+      ReplicationRecord::StagedOutcome(id, publish) => {
+        //  Replicated first, so that replicas of this one get it before the
+        //  writes that follow the parts.
+        self.replicate(subgraph, ReplicationRecord::StagedOutcome(id, publish));
+        if let Some((_, barrier)) = self.replicated_parts.remove(&id) {
+          barrier.decide(publish);
+        }
+      },
+    }
+  }
+
+  pub fn insert_subgraph_if_does_not_exist(
+    &self,
+    subgraph_name: &SubgraphName,
+  ) -> FanoutSender {
+    log_trace!();
+
+    if let Some(entry) = self.subgraphs_map.get(subgraph_name) {
+      entry.touch();
+      return entry.op_sender.clone();
+    }
+    self
+      .subgraphs_map
+      .entry(subgraph_name.clone())
+      .or_insert_with(|| {
+        log_trace!("Create subgraph");
+        self.new_graph_processor(
+          subgraph_name,
+          AugGraph::new(self.settings.clone()),
+        )
+      })
+      .op_sender
+      .clone()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::state_manager::test_utils::*;
+
+  use std::sync::atomic::Ordering;
+
+  #[tokio::test]
+  async fn context_aggregate_null_context_last_write_wins() {
+    // Verbatim aggregate: "" receives each edge write as-is; last write wins for same (src, dst).
+    let proc = default_processor();
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "B1".into(),
+        dst:        "U2".into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "Y".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "B1".into(),
+        dst:        "U2".into(),
+        amount:     2.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    sync(&proc).await;
+    let response = proc.process_request(&Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::ReadEdges,
+    }).await;
+    let edges = edges_from_response(response);
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].0, "B1");
+    assert_eq!(edges[0].1, "U2");
+    assert!((edges[0].2 - 2.0).abs() < 1e-6, "expected weight ~2.0 (last write wins), got {}", edges[0].2);
+  }
+
+  #[tokio::test]
+  async fn context_aggregate_null_context_contains_all_users() {
+    let proc = default_processor();
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        "U2".into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "Y".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        "U3".into(),
+        amount:     2.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    sync(&proc).await;
+    let response = proc.process_request(&Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::ReadEdges,
+    }).await;
+    let edges = edges_from_response(response);
+    let expected = vec![
+      ("U1".to_string(), "U2".to_string(), 1.0),
+      ("U1".to_string(), "U3".to_string(), 2.0),
+    ];
+    assert_eq!(edges.len(), expected.len());
+    for exp in &expected {
+      assert!(edges.iter().any(|e| e.0 == exp.0 && e.1 == exp.1 && (e.2 - exp.2).abs() < 1e-9));
+    }
+  }
+
+  #[tokio::test]
+  async fn context_aggregate_delete_contexted_edge() {
+    // Verbatim: deleting from X sends WriteEdge(0) to ""; edge is removed or zeroed in "".
+    let proc = default_processor();
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "B1".into(),
+        dst:        "U2".into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "Y".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "B1".into(),
+        dst:        "U2".into(),
+        amount:     2.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteDeleteEdge(OpWriteDeleteEdge {
+        src:   "B1".into(),
+        dst:   "U2".into(),
+        index: -1,
+      }),
+    }).await;
+    sync(&proc).await;
+    let response = proc.process_request(&Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::ReadEdges,
+    }).await;
+    let edges = edges_from_response(response);
+    // After verbatim delete, "" has WriteEdge(0); graph may omit zero-weight edges from ReadEdges.
+    assert!(edges.is_empty() || (edges.len() == 1 && (edges[0].2 - 0.0).abs() < 1e-6),
+      "expected no edges or single edge with weight 0, got {} edges", edges.len());
+  }
+
+  #[tokio::test]
+  async fn context_aggregate_null_context_invariant() {
+    // Verbatim: delete from X (sends 0 to ""), then re-add 1.0 from X; "" ends with 1.0.
+    let proc = default_processor();
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "B1".into(),
+        dst:        "U2".into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "Y".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "B1".into(),
+        dst:        "U2".into(),
+        amount:     2.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteDeleteEdge(OpWriteDeleteEdge {
+        src:   "B1".into(),
+        dst:   "U2".into(),
+        index: -1,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "B1".into(),
+        dst:        "U2".into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    sync(&proc).await;
+    let response = proc.process_request(&Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::ReadEdges,
+    }).await;
+    let edges = edges_from_response(response);
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].0, "B1");
+    assert_eq!(edges[0].1, "U2");
+    assert!((edges[0].2 - 1.0).abs() < 1e-6, "expected weight ~1.0 (verbatim), got {}", edges[0].2);
+  }
+
+  #[tokio::test]
+  async fn context_aggregate_user_edges_dup() {
+    let proc = default_processor();
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        "U2".into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        "U3".into(),
+        amount:     2.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    sync(&proc).await; // ensure "" has edges before we seed Y from it
+    let _ = proc.process_request(&Request {
+      subgraph: "Y".into(),
+      token:    String::new(),
+      data:     ReqData::WriteCreateContext,
+    }).await;
+    sync(&proc).await;
+    let response = proc.process_request(&Request {
+      subgraph: "Y".into(),
+      token:    String::new(),
+      data:     ReqData::ReadEdges,
+    }).await;
+    let edges = edges_from_response(response);
+    assert_eq!(edges.len(), 2);
+    assert!(edges.iter().any(|e| e.0 == "U1" && e.1 == "U2" && (e.2 - 1.0).abs() < 1e-9));
+    assert!(edges.iter().any(|e| e.0 == "U1" && e.1 == "U3" && (e.2 - 2.0).abs() < 1e-9));
+  }
+
+  #[tokio::test]
+  async fn context_aggregate_non_user_edges_no_dup() {
+    let proc = default_processor();
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        "C2".into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        "C3".into(),
+        amount:     2.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    }).await;
+    let _ = proc.process_request(&Request {
+      subgraph: "Y".into(),
+      token:    String::new(),
+      data:     ReqData::WriteCreateContext,
+    }).await;
+    sync(&proc).await;
+    let response = proc.process_request(&Request {
+      subgraph: "Y".into(),
+      token:    String::new(),
+      data:     ReqData::ReadEdges,
+    }).await;
+    let edges = edges_from_response(response);
+    assert_eq!(edges.len(), 0);
+  }
+
+  #[tokio::test]
+  async fn bulk_load_single_context() {
+    let proc = default_processor();
+    let edges = vec![
+      BulkEdge {
+        src:       "U1".into(),
+        dst:       "U2".into(),
+        amount:    1.0,
+        magnitude: 0,
+        context:   String::new(),
+      },
+      BulkEdge {
+        src:       "U1".into(),
+        dst:       "U3".into(),
+        amount:    2.0,
+        magnitude: 0,
+        context:   String::new(),
+      },
+    ];
+    let resp = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::WriteBulkEdges(OpWriteBulkEdges { edges }),
+      })
+      .await;
+    assert!(matches!(resp, Response::Ok));
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadEdges,
+      })
+      .await;
+    let loaded = edges_from_response(response);
+    assert_eq!(loaded.len(), 2);
+    let scores_resp = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadScores(OpReadScores {
+          ego:           "U1".into(),
+          score_options: FilterOptions::default(),
+        }),
+      })
+      .await;
+    match scores_resp {
+      Response::Scores(ResScores { scores }) => assert!(!scores.is_empty()),
+      _ => panic!("expected scores"),
+    }
+  }
+
+  #[tokio::test]
+  async fn bulk_load_multi_context() {
+    let proc = default_processor();
+    let edges = vec![
+      BulkEdge {
+        src:       "U1".into(),
+        dst:       "U2".into(),
+        amount:    1.0,
+        magnitude: 0,
+        context:   String::new(),
+      },
+      BulkEdge {
+        src:       "U1".into(),
+        dst:       "B1".into(),
+        amount:    3.0,
+        magnitude: 0,
+        context:   "X".into(),
+      },
+    ];
+    let _ = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::WriteBulkEdges(OpWriteBulkEdges { edges }),
+      })
+      .await;
+    let agg = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadEdges,
+      })
+      .await;
+    let agg_edges = edges_from_response(agg);
+    assert_eq!(agg_edges.len(), 2);
+    let ctx_x = proc
+      .process_request(&Request {
+        subgraph: "X".into(),
+        token:    String::new(),
+        data:     ReqData::ReadEdges,
+      })
+      .await;
+    let x_edges = edges_from_response(ctx_x);
+    assert_eq!(x_edges.len(), 2);
+  }
+
+  #[tokio::test]
+  async fn bulk_load_lazy_calc_on_read() {
+    let proc = default_processor();
+    let edges = vec![BulkEdge {
+      src:       "U1".into(),
+      dst:       "U2".into(),
+      amount:    1.0,
+      magnitude: 0,
+      context:   String::new(),
+    }];
+    let _ = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::WriteBulkEdges(OpWriteBulkEdges { edges }),
+      })
+      .await;
+    let scores_resp = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadScores(OpReadScores {
+          ego:           "U1".into(),
+          score_options: FilterOptions::default(),
+        }),
+      })
+      .await;
+    match scores_resp {
+      Response::Scores(ResScores { scores }) => {
+        assert!(!scores.is_empty());
+        assert!(scores.iter().any(|s| s.target == "U2" && s.score > 0.0));
+      },
+      _ => panic!("expected scores"),
+    }
+  }
+
+  #[tokio::test]
+  async fn bulk_load_blocks_reads() {
+    let proc = default_processor();
+    proc.loading.store(true, Ordering::SeqCst);
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadEdges,
+      })
+      .await;
+    proc.loading.store(false, Ordering::SeqCst);
+    assert!(matches!(response, Response::Fail));
+  }
+
+  #[tokio::test]
+  async fn import_from_sql_needs_query() {
+    let proc = default_processor();
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ImportFromSql,
+      })
+      .await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::ImportFailed(_))
+    ));
+  }
+
+  #[tokio::test]
+  async fn fork_context_copies_state_and_diverges() {
+    let proc = default_processor();
+    let write = |subgraph: &str, dst: &str| Request {
+      subgraph: subgraph.into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "B1".into(),
+        dst:        dst.into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    };
+    let fork = |source: &str, destination: &str| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteForkContext(OpWriteForkContext {
+        source:      source.into(),
+        destination: destination.into(),
+      }),
+    };
+    let read_edges = |subgraph: &str| Request {
+      subgraph: subgraph.into(),
+      token:    String::new(),
+      data:     ReqData::ReadEdges,
+    };
+
+    let _ = proc.process_request(&write("X", "U2")).await;
+    assert!(matches!(proc.process_request(&fork("X", "F")).await, Response::Ok));
+    assert!(matches!(proc.process_request(&fork("X", "F")).await, Response::Fail));
+    assert!(matches!(proc.process_request(&fork("Z", "G")).await, Response::Fail));
+
+    let edges = edges_from_response(proc.process_request(&read_edges("F")).await);
+    assert_eq!(edges.len(), 1);
+    assert_eq!((edges[0].0.as_str(), edges[0].1.as_str()), ("B1", "U2"));
+
+    let _ = proc.process_request(&write("F", "U3")).await;
+    sync(&proc).await;
+    let edges = edges_from_response(proc.process_request(&read_edges("F")).await);
+    assert_eq!(edges.len(), 2);
+    let edges = edges_from_response(proc.process_request(&read_edges("X")).await);
+    assert_eq!(edges.len(), 1);
+  }
+
+  #[tokio::test]
+  async fn context_snapshot_replaces_context() {
+    let proc = default_processor();
+    let request = |subgraph: &str, data: ReqData| Request {
+      subgraph: subgraph.into(),
+      token:    String::new(),
+      data,
+    };
+    let write = ReqData::WriteEdge(OpWriteEdge {
+      src:        "B1".into(),
+      dst:        "U2".into(),
+      amount:     1.0,
+      magnitude:  0,
+      edge_kind:  EdgeKind::Vote,
+      provenance: None,
+    });
+
+    let _ = proc.process_request(&request("X", write)).await;
+    let snapshot = match proc
+      .process_request(&request("X", ReqData::ReadContextSnapshot))
+      .await
+    {
+      Response::ContextSnapshot(snapshot) => snapshot,
+      _ => panic!("no snapshot"),
+    };
+    let response = proc
+      .process_request(&request("Z", ReqData::ReadContextSnapshot))
+      .await;
+    assert!(matches!(response, Response::Fail));
+
+    let data = ReqData::WriteContextSnapshot(snapshot);
+    assert!(matches!(proc.process_request(&request("Y", data)).await, Response::Ok));
+    sync(&proc).await;
+    let edges = edges_from_response(
+      proc.process_request(&request("Y", ReqData::ReadEdges)).await,
+    );
+    assert_eq!(edges.len(), 1);
+    assert_eq!((edges[0].0.as_str(), edges[0].1.as_str()), ("B1", "U2"));
+  }
+
+  #[tokio::test]
+  async fn merge_context_resolves_conflicts() {
+    let proc = default_processor();
+    let write = |subgraph: &str, src: &str, dst: &str, amount: Weight| Request {
+      subgraph: subgraph.into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        src.into(),
+        dst:        dst.into(),
+        amount,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    };
+    let merge = |strategy: MergeStrategy, dry_run: bool| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteMergeContext(OpWriteMergeContext {
+        source: "Y".into(),
+        destination: "X".into(),
+        strategy,
+        dry_run,
+      }),
+    };
+    let read_edges = || Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data:     ReqData::ReadEdges,
+    };
+
+    let _ = proc.process_request(&write("X", "B1", "U2", 1.0)).await;
+    let _ = proc.process_request(&write("Y", "B1", "U2", 2.0)).await;
+    let _ = proc.process_request(&write("Y", "B2", "U3", 1.0)).await;
+
+    match proc.process_request(&merge(MergeStrategy::Sum, true)).await {
+      Response::Merge(res) => {
+        assert_eq!(res.conflicts, 1);
+        assert_eq!(res.merged, 2);
+      },
+      other => panic!("expected merge result, got {:?}", other),
+    }
+    let edges = edges_from_response(proc.process_request(&read_edges()).await);
+    assert_eq!(edges.len(), 1);
+
+    match proc.process_request(&merge(MergeStrategy::Max, false)).await {
+      Response::Merge(res) => {
+        assert_eq!(res.conflicts, 1);
+        assert_eq!(res.merged, 2);
+      },
+      other => panic!("expected merge result, got {:?}", other),
+    }
+    let _ = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::Sync(100),
+      })
+      .await;
+    let mut edges = edges_from_response(proc.process_request(&read_edges()).await);
+    edges.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(edges.len(), 2);
+    assert_eq!((edges[0].0.as_str(), edges[0].1.as_str()), ("B1", "U2"));
+    assert!((edges[0].2 - 2.0).abs() < 1e-6);
+    assert_eq!((edges[1].0.as_str(), edges[1].1.as_str()), ("B2", "U3"));
+    assert!((edges[1].2 - 1.0).abs() < 1e-6);
+  }
+
+  #[tokio::test]
+  async fn list_and_delete_contexts() {
+    let proc = default_processor();
+    let request = |subgraph: &str, data: ReqData| Request {
+      subgraph: subgraph.into(),
+      token:    String::new(),
+      data,
+    };
+    let list = |response: Response| match response {
+      Response::Contexts(ResContexts { contexts }) => contexts,
+      other => panic!("expected contexts, got {:?}", other),
+    };
+
+    let _ = proc
+      .process_request(&request(
+        "X",
+        ReqData::WriteEdge(OpWriteEdge {
+          src:        "B1".into(),
+          dst:        "U2".into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        }),
+      ))
+      .await;
+    sync(&proc).await;
+
+    let contexts =
+      list(proc.process_request(&request("", ReqData::ReadContexts)).await);
+    assert_eq!(contexts.len(), 2);
+    assert_eq!(contexts[0].name, "");
+    assert_eq!(contexts[1].name, "X");
+    assert_eq!(contexts[1].nodes, 2);
+    assert_eq!(contexts[1].edges, 1);
+    assert!(contexts[1].last_access > 0);
+
+    let response = proc
+      .process_request(&request("", ReqData::WriteDeleteContext))
+      .await;
+    assert!(matches!(response, Response::Fail));
+    let response = proc
+      .process_request(&request("X", ReqData::WriteDeleteContext))
+      .await;
+    assert!(matches!(response, Response::Ok));
+    let response = proc
+      .process_request(&request("X", ReqData::WriteDeleteContext))
+      .await;
+    assert!(matches!(response, Response::Fail));
+
+    let contexts =
+      list(proc.process_request(&request("", ReqData::ReadContexts)).await);
+    assert_eq!(contexts.len(), 1);
+    assert_eq!(contexts[0].name, "");
+  }
+
+  #[tokio::test]
+  async fn idle_context_is_evicted_and_reloaded() {
+    let dir = std::env::temp_dir()
+      .join(format!("meritrank-evict-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let proc = MultiGraphProcessor::new(Settings {
+      cold_storage_dir: dir.to_string_lossy().into(),
+      max_resident_contexts: 2,
+      ..Settings::default()
+    });
+    let write = |subgraph: &str, src: &str, dst: &str| Request {
+      subgraph: subgraph.into(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        src.into(),
+        dst:        dst.into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    };
+    let read_edges = |subgraph: &str| Request {
+      subgraph: subgraph.into(),
+      token:    String::new(),
+      data:     ReqData::ReadEdges,
+    };
+    let sync_to = |stamp: u64| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::Sync(stamp),
+    };
+
+    let _ = proc.process_request(&write("X", "B1", "U2")).await;
+    let _ = proc.process_request(&sync_to(1)).await;
+    let _ = proc.process_request(&write("Y", "B2", "U3")).await;
+
+    //  Three contexts are resident now, so the next request evicts the least
+    //  recently used one, X.
+    let _ = proc.process_request(&sync_to(2)).await;
+    assert!(!proc.subgraphs_map.contains_key("X"));
+    assert!(proc.subgraphs_map.contains_key("Y"));
+
+    //  User edges written while X is evicted are seeded on reload.
+    let _ = proc.process_request(&write("", "U1", "U4")).await;
+    let _ = proc.process_request(&sync_to(3)).await;
+    let _ = proc.process_request(&read_edges("X")).await;
+    assert!(proc.subgraphs_map.contains_key("X"));
+    assert!(!proc.subgraphs_map.contains_key("Y"));
+    let _ = proc.process_request(&sync_to(4)).await;
+
+    let mut edges =
+      edges_from_response(proc.process_request(&read_edges("X")).await);
+    edges.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(edges.len(), 2);
+    assert_eq!((edges[0].0.as_str(), edges[0].1.as_str()), ("B1", "U2"));
+    assert_eq!((edges[1].0.as_str(), edges[1].1.as_str()), ("U1", "U4"));
+
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::GetStats,
+      })
+      .await;
+    match response {
+      Response::Stats(stats) => {
+        assert_eq!(stats.evictions, 2);
+        assert_eq!(stats.reloads, 1);
+      },
+      other => panic!("expected stats, got {:?}", other),
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[tokio::test]
+  async fn import_edge_list_loads_file() {
+    let dir = std::env::temp_dir()
+      .join(format!("meritrank-import-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("edges.csv"), "U1,U2,1\nU2,B1,2,ctx\n").unwrap();
+    let proc = MultiGraphProcessor::new(Settings {
+      import_dir: dir.to_string_lossy().into(),
+      ..Settings::default()
+    });
+    let import = |path: &str| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::ImportEdgeList(OpImportEdgeList {
+        path: path.into(),
+      }),
+    };
+
+    let response = proc.process_request(&import("edges.csv")).await;
+    assert!(matches!(response, Response::Ok));
+    let edges = edges_from_response(
+      proc
+        .process_request(&Request {
+          subgraph: "ctx".into(),
+          token:    String::new(),
+          data:     ReqData::ReadEdges,
+        })
+        .await,
+    );
+    assert!(edges.iter().any(|(src, dst, _)| src == "U2" && dst == "B1"));
+
+    //  The progress is only reported while an import runs.
+    match proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::GetStats,
+      })
+      .await
+    {
+      Response::Stats(stats) => assert_eq!(stats.import_rows, 0),
+      other => panic!("expected stats, got {:?}", other),
+    }
+
+    for path in ["missing.csv", "../edges.csv"] {
+      assert!(matches!(
+        proc.process_request(&import(path)).await,
+        Response::Error(ServiceError::ImportFailed(_))
+      ));
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
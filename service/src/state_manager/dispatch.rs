@@ -0,0 +1,601 @@
+use crate::aug_graph::*;
+use crate::data::*;
+use crate::file_import::import_path;
+use crate::idempotency;
+use crate::protocol::{route, Route};
+use crate::slow_log::{self, RequestTrace};
+use crate::utils::log::*;
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::validation::validate_write;
+use super::{FanoutSender, MultiGraphProcessor};
+
+impl MultiGraphProcessor {
+  /// Checks the request token against the ACL. Reset and bulk load touch every
+  /// context, so they require unrestricted write access.
+  fn authorize(
+    &self,
+    req: &Request,
+  ) -> Result<(), ServiceError> {
+    let acl = &self.settings.acl;
+    if !acl.is_enabled() {
+      return Ok(());
+    }
+    match &req.data {
+      ReqData::Sync(_) | ReqData::GetStats | ReqData::ReadContexts => {
+        acl.authenticate(&req.token).map(|_| ())
+      },
+      ReqData::WriteReset
+      | ReqData::WriteBulkEdges(_)
+      | ReqData::ResetStats
+      | ReqData::Promote
+      | ReqData::MoveContext(_)
+      | ReqData::ImportFromSql
+      | ReqData::ImportEdgeList(_)
+      | ReqData::SetMaintenanceMode(_)
+      | ReqData::ExportScores(_)
+      | ReqData::Rerank(_)
+      | ReqData::WriteWebhook(_)
+      | ReqData::DeleteWebhook(_)
+      | ReqData::ReadWebhooks => acl.check_all(&req.token, true),
+      ReqData::DebugEgo(_) | ReqData::ReadSlowLog(_) => {
+        acl.check_all(&req.token, false)
+      },
+      ReqData::WriteForkContext(data) => {
+        acl.check(&req.token, &data.source, false)?;
+        acl.check(&req.token, &data.destination, true)
+      },
+      ReqData::WriteMergeContext(data) => {
+        acl.check(&req.token, &data.source, false)?;
+        acl.check(&req.token, &data.destination, !data.dry_run)
+      },
+      ReqData::WriteGcOrphans(data) => {
+        acl.check(&req.token, &req.subgraph, !data.dry_run)
+      },
+      ReqData::WriteMulti(data) => data
+        .ops
+        .iter()
+        .try_for_each(|(context, _)| acl.check(&req.token, context, true)),
+      data => acl.check(&req.token, &req.subgraph, data.is_write()),
+    }
+  }
+
+  /// Processes the wrapped write unless a request with the same key already
+  /// was, see `idempotency`.
+  async fn process_idempotent(
+    &self,
+    req: &Request,
+    data: &OpIdempotent,
+  ) -> Response {
+    if let Err(e) = idempotency::validate(data) {
+      log_warning!("Write rejected: {:?}", e);
+      return Response::Error(ServiceError::InvalidWrite(e));
+    }
+    let inner = idempotency::inner_request(req, data);
+    let process = Box::pin(self.process(&inner));
+    self.idempotency.process(&req.subgraph, &data.key, process).await
+  }
+
+  pub async fn process_request(
+    &self,
+    req: &Request,
+  ) -> Response {
+    let threshold = match self.settings.slow_threshold_of(req.data.opcode()) {
+      Some(x) => x,
+      None => return self.process(req).await,
+    };
+    let trace = Arc::new(RequestTrace::default());
+    let start = Instant::now();
+    let response =
+      slow_log::traced(Arc::clone(&trace), self.process(req)).await;
+    let elapsed = start.elapsed();
+    if elapsed >= threshold {
+      self.slow_log.record(req, &response, elapsed, &trace);
+    }
+    response
+  }
+
+  /// Checks the request and loads its context, or the response to send
+  /// instead.
+  pub(crate) async fn admit(
+    &self,
+    req: &Request,
+  ) -> Result<(), Response> {
+    if let Err(e) = self.authorize(req) {
+      log_warning!("Request rejected: {:?}", e);
+      return Err(Response::Error(e));
+    }
+
+    if let Err(e) = validate_write(&req.data) {
+      log_warning!("Write rejected: {:?}", e);
+      return Err(Response::Error(ServiceError::InvalidWrite(e)));
+    }
+
+    if self.is_replica()
+      && req.data.is_write()
+      && !matches!(&req.data, ReqData::ResetStats)
+    {
+      log_warning!("Replica rejected write: {}", req.data.opcode());
+      return Err(Response::Error(ServiceError::ReadOnly));
+    }
+
+    if self.maintenance.load(Ordering::SeqCst)
+      && req.data.is_write()
+      && !matches!(&req.data, ReqData::ResetStats)
+    {
+      log_warning!("Write rejected in maintenance: {}", req.data.opcode());
+      return Err(Response::Error(ServiceError::Maintenance));
+    }
+
+    if self.loading.load(Ordering::SeqCst)
+      && !matches!(&req.data, ReqData::WriteBulkEdges(_) | ReqData::GetStats)
+    {
+      return Err(Response::Fail);
+    }
+
+    if !matches!(&req.data, ReqData::WriteDeleteContext) {
+      self.reload_if_evicted(&req.subgraph).await;
+    }
+    self.evict_idle_contexts(&req.subgraph);
+
+    Ok(())
+  }
+
+  async fn process(
+    &self,
+    req: &Request,
+  ) -> Response {
+    //  FIXME: No need to clone here, but borrow checker!!!
+
+    log_trace!();
+
+    if let ReqData::Idempotent(data) = &req.data {
+      return self.process_idempotent(req, data).await;
+    }
+
+    if let Err(response) = self.admit(req).await {
+      return response;
+    }
+
+    if let Some(fallback) = self.house_ego_request(req) {
+      return match Box::pin(self.process(&fallback)).await {
+        Response::Scores(mut res) => {
+          for x in &mut res.scores {
+            x.fallback = true;
+          }
+          Response::Scores(res)
+        },
+        other => other,
+      };
+    }
+    if let Some(shared) = self.shared_scores_request(req) {
+      return match Box::pin(self.process(&shared)).await {
+        Response::Scores(mut res) => {
+          for x in &mut res.scores {
+            x.shared = true;
+          }
+          Response::Scores(res)
+        },
+        other => other,
+      };
+    }
+
+    let data = req.data.clone();
+
+    let prepare_start = Instant::now();
+    let prepared = self.prepare_request(req).await;
+    slow_log::record_calculate_time(prepare_start.elapsed());
+    if let Err(e) = prepared {
+      log_warning!("Request failed: {:?}", e);
+      return Response::Error(e);
+    }
+
+    let data = match route(data) {
+      Route::Read(data) => {
+        return self
+          .dispatch_read(&req.subgraph, move |aug_graph| {
+            aug_graph.read_request(data)
+          })
+          .await;
+      },
+      Route::Write(op) => return self.send_op(&req.subgraph, op).await,
+      Route::Processor(data) => data,
+    };
+
+    match data {
+      ReqData::ResetStats => {
+        if let Some(s) = &self.stats {
+          s.reset();
+        }
+        Response::Ok
+      },
+      ReqData::GetStats => {
+        let snap = self
+          .stats
+          .as_ref()
+          .map(|s| s.snapshot())
+          .unwrap_or(crate::processor_stats::StatsSnapshot {
+            pending:    0,
+            median_us:  0,
+            p95_us:     0,
+            p99_us:     0,
+            min_us:     0,
+            max_us:     0,
+            count:      0,
+            publishes:  0,
+            published:  0,
+            max_batch:  0,
+            panics:     0,
+          });
+        Response::Stats(ResStats {
+          pending:             snap.pending,
+          median_us:           snap.median_us,
+          p95_us:              snap.p95_us,
+          p99_us:              snap.p99_us,
+          min_us:              snap.min_us,
+          max_us:              snap.max_us,
+          count:               snap.count,
+          queue_depth:         self.max_queue_depth(),
+          queue_capacity:      self.settings.subgraph_queue_capacity,
+          evictions:           self
+            .cold_storage
+            .as_ref()
+            .map_or(0, |s| s.evictions.load(Ordering::Relaxed)),
+          reloads:             self
+            .cold_storage
+            .as_ref()
+            .map_or(0, |s| s.reloads.load(Ordering::Relaxed)),
+          cluster_queue_depth: self.cluster_queue_depth(),
+          compression_saved:   self.compression_saved.load(Ordering::Relaxed),
+          publishes:           snap.publishes,
+          published_ops:       snap.published,
+          max_publish_batch:   snap.max_batch,
+          writer_panics:       snap.panics,
+          replication_seq:     self
+            .replication
+            .as_ref()
+            .map_or(0, |replication| replication.last_seq()),
+          import_rows:         self
+            .import_progress
+            .rows
+            .load(Ordering::Relaxed),
+          import_progress:     self.import_progress.fraction(),
+          maintenance:         self.maintenance.load(Ordering::SeqCst),
+          walk_rejections:     self
+            .walk_budgets
+            .rejections
+            .load(Ordering::Relaxed),
+        })
+      },
+      ReqData::WriteEdge(data) => {
+        self.process_write_edge(&req.subgraph, &data).await
+      },
+      ReqData::WriteEdgeCas(data) => {
+        self.process_write_edge_cas(&req.subgraph, data).await
+      },
+      ReqData::WriteMulti(data) => self.process_write_multi(data).await,
+      ReqData::WriteBulkEdges(data) => self.bulk_load(data.edges).await,
+      ReqData::ImportFromSql => self.import_from_sql().await,
+      ReqData::ImportEdgeList(data) => {
+        match import_path(&self.settings.import_dir, &data.path) {
+          Ok(path) => self.import_edge_list(path).await,
+          Err(e) => Response::Error(e),
+        }
+      },
+      ReqData::RecalculateEgo(data) => {
+        self.process_recalculate(&req.subgraph, Some(&data.ego)).await
+      },
+      ReqData::RecalculateAll => {
+        self.process_recalculate(&req.subgraph, None).await
+      },
+      ReqData::Rerank(data) => Response::Rerank(ResRerank {
+        contexts: self.rerank(data.force).await,
+      }),
+      ReqData::WriteWebhook(data) => {
+        self.webhooks.register(data);
+        Response::Ok
+      },
+      ReqData::DeleteWebhook(data) => {
+        if self.webhooks.remove(&data.url) {
+          Response::Ok
+        } else {
+          Response::Fail
+        }
+      },
+      ReqData::ReadSlowLog(data) => Response::SlowLog(ResSlowLog {
+        ops: self.slow_log.read(data.limit),
+      }),
+      ReqData::ReadWebhooks => Response::Webhooks(ResWebhooks {
+        webhooks: self.webhooks.list(),
+      }),
+      ReqData::WriteCreateContext => {
+        use dashmap::mapref::entry::Entry;
+        let was_new = match self.subgraphs_map.entry(req.subgraph.clone()) {
+          Entry::Occupied(_) => false,
+          Entry::Vacant(v) => {
+            v.insert(self.new_graph_processor(
+              &req.subgraph,
+              AugGraph::new(self.settings.clone()),
+            ));
+            true
+          },
+        };
+        if was_new {
+          self.replicate(&req.subgraph, ReplicationRecord::CreateContext);
+          self.seed_context_from_aggregate(&req.subgraph).await;
+        }
+        Response::Ok
+      },
+      ReqData::WriteForkContext(data) => {
+        self.process_fork_context(&data.source, &data.destination).await
+      },
+      ReqData::WriteMergeContext(data) => {
+        self.process_merge_context(&data).await
+      },
+      ReqData::ReadContexts => self.process_read_contexts(&req.token),
+      ReqData::WriteDeleteContext => self.delete_context(&req.subgraph),
+      ReqData::WriteDeleteEdge(data) => {
+        self
+          .process_write_edge(
+            &req.subgraph,
+            &OpWriteEdge {
+              src:        data.src,
+              dst:        data.dst,
+              amount:     0.0,
+              magnitude:  data.index as u32,
+              edge_kind:  EdgeKind::Vote,
+              provenance: None,
+            },
+          )
+          .await
+      },
+      ReqData::WriteDeleteNode(data) => {
+        let senders = [
+          self.get_tx_channel(&req.subgraph),
+          self.get_tx_channel(&String::new()),
+        ];
+        self.try_send_op_all(&senders, AugGraphOp::DeleteNode(data.node))
+      },
+      ReqData::WriteNode(data) => self.process_write_node(&req.subgraph, data),
+      ReqData::WriteReset => {
+        self.clear_contexts();
+        Response::Ok
+      },
+      ReqData::SetMaintenanceMode(data) => {
+        self.maintenance.store(data.read_only, Ordering::SeqCst);
+        log_info!("Maintenance mode: {}", data.read_only);
+        Response::Ok
+      },
+      ReqData::Promote => match &self.replication {
+        Some(replication) if replication.promote() => Response::Ok,
+        _ => {
+          log_warning!("Not a replica, nothing to promote");
+          Response::Fail
+        },
+      },
+      ReqData::WriteScoreClusters(data) => {
+        self
+          .send_op(&req.subgraph, AugGraphOp::WriteScoreClusters(data))
+          .await
+      },
+      ReqData::WriteAliasNode(data) => {
+        //  Users are present in every context, so rename in all of them.
+        self.insert_subgraph_if_does_not_exist(&String::new());
+        let senders: Vec<FanoutSender> = self
+          .subgraphs_map
+          .iter()
+          .map(|r| r.value().op_sender.clone())
+          .collect();
+        self.try_send_op_all(&senders, AugGraphOp::AliasNode(data))
+      },
+      ReqData::WriteGcOrphans(data) => {
+        self.process_gc_orphans(&req.subgraph, &data).await
+      },
+      ReqData::ReadQuota => self.process_read_quota(&req.subgraph),
+      ReqData::ReadContextSnapshot => {
+        match self.snapshot_context(&req.subgraph).await {
+          Some(snapshot) => Response::ContextSnapshot(snapshot),
+          None => {
+            log_warning!("Subgraph not found for name: {:?}", req.subgraph);
+            Response::Fail
+          },
+        }
+      },
+      ReqData::WriteContextSnapshot(snapshot) => {
+        self.restore_context(&req.subgraph, snapshot);
+        Response::Ok
+      },
+      ReqData::MoveContext(_) => {
+        log_warning!("Contexts can only be moved by a router");
+        Response::NotImplemented
+      },
+      ReqData::ReadScoresBulk(data) => {
+        self.process_read_scores_bulk(&req.subgraph, data).await
+      },
+      ReqData::ExportScores(data) => {
+        self.export_scores(&req.subgraph, data).await
+      },
+      ReqData::Sync(stamp) => {
+        //  Internal syncs wait for stamps of the same counter, so it is moved
+        //  past the stamp of the client, which then waits for a new one.
+        self.internal_stamp.fetch_max(stamp, Ordering::SeqCst);
+        self.sync_future(self.next_stamp()).await;
+        Response::Ok
+      },
+      data => {
+        log_error!("Not a processor request: {:?}", data);
+        Response::Fail
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::settings::*;
+  use crate::state_manager::test_utils::*;
+
+  #[tokio::test]
+  async fn acl_rejects_unauthorized_writes() {
+    let proc = MultiGraphProcessor::new(Settings {
+      acl: "writer:rw:X;reader:ro:X".parse().unwrap(),
+      ..Settings::default()
+    });
+    let write = |token: &str, subgraph: &str| Request {
+      token: token.into(),
+      ..write_edge_req(subgraph, "U1", "U2", 1.0)
+    };
+
+    let response = proc.process_request(&write("writer", "X")).await;
+    assert!(matches!(response, Response::Ok));
+
+    let response = proc.process_request(&write("reader", "X")).await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::Forbidden(ref ctx)) if ctx == "X"
+    ));
+
+    let response = proc.process_request(&write("writer", "Y")).await;
+    assert!(matches!(response, Response::Error(ServiceError::Forbidden(_))));
+
+    let response = proc.process_request(&write("", "X")).await;
+    assert!(matches!(response, Response::Error(ServiceError::Unauthorized)));
+
+    let response = proc
+      .process_request(&Request {
+        subgraph: "X".into(),
+        token:    "reader".into(),
+        data:     ReqData::ReadEdges,
+      })
+      .await;
+    assert!(matches!(response, Response::Edges(_)));
+  }
+
+  #[tokio::test]
+  async fn idempotent_retry_is_not_applied_again() {
+    let proc = default_processor();
+    let write = |key: Option<&str>, amount: Weight| {
+      let data = write_edge_req("", "U1", "U2", amount).data;
+      Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     match key {
+          Some(key) => ReqData::Idempotent(OpIdempotent {
+            key:  key.into(),
+            data: Box::new(data),
+          }),
+          None => data,
+        },
+      }
+    };
+    let weight = || async {
+      sync(&proc).await;
+      let edges = edges_from_response(
+        proc
+          .process_request(&Request {
+            subgraph: String::new(),
+            token:    String::new(),
+            data:     ReqData::ReadEdges,
+          })
+          .await,
+      );
+      edges[0].2
+    };
+
+    let response = proc.process_request(&write(Some("k1"), 1.0)).await;
+    assert!(matches!(response, Response::Ok));
+    let response = proc.process_request(&write(None, 2.0)).await;
+    assert!(matches!(response, Response::Ok));
+    assert_eq!(weight().await, 2.0);
+
+    //  A late retry of the first write does not overwrite the second one.
+    let response = proc.process_request(&write(Some("k1"), 1.0)).await;
+    assert!(matches!(response, Response::Ok));
+    assert_eq!(weight().await, 2.0);
+
+    let response = proc.process_request(&write(Some(""), 1.0)).await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::InvalidWrite(
+        InvalidWrite::InvalidIdempotent
+      ))
+    ));
+  }
+
+  #[tokio::test]
+  async fn maintenance_mode_rejects_writes() {
+    let proc = default_processor();
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token: String::new(),
+      data,
+    };
+    let set_mode = |read_only: bool| {
+      request(ReqData::SetMaintenanceMode(OpSetMaintenanceMode {
+        read_only,
+      }))
+    };
+    let write = || write_edge_req("", "U1", "U2", 1.0);
+
+    let response = proc.process_request(&set_mode(true)).await;
+    assert!(matches!(response, Response::Ok));
+    let response = proc.process_request(&write()).await;
+    assert!(matches!(response, Response::Error(ServiceError::Maintenance)));
+    match proc.process_request(&request(ReqData::GetStats)).await {
+      Response::Stats(stats) => assert!(stats.maintenance),
+      other => panic!("expected stats, got {:?}", other),
+    }
+    let response = proc.process_request(&request(ReqData::ReadEdges)).await;
+    assert!(edges_from_response(response).is_empty());
+
+    let response = proc.process_request(&set_mode(false)).await;
+    assert!(matches!(response, Response::Ok));
+    let response = proc.process_request(&write()).await;
+    assert!(matches!(response, Response::Ok));
+  }
+
+  #[tokio::test]
+  async fn slow_log_keeps_slow_requests() {
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 100_000,
+      slow_op_threshold: 1000,
+      slow_op_thresholds: vec![("ReadScores".into(), 1)],
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token: String::new(),
+      data,
+    };
+    for (src, dst) in [("U1", "U2"), ("U2", "U3")] {
+      let _ = proc.process_request(&write_edge_req("", src, dst, 1.0)).await;
+    }
+    sync(&proc).await;
+
+    //  The first read calculates the ego, which takes well over 1 ms.
+    let read = request(ReqData::ReadScores(OpReadScores {
+      ego:           "U1".into(),
+      score_options: FilterOptions::default(),
+    }));
+    let _ = proc.process_request(&read).await;
+
+    let read_log = request(ReqData::ReadSlowLog(OpReadSlowLog {
+      limit: 0,
+    }));
+    let ops = match proc.process_request(&read_log).await {
+      Response::SlowLog(res) => res.ops,
+      other => panic!("expected slow log, got {:?}", other),
+    };
+    //  Writes are under their threshold.
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].opcode, "ReadScores");
+    assert_eq!(ops[0].egos_calculated, 1);
+    assert_eq!(ops[0].walks, 100_000);
+    assert!(ops[0].calculate_us > 0);
+    assert!(ops[0].request.contains("U1"));
+  }
+}
@@ -0,0 +1,816 @@
+use crate::aug_graph::*;
+use crate::data::*;
+use crate::history::unix_time_secs;
+use crate::utils::log::*;
+use crate::webhooks::send_digest;
+
+use parking_lot::RwLock;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::MultiGraphProcessor;
+
+const CLUSTER_WORKER_INTERVAL_MSEC: u64 = 100;
+
+impl MultiGraphProcessor {
+  /// Calculates the ego again, or every ego with walks if none is given,
+  /// and waits until the new walks are published.
+  pub(crate) async fn process_recalculate(
+    &self,
+    subgraph_name: &SubgraphName,
+    ego: Option<&NodeName>,
+  ) -> Response {
+    self.reload_if_evicted(subgraph_name).await;
+    if !self.subgraphs_map.contains_key(subgraph_name) {
+      log_warning!("Context not found: {:?}", subgraph_name);
+      return Response::Fail;
+    }
+
+    let mut egos = 0;
+    let known = self.process_read(subgraph_name, |aug_graph| {
+      egos = aug_graph.mr.get_personal_hits().len();
+      match ego {
+        Some(ego) if aug_graph.nodes.get_by_name(ego).is_none() => {
+          Response::Fail
+        },
+        _ => Response::Ok,
+      }
+    });
+    if !matches!(known, Response::Ok) {
+      log_error!("Node not found: {:?}", ego);
+      return Response::Fail;
+    }
+
+    let (op, egos) = match ego {
+      Some(ego) => (
+        AugGraphOp::WriteCalculate(OpWriteCalculate {
+          ego: ego.clone(),
+        }),
+        1,
+      ),
+      None => (AugGraphOp::RecalculateAll, egos),
+    };
+    let start = Instant::now();
+    match self.send_op(subgraph_name, op).await {
+      Response::Ok => {},
+      other => return other,
+    }
+    let stamp = self.next_stamp();
+    self.sync_future(stamp).await;
+
+    Response::Recalculation(ResRecalculation {
+      egos,
+      elapsed_ms: start.elapsed().as_millis() as u64,
+    })
+  }
+
+  pub(crate) async fn process_gc_orphans(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: &OpWriteGcOrphans,
+  ) -> Response {
+    self.reload_if_evicted(subgraph_name).await;
+    if !self.subgraphs_map.contains_key(subgraph_name) {
+      log_warning!("Context not found: {:?}", subgraph_name);
+      return Response::Fail;
+    }
+
+    let stamp = self.next_stamp();
+    self.sync_future(stamp).await;
+
+    let mut nodes = 0;
+    let orphans = match self.process_read(subgraph_name, |aug_graph| {
+      nodes = aug_graph.nodes.len();
+      Response::NodeList(ResNodeList {
+        nodes: aug_graph
+          .find_orphans()
+          .into_iter()
+          .map(|name| (name,))
+          .collect(),
+      })
+    }) {
+      Response::NodeList(ResNodeList { nodes }) => {
+        nodes.into_iter().map(|(name,)| name).collect::<Vec<_>>()
+      },
+      _ => vec![],
+    };
+
+    let result = ResGcOrphans {
+      orphans: orphans.len(),
+      nodes,
+    };
+    if data.dry_run || orphans.is_empty() {
+      return Response::GcOrphans(result);
+    }
+
+    //  Orphans are checked again when the op is applied, in case edges were
+    //  written to them in the meantime.
+    match self.send_op(subgraph_name, AugGraphOp::RemoveOrphans(orphans)).await
+    {
+      Response::Ok => Response::GcOrphans(result),
+      other => other,
+    }
+  }
+
+  /// One warming pass: in every context with no queued writes, recalculates
+  /// the walks of the hottest egos that have none and precomputes their cluster
+  /// bounds. Returns the number of egos recalculated.
+  pub async fn warm_hot_egos(&self) -> usize {
+    let mut limit = self.settings.warm_egos;
+    if self.settings.walks_cache_size > 0 {
+      //  Warming more egos than the walks cache holds would evict them again.
+      limit = limit.min(self.settings.walks_cache_size);
+    }
+
+    let names: Vec<SubgraphName> =
+      self.subgraphs_map.iter().map(|r| r.key().clone()).collect();
+
+    let mut num_calculated = 0;
+    for name in names {
+      let hot = match self.subgraphs_map.get(&name) {
+        Some(entry) => {
+          if entry.op_sender.unpublished() > 0 {
+            continue;
+          }
+          let hot = entry.ego_heat.hottest(limit);
+          entry.ego_heat.decay();
+          hot
+        },
+        None => continue,
+      };
+      if hot.is_empty() {
+        continue;
+      }
+
+      let cold: Vec<NodeName> = {
+        let arc = match self.subgraphs_map.get(&name) {
+          Some(entry) => entry.shared.load_full(),
+          None => continue,
+        };
+        let guard = arc.read();
+        hot
+          .iter()
+          .filter(|ego| match guard.nodes.get_by_name(ego) {
+            Some(info) => {
+              !guard.mr.get_personal_hits().contains_key(&info.id)
+            },
+            None => false,
+          })
+          .cloned()
+          .collect()
+      };
+
+      for ego in &cold {
+        let op = AugGraphOp::WriteCalculate(OpWriteCalculate {
+          ego: ego.clone(),
+        });
+        if !matches!(self.send_op(&name, op).await, Response::Ok) {
+          break;
+        }
+        self.touch_ego_in_tracker(&name, ego).await;
+        num_calculated += 1;
+      }
+      if !cold.is_empty() {
+        let stamp = self.next_stamp();
+        self.sync_future(stamp).await;
+      }
+
+      let _ = self
+        .dispatch_read(&name, move |aug_graph| {
+          for ego in &hot {
+            if let Some(info) = aug_graph.nodes.get_by_name(ego) {
+              if aug_graph.mr.get_personal_hits().contains_key(&info.id) {
+                aug_graph.warm_score_clusters(info.id);
+              }
+            }
+          }
+          Response::Ok
+        })
+        .await;
+    }
+
+    if num_calculated > 0 {
+      log_verbose!("Warmed {} egos", num_calculated);
+    }
+    num_calculated
+  }
+
+  /// Factor edge weights are multiplied by on each decay pass, so that they
+  /// halve every `decay_half_life` seconds.
+  fn decay_factor(&self) -> f64 {
+    let interval = self.settings.decay_interval.max(1) as f64;
+    0.5f64.powf(interval / self.settings.decay_half_life as f64)
+  }
+
+  /// One edge decay pass: queues the decay of all edges in every loaded
+  /// context. Contexts with a full write queue are skipped until the next
+  /// pass. Returns the number of contexts queued.
+  pub async fn decay_edges(&self) -> usize {
+    //  Replicas get the decay from the primary.
+    if self.is_replica() {
+      return 0;
+    }
+    let factor = self.decay_factor();
+    let names: Vec<SubgraphName> =
+      self.subgraphs_map.iter().map(|r| r.key().clone()).collect();
+
+    let mut num_decayed = 0;
+    for name in &names {
+      match self.send_op(name, AugGraphOp::DecayEdges(factor)).await {
+        Response::Ok => num_decayed += 1,
+        _ => log_warning!("Edge decay skipped for context {:?}", name),
+      }
+    }
+    log_verbose!(
+      "Edge decay by {} queued for {} of {} contexts",
+      factor,
+      num_decayed,
+      names.len()
+    );
+    num_decayed
+  }
+
+  /// One poll archiving pass: queues the archiving of expired polls in every
+  /// loaded context that has some. Returns the number of contexts queued.
+  pub async fn archive_polls(&self) -> usize {
+    //  Replicas get the archiving from the primary.
+    if self.is_replica() {
+      return 0;
+    }
+    let now = unix_time_secs();
+    let names: Vec<SubgraphName> = self
+      .subgraphs_map
+      .iter()
+      .filter(|r| r.value().shared.load().read().has_expired_polls(now))
+      .map(|r| r.key().clone())
+      .collect();
+
+    let mut num_archived = 0;
+    for name in &names {
+      match self.send_op(name, AugGraphOp::ArchivePolls(now)).await {
+        Response::Ok => num_archived += 1,
+        _ => log_warning!("Poll archiving skipped for context {:?}", name),
+      }
+    }
+    num_archived
+  }
+
+  /// Recalculates the egos of every loaded context that needs it, see
+  /// `needs_rerank`, or of every one with `force`. Contexts are done one at
+  /// a time, each waiting until its new walks are published. Returns the
+  /// contexts recalculated.
+  pub async fn rerank(
+    &self,
+    force: bool,
+  ) -> Vec<SubgraphName> {
+    let names: Vec<SubgraphName> = self
+      .subgraphs_map
+      .iter()
+      .filter(|r| force || r.value().shared.load_full().read().needs_rerank())
+      .map(|r| r.key().clone())
+      .collect();
+
+    let mut reranked = vec![];
+    for name in names {
+      match self.process_recalculate(&name, None).await {
+        Response::Recalculation(res) => {
+          log_verbose!(
+            "Reranked {} egos of context {:?} in {} ms",
+            res.egos,
+            name,
+            res.elapsed_ms
+          );
+          reranked.push(name);
+        },
+        _ => log_warning!("Rerank skipped for context {:?}", name),
+      }
+    }
+    reranked
+  }
+
+  /// Records current scores of all pairs with history in every context.
+  /// Returns the number of pairs sampled.
+  pub async fn sample_score_history(&self) -> usize {
+    let graphs: Vec<Arc<RwLock<AugGraph>>> = self
+      .subgraphs_map
+      .iter()
+      .map(|r| r.value().shared.load_full())
+      .collect();
+
+    let mut total = 0;
+    for graph in graphs {
+      match tokio::task::spawn_blocking(move || {
+        graph.read().sample_score_history()
+      })
+      .await
+      {
+        Ok(n) => total += n,
+        Err(e) => log_error!("Score history sampling failed: {}", e),
+      }
+    }
+    total
+  }
+
+  /// Stale cluster bounds queued in the published copies of all contexts.
+  pub fn cluster_queue_depth(&self) -> usize {
+    self
+      .subgraphs_map
+      .iter()
+      .map(|r| r.value().shared.load().read().pending_clusters_len())
+      .sum()
+  }
+
+  /// Recalculates queued stale cluster bounds of every context off the async
+  /// runtime. Returns the number recalculated.
+  pub async fn process_pending_clusters(&self) -> usize {
+    let graphs: Vec<Arc<RwLock<AugGraph>>> = self
+      .subgraphs_map
+      .iter()
+      .map(|r| r.value().shared.load_full())
+      .collect();
+
+    let mut total = 0;
+    for graph in graphs {
+      if graph.read().pending_clusters_len() == 0 {
+        continue;
+      }
+      match tokio::task::spawn_blocking(move || {
+        graph.read().process_pending_clusters()
+      })
+      .await
+      {
+        Ok(n) => total += n,
+        Err(e) => log_error!("Cluster recalculation failed: {}", e),
+      }
+    }
+    total
+  }
+
+  /// Runs the background clustering worker until cancelled.
+  pub async fn run_cluster_worker(
+    self: Arc<Self>,
+    running: CancellationToken,
+  ) {
+    let period = Duration::from_millis(CLUSTER_WORKER_INTERVAL_MSEC);
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+      tokio::select! {
+        _ = running.cancelled() => break,
+        _ = interval.tick() => {
+          self.process_pending_clusters().await;
+        }
+      }
+    }
+  }
+
+  /// Runs warming passes every `warm_interval` seconds until cancelled.
+  pub async fn run_warming(
+    self: Arc<Self>,
+    running: CancellationToken,
+  ) {
+    let period = Duration::from_secs(self.settings.warm_interval.max(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+      tokio::select! {
+        _ = running.cancelled() => break,
+        _ = interval.tick() => {
+          self.warm_hot_egos().await;
+        }
+      }
+    }
+  }
+
+  /// Runs edge decay passes every `decay_interval` seconds until cancelled.
+  pub async fn run_decay(
+    self: Arc<Self>,
+    running: CancellationToken,
+  ) {
+    let period = Duration::from_secs(self.settings.decay_interval.max(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    //  The first tick completes immediately; skip it so that restarts do not
+    //  decay edges right away.
+    interval.tick().await;
+    loop {
+      tokio::select! {
+        _ = running.cancelled() => break,
+        _ = interval.tick() => {
+          self.decay_edges().await;
+        }
+      }
+    }
+  }
+
+  /// Runs poll archiving passes every `poll_archive_interval` seconds until
+  /// cancelled.
+  pub async fn run_poll_archiving(
+    self: Arc<Self>,
+    running: CancellationToken,
+  ) {
+    let period =
+      Duration::from_secs(self.settings.poll_archive_interval.max(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+      tokio::select! {
+        _ = running.cancelled() => break,
+        _ = interval.tick() => {
+          self.archive_polls().await;
+        }
+      }
+    }
+  }
+
+  /// Sends the score changes of every context to the webhooks that watch it.
+  /// Returns the number of digests sent.
+  pub async fn deliver_webhooks(&self) -> usize {
+    let graphs: Vec<(SubgraphName, Arc<RwLock<AugGraph>>)> = self
+      .subgraphs_map
+      .iter()
+      .map(|r| (r.key().clone(), r.value().shared.load_full()))
+      .collect();
+
+    let mut sent = 0;
+    for (context, graph) in graphs {
+      let egos = match self.webhooks.watched_egos(&context) {
+        Some(x) => x,
+        None => continue,
+      };
+      let scores = match tokio::task::spawn_blocking(move || {
+        graph.read_recursive().ego_scores(&egos)
+      })
+      .await
+      {
+        Ok(x) => x,
+        Err(e) => {
+          log_error!("Reading scores for webhooks failed: {}", e);
+          continue;
+        },
+      };
+      for (url, changes) in self.webhooks.digests(&context, &scores) {
+        match send_digest(&url, &context, &changes).await {
+          Ok(()) => sent += 1,
+          Err(e) => log_warning!("Webhook {} failed: {}", url, e),
+        }
+      }
+    }
+    sent
+  }
+
+  /// Sends webhook digests every `webhook_interval` seconds until cancelled.
+  pub async fn run_webhooks(
+    self: Arc<Self>,
+    running: CancellationToken,
+  ) {
+    let period = Duration::from_secs(self.settings.webhook_interval.max(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+      tokio::select! {
+        _ = running.cancelled() => break,
+        _ = interval.tick() => {
+          self.deliver_webhooks().await;
+        }
+      }
+    }
+  }
+
+  /// Runs rerank checks every `rerank_interval` seconds until cancelled.
+  pub async fn run_rerank(
+    self: Arc<Self>,
+    running: CancellationToken,
+  ) {
+    let period = Duration::from_secs(self.settings.rerank_interval.max(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+      tokio::select! {
+        _ = running.cancelled() => break,
+        _ = interval.tick() => {
+          self.rerank(false).await;
+        }
+      }
+    }
+  }
+
+  /// Samples score history every `history_interval` seconds until cancelled.
+  pub async fn run_history_sampling(
+    self: Arc<Self>,
+    running: CancellationToken,
+  ) {
+    let period = Duration::from_secs(self.settings.history_interval.max(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+      tokio::select! {
+        _ = running.cancelled() => break,
+        _ = interval.tick() => {
+          self.sample_score_history().await;
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::settings::*;
+  use crate::state_manager::test_utils::*;
+
+  #[tokio::test]
+  async fn gc_orphans_removes_nodes_without_edges() {
+    let proc = default_processor();
+    let request = |data: ReqData| Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data,
+    };
+    let gc = |dry_run: bool| {
+      request(ReqData::WriteGcOrphans(OpWriteGcOrphans {
+        dry_run,
+      }))
+    };
+
+    for dst in ["B1", "B2"] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+          src: "U1".into(),
+          dst: dst.into(),
+          amount: 1.0,
+          magnitude: 0,
+          edge_kind: EdgeKind::Vote,
+          provenance: None,
+        })))
+        .await;
+    }
+    let _ = proc
+      .process_request(&request(ReqData::WriteDeleteEdge(OpWriteDeleteEdge {
+        src: "U1".into(),
+        dst: "B1".into(),
+        index: -1,
+      })))
+      .await;
+
+    match proc.process_request(&gc(true)).await {
+      Response::GcOrphans(res) => {
+        assert_eq!(res.orphans, 1);
+        assert_eq!(res.nodes, 3);
+      },
+      other => panic!("expected gc result, got {:?}", other),
+    }
+    match proc.process_request(&gc(false)).await {
+      Response::GcOrphans(res) => assert_eq!(res.orphans, 1),
+      other => panic!("expected gc result, got {:?}", other),
+    }
+    sync(&proc).await;
+
+    match proc.process_request(&request(ReqData::ReadNodeList)).await {
+      Response::NodeList(res) => {
+        let mut names: Vec<_> =
+          res.nodes.into_iter().map(|(name,)| name).collect();
+        names.sort();
+        assert_eq!(names, vec!["B2".to_string(), "U1".to_string()]);
+      },
+      other => panic!("expected node list, got {:?}", other),
+    }
+    match proc.process_request(&gc(true)).await {
+      Response::GcOrphans(res) => {
+        assert_eq!(res.orphans, 0);
+        assert_eq!(res.nodes, 2);
+      },
+      other => panic!("expected gc result, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn warming_recalculates_hot_egos() {
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 50,
+      warm_egos: 1,
+      ..Settings::default()
+    });
+    let ctx = String::new();
+    let _ = proc
+      .process_request(&Request {
+        subgraph: ctx.clone(),
+        token:    String::new(),
+        data:     ReqData::WriteEdge(OpWriteEdge {
+          src:        "U1".into(),
+          dst:        "U2".into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        }),
+      })
+      .await;
+    sync(&proc).await;
+    let _ = proc
+      .process_request(&Request {
+        subgraph: ctx.clone(),
+        token:    String::new(),
+        data:     ReqData::ReadScores(OpReadScores {
+          ego:           "U1".into(),
+          score_options: FilterOptions::default(),
+        }),
+      })
+      .await;
+
+    let id = {
+      let arc = proc.subgraphs_map.get(&ctx).unwrap().shared.load_full();
+      let guard = arc.read();
+      guard.nodes.get_by_name("U1").unwrap().id
+    };
+    let has_walks = |proc: &MultiGraphProcessor| {
+      let arc = proc.subgraphs_map.get(&ctx).unwrap().shared.load_full();
+      let guard = arc.read();
+      guard.mr.get_personal_hits().contains_key(&id)
+    };
+    assert!(has_walks(&proc));
+
+    let _ = proc.send_op(&ctx, AugGraphOp::ClearEgo(id)).await;
+    proc.sync_future(proc.next_stamp()).await;
+    assert!(!has_walks(&proc));
+
+    assert_eq!(proc.warm_hot_egos().await, 1);
+    assert!(has_walks(&proc));
+    assert_eq!(proc.warm_hot_egos().await, 0);
+  }
+
+  #[tokio::test]
+  async fn recalculate_ego_and_all() {
+    let proc = default_processor();
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    for (src, dst) in [("U1", "U2"), ("U2", "U3")] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+          src:        src.into(),
+          dst:        dst.into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        })))
+        .await;
+    }
+    sync(&proc).await;
+    for ego in ["U1", "U2"] {
+      let _ = proc
+        .process_request(&request(ReqData::ReadScores(OpReadScores {
+          ego:           ego.into(),
+          score_options: FilterOptions::default(),
+        })))
+        .await;
+    }
+
+    let response = proc
+      .process_request(&request(ReqData::RecalculateEgo(OpRecalculateEgo {
+        ego: "U1".into(),
+      })))
+      .await;
+    assert!(matches!(
+      response,
+      Response::Recalculation(ResRecalculation { egos: 1, .. })
+    ));
+    let response = proc.process_request(&request(ReqData::RecalculateAll)).await;
+    assert!(matches!(
+      response,
+      Response::Recalculation(ResRecalculation { egos: 2, .. })
+    ));
+    let response = proc
+      .process_request(&request(ReqData::RecalculateEgo(OpRecalculateEgo {
+        ego: "U9".into(),
+      })))
+      .await;
+    assert!(matches!(response, Response::Fail));
+
+    let scores = match proc
+      .process_request(&request(ReqData::ReadNodeScore(OpReadNodeScore {
+        ego:    "U1".into(),
+        target: "U2".into(),
+      })))
+      .await
+    {
+      Response::Scores(res) => res.scores,
+      other => panic!("expected scores, got {:?}", other),
+    };
+    assert!(scores[0].score > 0.0);
+  }
+
+  #[tokio::test]
+  async fn rerank_after_many_edges_changed() {
+    let proc = MultiGraphProcessor::new(Settings {
+      rerank_threshold: 0.5,
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    let write = |src: &str, dst: &str, weight: f64| {
+      request(ReqData::WriteEdge(OpWriteEdge {
+        src:        src.into(),
+        dst:        dst.into(),
+        amount:     weight,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }))
+    };
+    for (src, dst) in [("U1", "U2"), ("U2", "U3"), ("U3", "U4")] {
+      let _ = proc.process_request(&write(src, dst, 1.0)).await;
+    }
+    sync(&proc).await;
+    let _ = proc
+      .process_request(&request(ReqData::ReadScores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions::default(),
+      })))
+      .await;
+    assert!(proc.rerank(false).await.is_empty());
+
+    for (src, dst) in [("U1", "U2"), ("U2", "U3")] {
+      let _ = proc.process_request(&write(src, dst, 2.0)).await;
+    }
+    sync(&proc).await;
+    assert_eq!(proc.rerank(false).await, vec![String::new()]);
+    assert!(proc.rerank(false).await.is_empty());
+    assert_eq!(proc.rerank(true).await, vec![String::new()]);
+  }
+
+  #[tokio::test]
+  async fn webhooks_receive_score_changes() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut request = vec![];
+      let mut buf = [0u8; 4096];
+      while !request.ends_with(b"]}") {
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(n > 0);
+        request.extend_from_slice(&buf[..n]);
+      }
+      stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+      String::from_utf8_lossy(&request).to_string()
+    });
+
+    let proc = default_processor();
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    let write = |dst: &str| {
+      request(ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        dst.into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }))
+    };
+    let response = proc
+      .process_request(&request(ReqData::WriteWebhook(OpWriteWebhook {
+        url:        format!("http://127.0.0.1:{}/scores", port),
+        context:    None,
+        egos:       vec!["U1".into()],
+        min_change: 0.01,
+      })))
+      .await;
+    assert!(matches!(response, Response::Ok));
+
+    let _ = proc.process_request(&write("B1")).await;
+    sync(&proc).await;
+    let _ = proc
+      .process_request(&request(ReqData::ReadScores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions::default(),
+      })))
+      .await;
+    //  The first scores are the baseline.
+    assert_eq!(proc.deliver_webhooks().await, 0);
+
+    let _ = proc.process_request(&write("B2")).await;
+    proc.sync_future(proc.next_stamp()).await;
+    assert_eq!(proc.deliver_webhooks().await, 1);
+    let digest = server.await.unwrap();
+    assert!(digest.starts_with("POST /scores HTTP/1.1\r\n"));
+    assert!(digest.contains("\"node\":\"B2\""));
+  }
+}
@@ -0,0 +1,251 @@
+use crate::data::*;
+use crate::file_import::ImportProgress;
+use crate::idempotency::IdempotencyWindow;
+use crate::settings::*;
+use crate::slow_log::SlowLog;
+use crate::utils::log::*;
+
+use dashmap::DashMap;
+use tokio::sync::mpsc;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cold_storage::ColdStorage;
+use crate::processor_stats::ProcessorStats;
+use crate::publish_barrier::PublishBarrier;
+use crate::read_pool::ReadPool;
+use crate::replication::Replication;
+use crate::walk_budget::WalkBudgets;
+use crate::webhooks::Webhooks;
+
+mod contexts;
+mod dispatch;
+mod maintenance;
+mod prepare;
+mod quota;
+mod reads;
+#[cfg(test)]
+pub(crate) mod test_utils;
+mod validation;
+mod writer;
+mod writes;
+
+pub use reads::{ReadPin, ScorePages};
+pub use writer::*;
+
+pub struct MultiGraphProcessor {
+  pub subgraphs_map:     DashMap<SubgraphName, GraphProcessor>,
+  settings:              Settings,
+  loading:               AtomicBool,
+  internal_stamp:        AtomicU64,
+  publish_notify:        Arc<tokio::sync::Notify>,
+  pub stats:             Option<Arc<ProcessorStats>>,
+  read_pool:             ReadPool,
+  cold_storage:          Option<ColdStorage>,
+  /// Bytes saved by compressing responses since startup.
+  pub compression_saved: AtomicU64,
+  replication:           Option<Arc<Replication>>,
+  webhooks:              Webhooks,
+  import_progress:       Arc<ImportProgress>,
+  idempotency:           IdempotencyWindow,
+  /// Writes are rejected, see `SetMaintenanceMode`.
+  maintenance:           AtomicBool,
+  slow_log:              SlowLog,
+  walk_budgets:          WalkBudgets,
+  /// Held while the parts of a `WriteMulti` are sent, see
+  /// `process_write_multi`. Counts the requests, to number their parts.
+  multi_write_order:     parking_lot::Mutex<u64>,
+  /// Barriers of the parts of `WriteMulti` requests replicated from the
+  /// primary, until their outcome is replicated, see `PublishGate`.
+  replicated_parts:      DashMap<u64, Arc<PublishBarrier>>,
+}
+
+fn new_replication(settings: &Settings) -> Option<Arc<Replication>> {
+  if settings.replication_port == 0 && settings.replicate_from.is_empty() {
+    return None;
+  }
+  Some(Arc::new(Replication::new(settings)))
+}
+
+fn new_idempotency(settings: &Settings) -> IdempotencyWindow {
+  IdempotencyWindow::new(Duration::from_secs(settings.idempotency_window))
+}
+
+fn new_cold_storage(settings: &Settings) -> Option<ColdStorage> {
+  if settings.cold_storage_dir.is_empty() {
+    return None;
+  }
+  match ColdStorage::new(settings.cold_storage_dir.clone().into()) {
+    Ok(x) => Some(x),
+    Err(e) => {
+      log_error!("Cold storage disabled: {}", e);
+      None
+    },
+  }
+}
+
+impl MultiGraphProcessor {
+  pub fn new(settings: Settings) -> Self {
+    Self::new_with_stats(settings, None)
+  }
+
+  pub fn new_with_stats(
+    settings: Settings,
+    stats: Option<Arc<ProcessorStats>>,
+  ) -> Self {
+    let read_pool =
+      ReadPool::new(settings.read_workers, &settings.pinned_read_contexts);
+    let cold_storage = new_cold_storage(&settings);
+    let replication = new_replication(&settings);
+    let idempotency = new_idempotency(&settings);
+    let slow_log = SlowLog::new(settings.slow_log_size);
+    let mgp = MultiGraphProcessor {
+      subgraphs_map:     DashMap::new(),
+      settings,
+      loading:           AtomicBool::new(false),
+      internal_stamp:    AtomicU64::new(0),
+      publish_notify:    Arc::new(tokio::sync::Notify::new()),
+      stats,
+      read_pool,
+      cold_storage,
+      compression_saved: AtomicU64::new(0),
+      replication,
+      webhooks:          Webhooks::new(),
+      import_progress:   Arc::new(ImportProgress::default()),
+      idempotency,
+      maintenance:       AtomicBool::new(false),
+      slow_log,
+      walk_budgets:      WalkBudgets::new(),
+      multi_write_order: parking_lot::Mutex::new(0),
+      replicated_parts:  DashMap::new(),
+    };
+    mgp.insert_subgraph_if_does_not_exist(&String::new());
+    mgp
+  }
+
+  pub fn replication(&self) -> Option<&Arc<Replication>> {
+    self.replication.as_ref()
+  }
+
+  fn is_replica(&self) -> bool {
+    self
+      .replication
+      .as_ref()
+      .is_some_and(|replication| replication.is_replica())
+  }
+
+  /// Appends a change that is not an op to the replication log.
+  fn replicate(
+    &self,
+    subgraph: &SubgraphName,
+    record: ReplicationRecord,
+  ) {
+    if let Some(replication) = &self.replication {
+      replication.append(subgraph, record, || {});
+    }
+  }
+
+  fn next_stamp(&self) -> u64 {
+    self.internal_stamp.fetch_add(1, Ordering::SeqCst) + 1
+  }
+
+  pub fn get_tx_channel(
+    &self,
+    subgraph_name: &SubgraphName,
+  ) -> FanoutSender {
+    self.insert_subgraph_if_does_not_exist(subgraph_name)
+  }
+
+  async fn send_op(
+    &self,
+    subgraph_name: &SubgraphName,
+    op: AugGraphOp,
+  ) -> Response {
+    log_trace!();
+
+    let sender = self.get_tx_channel(subgraph_name);
+    self.try_send_op_all(&[sender], op)
+  }
+
+  /// Sends the op to every given subgraph, or to none of them if any write queue is full.
+  fn try_send_op_all(
+    &self,
+    senders: &[FanoutSender],
+    op: AugGraphOp,
+  ) -> Response {
+    self.try_send_ops(senders.iter().map(|sender| (sender, op.clone())))
+  }
+
+  /// Sends each op through its sender, or none of them if any write queue is
+  /// full: slots are reserved in every queue before the first op is sent.
+  fn try_send_ops<'a>(
+    &self,
+    ops: impl IntoIterator<Item = (&'a FanoutSender, AugGraphOp)>,
+  ) -> Response {
+    let mut permits = vec![];
+    for (sender, op) in ops {
+      match sender.try_reserve() {
+        Ok(permit) => permits.push((permit, op)),
+        Err(mpsc::error::TrySendError::Full(_)) => {
+          log_warning!("Write queue is full, rejecting op: {:?}", op);
+          return Response::Busy;
+        },
+        Err(mpsc::error::TrySendError::Closed(_)) => {
+          log_error!("Write queue is closed");
+          return Response::Fail;
+        },
+      }
+    }
+
+    for (permit, op) in permits {
+      if let Some(s) = &self.stats {
+        s.record_enqueue();
+      }
+      permit.send(op);
+    }
+    Response::Ok
+  }
+
+  /// Depth of the fullest subgraph write queue.
+  pub fn max_queue_depth(&self) -> usize {
+    self
+      .subgraphs_map
+      .iter()
+      .map(|r| r.value().op_sender.depth())
+      .max()
+      .unwrap_or(0)
+  }
+
+  pub async fn sync_future(
+    &self,
+    stamp: u64,
+  ) {
+    log_trace!();
+
+    let senders: Vec<FanoutSender> = self
+      .subgraphs_map
+      .iter()
+      .map(|r| r.value().op_sender.clone())
+      .collect();
+
+    for s in &senders {
+      let _ = s.send(AugGraphOp::Stamp(stamp)).await;
+    }
+
+    loop {
+      let notified = self.publish_notify.notified();
+
+      let done = self
+        .subgraphs_map
+        .iter()
+        .all(|r| r.value().shared.load_full().read().stamp >= stamp);
+      if done {
+        break;
+      }
+
+      notified.await;
+    }
+  }
+}
@@ -0,0 +1,704 @@
+use crate::cache_sizing::{auto_cache_sizes, needs_resize};
+use crate::data::*;
+use crate::node_registry::*;
+use crate::slow_log;
+use crate::utils::log::*;
+
+use meritrank_core::NodeId;
+use tracing::Instrument;
+
+use std::collections::BTreeSet;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use super::MultiGraphProcessor;
+
+impl MultiGraphProcessor {
+  /// Bookkeeping after the ego's walks are calculated for a read.
+  async fn record_ego_read(
+    &self,
+    subgraph_name: &SubgraphName,
+    ego: &NodeName,
+  ) {
+    if self.settings.warm_egos > 0 {
+      if let Some(entry) = self.subgraphs_map.get(subgraph_name) {
+        entry.ego_heat.record(ego);
+      }
+    }
+    self.touch_ego_in_tracker(subgraph_name, ego).await;
+  }
+
+  /// If the ego has no walks in this subgraph, send WriteCalculate and sync so the next read sees scores.
+  /// An ego with kept evicted scores is served from them, so the sync is skipped.
+  fn is_calculated(
+    &self,
+    subgraph: &SubgraphName,
+    ego: &NodeName,
+  ) -> bool {
+    let calculated = self.process_read(subgraph, |aug_graph| {
+      match aug_graph.nodes.get_by_name(ego) {
+        Some(info) if aug_graph.mr.get_personal_hits().contains_key(&info.id) => {
+          Response::Ok
+        },
+        _ => Response::Fail,
+      }
+    });
+    matches!(calculated, Response::Ok)
+  }
+
+  /// Calculates the ego if it has no walks. With a deadline, the calculation
+  /// is given the time left and `Timeout` is returned if it does not finish.
+  /// With a latency, the walks are given at most that long; if they are not
+  /// done by then, reads are served from the walks done so far, see
+  /// `AugGraph::calculate_partial`, and the ego is calculated in full for the
+  /// next reads.
+  pub(crate) async fn ensure_calculated(
+    &self,
+    subgraph: &SubgraphName,
+    ego: &NodeName,
+    deadline: Option<Instant>,
+    latency: Option<Duration>,
+  ) -> Result<(), ServiceError> {
+    if !self.queue_calculate(subgraph, ego, deadline, latency).await? {
+      return Ok(());
+    }
+
+    let synced = self.sync_until(deadline).await;
+    if latency.is_some()
+      && synced
+      && !self.is_calculated(subgraph, ego)
+      && self.has_partial_scores(subgraph, ego)
+    {
+      let _ = self
+        .send_op(
+          subgraph,
+          AugGraphOp::WriteCalculate(OpWriteCalculate {
+            ego: ego.clone(),
+          }),
+        )
+        .await;
+      return Ok(());
+    }
+    if deadline.is_some() && (!synced || !self.is_calculated(subgraph, ego)) {
+      log_warning!("Calculation of {:?} timed out", ego);
+      return Err(ServiceError::Timeout);
+    }
+    Ok(())
+  }
+
+  /// Like `ensure_calculated` without a latency, for several egos: the
+  /// calculations of all of them are queued first, then waited for with a
+  /// single sync.
+  async fn ensure_calculated_all(
+    &self,
+    subgraph: &SubgraphName,
+    egos: &[&NodeName],
+    deadline: Option<Instant>,
+  ) -> Result<(), ServiceError> {
+    let mut queued = vec![];
+    for ego in egos.iter().copied().collect::<BTreeSet<_>>() {
+      if self.queue_calculate(subgraph, ego, deadline, None).await? {
+        queued.push(ego);
+      }
+    }
+    if queued.is_empty() {
+      return Ok(());
+    }
+
+    let synced = self.sync_until(deadline).await;
+    if deadline.is_some()
+      && (!synced || queued.iter().any(|ego| !self.is_calculated(subgraph, ego)))
+    {
+      log_warning!("Calculation of {:?} timed out", queued);
+      return Err(ServiceError::Timeout);
+    }
+    Ok(())
+  }
+
+  /// Queues the calculation of the ego if it has no walks. Returns whether
+  /// the read must wait for it: an ego with kept evicted scores is served
+  /// from them meanwhile.
+  async fn queue_calculate(
+    &self,
+    subgraph: &SubgraphName,
+    ego: &NodeName,
+    deadline: Option<Instant>,
+    latency: Option<Duration>,
+  ) -> Result<bool, ServiceError> {
+    let mut has_evicted_scores = false;
+    let needs_calc = self.process_read(subgraph, |aug_graph| {
+      match aug_graph.nodes.get_by_name(ego) {
+        Some(info) if !aug_graph.mr.get_personal_hits().contains_key(&info.id) => {
+          has_evicted_scores = aug_graph.has_evicted_scores(info.id);
+          Response::Fail
+        },
+        _ => Response::Ok,
+      }
+    });
+    if !matches!(needs_calc, Response::Fail) {
+      slow_log::record_cached();
+      return Ok(false);
+    }
+
+    let budget = deadline
+      .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    if budget.is_some_and(|budget| budget.is_zero()) {
+      return Err(ServiceError::Timeout);
+    }
+    self.walk_budgets.charge(
+      subgraph,
+      self.settings.walk_budget(subgraph),
+      self.settings.num_walks as u64,
+    )?;
+    let op = match (latency, budget) {
+      (Some(latency), _) => {
+        let budget = budget.map_or(latency, |budget| budget.min(latency));
+        AugGraphOp::CalculatePartial(ego.clone(), budget.as_millis() as u64)
+      },
+      (None, Some(budget)) => {
+        AugGraphOp::CalculateWithin(ego.clone(), budget.as_millis() as u64)
+      },
+      (None, None) => AugGraphOp::WriteCalculate(OpWriteCalculate {
+        ego: ego.clone(),
+      }),
+    };
+    let _ = self.send_op(subgraph, op).await;
+    slow_log::record_calculated(self.settings.num_walks);
+    Ok(!has_evicted_scores)
+  }
+
+  /// Waits for the ops queued so far to be published, until the deadline if
+  /// any. Returns whether they were.
+  async fn sync_until(
+    &self,
+    deadline: Option<Instant>,
+  ) -> bool {
+    let stamp = self.next_stamp();
+    match deadline {
+      Some(deadline) => {
+        tokio::time::timeout_at(deadline.into(), self.sync_future(stamp))
+          .await
+          .is_ok()
+      },
+      None => {
+        self.sync_future(stamp).await;
+        true
+      },
+    }
+  }
+
+  fn has_partial_scores(
+    &self,
+    subgraph: &SubgraphName,
+    ego: &NodeName,
+  ) -> bool {
+    let partial = self.process_read(subgraph, |aug_graph| {
+      match aug_graph.nodes.get_by_name(ego) {
+        Some(info) if aug_graph.has_partial_scores(info.id) => Response::Ok,
+        _ => Response::Fail,
+      }
+    });
+    matches!(partial, Response::Ok)
+  }
+
+  /// `ReadScores` by the house ego of the context in place of an ego that
+  /// has no edges there, see `MERITRANK_HOUSE_EGOS`.
+  pub(crate) fn house_ego_request(
+    &self,
+    req: &Request,
+  ) -> Option<Request> {
+    let data = match &req.data {
+      ReqData::ReadScores(x) => x,
+      _ => return None,
+    };
+    let house_ego = self.settings.house_ego(&req.subgraph)?;
+    let no_edges = *house_ego != data.ego
+      && self.read_published(&req.subgraph, |aug_graph| {
+        aug_graph.has_no_edges(&data.ego)
+      })?;
+    no_edges.then(|| Request {
+      subgraph: req.subgraph.clone(),
+      token:    req.token.clone(),
+      data:     ReqData::ReadScores(OpReadScores {
+        ego:           house_ego.clone(),
+        score_options: data.score_options.clone(),
+      }),
+    })
+  }
+
+  /// `ReadScores` by a calculated ego with nearly the same edges in place of
+  /// an ego without walks, see `MERITRANK_SHARED_SCORES_SIMILARITY`.
+  pub(crate) fn shared_scores_request(
+    &self,
+    req: &Request,
+  ) -> Option<Request> {
+    let data = match &req.data {
+      ReqData::ReadScores(x) => x,
+      _ => return None,
+    };
+    let threshold = self.settings.shared_scores_similarity;
+    if threshold <= 0.0 {
+      return None;
+    }
+    let similar = self.read_published(&req.subgraph, |aug_graph| {
+      aug_graph.similar_calculated_ego(&data.ego, threshold)
+    })??;
+    log_verbose!("Serve scores of {:?} to {:?}", similar, data.ego);
+    Some(Request {
+      subgraph: req.subgraph.clone(),
+      token:    req.token.clone(),
+      data:     ReqData::ReadScores(OpReadScores {
+        ego:           similar,
+        score_options: data.score_options.clone(),
+      }),
+    })
+  }
+
+  /// Calculates the egos a read needs, within the time budget of the request.
+  pub(crate) async fn prepare_request(
+    &self,
+    req: &Request,
+  ) -> Result<(), ServiceError> {
+    let deadline = self
+      .settings
+      .timeout_of(req.data.opcode())
+      .map(|timeout| Instant::now() + timeout);
+
+    let egos: Vec<&NodeName> = match &req.data {
+      ReqData::ReadScoresBulk(data) => data.egos.iter().collect(),
+      ReqData::ReadCompareEgos(data) => vec![&data.a, &data.b],
+      _ => vec![],
+    };
+    if !egos.is_empty() {
+      self.ensure_calculated_all(&req.subgraph, &egos, deadline).await?;
+      for ego in egos {
+        self.record_ego_read(&req.subgraph, ego).await;
+      }
+    }
+
+    if let Some(ego) = req.data.read_ego() {
+      let latency = req.data.max_latency();
+      self
+        .ensure_calculated(&req.subgraph, ego, deadline, latency)
+        .instrument(tracing::debug_span!("calculate", ego = ego.as_str()))
+        .await?;
+      // Mutual scores need reverse_score (target's score for ego), so ensure all user nodes are calculated.
+      if let ReqData::ReadMutualScores(_) = &req.data {
+        let list = self.process_read(&req.subgraph, |aug_graph| {
+          Response::NodeList(ResNodeList {
+            nodes: aug_graph
+              .nodes
+              .iter()
+              .map(|info| (info.name.clone(),))
+              .collect(),
+          })
+        });
+        if let Response::NodeList(ResNodeList { nodes }) = list {
+          for (name,) in nodes {
+            if node_kind_from_prefix(&name) == Some(NodeKind::User) && name != *ego {
+              self
+                .ensure_calculated(&req.subgraph, &name, deadline, None)
+                .await?;
+            }
+          }
+        }
+      }
+      // Recommendations compare the ego with the users it trusts most.
+      if let ReqData::ReadRecommendations(_) = &req.data {
+        let list = self.process_read(&req.subgraph, |aug_graph| {
+          Response::NodeList(ResNodeList {
+            nodes: aug_graph
+              .recommendation_candidates(ego)
+              .into_iter()
+              .map(|name| (name,))
+              .collect(),
+          })
+        });
+        if let Response::NodeList(ResNodeList { nodes }) = list {
+          for (name,) in nodes {
+            self
+              .ensure_calculated(&req.subgraph, &name, deadline, None)
+              .await?;
+          }
+        }
+      }
+      self.record_ego_read(&req.subgraph, ego).await;
+    }
+
+    Ok(())
+  }
+
+  /// Records ego usage in the walk tracker and sends ClearEgo for any evicted egos.
+  pub(crate) async fn touch_ego_in_tracker(
+    &self,
+    subgraph_name: &SubgraphName,
+    ego: &NodeName,
+  ) {
+    let (ego_id, num_nodes) = {
+      let arc = match self.subgraphs_map.get(subgraph_name) {
+        Some(entry) => entry.shared.load_full(),
+        None => return,
+      };
+      let guard = arc.read();
+      match guard.nodes.get_by_name(ego) {
+        Some(info) => (info.id, guard.nodes.len()),
+        None => return,
+      }
+    };
+
+    let evicted_ids: Vec<NodeId> = {
+      match self.subgraphs_map.get(subgraph_name) {
+        Some(entry) => {
+          if let Some(ref tracker) = entry.walk_tracker {
+            if self.settings.cache_memory_budget > 0
+              && (tracker.sized_for() == 0
+                || needs_resize(tracker.sized_for(), num_nodes))
+            {
+              let sizes = auto_cache_sizes(num_nodes, &self.settings);
+              tracker.resize(sizes.walk_egos as u64, sizes.nodes);
+            }
+            tracker.touch(ego_id);
+            tracker.drain_evicted()
+          } else {
+            vec![]
+          }
+        },
+        None => vec![],
+      }
+    };
+
+    for evicted_id in evicted_ids {
+      let _ = self
+        .send_op(subgraph_name, AugGraphOp::ClearEgo(evicted_id))
+        .await;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::settings::*;
+  use crate::state_manager::test_utils::*;
+
+  #[tokio::test]
+  async fn request_timeout_aborts_calculation() {
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 100_000,
+      request_timeouts: vec![("ReadScores".into(), 1)],
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: "".into(),
+      token:    String::new(),
+      data,
+    };
+    for i in 1..=10 {
+      let _ = proc
+        .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+          src:        format!("U{}", i),
+          dst:        format!("U{}", i % 10 + 1),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        })))
+        .await;
+    }
+    sync(&proc).await;
+
+    let read_scores = ReqData::ReadScores(OpReadScores {
+      ego:           "U1".into(),
+      score_options: FilterOptions::default(),
+    });
+    assert!(matches!(
+      proc.process_request(&request(read_scores)).await,
+      Response::Error(ServiceError::Timeout)
+    ));
+    assert!(!proc.is_calculated(&String::new(), &"U1".into()));
+
+    //  Requests of other types have no budget.
+    let read_node_score = ReqData::ReadNodeScore(OpReadNodeScore {
+      ego:    "U1".into(),
+      target: "U2".into(),
+    });
+    assert!(matches!(
+      proc.process_request(&request(read_node_score)).await,
+      Response::Scores(_)
+    ));
+  }
+
+  #[tokio::test]
+  async fn normal_write_no_auto_calc() {
+    let proc = default_processor();
+    let _ = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::WriteEdge(OpWriteEdge {
+          src:        "U1".into(),
+          dst:        "U2".into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        }),
+      })
+      .await;
+    sync(&proc).await;
+    let mut scores_resp = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadScores(OpReadScores {
+          ego:           "U1".into(),
+          score_options: FilterOptions::default(),
+        }),
+      })
+      .await;
+    for _ in 0..100 {
+      if let Response::Scores(ResScores { scores }) = &scores_resp {
+        if !scores.is_empty() {
+          return;
+        }
+      }
+      tokio::task::yield_now().await;
+      scores_resp = proc
+        .process_request(&Request {
+          subgraph: String::new(),
+          token:    String::new(),
+          data:     ReqData::ReadScores(OpReadScores {
+            ego:           "U1".into(),
+            score_options: FilterOptions::default(),
+          }),
+        })
+        .await;
+    }
+    match scores_resp {
+      Response::Scores(ResScores { scores }) => assert!(!scores.is_empty(), "expected scores from lazy calc"),
+      other => panic!("expected scores, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn max_latency_returns_approximate_scores() {
+    //  Enough walks that they can't be done within the latency.
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 100_000,
+      ..Settings::default()
+    });
+    for (src, dst) in [("U1", "U2"), ("U2", "U3"), ("U3", "U1")] {
+      let _ = proc
+        .process_request(&Request {
+          subgraph: String::new(),
+          token:    String::new(),
+          data:     ReqData::WriteEdge(OpWriteEdge {
+            src:        src.into(),
+            dst:        dst.into(),
+            amount:     1.0,
+            magnitude:  0,
+            edge_kind:  EdgeKind::Vote,
+            provenance: None,
+          }),
+        })
+        .await;
+    }
+    sync(&proc).await;
+    let read = |max_latency_ms: u64| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::ReadScores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions {
+          max_latency_ms,
+          ..FilterOptions::default()
+        },
+      }),
+    };
+
+    match proc.process_request(&read(30)).await {
+      Response::Scores(ResScores { scores }) => {
+        assert!(!scores.is_empty());
+        assert!(scores.iter().all(|x| x.approximate));
+        assert!(scores.iter().all(|x| x.walks > 0 && x.walks < 100_000));
+      },
+      other => panic!("expected scores, got {:?}", other),
+    }
+
+    //  The ego is calculated in full for the next reads.
+    match proc.process_request(&read(0)).await {
+      Response::Scores(ResScores { scores }) => {
+        assert!(scores.iter().all(|x| !x.approximate));
+        assert!(scores.iter().all(|x| x.walks == 100_000));
+      },
+      other => panic!("expected scores, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn new_ego_gets_scores_of_house_ego() {
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 100,
+      house_egos: vec![("*".into(), "U1".into())],
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    let _ = proc
+      .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        "U2".into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      })))
+      .await;
+    sync(&proc).await;
+
+    let read = |ego: &str| {
+      request(ReqData::ReadScores(OpReadScores {
+        ego:           ego.into(),
+        score_options: FilterOptions::default(),
+      }))
+    };
+    match proc.process_request(&read("U9")).await {
+      Response::Scores(ResScores { scores }) => {
+        assert!(scores.iter().any(|x| x.target == "U2"));
+        assert!(scores.iter().all(|x| x.fallback && x.ego == "U1"));
+      },
+      other => panic!("expected scores, got {:?}", other),
+    }
+    match proc.process_request(&read("U2")).await {
+      Response::Scores(ResScores { scores }) => {
+        assert!(scores.iter().all(|x| !x.fallback));
+      },
+      other => panic!("expected scores, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn similar_egos_share_scores() {
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 100,
+      shared_scores_similarity: 0.9,
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    for (src, dst) in [
+      ("U1", "B1"),
+      ("U1", "B2"),
+      ("U2", "B1"),
+      ("U2", "B2"),
+      ("U3", "B1"),
+      ("U3", "B3"),
+    ] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+          src:        src.into(),
+          dst:        dst.into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        })))
+        .await;
+    }
+    sync(&proc).await;
+
+    let read = |ego: &str| {
+      request(ReqData::ReadScores(OpReadScores {
+        ego:           ego.into(),
+        score_options: FilterOptions::default(),
+      }))
+    };
+    match proc.process_request(&read("U1")).await {
+      Response::Scores(ResScores { scores }) => {
+        assert!(scores.iter().all(|x| !x.shared));
+      },
+      other => panic!("expected scores, got {:?}", other),
+    }
+    match proc.process_request(&read("U2")).await {
+      Response::Scores(ResScores { scores }) => {
+        assert!(scores.iter().any(|x| x.target == "B2"));
+        assert!(scores.iter().all(|x| x.shared && x.ego == "U1"));
+      },
+      other => panic!("expected scores, got {:?}", other),
+    }
+    assert!(!proc.is_calculated(&String::new(), &"U2".into()));
+
+    //  Only one of two neighbors in common is not similar enough.
+    match proc.process_request(&read("U3")).await {
+      Response::Scores(ResScores { scores }) => {
+        assert!(scores.iter().all(|x| !x.shared && x.ego == "U3"));
+      },
+      other => panic!("expected scores, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn walk_budget_rejects_calculations_over_budget() {
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 100,
+      walk_budgets: vec![WalkBudget {
+        context:   "*".into(),
+        max_walks: 100,
+        period:    3600,
+      }],
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    for (src, dst) in [("U1", "U2"), ("U2", "U1")] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+          src:        src.into(),
+          dst:        dst.into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        })))
+        .await;
+    }
+    sync(&proc).await;
+
+    let read = |ego: &str| {
+      request(ReqData::ReadScores(OpReadScores {
+        ego:           ego.into(),
+        score_options: FilterOptions::default(),
+      }))
+    };
+    let response = proc.process_request(&read("U1")).await;
+    assert!(matches!(response, Response::Scores(_)));
+    let response = proc.process_request(&read("U2")).await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::WalkBudgetExceeded(_))
+    ));
+    //  The calculated ego is still served.
+    let response = proc.process_request(&read("U1")).await;
+    assert!(matches!(response, Response::Scores(_)));
+
+    match proc.process_request(&request(ReqData::ReadContexts)).await {
+      Response::Contexts(ResContexts { contexts }) => {
+        assert_eq!(contexts[0].walks, 100);
+      },
+      other => panic!("expected contexts, got {:?}", other),
+    }
+    match proc.process_request(&request(ReqData::GetStats)).await {
+      Response::Stats(stats) => assert_eq!(stats.walk_rejections, 1),
+      other => panic!("expected stats, got {:?}", other),
+    }
+  }
+}
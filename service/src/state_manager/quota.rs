@@ -0,0 +1,262 @@
+use crate::aug_graph::*;
+use crate::data::*;
+use crate::history::unix_time_secs;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::MultiGraphProcessor;
+
+impl MultiGraphProcessor {
+  /// Estimated memory of all loaded contexts, in bytes.
+  fn total_memory(&self) -> usize {
+    self
+      .subgraphs_map
+      .iter()
+      .map(|r| r.value().shared.load_full().read().estimated_memory())
+      .sum()
+  }
+
+  /// Rejects a write that would add nodes or an edge beyond the limits. Checked
+  /// against the last published state, so queued writes are not counted.
+  pub(crate) fn check_quota(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: &OpWriteEdge,
+  ) -> Result<(), ServiceError> {
+    self.check_quota_batch(&[(subgraph_name, data)])
+  }
+
+  /// Like `check_quota`, with the nodes and edges new to a context counted
+  /// over all the writes to it, so that a batch is rejected if it exceeds a
+  /// limit together, even if each write is under it.
+  pub(crate) fn check_quota_batch(
+    &self,
+    writes: &[(&SubgraphName, &OpWriteEdge)],
+  ) -> Result<(), ServiceError> {
+    let settings = &self.settings;
+    if settings.max_context_nodes == 0
+      && settings.max_context_edges == 0
+      && settings.max_memory == 0
+    {
+      return Ok(());
+    }
+
+    let mut contexts: BTreeMap<&SubgraphName, Vec<&OpWriteEdge>> =
+      BTreeMap::new();
+    for (subgraph_name, data) in writes {
+      if data.amount != 0.0 {
+        contexts.entry(*subgraph_name).or_default().push(*data);
+      }
+    }
+
+    let (mut total_nodes, mut total_edges) = (0, 0);
+    let mut grown = None;
+    for (subgraph_name, data) in contexts {
+      let (nodes, edges, new_nodes, new_edges) =
+        self.quota_growth(subgraph_name, &data);
+      let exceeded = |limit: QuotaLimit| {
+        Err(ServiceError::QuotaExceeded(subgraph_name.clone(), limit))
+      };
+      if settings.max_context_nodes > 0
+        && nodes + new_nodes > settings.max_context_nodes
+        && new_nodes > 0
+      {
+        return exceeded(QuotaLimit::Nodes);
+      }
+      if settings.max_context_edges > 0
+        && edges + new_edges > settings.max_context_edges
+        && new_edges > 0
+      {
+        return exceeded(QuotaLimit::Edges);
+      }
+      if new_nodes + new_edges > 0 {
+        total_nodes += new_nodes;
+        total_edges += new_edges;
+        grown = Some(subgraph_name);
+      }
+    }
+
+    match grown {
+      Some(subgraph_name)
+        if settings.max_memory > 0
+          && self.total_memory()
+            + estimate_memory(total_nodes, total_edges, 0)
+            > settings.max_memory =>
+      {
+        Err(ServiceError::QuotaExceeded(
+          subgraph_name.clone(),
+          QuotaLimit::Memory,
+        ))
+      },
+      _ => Ok(()),
+    }
+  }
+
+  /// Nodes and edges of the context, and how many distinct ones the writes
+  /// add to it.
+  fn quota_growth(
+    &self,
+    subgraph_name: &SubgraphName,
+    writes: &[&OpWriteEdge],
+  ) -> (usize, usize, usize, usize) {
+    let mut new_nodes = BTreeSet::new();
+    let mut new_edges = BTreeSet::new();
+    let entry = match self.subgraphs_map.get(subgraph_name) {
+      Some(x) => x,
+      None => {
+        for data in writes {
+          new_nodes.extend([&data.src, &data.dst]);
+          new_edges.insert((&data.src, &data.dst));
+        }
+        return (0, 0, new_nodes.len(), new_edges.len());
+      },
+    };
+    let arc = entry.shared.load_full();
+    let aug_graph = arc.read();
+    let (new_nodes, new_edges) = aug_graph.growth(writes);
+    (aug_graph.nodes.len(), aug_graph.edge_count(), new_nodes, new_edges)
+  }
+
+  /// Checks the new weight of the edge against its weight `period` seconds
+  /// ago, taken from the edge log of the published copy. Only reads the
+  /// published copy, so a write rejected after this check is not counted.
+  pub(crate) fn check_edge_rate(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: &OpWriteEdge,
+  ) -> Result<(), ServiceError> {
+    let limit = match self.settings.edge_rate_limit(subgraph_name) {
+      Some(x) => x,
+      None => return Ok(()),
+    };
+    let since = unix_time_secs().saturating_sub(limit.period);
+    let old_weight = match self.subgraphs_map.get(subgraph_name) {
+      Some(entry) => {
+        let arc = entry.shared.load_full();
+        let aug_graph = arc.read_recursive();
+        aug_graph.edge_weight_at(&data.src, &data.dst, since)
+      },
+      None => 0.0,
+    };
+    if (data.amount - old_weight).abs() > limit.max_delta {
+      return Err(ServiceError::EdgeRateExceeded(subgraph_name.clone()));
+    }
+    Ok(())
+  }
+
+  pub(crate) fn process_read_quota(
+    &self,
+    subgraph_name: &SubgraphName,
+  ) -> Response {
+    let total_memory = self.total_memory();
+    self.process_read(subgraph_name, |aug_graph| {
+      Response::Quota(ResQuota {
+        nodes: aug_graph.nodes.len(),
+        max_nodes: self.settings.max_context_nodes,
+        edges: aug_graph.edge_count(),
+        max_edges: self.settings.max_context_edges,
+        memory: aug_graph.estimated_memory(),
+        total_memory,
+        max_memory: self.settings.max_memory,
+      })
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::settings::*;
+  use crate::state_manager::test_utils::*;
+
+  #[tokio::test]
+  async fn quota_rejects_writes_over_limit() {
+    let proc = MultiGraphProcessor::new(Settings {
+      max_context_nodes: 3,
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: "X".into(),
+      token:    String::new(),
+      data,
+    };
+    let write = |dst: &str| {
+      request(ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        dst.into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }))
+    };
+
+    for dst in ["B1", "B2"] {
+      assert!(matches!(proc.process_request(&write(dst)).await, Response::Ok));
+      sync(&proc).await;
+    }
+    match proc.process_request(&write("B3")).await {
+      Response::Error(ServiceError::QuotaExceeded(ctx, QuotaLimit::Nodes)) => {
+        assert_eq!(ctx, "X")
+      },
+      other => panic!("expected quota error, got {:?}", other),
+    }
+    //  Existing nodes can still get edges.
+    let _ = proc
+      .process_request(&request(ReqData::WriteDeleteEdge(OpWriteDeleteEdge {
+        src:   "U1".into(),
+        dst:   "B1".into(),
+        index: -1,
+      })))
+      .await;
+    sync(&proc).await;
+    assert!(matches!(proc.process_request(&write("B1")).await, Response::Ok));
+    sync(&proc).await;
+
+    match proc.process_request(&request(ReqData::ReadQuota)).await {
+      Response::Quota(res) => {
+        assert_eq!(res.nodes, 3);
+        assert_eq!(res.max_nodes, 3);
+        assert_eq!(res.edges, 2);
+        assert_eq!(res.max_edges, 0);
+        assert!(res.memory > 0);
+        assert!(res.total_memory >= res.memory);
+      },
+      other => panic!("expected quota, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn edge_rate_limit_rejects_fast_changes() {
+    let proc = MultiGraphProcessor::new(Settings {
+      edge_log_size: 100,
+      edge_rate_limits: vec![EdgeRateLimit {
+        context:   "*".into(),
+        max_delta: 1.0,
+        period:    3600,
+      }],
+      ..Settings::default()
+    });
+    let write = |weight: f64| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        "U2".into(),
+        amount:     weight,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    };
+
+    assert!(matches!(proc.process_request(&write(1.0)).await, Response::Ok));
+    sync(&proc).await;
+    //  The edge did not exist an hour ago.
+    assert!(matches!(
+      proc.process_request(&write(1.5)).await,
+      Response::Error(ServiceError::EdgeRateExceeded(_))
+    ));
+    assert!(matches!(proc.process_request(&write(-0.5)).await, Response::Ok));
+  }
+}
@@ -0,0 +1,1051 @@
+use crate::aug_graph::*;
+use crate::data::*;
+use crate::export::{export_path, ScoresWriter};
+use crate::read_pool::ReadJob;
+use crate::utils::files::write_atomic_with;
+use crate::utils::log::*;
+
+use arc_swap::ArcSwap;
+use meritrank_core::NodeId;
+use parking_lot::{ArcRwLockReadGuard, RawRwLock, RwLock};
+use tokio::sync::mpsc;
+
+use std::collections::BTreeSet;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use super::MultiGraphProcessor;
+
+/// Egos whose scores wait for the export writer; bounds the memory of an
+/// export.
+const EXPORT_QUEUE_SIZE: usize = 4;
+
+/// Read access to one copy of a context, see `pin_read`.
+pub type ReadPin = Arc<ArcRwLockReadGuard<RawRwLock, AugGraph>>;
+
+/// How many times `pin_read` tries to lock the published copy before it
+/// gives up with `Busy`.
+const PIN_READ_ATTEMPTS: usize = 100;
+
+/// Scores of a `ReadScoresChunked`, written a page at a time.
+pub struct ScorePages(std::vec::IntoIter<ScoreResult>);
+
+impl ScorePages {
+  pub fn new(scores: Vec<ScoreResult>) -> Self {
+    ScorePages(scores.into_iter())
+  }
+
+  /// Next page of at most `chunk_size` scores, and whether more follow.
+  pub fn read_page(
+    &mut self,
+    chunk_size: u32,
+  ) -> (Vec<ScoreResult>, bool) {
+    let page = self.0.by_ref().take(chunk_size as usize).collect();
+    (page, self.0.len() > 0)
+  }
+}
+
+impl MultiGraphProcessor {
+  pub fn process_read<F>(
+    &self,
+    subgraph_name: &SubgraphName,
+    read_function: F,
+  ) -> Response
+  where
+    F: FnOnce(&AugGraph) -> Response,
+  {
+    log_trace!();
+
+    let arc = match self.subgraphs_map.get(subgraph_name) {
+      Some(subgraph) => {
+        log_verbose!("Found subgraph for name: {:?}", subgraph_name);
+        subgraph.touch();
+        subgraph.shared.load_full()
+      },
+      None => {
+        log_warning!("Subgraph not found for name: {:?}", subgraph_name);
+        return Response::Fail;
+      },
+    };
+
+    let rw_guard = arc.read_recursive();
+    log_trace!(
+      "Successfully accessed AugGraph for subgraph: {:?}",
+      subgraph_name
+    );
+
+    let response = read_function(&rw_guard);
+    log_verbose!("Executed read function for subgraph: {:?}", subgraph_name);
+
+    response
+  }
+
+  /// Like `process_read`, but runs the read on the reader pool so it does not
+  /// block the async runtime. Falls back to running inline if there is no worker.
+  pub async fn dispatch_read<F>(
+    &self,
+    subgraph_name: &SubgraphName,
+    read_function: F,
+  ) -> Response
+  where
+    F: FnOnce(&AugGraph) -> Response + Send + 'static,
+  {
+    log_trace!();
+
+    let shared = match self.subgraphs_map.get(subgraph_name) {
+      Some(subgraph) => {
+        subgraph.touch();
+        Arc::clone(&subgraph.shared)
+      },
+      None => {
+        log_warning!("Subgraph not found for name: {:?}", subgraph_name);
+        return Response::Fail;
+      },
+    };
+
+    self
+      .queue_read(subgraph_name, shared, read_function)
+      .await
+      .unwrap_or_else(|_| {
+        log_error!("Read job for {:?} did not complete", subgraph_name);
+        Response::Fail
+      })
+  }
+
+  /// Queues the read on the reader pool (or runs it inline) and returns the
+  /// receiver of its response, so that several reads can run in parallel.
+  fn queue_read<F>(
+    &self,
+    subgraph_name: &SubgraphName,
+    shared: Arc<ArcSwap<RwLock<AugGraph>>>,
+    read_function: F,
+  ) -> tokio::sync::oneshot::Receiver<Response>
+  where
+    F: FnOnce(&AugGraph) -> Response + Send + 'static,
+  {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    //  Created here so that it is a child of the request span.
+    let span = tracing::debug_span!("read");
+    let log_fields = current_log_fields();
+    let job: ReadJob = Box::new(move || {
+      let _entered = span.enter();
+      let _log_fields = enter_log_fields(log_fields);
+      let arc = shared.load_full();
+      //  Recursive, so that a writer waiting for a pinned copy does not block
+      //  the reader pool, see `pin_read`.
+      let response = read_function(&arc.read_recursive());
+      let _ = tx.send(response);
+    });
+    if let Err(job) = self.read_pool.dispatch(subgraph_name, job) {
+      job();
+    }
+    rx
+  }
+
+  /// Pins the copy of the context published now, for reads that must all
+  /// see the same state, e.g. the parts of a bulk read. The writer does not
+  /// apply ops to the copy until the pin and all its clones are dropped, so
+  /// pins must not be held while waiting for clients. Fails with `Busy` if
+  /// the copy can't be locked.
+  pub async fn pin_read(
+    &self,
+    subgraph_name: &SubgraphName,
+  ) -> Result<ReadPin, Response> {
+    let shared = match self.subgraphs_map.get(subgraph_name) {
+      Some(subgraph) => {
+        subgraph.touch();
+        Arc::clone(&subgraph.shared)
+      },
+      None => {
+        log_warning!("Subgraph not found for name: {:?}", subgraph_name);
+        return Err(Response::Fail);
+      },
+    };
+    for _ in 0..PIN_READ_ATTEMPTS {
+      //  The writer may have locked the copy right after publishing the
+      //  other one; the other one is then the one to pin.
+      if let Some(guard) = shared.load_full().try_read_arc() {
+        return Ok(Arc::new(guard));
+      }
+      tokio::task::yield_now().await;
+    }
+    log_warning!("Could not pin a copy of {:?}", subgraph_name);
+    Err(Response::Busy)
+  }
+
+  /// Like `queue_read`, but reads the pinned copy.
+  fn queue_pinned_read<F>(
+    &self,
+    subgraph_name: &SubgraphName,
+    pin: ReadPin,
+    read_function: F,
+  ) -> tokio::sync::oneshot::Receiver<Response>
+  where
+    F: FnOnce(&AugGraph) -> Response + Send + 'static,
+  {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let span = tracing::debug_span!("read");
+    let log_fields = current_log_fields();
+    let job: ReadJob = Box::new(move || {
+      let _entered = span.enter();
+      let _log_fields = enter_log_fields(log_fields);
+      let _ = tx.send(read_function(&pin));
+    });
+    if let Err(job) = self.read_pool.dispatch(subgraph_name, job) {
+      job();
+    }
+    rx
+  }
+
+  /// Reads scores of every ego in parallel on the reader pool. All egos are
+  /// read from the same copy of the context.
+  pub(crate) async fn process_read_scores_bulk(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: OpReadScoresBulk,
+  ) -> Response {
+    log_trace!();
+
+    let pin = match self.pin_read(subgraph_name).await {
+      Ok(x) => x,
+      Err(response) => return response,
+    };
+
+    let compact = data.compact;
+    let receivers: Vec<_> = data
+      .egos
+      .iter()
+      .map(|ego| {
+        let op = OpReadScores {
+          ego:           ego.clone(),
+          score_options: data.score_options.clone(),
+        };
+        self.queue_pinned_read(
+          subgraph_name,
+          Arc::clone(&pin),
+          move |aug_graph| {
+            if compact {
+              let ego = op.ego.clone();
+              match aug_graph.read_scores_compact(op) {
+                Ok(scores) => Response::ScoresCompact(ResScoresCompact {
+                  nodes:  vec![],
+                  scores: vec![(ego, scores)],
+                }),
+                Err(e) => Response::Error(e),
+              }
+            } else {
+              match aug_graph.read_scores(op) {
+                Ok(scores) => Response::Scores(ResScores {
+                  scores,
+                }),
+                Err(e) => Response::Error(e),
+              }
+            }
+          },
+        )
+      })
+      .collect();
+
+    let mut scores = Vec::with_capacity(receivers.len());
+    let mut compact_scores = Vec::with_capacity(receivers.len());
+    for (ego, rx) in data.egos.into_iter().zip(receivers) {
+      match rx.await {
+        Ok(Response::Scores(res)) => scores.push((ego, res.scores)),
+        Ok(Response::ScoresCompact(res)) => compact_scores.extend(res.scores),
+        Ok(Response::Error(e)) => return Response::Error(e),
+        _ => {
+          log_error!("Read job for {:?} did not complete", subgraph_name);
+          return Response::Fail;
+        },
+      }
+    }
+    if !compact {
+      return Response::ScoresBulk(ResScoresBulk { scores });
+    }
+
+    let ids: BTreeSet<NodeId> = compact_scores
+      .iter()
+      .flat_map(|(_, scores)| scores.iter().map(|x| x.target))
+      .collect();
+    let nodes = pin.resolve_nodes(&ids);
+    Response::ScoresCompact(ResScoresCompact {
+      nodes,
+      scores: compact_scores,
+    })
+  }
+
+  /// Reads the scores of a `ReadScoresChunked`, once the request is admitted
+  /// and the ego is calculated. All the pages are read at once, from the
+  /// same copy of the context, so that the copy is not locked while they
+  /// are written to the client. Fails with the response to send instead.
+  pub async fn read_score_pages(
+    &self,
+    req: &Request,
+  ) -> Result<ScorePages, Response> {
+    let data = match &req.data {
+      ReqData::ReadScoresChunked(x) => x,
+      _ => return Err(Response::Fail),
+    };
+    self.admit(req).await?;
+    if let Err(e) = self.prepare_request(req).await {
+      log_warning!("Request failed: {:?}", e);
+      return Err(Response::Error(e));
+    }
+    let op = OpReadScores {
+      ego:           data.ego.clone(),
+      score_options: data.score_options.clone(),
+    };
+    let response = self
+      .dispatch_read(&req.subgraph, move |aug_graph| {
+        match aug_graph.read_scores(op) {
+          Ok(scores) => Response::Scores(ResScores {
+            scores,
+          }),
+          Err(e) => Response::Error(e),
+        }
+      })
+      .await;
+    match response {
+      Response::Scores(res) => Ok(ScorePages::new(res.scores)),
+      response => Err(response),
+    }
+  }
+
+  /// Writes the scores by the egos to a file, one ego at a time, see
+  /// `export`.
+  pub(crate) async fn export_scores(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: OpExportScores,
+  ) -> Response {
+    let path = match export_path(&self.settings.export_dir, &data.destination)
+    {
+      Ok(path) => path,
+      Err(e) => return Response::Error(e),
+    };
+    let egos = if data.egos.is_empty() {
+      let ego_kinds = self.settings.ego_kinds.clone();
+      match self.process_read(subgraph_name, |aug_graph| {
+        Response::NodeList(ResNodeList {
+          nodes: aug_graph
+            .nodes
+            .iter()
+            .filter(|info| ego_kinds.contains(&info.kind))
+            .map(|info| (info.name.clone(),))
+            .collect(),
+        })
+      }) {
+        Response::NodeList(list) => {
+          list.nodes.into_iter().map(|(name,)| name).collect()
+        },
+        response => return response,
+      }
+    } else {
+      data.egos
+    };
+
+    let format = data.format;
+    let total = egos.len();
+    let (tx, mut rx) =
+      mpsc::channel::<(NodeName, Vec<ScoreResult>)>(EXPORT_QUEUE_SIZE);
+    //  The file is replaced only once the scores of all egos are written, so
+    //  a failed export keeps the previous one.
+    let writer = tokio::task::spawn_blocking(move || {
+      write_atomic_with(&path, |tmp| {
+        let mut writer = ScoresWriter::create(format, tmp)?;
+        let mut written = 0;
+        let mut rows = 0;
+        while let Some((ego, scores)) = rx.blocking_recv() {
+          writer.write(&ego, &scores)?;
+          written += 1;
+          rows += scores.len();
+        }
+        if written < total {
+          return Err(std::io::Error::other("Export aborted"));
+        }
+        writer.finish()?;
+        Ok(rows)
+      })
+    });
+
+    let mut failed = None;
+    for ego in &egos {
+      if let Err(e) =
+        self.ensure_calculated(subgraph_name, ego, None, None).await
+      {
+        failed = Some(Response::Error(e));
+        break;
+      }
+      let op = OpReadScores {
+        ego:           ego.clone(),
+        score_options: FilterOptions {
+          include_reverse_scores: true,
+          ..FilterOptions::default()
+        },
+      };
+      let response = self
+        .dispatch_read(subgraph_name, move |aug_graph| {
+          match aug_graph.read_scores(op) {
+            Ok(scores) => Response::Scores(ResScores {
+              scores,
+            }),
+            Err(e) => Response::Error(e),
+          }
+        })
+        .await;
+      let scores = match response {
+        Response::Scores(res) => res.scores,
+        response => {
+          failed = Some(response);
+          break;
+        },
+      };
+      //  The writer stopped on an error, reported below.
+      if tx.send((ego.clone(), scores)).await.is_err() {
+        break;
+      }
+    }
+    //  The writer then finds the egos missing, and removes the partial file.
+    drop(tx);
+    let written = writer.await;
+    if let Some(response) = failed {
+      log_error!("Export of {:?} failed: {:?}", subgraph_name, response);
+      return response;
+    }
+
+    let error = match written {
+      Ok(Ok(rows)) => {
+        return Response::Export(ResExport {
+          egos: egos.len(),
+          rows,
+        })
+      },
+      Ok(Err(e)) => e.to_string(),
+      Err(e) => e.to_string(),
+    };
+    log_error!("Export of {:?} failed: {}", subgraph_name, error);
+    Response::Error(ServiceError::ExportFailed(error))
+  }
+
+  /// Result of `f` on the published copy of the context, if it is loaded.
+  pub(crate) fn read_published<T>(
+    &self,
+    subgraph_name: &SubgraphName,
+    f: impl FnOnce(&AugGraph) -> T,
+  ) -> Option<T> {
+    let entry = self.subgraphs_map.get(subgraph_name)?;
+    let arc = entry.shared.load_full();
+    let aug_graph = arc.read_recursive();
+    Some(f(&aug_graph))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::settings::*;
+  use crate::state_manager::test_utils::*;
+
+  use std::sync::atomic::Ordering;
+
+  #[tokio::test]
+  async fn read_scores_bulk_groups_per_ego() {
+    let proc = default_processor();
+    let edges = vec![
+      BulkEdge {
+        src:       "U1".into(),
+        dst:       "U2".into(),
+        amount:    1.0,
+        magnitude: 0,
+        context:   String::new(),
+      },
+      BulkEdge {
+        src:       "U3".into(),
+        dst:       "U4".into(),
+        amount:    1.0,
+        magnitude: 0,
+        context:   String::new(),
+      },
+    ];
+    let _ = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::WriteBulkEdges(OpWriteBulkEdges { edges }),
+      })
+      .await;
+    sync(&proc).await;
+    let stamp = proc.internal_stamp.load(Ordering::SeqCst);
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadScoresBulk(OpReadScoresBulk {
+          egos:          vec!["U3".into(), "U1".into()],
+          score_options: FilterOptions::default(),
+          compact:       false,
+        }),
+      })
+      .await;
+    match response {
+      Response::ScoresBulk(ResScoresBulk { scores }) => {
+        assert_eq!(scores.len(), 2);
+        assert_eq!(scores[0].0, "U3");
+        assert!(scores[0].1.iter().any(|s| s.target == "U4" && s.score > 0.0));
+        assert_eq!(scores[1].0, "U1");
+        assert!(scores[1].1.iter().any(|s| s.target == "U2" && s.score > 0.0));
+        assert!(scores[1].1.iter().all(|s| s.target != "U4"));
+      },
+      _ => panic!("expected bulk scores"),
+    }
+    //  Both egos were calculated, waited for with a single sync.
+    assert_eq!(proc.internal_stamp.load(Ordering::SeqCst), stamp + 1);
+  }
+
+  #[tokio::test]
+  async fn read_scores_compact_resolves_nodes_once() {
+    let proc = default_processor();
+    let edges = vec![
+      BulkEdge {
+        src:       "U1".into(),
+        dst:       "U3".into(),
+        amount:    1.0,
+        magnitude: 0,
+        context:   String::new(),
+      },
+      BulkEdge {
+        src:       "U2".into(),
+        dst:       "U3".into(),
+        amount:    1.0,
+        magnitude: 0,
+        context:   String::new(),
+      },
+    ];
+    let _ = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::WriteBulkEdges(OpWriteBulkEdges { edges }),
+      })
+      .await;
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadScoresBulk(OpReadScoresBulk {
+          egos:          vec!["U1".into(), "U2".into()],
+          score_options: FilterOptions::default(),
+          compact:       true,
+        }),
+      })
+      .await;
+    match response {
+      Response::ScoresCompact(ResScoresCompact { nodes, scores }) => {
+        assert_eq!(scores.len(), 2);
+        assert_eq!(scores[0].0, "U1");
+        assert_eq!(scores[1].0, "U2");
+        let u3 = nodes.iter().find(|node| node.name == "U3").unwrap();
+        assert_eq!(u3.kind, NodeKind::User);
+        for (_, ego_scores) in &scores {
+          assert!(ego_scores
+            .iter()
+            .any(|s| s.target == u3.id && s.score > 0.0));
+          assert!(ego_scores
+            .iter()
+            .all(|s| nodes.iter().any(|node| node.id == s.target)));
+        }
+        assert_eq!(nodes.iter().filter(|node| node.name == "U3").count(), 1);
+      },
+      _ => panic!("expected compact scores"),
+    }
+  }
+
+  #[tokio::test]
+  async fn anomalies_flag_untrusted_and_ring_nodes() {
+    let proc = MultiGraphProcessor::new(Settings {
+      edge_log_size: 100,
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    for (src, dst) in [
+      ("U1", "U2"),
+      ("U2", "U1"),
+      ("U1", "U5"),
+      ("U2", "U5"),
+      ("U4", "U3"),
+    ] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+          src:        src.into(),
+          dst:        dst.into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        })))
+        .await;
+    }
+    sync(&proc).await;
+    for ego in ["U1", "U2"] {
+      let _ = proc
+        .process_request(&request(ReqData::ReadScores(OpReadScores {
+          ego:           ego.into(),
+          score_options: FilterOptions::default(),
+        })))
+        .await;
+    }
+    sync(&proc).await;
+
+    let nodes = match proc
+      .process_request(&request(ReqData::ReadAnomalies(OpReadAnomalies {
+        window: 3600,
+        limit:  10,
+      })))
+      .await
+    {
+      Response::Anomalies(res) => res.nodes,
+      other => panic!("expected anomalies, got {:?}", other),
+    };
+    let u3 = nodes.iter().find(|x| x.node == "U3").unwrap();
+    assert_eq!(u3.low_trust, 1.0);
+    assert_eq!(u3.burst, 1.0);
+    let u1 = nodes.iter().find(|x| x.node == "U1").unwrap();
+    assert!(u1.ring > 0.0);
+    assert_eq!(u1.low_trust, 0.0);
+  }
+
+  #[tokio::test]
+  async fn sybil_resistance_without_attack_edges() {
+    let proc = default_processor();
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    for (src, dst) in [
+      ("U1", "U2"),
+      ("U2", "U1"),
+      ("U1", "U3"),
+      ("U2", "U9"),
+      ("U9", "U10"),
+      ("U10", "U9"),
+    ] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+          src:        src.into(),
+          dst:        dst.into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        })))
+        .await;
+    }
+    sync(&proc).await;
+
+    let response = proc
+      .process_request(&request(ReqData::EvaluateSybilResistance(
+        OpEvaluateSybilResistance {
+          suspects: vec!["U9".into(), "U10".into()],
+          egos:     3,
+          top_k:    10,
+        },
+      )))
+      .await;
+    let res = match response {
+      Response::SybilResistance(res) => res,
+      other => panic!("expected sybil resistance, got {:?}", other),
+    };
+    assert_eq!(res.egos, 3);
+    assert_eq!(res.attack_edges, 1);
+    assert!(res.before > 0.0);
+    assert_eq!(res.after, 0.0);
+
+    //  The evaluation does not change the context.
+    let edges = edges_from_response(
+      proc.process_request(&request(ReqData::ReadEdges)).await,
+    );
+    assert_eq!(edges.len(), 6);
+  }
+
+  #[tokio::test]
+  async fn beacon_comments_blend_comment_and_author_scores() {
+    let proc = default_processor();
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    //  The edges of the authors come first, as they make them the owners.
+    for (src, dst, amount) in [
+      ("B1", "U2", 1.0),
+      ("C1", "U2", 1.0),
+      ("C2", "U3", 1.0),
+      ("U1", "U2", 1.0),
+      ("U1", "U3", -1.0),
+      ("U1", "B1", 1.0),
+      ("U1", "C1", 1.0),
+    ] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+          src:        src.into(),
+          dst:        dst.into(),
+          amount,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        })))
+        .await;
+    }
+    sync(&proc).await;
+
+    let response = proc
+      .process_request(&request(ReqData::ReadBeaconComments(
+        OpReadBeaconComments {
+          ego:            "U1".into(),
+          beacon:         "B1".into(),
+          comments:       vec!["C2".into(), "C1".into(), "C9".into()],
+          beacon_weight:  1.0,
+          comment_weight: 1.0,
+          author_weight:  1.0,
+          limit:          10,
+        },
+      )))
+      .await;
+    let res = match response {
+      Response::BeaconComments(res) => res,
+      other => panic!("expected beacon comments, got {:?}", other),
+    };
+    assert!(res.beacon_score > 0.0);
+    //  Unknown comments are skipped.
+    assert_eq!(res.comments.len(), 2);
+    assert_eq!(res.comments[0].comment, "C1");
+    assert_eq!(res.comments[0].author.as_deref(), Some("U2"));
+    assert!(res.comments[0].comment_score > 0.0);
+    assert_eq!(res.comments[1].author.as_deref(), Some("U3"));
+    assert!(res.comments[1].author_score < 0.0);
+    assert_eq!(
+      res.comments[1].score,
+      res.beacon_score
+        + res.comments[1].comment_score
+        + res.comments[1].author_score
+    );
+  }
+
+  #[tokio::test]
+  async fn top_nodes_by_zero_opinion() {
+    let proc = default_processor();
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    for (src, dst) in [("U1", "U2"), ("U1", "U3"), ("U1", "B1"), ("B1", "U2")]
+    {
+      let _ = proc
+        .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+          src:        src.into(),
+          dst:        dst.into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        })))
+        .await;
+    }
+    for (node, score) in [("U2", 0.5), ("U3", 0.9), ("B1", 0.3)] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteZeroOpinion(
+          OpWriteZeroOpinion {
+            node: node.into(),
+            score,
+          },
+        )))
+        .await;
+    }
+    sync(&proc).await;
+
+    let top = |ego: Option<&'static str>, kind, index, limit| {
+      let proc = &proc;
+      async move {
+        match proc
+          .process_request(&request(ReqData::ReadTopNodes(OpReadTopNodes {
+            ego: ego.map(|x| x.into()),
+            kind,
+            index,
+            limit,
+          })))
+          .await
+        {
+          Response::TopNodes(res) => res
+            .nodes
+            .into_iter()
+            .map(|x| (x.node, x.score))
+            .collect::<Vec<_>>(),
+          other => panic!("expected top nodes, got {:?}", other),
+        }
+      }
+    };
+
+    assert_eq!(
+      top(None, None, 0, 10).await,
+      vec![("U3".into(), 0.9), ("U2".into(), 0.5), ("B1".into(), 0.3)]
+    );
+    let users = top(None, Some(NodeKind::User), 0, 10).await;
+    assert_eq!(users.len(), 2);
+    assert_eq!(top(None, None, 1, 1).await, vec![("U2".into(), 0.5)]);
+
+    //  Blended with the ego's own scores.
+    let personal = top(Some("U1"), Some(NodeKind::User), 0, 10).await;
+    assert!(personal
+      .iter()
+      .any(|(node, score)| node == "U2" && *score > 0.1));
+  }
+
+  #[tokio::test]
+  async fn debug_ego_exposes_walks() {
+    let debug = |debug_ops: bool| async move {
+      let proc = MultiGraphProcessor::new(Settings {
+        num_walks: 50,
+        debug_ops,
+        ..Settings::default()
+      });
+      let request = |data: ReqData| Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data,
+      };
+      for (dst, amount) in [("U2", 1.0), ("U3", -1.0)] {
+        let _ = proc
+          .process_request(&request(ReqData::WriteEdge(OpWriteEdge {
+            src: "U1".into(),
+            dst: dst.into(),
+            amount,
+            magnitude: 0,
+            edge_kind: EdgeKind::Vote,
+            provenance: None,
+          })))
+          .await;
+      }
+      let _ = proc
+        .process_request(&request(ReqData::WriteCalculate(OpWriteCalculate {
+          ego: "U1".into(),
+        })))
+        .await;
+      sync(&proc).await;
+      proc
+        .process_request(&request(ReqData::DebugEgo(OpDebugEgo {
+          ego:   "U1".into(),
+          walks: 5,
+        })))
+        .await
+    };
+
+    assert!(matches!(debug(false).await, Response::NotImplemented));
+    let res = match debug(true).await {
+      Response::DebugEgo(res) => res,
+      other => panic!("expected debug data, got {:?}", other),
+    };
+    assert_eq!(res.walks, 50);
+    assert_eq!(res.sample.len(), 5);
+    assert!(res.sample.iter().all(|walk| walk[0] == "U1"));
+    assert!(res.positive_hits.iter().any(|(node, _)| node == "U2"));
+    assert!(res.negative_hits.iter().any(|(node, _)| node == "U3"));
+  }
+
+  #[tokio::test]
+  async fn pinned_read_sees_one_state() {
+    let proc = default_processor();
+    let write = |dst: &str| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        dst.into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    };
+    let sync_to = |stamp: u64| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::Sync(stamp),
+    };
+    let has_node =
+      |aug_graph: &AugGraph| aug_graph.nodes.get_by_name("U3").is_some();
+
+    let _ = proc.process_request(&write("U2")).await;
+    let _ = proc.process_request(&sync_to(1)).await;
+    let pin = proc.pin_read(&String::new()).await.unwrap();
+
+    //  The write is published while the pin holds the previous copy.
+    let _ = proc.process_request(&write("U3")).await;
+    let _ = proc.process_request(&sync_to(2)).await;
+    assert!(!has_node(&pin));
+    let published = proc.process_read(&String::new(), |aug_graph| {
+      if has_node(aug_graph) {
+        Response::Ok
+      } else {
+        Response::Fail
+      }
+    });
+    assert!(matches!(published, Response::Ok));
+
+    drop(pin);
+    let _ = proc.process_request(&sync_to(3)).await;
+    assert!(has_node(&proc.pin_read(&String::new()).await.unwrap()));
+  }
+
+  #[tokio::test]
+  async fn export_scores_writes_csv() {
+    let dir = std::env::temp_dir()
+      .join(format!("meritrank-export-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let proc = MultiGraphProcessor::new(Settings {
+      export_dir: dir.to_string_lossy().into(),
+      ..Settings::default()
+    });
+    let _ = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::WriteEdge(OpWriteEdge {
+          src:        "U1".into(),
+          dst:        "U2".into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        }),
+      })
+      .await;
+    sync(&proc).await;
+
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ExportScores(OpExportScores {
+          egos:        vec![],
+          format:      ExportFormat::Csv,
+          destination: "daily/scores.csv".into(),
+        }),
+      })
+      .await;
+    match response {
+      Response::Export(res) => {
+        assert_eq!(res.egos, 2);
+        assert!(res.rows >= 2);
+      },
+      other => panic!("expected export, got {:?}", other),
+    }
+    let csv = std::fs::read_to_string(dir.join("daily/scores.csv")).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+      lines.next(),
+      Some("ego,target,score,reverse_score,cluster,reverse_cluster")
+    );
+    assert!(lines.any(|line| line.starts_with("U1,U2,")));
+
+    //  A failed export keeps the previous file.
+    std::fs::create_dir(dir.join("daily/scores.csv.tmp")).unwrap();
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ExportScores(OpExportScores {
+          egos:        vec!["U1".into()],
+          format:      ExportFormat::Csv,
+          destination: "daily/scores.csv".into(),
+        }),
+      })
+      .await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::ExportFailed(_))
+    ));
+    assert_eq!(
+      std::fs::read_to_string(dir.join("daily/scores.csv")).unwrap(),
+      csv
+    );
+
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ExportScores(OpExportScores {
+          egos:        vec!["U1".into()],
+          format:      ExportFormat::Csv,
+          destination: "../scores.csv".into(),
+        }),
+      })
+      .await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::ExportFailed(_))
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[tokio::test]
+  async fn failed_export_removes_the_partial_file() {
+    let dir = std::env::temp_dir()
+      .join(format!("meritrank-export-failed-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("scores.csv"), "old").unwrap();
+    //  Enough walks to calculate one ego.
+    let proc = MultiGraphProcessor::new(Settings {
+      export_dir: dir.to_string_lossy().into(),
+      walk_budgets: vec![WalkBudget {
+        context:   "*".into(),
+        max_walks: 100,
+        period:    3600,
+      }],
+      ..Settings::default()
+    });
+    for (src, dst) in [("U1", "U2"), ("U2", "U1")] {
+      let _ = proc
+        .process_request(&Request {
+          subgraph: String::new(),
+          token:    String::new(),
+          data:     ReqData::WriteEdge(OpWriteEdge {
+            src:        src.into(),
+            dst:        dst.into(),
+            amount:     1.0,
+            magnitude:  0,
+            edge_kind:  EdgeKind::Vote,
+            provenance: None,
+          }),
+        })
+        .await;
+    }
+    sync(&proc).await;
+
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ExportScores(OpExportScores {
+          egos:        vec!["U1".into(), "U2".into()],
+          format:      ExportFormat::Csv,
+          destination: "scores.csv".into(),
+        }),
+      })
+      .await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::WalkBudgetExceeded(_))
+    ));
+    //  Removed by the time the export is answered.
+    assert!(!dir.join("scores.csv.tmp").exists());
+    assert_eq!(std::fs::read_to_string(dir.join("scores.csv")).unwrap(), "old");
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+}
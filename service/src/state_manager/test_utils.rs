@@ -0,0 +1,48 @@
+use crate::data::*;
+use crate::settings::Settings;
+
+use super::MultiGraphProcessor;
+
+pub(crate) fn default_processor() -> MultiGraphProcessor {
+  MultiGraphProcessor::new(Settings::default())
+}
+
+/// Waits for the processor to apply a sync point (process_request(Sync) already awaits).
+pub(crate) async fn sync(proc: &MultiGraphProcessor) {
+  let _ = proc.process_request(&Request {
+    subgraph: String::new(),
+    token:    String::new(),
+    data:     ReqData::Sync(1),
+  }).await;
+}
+
+/// Vote edge write to the context, with no provenance.
+pub(crate) fn write_edge_req(
+  context: &str,
+  src: &str,
+  dst: &str,
+  amount: Weight,
+) -> Request {
+  Request {
+    subgraph: context.into(),
+    token:    String::new(),
+    data:     ReqData::WriteEdge(OpWriteEdge {
+      src:        src.into(),
+      dst:        dst.into(),
+      amount,
+      magnitude:  0,
+      edge_kind:  EdgeKind::Vote,
+      provenance: None,
+    }),
+  }
+}
+
+pub(crate) fn edges_from_response(response: Response) -> Vec<(String, String, Weight)> {
+  match response {
+    Response::Edges(ResEdges { edges }) => edges
+      .into_iter()
+      .map(|e: EdgeResult| (e.src, e.dst, e.weight))
+      .collect(),
+    _ => vec![],
+  }
+}
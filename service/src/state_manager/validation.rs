@@ -0,0 +1,225 @@
+use crate::data::*;
+use crate::node_registry::*;
+
+fn validate_name(name: &NodeName) -> Result<(), InvalidWrite> {
+  if name.len() > MAX_NODE_NAME_LEN {
+    return Err(InvalidWrite::NameTooLong(name.clone()));
+  }
+  if node_kind_from_prefix(name).is_none() {
+    return Err(InvalidWrite::UnknownNodeKind(name.clone()));
+  }
+  Ok(())
+}
+
+fn validate_edge(
+  src: &NodeName,
+  dst: &NodeName,
+  amount: Weight,
+) -> Result<(), InvalidWrite> {
+  validate_name(src)?;
+  validate_name(dst)?;
+  if src == dst {
+    return Err(InvalidWrite::SelfLoop(src.clone()));
+  }
+  if !amount.is_finite() {
+    return Err(InvalidWrite::NonFiniteWeight);
+  }
+  Ok(())
+}
+
+fn validate_provenance(data: &OpWriteEdge) -> Result<(), InvalidWrite> {
+  let too_long = |x: &EdgeProvenance| {
+    x.source.len() > MAX_PROVENANCE_LEN
+      || x.external_id.len() > MAX_PROVENANCE_LEN
+  };
+  if data.provenance.as_ref().is_some_and(too_long) {
+    return Err(InvalidWrite::ProvenanceTooLong);
+  }
+  Ok(())
+}
+
+/// The edge written by a part of `WriteMulti`, None if the part is not an
+/// edge write. Deletes are writes of a zero weight, as with
+/// `WriteDeleteEdge`.
+pub(crate) fn multi_edge(data: &ReqData) -> Option<OpWriteEdge> {
+  match data {
+    ReqData::WriteEdge(data) => Some(data.clone()),
+    ReqData::WriteDeleteEdge(data) => Some(OpWriteEdge {
+      src:        data.src.clone(),
+      dst:        data.dst.clone(),
+      amount:     0.0,
+      magnitude:  data.index as u32,
+      edge_kind:  EdgeKind::Vote,
+      provenance: None,
+    }),
+    _ => None,
+  }
+}
+
+fn is_poll_edge(data: &OpWriteEdge) -> bool {
+  [&data.src, &data.dst].into_iter().any(|name| {
+    matches!(
+      node_kind_from_prefix(name),
+      Some(NodeKind::Poll) | Some(NodeKind::PollVariant)
+    )
+  })
+}
+
+/// Rejects writes the graph cannot apply, before they are queued. Edges of
+/// bulk loads and snapshots are checked by the graph, which skips bad ones.
+pub(crate) fn validate_write(data: &ReqData) -> Result<(), InvalidWrite> {
+  match data {
+    ReqData::WriteEdge(data) => {
+      validate_edge(&data.src, &data.dst, data.amount)?;
+      validate_provenance(data)
+    },
+    ReqData::WriteDeleteEdge(data) => validate_edge(&data.src, &data.dst, 0.0),
+    ReqData::WriteMulti(data) => {
+      if data.ops.is_empty() {
+        return Err(InvalidWrite::InvalidMulti);
+      }
+      data.ops.iter().try_for_each(|(_, op)| match multi_edge(op) {
+        Some(edge) if !is_poll_edge(&edge) => validate_write(op),
+        _ => Err(InvalidWrite::InvalidMulti),
+      })
+    },
+    ReqData::WriteEdgeCas(data) => {
+      if !data.expected_old_weight.is_finite() {
+        return Err(InvalidWrite::NonFiniteWeight);
+      }
+      validate_edge(&data.src, &data.dst, data.new_weight)
+    },
+    ReqData::ReadDryRun(data) => data
+      .edges
+      .iter()
+      .try_for_each(|edge| validate_edge(&edge.src, &edge.dst, edge.amount)),
+    ReqData::WriteZeroOpinion(data) => {
+      validate_name(&data.node)?;
+      if !data.score.is_finite() {
+        return Err(InvalidWrite::NonFiniteWeight);
+      }
+      Ok(())
+    },
+    ReqData::WriteNode(data) => validate_name(&data.name),
+    ReqData::WriteWebhook(data) => {
+      if !data.url.starts_with("http://") {
+        return Err(InvalidWrite::UnsupportedUrl(data.url.clone()));
+      }
+      if !data.min_change.is_finite() {
+        return Err(InvalidWrite::NonFiniteWeight);
+      }
+      Ok(())
+    },
+    ReqData::SetUserParams(data) => {
+      validate_name(&data.ego)?;
+      if data.alpha.is_some_and(|alpha| !(0.0..=1.0).contains(&alpha)) {
+        return Err(InvalidWrite::AlphaOutOfRange);
+      }
+      Ok(())
+    },
+    ReqData::WriteMute(data) => validate_edge(&data.ego, &data.node, 0.0),
+    ReqData::WritePoll(data) => {
+      validate_name(&data.poll)?;
+      if node_kind_from_prefix(&data.poll) != Some(NodeKind::Poll) {
+        return Err(InvalidWrite::NotAPoll(data.poll.clone()));
+      }
+      Ok(())
+    },
+    ReqData::WriteScoreClusters(data) if data.num_clusters < 2 => {
+      Err(InvalidWrite::TooFewClusters(data.num_clusters))
+    },
+    ReqData::WriteAliasNode(data) => {
+      validate_name(&data.old)?;
+      validate_name(&data.new)?;
+      let old = node_kind_from_prefix(&data.old);
+      match (old, node_kind_from_prefix(&data.new)) {
+        (Some(old), Some(new)) if old != new => {
+          Err(InvalidWrite::AliasKindMismatch(old, new))
+        },
+        _ => Ok(()),
+      }
+    },
+    _ => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::state_manager::test_utils::*;
+
+  #[tokio::test]
+  async fn invalid_writes_are_rejected() {
+    let proc = default_processor();
+    let write = |src: &str, dst: &str, amount: Weight| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        src.into(),
+        dst:        dst.into(),
+        amount,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    };
+    let rejected = |response: Response| match response {
+      Response::Error(ServiceError::InvalidWrite(e)) => e,
+      other => panic!("expected invalid write, got {:?}", other),
+    };
+
+    assert_eq!(
+      rejected(proc.process_request(&write("U1", "U1", 1.0)).await),
+      InvalidWrite::SelfLoop("U1".into())
+    );
+    assert_eq!(
+      rejected(proc.process_request(&write("U1", "U2", f64::NAN)).await),
+      InvalidWrite::NonFiniteWeight
+    );
+    assert_eq!(
+      rejected(proc.process_request(&write("U1", "X2", 1.0)).await),
+      InvalidWrite::UnknownNodeKind("X2".into())
+    );
+    let long = format!("U{}", "1".repeat(MAX_NODE_NAME_LEN));
+    assert_eq!(
+      rejected(proc.process_request(&write(&long, "U2", 1.0)).await),
+      InvalidWrite::NameTooLong(long)
+    );
+    let clusters = Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteScoreClusters(OpWriteScoreClusters {
+        num_clusters: 1,
+      }),
+    };
+    assert_eq!(
+      rejected(proc.process_request(&clusters).await),
+      InvalidWrite::TooFewClusters(1)
+    );
+    let alias = Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteAliasNode(OpWriteAliasNode {
+        old: "U1".into(),
+        new: "B1".into(),
+      }),
+    };
+    assert_eq!(
+      rejected(proc.process_request(&alias).await),
+      InvalidWrite::AliasKindMismatch(NodeKind::User, NodeKind::Beacon)
+    );
+
+    let _ = proc.process_request(&write("U1", "U2", 1.0)).await;
+    sync(&proc).await;
+    let edges = edges_from_response(
+      proc
+        .process_request(&Request {
+          subgraph: String::new(),
+          token:    String::new(),
+          data:     ReqData::ReadEdges,
+        })
+        .await,
+    );
+    assert_eq!(edges, vec![("U1".into(), "U2".into(), 1.0)]);
+  }
+}
@@ -0,0 +1,756 @@
+use crate::aug_graph::*;
+use crate::data::*;
+use crate::history::unix_time_secs;
+use crate::processor_stats::ProcessorStats;
+use crate::publish_barrier::*;
+use crate::replication::Replication;
+use crate::settings::*;
+use crate::utils::log::*;
+use crate::walk_tracker::WalkTracker;
+use crate::warming::EgoHeat;
+
+use arc_swap::ArcSwap;
+use parking_lot::RwLock;
+use tokio::sync::mpsc;
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Context of the ops sent through a `FanoutSender`, for the replication log.
+#[derive(Clone)]
+pub struct ReplicationTap {
+  pub(crate) subgraph:    SubgraphName,
+  pub(crate) replication: Arc<Replication>,
+}
+
+/// Sends each op to both write channels (fan-out) for double-buffered eventual consistency.
+#[derive(Clone)]
+pub struct FanoutSender {
+  tx_a:    mpsc::Sender<AugGraphOp>,
+  tx_b:    mpsc::Sender<AugGraphOp>,
+  /// Replicated ops are appended to the replication log as they are sent.
+  pub tap: Option<ReplicationTap>,
+}
+
+impl FanoutSender {
+  pub async fn send(
+    &self,
+    op: AugGraphOp,
+  ) -> Result<(), mpsc::error::SendError<AugGraphOp>> {
+    let permit_a = match self.tx_a.reserve().await {
+      Ok(x) => x,
+      Err(_) => return Err(mpsc::error::SendError(op)),
+    };
+    let permit_b = match self.tx_b.reserve().await {
+      Ok(x) => x,
+      Err(_) => return Err(mpsc::error::SendError(op)),
+    };
+    FanoutPermit {
+      permit_a,
+      permit_b,
+      tap: self.tap.as_ref(),
+    }
+    .send(op);
+    Ok(())
+  }
+
+  /// Reserves a slot in both channels without waiting. Fails with `Full` if either queue is at capacity.
+  pub fn try_reserve(
+    &self
+  ) -> Result<FanoutPermit<'_>, mpsc::error::TrySendError<()>> {
+    Ok(FanoutPermit {
+      permit_a: self.tx_a.try_reserve()?,
+      permit_b: self.tx_b.try_reserve()?,
+      tap:      self.tap.as_ref(),
+    })
+  }
+
+  /// Number of ops queued but not yet applied (the deeper of the two channels).
+  pub fn depth(&self) -> usize {
+    let depth_a = self.tx_a.max_capacity() - self.tx_a.capacity();
+    let depth_b = self.tx_b.max_capacity() - self.tx_b.capacity();
+    depth_a.max(depth_b)
+  }
+
+  /// Number of ops not yet published (the shallower of the two channels:
+  /// the other one keeps the published ops until the writer replays them).
+  pub fn unpublished(&self) -> usize {
+    let depth_a = self.tx_a.max_capacity() - self.tx_a.capacity();
+    let depth_b = self.tx_b.max_capacity() - self.tx_b.capacity();
+    depth_a.min(depth_b)
+  }
+}
+
+/// Reserved slots in both write channels; sending through it cannot block or fail.
+pub struct FanoutPermit<'a> {
+  permit_a: mpsc::Permit<'a, AugGraphOp>,
+  permit_b: mpsc::Permit<'a, AugGraphOp>,
+  tap:      Option<&'a ReplicationTap>,
+}
+
+impl FanoutPermit<'_> {
+  pub fn send(
+    self,
+    op: AugGraphOp,
+  ) {
+    let (permit_a, permit_b) = (self.permit_a, self.permit_b);
+    match self.tap {
+      Some(tap) if op.is_replicated() => {
+        let record = ReplicationRecord::Op(op.clone());
+        tap.replication.append(&tap.subgraph, record, || {
+          permit_a.send(op.clone());
+          permit_b.send(op);
+        });
+      },
+      _ => {
+        permit_a.send(op.clone());
+        permit_b.send(op);
+      },
+    }
+  }
+}
+
+pub struct ConcurrentDataProcessor {
+  #[allow(unused)]
+  processing_thread: thread::JoinHandle<()>,
+  pub op_sender:     FanoutSender,
+  pub shared:        Arc<ArcSwap<RwLock<AugGraph>>>,
+  pub walk_tracker:  Option<WalkTracker>,
+  /// Unix time in seconds of the last read or write, see `touch`.
+  pub last_access:   AtomicU64,
+  /// Query frequency of egos, for the warming worker.
+  pub ego_heat:      EgoHeat,
+}
+
+pub type GraphProcessor = ConcurrentDataProcessor;
+
+/// When the writer publishes the back copy to readers.
+#[derive(Debug, Clone, Copy)]
+pub struct PublishPolicy {
+  /// Ops a batch waits for before it is published.
+  pub min_ops:     usize,
+  /// A batch is published once it has that many ops, even if more are queued
+  /// (0 = unlimited).
+  pub max_ops:     usize,
+  /// A batch is published once that much time has passed since its first op,
+  /// even if it has fewer than `min_ops` ops.
+  pub max_latency: Option<Duration>,
+}
+
+impl PublishPolicy {
+  pub fn from_settings(settings: &Settings) -> Self {
+    PublishPolicy {
+      min_ops:     settings.min_ops_before_swap,
+      max_ops:     settings.max_ops_per_publish,
+      max_latency: match settings.max_publish_latency {
+        0 => None,
+        msec => Some(Duration::from_millis(msec)),
+      },
+    }
+  }
+
+  fn is_full(
+    &self,
+    applied: usize,
+  ) -> bool {
+    self.max_ops > 0 && applied >= self.max_ops
+  }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+  if let Some(message) = payload.downcast_ref::<&str>() {
+    message
+  } else if let Some(message) = payload.downcast_ref::<String>() {
+    message
+  } else {
+    "unknown panic"
+  }
+}
+
+/// Receives the next op, waiting until `deadline` if there is one. None if
+/// the deadline has passed or the channel is closed.
+fn recv_until(
+  runtime: &tokio::runtime::Runtime,
+  rx: &mut mpsc::Receiver<AugGraphOp>,
+  deadline: Option<Instant>,
+) -> Option<AugGraphOp> {
+  match deadline {
+    //  The timer is created inside the runtime, which owns it.
+    Some(deadline) => runtime
+      .block_on(async {
+        tokio::time::timeout_at(deadline.into(), rx.recv()).await
+      })
+      .ok()
+      .flatten(),
+    None => rx.blocking_recv(),
+  }
+}
+
+/// The ops of one of the two copies of a context.
+struct CopyOps {
+  rx:       mpsc::Receiver<AugGraphOp>,
+  /// Ops of the channel the copy is past. Both copies get the same ops, so
+  /// copies at the same position have the same graph.
+  position: u64,
+  /// Part of a `WriteMulti` taken from the channel, kept until the other copy
+  /// stages it, see `take_op`.
+  carried:  Option<AugGraphOp>,
+}
+
+impl CopyOps {
+  fn new(rx: mpsc::Receiver<AugGraphOp>) -> Self {
+    CopyOps {
+      rx,
+      position: 0,
+      carried: None,
+    }
+  }
+
+  fn try_recv(&mut self) -> Result<AugGraphOp, mpsc::error::TryRecvError> {
+    match self.carried.take() {
+      Some(op) => Ok(op),
+      None => self.rx.try_recv(),
+    }
+  }
+
+  fn blocking_recv(&mut self) -> Option<AugGraphOp> {
+    self.carried.take().or_else(|| self.rx.blocking_recv())
+  }
+}
+
+/// What taking an op did to the back copy.
+enum Taken {
+  /// The op was applied, or skipped if it is a part that was dropped.
+  Op,
+  /// A part was staged and all the contexts voted to publish it.
+  Commit(Arc<PublishBarrier>),
+  /// A part was staged and dropped: the back copy is synced from the front.
+  Abort,
+  /// A part that can only be staged once the front copy caught up, so the
+  /// back copy must be published first.
+  Carried,
+}
+
+/// Applies the op, and returns whether it did not panic.
+fn apply_one(
+  guard: &mut AugGraph,
+  op: &AugGraphOp,
+  stats: &Option<Arc<ProcessorStats>>,
+  record_stats: bool,
+) -> bool {
+  let start = Instant::now();
+  //  A panicking op must not stop the writer, or the context would stop
+  //  accepting writes. The op may be partially applied.
+  let applied = panic::catch_unwind(AssertUnwindSafe(|| guard.apply_op(op)));
+  if let Err(e) = &applied {
+    log_error!("Op {:?} panicked: {}", op, panic_message(&**e));
+    if let Some(s) = stats {
+      s.record_panic();
+    }
+  }
+  if record_stats {
+    if let Some(s) = stats {
+      s.record_applied(start.elapsed());
+    }
+  }
+  applied.is_ok()
+}
+
+/// Takes the op to the back copy. A part of a `WriteMulti` is staged when
+/// the back copy is at the position of the front one, so that dropping it
+/// only takes syncing the back copy from the front one. The part ends the
+/// batch.
+fn take_op(
+  back: &mut AugGraph,
+  back_ops: &mut CopyOps,
+  front: &RwLock<AugGraph>,
+  front_position: u64,
+  op: AugGraphOp,
+  stats: &Option<Arc<ProcessorStats>>,
+  record_stats: bool,
+) -> Taken {
+  let staged = match &op {
+    AugGraphOp::Staged(_, gate) => gate
+      .barrier()
+      .map(|barrier| (gate.clone(), Arc::clone(barrier))),
+    _ => None,
+  };
+  let (gate, barrier) = match staged {
+    Some(x) => x,
+    None => {
+      apply_one(back, &op, stats, record_stats);
+      back_ops.position += 1;
+      return Taken::Op;
+    },
+  };
+  match barrier.decision() {
+    //  Staged and decided by the other copy.
+    Some(publish) => {
+      if publish {
+        apply_one(back, &op, stats, record_stats);
+      }
+      back_ops.position += 1;
+      Taken::Op
+    },
+    None if back_ops.position > front_position => {
+      back_ops.carried = Some(op);
+      Taken::Carried
+    },
+    None => {
+      let rejected = match back.check_part(gate.counted()) {
+        Err(limit) => {
+          let e = ServiceError::QuotaExceeded(gate.context().clone(), limit);
+          log_warning!("Write rejected: {:?}", e);
+          Some(Response::Error(e))
+        },
+        Ok(()) if !apply_one(back, &op, stats, record_stats) => {
+          Some(Response::Fail)
+        },
+        Ok(()) => None,
+      };
+      back_ops.position += 1;
+      let timeout = Duration::from_millis(STAGED_VOTE_TIMEOUT_MSEC);
+      if barrier.vote(rejected, timeout) {
+        Taken::Commit(barrier)
+      } else {
+        *back = front.read().fork();
+        Taken::Abort
+      }
+    },
+  }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn processing_loop(
+  copy_a: Arc<RwLock<AugGraph>>,
+  copy_b: Arc<RwLock<AugGraph>>,
+  write_rx_a: mpsc::Receiver<AugGraphOp>,
+  write_rx_b: mpsc::Receiver<AugGraphOp>,
+  shared: Arc<ArcSwap<RwLock<AugGraph>>>,
+  publish_notify: Arc<tokio::sync::Notify>,
+  policy: PublishPolicy,
+  stats: Option<Arc<ProcessorStats>>,
+) {
+  let mut front_arc = copy_a;
+  let mut back_arc = copy_b;
+  let mut front_ops = CopyOps::new(write_rx_a);
+  let mut back_ops = CopyOps::new(write_rx_b);
+
+  //  Only used to wait for ops with a timeout.
+  let runtime = tokio::runtime::Builder::new_current_thread()
+    .enable_time()
+    .build()
+    .expect("Failed to create writer runtime");
+
+  shared.store(Arc::clone(&front_arc));
+  let mut back_guard = back_arc.write();
+
+  loop {
+    let op = match back_ops.blocking_recv() {
+      Some(o) => o,
+      None => return,
+    };
+    //  The latency is counted from the first op of the batch, so an idle
+    //  writer does not publish.
+    let deadline = policy.max_latency.map(|latency| Instant::now() + latency);
+    let mut taken = take_op(
+      &mut back_guard,
+      &mut back_ops,
+      &front_arc,
+      front_ops.position,
+      op,
+      &stats,
+      true,
+    );
+    let mut applied = 1usize;
+    while matches!(taken, Taken::Op) && !policy.is_full(applied) {
+      let op = match back_ops.try_recv() {
+        Ok(op) => op,
+        Err(mpsc::error::TryRecvError::Empty) if applied < policy.min_ops => {
+          match recv_until(&runtime, &mut back_ops.rx, deadline) {
+            Some(op) => op,
+            None => break,
+          }
+        },
+        Err(_) => break,
+      };
+      taken = take_op(
+        &mut back_guard,
+        &mut back_ops,
+        &front_arc,
+        front_ops.position,
+        op,
+        &stats,
+        true,
+      );
+      applied += 1;
+    }
+    //  The back copy is the front one again, there is nothing to publish.
+    if matches!(taken, Taken::Abort) {
+      continue;
+    }
+
+    //  Recorded before publishing, so a reader that sees the ops also sees
+    //  the publish in the stats.
+    if let Some(s) = &stats {
+      s.record_publish(applied);
+    }
+    drop(back_guard);
+    shared.store(Arc::clone(&back_arc));
+    publish_notify.notify_waiters();
+    if let Taken::Commit(barrier) = &taken {
+      barrier.published();
+    }
+
+    std::mem::swap(&mut front_arc, &mut back_arc);
+    std::mem::swap(&mut front_ops, &mut back_ops);
+
+    //  The ops the other copy got meanwhile. Ops past them start the next
+    //  batch, so that they are published within the latency.
+    back_guard = back_arc.write();
+    while back_ops.position < front_ops.position {
+      let op = match back_ops.try_recv() {
+        Ok(op) => op,
+        Err(_) => break,
+      };
+      //  The parts the front copy is past are decided.
+      take_op(
+        &mut back_guard,
+        &mut back_ops,
+        &front_arc,
+        front_ops.position,
+        op,
+        &stats,
+        false,
+      );
+    }
+  }
+}
+
+impl ConcurrentDataProcessor {
+  pub fn new(
+    subgraph_name: &SubgraphName,
+    initial: AugGraph,
+    queue_len: usize,
+    policy: PublishPolicy,
+    publish_notify: Arc<tokio::sync::Notify>,
+    stats: Option<Arc<ProcessorStats>>,
+    walks_cache_size: usize,
+  ) -> Self {
+    //  Walks, and so score cache generations, differ between the copies,
+    //  so each copy needs its own caches.
+    let copy_a = Arc::new(RwLock::new(initial.fork()));
+    let copy_b = Arc::new(RwLock::new(initial));
+    let shared = Arc::new(ArcSwap::new(Arc::clone(&copy_a)));
+
+    let (tx_a, write_rx_a) = mpsc::channel(queue_len);
+    let (tx_b, write_rx_b) = mpsc::channel(queue_len);
+    let op_sender = FanoutSender {
+      tx_a,
+      tx_b,
+      tap: None,
+    };
+
+    let walk_tracker = if walks_cache_size > 0 {
+      Some(WalkTracker::new(walks_cache_size as u64))
+    } else {
+      None
+    };
+
+    let shared_clone = Arc::clone(&shared);
+    let notify_clone = Arc::clone(&publish_notify);
+    let log_fields = LogFields {
+      context: Some(subgraph_name.clone()),
+      ..LogFields::default()
+    };
+    let loop_thread = thread::spawn(move || {
+      let _log_fields = enter_log_fields(Some(log_fields));
+      processing_loop(
+        copy_a,
+        copy_b,
+        write_rx_a,
+        write_rx_b,
+        shared_clone,
+        notify_clone,
+        policy,
+        stats,
+      );
+    });
+
+    ConcurrentDataProcessor {
+      processing_thread: loop_thread,
+      op_sender,
+      shared,
+      walk_tracker,
+      last_access: AtomicU64::new(unix_time_secs()),
+      ego_heat: EgoHeat::new(),
+    }
+  }
+
+  pub fn touch(&self) {
+    self.last_access.store(unix_time_secs(), Ordering::Relaxed);
+  }
+
+  #[allow(unused)]
+  pub fn shutdown(self) -> thread::Result<()> {
+    drop(self.op_sender);
+    self.processing_thread.join()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::processor_stats::ProcessorStats;
+  use crate::state_manager::test_utils::*;
+  use crate::state_manager::MultiGraphProcessor;
+
+  use std::sync::Arc;
+  use std::time::Duration;
+
+  #[tokio::test]
+  async fn full_write_queue_returns_busy() {
+    // The front channel is not drained until the back copy has applied
+    // min_ops_before_swap ops, so with capacity 1 the second write is rejected.
+    let proc = MultiGraphProcessor::new(Settings {
+      subgraph_queue_capacity: 1,
+      min_ops_before_swap:     1000,
+      ..Settings::default()
+    });
+    let write = |dst: &str| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        dst.into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind:  EdgeKind::Vote,
+        provenance: None,
+      }),
+    };
+
+    let response = proc.process_request(&write("U2")).await;
+    assert!(matches!(response, Response::Ok));
+    let response = proc.process_request(&write("U3")).await;
+    assert!(matches!(response, Response::Busy));
+
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::GetStats,
+      })
+      .await;
+    match response {
+      Response::Stats(stats) => {
+        assert_eq!(stats.queue_depth, 1);
+        assert_eq!(stats.queue_capacity, 1);
+      },
+      other => panic!("expected stats, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_latency_flushes_small_batches() {
+    // Without the latency bound, the writer would wait for 1000 ops and the
+    // sync would never complete.
+    let proc = MultiGraphProcessor::new_with_stats(
+      Settings {
+        min_ops_before_swap: 1000,
+        max_publish_latency: 10,
+        ..Settings::default()
+      },
+      Some(Arc::new(ProcessorStats::new(100))),
+    );
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::WriteEdge(OpWriteEdge {
+          src:        "U1".into(),
+          dst:        "U2".into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        }),
+      })
+      .await;
+    assert!(matches!(response, Response::Ok));
+    tokio::time::timeout(Duration::from_secs(5), sync(&proc))
+      .await
+      .expect("writes were not published");
+
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::GetStats,
+      })
+      .await;
+    match response {
+      Response::Stats(stats) => {
+        assert!(stats.publishes >= 1);
+        assert!(stats.published_ops >= 1);
+        assert!(stats.max_publish_batch < 1000);
+      },
+      other => panic!("expected stats, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn rejected_part_leaves_other_contexts_unchanged() {
+    let notify = Arc::new(tokio::sync::Notify::new());
+    let processor = |settings: Settings| {
+      GraphProcessor::new(
+        &String::new(),
+        AugGraph::new(settings.clone()),
+        10,
+        PublishPolicy::from_settings(&settings),
+        Arc::clone(&notify),
+        None,
+        0,
+      )
+    };
+    //  Waits for the published copy to get the edges written so far.
+    let published = |proc: &GraphProcessor, stamp: u64| {
+      let notify = Arc::clone(&notify);
+      let shared = Arc::clone(&proc.shared);
+      let sender = proc.op_sender.clone();
+      async move {
+        let _ = sender.send(AugGraphOp::Stamp(stamp)).await;
+        loop {
+          let n = notify.notified();
+          if shared.load().read().stamp >= stamp {
+            break;
+          }
+          n.await;
+        }
+        let graph = shared.load_full();
+        let weight =
+          |dst: &str| graph.read().edge_weight(&"U1".into(), &dst.into());
+        (weight("U3"), weight("U4"))
+      }
+    };
+    let edge = |dst: &str| OpWriteEdge {
+      src:        "U1".into(),
+      dst:        dst.into(),
+      amount:     1.0,
+      magnitude:  0,
+      edge_kind:  EdgeKind::Vote,
+      provenance: None,
+    };
+    let part = |id: u64, context: &str, barrier: &Arc<PublishBarrier>| {
+      let counted = vec![edge("U3")];
+      let gate = PublishGate::new(id, &context.into(), counted, barrier);
+      AugGraphOp::Staged(vec![AugGraphOp::WriteEdge(edge("U3"))], gate)
+    };
+
+    //  Publishes in batches, so the part comes while an edge is not published.
+    let accepting = processor(Settings {
+      min_ops_before_swap: 1000,
+      max_publish_latency: 50,
+      ..Settings::default()
+    });
+    //  Has room for the nodes of one edge only.
+    let rejecting = processor(Settings {
+      max_context_nodes: 2,
+      ..Settings::default()
+    });
+    let _ = rejecting
+      .op_sender
+      .send(AugGraphOp::WriteEdge(edge("U2")))
+      .await;
+    let _ = accepting
+      .op_sender
+      .send(AugGraphOp::WriteEdge(edge("U4")))
+      .await;
+
+    let (barrier, response) = PublishBarrier::new(2);
+    let _ = accepting.op_sender.send(part(1, "A", &barrier)).await;
+    let _ = rejecting.op_sender.send(part(1, "B", &barrier)).await;
+    match response.await {
+      Ok(Response::Error(ServiceError::QuotaExceeded(context, limit))) => {
+        assert_eq!(context, "B");
+        assert_eq!(limit, QuotaLimit::Nodes);
+      },
+      other => panic!("expected a rejected part, got {:?}", other),
+    }
+    assert_eq!(published(&accepting, 1).await, (0.0, 1.0));
+    assert_eq!(published(&rejecting, 1).await, (0.0, 0.0));
+
+    //  Both copies dropped the part, and the next one is published.
+    let (barrier, response) = PublishBarrier::new(1);
+    let _ = accepting.op_sender.send(part(2, "A", &barrier)).await;
+    assert!(matches!(response.await, Ok(Response::Ok)));
+    assert_eq!(published(&accepting, 2).await, (1.0, 1.0));
+    assert_eq!(published(&accepting, 3).await, (1.0, 1.0));
+  }
+
+  #[tokio::test]
+  async fn nonblocking() {
+    let notify = Arc::new(tokio::sync::Notify::new());
+    let proc = GraphProcessor::new(
+      &String::new(),
+      AugGraph::new(Settings::default()),
+      10,
+      PublishPolicy::from_settings(&Settings::default()),
+      Arc::clone(&notify),
+      None,
+      0,
+    );
+    let _ = proc.op_sender.send(AugGraphOp::Stamp(1)).await;
+    let _ = proc.op_sender.send(AugGraphOp::Stamp(2)).await;
+    let _ = proc.op_sender.send(AugGraphOp::Stamp(3)).await;
+
+    for _ in 0..20 {
+      let n = notify.notified();
+      let s = proc.shared.load().read().stamp;
+      if s >= 3 {
+        break;
+      }
+      n.await;
+    }
+    assert_eq!(proc.shared.load().read().stamp, 3);
+    proc.shutdown().ok();
+  }
+
+  #[tokio::test]
+  async fn drained_ops_are_published_in_bounded_batches() {
+    let notify = Arc::new(tokio::sync::Notify::new());
+    let stats = Arc::new(ProcessorStats::new(100));
+    let proc = GraphProcessor::new(
+      &String::new(),
+      AugGraph::new(Settings::default()),
+      100,
+      PublishPolicy {
+        min_ops:     1,
+        max_ops:     2,
+        max_latency: None,
+      },
+      Arc::clone(&notify),
+      Some(Arc::clone(&stats)),
+      0,
+    );
+    for stamp in 1..=20 {
+      let _ = proc.op_sender.send(AugGraphOp::Stamp(stamp)).await;
+    }
+
+    loop {
+      let n = notify.notified();
+      if proc.shared.load().read().stamp >= 20 {
+        break;
+      }
+      n.await;
+    }
+    let snap = stats.snapshot();
+    assert!(snap.publishes >= 10);
+    assert!(snap.max_batch <= 2);
+    proc.shutdown().ok();
+  }
+}
@@ -0,0 +1,1116 @@
+use crate::data::*;
+use crate::node_registry::*;
+use crate::op_reply::OpReply;
+use crate::publish_barrier::*;
+use crate::utils::log::*;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use super::validation::multi_edge;
+use super::{FanoutSender, MultiGraphProcessor};
+
+impl MultiGraphProcessor {
+  pub(crate) async fn process_write_edge(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: &OpWriteEdge,
+  ) -> Response {
+    log_trace!("{:?} {:?}", subgraph_name, data);
+
+    let scaled = OpWriteEdge {
+      amount: data.amount * self.settings.edge_kind_weight(data.edge_kind),
+      ..data.clone()
+    };
+    let data = &scaled;
+
+    if let Err(e) = self.check_quota(subgraph_name, data) {
+      log_warning!("Write rejected: {:?}", e);
+      return Response::Error(e);
+    }
+    if let Err(e) = self.check_edge_rate(subgraph_name, data) {
+      log_warning!("Write rejected: {:?}", e);
+      return Response::Error(e);
+    }
+
+    let src_kind_opt = node_kind_from_prefix(&data.src);
+    let dst_kind_opt = node_kind_from_prefix(&data.dst);
+
+    let response = match (src_kind_opt, dst_kind_opt) {
+      (Some(NodeKind::User), Some(NodeKind::User)) => {
+        self
+          .process_user_to_user_edge(subgraph_name, data)
+          .await
+      },
+
+      (Some(NodeKind::User), Some(NodeKind::PollVariant))
+      | (Some(NodeKind::PollVariant), Some(NodeKind::Poll)) => {
+        self.process_poll_edge(subgraph_name, data).await
+      },
+      (Some(src_kind), Some(dst_kind))
+        if src_kind == NodeKind::PollVariant
+          || src_kind == NodeKind::Poll
+          || dst_kind == NodeKind::PollVariant
+          || dst_kind == NodeKind::Poll =>
+      {
+        log_error!("Unexpected edge type: {:?} -> {:?} in context {:?}. No action taken.", src_kind_opt, dst_kind_opt, subgraph_name);
+        Response::Fail
+      },
+      _ => {
+        let op = AugGraphOp::WriteEdge(data.clone());
+        let senders = [
+          self.get_tx_channel(subgraph_name),
+          self.get_tx_channel(&String::new()),
+        ];
+        self.try_send_op_all(&senders, op)
+      },
+    };
+    response
+  }
+
+  /// Writes the edges of all parts, each to the contexts `WriteEdge` sends
+  /// it to. Every part is validated and rate checked, the quotas of the parts
+  /// are checked together, and a slot is reserved in the write queue of every
+  /// context, before anything is sent: if any part is rejected, no context is
+  /// changed.
+  ///
+  /// The ops of a context are sent as one `Staged` op. Each context checks
+  /// its part against its limits with the writes queued before it, and the
+  /// parts are published only if all the contexts accept theirs, see
+  /// `PublishBarrier`. Answers `Ok` once they are all published. Otherwise
+  /// no context is changed, and the request is answered with the reason:
+  /// the limit a part exceeds, `Timeout` if a context did not get to its
+  /// part within `STAGED_VOTE_TIMEOUT_MSEC`, or `Fail` if a part panicked.
+  pub(crate) async fn process_write_multi(
+    &self,
+    data: OpWriteMulti,
+  ) -> Response {
+    log_trace!("{:?}", data);
+
+    let mut parts = Vec::with_capacity(data.ops.len());
+    for (context, op) in &data.ops {
+      let edge = match multi_edge(op) {
+        Some(x) => x,
+        None => return Response::Fail,
+      };
+      let edge = OpWriteEdge {
+        amount: edge.amount * self.settings.edge_kind_weight(edge.edge_kind),
+        ..edge
+      };
+      parts.push((context, edge));
+    }
+    for (context, _) in &parts {
+      self.reload_if_evicted(context).await;
+    }
+    for (context, edge) in &parts {
+      if let Err(e) = self.check_edge_rate(context, edge) {
+        log_warning!("Write rejected: {:?}", e);
+        return Response::Error(e);
+      }
+    }
+    let writes: Vec<_> = parts.iter().map(|(x, edge)| (*x, edge)).collect();
+    if let Err(e) = self.check_quota_batch(&writes) {
+      log_warning!("Write rejected: {:?}", e);
+      return Response::Error(e);
+    }
+
+    //  Contexts are created first, so that they get the user-to-user edges
+    //  of the other parts.
+    self.insert_subgraph_if_does_not_exist(&String::new());
+    for (context, _) in &parts {
+      self.insert_subgraph_if_does_not_exist(context);
+    }
+
+    //  The ops of each context, and the edges counted against its quotas.
+    let mut batches: BTreeMap<SubgraphName, (Vec<AugGraphOp>, Vec<_>)> =
+      BTreeMap::new();
+    for (context, edge) in parts {
+      let user_edge = node_kind_from_prefix(&edge.src) == Some(NodeKind::User)
+        && node_kind_from_prefix(&edge.dst) == Some(NodeKind::User);
+      let mut targets: Vec<SubgraphName> = if user_edge {
+        self.subgraphs_map.iter().map(|r| r.key().clone()).collect()
+      } else {
+        vec![context.clone(), String::new()]
+      };
+      targets.dedup();
+      for target in targets {
+        let (ops, counted) = batches.entry(target.clone()).or_default();
+        ops.push(AugGraphOp::WriteEdge(edge.clone()));
+        if target == *context {
+          counted.push(edge.clone());
+        }
+      }
+    }
+
+    let (barrier, response) = PublishBarrier::new(batches.len());
+    let (senders, parts): (Vec<FanoutSender>, Vec<_>) = batches
+      .into_iter()
+      .map(|(context, part)| (self.get_tx_channel(&context), (context, part)))
+      .unzip();
+
+    //  Writers wait for each other at the barrier, so all of them must get
+    //  the parts of concurrent requests in the same order.
+    let sent = {
+      let mut last_id = self.multi_write_order.lock();
+      *last_id += 1;
+      let id = *last_id;
+      if let Some(replication) = &self.replication {
+        let replication = Arc::clone(replication);
+        barrier.on_decide(move |publish| {
+          let record = ReplicationRecord::StagedOutcome(id, publish);
+          replication.append(&String::new(), record, || {});
+        });
+      }
+      let ops = parts.into_iter().map(|(context, (ops, counted))| {
+        let gate = PublishGate::new(id, &context, counted, &barrier);
+        AugGraphOp::Staged(ops, gate)
+      });
+      self.try_send_ops(senders.iter().zip(ops))
+    };
+    drop(barrier);
+    if !matches!(sent, Response::Ok) {
+      return sent;
+    }
+    match response.await {
+      Ok(response) => response,
+      Err(_) => {
+        log_error!("Contexts of the write were dropped before they voted");
+        Response::Fail
+      },
+    }
+  }
+
+  /// Writes the node to the context and to the default one, like edges.
+  pub(crate) fn process_write_node(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: OpWriteNode,
+  ) -> Response {
+    let max_nodes = self.settings.max_context_nodes;
+    let exceeded = max_nodes > 0
+      && self.subgraphs_map.get(subgraph_name).is_some_and(|entry| {
+        let arc = entry.shared.load_full();
+        let aug_graph = arc.read();
+        aug_graph.nodes.get_by_name(&data.name).is_none()
+          && aug_graph.nodes.len() >= max_nodes
+      });
+    if exceeded {
+      let e =
+        ServiceError::QuotaExceeded(subgraph_name.clone(), QuotaLimit::Nodes);
+      log_warning!("Write rejected: {:?}", e);
+      return Response::Error(e);
+    }
+
+    let senders = [
+      self.get_tx_channel(subgraph_name),
+      self.get_tx_channel(&String::new()),
+    ];
+    self.try_send_op_all(&senders, AugGraphOp::WriteNode(data))
+  }
+
+  /// Writes the edge if its weight in the context is the expected one. The
+  /// writer compares the weight as it applies the op, after the writes
+  /// queued before it, and answers whether the edge was set. The edge then
+  /// goes to the other contexts that get it, as with `WriteEdge`.
+  pub(crate) async fn process_write_edge_cas(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: OpWriteEdgeCas,
+  ) -> Response {
+    log_trace!("{:?} {:?}", subgraph_name, data);
+
+    self.reload_if_evicted(subgraph_name).await;
+    //  Weights are compared and written scaled, as `WriteEdge` stores them.
+    let factor = self.settings.edge_kind_weight(data.edge_kind);
+    let data = OpWriteEdgeCas {
+      expected_old_weight: data.expected_old_weight * factor,
+      new_weight:          data.new_weight * factor,
+      ..data
+    };
+    let write = OpWriteEdge {
+      src:        data.src.clone(),
+      dst:        data.dst.clone(),
+      amount:     data.new_weight,
+      magnitude:  0,
+      edge_kind:  data.edge_kind,
+      provenance: None,
+    };
+    if let Err(e) = self
+      .check_quota(subgraph_name, &write)
+      .and_then(|()| self.check_edge_rate(subgraph_name, &write))
+    {
+      log_warning!("Write rejected: {:?}", e);
+      return Response::Error(e);
+    }
+
+    let (reply, applied) = OpReply::new();
+    let op = AugGraphOp::WriteEdgeCas(data.clone(), reply);
+    match self.send_op(subgraph_name, op).await {
+      Response::Ok => {},
+      other => return other,
+    }
+    match applied.await {
+      Ok(true) => {},
+      Ok(false) => {
+        let e = ServiceError::WriteConflict(subgraph_name.clone());
+        return Response::Error(e);
+      },
+      Err(_) => {
+        log_error!("Compare-and-set of the edge was dropped unapplied");
+        return Response::Fail;
+      },
+    }
+
+    let user_edge = node_kind_from_prefix(&data.src) == Some(NodeKind::User)
+      && node_kind_from_prefix(&data.dst) == Some(NodeKind::User);
+    let senders: Vec<FanoutSender> = if user_edge {
+      self
+        .subgraphs_map
+        .iter()
+        .filter(|r| r.key() != subgraph_name)
+        .map(|r| r.value().op_sender.clone())
+        .collect()
+    } else if subgraph_name.is_empty() {
+      vec![]
+    } else {
+      vec![self.get_tx_channel(&String::new())]
+    };
+    if senders.is_empty() {
+      return Response::Ok;
+    }
+    self.try_send_op_all(&senders, AugGraphOp::WriteEdge(write))
+  }
+
+  /// Votes and options of polls are only written to the context. Those of a
+  /// closed poll, and repeated votes in an anonymous poll, are rejected, see
+  /// `WritePoll`.
+  async fn process_poll_edge(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: &OpWriteEdge,
+  ) -> Response {
+    self.reload_if_evicted(subgraph_name).await;
+    let (closed, voted) = self
+      .read_published(subgraph_name, |aug_graph| {
+        (aug_graph.closed_poll(data), aug_graph.already_voted(data))
+      })
+      .unwrap_or((None, None));
+    if let Some(poll) = closed {
+      log_warning!("Write rejected: poll {:?} is closed", poll);
+      return Response::Error(ServiceError::PollClosed(poll));
+    }
+    if let Some(poll) = voted {
+      log_warning!(
+        "Write rejected: {:?} already voted in {:?}",
+        data.src,
+        poll
+      );
+      return Response::Error(ServiceError::AlreadyVoted(poll));
+    }
+    self
+      .send_op(subgraph_name, AugGraphOp::WriteEdge(data.clone()))
+      .await
+  }
+
+  async fn process_user_to_user_edge(
+    &self,
+    subgraph_name: &SubgraphName,
+    data: &OpWriteEdge,
+  ) -> Response {
+    log_trace!();
+
+    self.insert_subgraph_if_does_not_exist(subgraph_name);
+
+    let senders: Vec<FanoutSender> = self
+      .subgraphs_map
+      .iter()
+      .map(|r| r.value().op_sender.clone())
+      .collect();
+
+    self.try_send_op_all(&senders, AugGraphOp::WriteEdge(data.clone()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::aug_graph::AugGraph;
+  use crate::settings::*;
+  use crate::state_manager::test_utils::*;
+
+  #[tokio::test]
+  async fn user_params_apply_to_own_scores() {
+    let proc = default_processor();
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    let write =
+      |dst: &str, amount: Weight| write_edge_req("", "U1", dst, amount);
+    let _ = proc.process_request(&write("U2", 1.0)).await;
+    let _ = proc.process_request(&write("U3", -1.0)).await;
+    let response = proc
+      .process_request(&request(ReqData::SetUserParams(OpSetUserParams {
+        ego:         "U1".into(),
+        alpha:       Some(0.5),
+        walk_policy: WalkPolicy::PositiveOnly,
+      })))
+      .await;
+    assert!(matches!(response, Response::Ok));
+    sync(&proc).await;
+
+    let scores = match proc
+      .process_request(&request(ReqData::ReadScores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions::default(),
+      })))
+      .await
+    {
+      Response::Scores(ResScores { scores }) => scores,
+      other => panic!("expected scores, got {:?}", other),
+    };
+    assert!(scores.iter().any(|s| s.target == "U2" && s.score > 0.0));
+    assert!(scores.iter().all(|s| s.target != "U3" || s.score >= 0.0));
+
+    let snapshot = match proc
+      .process_request(&request(ReqData::ReadContextSnapshot))
+      .await
+    {
+      Response::ContextSnapshot(snapshot) => snapshot,
+      other => panic!("expected snapshot, got {:?}", other),
+    };
+    assert_eq!(snapshot.user_params.len(), 1);
+    assert_eq!(snapshot.user_params[0].alpha, Some(0.5));
+    assert_eq!(snapshot.user_params[0].walk_policy, WalkPolicy::PositiveOnly);
+
+    let response = proc
+      .process_request(&request(ReqData::SetUserParams(OpSetUserParams {
+        ego:         "U1".into(),
+        alpha:       Some(2.0),
+        walk_policy: WalkPolicy::Default,
+      })))
+      .await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::InvalidWrite(InvalidWrite::AlphaOutOfRange))
+    ));
+  }
+
+  #[tokio::test]
+  async fn write_node_sets_kind_and_flags() {
+    let proc = default_processor();
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    for (name, kind, flags) in
+      [("B1", NodeKind::Beacon, 1), ("C1", NodeKind::Opinion, 0)]
+    {
+      let response = proc
+        .process_request(&request(ReqData::WriteNode(OpWriteNode {
+          name: name.into(),
+          kind,
+          flags,
+        })))
+        .await;
+      assert!(matches!(response, Response::Ok));
+    }
+    let _ = proc
+      .process_request(&write_edge_req("", "U1", "C1", 1.0))
+      .await;
+    sync(&proc).await;
+
+    let snapshot = match proc
+      .process_request(&request(ReqData::ReadContextSnapshot))
+      .await
+    {
+      Response::ContextSnapshot(snapshot) => snapshot,
+      other => panic!("expected snapshot, got {:?}", other),
+    };
+    let mut nodes: Vec<(String, NodeKind, u32)> = snapshot
+      .nodes
+      .iter()
+      .map(|x| (x.name.clone(), x.kind, x.flags))
+      .collect();
+    nodes.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+      nodes,
+      vec![
+        ("B1".to_string(), NodeKind::Beacon, 1),
+        ("C1".to_string(), NodeKind::Opinion, 0),
+      ]
+    );
+
+    //  The edge found the node with its explicit kind.
+    let rebuilt = AugGraph::from_snapshot(Settings::default(), snapshot);
+    let c1 = rebuilt.nodes.get_by_name("C1").unwrap();
+    assert_eq!(c1.kind, NodeKind::Opinion);
+    assert_eq!(rebuilt.edge_count(), 1);
+
+    let response = proc
+      .process_request(&request(ReqData::WriteNode(OpWriteNode {
+        name:  "X1".into(),
+        kind:  NodeKind::User,
+        flags: 0,
+      })))
+      .await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::InvalidWrite(
+        InvalidWrite::UnknownNodeKind(_)
+      ))
+    ));
+  }
+
+  #[tokio::test]
+  async fn write_edge_cas_detects_conflicts() {
+    let proc = default_processor();
+    let subgraph: SubgraphName = "news".into();
+    let cas = |old: f64, new: f64| Request {
+      subgraph: subgraph.clone(),
+      token:    String::new(),
+      data:     ReqData::WriteEdgeCas(OpWriteEdgeCas {
+        src:                 "U1".into(),
+        dst:                 "B1".into(),
+        expected_old_weight: old,
+        new_weight:          new,
+        edge_kind:           EdgeKind::Vote,
+      }),
+    };
+
+    let response = proc.process_request(&cas(0.0, 1.0)).await;
+    assert!(matches!(response, Response::Ok));
+    assert!(matches!(
+      proc.process_request(&cas(0.0, 2.0)).await,
+      Response::Error(ServiceError::WriteConflict(_))
+    ));
+    let response = proc.process_request(&cas(1.0, 2.0)).await;
+    assert!(matches!(response, Response::Ok));
+    proc.sync_future(proc.next_stamp()).await;
+
+    //  The edge is copied to the default context, as other edges are.
+    for context in [subgraph.clone(), String::new()] {
+      let weight = proc.read_published(&context, |aug_graph| {
+        aug_graph.edge_weight(&"U1".into(), &"B1".into())
+      });
+      assert_eq!(weight, Some(2.0));
+    }
+  }
+
+  #[tokio::test]
+  async fn write_edge_cas_counts_queued_writes() {
+    //  Nothing is published before 1000 ops.
+    let proc = MultiGraphProcessor::new(Settings {
+      min_ops_before_swap: 1000,
+      ..Settings::default()
+    });
+    let subgraph: SubgraphName = "news".into();
+    let cas = |old: f64, new: f64| Request {
+      subgraph: subgraph.clone(),
+      token:    String::new(),
+      data:     ReqData::WriteEdgeCas(OpWriteEdgeCas {
+        src:                 "U1".into(),
+        dst:                 "B1".into(),
+        expected_old_weight: old,
+        new_weight:          new,
+        edge_kind:           EdgeKind::Vote,
+      }),
+    };
+    let response = proc
+      .process_request(&write_edge_req(&subgraph, "U1", "B1", 1.0))
+      .await;
+    assert!(matches!(response, Response::Ok));
+
+    assert!(matches!(
+      proc.process_request(&cas(0.0, 2.0)).await,
+      Response::Error(ServiceError::WriteConflict(_))
+    ));
+    let response = proc.process_request(&cas(1.0, 2.0)).await;
+    assert!(matches!(response, Response::Ok));
+  }
+
+  #[tokio::test]
+  async fn write_edge_cas_uses_the_edge_kind() {
+    let proc = MultiGraphProcessor::new(Settings {
+      edge_kind_weights: vec![(EdgeKind::Follow, 2.0)],
+      ..Settings::default()
+    });
+    let subgraph: SubgraphName = "news".into();
+    let cas = |old: f64, new: f64, edge_kind: EdgeKind| Request {
+      subgraph: subgraph.clone(),
+      token:    String::new(),
+      data:     ReqData::WriteEdgeCas(OpWriteEdgeCas {
+        src:                 "U1".into(),
+        dst:                 "B1".into(),
+        expected_old_weight: old,
+        new_weight:          new,
+        edge_kind,
+      }),
+    };
+
+    let response = proc.process_request(&cas(0.0, 1.0, EdgeKind::Follow)).await;
+    assert!(matches!(response, Response::Ok));
+    //  The expected weight is before the factor of the kind.
+    let response = proc.process_request(&cas(1.0, 3.0, EdgeKind::Follow)).await;
+    assert!(matches!(response, Response::Ok));
+    assert!(matches!(
+      proc.process_request(&cas(3.0, 4.0, EdgeKind::Vote)).await,
+      Response::Error(ServiceError::WriteConflict(_))
+    ));
+    proc.sync_future(proc.next_stamp()).await;
+
+    let (weight, kind) = proc
+      .read_published(&subgraph, |aug_graph| {
+        let (src, dst) = ("U1".into(), "B1".into());
+        (aug_graph.edge_weight(&src, &dst), aug_graph.edge_kind(&src, &dst))
+      })
+      .unwrap();
+    assert_eq!(weight, 6.0);
+    assert_eq!(kind, EdgeKind::Follow);
+  }
+
+  #[tokio::test]
+  async fn edge_kinds_scale_weights_and_filter_neighbors() {
+    let proc = MultiGraphProcessor::new(Settings {
+      edge_kind_weights: vec![(EdgeKind::Follow, 2.0)],
+      ..Settings::default()
+    });
+    let write = |dst: &str, edge_kind: EdgeKind| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src:        "U1".into(),
+        dst:        dst.into(),
+        amount:     1.0,
+        magnitude:  0,
+        edge_kind,
+        provenance: None,
+      }),
+    };
+    let response = proc.process_request(&write("U2", EdgeKind::Vote)).await;
+    assert!(matches!(response, Response::Ok));
+    let response = proc.process_request(&write("U3", EdgeKind::Follow)).await;
+    assert!(matches!(response, Response::Ok));
+    proc.sync_future(proc.next_stamp()).await;
+
+    let (vote, follow) = proc
+      .read_published(&String::new(), |aug_graph| {
+        let weight =
+          |dst: &str| aug_graph.edge_weight(&"U1".into(), &dst.into());
+        (weight("U2"), weight("U3"))
+      })
+      .unwrap();
+    assert!((follow - 2.0 * vote).abs() < 1e-9);
+
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadNeighbors(OpReadNeighbors {
+          ego:           "U1".into(),
+          focus:         "U1".into(),
+          direction:     NEIGHBORS_OUTBOUND,
+          kind:          None,
+          edge_kind:     Some(EdgeKind::Follow),
+          hide_personal: false,
+          lt:            f64::MAX,
+          lte:           true,
+          gt:            f64::MIN,
+          gte:           true,
+          index:         0,
+          count:         u32::MAX,
+        }),
+      })
+      .await;
+    match response {
+      Response::Scores(ResScores { scores }) => {
+        let targets: Vec<&str> =
+          scores.iter().map(|x| x.target.as_str()).collect();
+        assert_eq!(targets, vec!["U3"]);
+      },
+      _ => panic!("expected scores"),
+    }
+  }
+
+  #[tokio::test]
+  async fn edge_provenance_is_returned_by_neighbors() {
+    let proc = MultiGraphProcessor::new(Settings::default());
+    let write = |dst: &str, provenance: Option<EdgeProvenance>| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteEdge(OpWriteEdge {
+        src: "U1".into(),
+        dst: dst.into(),
+        amount: 1.0,
+        magnitude: 0,
+        edge_kind: EdgeKind::Vote,
+        provenance,
+      }),
+    };
+    let provenance = |external_id: &str| EdgeProvenance {
+      source:      "forum".into(),
+      external_id: external_id.into(),
+      timestamp:   1_700_000_000,
+    };
+    let read = || Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::ReadNeighbors(OpReadNeighbors {
+        ego:           "U1".into(),
+        focus:         "U1".into(),
+        direction:     NEIGHBORS_OUTBOUND,
+        kind:          None,
+        edge_kind:     None,
+        hide_personal: false,
+        lt:            f64::MAX,
+        lte:           true,
+        gt:            f64::MIN,
+        gte:           true,
+        index:         0,
+        count:         u32::MAX,
+      }),
+    };
+    let provenance_of = |response: Response, target: &str| match response {
+      Response::Scores(ResScores { scores }) => scores
+        .into_iter()
+        .find(|x| x.target == target)
+        .and_then(|x| x.provenance),
+      _ => panic!("expected scores"),
+    };
+
+    let response =
+      proc.process_request(&write("U2", Some(provenance("v1")))).await;
+    assert!(matches!(response, Response::Ok));
+    let response = proc.process_request(&write("U3", None)).await;
+    assert!(matches!(response, Response::Ok));
+    proc.sync_future(proc.next_stamp()).await;
+
+    let response = proc.process_request(&read()).await;
+    assert_eq!(provenance_of(response, "U2"), Some(provenance("v1")));
+    let response = proc.process_request(&read()).await;
+    assert_eq!(provenance_of(response, "U3"), None);
+
+    //  A write without provenance clears it.
+    let response = proc.process_request(&write("U2", None)).await;
+    assert!(matches!(response, Response::Ok));
+    proc.sync_future(proc.next_stamp()).await;
+    let response = proc.process_request(&read()).await;
+    assert_eq!(provenance_of(response, "U2"), None);
+
+    let long = provenance(&"x".repeat(MAX_PROVENANCE_LEN + 1));
+    let response = proc.process_request(&write("U2", Some(long))).await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::InvalidWrite(
+        InvalidWrite::ProvenanceTooLong
+      ))
+    ));
+  }
+
+  #[tokio::test]
+  async fn write_multi_applies_all_parts_or_none() {
+    let proc = MultiGraphProcessor::new(Settings {
+      max_context_edges: 1,
+      ..Settings::default()
+    });
+    let request = |subgraph: &str, data: ReqData| Request {
+      subgraph: subgraph.into(),
+      token: String::new(),
+      data,
+    };
+    let edge = |src: &str, dst: &str| write_edge_req("", src, dst, 1.0).data;
+    let multi = |ops: Vec<(&str, ReqData)>| {
+      request(
+        "",
+        ReqData::WriteMulti(OpWriteMulti {
+          ops: ops.into_iter().map(|(ctx, op)| (ctx.into(), op)).collect(),
+        }),
+      )
+    };
+    let read_edges = |subgraph: &str| request(subgraph, ReqData::ReadEdges);
+
+    let _ = proc
+      .process_request(&write_edge_req("B", "U1", "B1", 1.0))
+      .await;
+    sync(&proc).await;
+
+    //  "B" is full, so the part of "A" is not applied either.
+    let response = proc
+      .process_request(&multi(vec![
+        ("A", edge("U1", "B2")),
+        ("B", edge("U1", "B3")),
+      ]))
+      .await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::QuotaExceeded(_, QuotaLimit::Edges))
+    ));
+    sync(&proc).await;
+    let response = proc.process_request(&read_edges("A")).await;
+    assert!(edges_from_response(response).is_empty());
+
+    let response = proc
+      .process_request(&multi(vec![
+        ("A", edge("U1", "B2")),
+        ("C", edge("U1", "B3")),
+      ]))
+      .await;
+    assert!(matches!(response, Response::Ok));
+    //  Both contexts are published by the time the write is answered.
+    for (ctx, dst) in [("A", "B2"), ("C", "B3")] {
+      let response = proc.process_request(&read_edges(ctx)).await;
+      assert_eq!(
+        edges_from_response(response),
+        vec![("U1".into(), dst.into(), 1.0)]
+      );
+    }
+
+    let response = proc
+      .process_request(&multi(vec![(
+        "A",
+        ReqData::WriteCalculate(OpWriteCalculate {
+          ego: "U1".into(),
+        }),
+      )]))
+      .await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::InvalidWrite(InvalidWrite::InvalidMulti))
+    ));
+  }
+
+  #[tokio::test]
+  async fn write_multi_checks_quota_of_the_whole_batch() {
+    let proc = MultiGraphProcessor::new(Settings {
+      max_context_nodes: 3,
+      ..Settings::default()
+    });
+    let edge = |src: &str, dst: &str| write_edge_req("", src, dst, 1.0).data;
+    let request = |ops: Vec<ReqData>| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data:     ReqData::WriteMulti(OpWriteMulti {
+        ops: ops.into_iter().map(|op| ("A".into(), op)).collect(),
+      }),
+    };
+
+    //  Each part adds 2 nodes, under the limit, but together they add 4.
+    let response = proc
+      .process_request(&request(vec![edge("U1", "B1"), edge("U2", "B2")]))
+      .await;
+    assert!(matches!(
+      response,
+      Response::Error(ServiceError::QuotaExceeded(_, QuotaLimit::Nodes))
+    ));
+
+    //  A node new to several parts is counted once.
+    let response = proc
+      .process_request(&request(vec![edge("U1", "B1"), edge("U1", "B2")]))
+      .await;
+    assert!(matches!(response, Response::Ok));
+  }
+
+  #[tokio::test]
+  async fn muted_nodes_are_hidden_from_ego_only() {
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 100,
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    for (src, dst) in [("U1", "B1"), ("U2", "B1"), ("U1", "U2")] {
+      let _ = proc.process_request(&write_edge_req("", src, dst, 1.0)).await;
+    }
+    let mute = |muted: bool| {
+      request(ReqData::WriteMute(OpWriteMute {
+        ego: "U1".into(),
+        node: "B1".into(),
+        muted,
+      }))
+    };
+    assert!(matches!(proc.process_request(&mute(true)).await, Response::Ok));
+    sync(&proc).await;
+
+    let read = |ego: &str| {
+      request(ReqData::ReadScores(OpReadScores {
+        ego:           ego.into(),
+        score_options: FilterOptions::default(),
+      }))
+    };
+    let has_b1 = |response: Response| match response {
+      Response::Scores(ResScores { scores }) => {
+        scores.iter().any(|x| x.target == "B1")
+      },
+      other => panic!("expected scores, got {:?}", other),
+    };
+    assert!(!has_b1(proc.process_request(&read("U1")).await));
+    assert!(has_b1(proc.process_request(&read("U2")).await));
+
+    let read_mutes = request(ReqData::ReadMutes(OpReadMutes {
+      ego: "U1".into(),
+    }));
+    match proc.process_request(&read_mutes).await {
+      Response::NodeList(ResNodeList { nodes }) => {
+        assert_eq!(nodes, vec![("B1".to_string(),)]);
+      },
+      other => panic!("expected nodes, got {:?}", other),
+    }
+
+    let _ = proc.process_request(&mute(false)).await;
+    sync(&proc).await;
+    assert!(has_b1(proc.process_request(&read("U1")).await));
+  }
+
+  #[tokio::test]
+  async fn expired_polls_reject_votes_and_are_archived() {
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 100,
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    let edge = |src: &str, dst: &str| write_edge_req("", src, dst, 1.0);
+    for (src, dst) in [
+      ("V1", "P1"),
+      ("V2", "P1"),
+      ("U1", "U2"),
+      ("U2", "U1"),
+      ("U1", "V1"),
+      ("U2", "V2"),
+    ] {
+      let response = proc.process_request(&edge(src, dst)).await;
+      assert!(matches!(response, Response::Ok));
+    }
+    for node in ["U1", "U2"] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteZeroOpinion(
+          OpWriteZeroOpinion {
+            node:  node.into(),
+            score: 0.5,
+          },
+        )))
+        .await;
+    }
+    sync(&proc).await;
+
+    let read = request(ReqData::ReadNeighbors(OpReadNeighbors {
+      ego:           "U1".into(),
+      focus:         "P1".into(),
+      direction:     NEIGHBORS_INBOUND,
+      kind:          Some(NodeKind::PollVariant),
+      edge_kind:     None,
+      hide_personal: false,
+      lt:            f64::MAX,
+      lte:           true,
+      gt:            f64::MIN,
+      gte:           true,
+      index:         0,
+      count:         u32::MAX,
+    }));
+    let results = |response: Response| match response {
+      Response::Scores(ResScores { scores }) => scores
+        .into_iter()
+        .map(|x| (x.target, x.score, x.reverse_score, x.reverse_cluster))
+        .collect::<Vec<_>>(),
+      other => panic!("expected scores, got {:?}", other),
+    };
+    let live = results(proc.process_request(&read).await);
+    assert_eq!(live.len(), 2);
+    //  U1 only scores U2 among the other voters.
+    assert_eq!((live[1].0.as_str(), live[1].1), ("V2", 1.0));
+    assert_eq!(live[0].2 + live[1].2, 1.0);
+
+    let expire = request(ReqData::WritePoll(OpWritePoll {
+      poll:       "P1".into(),
+      expires_at: 1,
+      anonymous:  false,
+    }));
+    assert!(matches!(proc.process_request(&expire).await, Response::Ok));
+    sync(&proc).await;
+    assert!(matches!(
+      proc.process_request(&edge("U1", "V2")).await,
+      Response::Error(ServiceError::PollClosed(poll)) if poll == "P1"
+    ));
+
+    assert_eq!(proc.archive_polls().await, 1);
+    sync(&proc).await;
+    assert_eq!(proc.archive_polls().await, 0);
+    let frozen = results(proc.process_request(&read).await);
+    assert_eq!(frozen.len(), 2);
+    for (row, live) in frozen.iter().zip(&live) {
+      assert_eq!(row.0, live.0);
+      assert_eq!((row.1, row.2, row.3), (live.2, live.2, 1));
+    }
+
+    match proc.process_request(&request(ReqData::ReadContextSnapshot)).await {
+      Response::ContextSnapshot(snapshot) => {
+        assert_eq!(snapshot.polls.len(), 1);
+        assert!(snapshot.polls[0].votes.is_empty());
+        assert_eq!(snapshot.polls[0].results.as_ref().unwrap().len(), 2);
+        let restored = AugGraph::from_snapshot(Settings::default(), snapshot);
+        let polls = restored.polls();
+        assert_eq!(polls.len(), 1);
+        assert_eq!(polls[0].results.as_ref().unwrap().len(), 2);
+      },
+      other => panic!("expected snapshot, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn anonymous_polls_keep_tallies_and_reject_second_votes() {
+    let proc = MultiGraphProcessor::new(Settings {
+      num_walks: 100,
+      ..Settings::default()
+    });
+    let request = |data: ReqData| Request {
+      subgraph: String::new(),
+      token:    String::new(),
+      data,
+    };
+    let edge = |src: &str, dst: &str| write_edge_req("", src, dst, 1.0);
+    for (src, dst) in [
+      ("V1", "P1"),
+      ("V2", "P1"),
+      ("U1", "U2"),
+      ("U2", "U1"),
+      ("U1", "V1"),
+    ] {
+      let response = proc.process_request(&edge(src, dst)).await;
+      assert!(matches!(response, Response::Ok));
+    }
+    for (node, score) in [("U1", 0.75), ("U2", 0.25)] {
+      let _ = proc
+        .process_request(&request(ReqData::WriteZeroOpinion(
+          OpWriteZeroOpinion {
+            node: node.into(),
+            score,
+          },
+        )))
+        .await;
+    }
+    //  The vote cast before the poll is made anonymous moves to its tally.
+    let anonymous = request(ReqData::WritePoll(OpWritePoll {
+      poll:       "P1".into(),
+      expires_at: 0,
+      anonymous:  true,
+    }));
+    assert!(matches!(proc.process_request(&anonymous).await, Response::Ok));
+    sync(&proc).await;
+
+    assert!(matches!(
+      proc.process_request(&edge("U2", "V2")).await,
+      Response::Ok
+    ));
+    sync(&proc).await;
+    for dst in ["V1", "V2"] {
+      assert!(matches!(
+        proc.process_request(&edge("U1", dst)).await,
+        Response::Error(ServiceError::AlreadyVoted(poll)) if poll == "P1"
+      ));
+    }
+
+    let read = request(ReqData::ReadNeighbors(OpReadNeighbors {
+      ego:           "U1".into(),
+      focus:         "P1".into(),
+      direction:     NEIGHBORS_INBOUND,
+      kind:          Some(NodeKind::PollVariant),
+      edge_kind:     None,
+      hide_personal: false,
+      lt:            f64::MAX,
+      lte:           true,
+      gt:            f64::MIN,
+      gte:           true,
+      index:         0,
+      count:         u32::MAX,
+    }));
+    match proc.process_request(&read).await {
+      Response::Scores(ResScores { scores }) => {
+        let rows: Vec<_> = scores
+          .into_iter()
+          .map(|x| (x.target, x.score, x.reverse_cluster))
+          .collect();
+        assert_eq!(
+          rows,
+          vec![("V1".to_string(), 0.75, 1), ("V2".to_string(), 0.25, 1)]
+        );
+      },
+      other => panic!("expected scores, got {:?}", other),
+    }
+
+    match proc.process_request(&request(ReqData::ReadContextSnapshot)).await {
+      Response::ContextSnapshot(snapshot) => {
+        let poll = &snapshot.polls[0];
+        assert!(poll.anonymous && poll.votes.is_empty());
+        assert_eq!(poll.tallies.len(), 2);
+        assert!(!snapshot.edges.iter().any(|x| x.dst.starts_with('V')));
+        let restored = AugGraph::from_snapshot(Settings::default(), snapshot);
+        let second = OpWriteEdge {
+          src:        "U2".into(),
+          dst:        "V1".into(),
+          amount:     1.0,
+          magnitude:  0,
+          edge_kind:  EdgeKind::Vote,
+          provenance: None,
+        };
+        assert_eq!(restored.already_voted(&second), Some("P1".into()));
+      },
+      other => panic!("expected snapshot, got {:?}", other),
+    }
+  }
+
+  #[tokio::test]
+  async fn dry_run_leaves_graph_unchanged() {
+    let proc = default_processor();
+    let _ = proc.process_request(&write_edge_req("", "U1", "U2", 1.0)).await;
+    let _ = proc.process_request(&write_edge_req("", "U2", "U3", 1.0)).await;
+    sync(&proc).await;
+
+    let response = proc
+      .process_request(&Request {
+        subgraph: String::new(),
+        token:    String::new(),
+        data:     ReqData::ReadDryRun(OpReadDryRun {
+          ego:           "U1".into(),
+          edges:         vec![OpWriteEdge {
+            src:        "U1".into(),
+            dst:        "U2".into(),
+            amount:     0.0,
+            magnitude:  0,
+            edge_kind:  EdgeKind::Vote,
+            provenance: None,
+          }],
+          score_options: FilterOptions::default(),
+        }),
+      })
+      .await;
+    let nodes = match response {
+      Response::DryRun(res) => res.nodes,
+      other => panic!("expected dry run, got {:?}", other),
+    };
+    for target in ["U2", "U3"] {
+      let delta = nodes.iter().find(|n| n.target == target).unwrap();
+      assert!(delta.before > 0.0);
+      assert_eq!(delta.after, 0.0);
+    }
+
+    let edges = edges_from_response(
+      proc
+        .process_request(&Request {
+          subgraph: String::new(),
+          token:    String::new(),
+          data:     ReqData::ReadEdges,
+        })
+        .await,
+    );
+    assert_eq!(edges.len(), 2);
+  }
+}
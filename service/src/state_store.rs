@@ -0,0 +1,239 @@
+//! Saved state of the contexts, walks included, for warm restarts.
+//!
+//! On shutdown every context in memory is saved along with the walks of its
+//! calculated egos. On startup the contexts are rebuilt from their graphs and
+//! the walks are imported, so the first reads don't wait for `calculate`.
+//! Files are removed once loaded: after a crash the service starts cold
+//! rather than from a stale state.
+
+use crate::aug_graph::AugGraph;
+use crate::data::*;
+use crate::settings::Settings;
+use crate::utils::files::{context_file, write_atomic};
+use crate::utils::log::*;
+
+use bincode::{config::standard, decode_from_slice, encode_to_vec, Decode, Encode};
+use meritrank_core::{MeritRankError, NodeId, RandomWalk};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const EXTENSION: &str = "state";
+
+#[derive(Encode, Decode)]
+struct StoredWalk {
+  nodes:                  Vec<NodeId>,
+  negative_segment_start: Option<usize>,
+  positive_only:          bool,
+}
+
+#[derive(Encode, Decode)]
+struct StoredContext {
+  snapshot:  ContextSnapshot,
+  /// Node names by id at the time of saving; ids of the rebuilt graph may
+  /// differ.
+  nodes:     Vec<NodeName>,
+  num_walks: usize,
+  egos:      Vec<(NodeId, Vec<StoredWalk>)>,
+}
+
+pub struct StateStore {
+  dir: PathBuf,
+}
+
+fn to_io_error<E: std::fmt::Display>(e: E) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl StateStore {
+  pub fn new(dir: PathBuf) -> io::Result<Self> {
+    fs::create_dir_all(&dir)?;
+    Ok(StateStore {
+      dir,
+    })
+  }
+
+  fn path(
+    &self,
+    context: &SubgraphName,
+  ) -> PathBuf {
+    context_file(&self.dir, context, EXTENSION)
+  }
+
+  /// Saves the context with its walks; returns the number of egos saved.
+  pub fn save(
+    &self,
+    context: &SubgraphName,
+    aug_graph: &AugGraph,
+  ) -> io::Result<usize> {
+    let egos: Vec<(NodeId, Vec<StoredWalk>)> = aug_graph
+      .mr
+      .get_personal_hits()
+      .keys()
+      .map(|&ego| {
+        let walks = aug_graph
+          .mr
+          .get_ego_walks(ego)
+          .map(|walk| StoredWalk {
+            nodes:                  walk.nodes.clone(),
+            negative_segment_start: walk.negative_segment_start,
+            positive_only:          walk.positive_only,
+          })
+          .collect();
+        (ego, walks)
+      })
+      .collect();
+    let num_egos = egos.len();
+
+    let bytes = encode_to_vec(
+      StoredContext {
+        snapshot: aug_graph.snapshot(context.clone()),
+        nodes: aug_graph
+          .nodes
+          .id_to_info
+          .iter()
+          .map(|info| info.name.clone())
+          .collect(),
+        num_walks: aug_graph.settings.num_walks,
+        egos,
+      },
+      standard(),
+    )
+    .map_err(to_io_error)?;
+
+    write_atomic(&self.path(context), &bytes)?;
+    Ok(num_egos)
+  }
+
+  /// Rebuilds every saved context and removes its file. Files that fail to
+  /// load are logged and kept.
+  pub fn load_all(
+    &self,
+    settings: &Settings,
+  ) -> Vec<(SubgraphName, AugGraph)> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(x) => x,
+      Err(e) => {
+        log_error!("Failed to read state dir: {}", e);
+        return vec![];
+      },
+    };
+    let mut contexts = vec![];
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if path.extension().and_then(|x| x.to_str()) != Some(EXTENSION) {
+        continue;
+      }
+      match load_file(&path, settings) {
+        Ok(context) => {
+          if let Err(e) = fs::remove_file(&path) {
+            log_error!("Failed to remove {:?}: {}", path, e);
+          }
+          contexts.push(context);
+        },
+        Err(e) => log_error!("Failed to load {:?}: {}", path, e),
+      }
+    }
+    contexts
+  }
+}
+
+fn load_file(
+  path: &Path,
+  settings: &Settings,
+) -> io::Result<(SubgraphName, AugGraph)> {
+  let bytes = fs::read(path)?;
+  let (stored, _): (StoredContext, usize) =
+    decode_from_slice(&bytes, standard()).map_err(to_io_error)?;
+
+  let context = stored.snapshot.context.clone();
+  let mut aug_graph =
+    AugGraph::from_snapshot(settings.clone(), stored.snapshot);
+  if stored.num_walks != settings.num_walks {
+    log_warning!(
+      "Walks of context {:?} not loaded: saved with {} walks per ego",
+      context,
+      stored.num_walks
+    );
+    return Ok((context, aug_graph));
+  }
+
+  let ids: Vec<Option<NodeId>> = stored
+    .nodes
+    .iter()
+    .map(|name| aug_graph.nodes.get_by_name(name).map(|info| info.id))
+    .collect();
+  let new_id = |id: NodeId| ids.get(id).copied().flatten();
+
+  let mut num_egos = 0;
+  for (ego, walks) in stored.egos {
+    let walks: Option<Vec<RandomWalk>> = walks
+      .into_iter()
+      .map(|walk| {
+        let nodes = walk.nodes.into_iter().map(new_id).collect::<Option<_>>()?;
+        Some(RandomWalk {
+          nodes,
+          negative_segment_start: walk.negative_segment_start,
+          positive_only: walk.positive_only,
+        })
+      })
+      .collect();
+    let result = match (new_id(ego), walks) {
+      (Some(ego_id), Some(walks)) => aug_graph.import_walks(ego_id, walks),
+      _ => Err(MeritRankError::NodeNotFound),
+    };
+    match result {
+      Ok(()) => num_egos += 1,
+      Err(e) => {
+        log_warning!("Walks of ego {} in {:?} not loaded: {}", ego, context, e);
+      },
+    }
+  }
+  log_verbose!("Loaded context {:?} with {} egos", context, num_egos);
+  Ok((context, aug_graph))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+      .join(format!("meritrank-state-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+  }
+
+  #[test]
+  fn save_and_load_keep_walks() {
+    let store = StateStore::new(temp_dir("roundtrip")).unwrap();
+    let settings = Settings {
+      num_walks: 100,
+      ..Settings::default()
+    };
+    let context: SubgraphName = "ctx/1".into();
+
+    let mut aug_graph = AugGraph::new(settings.clone());
+    aug_graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+    aug_graph.set_edge("U2".into(), "B1".into(), 1.0, 0);
+    aug_graph.calculate("U1".into());
+    assert_eq!(store.save(&context, &aug_graph).unwrap(), 1);
+
+    let mut contexts = store.load_all(&settings);
+    assert_eq!(contexts.len(), 1);
+    let (name, loaded) = contexts.pop().unwrap();
+    assert_eq!(name, context);
+    let ego = loaded.nodes.get_by_name("U1").unwrap().id;
+    assert_eq!(loaded.mr.get_ego_walks(ego).count(), 100);
+    let score = |graph: &AugGraph, target: &str| {
+      let target = graph.nodes.get_by_name(target).unwrap().id;
+      let ego = graph.nodes.get_by_name("U1").unwrap().id;
+      graph.mr.get_node_score(ego, target).unwrap()
+    };
+    assert_eq!(score(&loaded, "B1"), score(&aug_graph, "B1"));
+
+    //  Loaded files are removed.
+    assert!(store.load_all(&settings).is_empty());
+  }
+}
@@ -0,0 +1,141 @@
+//  Tracing of requests.
+//
+//  Requests are traced with `tracing` spans: `request` for the whole request,
+//  with `decode`, `calculate` (waiting for the ego's walks), `read`,
+//  `clustering` and `encode` inside. The spans are exported over OTLP when
+//  the service is built with the `otel` feature and `MERITRANK_OTLP_ENDPOINT`
+//  is set; otherwise they cost next to nothing.
+
+use crate::settings::Settings;
+#[allow(unused_imports)]
+use crate::utils::log::*;
+
+/// W3C trace id sent by the client before a request, see `TRACE_MAGIC`.
+/// All zeros means none.
+pub type TraceId = [u8; 16];
+
+/// Keeps the exporter running; spans still buffered are flushed on drop.
+#[derive(Default)]
+pub struct TelemetryGuard {
+  #[cfg(feature = "otel")]
+  provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+#[cfg(feature = "otel")]
+impl Drop for TelemetryGuard {
+  fn drop(&mut self) {
+    if let Some(provider) = self.provider.take() {
+      if let Err(e) = provider.shutdown() {
+        log_warning!("Failed to flush traces: {}", e);
+      }
+    }
+  }
+}
+
+/// Installs the OTLP exporter if `MERITRANK_OTLP_ENDPOINT` is set.
+pub fn init_telemetry(settings: &Settings) -> TelemetryGuard {
+  if settings.otlp_endpoint.is_empty() {
+    return TelemetryGuard::default();
+  }
+  install_exporter(&settings.otlp_endpoint)
+}
+
+#[cfg(feature = "otel")]
+fn install_exporter(endpoint: &str) -> TelemetryGuard {
+  match otel::install(endpoint) {
+    Ok(provider) => {
+      log_info!("Exporting traces to {}", endpoint);
+      TelemetryGuard {
+        provider: Some(provider),
+      }
+    },
+    Err(e) => {
+      log_error!("Failed to set up trace export: {}", e);
+      TelemetryGuard::default()
+    },
+  }
+}
+
+#[cfg(not(feature = "otel"))]
+fn install_exporter(_endpoint: &str) -> TelemetryGuard {
+  log_warning!(
+    "MERITRANK_OTLP_ENDPOINT is ignored: the service is built without the \
+     otel feature"
+  );
+  TelemetryGuard::default()
+}
+
+/// Span of one request. The trace id, if any, becomes the trace of the span
+/// when traces are exported, and is recorded as a field anyway.
+pub fn request_span(trace_id: Option<TraceId>) -> tracing::Span {
+  let span = tracing::info_span!(
+    "request",
+    trace_id = tracing::field::Empty,
+    opcode = tracing::field::Empty,
+    context = tracing::field::Empty,
+  );
+  if let Some(trace_id) = trace_id.filter(|id| *id != [0; 16]) {
+    let hex: String = trace_id.iter().map(|b| format!("{:02x}", b)).collect();
+    span.record("trace_id", hex.as_str());
+    #[cfg(feature = "otel")]
+    otel::set_remote_parent(&span, trace_id);
+  }
+  span
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+  use super::TraceId;
+
+  use opentelemetry::trace::{
+    SpanContext,
+    SpanId,
+    TraceContextExt,
+    TraceFlags,
+    TraceState,
+    TracerProvider,
+  };
+  use opentelemetry_otlp::WithExportConfig;
+  use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+  use tracing_opentelemetry::OpenTelemetrySpanExt;
+  use tracing_subscriber::layer::SubscriberExt;
+
+  use std::error::Error;
+
+  pub fn install(endpoint: &str) -> Result<SdkTracerProvider, Box<dyn Error>> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+      .with_http()
+      .with_endpoint(endpoint)
+      .build()?;
+    let provider = SdkTracerProvider::builder()
+      .with_batch_exporter(exporter)
+      .with_resource(
+        Resource::builder().with_service_name("meritrank").build(),
+      )
+      .build();
+    let layer =
+      tracing_opentelemetry::layer().with_tracer(provider.tracer("meritrank"));
+    tracing::subscriber::set_global_default(
+      tracing_subscriber::registry().with(layer),
+    )?;
+    Ok(provider)
+  }
+
+  /// Makes the span a child of the client's trace. The client's span is not
+  /// known, so a random one stands for it.
+  pub fn set_remote_parent(
+    span: &tracing::Span,
+    trace_id: TraceId,
+  ) {
+    let parent = SpanContext::new(
+      opentelemetry::TraceId::from_bytes(trace_id),
+      SpanId::from_bytes((rand::random::<u64>() | 1).to_be_bytes()),
+      TraceFlags::SAMPLED,
+      true,
+      TraceState::default(),
+    );
+    let _ = span.set_parent(
+      opentelemetry::Context::new().with_remote_span_context(parent),
+    );
+  }
+}
@@ -0,0 +1,179 @@
+//! TLS for TCP connections, with optional client certificate authentication.
+//!
+//! Certificates and keys are read from PEM files given in the settings. The
+//! acceptor is used for connections to this service, the connector for
+//! connections it makes to other services (shards, the primary), and by the
+//! bundled clients.
+
+use crate::settings::Settings;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::{
+  CertificateDer,
+  PrivateKeyDer,
+  ServerName,
+};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+
+/// Reexported for the connector, see `rpc_sync::connect_tls_sync`.
+pub use tokio_rustls::rustls::ClientConfig;
+
+fn load_certs(
+  path: &str,
+) -> Result<Vec<CertificateDer<'static>>, Box<dyn Error>> {
+  let mut reader = BufReader::new(File::open(path)?);
+  let certs = rustls_pemfile::certs(&mut reader).collect::<Result<_, _>>()?;
+  Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>, Box<dyn Error>> {
+  let mut reader = BufReader::new(File::open(path)?);
+  let key = rustls_pemfile::private_key(&mut reader)?
+    .ok_or_else(|| format!("No private key in {:?}", path))?;
+  Ok(key)
+}
+
+fn load_roots(path: &str) -> Result<RootCertStore, Box<dyn Error>> {
+  let mut roots = RootCertStore::empty();
+  for cert in load_certs(path)? {
+    roots.add(cert)?;
+  }
+  Ok(roots)
+}
+
+/// Acceptor for TLS connections, or None if `tls_cert` is not set. If
+/// `tls_client_ca` is set, clients must present a certificate signed by it.
+pub fn tls_acceptor(
+  settings: &Settings,
+) -> Result<Option<TlsAcceptor>, Box<dyn Error>> {
+  if settings.tls_cert.is_empty() {
+    return Ok(None);
+  }
+
+  let certs = load_certs(&settings.tls_cert)?;
+  let key = load_key(&settings.tls_key)?;
+
+  let builder = ServerConfig::builder();
+  let config = if settings.tls_client_ca.is_empty() {
+    builder.with_no_client_auth().with_single_cert(certs, key)?
+  } else {
+    let roots = load_roots(&settings.tls_client_ca)?;
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots)).build()?;
+    builder
+      .with_client_cert_verifier(verifier)
+      .with_single_cert(certs, key)?
+  };
+
+  Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+/// Config of TLS connections to a service whose certificate is signed by a
+/// CA in `ca`, or None if `ca` is empty. If `cert` is not empty, it is
+/// presented with the private key in `key` as the client certificate.
+pub fn tls_client_config(
+  ca: &str,
+  cert: &str,
+  key: &str,
+) -> Result<Option<Arc<ClientConfig>>, Box<dyn Error>> {
+  if ca.is_empty() {
+    return Ok(None);
+  }
+
+  let builder = ClientConfig::builder().with_root_certificates(load_roots(ca)?);
+  let config = if cert.is_empty() {
+    builder.with_no_client_auth()
+  } else {
+    builder.with_client_auth_cert(load_certs(cert)?, load_key(key)?)?
+  };
+
+  Ok(Some(Arc::new(config)))
+}
+
+/// Connector for TLS connections to other services, or None if `tls_ca` is
+/// not set, see `tls_client_config`.
+pub fn tls_connector(
+  settings: &Settings,
+) -> Result<Option<TlsConnector>, Box<dyn Error>> {
+  let config = tls_client_config(
+    &settings.tls_ca,
+    &settings.tls_client_cert,
+    &settings.tls_client_key,
+  )?;
+  Ok(config.map(TlsConnector::from))
+}
+
+/// Name the certificate of the service at `host:port` must be valid for.
+pub fn server_name(address: &str) -> io::Result<ServerName<'static>> {
+  let host = match address.rsplit_once(':') {
+    Some((host, _)) => host,
+    None => address,
+  };
+  let host = host.trim_start_matches('[').trim_end_matches(']');
+  ServerName::try_from(host.to_string())
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Connection to another service, over TLS or plain TCP.
+pub trait ServiceStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin> ServiceStream for S {}
+
+/// Connects to the service at `address`, over TLS if `tls` is set.
+pub async fn connect(
+  address: &str,
+  tls: Option<&TlsConnector>,
+) -> io::Result<Box<dyn ServiceStream>> {
+  let stream = TcpStream::connect(address).await?;
+  stream.set_nodelay(true)?;
+  match tls {
+    Some(connector) => {
+      let name = server_name(address)?;
+      Ok(Box::new(connector.connect(name, stream).await?))
+    },
+    None => Ok(Box::new(stream)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tls_disabled_by_default() {
+    assert!(tls_acceptor(&Settings::default()).unwrap().is_none());
+  }
+
+  #[test]
+  fn missing_certificate_is_an_error() {
+    let settings = Settings {
+      tls_cert: "/nonexistent/cert.pem".into(),
+      tls_key: "/nonexistent/key.pem".into(),
+      ..Settings::default()
+    };
+    assert!(tls_acceptor(&settings).is_err());
+  }
+
+  #[test]
+  fn server_names() {
+    assert_eq!(
+      server_name("localhost:8080").unwrap(),
+      ServerName::try_from("localhost").unwrap()
+    );
+    assert_eq!(
+      server_name("127.0.0.1:8080").unwrap(),
+      ServerName::try_from("127.0.0.1").unwrap()
+    );
+    assert_eq!(
+      server_name("[::1]:8080").unwrap(),
+      ServerName::try_from("::1").unwrap()
+    );
+  }
+}
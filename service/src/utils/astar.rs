@@ -264,13 +264,10 @@ mod astar_internal {
             //  Check if this node is already in the closed set
             //
 
-            let mut index_in_closed = usize::MAX;
-            for i in 0..state.num_closed {
-              if closed[i].id == neighbor_node.id {
-                index_in_closed = i;
-                break;
-              }
-            }
+            let index_in_closed = closed[..state.num_closed]
+              .iter()
+              .position(|node| node.id == neighbor_node.id)
+              .unwrap_or(usize::MAX);
 
             if index_in_closed != usize::MAX {
               //  Check if this node has a better distance
@@ -300,13 +297,10 @@ mod astar_internal {
             //  Check if this node is already in the open set
             //
 
-            let mut index_in_open: usize = usize::MAX;
-            for i in 0..state.num_open {
-              if open[i].id == neighbor_node.id {
-                index_in_open = i;
-                break;
-              }
-            }
+            let index_in_open: usize = open[..state.num_open]
+              .iter()
+              .position(|node| node.id == neighbor_node.id)
+              .unwrap_or(usize::MAX);
 
             if index_in_open != usize::MAX {
               //  Check if this node has a better distance
@@ -0,0 +1,247 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+fn bloom_filter_hashes(
+  num_hashes: usize,
+  id: usize,
+) -> impl Iterator<Item = u64> {
+  (1..=num_hashes).map(move |n| {
+    let mut h = DefaultHasher::new();
+    h.write_u16(n as u16);
+    h.write_u64(id as u64);
+    h.finish()
+  })
+}
+
+/// Bit pattern of `id` for a filter of `size` u64 words.
+pub fn bloom_filter_bits(
+  size: usize,
+  num_hashes: usize,
+  id: usize,
+) -> Vec<u64> {
+  let mut v: Vec<u64> = vec![0; size];
+
+  for hash in bloom_filter_hashes(num_hashes, id) {
+    let u64_index = ((hash / 64u64) as usize) % size;
+    let bit_index = hash % 64u64;
+
+    v[u64_index] |= 1u64 << bit_index;
+  }
+
+  v
+}
+
+/// Mask and bits must be of the same size.
+pub fn bloom_filter_add(
+  mask: &mut [u64],
+  bits: &[u64],
+) {
+  debug_assert_eq!(mask.len(), bits.len());
+
+  for (m, b) in mask.iter_mut().zip(bits) {
+    *m |= b;
+  }
+}
+
+/// Mask and bits must be of the same size. May return false positives.
+pub fn bloom_filter_contains(
+  mask: &[u64],
+  bits: &[u64],
+) -> bool {
+  debug_assert_eq!(mask.len(), bits.len());
+
+  mask.iter().zip(bits).all(|(m, b)| (m & b) == *b)
+}
+
+/// Number of slots and hashes of a filter that holds `capacity` ids with
+/// the false positive rate of `fp_rate`.
+pub fn bloom_filter_size(
+  capacity: usize,
+  fp_rate: f64,
+) -> (usize, usize) {
+  let n = capacity.max(1) as f64;
+  let p = fp_rate.clamp(f64::MIN_POSITIVE, 0.5);
+  let ln2 = std::f64::consts::LN_2;
+
+  let size = (-n * p.ln() / (ln2 * ln2)).ceil().max(1.0);
+  let num_hashes = (size / n * ln2).round().max(1.0);
+  (size as usize, num_hashes as usize)
+}
+
+/// Bloom filter with a counter per slot instead of a bit, so that ids can be
+/// removed. A counter that reached `u8::MAX` is saturated and never decremented;
+/// after removing an id that hit a saturated counter the filter may keep
+/// reporting it. The filter is sized for `capacity` ids, and has a higher
+/// false positive rate when it holds more. In both cases it should be rebuilt
+/// from the actual set of ids.
+#[derive(Clone, Debug)]
+pub struct CountingBloomFilter {
+  counters:   Vec<u8>,
+  num_hashes: usize,
+  capacity:   usize,
+  fp_rate:    f64,
+  len:        usize,
+  stale:      bool,
+}
+
+impl CountingBloomFilter {
+  pub fn new(
+    capacity: usize,
+    fp_rate: f64,
+  ) -> Self {
+    let (size, num_hashes) = bloom_filter_size(capacity, fp_rate);
+    CountingBloomFilter {
+      counters: vec![0; size],
+      num_hashes,
+      capacity: capacity.max(1),
+      fp_rate,
+      len: 0,
+      stale: false,
+    }
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  fn indices(
+    &self,
+    id: usize,
+  ) -> impl Iterator<Item = usize> {
+    let size = self.counters.len() as u64;
+    bloom_filter_hashes(self.num_hashes, id).map(move |h| (h % size) as usize)
+  }
+
+  pub fn add(
+    &mut self,
+    id: usize,
+  ) {
+    for i in self.indices(id).collect::<Vec<_>>() {
+      self.counters[i] = self.counters[i].saturating_add(1);
+    }
+    self.len += 1;
+  }
+
+  /// The id must have been added before, otherwise other ids may be lost.
+  pub fn remove(
+    &mut self,
+    id: usize,
+  ) {
+    for i in self.indices(id).collect::<Vec<_>>() {
+      match self.counters[i] {
+        0 => {},
+        u8::MAX => self.stale = true,
+        _ => self.counters[i] -= 1,
+      }
+    }
+    self.len = self.len.saturating_sub(1);
+  }
+
+  /// May return false positives.
+  pub fn contains(
+    &self,
+    id: usize,
+  ) -> bool {
+    self.indices(id).all(|i| self.counters[i] > 0)
+  }
+
+  /// True if a removal hit a saturated counter, or the filter holds more
+  /// ids than it was sized for.
+  pub fn needs_rebuild(&self) -> bool {
+    self.stale || self.len > self.capacity
+  }
+
+  /// Refills the filter with `ids`, growing it to twice their number if they
+  /// do not fit.
+  pub fn rebuild(
+    &mut self,
+    ids: impl IntoIterator<Item = usize>,
+  ) {
+    let ids: Vec<usize> = ids.into_iter().collect();
+    let capacity = if ids.len() > self.capacity {
+      ids.len() * 2
+    } else {
+      self.capacity
+    };
+    *self = CountingBloomFilter::new(capacity, self.fp_rate);
+    for id in ids {
+      self.add(id);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn added_ids_are_contained() {
+    let mut mask = vec![0; 16];
+    for id in 0..20 {
+      bloom_filter_add(&mut mask, &bloom_filter_bits(16, 4, id));
+    }
+
+    for id in 0..20 {
+      assert!(bloom_filter_contains(&mask, &bloom_filter_bits(16, 4, id)));
+    }
+    let false_positives = (1000..2000)
+      .filter(|&id| bloom_filter_contains(&mask, &bloom_filter_bits(16, 4, id)))
+      .count();
+    assert!(false_positives < 50);
+  }
+
+  #[test]
+  fn counting_filter_removes_ids() {
+    let mut filter = CountingBloomFilter::new(10, 0.01);
+    for id in 0..10 {
+      filter.add(id);
+    }
+    for id in 0..5 {
+      filter.remove(id);
+    }
+
+    assert!((5..10).all(|id| filter.contains(id)));
+    assert!((0..5).filter(|&id| filter.contains(id)).count() < 2);
+    assert!(!filter.needs_rebuild());
+  }
+
+  #[test]
+  fn counting_filter_saturation() {
+    let mut filter = CountingBloomFilter::new(1000, 0.01);
+    for _ in 0..300 {
+      filter.add(1);
+    }
+    for _ in 0..299 {
+      filter.remove(1);
+    }
+    assert!(filter.needs_rebuild());
+
+    filter.rebuild([2]);
+    assert!(!filter.needs_rebuild());
+    assert!(filter.contains(2));
+  }
+
+  #[test]
+  fn filter_size_from_fp_rate() {
+    let (size, num_hashes) = bloom_filter_size(1000, 0.01);
+    assert!((9500..=9700).contains(&size));
+    assert_eq!(num_hashes, 7);
+
+    let (smaller, _) = bloom_filter_size(1000, 0.1);
+    assert!(smaller < size);
+  }
+
+  #[test]
+  fn counting_filter_grows() {
+    let mut filter = CountingBloomFilter::new(10, 0.01);
+    for id in 0..20 {
+      filter.add(id);
+    }
+    assert!(filter.needs_rebuild());
+
+    filter.rebuild(0..20);
+    assert!(!filter.needs_rebuild());
+    assert_eq!(filter.capacity(), 40);
+    assert!((0..20).all(|id| filter.contains(id)));
+  }
+}
@@ -0,0 +1,95 @@
+//! File paths and writes shared by the stores, exports and imports.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// File of the context in `dir`. Context names are arbitrary strings, so
+/// file names are hex-encoded.
+pub fn context_file(
+  dir: &Path,
+  context: &str,
+  extension: &str,
+) -> PathBuf {
+  let hex: String = context.bytes().map(|b| format!("{:02x}", b)).collect();
+  dir.join(format!("{}.{}", hex, extension))
+}
+
+/// `relative` under `dir`, or None unless it is a non-empty relative path
+/// that stays inside `dir`.
+pub fn path_inside(
+  dir: &str,
+  relative: &str,
+) -> Option<PathBuf> {
+  let inside = Path::new(relative)
+    .components()
+    .all(|component| matches!(component, Component::Normal(_)));
+  if relative.is_empty() || !inside {
+    return None;
+  }
+  Some(Path::new(dir).join(relative))
+}
+
+/// Path `path` is written to before it is renamed over it.
+fn temp_path(path: &Path) -> PathBuf {
+  let mut name = path.as_os_str().to_owned();
+  name.push(".tmp");
+  PathBuf::from(name)
+}
+
+/// Writes the file through a temporary one, so that a crash or a failed
+/// write never leaves a partial file.
+pub fn write_atomic(
+  path: &Path,
+  bytes: &[u8],
+) -> io::Result<()> {
+  write_atomic_with(path, |tmp| fs::write(tmp, bytes))
+}
+
+/// Same as `write_atomic`, with the temporary file written by `write`, e.g.
+/// streamed. The file is only replaced if `write` succeeds.
+pub fn write_atomic_with<T>(
+  path: &Path,
+  write: impl FnOnce(&Path) -> io::Result<T>,
+) -> io::Result<T> {
+  let tmp = temp_path(path);
+  let written = write(&tmp).and_then(|x| fs::rename(&tmp, path).map(|_| x));
+  if written.is_err() {
+    let _ = fs::remove_file(&tmp);
+  }
+  written
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn failed_write_keeps_the_file() {
+    let dir = std::env::temp_dir()
+      .join(format!("meritrank-files-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("scores.csv");
+
+    write_atomic(&path, b"old").unwrap();
+    let failed = write_atomic_with(&path, |tmp| {
+      fs::write(tmp, b"partial")?;
+      Err::<(), _>(io::Error::other("failed"))
+    });
+    assert!(failed.is_err());
+    assert_eq!(fs::read(&path).unwrap(), b"old");
+    assert!(!temp_path(&path).exists());
+  }
+
+  #[test]
+  fn paths_stay_inside_the_dir() {
+    assert!(path_inside("/exports", "").is_none());
+    assert!(path_inside("/exports", "/etc/passwd").is_none());
+    assert!(path_inside("/exports", "a/../../scores.csv").is_none());
+    assert_eq!(
+      path_inside("/exports", "daily/scores.csv"),
+      Some(PathBuf::from("/exports/daily/scores.csv"))
+    );
+  }
+}
@@ -8,7 +8,14 @@ pub use crate::log_warning;
 pub use crate::log_info;
 
 pub use std::sync::atomic::Ordering;
-use std::{sync::atomic::AtomicBool, sync::Mutex, thread};
+use std::{
+  cell::RefCell,
+  future::Future,
+  str::FromStr,
+  sync::atomic::AtomicBool,
+  sync::Mutex,
+  thread,
+};
 
 pub static ERROR: AtomicBool = AtomicBool::new(true);
 pub static WARNING: AtomicBool = AtomicBool::new(true);
@@ -17,34 +24,225 @@ pub static VERBOSE: AtomicBool = AtomicBool::new(false);
 pub static TRACE: AtomicBool = AtomicBool::new(false);
 /// Command/ops logging (read_scores, apply_op, etc.). Off by default so perf tests are not affected.
 pub static CMD: AtomicBool = AtomicBool::new(false);
+/// Log lines are JSON objects instead of text, see `LogFormat`.
+pub static LOG_JSON: AtomicBool = AtomicBool::new(false);
 
 static LOG_MUTEX: Mutex<()> = Mutex::new(());
 
-pub fn log_with_time(message: String) {
-  let time = chrono::offset::Local::now();
-  let time_str = time.format("%Y-%m-%d %H:%M:%S");
-  let millis = time.timestamp_millis() % 1000;
+/// Format of log lines, see `MERITRANK_LOG_FORMAT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+  #[default]
+  Human,
+  /// One JSON object per line, with the fields of the request as keys.
+  Json,
+}
+
+impl FromStr for LogFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "human" | "text" => Ok(LogFormat::Human),
+      "json" => Ok(LogFormat::Json),
+      _ => Err(format!("Unknown log format: {}", s)),
+    }
+  }
+}
+
+pub fn set_log_format(format: LogFormat) {
+  LOG_JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// Fields of the request being processed, added to every log line written
+/// while it is processed.
+#[derive(Debug, Clone, Default)]
+pub struct LogFields {
+  pub context: Option<String>,
+  pub opcode:  Option<&'static str>,
+  pub ego:     Option<String>,
+}
+
+tokio::task_local! {
+  static TASK_LOG_FIELDS: LogFields;
+}
+
+thread_local! {
+  static THREAD_LOG_FIELDS: RefCell<Option<LogFields>> =
+    const { RefCell::new(None) };
+}
+
+/// Runs the future with the fields set for its log lines.
+pub async fn with_log_fields<F: Future>(
+  fields: LogFields,
+  f: F,
+) -> F::Output {
+  TASK_LOG_FIELDS.scope(fields, f).await
+}
+
+/// Fields of the current thread, or else of the current task. Jobs sent to
+/// other threads take them along with `enter_log_fields`.
+pub fn current_log_fields() -> Option<LogFields> {
+  THREAD_LOG_FIELDS
+    .with(|fields| fields.borrow().clone())
+    .or_else(|| TASK_LOG_FIELDS.try_with(|fields| fields.clone()).ok())
+}
+
+/// Restores the previous fields of the thread on drop.
+pub struct LogFieldsGuard {
+  previous: Option<LogFields>,
+}
+
+impl Drop for LogFieldsGuard {
+  fn drop(&mut self) {
+    let previous = self.previous.take();
+    THREAD_LOG_FIELDS.with(|fields| *fields.borrow_mut() = previous);
+  }
+}
+
+/// Sets the fields of the thread's log lines until the guard is dropped.
+pub fn enter_log_fields(fields: Option<LogFields>) -> LogFieldsGuard {
+  let previous = THREAD_LOG_FIELDS.with(|current| current.replace(fields));
+  LogFieldsGuard { previous }
+}
+
+/// One log line. The macros below fill in what their level shows.
+pub struct LogEvent<'a> {
+  pub level:    &'static str,
+  pub location: Option<(&'static str, u32)>,
+  pub function: Option<String>,
+  pub message:  String,
+  /// Fields of this line only, e.g. the latency of a request.
+  pub extra:    &'a [(&'static str, serde_json::Value)],
+}
+
+impl LogEvent<'_> {
+  /// The line as it has always been printed, followed by the fields.
+  fn human(
+    &self,
+    fields: &Option<LogFields>,
+  ) -> String {
+    let location = match self.location {
+      Some((file, line)) => format!("{}:{} ", file, line),
+      None => String::new(),
+    };
+    let function = self.function.as_deref().unwrap_or("");
+    let with_message = |prefix: String| {
+      if self.message.is_empty() {
+        prefix
+      } else {
+        format!("{}: {}", prefix, self.message)
+      }
+    };
+    let mut line = match self.level {
+      "ERROR" => {
+        format!("{}ERROR in {}: {}", location, function, self.message)
+      },
+      "WARNING" => format!("{}WARNING {}", location, self.message),
+      "VERBOSE" => format!("VERBOSE --- {}", self.message),
+      "TRACE" => {
+        with_message(format!("{}TRACE --- --- {}", location, function))
+      },
+      "CMD" => with_message(format!("CMD {}", function)),
+      level => format!("{} {}", level, self.message),
+    };
+
+    let mut pairs: Vec<String> = vec![];
+    if let Some(fields) = fields {
+      if let Some(context) = &fields.context {
+        pairs.push(format!("context={:?}", context));
+      }
+      if let Some(opcode) = fields.opcode {
+        pairs.push(format!("opcode={}", opcode));
+      }
+      if let Some(ego) = &fields.ego {
+        pairs.push(format!("ego={:?}", ego));
+      }
+    }
+    for (key, value) in self.extra {
+      pairs.push(format!("{}={}", key, value));
+    }
+    if !pairs.is_empty() {
+      line = format!("{} [{}]", line, pairs.join(" "));
+    }
+    line
+  }
+
+  fn json(
+    &self,
+    time: String,
+    thread: String,
+    fields: &Option<LogFields>,
+  ) -> String {
+    let mut object = serde_json::Map::new();
+    object.insert("time".into(), time.into());
+    object.insert("level".into(), self.level.into());
+    object.insert("thread".into(), thread.into());
+    if let Some((file, line)) = self.location {
+      object.insert("file".into(), file.into());
+      object.insert("line".into(), line.into());
+    }
+    if let Some(function) = &self.function {
+      object.insert("function".into(), function.as_str().into());
+    }
+    object.insert("message".into(), self.message.as_str().into());
+    if let Some(fields) = fields {
+      if let Some(context) = &fields.context {
+        object.insert("context".into(), context.as_str().into());
+      }
+      if let Some(opcode) = fields.opcode {
+        object.insert("opcode".into(), opcode.into());
+      }
+      if let Some(ego) = &fields.ego {
+        object.insert("ego".into(), ego.as_str().into());
+      }
+    }
+    for (key, value) in self.extra {
+      object.insert((*key).into(), value.clone());
+    }
+    serde_json::Value::Object(object).to_string()
+  }
+}
+
+fn thread_name() -> String {
   let full_thread_id = format!("{:?}", thread::current().id());
 
   let thread_begin = full_thread_id.rfind("(").unwrap_or(0) + 1;
   let thread_len = full_thread_id.find(")").unwrap_or(0) - thread_begin;
 
-  let thread: String = full_thread_id
+  full_thread_id
     .chars()
     .skip(thread_begin)
     .take(thread_len)
-    .collect();
+    .collect()
+}
+
+pub fn log_event(event: LogEvent) {
+  let time = chrono::offset::Local::now();
+  let thread = thread_name();
+  let fields = current_log_fields();
+
+  let line = if LOG_JSON.load(Ordering::Relaxed) {
+    event.json(
+      time.to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+      thread.clone(),
+      &fields,
+    )
+  } else {
+    let time_str = time.format("%Y-%m-%d %H:%M:%S");
+    let millis = time.timestamp_millis() % 1000;
+    format!(
+      "[{:3}.{:03}] {}  {}",
+      time_str,
+      millis,
+      thread,
+      event.human(&fields)
+    )
+  };
 
   match LOG_MUTEX.lock() {
-    Ok(_) => {
-      println!("[{:3}.{:03}] {}  {}", time_str, millis, thread, message);
-    },
-    _ => {
-      println!(
-        "[{:3}.{:03}] {}  LOG MUTEX FAILED",
-        time_str, millis, thread
-      );
-    },
+    Ok(_) => println!("{}", line),
+    _ => println!("{}  LOG MUTEX FAILED", thread),
   };
 }
 
@@ -74,7 +272,13 @@ macro_rules! log_func_name {
 macro_rules! log_error {
   ($($arg:expr),*) => {
     if ERROR.load(Ordering::Relaxed) {
-      log_with_time(format!("{}:{} ERROR in {}: {}", file!(), line!(), $crate::log_func_name!(), format!($($arg),*)));
+      log_event(LogEvent {
+        level:    "ERROR",
+        location: Some((file!(), line!())),
+        function: Some($crate::log_func_name!()),
+        message:  format!($($arg),*),
+        extra:    &[],
+      });
     }
   };
 }
@@ -83,7 +287,13 @@ macro_rules! log_error {
 macro_rules! log_warning {
   ($($arg:expr),*) => {
     if WARNING.load(Ordering::Relaxed) {
-      log_with_time(format!("{}:{} WARNING {}", file!(), line!(), format!($($arg),*)));
+      log_event(LogEvent {
+        level:    "WARNING",
+        location: Some((file!(), line!())),
+        function: None,
+        message:  format!($($arg),*),
+        extra:    &[],
+      });
     }
   };
 }
@@ -92,7 +302,13 @@ macro_rules! log_warning {
 macro_rules! log_info {
   ($($arg:expr),*) => {
     if INFO.load(Ordering::Relaxed) {
-      log_with_time(format!("INFO {}", format!($($arg),*)));
+      log_event(LogEvent {
+        level:    "INFO",
+        location: None,
+        function: None,
+        message:  format!($($arg),*),
+        extra:    &[],
+      });
     }
   };
 }
@@ -101,7 +317,13 @@ macro_rules! log_info {
 macro_rules! log_verbose {
   ($($arg:expr),*) => {
     if VERBOSE.load(Ordering::Relaxed) {
-      log_with_time(format!("VERBOSE --- {}", format!($($arg),*)));
+      log_event(LogEvent {
+        level:    "VERBOSE",
+        location: None,
+        function: None,
+        message:  format!($($arg),*),
+        extra:    &[],
+      });
     }
   };
 }
@@ -110,13 +332,25 @@ macro_rules! log_verbose {
 macro_rules! log_trace {
   () => {
     if TRACE.load(Ordering::Relaxed) {
-      log_with_time(format!("{}:{} TRACE --- --- {}", file!(), line!(), $crate::log_func_name!()));
+      log_event(LogEvent {
+        level:    "TRACE",
+        location: Some((file!(), line!())),
+        function: Some($crate::log_func_name!()),
+        message:  String::new(),
+        extra:    &[],
+      });
     }
   };
 
   ($($arg:expr),+) => {
     if TRACE.load(Ordering::Relaxed) {
-      log_with_time(format!("{}:{} TRACE --- --- {}: {}", file!(), line!(), $crate::log_func_name!(), format!($($arg),*)));
+      log_event(LogEvent {
+        level:    "TRACE",
+        location: Some((file!(), line!())),
+        function: Some($crate::log_func_name!()),
+        message:  format!($($arg),*),
+        extra:    &[],
+      });
     }
   };
 }
@@ -125,13 +359,25 @@ macro_rules! log_trace {
 macro_rules! log_command {
   () => {
     if CMD.load(Ordering::Relaxed) {
-      log_with_time(format!("CMD {}", $crate::log_func_name!()));
+      log_event(LogEvent {
+        level:    "CMD",
+        location: None,
+        function: Some($crate::log_func_name!()),
+        message:  String::new(),
+        extra:    &[],
+      });
     }
   };
 
   ($($arg:expr),+) => {
     if CMD.load(Ordering::Relaxed) {
-      log_with_time(format!("CMD {}: {}", $crate::log_func_name!(), format!($($arg),*)));
+      log_event(LogEvent {
+        level:    "CMD",
+        location: None,
+        function: Some($crate::log_func_name!()),
+        message:  format!($($arg),*),
+        extra:    &[],
+      });
     }
   };
 }
@@ -150,3 +396,71 @@ pub fn init_log_cmd_from_env() {
     );
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn event<'a>(
+    extra: &'a [(&'static str, serde_json::Value)],
+  ) -> LogEvent<'a> {
+    LogEvent {
+      level:    "ERROR",
+      location: Some(("src/x.rs", 7)),
+      function: Some("f".into()),
+      message:  "failed".into(),
+      extra,
+    }
+  }
+
+  #[test]
+  fn human_format_keeps_the_line_and_appends_fields() {
+    assert_eq!(event(&[]).human(&None), "src/x.rs:7 ERROR in f: failed");
+
+    let fields = Some(LogFields {
+      context: Some("X".into()),
+      opcode:  Some("ReadScores"),
+      ego:     None,
+    });
+    assert_eq!(
+      event(&[("outcome", "ok".into())]).human(&fields),
+      "src/x.rs:7 ERROR in f: failed [context=\"X\" opcode=ReadScores outcome=\"ok\"]"
+    );
+  }
+
+  #[test]
+  fn json_format_has_fields_as_keys() {
+    let fields = Some(LogFields {
+      context: Some("X".into()),
+      opcode:  None,
+      ego:     Some("U1".into()),
+    });
+    let line = event(&[("latency_ms", 1.5.into())]).json(
+      "t".into(),
+      "1".into(),
+      &fields,
+    );
+    let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(value["level"], "ERROR");
+    assert_eq!(value["message"], "failed");
+    assert_eq!(value["context"], "X");
+    assert_eq!(value["ego"], "U1");
+    assert_eq!(value["latency_ms"], 1.5);
+    assert!(value.get("opcode").is_none());
+  }
+
+  #[test]
+  fn thread_fields_are_restored() {
+    let outer = enter_log_fields(Some(LogFields {
+      context: Some("A".into()),
+      ..LogFields::default()
+    }));
+    {
+      let _inner = enter_log_fields(None);
+      assert!(current_log_fields().is_none());
+    }
+    assert_eq!(current_log_fields().unwrap().context.as_deref(), Some("A"));
+    drop(outer);
+    assert!(current_log_fields().is_none());
+  }
+}
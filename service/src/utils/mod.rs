@@ -1,4 +1,6 @@
 pub mod astar;
+pub mod bloom_filter;
+pub mod files;
 pub mod log;
 // pub mod pushsum;
 pub mod quantiles;
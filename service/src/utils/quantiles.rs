@@ -35,6 +35,131 @@ pub fn calculate_quantiles_bounds(
   bounds
 }
 
+/// Rank error of `QuantileSummary` used for cluster bounds. Well below the
+/// width of one cluster for any practical number of quantiles.
+pub const STREAMING_QUANTILES_EPSILON: f64 = 0.001;
+
+/// Kinds with more nodes than this get cluster bounds from a `QuantileSummary`
+/// instead of sorting all scores.
+pub const STREAMING_QUANTILES_MIN_NODES: usize = 10_000;
+
+/// Streaming quantile summary (Greenwald-Khanna). Takes values one by one
+/// without storing them all, and answers quantile queries with a rank error
+/// of at most `epsilon * count`.
+pub struct QuantileSummary {
+  epsilon: f64,
+  count:   usize,
+  //  Sorted by value. `g` is the difference between the min rank of the tuple
+  //  and the previous one, `delta` is the max rank minus the min rank.
+  tuples:  Vec<GkTuple>,
+}
+
+struct GkTuple {
+  value: f64,
+  g:     usize,
+  delta: usize,
+}
+
+impl QuantileSummary {
+  pub fn new(epsilon: f64) -> Self {
+    QuantileSummary {
+      epsilon,
+      count: 0,
+      tuples: Vec::new(),
+    }
+  }
+
+  pub fn count(&self) -> usize {
+    self.count
+  }
+
+  pub fn insert(
+    &mut self,
+    value: f64,
+  ) {
+    let index = self.tuples.partition_point(|t| t.value < value);
+    let delta = if index == 0 || index == self.tuples.len() {
+      0
+    } else {
+      self.band().saturating_sub(1)
+    };
+    self.tuples.insert(
+      index,
+      GkTuple {
+        value,
+        g: 1,
+        delta,
+      },
+    );
+    self.count += 1;
+
+    let compress_interval = ((1.0 / (2.0 * self.epsilon)) as usize).max(1);
+    if self.count.is_multiple_of(compress_interval) {
+      self.compress();
+    }
+  }
+
+  fn band(&self) -> usize {
+    (2.0 * self.epsilon * self.count as f64) as usize
+  }
+
+  /// Merges adjacent tuples while the rank error stays within bounds.
+  /// The min and max values are always kept.
+  fn compress(&mut self) {
+    let band = self.band();
+    let mut tuples = std::mem::take(&mut self.tuples).into_iter();
+    let first = tuples.next();
+    let mut merged: Vec<GkTuple> = Vec::with_capacity(tuples.len() + 1);
+    for tuple in tuples.rev() {
+      match merged.last_mut() {
+        Some(next) if tuple.g + next.g + next.delta <= band => {
+          next.g += tuple.g;
+        },
+        _ => merged.push(tuple),
+      }
+    }
+    merged.extend(first);
+    merged.reverse();
+    self.tuples = merged;
+  }
+
+  /// Returns a value whose rank is within `epsilon * count` of `phi * count`.
+  pub fn query(
+    &self,
+    phi: f64,
+  ) -> Option<f64> {
+    let first = self.tuples.first()?;
+    let bound = (phi + self.epsilon) * self.count as f64;
+    let mut min_rank = 0;
+    let mut prev = first;
+    for tuple in &self.tuples {
+      min_rank += tuple.g;
+      if (min_rank + tuple.delta) as f64 > bound {
+        return Some(prev.value);
+      }
+      prev = tuple;
+    }
+    Some(prev.value)
+  }
+
+  /// Cluster bounds like `calculate_quantiles_bounds`, approximated from the summary.
+  pub fn bounds(
+    &self,
+    num_quantiles: usize,
+  ) -> Vec<f64> {
+    match self.count {
+      0 => vec![0.0; num_quantiles - 1],
+      1 => {
+        let bound = self.tuples[0].value - f64::EPSILON;
+        vec![bound; num_quantiles - 1]
+      },
+      _ => (1..num_quantiles)
+        .filter_map(|i| self.query(i as f64 / num_quantiles as f64))
+        .collect(),
+    }
+  }
+}
+
 /*
 
 TODO
@@ -103,3 +228,53 @@ mod tests {
     }
 }
 */
+
+#[cfg(test)]
+mod summary_tests {
+  use super::*;
+
+  fn rank_of(
+    sorted: &[f64],
+    value: f64,
+  ) -> usize {
+    sorted.partition_point(|x| *x < value)
+  }
+
+  #[test]
+  fn summary_rank_error_is_bounded() {
+    let n = 100_000;
+    let epsilon = 0.001;
+    let mut summary = QuantileSummary::new(epsilon);
+    //  Deterministic permutation of 0..n.
+    for i in 0..n {
+      summary.insert(((i * 7919) % n) as f64);
+    }
+    assert_eq!(summary.count(), n);
+    assert!(summary.tuples.len() < n / 10);
+
+    let sorted: Vec<f64> = (0..n).map(|x| x as f64).collect();
+    for phi in [0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99] {
+      let value = summary.query(phi).unwrap();
+      let rank = rank_of(&sorted, value) as f64;
+      let error = (rank - phi * n as f64).abs();
+      assert!(error <= 2.0 * epsilon * n as f64, "phi {}: rank {}", phi, rank);
+    }
+  }
+
+  #[test]
+  fn summary_bounds_match_exact_shape() {
+    let mut summary = QuantileSummary::new(STREAMING_QUANTILES_EPSILON);
+    assert_eq!(summary.bounds(5), vec![0.0; 4]);
+
+    summary.insert(10.0);
+    assert_eq!(summary.bounds(5), vec![10.0 - f64::EPSILON; 4]);
+
+    for x in [4.0, 2.0, 8.0, 6.0] {
+      summary.insert(x);
+    }
+    let bounds = summary.bounds(5);
+    assert_eq!(bounds.len(), 4);
+    assert!(bounds.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(bounds, vec![2.0, 4.0, 6.0, 8.0]);
+  }
+}
@@ -135,6 +135,15 @@ impl VSIDSManager {
     (scaled_weight, rescale_factor, updated_max, updated_min)
   }
 
+  /// Scales the stored min and max weights of every src after all edge weights
+  /// were multiplied by `factor`.
+  pub fn scale(&mut self, factor: Weight) {
+    for (min, max, _) in self.min_max_weights.values_mut() {
+      *min *= factor;
+      *max *= factor;
+    }
+  }
+
   /// Updates the stored min for this src after the caller has applied rescales/deletions.
   /// Call this once you have the actual min from the graph (e.g. from apply_edge_rescales_and_deletions).
   pub fn finish_edge_update(&mut self, src_id: NodeId, actual_min: Weight) {
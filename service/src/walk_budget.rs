@@ -0,0 +1,142 @@
+//! Accounting of walks run for reads, see `MERITRANK_WALK_BUDGETS`.
+//!
+//! Reads of an ego without walks calculate it, which costs `num_walks` walks.
+//! The walks are charged to the context of the read, in a sliding window of
+//! the budget's period. Once a context has used its budget, reads that need
+//! a calculation are rejected with `WalkBudgetExceeded` until old walks leave
+//! the window, so one busy context can't take the CPU from the others. Reads
+//! of calculated egos are always served.
+
+use crate::data::*;
+use crate::settings::WalkBudget;
+
+use parking_lot::Mutex;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Window {
+  /// Walks charged and when, oldest first.
+  charges: VecDeque<(Instant, u64)>,
+  used:    u64,
+}
+
+impl Window {
+  fn expire(
+    &mut self,
+    now: Instant,
+    period: Duration,
+  ) {
+    while let Some((time, walks)) = self.charges.front() {
+      if now.duration_since(*time) < period {
+        break;
+      }
+      self.used -= walks;
+      self.charges.pop_front();
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct WalkBudgets {
+  windows:        Mutex<HashMap<SubgraphName, Window>>,
+  /// Calculations rejected since startup.
+  pub rejections: AtomicU64,
+}
+
+impl WalkBudgets {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Charges the walks to the context, or fails if it has used its budget.
+  /// The last calculation within the budget may overrun it.
+  pub fn charge(
+    &self,
+    context: &SubgraphName,
+    budget: Option<&WalkBudget>,
+    walks: u64,
+  ) -> Result<(), ServiceError> {
+    let budget = match budget {
+      Some(x) => x,
+      None => return Ok(()),
+    };
+    let now = Instant::now();
+    let mut windows = self.windows.lock();
+    let window = windows.entry(context.clone()).or_default();
+    window.expire(now, Duration::from_secs(budget.period));
+    if window.used >= budget.max_walks {
+      self.rejections.fetch_add(1, Ordering::Relaxed);
+      return Err(ServiceError::WalkBudgetExceeded(context.clone()));
+    }
+    window.charges.push_back((now, walks));
+    window.used += walks;
+    Ok(())
+  }
+
+  /// Walks charged to the context within the budget's period.
+  pub fn used(
+    &self,
+    context: &SubgraphName,
+    budget: Option<&WalkBudget>,
+  ) -> u64 {
+    let budget = match budget {
+      Some(x) => x,
+      None => return 0,
+    };
+    let mut windows = self.windows.lock();
+    match windows.get_mut(context) {
+      Some(window) => {
+        window.expire(Instant::now(), Duration::from_secs(budget.period));
+        window.used
+      },
+      None => 0,
+    }
+  }
+
+  /// Forgets the walks of a deleted context.
+  pub fn remove(
+    &self,
+    context: &SubgraphName,
+  ) {
+    self.windows.lock().remove(context);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn charges_until_budget_is_used() {
+    let budgets = WalkBudgets::new();
+    let budget = WalkBudget {
+      context:   "*".into(),
+      max_walks: 150,
+      period:    60,
+    };
+    let (a, b): (SubgraphName, SubgraphName) = ("a".into(), "b".into());
+
+    assert!(budgets.charge(&a, Some(&budget), 100).is_ok());
+    assert!(budgets.charge(&a, Some(&budget), 100).is_ok());
+    assert_eq!(budgets.used(&a, Some(&budget)), 200);
+    assert_eq!(
+      budgets.charge(&a, Some(&budget), 100),
+      Err(ServiceError::WalkBudgetExceeded(a.clone()))
+    );
+    assert_eq!(budgets.rejections.load(Ordering::Relaxed), 1);
+
+    //  Contexts have budgets of their own.
+    assert!(budgets.charge(&b, Some(&budget), 100).is_ok());
+    assert!(budgets.charge(&a, None, 100).is_ok());
+
+    //  Walks leave the window after the period.
+    let expired = WalkBudget {
+      period: 0,
+      ..budget
+    };
+    assert_eq!(budgets.used(&a, Some(&expired)), 0);
+  }
+}
@@ -4,13 +4,28 @@
 use meritrank_core::NodeId;
 use moka::notification::RemovalCause;
 use moka::sync::Cache;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 /// Tracks which egos have walks in the cache and collects evicted ego IDs when capacity is exceeded.
 pub struct WalkTracker {
-  cache:   Cache<NodeId, ()>,
-  evicted: Arc<Mutex<Vec<NodeId>>>,
+  cache:     RwLock<Cache<NodeId, ()>>,
+  evicted:   Arc<Mutex<Vec<NodeId>>>,
+  /// Node count the capacity is for, 0 until `resize`, see `cache_sizing`.
+  sized_for: AtomicUsize,
+}
+
+fn new_cache(max_egos: u64, evicted: &Arc<Mutex<Vec<NodeId>>>) -> Cache<NodeId, ()> {
+  let evicted_clone = Arc::clone(evicted);
+  Cache::builder()
+    .max_capacity(max_egos)
+    .eviction_listener(move |key: Arc<NodeId>, _value: (), cause: RemovalCause| {
+      if matches!(cause, RemovalCause::Size) {
+        evicted_clone.lock().push(*key);
+      }
+    })
+    .build()
 }
 
 impl WalkTracker {
@@ -19,24 +34,31 @@ impl WalkTracker {
   /// collected and can be drained via `drain_evicted`.
   pub fn new(max_egos: u64) -> Self {
     let evicted = Arc::new(Mutex::new(Vec::new()));
-    let evicted_clone = Arc::clone(&evicted);
-
-    let cache = Cache::builder()
-      .max_capacity(max_egos)
-      .eviction_listener(move |key: Arc<NodeId>, _value: (), cause: RemovalCause| {
-        if matches!(cause, RemovalCause::Size) {
-          evicted_clone.lock().push(*key);
-        }
-      })
-      .build();
-
-    WalkTracker { cache, evicted }
+    let cache = RwLock::new(new_cache(max_egos, &evicted));
+    WalkTracker { cache, evicted, sized_for: AtomicUsize::new(0) }
+  }
+
+  pub fn sized_for(&self) -> usize {
+    self.sized_for.load(Ordering::Relaxed)
+  }
+
+  /// Changes the capacity to `max_egos`, keeping the tracked egos. If it shrinks, the
+  /// egos over capacity are evicted and available from `drain_evicted`.
+  pub fn resize(&self, max_egos: u64, sized_for: usize) {
+    let mut cache = self.cache.write();
+    let resized = new_cache(max_egos, &self.evicted);
+    for (ego_id, _) in cache.iter() {
+      resized.insert(*ego_id, ());
+    }
+    resized.run_pending_tasks();
+    *cache = resized;
+    self.sized_for.store(sized_for, Ordering::Relaxed);
   }
 
   /// Records that the given ego was used (read or calculated). If the cache is at capacity,
   /// this may trigger an eviction; the evicted ego ID will be available from `drain_evicted`.
   pub fn touch(&self, ego_id: NodeId) {
-    self.cache.insert(ego_id, ());
+    self.cache.read().insert(ego_id, ());
   }
 
   /// Returns and clears the list of ego IDs that were evicted since the last drain.
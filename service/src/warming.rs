@@ -0,0 +1,88 @@
+//! Per-context query frequency of egos, used by the background warming worker
+//! to recompute walks and cluster bounds of hot egos before they are queried.
+
+use crate::data::NodeName;
+
+use parking_lot::Mutex;
+
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct EgoHeat {
+  hits: Mutex<HashMap<NodeName, u64>>,
+}
+
+impl EgoHeat {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn record(
+    &self,
+    ego: &NodeName,
+  ) {
+    let mut hits = self.hits.lock();
+    match hits.get_mut(ego) {
+      Some(count) => *count += 1,
+      None => {
+        hits.insert(ego.clone(), 1);
+      },
+    }
+  }
+
+  /// Returns up to `limit` most queried egos, most frequent first.
+  pub fn hottest(
+    &self,
+    limit: usize,
+  ) -> Vec<NodeName> {
+    let mut egos: Vec<(NodeName, u64)> = self
+      .hits
+      .lock()
+      .iter()
+      .map(|(ego, count)| (ego.clone(), *count))
+      .collect();
+    egos.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    egos.into_iter().take(limit).map(|(ego, _)| ego).collect()
+  }
+
+  /// Halves all counts, so that egos that are no longer queried cool down and
+  /// are eventually forgotten.
+  pub fn decay(&self) {
+    let mut hits = self.hits.lock();
+    hits.retain(|_, count| {
+      *count /= 2;
+      *count > 0
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hottest_egos_first() {
+    let heat = EgoHeat::new();
+    for (ego, n) in [("U1", 1), ("U2", 3), ("U3", 2)] {
+      for _ in 0..n {
+        heat.record(&ego.to_string());
+      }
+    }
+
+    assert_eq!(heat.hottest(2), vec!["U2".to_string(), "U3".to_string()]);
+    assert_eq!(heat.hottest(10).len(), 3);
+  }
+
+  #[test]
+  fn decay_forgets_cold_egos() {
+    let heat = EgoHeat::new();
+    heat.record(&"U1".to_string());
+    heat.record(&"U2".to_string());
+    heat.record(&"U2".to_string());
+
+    heat.decay();
+    assert_eq!(heat.hottest(10), vec!["U2".to_string()]);
+    heat.decay();
+    assert!(heat.hottest(10).is_empty());
+  }
+}
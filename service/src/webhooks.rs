@@ -0,0 +1,320 @@
+//! Webhooks registered by operators with `WriteWebhook`. A background worker
+//! reads the scores of the watched egos and POSTs the changes since the last
+//! report to each webhook as a JSON digest, so that downstream systems stay
+//! in sync without polling. Only plain `http://` URLs are supported.
+
+use crate::data::*;
+use crate::history::unix_time_secs;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Scores of an ego by target name.
+pub type EgoScores = HashMap<NodeName, NodeScore>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScoreChange {
+  pub ego:  NodeName,
+  pub node: NodeName,
+  pub old:  NodeScore,
+  pub new:  NodeScore,
+}
+
+/// Body of a POST to a webhook.
+#[derive(Debug, Serialize)]
+pub struct Digest<'a> {
+  pub context: &'a str,
+  /// Unix time in seconds.
+  pub time:    u64,
+  pub changes: &'a [ScoreChange],
+}
+
+struct Hook {
+  config: OpWriteWebhook,
+  /// Scores last reported, by context and ego.
+  sent:   HashMap<(SubgraphName, NodeName), EgoScores>,
+}
+
+impl Hook {
+  fn watches(
+    &self,
+    context: &SubgraphName,
+  ) -> bool {
+    self.config.context.as_ref().is_none_or(|x| x == context)
+  }
+}
+
+#[derive(Default)]
+pub struct Webhooks {
+  hooks: Mutex<Vec<Hook>>,
+}
+
+impl Webhooks {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Registers the webhook, replacing the one with the same URL.
+  pub fn register(
+    &self,
+    config: OpWriteWebhook,
+  ) {
+    let mut hooks = self.hooks.lock();
+    hooks.retain(|hook| hook.config.url != config.url);
+    hooks.push(Hook {
+      config,
+      sent: HashMap::new(),
+    });
+  }
+
+  /// Returns whether there was a webhook with the URL.
+  pub fn remove(
+    &self,
+    url: &str,
+  ) -> bool {
+    let mut hooks = self.hooks.lock();
+    let len = hooks.len();
+    hooks.retain(|hook| hook.config.url != url);
+    hooks.len() < len
+  }
+
+  pub fn list(&self) -> Vec<OpWriteWebhook> {
+    self.hooks.lock().iter().map(|hook| hook.config.clone()).collect()
+  }
+
+  /// Egos whose scores the webhooks need in the context: None if no webhook
+  /// watches it, empty if one watches all calculated egos.
+  pub fn watched_egos(
+    &self,
+    context: &SubgraphName,
+  ) -> Option<Vec<NodeName>> {
+    let hooks = self.hooks.lock();
+    let mut egos = vec![];
+    let mut watched = false;
+    for hook in hooks.iter().filter(|hook| hook.watches(context)) {
+      if hook.config.egos.is_empty() {
+        return Some(vec![]);
+      }
+      egos.extend(hook.config.egos.iter().cloned());
+      watched = true;
+    }
+    egos.sort();
+    egos.dedup();
+    watched.then_some(egos)
+  }
+
+  /// Changes of the scores in the context to report to each webhook, by
+  /// URL, recording them as reported. The first scores of an ego are only
+  /// recorded: they are the baseline of later changes.
+  pub fn digests(
+    &self,
+    context: &SubgraphName,
+    scores: &HashMap<NodeName, EgoScores>,
+  ) -> Vec<(String, Vec<ScoreChange>)> {
+    let mut hooks = self.hooks.lock();
+    let mut digests = vec![];
+    for hook in hooks.iter_mut().filter(|hook| hook.watches(context)) {
+      let min_change = hook.config.min_change;
+      let mut changes = vec![];
+      for (ego, new_scores) in scores {
+        if !hook.config.egos.is_empty() && !hook.config.egos.contains(ego) {
+          continue;
+        }
+        let key = (context.clone(), ego.clone());
+        let sent = match hook.sent.get_mut(&key) {
+          Some(x) => x,
+          None => {
+            hook.sent.insert(key, new_scores.clone());
+            continue;
+          },
+        };
+        let mut nodes: Vec<&NodeName> =
+          new_scores.keys().chain(sent.keys()).collect();
+        nodes.sort();
+        nodes.dedup();
+        let mut ego_changes = vec![];
+        for node in nodes {
+          let old = sent.get(node).copied().unwrap_or(0.0);
+          let new = new_scores.get(node).copied().unwrap_or(0.0);
+          if old != new && (new - old).abs() >= min_change {
+            ego_changes.push(ScoreChange {
+              ego: ego.clone(),
+              node: node.clone(),
+              old,
+              new,
+            });
+          }
+        }
+        for change in &ego_changes {
+          if new_scores.contains_key(&change.node) {
+            sent.insert(change.node.clone(), change.new);
+          } else {
+            sent.remove(&change.node);
+          }
+        }
+        changes.extend(ego_changes);
+      }
+      if !changes.is_empty() {
+        changes.sort_by(|a, b| (&a.ego, &a.node).cmp(&(&b.ego, &b.node)));
+        digests.push((hook.config.url.clone(), changes));
+      }
+    }
+    digests
+  }
+}
+
+/// POSTs the changes in the context to the webhook.
+pub async fn send_digest(
+  url: &str,
+  context: &str,
+  changes: &[ScoreChange],
+) -> io::Result<()> {
+  let body = serde_json::to_vec(&Digest {
+    context,
+    time: unix_time_secs(),
+    changes,
+  })?;
+  post_json(url, &body).await
+}
+
+/// POSTs the body to an `http://host[:port][/path]` URL. Fails unless the
+/// response status is 2xx.
+pub async fn post_json(
+  url: &str,
+  body: &[u8],
+) -> io::Result<()> {
+  let rest = url.strip_prefix("http://").ok_or_else(|| {
+    io::Error::new(io::ErrorKind::InvalidInput, "not an http:// URL")
+  })?;
+  let (host, path) = match rest.find('/') {
+    Some(i) => (&rest[..i], &rest[i..]),
+    None => (rest, "/"),
+  };
+  let address = if host.contains(':') {
+    host.to_string()
+  } else {
+    format!("{}:80", host)
+  };
+
+  let post = async {
+    let mut stream = TcpStream::connect(&address).await?;
+    let head = format!(
+      "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+       Content-Length: {}\r\nConnection: close\r\n\r\n",
+      path,
+      host,
+      body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await?;
+    Ok::<_, io::Error>(response)
+  };
+  let response = tokio::time::timeout(POST_TIMEOUT, post).await.map_err(|_| {
+    io::Error::new(io::ErrorKind::TimedOut, "webhook timed out")
+  })??;
+
+  //  The status line is e.g. `HTTP/1.1 200 OK`.
+  let status = String::from_utf8_lossy(&response)
+    .split_whitespace()
+    .nth(1)
+    .and_then(|x| x.parse::<u16>().ok());
+  match status {
+    Some(200..=299) => Ok(()),
+    _ => Err(io::Error::other(format!(
+      "webhook responded with status {:?}",
+      status
+    ))),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use tokio::net::TcpListener;
+
+  fn webhook(url: &str) -> OpWriteWebhook {
+    OpWriteWebhook {
+      url:        url.into(),
+      context:    None,
+      egos:       vec![],
+      min_change: 0.1,
+    }
+  }
+
+  fn scores(items: &[(&str, &str, f64)]) -> HashMap<NodeName, EgoScores> {
+    let mut scores: HashMap<NodeName, EgoScores> = HashMap::new();
+    for (ego, node, score) in items {
+      scores
+        .entry(ego.to_string())
+        .or_default()
+        .insert(node.to_string(), *score);
+    }
+    scores
+  }
+
+  #[test]
+  fn digests_report_changes_above_min_change() {
+    let webhooks = Webhooks::new();
+    webhooks.register(webhook("http://a"));
+    let context: SubgraphName = String::new();
+
+    //  The first scores are the baseline.
+    let first = scores(&[("U1", "B1", 0.5), ("U1", "B2", 0.2)]);
+    assert!(webhooks.digests(&context, &first).is_empty());
+
+    let second = scores(&[("U1", "B1", 0.55), ("U1", "B3", 0.3)]);
+    let digests = webhooks.digests(&context, &second);
+    assert_eq!(digests.len(), 1);
+    let changes: Vec<(&str, f64, f64)> = digests[0]
+      .1
+      .iter()
+      .map(|x| (x.node.as_str(), x.old, x.new))
+      .collect();
+    assert_eq!(changes, vec![("B2", 0.2, 0.0), ("B3", 0.0, 0.3)]);
+
+    //  Small changes add up until they are reported.
+    let third = scores(&[("U1", "B1", 0.61), ("U1", "B3", 0.3)]);
+    let digests = webhooks.digests(&context, &third);
+    assert_eq!(digests[0].1[0].old, 0.5);
+
+    assert!(webhooks.remove("http://a"));
+    assert_eq!(webhooks.watched_egos(&context), None);
+  }
+
+  #[tokio::test]
+  async fn post_json_sends_request() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut request = vec![];
+      let mut buf = [0u8; 4096];
+      while !request.ends_with(b"{}") {
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(n > 0);
+        request.extend_from_slice(&buf[..n]);
+      }
+      stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+      String::from_utf8_lossy(&request).to_string()
+    });
+
+    let url = format!("http://127.0.0.1:{}/hook", port);
+    post_json(&url, b"{}").await.unwrap();
+    let request = server.await.unwrap();
+    assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+    assert!(request.ends_with("\r\n\r\n{}"));
+
+    assert!(post_json("https://example.com", b"{}").await.is_err());
+  }
+}
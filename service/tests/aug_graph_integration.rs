@@ -2,11 +2,18 @@
 
 use meritrank_service::aug_graph::AugGraph;
 use meritrank_service::data::{
-  FilterOptions, GraphResult, OpReadGraph, OpReadMutualScores,
-  OpReadNeighbors, OpReadNodeScore, OpReadScores, ScoreResult,
-  NEIGHBORS_ALL, NEIGHBORS_INBOUND, NEIGHBORS_OUTBOUND,
+  AugGraphOp, FilterOptions, GraphResult, OpReadGraph, OpReadMutualScores,
+  OpReadCompareEgos, OpReadMutualSuggestions, OpReadNeighbors,
+  OpReadNodeScore, OpReadRecommendations, OpReadScoreHistory,
+  OpReadScores,
+  OpWriteAliasNode,
+  OpWriteNode,
+  OpWriteScoreClusters,
+  ScoreComponent,
+  ScoreResult,
+  NodeKind, NEIGHBORS_ALL, NEIGHBORS_INBOUND, NEIGHBORS_OUTBOUND,
 };
-use meritrank_service::node_registry::node_kind_from_prefix;
+use meritrank_service::node_registry::{node_kind_from_prefix, register_node_kind};
 use meritrank_service::settings::Settings;
 
 // ================================================================
@@ -28,6 +35,7 @@ fn default_graph_zero() -> AugGraph {
   })
 }
 
+#[allow(clippy::too_many_arguments)]
 fn read_scores(
   graph: &AugGraph,
   ego: &str,
@@ -41,19 +49,31 @@ fn read_scores(
   count: u32,
 ) -> Vec<ScoreResult> {
   let node_kind = node_kind_from_prefix(kind_prefix);
-  graph.read_scores(OpReadScores {
-    ego:           ego.into(),
-    score_options: FilterOptions {
-      node_kind,
-      hide_personal,
-      score_lt,
-      score_lte,
-      score_gt,
-      score_gte,
-      index,
-      count,
-    },
-  })
+  graph
+    .read_scores(OpReadScores {
+      ego:           ego.into(),
+      score_options: FilterOptions {
+        node_kind,
+        hide_personal,
+        score_lt,
+        score_lte,
+        score_gt,
+        score_gte,
+        index,
+        count,
+        num_clusters: 0,
+        cluster_min: 0,
+        cluster_max: u32::MAX,
+        filter_on: ScoreComponent::Blended,
+        include_negative_only: false,
+        ascending: false,
+        exclude_flags: 0,
+        require_flags: 0,
+        max_latency_ms: 0,
+        include_reverse_scores: true,
+      },
+    })
+    .unwrap()
 }
 
 fn read_node_score_helper(
@@ -93,6 +113,7 @@ fn read_mutual_scores_helper(
   })
 }
 
+#[allow(clippy::too_many_arguments)]
 fn read_neighbors_helper(
   graph: &AugGraph,
   ego: &str,
@@ -112,6 +133,7 @@ fn read_neighbors_helper(
     focus: focus.into(),
     direction,
     kind: node_kind_from_prefix(kind_str),
+    edge_kind: None,
     hide_personal,
     lt,
     lte,
@@ -207,6 +229,33 @@ fn scores_reversed() {
   }
 }
 
+#[test]
+fn reverse_scores_only_on_request() {
+  let mut graph = default_graph();
+
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "U1".into(), 1.0, 0);
+  graph.calculate("U1".into());
+  graph.calculate("U2".into());
+
+  let read = |include_reverse_scores: bool| {
+    graph
+      .read_scores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions {
+          include_reverse_scores,
+          ..FilterOptions::default()
+        },
+      })
+      .unwrap()
+  };
+  let u2 = |scores: Vec<ScoreResult>| {
+    scores.into_iter().find(|x| x.target == "U2").unwrap().reverse_score
+  };
+  assert_eq!(u2(read(false)), 0.0);
+  assert!(u2(read(true)) > 0.0);
+}
+
 #[test]
 fn scores_sort_order() {
   let mut graph = default_graph();
@@ -914,7 +963,8 @@ fn vsids_write_edge() {
   });
   // Test expects magnitude-20 to scale 1.0 above 3.0 (bump^20 > 3 => bump > ~1.056).
   // Default bump is 1.03; set explicitly so the test is self-contained and not env-dependent.
-  graph.vsids.bump_factor = 1.06;
+  // 1.1^20 is about 6.7, far enough above 3.0 for 500 walks to tell apart.
+  graph.vsids.bump_factor = 1.1;
 
   graph.set_edge("U1".into(), "U4".into(), 3.0, 0);
   graph.set_edge("U1".into(), "U2".into(), 3.0, 0);
@@ -1078,3 +1128,611 @@ fn omit_neg_edges_scores_mutual_scores() {
   );
   // With omit_neg=false, U2 may still not appear if its score is <= 0 (we only show positive forward scores)
 }
+
+#[test]
+fn node_score_cache_invalidated_by_edge_write() {
+  let mut graph = default_graph();
+
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.calculate("U1".into());
+
+  let before = read_node_score_helper(&graph, "U1", "U2");
+  assert_eq!(before.len(), 1);
+
+  // U1 now splits its trust between U2 and U3, so the cached U2 score is stale.
+  graph.set_edge("U1".into(), "U3".into(), 10.0, 0);
+
+  let after = read_node_score_helper(&graph, "U1", "U2");
+  assert_eq!(after.len(), 1);
+  assert!(after[0].score < before[0].score);
+}
+
+#[test]
+fn evicted_ego_scores_fallback() {
+  for keep_evicted_scores in [false, true] {
+    let mut graph = AugGraph::new(Settings {
+      num_walks: 50,
+      zero_opinion_factor: 0.0,
+      keep_evicted_scores,
+      ..Settings::default()
+    });
+
+    graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+    graph.calculate("U1".into());
+
+    let before = read_node_score_helper(&graph, "U1", "U2");
+    assert!(before[0].score > 0.0);
+
+    let ego_id = graph.nodes.get_by_name("U1").unwrap().id;
+    graph.apply_op(&AugGraphOp::ClearEgo(ego_id));
+    assert_eq!(graph.has_evicted_scores(ego_id), keep_evicted_scores);
+
+    let after = read_node_score_helper(&graph, "U1", "U2");
+    if keep_evicted_scores {
+      assert_eq!(after[0].score, before[0].score);
+    } else {
+      assert_eq!(after[0].score, 0.0);
+    }
+
+    // Recalculation replaces the kept scores.
+    graph.calculate("U1".into());
+    assert!(!graph.has_evicted_scores(ego_id));
+  }
+}
+
+#[test]
+fn scores_coarser_clusters_on_request() {
+  let mut graph = default_graph();
+
+  for dst in ["U2", "U3", "U4", "U5", "U6"] {
+    graph.set_edge("U1".into(), dst.into(), 1.0, 0);
+  }
+  graph.calculate("U1".into());
+
+  let read = |graph: &AugGraph, num_clusters: u32| {
+    graph
+      .read_scores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions {
+          num_clusters,
+          ..FilterOptions::default()
+        },
+      })
+      .unwrap()
+  };
+
+  let fine = read(&graph, 0);
+  assert!(fine.iter().any(|x| x.cluster > 2));
+
+  let coarse = read(&graph, 2);
+  assert_eq!(coarse.len(), fine.len());
+  assert!(coarse.iter().all(|x| x.cluster <= 2));
+
+  // Requests finer than the context setting get the context setting.
+  assert_eq!(graph.num_clusters(1000), graph.settings.num_score_quantiles);
+
+  graph.apply_op(&AugGraphOp::WriteScoreClusters(OpWriteScoreClusters {
+    num_clusters: 3,
+  }));
+  assert!(read(&graph, 0).iter().all(|x| x.cluster <= 3));
+}
+
+#[test]
+fn scores_cluster_filter() {
+  let mut graph = default_graph();
+
+  for dst in ["U2", "U3", "U4", "U5", "U6"] {
+    graph.set_edge("U1".into(), dst.into(), 1.0, 0);
+  }
+  graph.calculate("U1".into());
+
+  let read = |cluster_min: u32, cluster_max: u32| {
+    graph
+      .read_scores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions {
+          num_clusters: 10,
+          cluster_min,
+          cluster_max,
+          ..FilterOptions::default()
+        },
+      })
+      .unwrap()
+  };
+
+  let all = read(0, u32::MAX);
+  let top = read(8, 10);
+  assert!(!top.is_empty());
+  assert!(top.len() < all.len());
+  assert!(top.iter().all(|x| (8..=10).contains(&x.cluster)));
+  assert!(read(11, u32::MAX).is_empty());
+}
+
+#[test]
+fn scores_filter_on_component() {
+  let mut graph = default_graph_zero();
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "U3".into(), 1.0, 0);
+  graph.set_edge("U5".into(), "U4".into(), 1.0, 0);
+  graph.calculate("U1".into());
+  graph.set_zero_opinions(vec![("U4".into(), 1.0)]);
+
+  let read = |filter_on: ScoreComponent| {
+    let mut targets: Vec<String> = graph
+      .read_scores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions {
+          node_kind: Some(NodeKind::User),
+          score_gt: 0.05,
+          score_gte: false,
+          filter_on,
+          ..FilterOptions::default()
+        },
+      })
+      .unwrap()
+      .into_iter()
+      .map(|x| x.target)
+      .collect();
+    targets.sort();
+    targets
+  };
+
+  //  U4 is only known to the zero opinion.
+  assert!(read(ScoreComponent::Blended).contains(&"U4".to_string()));
+  assert!(!read(ScoreComponent::Personal).contains(&"U4".to_string()));
+  assert_eq!(read(ScoreComponent::ZeroOpinion), vec!["U4".to_string()]);
+}
+
+#[test]
+fn scores_negative_only_ascending() {
+  let mut graph = AugGraph::new(Settings {
+    num_walks: 500,
+    zero_opinion_factor: 0.0,
+    ..Settings::default()
+  });
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "U3".into(), -1.0, 0);
+  graph.set_edge("U1".into(), "U4".into(), -3.0, 0);
+  graph.calculate("U1".into());
+
+  let read = |include_negative_only: bool, ascending: bool| {
+    graph
+      .read_scores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions {
+          node_kind: Some(NodeKind::User),
+          include_negative_only,
+          ascending,
+          ..FilterOptions::default()
+        },
+      })
+      .unwrap()
+      .into_iter()
+      .map(|x| (x.target, x.score))
+      .collect::<Vec<_>>()
+  };
+
+  let negative = read(true, true);
+  let targets: Vec<&str> = negative.iter().map(|(x, _)| x.as_str()).collect();
+  assert_eq!(targets, vec!["U4", "U3"]);
+  assert!(negative.iter().all(|(_, score)| *score < 0.0));
+
+  //  Ascending order puts the most penalized nodes first.
+  let all = read(false, true);
+  assert_eq!(all.first().unwrap().0, "U4");
+  assert!(all.windows(2).all(|x| x[0].1 <= x[1].1));
+}
+
+#[test]
+fn scores_filtered_by_node_flags() {
+  const NSFW: u32 = 1;
+  const PINNED: u32 = 2;
+
+  let mut graph = default_graph();
+  for (name, flags) in [("B1", NSFW), ("B2", PINNED), ("B3", NSFW | PINNED)] {
+    graph.write_node(&OpWriteNode {
+      name: name.into(),
+      kind: NodeKind::Beacon,
+      flags,
+    });
+  }
+  for dst in ["B1", "B2", "B3", "B4"] {
+    graph.set_edge("U1".into(), dst.into(), 1.0, 0);
+  }
+  graph.calculate("U1".into());
+
+  let read = |exclude_flags: u32, require_flags: u32| {
+    let mut targets: Vec<String> = graph
+      .read_scores(OpReadScores {
+        ego:           "U1".into(),
+        score_options: FilterOptions {
+          node_kind: Some(NodeKind::Beacon),
+          exclude_flags,
+          require_flags,
+          ..FilterOptions::default()
+        },
+      })
+      .unwrap()
+      .into_iter()
+      .map(|x| x.target)
+      .collect();
+    targets.sort();
+    targets
+  };
+
+  assert_eq!(read(0, 0), vec!["B1", "B2", "B3", "B4"]);
+  assert_eq!(read(NSFW, 0), vec!["B2", "B4"]);
+  assert_eq!(read(0, PINNED), vec!["B2", "B3"]);
+  assert_eq!(read(NSFW, PINNED), vec!["B2"]);
+}
+
+#[test]
+fn beacon_ego_scores_clustering() {
+  let edges = [("B1", "U1", 3.0), ("B1", "U2", 2.0), ("B1", "U3", 1.0)];
+
+  let mut graph = default_graph();
+  for (src, dst, weight) in &edges {
+    graph.set_edge((*src).into(), (*dst).into(), *weight, 0);
+  }
+  graph.calculate("B1".into());
+  assert!(read_scores(&graph, "B1", "U", false, 10.0, false, 0.0, false, 0, u32::MAX).is_empty());
+
+  let mut graph = AugGraph::new(Settings {
+    num_walks: 500,
+    zero_opinion_factor: 0.0,
+    ego_kinds: vec![NodeKind::User, NodeKind::Beacon],
+    ..Settings::default()
+  });
+  for (src, dst, weight) in &edges {
+    graph.set_edge((*src).into(), (*dst).into(), *weight, 0);
+  }
+  graph.calculate("B1".into());
+
+  let res = read_scores(&graph, "B1", "U", false, 10.0, false, 0.0, false, 0, u32::MAX);
+  assert_eq!(res.len(), 3);
+  assert_eq!(res[0].target, "U1");
+  assert!(res[0].cluster > res[2].cluster);
+
+  let node = read_node_score_helper(&graph, "B1", "U1");
+  assert_eq!(node[0].cluster, res[0].cluster);
+}
+
+#[test]
+fn stale_clusters_recalculated_in_background() {
+  let mut graph = AugGraph::new(Settings {
+    num_walks: 50,
+    zero_opinion_factor: 0.0,
+    background_clustering: true,
+    // Every cached bound is stale right away.
+    score_clusters_timeout: 0,
+    ..Settings::default()
+  });
+
+  graph.set_edge("U1".into(), "U2".into(), 2.0, 0);
+  graph.set_edge("U1".into(), "U3".into(), 1.0, 0);
+  graph.calculate("U1".into());
+
+  let first = read_scores(&graph, "U1", "U", false, 10.0, false, 0.0, false, 0, u32::MAX);
+  graph.process_pending_clusters();
+  assert_eq!(graph.pending_clusters_len(), 0);
+
+  // Stale bounds are served and queued instead of recalculated.
+  let second = read_scores(&graph, "U1", "U", false, 10.0, false, 0.0, false, 0, u32::MAX);
+  assert!(graph.pending_clusters_len() > 0);
+  let clusters = |res: &[ScoreResult]| {
+    res.iter().map(|x| x.cluster).collect::<Vec<_>>()
+  };
+  assert_eq!(clusters(&first), clusters(&second));
+
+  assert!(graph.process_pending_clusters() > 0);
+  assert_eq!(graph.pending_clusters_len(), 0);
+}
+
+#[test]
+fn hide_personal_nodes_of_every_owner() {
+  let mut graph = default_graph();
+
+  graph.set_edge("C1".into(), "U1".into(), 1.0, 0);
+  graph.set_edge("C1".into(), "U2".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "C1".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "C1".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "C2".into(), 1.0, 0);
+  graph.calculate("U1".into());
+  graph.calculate("U2".into());
+
+  let names = |ego: &str, hide_personal: bool| {
+    read_scores(&graph, ego, "C", hide_personal, 10.0, false, 0.0, false, 0, u32::MAX)
+      .into_iter()
+      .map(|x| x.target)
+      .collect::<Vec<_>>()
+  };
+
+  assert!(names("U2", false).contains(&"C1".to_string()));
+  // C1 is owned by U1, but it is also personal for U2.
+  assert!(!names("U1", true).contains(&"C1".to_string()));
+  assert_eq!(names("U2", true), vec!["C2".to_string()]);
+}
+
+#[test]
+fn hide_personal_after_node_deletion() {
+  let mut graph = default_graph();
+
+  graph.set_edge("C1".into(), "U1".into(), 1.0, 0);
+  graph.set_edge("C2".into(), "U1".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "C1".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "C2".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+
+  // C2 stops being personal for U1 once its edge to U1 is gone.
+  graph.apply_op(&AugGraphOp::DeleteNode("C2".into()));
+  graph.calculate("U1".into());
+
+  let names = read_scores(&graph, "U1", "C", true, 10.0, false, 0.0, false, 0, u32::MAX)
+    .into_iter()
+    .map(|x| x.target)
+    .collect::<Vec<_>>();
+  assert_eq!(names, vec!["C2".to_string()]);
+}
+
+#[test]
+fn hide_personal_filter_grows() {
+  let mut graph = AugGraph::new(Settings {
+    num_walks: 500,
+    zero_opinion_factor: 0.0,
+    filter_capacity: 2,
+    ..Settings::default()
+  });
+
+  for comment in ["C1", "C2", "C3", "C4", "C5"] {
+    graph.set_edge(comment.into(), "U1".into(), 1.0, 0);
+    graph.set_edge("U2".into(), comment.into(), 1.0, 0);
+  }
+  graph.set_edge("U2".into(), "C6".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.calculate("U1".into());
+
+  let names = read_scores(&graph, "U1", "C", true, 10.0, false, 0.0, false, 0, u32::MAX)
+    .into_iter()
+    .map(|x| x.target)
+    .collect::<Vec<_>>();
+  assert_eq!(names, vec!["C6".to_string()]);
+}
+
+#[test]
+fn renamed_node_keeps_edges() {
+  let mut graph = default_graph();
+
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "U3".into(), 1.0, 0);
+  graph.apply_op(&AugGraphOp::AliasNode(OpWriteAliasNode {
+    old: "U2".into(),
+    new: "U9".into(),
+  }));
+  graph.calculate("U1".into());
+
+  fn names(
+    graph: &AugGraph,
+    ego: &str,
+  ) -> Vec<String> {
+    read_scores(graph, ego, "U", false, 10.0, false, 0.0, false, 0, u32::MAX)
+      .into_iter()
+      .map(|x| x.target)
+      .collect()
+  }
+  let from_u1 = names(&graph, "U1");
+  assert!(from_u1.contains(&"U9".to_string()));
+  assert!(!from_u1.contains(&"U2".to_string()));
+
+  // The old name is an alias of the same node.
+  graph.set_edge("U2".into(), "U4".into(), 1.0, 0);
+  graph.calculate("U9".into());
+  assert!(names(&graph, "U9").contains(&"U4".to_string()));
+  assert_eq!(names(&graph, "U2"), names(&graph, "U9"));
+}
+
+#[test]
+fn custom_node_kind_scores() {
+  register_node_kind('A', "Article").unwrap();
+  let mut graph = default_graph();
+
+  graph.set_edge("A1".into(), "U1".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "A2".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "B1".into(), 1.0, 0);
+  graph.calculate("U1".into());
+
+  assert_eq!(
+    graph.nodes.get_by_name("A1").unwrap().owner,
+    graph.nodes.get_by_name("U1").map(|x| x.id)
+  );
+  let res = read_scores(&graph, "U1", "A", false, 10.0, false, 0.0, false, 0, u32::MAX);
+  assert_eq!(res.len(), 1);
+  assert_eq!(res[0].target, "A2");
+}
+
+#[test]
+fn score_weights_per_kind() {
+  let mut graph = default_graph();
+  graph.set_edge("U1".into(), "C1".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "B1".into(), 1.0, 0);
+  graph.calculate("U1".into());
+
+  // Same walks, different weights.
+  let mut weighted = graph.fork();
+  weighted.settings.score_weights = vec![(NodeKind::Comment, 0.5)];
+
+  let score = |graph: &AugGraph, dst: &str| {
+    read_scores(graph, "U1", &dst[..1], false, 10.0, false, -10.0, false, 0, u32::MAX)
+      .into_iter()
+      .find(|x| x.target == dst)
+      .map(|x| x.score)
+      .unwrap()
+  };
+  assert!(score(&graph, "C1") > 0.0);
+  assert!((score(&weighted, "C1") - 0.5 * score(&graph, "C1")).abs() < 1e-9);
+  assert_eq!(score(&weighted, "B1"), score(&graph, "B1"));
+}
+
+#[test]
+fn scores_rank_kind_and_percentile() {
+  let mut graph = default_graph();
+  for (dst, weight) in [("U2", 1.0), ("U3", 2.0), ("U4", 3.0), ("B1", 1.0)] {
+    graph.set_edge("U1".into(), dst.into(), weight, 0);
+  }
+  graph.calculate("U1".into());
+
+  let res = read_scores(&graph, "U1", "U", false, 10.0, false, 0.0, false, 1, u32::MAX);
+  assert!(!res.is_empty());
+  for (i, x) in res.iter().enumerate() {
+    assert_eq!(x.rank as usize, i + 2);
+    assert_eq!(x.kind, NodeKind::User);
+    assert!((0.0..=100.0).contains(&x.percentile));
+  }
+  assert!(res.windows(2).all(|w| w[0].percentile >= w[1].percentile));
+
+  let res = read_scores(&graph, "U1", "B", false, 10.0, false, 0.0, false, 0, u32::MAX);
+  assert_eq!(res[0].kind, NodeKind::Beacon);
+  assert_eq!(res[0].rank, 1);
+}
+
+#[test]
+fn compare_egos_divergent_nodes_first() {
+  let mut graph = default_graph();
+  graph.set_edge("U1".into(), "U3".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "U3".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "U4".into(), 5.0, 0);
+  graph.calculate("U1".into());
+  graph.calculate("U2".into());
+
+  let res = graph.compare_egos(OpReadCompareEgos {
+    a:     "U1".into(),
+    b:     "U2".into(),
+    limit: 10,
+  });
+  assert!(res.windows(2).all(|w| {
+    (w[0].score_a - w[0].score_b).abs() >= (w[1].score_a - w[1].score_b).abs()
+  }));
+  let u4 = res.iter().find(|x| x.target == "U4").unwrap();
+  assert_eq!(u4.score_a, 0.0);
+  assert!(u4.score_b > 0.0);
+
+  let res = graph.compare_egos(OpReadCompareEgos {
+    a:     "U1".into(),
+    b:     "U2".into(),
+    limit: 1,
+  });
+  assert_eq!(res.len(), 1);
+}
+
+#[test]
+fn recommendations_from_similar_egos() {
+  let mut graph = default_graph();
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "U3".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "U3".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "B1".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "B1".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "B2".into(), 1.0, 0);
+  graph.calculate("U1".into());
+  graph.calculate("U2".into());
+  graph.calculate("U3".into());
+
+  let res = graph.read_recommendations(OpReadRecommendations {
+    ego:   "U1".into(),
+    kind:  Some(NodeKind::Beacon),
+    limit: 10,
+  });
+  let targets: Vec<_> = res.iter().map(|x| x.target.as_str()).collect();
+  assert_eq!(targets, vec!["B2"]);
+  assert!(res[0].score > 0.0);
+  assert_eq!(res[0].egos, 1);
+
+  let res = graph.read_recommendations(OpReadRecommendations {
+    ego:   "U1".into(),
+    kind:  Some(NodeKind::Beacon),
+    limit: 0,
+  });
+  assert!(res.is_empty());
+}
+
+#[test]
+fn mutual_suggestions_by_peer_count() {
+  let mut graph = default_graph();
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "U3".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "U5".into(), -1.0, 0);
+  graph.set_edge("U2".into(), "U4".into(), 1.0, 0);
+  graph.set_edge("U3".into(), "U4".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "U6".into(), 3.0, 0);
+  graph.set_edge("U2".into(), "U5".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "U3".into(), 1.0, 0);
+  graph.set_edge("U2".into(), "B1".into(), 1.0, 0);
+
+  let res = graph.read_mutual_suggestions(OpReadMutualSuggestions {
+    ego:   "U1".into(),
+    limit: 10,
+  });
+  let targets: Vec<_> = res.iter().map(|x| x.target.as_str()).collect();
+  assert_eq!(targets, vec!["U4", "U6"]);
+  assert_eq!(res[0].peers, 2);
+  assert_eq!(res[0].weight, 2.0);
+  assert_eq!(res[1].peers, 1);
+}
+
+#[test]
+fn score_history_records_reads() {
+  let mut graph = AugGraph::new(Settings {
+    num_walks:              50,
+    zero_opinion_factor:    0.0,
+    history_size:           2,
+    history_interval:       0,
+    ..Settings::default()
+  });
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "U3".into(), 1.0, 0);
+  graph.calculate("U1".into());
+
+  let res = read_scores(&graph, "U1", "U", false, 10.0, false, 0.0, false, 0, u32::MAX);
+  let history = |target: &str| {
+    graph.read_score_history(OpReadScoreHistory {
+      ego:    "U1".into(),
+      target: target.into(),
+    })
+  };
+  assert_eq!(history("U2").len(), 1);
+  assert_eq!(history("U3").len(), 1);
+
+  assert_eq!(graph.sample_score_history(), res.len());
+  assert_eq!(graph.sample_score_history(), res.len());
+  let samples = history("U2");
+  assert_eq!(samples.len(), 2);
+  assert!(samples.iter().all(|(_, score)| *score > 0.0));
+  assert!(history("U4").is_empty());
+}
+
+#[test]
+fn decay_edges_scales_and_prunes() {
+  let mut graph = default_graph();
+  graph.set_edge("U1".into(), "U2".into(), 1.0, 0);
+  graph.set_edge("U1".into(), "U3".into(), 0.01, 0);
+  graph.calculate("U1".into());
+  let before = read_node_score_helper(&graph, "U1", "U2")[0].score;
+
+  graph.apply_op(&AugGraphOp::DecayEdges(0.5));
+  fn weight(
+    graph: &AugGraph,
+    dst: &str,
+  ) -> Option<f64> {
+    graph
+      .read_edges()
+      .into_iter()
+      .find(|e| e.src == "U1" && e.dst == dst)
+      .map(|e| e.weight)
+  }
+  let u2_weight = weight(&graph, "U2").unwrap();
+  assert!(u2_weight > 0.0);
+  assert_eq!(read_node_score_helper(&graph, "U1", "U2")[0].score, before);
+
+  assert_eq!(graph.decay_edges(1e-4), 1);
+  assert!(weight(&graph, "U3").is_none());
+  assert!((weight(&graph, "U2").unwrap() - u2_weight * 1e-4).abs() < 1e-12);
+}